@@ -0,0 +1,131 @@
+use std::{fs, io, path::Path, thread, time::Duration};
+
+use regex::Regex;
+use serde::Deserialize;
+
+/// The rule gives the alias to the process, or to its parent, whose executable path
+/// matches the pattern, `*` matches any substring, or whose command line matches the regex.
+#[derive(Deserialize)]
+struct RuleConfig {
+    exe: Option<String>,
+    cmdline: Option<String>,
+    alias: String,
+}
+
+enum Matcher {
+    Exe(String),
+    Cmdline(Regex),
+}
+
+struct Rule {
+    matcher: Matcher,
+    alias: String,
+}
+
+/// The alternative to the `BPF_ALIAS` environment variable of the node,
+/// the rules are evaluated against `/proc` when the libp2p helper starts.
+pub struct AliasRules {
+    rules: Vec<Rule>,
+}
+
+impl AliasRules {
+    /// Read the json array of the rules, the first matching rule wins, for example
+    /// `[{"exe": "*/mina.exe", "alias": "berkeley-0.0.0.0"}]`.
+    pub fn load<P>(path: P) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let invalid_data = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+        let configs = serde_json::from_slice::<Vec<RuleConfig>>(&fs::read(path)?)
+            .map_err(|err| invalid_data(err.to_string()))?;
+        let mut rules = Vec::with_capacity(configs.len());
+        for RuleConfig {
+            exe,
+            cmdline,
+            alias,
+        } in configs
+        {
+            let matcher = match (exe, cmdline) {
+                (Some(exe), None) => Matcher::Exe(exe),
+                (None, Some(cmdline)) => Regex::new(&cmdline)
+                    .map(Matcher::Cmdline)
+                    .map_err(|err| invalid_data(format!("{alias}: {err}")))?,
+                _ => {
+                    let msg = format!("{alias}: the rule needs either `exe` or `cmdline`");
+                    return Err(invalid_data(msg));
+                }
+            };
+            rules.push(Rule { matcher, alias });
+        }
+
+        Ok(AliasRules { rules })
+    }
+
+    /// The alias of the process, checks the process itself and then its parent,
+    /// the libp2p helper is usually spawned by the node.
+    pub fn resolve(&self, pid: u32) -> Option<String> {
+        // the kernel reports the process on enter to `execve`,
+        // give it a moment to replace the image of the forked parent
+        let mut tries = 5;
+        while tries > 0 && !exec_done(pid) {
+            tries -= 1;
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let ppid = parent(pid);
+        std::iter::once(pid)
+            .chain(ppid)
+            .find_map(|pid| self.resolve_single(pid))
+    }
+
+    fn resolve_single(&self, pid: u32) -> Option<String> {
+        let exe = fs::read_link(format!("/proc/{pid}/exe")).ok();
+        let exe = exe.as_ref().and_then(|p| p.to_str());
+        // the arguments are separated by the null character
+        let cmdline = fs::read(format!("/proc/{pid}/cmdline"))
+            .ok()
+            .map(|b| String::from_utf8_lossy(&b).replace('\0', " "));
+        let cmdline = cmdline.as_deref().map(str::trim_end);
+
+        self.rules
+            .iter()
+            .find(|rule| match &rule.matcher {
+                Matcher::Exe(pattern) => exe.map_or(false, |exe| wildcard(pattern, exe)),
+                Matcher::Cmdline(regex) => cmdline.map_or(false, |s| regex.is_match(s)),
+            })
+            .map(|rule| rule.alias.clone())
+    }
+}
+
+fn exec_done(pid: u32) -> bool {
+    let cmdline = fs::read(format!("/proc/{pid}/cmdline")).unwrap_or_default();
+    cmdline.starts_with(b"coda-libp2p_helper") || cmdline.starts_with(b"openmina")
+}
+
+fn parent(pid: u32) -> Option<u32> {
+    let stat = fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // the name in parentheses might contain spaces, the state and the ppid follow it
+    let (_, rest) = stat.rsplit_once(')')?;
+    rest.split_whitespace().nth(1)?.parse().ok()
+}
+
+fn wildcard(pattern: &str, s: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = s.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts = parts.collect::<Vec<_>>();
+    let Some(last) = parts.pop() else {
+        // no wildcard
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(i) => rest = &rest[(i + part.len())..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
@@ -18,6 +18,11 @@ pub enum Variant {
         addr_ptr: u64,
         addr_len: u64,
     },
+    Listen {
+        fd: u32,
+        backlog: u64,
+        _pad: u64,
+    },
     Connect {
         fd: u32,
         addr_ptr: u64,
@@ -72,6 +77,7 @@ impl Variant {
         match self {
             Variant::Empty { ptr, .. } => *ptr as *const u8,
             Variant::Bind { addr_ptr, .. } => *addr_ptr as *const u8,
+            Variant::Listen { .. } => core::ptr::null(),
             Variant::Connect { addr_ptr, .. } => *addr_ptr as *const u8,
             Variant::Accept { addr_ptr, .. } => *addr_ptr as *const u8,
             Variant::Write { data_ptr, .. } => *data_ptr as *const u8,
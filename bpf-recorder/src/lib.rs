@@ -3,6 +3,14 @@
 #[cfg(feature = "user")]
 pub mod proc;
 
+/// Give the alias to the process without `BPF_ALIAS` by the rules of the config.
+#[cfg(feature = "user")]
+pub mod alias;
+
+/// The raw events in a file, to run the userspace pipeline without the kernel module.
+#[cfg(feature = "user")]
+pub mod replay;
+
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
 pub struct StatsBlocked {
@@ -68,6 +76,7 @@ pub enum DataTag {
     Random,
     GetSockOpt,
     SnarkWorker,
+    Exec,
 }
 
 impl DataTag {
@@ -85,14 +94,56 @@ impl DataTag {
             DataTag::Random,
             DataTag::GetSockOpt,
             DataTag::SnarkWorker,
+            DataTag::Exec,
         ];
         values.into_iter().find(|&v| v as u32 == c)
     }
 }
 
+/// Kernel clock of the event timestamps, userspace writes it in `App::clock_source`.
+#[repr(u32)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ClockSource {
+    /// `CLOCK_BOOTTIME`, keeps counting while the system is suspended
+    #[default]
+    Boottime = 0,
+    /// `CLOCK_MONOTONIC`, stops while the system is suspended
+    Monotonic = 1,
+    /// `CLOCK_TAI`, the real time, linux 6.1 or newer,
+    /// the kernel module must be built with the feature `tai-clock`
+    Tai = 2,
+}
+
+impl ClockSource {
+    pub fn from_u32(c: u32) -> Self {
+        match c {
+            1 => ClockSource::Monotonic,
+            2 => ClockSource::Tai,
+            _ => ClockSource::Boottime,
+        }
+    }
+}
+
+#[cfg(feature = "user")]
+impl core::str::FromStr for ClockSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "boottime" => Ok(ClockSource::Boottime),
+            "monotonic" => Ok(ClockSource::Monotonic),
+            "tai" => Ok(ClockSource::Tai),
+            _ => Err(s.to_owned()),
+        }
+    }
+}
+
 #[cfg(feature = "user")]
 pub mod sniffer_event {
-    use std::net::{IpAddr, SocketAddr};
+    use std::{
+        collections::BTreeSet,
+        net::{IpAddr, SocketAddr},
+    };
 
     use bpf_ring_buffer::RingBufferData;
 
@@ -111,8 +162,12 @@ pub mod sniffer_event {
     #[derive(Debug)]
     pub enum SnifferEventVariant {
         NewApp(String),
+        /// the libp2p helper started without `BPF_ALIAS`, not recorded until the alias is known
+        NewUnaliasedApp,
         NewSnarkWorkerApp,
         Bind(SocketAddr),
+        /// the socket bound before is listening, its port is the port of the node
+        Listen,
         IncomingConnection(SocketAddr),
         OutgoingConnection(SocketAddr),
         Disconnected,
@@ -121,23 +176,46 @@ pub mod sniffer_event {
         Random(Vec<u8>),
         GetSockOpt(Vec<u8>),
         Error(DataTag, i32),
+        /// not produced by the kernel module, the process watcher reports it
+        ProcessExit,
+        /// not produced by the kernel module, the ring buffer overflowed
+        /// and the recorder skipped this many bytes of events
+        Gap(u64),
     }
 
+    /// The slice is shorter than the header, or than the payload the header tells.
     #[derive(Debug)]
-    pub struct ErrorSliceTooShort;
+    pub struct ErrorSliceTooShort {
+        pub expected: usize,
+        pub actual: usize,
+    }
 
-    impl RingBufferData for SnifferEvent {
-        type Error = ErrorSliceTooShort;
+    /// The event parsed in place, the payload is borrowed from the ring buffer.
+    pub struct SnifferEventRef<'a> {
+        pub pid: u32,
+        pub tid: u32,
+        pub fd: u32,
+        pub ts0: u64,
+        pub ts1: u64,
+        pub tag: DataTag,
+        /// the size of the payload, negative is the error code
+        pub size: i32,
+        pub data: &'a [u8],
+    }
 
-        fn from_rb_slice(slice: &[u8]) -> Result<Option<Self>, Self::Error> {
+    impl<'a> SnifferEventRef<'a> {
+        /// Reads the header in place, `None` if the slice is empty.
+        pub fn parse(slice: &'a [u8]) -> Result<Option<Self>, ErrorSliceTooShort> {
             use core::{mem, ptr};
 
             if slice.is_empty() {
                 return Ok(None);
             }
             if slice.len() < mem::size_of::<Event>() {
-                log::error!("slice too short: {}", hex::encode(slice));
-                return Ok(None);
+                return Err(ErrorSliceTooShort {
+                    expected: mem::size_of::<Event>(),
+                    actual: slice.len(),
+                });
             }
             let event = unsafe { ptr::read::<Event>(slice.as_ptr() as *const _) };
             let Event {
@@ -149,29 +227,67 @@ pub mod sniffer_event {
                 tag,
                 size,
             } = event;
-            let ret = |variant| -> Result<Option<Self>, ErrorSliceTooShort> {
-                Ok(Some(SnifferEvent {
+            let data: &[u8] = if size < 0 {
+                &[]
+            } else {
+                let size = size as usize;
+                if slice.len() < mem::size_of::<Event>() + size {
+                    return Err(ErrorSliceTooShort {
+                        expected: mem::size_of::<Event>() + size,
+                        actual: slice.len(),
+                    });
+                }
+                &slice[mem::size_of::<Event>()..(mem::size_of::<Event>() + size)]
+            };
+            Ok(Some(SnifferEventRef {
+                pid,
+                tid,
+                fd,
+                ts0,
+                ts1,
+                tag,
+                size,
+                data,
+            }))
+        }
+
+        /// Reads only `ts1` of the header, the slice may be shorter than the header.
+        pub fn peek_ts1(slice: &[u8]) -> Option<u64> {
+            use core::{mem::MaybeUninit, ptr};
+
+            // the offset of the field, the event is not read
+            let event = MaybeUninit::<Event>::uninit();
+            let base = event.as_ptr();
+            let offset = unsafe { ptr::addr_of!((*base).ts1) as usize - base as usize };
+            let bytes = slice.get(offset..(offset + 8))?;
+            Some(u64::from_ne_bytes(bytes.try_into().ok()?))
+        }
+
+        /// Copies the payload out of the ring buffer.
+        pub fn to_event(&self) -> Option<SnifferEvent> {
+            let &SnifferEventRef {
+                pid,
+                tid,
+                fd,
+                ts0,
+                ts1,
+                tag,
+                size,
+                data,
+            } = self;
+            let ret = |variant| {
+                Some(SnifferEvent {
                     pid,
                     tid,
                     fd,
                     ts0,
                     ts1,
                     variant,
-                }))
+                })
             };
             if size < 0 {
                 return ret(SnifferEventVariant::Error(tag, size));
             }
-            let size = size as usize;
-            if slice.len() < mem::size_of::<Event>() + size {
-                log::error!(
-                    "expected {} bytes header + {size} bytes body, got {}, cannot recover",
-                    mem::size_of::<Event>(),
-                    slice.len(),
-                );
-                std::process::exit(1);
-            }
-            let data = &slice[mem::size_of::<Event>()..(mem::size_of::<Event>() + size)];
             if let DataTag::Accept | DataTag::Connect | DataTag::Bind = tag {
                 let address_family = u16::from_ne_bytes(data[0..2].try_into().unwrap());
                 let port = u16::from_be_bytes(data[2..4].try_into().unwrap());
@@ -184,8 +300,10 @@ pub mod sniffer_event {
                         let ip = <[u8; 16]>::try_from(&data[8..24]).unwrap();
                         SocketAddr::new(IpAddr::V6(ip.into()), port)
                     }
-                    _ => return Ok(None),
+                    _ => return None,
                 };
+                // the dual stack socket reports ipv4 peers as `::ffff:a.b.c.d`
+                let addr = mina_recorder::canonical_addr(addr);
                 match tag {
                     DataTag::Accept => ret(SnifferEventVariant::IncomingConnection(addr)),
                     DataTag::Connect => ret(SnifferEventVariant::OutgoingConnection(addr)),
@@ -198,6 +316,8 @@ pub mod sniffer_event {
                 ret(SnifferEventVariant::OutgoingData(data.to_vec()))
             } else if let DataTag::Close = tag {
                 ret(SnifferEventVariant::Disconnected)
+            } else if let DataTag::Listen = tag {
+                ret(SnifferEventVariant::Listen)
             } else if let DataTag::Alias = tag {
                 ret(SnifferEventVariant::NewApp(
                     String::from_utf8(data[..(data.len() - 1)].to_vec())
@@ -209,6 +329,8 @@ pub mod sniffer_event {
                 ret(SnifferEventVariant::GetSockOpt(data.to_vec()))
             } else if let DataTag::SnarkWorker = tag {
                 ret(SnifferEventVariant::NewSnarkWorkerApp)
+            } else if let DataTag::Exec = tag {
+                ret(SnifferEventVariant::NewUnaliasedApp)
             } else if let DataTag::Debug = tag {
                 if data.len() == 4 {
                     log::info!(
@@ -218,9 +340,55 @@ pub mod sniffer_event {
                 } else {
                     log::info!("DEBUG: hex: {}", hex::encode(data));
                 }
-                Ok(None)
+                None
             } else {
-                Ok(None)
+                None
+            }
+        }
+    }
+
+    impl RingBufferData for SnifferEvent {
+        type Error = ErrorSliceTooShort;
+
+        fn from_rb_slice(slice: &[u8]) -> Result<Option<Self>, Self::Error> {
+            Ok(SnifferEventRef::parse(slice)?.and_then(|event| event.to_event()))
+        }
+    }
+
+    /// The sockets the recorder may handle, learned from the connect, the accept and
+    /// the close events in the order of the ring buffer. The payload of the other file
+    /// descriptors is not copied out of the ring buffer, the recorder ignores it anyway.
+    #[derive(Default)]
+    pub struct PayloadFilter {
+        sockets: BTreeSet<(u32, u32)>,
+        snark_workers: BTreeSet<u32>,
+    }
+
+    impl PayloadFilter {
+        /// Whether the event must be copied, updates the sockets.
+        pub fn wants(&mut self, event: &SnifferEventRef) -> bool {
+            let key = (event.pid, event.fd);
+            match event.tag {
+                DataTag::Accept | DataTag::Connect if event.size >= 0 => {
+                    self.sockets.insert(key);
+                    true
+                }
+                DataTag::Close => {
+                    self.sockets.remove(&key);
+                    true
+                }
+                DataTag::SnarkWorker => {
+                    self.snark_workers.insert(event.pid);
+                    true
+                }
+                // the standard input and output of the helper carry the capnp messages
+                DataTag::Read | DataTag::Write if event.size >= 0 => {
+                    event.fd == 0
+                        || event.fd == 1
+                        || self.sockets.contains(&key)
+                        || self.snark_workers.contains(&event.pid)
+                }
+                _ => true,
             }
         }
     }
@@ -20,6 +20,9 @@ pub struct App {
     pub pid: ebpf::HashMapRef<4, 4>,
     #[hashmap(size = 0x1000)]
     pub pid_snark_worker: ebpf::HashMapRef<4, 4>,
+    // the kernel clock of the timestamps, the key is zero, the value is `ClockSource`
+    #[hashmap(size = 1)]
+    pub clock_source: ebpf::HashMapRef<4, 4>,
     #[prog("tracepoint/syscalls/sys_enter_execve")]
     pub execve: ebpf::ProgRef,
     #[prog("tracepoint/syscalls/sys_enter_execveat")]
@@ -32,6 +35,10 @@ pub struct App {
     pub enter_bind: ebpf::ProgRef,
     #[prog("tracepoint/syscalls/sys_exit_bind")]
     pub exit_bind: ebpf::ProgRef,
+    #[prog("tracepoint/syscalls/sys_enter_listen")]
+    pub enter_listen: ebpf::ProgRef,
+    #[prog("tracepoint/syscalls/sys_exit_listen")]
+    pub exit_listen: ebpf::ProgRef,
     #[prog("tracepoint/syscalls/sys_enter_connect")]
     pub enter_connect: ebpf::ProgRef,
     #[prog("tracepoint/syscalls/sys_exit_connect")]
@@ -95,7 +102,15 @@ mod context;
 mod send;
 
 #[cfg(feature = "kern")]
-use bpf_recorder::{DataTag, Event, StatsBlocked};
+use bpf_recorder::{DataTag, Event, StatsBlocked, ClockSource};
+
+// `bpf_ktime_get_tai_ns`, the verifier rejects the module on kernels older than 6.1
+#[cfg(all(feature = "kern", feature = "tai-clock"))]
+#[inline(always)]
+unsafe fn ktime_get_tai_ns() -> u64 {
+    let f: unsafe extern "C" fn() -> u64 = core::mem::transmute(208_usize);
+    f()
+}
 
 #[cfg(feature = "kern")]
 #[no_mangle]
@@ -146,6 +161,22 @@ impl App {
         Err(0)
     }
 
+    #[inline(always)]
+    fn now(&self) -> u64 {
+        use ebpf::helpers;
+
+        let source = match self.clock_source.get(&0_u32.to_ne_bytes()) {
+            Some(&v) => ClockSource::from_u32(u32::from_ne_bytes(v)),
+            None => ClockSource::Boottime,
+        };
+        match source {
+            ClockSource::Monotonic => unsafe { helpers::ktime_get_ns() },
+            #[cfg(feature = "tai-clock")]
+            ClockSource::Tai => unsafe { ktime_get_tai_ns() },
+            _ => unsafe { helpers::ktime_get_boot_ns() },
+        }
+    }
+
     #[inline(always)]
     fn check_pid_snark_worker(&self) -> Result<(), i32> {
         use ebpf::helpers;
@@ -230,7 +261,7 @@ impl App {
                     ((x >> 32) as u32, (x & 0xffffffff) as u32)
                 };
 
-                let ts = unsafe { helpers::ktime_get_boot_ns() };
+                let ts = self.now();
                 let event = Event::new(pid, tid, ts, ts);
                 let event = event.set_tag_fd(DataTag::Alias, 0).set_ok(len as u64);
                 let name = unsafe { entry.offset(10) };
@@ -402,7 +433,7 @@ impl App {
                     ((x >> 32) as u32, (x & 0xffffffff) as u32)
                 };
 
-                let ts = unsafe { helpers::ktime_get_boot_ns() };
+                let ts = self.now();
                 let event = Event::new(pid, tid, ts, ts);
                 let event = event.set_tag_fd(DataTag::SnarkWorker, 0).set_ok(0);
                 send::dyn_sized::<typenum::B0>(&mut self.event_queue, event, ptr::null())?;
@@ -428,7 +459,7 @@ impl App {
         }
         self.check_name(argv)?;
         let env = ctx.read_here::<*const *const u8>(0x20);
-        self.check_env_flag(env)
+        self.check_env_flag(env).or_else(|_| self.report_exec())
     }
 
     #[inline(always)]
@@ -439,7 +470,23 @@ impl App {
         }
         self.check_name(argv)?;
         let env = ctx.read_here::<*const *const u8>(0x28);
-        self.check_env_flag(env)
+        self.check_env_flag(env).or_else(|_| self.report_exec())
+    }
+
+    // the helper without `BPF_ALIAS`, userspace might know its alias from the config
+    #[inline(always)]
+    fn report_exec(&mut self) -> Result<(), i32> {
+        use core::ptr;
+        use ebpf::helpers;
+
+        let (pid, tid) = {
+            let x = unsafe { helpers::get_current_pid_tgid() };
+            ((x >> 32) as u32, (x & 0xffffffff) as u32)
+        };
+        let ts = self.now();
+        let event = Event::new(pid, tid, ts, ts);
+        let event = event.set_tag_fd(DataTag::Exec, 0).set_ok(0);
+        send::dyn_sized::<typenum::B0>(&mut self.event_queue, event, ptr::null())
     }
 
     #[inline(always)]
@@ -458,7 +505,7 @@ impl App {
             let x = unsafe { helpers::get_current_pid_tgid() };
             ((x >> 32) as u32, (x & 0xffffffff) as u32)
         };
-        let ts = unsafe { helpers::ktime_get_boot_ns() };
+        let ts = self.now();
 
         let mut context = context::Parameters {
             data: context::Variant::Empty { ptr: 0, len: 0 },
@@ -488,7 +535,7 @@ impl App {
             let x = unsafe { helpers::get_current_pid_tgid() };
             ((x >> 32) as u32, (x & 0xffffffff) as u32)
         };
-        let ts1 = unsafe { helpers::ktime_get_boot_ns() };
+        let ts1 = self.now();
 
         match self
             .context_parameters
@@ -616,6 +663,14 @@ impl App {
                     event.set_ok(addr_len)
                 }
             }
+            context::Variant::Listen { fd, .. } => {
+                let event = event.set_tag_fd(DataTag::Listen, fd);
+                if ret < 0 {
+                    event.set_err(ret)
+                } else {
+                    event.set_ok(0)
+                }
+            }
             context::Variant::Connect { fd, addr_len, .. } => {
                 let _ip = check_addr(ptr)?;
 
@@ -755,6 +810,23 @@ impl App {
         self.exit(ctx)
     }
 
+    #[inline(always)]
+    pub fn enter_listen(&mut self, ctx: ebpf::Context) -> Result<(), i32> {
+        self.enter(
+            false,
+            context::Variant::Listen {
+                fd: ctx.read_here::<u64>(0x10) as u32,
+                backlog: ctx.read_here::<u64>(0x18),
+                _pad: 0,
+            },
+        )
+    }
+
+    #[inline(always)]
+    pub fn exit_listen(&mut self, ctx: ebpf::Context) -> Result<(), i32> {
+        self.exit(ctx)
+    }
+
     #[inline(always)]
     pub fn enter_connect(&mut self, ctx: ebpf::Context) -> Result<(), i32> {
         self.enter(
@@ -831,7 +903,7 @@ impl App {
             let x = unsafe { helpers::get_current_pid_tgid() };
             ((x >> 32) as u32, (x & 0xffffffff) as u32)
         };
-        let ts = unsafe { helpers::ktime_get_boot_ns() };
+        let ts = self.now();
 
         let socket_id = ((fd as u64) << 32) + (pid as u64);
         if self.connections.remove(&socket_id.to_ne_bytes())?.is_none() {
@@ -1122,20 +1194,32 @@ fn main() {
             atomic::{AtomicBool, Ordering},
             Arc, mpsc,
         },
-        time::{SystemTime, Duration},
+        time::{SystemTime, Duration, Instant},
         env, thread,
-        path::PathBuf,
+        path::{Path, PathBuf},
+        fs::{self, File},
+        io::{self, BufReader, BufWriter},
+        ffi::CString,
     };
 
     use bpf_recorder::{
-        sniffer_event::{SnifferEventVariant, SnifferEvent},
-        proc,
+        sniffer_event::{
+            SnifferEventVariant, SnifferEvent, SnifferEventRef, PayloadFilter, ErrorSliceTooShort,
+        },
+        replay::{ReplayReader, ReplayWriter},
+        proc, alias, ClockSource,
     };
     use simulator::registry::messages::{DebuggerReport, ConnectionMetadata};
-    use bpf_ring_buffer::RingBuffer;
+    use bpf_ring_buffer::{
+        RingBuffer, RingBufferConfig, RingBufferCorrupted, RingBufferOverflow, TeeReader,
+        WakeupHandle, PauseHandle,
+    };
     use mina_recorder::{
-        EventMetadata, ConnectionInfo, server, P2pRecorder, libp2p_helper::CapnpReader,
+        EventMetadata, ConnectionInfo, server, P2pRecorder,
+        libp2p_helper::CapnpReader,
         SnarkWorkerState, application,
+        sink::SinkConfig,
+        health::{RingBufferStatus, ParseFailure},
     };
     use ebpf::{kind::AppItem, Skeleton};
 
@@ -1149,6 +1233,128 @@ fn main() {
         });
     }
 
+    fn map_max_entries(fd: i32) -> usize {
+        let mut info = libbpf_sys::bpf_map_info::default();
+        let mut len = std::mem::size_of::<libbpf_sys::bpf_map_info>() as u32;
+        unsafe {
+            libbpf_sys::bpf_obj_get_info_by_fd(
+                fd,
+                &mut info as *mut libbpf_sys::bpf_map_info as *mut _,
+                &mut len as _,
+            )
+        };
+        info.max_entries as usize
+    }
+
+    // the kernel keeps the map and the unread events while it is pinned, even if we crash
+    fn pin_ring_buffer(fd: i32, path: &str) -> Option<CString> {
+        if Path::new(path).exists() {
+            log::error!(
+                "the ring buffer is pinned at {path} already, salvage it by \
+                 `bpf-recorder --salvage {path} <tee>` or remove it, do not pin this time"
+            );
+            return None;
+        }
+        let c_path = CString::new(path).ok()?;
+        if unsafe { libbpf_sys::bpf_obj_pin(fd, c_path.as_ptr()) } < 0 {
+            let err = io::Error::last_os_error();
+            log::error!("cannot pin the ring buffer at {path}: {err}");
+            return None;
+        }
+        log::info!("pinned the ring buffer at {path}");
+        Some(c_path)
+    }
+
+    // drains the ring buffer pinned by the recorder which crashed into the tee
+    fn salvage(map: &str, out: &str) {
+        let fd = match CString::new(map) {
+            Ok(path) => unsafe { libbpf_sys::bpf_obj_get(path.as_ptr()) },
+            Err(_) => -1,
+        };
+        if fd < 0 {
+            let err = io::Error::last_os_error();
+            log::error!("cannot open the pinned ring buffer {map}: {err}");
+            return;
+        }
+        let config = RingBufferConfig::default();
+        let mut rb = match RingBuffer::attach(fd, map_max_entries(fd), config) {
+            Ok(v) => v,
+            Err(err) => {
+                log::error!("cannot read the pinned ring buffer {map}: {err}");
+                unsafe { libc::close(fd) };
+                return;
+            }
+        };
+        match File::create(out) {
+            Ok(file) => rb.set_tee(BufWriter::new(file)),
+            Err(err) => {
+                log::error!("cannot create the ring buffer tee {out}: {err}");
+                unsafe { libc::close(fd) };
+                return;
+            }
+        }
+        let mut count = 0;
+        loop {
+            let consumed = rb.stats().bytes_consumed;
+            match rb.try_consume_with(|_| Ok::<_, ()>(())) {
+                Ok((n, _)) => count += n,
+                Err(err) => {
+                    log::error!("{err}, the rest of the ring buffer is lost");
+                    break;
+                }
+            }
+            // nobody writes the buffer anymore
+            if rb.stats().bytes_consumed == consumed {
+                break;
+            }
+        }
+        let tee = rb.take_tee().map(|mut tee| tee.flush());
+        drop(rb);
+        unsafe { libc::close(fd) };
+        if let Some(Err(err)) = tee {
+            log::error!("cannot write the ring buffer tee {out}: {err}");
+            return;
+        }
+        log::info!("salvaged {count} events, replay them by `bpf-recorder --replay {out}`");
+        // the events are consumed, release the memory of the map
+        match fs::remove_file(map) {
+            Ok(()) => log::info!("unpinned the ring buffer {map}"),
+            Err(err) => log::error!("cannot unpin the ring buffer {map}: {err}"),
+        }
+    }
+
+    // reports `ProcessExit` when the watched process is gone
+    fn watch_exit(
+        pids: mpsc::Receiver<u32>,
+        tx: mpsc::Sender<(Option<SnifferEvent>, usize)>,
+        terminating: Arc<AtomicBool>,
+        clock_source: ClockSource,
+    ) {
+        thread::spawn(move || {
+            let mut watched = BTreeSet::new();
+            while !terminating.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_secs(1));
+                watched.extend(pids.try_iter());
+                watched.retain(|pid| {
+                    if PathBuf::from(format!("/proc/{pid}")).exists() {
+                        return true;
+                    }
+                    let ts = proc::clock_now(clock_source);
+                    let event = SnifferEvent {
+                        pid: *pid,
+                        tid: 0,
+                        fd: 0,
+                        ts0: ts,
+                        ts1: ts,
+                        variant: SnifferEventVariant::ProcessExit,
+                    };
+                    tx.send((Some(event), 0)).unwrap_or_default();
+                    false
+                });
+            }
+        });
+    }
+
     // let env = env_logger::Env::default().default_filter_or("warn");
     // env_logger::init_from_env(env);
     // if let Err(err) = sudo::escalate_if_needed() {
@@ -1172,80 +1378,115 @@ fn main() {
     // let mut builder = env_logger::Builder::new();
     // builder.target(env_logger::Target::Pipe(Box::new(log)));
     // builder.try_init().expect("cannot setup logging");
-    env_logger::init();
+    mina_recorder::log_filter::init();
+
+    if let Ok(listen) = env::var("FORWARD_LISTEN") {
+        // remote instance, store and serve what edge recorders capture
+        let unseal = match mina_recorder::forward::Unseal::from_env(&db_path) {
+            Ok(v) => v,
+            Err(err) => {
+                log::error!("cannot prepare for sealed events: {err}");
+                return;
+            }
+        };
+        let (db, callback, server_thread) = server::spawn(port, db_path, None, key_path, cert_path);
+        let listener = match std::net::TcpListener::bind(&listen) {
+            Ok(v) => v,
+            Err(err) => {
+                log::error!("cannot listen {listen}: {err}");
+                callback();
+                return;
+            }
+        };
+        log::info!("receive forwarded events on {listen}");
+        mina_recorder::forward::spawn_receiver(listener, Arc::new(db), Arc::new(unseal));
+        let (tx, rx) = mpsc::channel();
+        if let Err(err) = ctrlc::set_handler(move || tx.send(()).unwrap_or_default()) {
+            log::error!("failed to set ctrlc handler {err}");
+        }
+        rx.recv().unwrap_or_default();
+        log::info!("ctrlc");
+        callback();
+        if server_thread.join().is_err() {
+            log::error!("server thread panic, this is a bug, must not happen");
+        }
+        return;
+    }
 
     let terminating = Arc::new(AtomicBool::new(dry));
 
     let mut interface = env::var("FIREWALL_INTERFACE").unwrap_or("eth0".to_string());
 
-    static CODE: &[u8] = include_bytes!(concat!("../", env!("BPF_CODE_RECORDER")));
-
-    let mut skeleton = Skeleton::<App>::open("bpf-recorder\0", CODE)
-        .unwrap_or_else(|code| panic!("failed to open bpf: {}", code));
-    skeleton
-        .load()
-        .unwrap_or_else(|code| panic!("failed to load bpf: {}", code));
-
-    skeleton
-        .app
-        .whitelist
-        .insert([0; 16], [0, 0, 0, 1])
-        .unwrap();
-
-    interface.push('\0');
-    let if_index = unsafe { libc::if_nametoindex(interface.as_ptr() as _) };
-
-    const XDP_FLAGS_SKB_MODE: u32 = 1 << 1;
-    skeleton
-        .attach_xdp("disable_connections", if_index as i32, XDP_FLAGS_SKB_MODE)
-        .unwrap();
-
-    let (skeleton, mut app) = skeleton
-        .attach()
-        .unwrap_or_else(|code| panic!("failed to attach bpf: {}", code));
-    log::info!("attached bpf module");
-
-    let fd = match app.event_queue.kind_mut() {
-        ebpf::kind::AppItemKindMut::Map(map) => map.fd(),
-        _ => unreachable!(),
-    };
-
-    let mut info = libbpf_sys::bpf_map_info::default();
-    let mut len = std::mem::size_of::<libbpf_sys::bpf_map_info>() as u32;
-    unsafe {
-        libbpf_sys::bpf_obj_get_info_by_fd(
-            fd,
-            &mut info as *mut libbpf_sys::bpf_map_info as *mut _,
-            &mut len as _,
-        )
+    let clock_source = match env::var("EVENT_CLOCK") {
+        Ok(s) => s.parse::<ClockSource>().unwrap_or_else(|s| {
+            log::error!("unknown clock {s}, use boottime");
+            ClockSource::Boottime
+        }),
+        Err(_) => ClockSource::Boottime,
     };
-    let mut rb = match RingBuffer::new(fd, info.max_entries as usize) {
-        Ok(v) => v,
-        Err(err) => {
-            log::error!("failed to create userspace part of the ring buffer: {err}");
-            std::process::exit(1);
+    log::info!("event clock: {clock_source:?}");
+
+    let auto_session = env::var("AUTO_SESSION").is_ok();
+    let (watch_tx, watch_rx) = mpsc::channel();
+
+    // the userspace pipeline, the events come from the kernel module or from the replay log
+    let consume = move |main_rx: mpsc::Receiver<(Option<SnifferEvent>, usize)>,
+                        main_thread: thread::JoinHandle<()>,
+                        app_client: Option<application::Application>,
+                        mut clock: proc::ClockMapping,
+                        mut replay_log: Option<ReplayWriter>,
+                        ring_buffer: Option<mpsc::Receiver<RingBufferStatus>>,
+                        wakeup: Option<WakeupHandle>,
+                        pause: Option<PauseHandle>,
+                        terminating: Arc<AtomicBool>| {
+        let (db, callback, server_thread) =
+            server::spawn(port, db_path, app_client.clone(), key_path, cert_path);
+        if let Some(ring_buffer) = ring_buffer {
+            let db = db.core();
+            thread::spawn(move || {
+                for status in ring_buffer {
+                    db.health().set_ring_buffer(status);
+                }
+            });
         }
-    };
-
-    let (app_client, app_server) = application::new(
-        app.whitelist.clone(),
-        app.whitelist_ports.clone(),
-        app.blocked.clone(),
-    );
-
-    let (main_tx, main_rx) = mpsc::channel();
-    let main_thread = thread::spawn({
-        let terminating = terminating.clone();
-        move || {
-            while let Ok(event) = rb.read_blocking::<SnifferEvent>(&terminating) {
-                main_tx.send(event).unwrap_or_default();
+        if let Some(pause) = pause {
+            db.core().health().set_pause(move |paused| {
+                if paused {
+                    pause.pause();
+                } else {
+                    pause.resume();
+                }
+            });
+        }
+        let sinks = match (env::var("SINKS"), env::var("FORWARD_TO")) {
+            (Ok(s), _) => SinkConfig::parse_list(&s)
+                .map_err(|s| log::error!("unknown sink {s}"))
+                .ok(),
+            (Err(_), Ok(remote)) => Some(vec![SinkConfig::Forward(remote)]),
+            (Err(_), Err(_)) => None,
+        };
+        if let Some(mut sinks) = sinks {
+            for sink in &mut sinks {
+                if let SinkConfig::Flows(_, rotation) = sink {
+                    let var = |name| env::var(name).ok().and_then(|s| s.parse().ok());
+                    if let Some(max_size) = var("FLOWS_MAX_SIZE") {
+                        rotation.max_size = max_size;
+                    }
+                    if let Some(max_age) = var("FLOWS_MAX_AGE") {
+                        rotation.max_age = Duration::from_secs(max_age);
+                    }
+                }
+            }
+            if let Err(err) = db.core().sinks().configure(&sinks) {
+                log::error!("cannot configure sinks: {err}");
             }
         }
-    });
-
-    let consumer_thread = thread::spawn(move || {
-        let (db, callback, server_thread) =
-            server::spawn(port, db_path, Some(app_client.clone()), key_path, cert_path);
+        if let Some(port) = env::var("GRPC_PORT")
+            .ok()
+            .and_then(|s| s.parse::<u16>().ok())
+        {
+            mina_recorder::grpc::spawn(([0, 0, 0, 0], port).into(), db.core());
+        }
         {
             let terminating = terminating.clone();
             let mut callback = Some(callback);
@@ -1255,6 +1496,10 @@ fn main() {
                     cb();
                 }
                 terminating.store(true, Ordering::SeqCst);
+                // the reader of the ring buffer drains it and stops without the poll timeout
+                if let Some(wakeup) = &wakeup {
+                    wakeup.notify();
+                }
             };
             if let Err(err) = ctrlc::set_handler(user_handler) {
                 log::error!("failed to set ctrlc handler {err}");
@@ -1262,17 +1507,41 @@ fn main() {
             }
         }
         let db_capnp = db.core();
+        if let Ok(path) = env::var("NODE_LOG_PATH") {
+            mina_recorder::node_log::spawn_tail(path.into(), db.core(), terminating.clone());
+        }
+        if let Ok(url) = env::var("NODE_GRAPHQL_URL") {
+            let interval = env::var("NODE_GRAPHQL_INTERVAL")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(10);
+            match url.parse() {
+                Ok(url) => {
+                    let interval = Duration::from_secs(interval);
+                    let terminating = terminating.clone();
+                    mina_recorder::node_status::spawn_poll(url, interval, db.core(), terminating);
+                }
+                Err(err) => log::error!("cannot parse NODE_GRAPHQL_URL={url}: {err}"),
+            }
+        }
+        if let Some(config) = mina_recorder::beacon::BeaconConfig::from_env() {
+            mina_recorder::beacon::spawn(config, db.core(), terminating.clone());
+        }
+        let registration = mina_recorder::policy::Registration::from_env();
+        mina_recorder::policy::spawn(registration, db.core(), terminating.clone());
+        mina_recorder::health::spawn(db.core(), terminating.clone());
+        mina_recorder::memory::spawn(db.core(), terminating.clone());
+        mina_recorder::verify::spawn(db.core(), terminating.clone());
+        mina_recorder::export::spawn(db.core(), terminating.clone());
+        let health = db.core();
 
         let test = env::var("TEST").is_ok();
 
-        let mut origin = proc::S::read().ok().and_then(|s| s.b_time);
-        if let Some(boot_time) = &origin {
-            log::info!("boot time: {boot_time:?}");
-        }
-
         let mut p2p_cns = BTreeMap::new();
         let counter = db.messages.clone();
         let mut pending_out_cns = BTreeMap::new();
+        // (pid, fd) -> the address the socket is bound to, until it listens
+        let mut bound = BTreeMap::new();
         let mut recorder = P2pRecorder::new(db, test);
         let mut watching = BTreeMap::new();
         let mut capnp_readers = BTreeMap::<_, CapnpReader>::new();
@@ -1290,6 +1559,12 @@ fn main() {
             let Some(event) = event else {
                 continue;
             };
+            if let Some(writer) = &mut replay_log {
+                if let Err(err) = writer.append(&event) {
+                    log::error!("cannot append to the replay log: {err}");
+                    replay_log = None;
+                }
+            }
 
             if buffered > max_buffered {
                 max_buffered = buffered;
@@ -1304,29 +1579,24 @@ fn main() {
                     event.ts1,
                     counter.load(Ordering::Relaxed)
                 );
+                health.health().on_unordered();
                 let max_unordered_ns = max_unordered_ns.entry(event.tid).or_default();
                 if unordered > *max_unordered_ns {
                     *max_unordered_ns = unordered;
                 }
             }
             last_ts.insert(event.tid, event.ts1);
-            let time = match &origin {
-                None => {
-                    let now = SystemTime::now();
-                    origin = Some(now - Duration::from_nanos(event.ts1));
-                    now
-                }
-                Some(origin) => *origin + Duration::from_nanos(event.ts1),
-            };
-            let better_time = {
+            recorder.on_key_injections();
+            let time = clock.time(event.ts1);
+            let better_time = if clock.is_recorded() {
+                // replaying, there is no lag
+                health.health().on_event(buffered, Duration::ZERO);
+                time
+            } else {
                 let instant_there = Duration::from_nanos(event.ts1);
-                let mut tp = libc::timespec {
-                    tv_sec: 0,
-                    tv_nsec: 0,
-                };
-                unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut tp) };
-                let instant_here = Duration::new(tp.tv_sec as _, tp.tv_nsec as _);
+                let instant_here = Duration::from_nanos(clock.now());
                 let delta = instant_here.checked_sub(instant_there).unwrap_or_default();
+                health.health().on_event(buffered, delta);
                 if delta >= max_lag + Duration::from_secs(60) {
                     max_lag = delta;
                     log::warn!("lagging: {delta:?}");
@@ -1338,9 +1608,16 @@ fn main() {
                 SnifferEventVariant::NewSnarkWorkerApp => {
                     snark_workers.insert(event.pid, SnarkWorkerState::default());
                 }
+                SnifferEventVariant::NewUnaliasedApp => {
+                    log::info!("exec without alias pid: {}, not recorded", event.pid);
+                }
                 SnifferEventVariant::NewApp(alias) => {
                     log::info!("exec {alias} pid: {}", event.pid);
                     recorder.on_alias(event.pid, alias);
+                    if auto_session {
+                        recorder.begin_session(event.pid, better_time);
+                        watch_tx.send(event.pid).unwrap_or_default();
+                    }
                     if !watching.contains_key(&event.pid) {
                         let version = env!("GIT_HASH");
                         watching.insert(
@@ -1357,7 +1634,21 @@ fn main() {
                     }
                 }
                 SnifferEventVariant::Bind(addr) => {
-                    recorder.set_port(event.pid, addr.port());
+                    // port 0, the kernel picks an ephemeral one, the node never listens on it
+                    if addr.port() != 0 {
+                        bound.insert((event.pid, event.fd), addr);
+                    }
+                }
+                SnifferEventVariant::Listen => {
+                    let Some(addr) = bound.remove(&(event.pid, event.fd)) else {
+                        continue;
+                    };
+                    if recorder.on_listen(event.pid, addr) {
+                        log::info!("pid: {} listens on {addr}", event.pid);
+                        if let Some(app_client) = &app_client {
+                            app_client.on_listen_port(addr.port());
+                        }
+                    }
                 }
                 SnifferEventVariant::OutgoingConnection(addr) => {
                     let metadata = EventMetadata {
@@ -1372,6 +1663,8 @@ fn main() {
                     };
 
                     log::info!("new unconfirmed {metadata}");
+                    // bound to the listening port to reuse it, but connects
+                    bound.remove(&(event.pid, event.fd));
                     pending_out_cns.insert((event.pid, event.fd), addr);
                 }
                 SnifferEventVariant::GetSockOpt(value) => {
@@ -1424,7 +1717,7 @@ fn main() {
                         recorder.on_disconnect(metadata, buffered);
                     }
                     log::info!("new outgoing connection {}", metadata);
-                    recorder.on_connect::<true>(
+                    recorder.on_connect::<false>(
                         false,
                         metadata,
                         buffered,
@@ -1465,7 +1758,7 @@ fn main() {
                         recorder.on_disconnect(metadata, buffered);
                     }
                     log::info!("new incoming connection {}", metadata);
-                    recorder.on_connect::<true>(
+                    recorder.on_connect::<false>(
                         true,
                         metadata,
                         buffered,
@@ -1497,7 +1790,32 @@ fn main() {
                         );
                     }
                 }
-                SnifferEventVariant::Error(_, -104) => {}
+                SnifferEventVariant::ProcessExit => {
+                    let keys = p2p_cns
+                        .keys()
+                        .filter(|(pid, _)| *pid == event.pid)
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    for (pid, fd) in keys {
+                        if let Some(addr) = p2p_cns.remove(&(pid, fd)) {
+                            let metadata = EventMetadata {
+                                id: ConnectionInfo { addr, pid, fd },
+                                time,
+                                better_time,
+                                duration,
+                            };
+                            log::info!("process exit, disconnected {metadata}");
+                            recorder.on_disconnect(metadata, buffered);
+                        }
+                    }
+                    bound.retain(|(pid, _), _| *pid != event.pid);
+                    log::info!("exit pid: {}", event.pid);
+                    recorder.end_session(event.pid, better_time);
+                }
+                SnifferEventVariant::Gap(skipped) => {
+                    log::error!("the ring buffer overflowed, {skipped} bytes of events are lost");
+                    health.health().on_gap(skipped);
+                }
                 SnifferEventVariant::Error(tag, code) => {
                     let key = (event.pid, event.fd);
                     if let Some(addr) = p2p_cns.get(&key) {
@@ -1512,7 +1830,12 @@ fn main() {
                             duration,
                         };
 
-                        log::error!("{metadata},  tag: {tag:?}, code: {code}");
+                        // connection reset by peer is usual
+                        if code != -104 {
+                            log::error!("{metadata},  tag: {tag:?}, code: {code}");
+                        }
+                        let syscall = format!("{tag:?}").to_lowercase();
+                        recorder.on_syscall_error(metadata, syscall, code.unsigned_abs());
                     }
                 }
                 SnifferEventVariant::IncomingData(data) => {
@@ -1727,9 +2050,431 @@ fn main() {
             };
             log::error!("join main thread error {msg}");
         }
-        app_client.terminate();
+        if let Some(app_client) = &app_client {
+            app_client.terminate();
+        }
+        if let Some(mut writer) = replay_log {
+            if let Err(err) = writer.flush() {
+                log::error!("cannot flush the replay log: {err}");
+            }
+        }
 
         log::info!("terminated");
+    };
+
+    if let Ok(path) = env::var("REPLAY") {
+        // feed the recorded events through the pipeline, no kernel module
+        let reader = match ReplayReader::open(&path) {
+            Ok(v) => v,
+            Err(err) => {
+                log::error!("cannot open the replay log {path}: {err}");
+                return;
+            }
+        };
+        log::info!(
+            "replay {path}, recorded with {:?} clock",
+            reader.clock_source
+        );
+        let clock = proc::ClockMapping::recorded(reader.clock_source, reader.offset);
+        let (tx, rx) = mpsc::sync_channel(0x1000);
+        let replay_thread = thread::spawn({
+            let terminating = terminating.clone();
+            move || {
+                let mut count = 0;
+                for event in reader {
+                    match event {
+                        Ok(event) => {
+                            count += 1;
+                            if tx.send((Some(event), 0)).is_err() {
+                                return;
+                            }
+                        }
+                        Err(err) => {
+                            log::error!("replay log {path}: {err}");
+                            break;
+                        }
+                    }
+                }
+                log::info!("replayed {count} events, serve the capture until ctrlc");
+                // keep the pipeline open until ctrlc
+                while !terminating.load(Ordering::SeqCst) {
+                    thread::sleep(Duration::from_millis(100));
+                }
+            }
+        });
+        consume(
+            rx,
+            replay_thread,
+            None,
+            clock,
+            None,
+            None,
+            None,
+            None,
+            terminating,
+        );
+        return;
+    }
+
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let tee_replay = match args.as_slice() {
+        [] => None,
+        [flag, path] if flag == "--replay" => Some(path.clone()),
+        [flag, map, out] if flag == "--salvage" => {
+            salvage(map, out);
+            return;
+        }
+        _ => {
+            log::error!(
+                "usage: bpf-recorder [--replay <ring buffer tee>] \
+                 [--salvage <pinned ring buffer> <ring buffer tee>]"
+            );
+            return;
+        }
+    };
+    if let Some(path) = tee_replay {
+        // feed the slices the ring buffer had through the pipeline, no kernel module
+        let mut reader = match File::open(&path) {
+            Ok(v) => TeeReader::new(BufReader::new(v)),
+            Err(err) => {
+                log::error!("cannot open the ring buffer tee {path}: {err}");
+                return;
+            }
+        };
+        log::info!("replay the ring buffer tee {path}");
+        let (tx, rx) = mpsc::sync_channel(0x1000);
+        let replay_thread = thread::spawn({
+            let terminating = terminating.clone();
+            move || {
+                let mut filter = PayloadFilter::default();
+                let (mut count, mut failures) = (0, 0);
+                loop {
+                    let slice = match reader.next_slice() {
+                        Ok(Some(v)) => v,
+                        Ok(None) => break,
+                        Err(err) => {
+                            log::error!("ring buffer tee {path}: {err}");
+                            break;
+                        }
+                    };
+                    // the same as the events read from the kernel
+                    let event = match SnifferEventRef::parse(slice) {
+                        Ok(event) => event
+                            .filter(|event| filter.wants(event))
+                            .and_then(|event| event.to_event()),
+                        Err(err) => {
+                            log::error!("ring buffer tee {path}: {err:?}");
+                            failures += 1;
+                            None
+                        }
+                    };
+                    if let Some(event) = event {
+                        count += 1;
+                        if tx.send((Some(event), 0)).is_err() {
+                            return;
+                        }
+                    }
+                }
+                log::info!(
+                    "replayed {count} events, {failures} cannot be parsed, \
+                     serve the capture until ctrlc"
+                );
+                while !terminating.load(Ordering::SeqCst) {
+                    thread::sleep(Duration::from_millis(100));
+                }
+            }
+        });
+        // the tee has no clock, the replay is meaningful on the host of the capture
+        let clock = proc::ClockMapping::new(clock_source);
+        consume(
+            rx,
+            replay_thread,
+            None,
+            clock,
+            None,
+            None,
+            None,
+            None,
+            terminating,
+        );
+        return;
+    }
+
+    static CODE: &[u8] = include_bytes!(concat!("../", env!("BPF_CODE_RECORDER")));
+
+    let mut skeleton = Skeleton::<App>::open("bpf-recorder\0", CODE)
+        .unwrap_or_else(|code| panic!("failed to open bpf: {}", code));
+    skeleton
+        .load()
+        .unwrap_or_else(|code| panic!("failed to load bpf: {}", code));
+
+    skeleton
+        .app
+        .whitelist
+        .insert([0; 16], [0, 0, 0, 1])
+        .unwrap();
+    skeleton
+        .app
+        .clock_source
+        .insert(0_u32.to_ne_bytes(), (clock_source as u32).to_ne_bytes())
+        .unwrap();
+
+    interface.push('\0');
+    let if_index = unsafe { libc::if_nametoindex(interface.as_ptr() as _) };
+
+    const XDP_FLAGS_SKB_MODE: u32 = 1 << 1;
+    skeleton
+        .attach_xdp("disable_connections", if_index as i32, XDP_FLAGS_SKB_MODE)
+        .unwrap();
+
+    let (skeleton, mut app) = skeleton
+        .attach()
+        .unwrap_or_else(|code| panic!("failed to attach bpf: {}", code));
+    log::info!("attached bpf module");
+
+    let fd = match app.event_queue.kind_mut() {
+        ebpf::kind::AppItemKindMut::Map(map) => map.fd(),
+        _ => unreachable!(),
+    };
+
+    let max_entries = map_max_entries(fd);
+    let pin = env::var("RB_PIN")
+        .ok()
+        .and_then(|path| pin_ring_buffer(fd, &path));
+    let rb_config = {
+        let default = RingBufferConfig::default();
+        let var = |name| env::var(name).ok().and_then(|s| s.parse::<u64>().ok());
+        RingBufferConfig {
+            read_budget_bytes: var("RB_READ_BUDGET")
+                .map_or(default.read_budget_bytes, |v| v as usize),
+            poll_timeout: var("RB_POLL_TIMEOUT_MS")
+                .map_or(default.poll_timeout, Duration::from_millis),
+            max_batch_events: var("RB_MAX_BATCH")
+                .map_or(default.max_batch_events, |v| (v as usize).max(1)),
+            commit_every: var("RB_COMMIT_EVERY")
+                .map_or(default.commit_every, |v| (v as usize).max(1)),
+        }
+    };
+    log::info!("{rb_config:?}");
+    let mut rb = match RingBuffer::with_config(fd, max_entries, rb_config) {
+        Ok(v) => v,
+        Err(err) => {
+            log::error!("failed to create userspace part of the ring buffer: {err}");
+            std::process::exit(1);
+        }
+    };
+
+    if let Ok(path) = env::var("RB_TEE") {
+        match File::create(&path) {
+            Ok(file) => {
+                log::info!("write the slices of the ring buffer to {path}");
+                rb.set_tee(BufWriter::new(file));
+            }
+            Err(err) => log::error!("cannot create the ring buffer tee {path}: {err}"),
+        }
+    }
+
+    let wakeup = match rb.wakeup_handle() {
+        Ok(v) => Some(v),
+        Err(err) => {
+            log::warn!("the shutdown waits for the poll timeout, no wakeup: {err}");
+            None
+        }
+    };
+
+    let pause = rb.pause_handle();
+
+    let (app_client, app_server) = application::new(
+        app.whitelist.clone(),
+        app.whitelist_ports.clone(),
+        app.blocked.clone(),
+    );
+
+    let (main_tx, main_rx) = mpsc::channel();
+    if auto_session {
+        watch_exit(watch_rx, main_tx.clone(), terminating.clone(), clock_source);
+    }
+    let alias_rules = match env::var("ALIAS_RULES") {
+        Ok(path) => match alias::AliasRules::load(&path) {
+            Ok(v) => {
+                log::info!("alias the processes without BPF_ALIAS by the rules {path}");
+                Some(v)
+            }
+            Err(err) => {
+                log::error!("cannot load the alias rules {path}: {err}");
+                None
+            }
+        },
+        Err(_) => None,
+    };
+    let (stats_tx, stats_rx) = mpsc::channel();
+    let main_thread = thread::spawn({
+        const STATS_INTERVAL: Duration = Duration::from_secs(5);
+
+        // the writer does not keep up long before the buffer is full
+        let lag_warn = env::var("RB_LAG_WARN_MS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .map_or(Duration::from_secs(1), Duration::from_millis);
+        let mut lag_max = 0;
+        let terminating = terminating.clone();
+        let mut pid_map = app.pid.clone();
+        let mut published = Instant::now();
+        let mut reported_parse_errors = 0;
+        let mut filter = PayloadFilter::default();
+        let mut events = vec![];
+        move || loop {
+            if published.elapsed() >= STATS_INTERVAL {
+                published = Instant::now();
+                let stats = rb.stats();
+                let parse_failures = rb.take_parse_failures();
+                if let Some(last) = parse_failures.last() {
+                    // likely the kernel module and the parser disagree on the layout of `Event`
+                    log::error!(
+                        "{} events cannot be parsed in {:?}, the layout of the event \
+                         might differ from the kernel module, the last: {last}",
+                        stats.parse_errors - reported_parse_errors,
+                        STATS_INTERVAL,
+                    );
+                }
+                reported_parse_errors = stats.parse_errors;
+                let lag = Duration::from_nanos(lag_max);
+                if lag > lag_warn {
+                    log::warn!(
+                        "the oldest unread event in the ring buffer is {lag:?} old, \
+                         the recorder does not keep up with the kernel"
+                    );
+                }
+                let status = RingBufferStatus {
+                    time: SystemTime::now(),
+                    fill_percent: stats.fill_percent,
+                    bytes_consumed: stats.bytes_consumed,
+                    events_consumed: stats.events_consumed,
+                    parse_errors: stats.parse_errors,
+                    parse_failures: parse_failures
+                        .into_iter()
+                        .map(|f| ParseFailure {
+                            offset: f.offset,
+                            length: f.length,
+                            error: f.error,
+                        })
+                        .collect(),
+                    largest_batch: stats.largest_batch,
+                    bytes_skipped: rb.skipped(),
+                    paused: rb.is_paused(),
+                    lag_ns: std::mem::take(&mut lag_max),
+                    bytes_per_second: 0.0,
+                    events_per_second: 0.0,
+                };
+                stats_tx.send(status).unwrap_or_default();
+            }
+            // copy only the payload the recorder handles
+            let mut collect = |slice: &[u8]| {
+                if let Some(event) = SnifferEventRef::parse(slice)? {
+                    if filter.wants(&event) {
+                        events.extend(event.to_event());
+                    }
+                }
+                Ok::<_, ErrorSliceTooShort>(())
+            };
+            let consumed = rb.consume_with(&terminating, &mut collect);
+            let (buffered, last) = match consumed {
+                Ok((_, buffered)) => (buffered, false),
+                Err(err) => match RingBufferOverflow::from_io(&err) {
+                    // drop the backlog and keep recording, the consumer records the gap
+                    Some(overflow) => {
+                        log::error!("{overflow}");
+                        let skipped = rb.resync();
+                        log::error!("skipped {skipped} bytes, {} in total", rb.skipped());
+                        let ts = proc::clock_now(clock_source);
+                        let event = SnifferEvent {
+                            pid: 0,
+                            tid: 0,
+                            fd: 0,
+                            ts0: ts,
+                            ts1: ts,
+                            variant: SnifferEventVariant::Gap(skipped as u64),
+                        };
+                        main_tx.send((Some(event), 0)).unwrap_or_default();
+                        continue;
+                    }
+                    // reading on would run out of the buffer, stop the capture instead
+                    None if RingBufferCorrupted::from_io(&err).is_some() => {
+                        log::error!("{err}, the kernel module does not match the recorder");
+                        terminating.store(true, Ordering::SeqCst);
+                        (0, true)
+                    }
+                    None => {
+                        if rb.is_paused() {
+                            log::warn!("paused, the events left in the ring buffer are lost");
+                        }
+                        // the terminating flag is set, take what the kernel already wrote
+                        let mut buffered = 0;
+                        while let Ok((count @ 1.., b)) = rb.try_consume_with(&mut collect) {
+                            log::info!("drained {count} events after the termination");
+                            buffered = b;
+                        }
+                        (buffered, true)
+                    }
+                },
+            };
+            if !last {
+                let now = proc::clock_now(clock_source);
+                if let Some(lag) = rb.lag(now, SnifferEventRef::peek_ts1) {
+                    lag_max = lag_max.max(lag);
+                }
+            }
+            for mut event in events.drain(..) {
+                // resolve the alias here, so the replay log has the resolved one
+                if let (SnifferEventVariant::NewUnaliasedApp, Some(rules)) =
+                    (&event.variant, &alias_rules)
+                {
+                    if let Some(alias) = rules.resolve(event.pid) {
+                        let pid = event.pid.to_ne_bytes();
+                        match pid_map.insert(pid, 0xffff_ffff_u32.to_ne_bytes()) {
+                            Ok(()) => event.variant = SnifferEventVariant::NewApp(alias),
+                            Err(err) => log::error!("cannot watch pid {}: {err:?}", event.pid),
+                        }
+                    }
+                }
+                main_tx.send((Some(event), buffered)).unwrap_or_default();
+            }
+            if last {
+                break;
+            }
+        }
+    });
+
+    let clock = proc::ClockMapping::new(clock_source);
+    let replay_log = match env::var("REPLAY_LOG") {
+        Ok(path) => match ReplayWriter::create(&path, clock_source, clock.offset()) {
+            Ok(v) => {
+                log::info!("append the events to the replay log {path}");
+                Some(v)
+            }
+            Err(err) => {
+                log::error!("cannot create the replay log {path}: {err}");
+                None
+            }
+        },
+        Err(_) => None,
+    };
+    let consumer_thread = thread::spawn({
+        let app_client = Some(app_client);
+        let terminating = terminating.clone();
+        move || {
+            consume(
+                main_rx,
+                main_thread,
+                app_client,
+                clock,
+                replay_log,
+                Some(stats_rx),
+                wakeup,
+                Some(pause),
+                terminating,
+            )
+        }
     });
 
     // blocking
@@ -1745,5 +2490,12 @@ fn main() {
         log::error!("join consumer thread error {msg}");
     }
 
+    // the events are consumed, nothing to salvage
+    if let Some(path) = pin {
+        if unsafe { libc::unlink(path.as_ptr()) } != 0 {
+            let err = io::Error::last_os_error();
+            log::error!("cannot unpin the ring buffer {path:?}: {err}");
+        }
+    }
     drop((skeleton, app));
 }
@@ -1,10 +1,12 @@
 use core::time::Duration;
 use std::{
-    time::SystemTime,
+    time::{SystemTime, Instant},
     io::{self, BufRead, Read},
     fs::File,
 };
 
+use super::ClockSource;
+
 /// Check whether the first command line argument matches the pattern
 pub fn cmd_prefix_matches(pid: u32, pattern: &str) -> io::Result<bool> {
     let mut tries = 5;
@@ -50,3 +52,98 @@ impl S {
         Ok(s)
     }
 }
+
+/// Current value of the clock in nanoseconds.
+pub fn clock_now(source: ClockSource) -> u64 {
+    let clock_id = match source {
+        ClockSource::Boottime => libc::CLOCK_BOOTTIME,
+        ClockSource::Monotonic => libc::CLOCK_MONOTONIC,
+        ClockSource::Tai => libc::CLOCK_TAI,
+    };
+    let mut tp = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(clock_id, &mut tp) };
+    Duration::new(tp.tv_sec as _, tp.tv_nsec as _).as_nanos() as u64
+}
+
+/// Maps the timestamps of the events to the real time.
+/// The offset between the real time and the clock changes when the system resumes
+/// from suspend (`monotonic`), migrates to another host or the real time is stepped,
+/// so it is sampled again every second.
+pub struct ClockMapping {
+    source: ClockSource,
+    // real time minus the clock, nanoseconds
+    offset: i64,
+    sampled: Instant,
+    recorded: bool,
+}
+
+impl ClockMapping {
+    const RESAMPLE: Duration = Duration::from_secs(1);
+
+    // smaller changes are the jitter of sampling two clocks one after another
+    const THRESHOLD_NS: i64 = 1_000_000;
+
+    pub fn new(source: ClockSource) -> Self {
+        ClockMapping {
+            source,
+            offset: Self::sample(source),
+            sampled: Instant::now(),
+            recorded: false,
+        }
+    }
+
+    /// The mapping at the time the replay log was written, it is never sampled again.
+    pub fn recorded(source: ClockSource, offset: i64) -> Self {
+        ClockMapping {
+            source,
+            offset,
+            sampled: Instant::now(),
+            recorded: true,
+        }
+    }
+
+    pub fn is_recorded(&self) -> bool {
+        self.recorded
+    }
+
+    /// Real time minus the clock, nanoseconds.
+    pub fn offset(&self) -> i64 {
+        self.offset
+    }
+
+    fn sample(source: ClockSource) -> i64 {
+        let real = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as i64;
+        real - clock_now(source) as i64
+    }
+
+    /// Current value of the clock in nanoseconds.
+    pub fn now(&self) -> u64 {
+        clock_now(self.source)
+    }
+
+    pub fn time(&mut self, ts: u64) -> SystemTime {
+        if !self.recorded && self.sampled.elapsed() >= Self::RESAMPLE {
+            let offset = Self::sample(self.source);
+            let shift = offset - self.offset;
+            if shift.abs() >= Self::THRESHOLD_NS {
+                if shift.abs() >= 1_000_000_000 {
+                    log::warn!(
+                        "{:?} clock shifted by {} ms relative to the real time",
+                        self.source,
+                        shift / 1_000_000
+                    );
+                }
+                self.offset = offset;
+            }
+            self.sampled = Instant::now();
+        }
+        let nanos = (ts as i64).saturating_add(self.offset).max(0);
+        SystemTime::UNIX_EPOCH + Duration::from_nanos(nanos as u64)
+    }
+}
@@ -0,0 +1,231 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    net::{IpAddr, SocketAddr},
+    path::Path,
+};
+
+use super::{
+    sniffer_event::{SnifferEvent, SnifferEventVariant},
+    ClockSource, DataTag,
+};
+
+const MAGIC: &[u8; 8] = b"minarply";
+const VERSION: u32 = 1;
+
+// pid, tid, fd, ts0, ts1 and the kind of the variant
+const RECORD_HEADER_SIZE: usize = 29;
+
+/// Appends the events, as the kernel module reports them, to the replay log.
+/// The log starts with the clock of the timestamps and its offset to the real time,
+/// each record is the event prefixed by its length.
+pub struct ReplayWriter {
+    inner: BufWriter<File>,
+    buf: Vec<u8>,
+}
+
+impl ReplayWriter {
+    /// `offset` is the real time minus the clock, nanoseconds.
+    pub fn create<P>(path: P, clock_source: ClockSource, offset: i64) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let mut inner = BufWriter::new(File::create(path)?);
+        inner.write_all(MAGIC)?;
+        inner.write_all(&VERSION.to_le_bytes())?;
+        inner.write_all(&(clock_source as u32).to_le_bytes())?;
+        inner.write_all(&offset.to_le_bytes())?;
+        Ok(ReplayWriter { inner, buf: vec![] })
+    }
+
+    pub fn append(&mut self, event: &SnifferEvent) -> io::Result<()> {
+        let buf = &mut self.buf;
+        buf.clear();
+        for v in [event.pid, event.tid, event.fd] {
+            buf.extend_from_slice(&v.to_le_bytes());
+        }
+        for v in [event.ts0, event.ts1] {
+            buf.extend_from_slice(&v.to_le_bytes());
+        }
+        match &event.variant {
+            SnifferEventVariant::NewApp(alias) => {
+                buf.push(0);
+                buf.extend_from_slice(alias.as_bytes());
+            }
+            SnifferEventVariant::NewSnarkWorkerApp => buf.push(1),
+            SnifferEventVariant::Bind(addr) => {
+                buf.push(2);
+                emit_addr(buf, addr);
+            }
+            SnifferEventVariant::IncomingConnection(addr) => {
+                buf.push(3);
+                emit_addr(buf, addr);
+            }
+            SnifferEventVariant::OutgoingConnection(addr) => {
+                buf.push(4);
+                emit_addr(buf, addr);
+            }
+            SnifferEventVariant::Disconnected => buf.push(5),
+            SnifferEventVariant::IncomingData(data) => {
+                buf.push(6);
+                buf.extend_from_slice(data);
+            }
+            SnifferEventVariant::OutgoingData(data) => {
+                buf.push(7);
+                buf.extend_from_slice(data);
+            }
+            SnifferEventVariant::Random(data) => {
+                buf.push(8);
+                buf.extend_from_slice(data);
+            }
+            SnifferEventVariant::GetSockOpt(data) => {
+                buf.push(9);
+                buf.extend_from_slice(data);
+            }
+            SnifferEventVariant::Error(tag, code) => {
+                buf.push(10);
+                buf.extend_from_slice(&(*tag as u32).to_le_bytes());
+                buf.extend_from_slice(&code.to_le_bytes());
+            }
+            SnifferEventVariant::ProcessExit => buf.push(11),
+            SnifferEventVariant::NewUnaliasedApp => buf.push(12),
+            SnifferEventVariant::Listen => buf.push(13),
+            SnifferEventVariant::Gap(skipped) => {
+                buf.push(14);
+                buf.extend_from_slice(&skipped.to_le_bytes());
+            }
+        }
+        self.inner.write_all(&(buf.len() as u32).to_le_bytes())?;
+        self.inner.write_all(buf)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn emit_addr(buf: &mut Vec<u8>, addr: &SocketAddr) {
+    match addr.ip() {
+        IpAddr::V4(ip) => buf.extend_from_slice(&ip.octets()),
+        IpAddr::V6(ip) => buf.extend_from_slice(&ip.octets()),
+    }
+    buf.extend_from_slice(&addr.port().to_le_bytes());
+}
+
+fn absorb_addr(payload: &[u8]) -> Option<SocketAddr> {
+    let (ip, port) = payload.split_at(payload.len().checked_sub(2)?);
+    let ip = match ip.len() {
+        4 => IpAddr::V4(<[u8; 4]>::try_from(ip).ok()?.into()),
+        16 => IpAddr::V6(<[u8; 16]>::try_from(ip).ok()?.into()),
+        _ => return None,
+    };
+    let addr = SocketAddr::new(ip, u16::from_le_bytes(port.try_into().ok()?));
+    // the logs recorded before the addresses were normalized
+    Some(mina_recorder::canonical_addr(addr))
+}
+
+/// Reads the events back from the replay log, the truncated last record,
+/// for example if the debugger was killed, ends the log.
+pub struct ReplayReader {
+    inner: BufReader<File>,
+    pub clock_source: ClockSource,
+    /// the real time minus the clock when the log was created, nanoseconds
+    pub offset: i64,
+    buf: Vec<u8>,
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl ReplayReader {
+    pub fn open<P>(path: P) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let mut inner = BufReader::new(File::open(path)?);
+        let mut header = [0; 24];
+        inner.read_exact(&mut header)?;
+        if &header[..8] != MAGIC {
+            return Err(invalid_data("not a replay log"));
+        }
+        let u32_at = |i: usize| u32::from_le_bytes(header[i..(i + 4)].try_into().unwrap());
+        if u32_at(8) != VERSION {
+            return Err(invalid_data("unsupported replay log version"));
+        }
+        Ok(ReplayReader {
+            inner,
+            clock_source: ClockSource::from_u32(u32_at(12)),
+            offset: i64::from_le_bytes(header[16..].try_into().unwrap()),
+            buf: vec![],
+        })
+    }
+
+    fn next_event(&mut self) -> io::Result<Option<SnifferEvent>> {
+        let mut len = [0; 4];
+        match self.inner.read_exact(&mut len) {
+            Ok(()) => (),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        }
+        self.buf.resize(u32::from_le_bytes(len) as usize, 0);
+        match self.inner.read_exact(&mut self.buf) {
+            Ok(()) => (),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                log::warn!("the replay log is truncated");
+                return Ok(None);
+            }
+            Err(err) => return Err(err),
+        }
+        absorb_event(&self.buf)
+            .map(Some)
+            .ok_or_else(|| invalid_data("malformed replay record"))
+    }
+}
+
+fn absorb_event(b: &[u8]) -> Option<SnifferEvent> {
+    if b.len() < RECORD_HEADER_SIZE {
+        return None;
+    }
+    let u32_at = |i: usize| u32::from_le_bytes(b[i..(i + 4)].try_into().unwrap());
+    let u64_at = |i: usize| u64::from_le_bytes(b[i..(i + 8)].try_into().unwrap());
+    let payload = &b[RECORD_HEADER_SIZE..];
+    let variant = match b[28] {
+        0 => SnifferEventVariant::NewApp(String::from_utf8(payload.to_vec()).ok()?),
+        1 => SnifferEventVariant::NewSnarkWorkerApp,
+        2 => SnifferEventVariant::Bind(absorb_addr(payload)?),
+        3 => SnifferEventVariant::IncomingConnection(absorb_addr(payload)?),
+        4 => SnifferEventVariant::OutgoingConnection(absorb_addr(payload)?),
+        5 => SnifferEventVariant::Disconnected,
+        6 => SnifferEventVariant::IncomingData(payload.to_vec()),
+        7 => SnifferEventVariant::OutgoingData(payload.to_vec()),
+        8 => SnifferEventVariant::Random(payload.to_vec()),
+        9 => SnifferEventVariant::GetSockOpt(payload.to_vec()),
+        10 if payload.len() == 8 => {
+            let tag = DataTag::from_u32(u32::from_le_bytes(payload[..4].try_into().ok()?))?;
+            let code = i32::from_le_bytes(payload[4..].try_into().ok()?);
+            SnifferEventVariant::Error(tag, code)
+        }
+        11 => SnifferEventVariant::ProcessExit,
+        12 => SnifferEventVariant::NewUnaliasedApp,
+        13 => SnifferEventVariant::Listen,
+        14 => SnifferEventVariant::Gap(u64::from_le_bytes(payload.try_into().ok()?)),
+        _ => return None,
+    };
+    Some(SnifferEvent {
+        pid: u32_at(0),
+        tid: u32_at(4),
+        fd: u32_at(8),
+        ts0: u64_at(12),
+        ts1: u64_at(20),
+        variant,
+    })
+}
+
+impl Iterator for ReplayReader {
+    type Item = io::Result<SnifferEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_event().transpose()
+    }
+}
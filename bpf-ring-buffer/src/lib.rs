@@ -1,10 +1,27 @@
 use std::{
-    fmt, io, mem,
+    fmt,
+    io::{self, Write},
+    mem,
     os::unix::io::AsRawFd,
     ptr, slice,
-    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
 
+mod set;
+pub use self::set::RingBufferSet;
+
+mod tee;
+pub use self::tee::TeeReader;
+
+#[cfg(feature = "async")]
+mod nonblocking;
+#[cfg(feature = "async")]
+pub use self::nonblocking::{AsyncRingBuffer, RingBufferStream};
+
 pub trait RingBufferData
 where
     Self: Sized,
@@ -14,19 +31,150 @@ where
     fn from_rb_slice(slice: &[u8]) -> Result<Option<Self>, Self::Error>;
 }
 
+/// The slice the parser rejected. The spike of them almost always means the layout
+/// of the producer and the parser of the consumer drifted apart.
+#[derive(Debug, Clone)]
+pub struct ParseFailure {
+    /// the position of the slice in the stream of the producer
+    pub offset: u64,
+    pub length: usize,
+    pub error: String,
+}
+
+impl fmt::Display for ParseFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cannot parse {} bytes at 0x{:x}: {}",
+            self.length, self.offset, self.error
+        )
+    }
+}
+
 pub enum Output<D> {
     Value(D),
     /// How many bytes are remaining in the ring buffer
     Remaining(usize),
 }
 
+/// The counters of the consumer, to see whether it keeps up with the producer.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RingBufferStats {
+    /// how full the buffer was after the last read
+    pub fill_percent: u8,
+    /// including the headers and the discarded slices
+    pub bytes_consumed: u64,
+    /// the slices parsed into values
+    pub events_consumed: u64,
+    pub parse_errors: u64,
+    /// the most values read in a row without waiting for the producer
+    pub largest_batch: usize,
+    /// how old the oldest unread record was when `lag` looked at it last time
+    pub lag_ns: u64,
+}
+
+/// How much the consumer reads at once and how long it waits for the producer.
+/// The default is what the consumer did before it was configurable.
+#[derive(Debug, Clone, Copy)]
+pub struct RingBufferConfig {
+    /// `read_batch_blocking` returns once it consumed this many bytes, even if more is ready
+    pub read_budget_bytes: usize,
+    /// how often the waiting consumer checks the terminating flag
+    pub poll_timeout: Duration,
+    /// `read_batch_blocking` returns at most this many values
+    pub max_batch_events: usize,
+    /// the batch reads store the position of the consumer for the kernel once per this many
+    /// values and once at the end of the batch, the store contends with the producer
+    pub commit_every: usize,
+}
+
+impl Default for RingBufferConfig {
+    fn default() -> Self {
+        RingBufferConfig {
+            read_budget_bytes: 0x100000,
+            poll_timeout: Duration::from_millis(50),
+            max_batch_events: 1024,
+            commit_every: 64,
+        }
+    }
+}
+
+/// Wakes up the consumer waiting for the producer, for example the ctrl-c handler
+/// sets the terminating flag and notifies, so the consumer does not wait for the timeout.
+#[derive(Clone)]
+pub struct WakeupHandle(Arc<EventFd>);
+
+struct EventFd(i32);
+
+impl WakeupHandle {
+    fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(WakeupHandle(Arc::new(EventFd(fd))))
+    }
+
+    pub fn notify(&self) {
+        let value = 1u64.to_ne_bytes();
+        unsafe { libc::write(self.0 .0, value.as_ptr() as *const _, value.len()) };
+    }
+
+    // the consumer is awake, the next wait blocks again
+    fn reset(&self) {
+        let mut value = [0; 8];
+        unsafe { libc::read(self.0 .0, value.as_mut_ptr() as *mut _, value.len()) };
+    }
+
+    fn fd(&self) -> i32 {
+        self.0 .0
+    }
+}
+
+/// Pauses and resumes the consumer from another thread, see `RingBuffer::pause`.
+#[derive(Clone, Default)]
+pub struct PauseHandle(Arc<AtomicBool>);
+
+impl PauseHandle {
+    pub fn pause(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for EventFd {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.0) };
+    }
+}
+
 pub struct RingBuffer {
     fd: i32,
+    // the `fd` itself, except for the simulated buffer in the tests
+    poll_fd: i32,
+    config: RingBufferConfig,
     mask: usize,
     consumer_pos_value: usize,
     // pointers to shared memory
     observer: RingBufferObserver,
     previous_distance: usize,
+    // total bytes skipped by `resync`
+    skipped: u64,
+    stats: RingBufferStats,
+    // values read since the last wait
+    batch: usize,
+    wakeup: Option<WakeupHandle>,
+    paused: PauseHandle,
+    tee: Option<Box<dyn Write + Send>>,
+    // the latest, see `take_parse_failures`
+    parse_failures: Vec<ParseFailure>,
 }
 
 impl AsRawFd for RingBuffer {
@@ -35,35 +183,205 @@ impl AsRawFd for RingBuffer {
     }
 }
 
+/// The memory mapped from the file, unmapped exactly once, when dropped.
+struct Mapping {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+// the mapping is shared with the kernel anyway, only the atomics are read or written
+unsafe impl Send for Mapping {}
+
+impl Mapping {
+    fn new(fd: i32, len: usize, prot: i32, offset: usize) -> io::Result<Self> {
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                prot,
+                libc::MAP_SHARED,
+                fd,
+                offset as libc::off_t,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Mapping { ptr, len })
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr, self.len) };
+    }
+}
+
 struct RingBufferObserver {
     page_size: usize,
-    data: Box<[AtomicUsize]>,
-    consumer_pos: Box<AtomicUsize>,
-    producer_pos: Box<AtomicUsize>,
+    // the consumer's page, the only mapping the consumer writes
+    consumer: Mapping,
+    // the producer's page followed by the data, mapped twice in a row
+    producer: Mapping,
     epfd: i32,
-    event: [epoll::Event; 1],
+    // the buffer and the wakeup
+    event: [epoll::Event; 2],
 }
 
 impl RingBufferObserver {
+    fn consumer_pos(&self) -> &AtomicUsize {
+        unsafe { &*(self.consumer.ptr as *const AtomicUsize) }
+    }
+
+    fn producer_pos(&self) -> &AtomicUsize {
+        unsafe { &*(self.producer.ptr as *const AtomicUsize) }
+    }
+
+    fn data_ptr(&self) -> *const u8 {
+        unsafe { (self.producer.ptr as *const u8).add(self.page_size) }
+    }
+
+    // the data as the `AtomicUsize` array, because we care only about the headers,
+    // which are sized and aligned by 8
+    fn data(&self) -> &[AtomicUsize] {
+        let length = self.len() / mem::size_of::<AtomicUsize>();
+        unsafe { slice::from_raw_parts(self.data_ptr() as *const AtomicUsize, length) }
+    }
+
     #[allow(clippy::len_without_is_empty)]
     fn len(&self) -> usize {
-        self.data.len() * mem::size_of::<AtomicUsize>()
+        self.producer.len - self.page_size
+    }
+
+    fn slice(&self, offset: usize, length: usize) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.data_ptr().add(offset), length) }
     }
 }
 
 impl AsRef<[u8]> for RingBufferObserver {
     fn as_ref(&self) -> &[u8] {
-        unsafe { slice::from_raw_parts(self.data.as_ptr() as *const u8, self.len()) }
+        self.slice(0, self.len())
     }
 }
 
 enum Error {
-    Overflown,
+    Overflown(usize),
+    Corrupted(RingBufferCorrupted),
     WouldBlock,
 }
 
+/// The producer is more than the whole buffer ahead of the consumer, the unread data
+/// is overwritten. The payload of the `io::Error` returned by `RingBuffer::read_blocking`.
+#[derive(Debug)]
+pub struct RingBufferOverflow {
+    /// how many bytes the producer is ahead
+    pub distance: usize,
+    pub capacity: usize,
+}
+
+impl fmt::Display for RingBufferOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ring buffer overflow, the producer is {} bytes ahead, the capacity is {}",
+            self.distance, self.capacity
+        )
+    }
+}
+
+impl std::error::Error for RingBufferOverflow {}
+
+impl RingBufferOverflow {
+    pub fn from_io(err: &io::Error) -> Option<&Self> {
+        err.get_ref()?.downcast_ref()
+    }
+}
+
+/// The header of the record is not consistent with the buffer, most likely the layout
+/// of the kernel side is different. The record would run past the data the producer wrote,
+/// or past the mapping, so it is not read, the consumer stays at it, `RingBuffer::resync`
+/// skips it. The payload of the `io::Error` returned by `RingBuffer::read_blocking`.
+#[derive(Debug, Clone)]
+pub struct RingBufferCorrupted {
+    /// the position of the record
+    pub position: usize,
+    /// the length in the header of the record
+    pub length: usize,
+    pub producer_pos: usize,
+    pub capacity: usize,
+}
+
+impl fmt::Display for RingBufferCorrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "corrupted ring buffer, the record at {} has the length {}, \
+             the producer is at {}, the capacity is {}",
+            self.position, self.length, self.producer_pos, self.capacity
+        )
+    }
+}
+
+impl std::error::Error for RingBufferCorrupted {}
+
+impl RingBufferCorrupted {
+    pub fn from_io(err: &io::Error) -> Option<&Self> {
+        err.get_ref()?.downcast_ref()
+    }
+
+    fn into_io(self) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, self)
+    }
+}
+
 impl RingBuffer {
+    // the epoll data of the wakeup fd, the buffer is 1
+    const WAKEUP: u64 = 2;
+
+    /// `take_parse_failures` returns at most this many, the older are only counted.
+    pub const MAX_PARSE_FAILURES: usize = 64;
+
+    /// `lag` shows the timestamp reader at most this many bytes of the record.
+    pub const LAG_PEEK: usize = 32;
+
+    // the first 8 bytes of the memory slice is a header (length and flags)
+    const HEADER_SIZE: usize = 8;
+    const BUSY_BIT: usize = 1 << 31;
+    const DISCARD_BIT: usize = 1 << 30;
+
     pub fn new(fd: i32, max_length: usize) -> io::Result<Self> {
+        Self::with_config(fd, max_length, RingBufferConfig::default())
+    }
+
+    pub fn with_config(fd: i32, max_length: usize, config: RingBufferConfig) -> io::Result<Self> {
+        Self::open(fd, fd, max_length, config)
+    }
+
+    /// Opens the buffer another consumer read before, like the pinned map of the recorder
+    /// which crashed, and continues from the position that consumer committed. Unlike
+    /// `with_config`, the position far behind the producer is not the overflow: the data
+    /// is lost already, it is skipped and counted in `skipped`.
+    pub fn attach(fd: i32, max_length: usize, config: RingBufferConfig) -> io::Result<Self> {
+        Self::open(fd, fd, max_length, config).map(Self::continue_committed)
+    }
+
+    fn continue_committed(mut self) -> Self {
+        self.consumer_pos_value = self.observer.consumer_pos().load(Ordering::Acquire);
+        let pr_pos = self.observer.producer_pos().load(Ordering::Acquire);
+        if pr_pos.saturating_sub(self.consumer_pos_value) > self.mask + 1 {
+            let skipped = self.resync();
+            log::warn!("the consumer is {skipped} bytes behind the producer, skip to it");
+        }
+        self
+    }
+
+    // the `poll_fd` is the `fd` itself, except for the simulated buffer in the tests
+    fn open(
+        fd: i32,
+        poll_fd: i32,
+        max_length: usize,
+        config: RingBufferConfig,
+    ) -> io::Result<Self> {
         debug_assert_eq!(max_length & (max_length - 1), 0);
 
         // The layout is:
@@ -76,50 +394,13 @@ impl RingBuffer {
         // consumers page, currently contains only one integer value,
         // offset where consumer should read;
         // map it read/write
-        let consumer_pos = unsafe {
-            let p = libc::mmap(
-                ptr::null_mut(),
-                page_size,
-                libc::PROT_READ | libc::PROT_WRITE,
-                libc::MAP_SHARED,
-                fd,
-                0,
-            );
-            if p == libc::MAP_FAILED {
-                return Err(io::Error::last_os_error());
-            }
-
-            Box::from_raw(p as *mut AtomicUsize)
-        };
+        let consumer = Mapping::new(fd, page_size, libc::PROT_READ | libc::PROT_WRITE, 0)?;
 
         // producers page and the buffer itself,
         // currently producers page contains only one integer value,
         // offset where producer has wrote, or still writing;
-        // let's refer buffer data as a slice of `AtomicUsize` array
-        // because we care only about data headers which sized and aligned by 8;
-        // map it read only
-        let (producer_pos, data) = unsafe {
-            let p = libc::mmap(
-                ptr::null_mut(),
-                page_size + max_length * 2,
-                libc::PROT_READ,
-                libc::MAP_SHARED,
-                fd,
-                page_size as i64,
-            );
-            if p == libc::MAP_FAILED {
-                libc::munmap(Box::into_raw(consumer_pos) as *mut _, page_size);
-                return Err(io::Error::last_os_error());
-            }
-
-            let length = max_length * 2 / mem::size_of::<AtomicUsize>();
-            let q = (p as usize) + page_size;
-            let q = slice::from_raw_parts_mut(q as *mut AtomicUsize, length);
-            (
-                Box::from_raw(p as *mut AtomicUsize),
-                Box::from_raw(q as *mut [AtomicUsize]),
-            )
-        };
+        // map it read only, the consumer's page is unmapped if it fails
+        let producer = Mapping::new(fd, page_size + max_length * 2, libc::PROT_READ, page_size)?;
 
         log::info!(
             "new RingBuffer: fd: {}, page_size: 0x{:016x}, mask: 0x{:016x}",
@@ -128,29 +409,205 @@ impl RingBuffer {
             max_length - 1
         );
         let event = epoll::Event::new(epoll::Events::EPOLLIN, 1);
+        let observer = RingBufferObserver {
+            page_size,
+            consumer,
+            producer,
+            epfd: epoll::create(true)?,
+            event: [event; 2],
+        };
+        // the observer closes the epoll and unmaps the memory if it fails
+        epoll::ctl(
+            observer.epfd,
+            epoll::ControlOptions::EPOLL_CTL_ADD,
+            poll_fd,
+            event,
+        )?;
+        let epoll::Event { events, data } = event;
+        assert_eq!(events, epoll::Events::EPOLLIN.bits());
+        assert_eq!(data, 1);
         Ok(RingBuffer {
             fd,
+            poll_fd,
+            config,
             mask: max_length - 1,
             consumer_pos_value: 0,
-            observer: RingBufferObserver {
-                page_size,
-                data,
-                consumer_pos,
-                producer_pos,
-                epfd: {
-                    let epfd = epoll::create(true)?;
-                    epoll::ctl(epfd, epoll::ControlOptions::EPOLL_CTL_ADD, fd, event)?;
-                    let epoll::Event { events, data } = event;
-                    assert_eq!(events, epoll::Events::EPOLLIN.bits());
-                    assert_eq!(data, 1);
-                    epfd
-                },
-                event: [event],
-            },
+            observer,
             previous_distance: 0,
+            skipped: 0,
+            stats: RingBufferStats::default(),
+            batch: 0,
+            wakeup: None,
+            paused: PauseHandle::default(),
+            tee: None,
+            parse_failures: vec![],
         })
     }
 
+    /// Skip the unread data after the overflow, continue from the position of the producer.
+    /// Returns how many bytes are skipped.
+    pub fn resync(&mut self) -> usize {
+        let pr_pos = self.observer.producer_pos().load(Ordering::Acquire);
+        let skipped = pr_pos.saturating_sub(self.consumer_pos_value);
+        self.consumer_pos_value = self.consumer_pos_value.max(pr_pos);
+        self.previous_distance = 0;
+        self.skipped += skipped as u64;
+        self.read_finish();
+        skipped
+    }
+
+    /// Total bytes skipped after the overflows.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    pub fn stats(&self) -> RingBufferStats {
+        self.stats
+    }
+
+    /// How old the oldest unread record is: `now` minus the timestamp which `timestamp`
+    /// takes from the first [`Self::LAG_PEEK`] bytes of the record, both of the same clock.
+    /// Nothing is consumed. `Some(0)` if nothing is unread, `None` if the record is being
+    /// written, the buffer is overflown or corrupted, or `timestamp` finds nothing.
+    /// The lag is kept in the stats as well.
+    pub fn lag<F>(&mut self, now: u64, mut timestamp: F) -> Option<u64>
+    where
+        F: FnMut(&[u8]) -> Option<u64>,
+    {
+        let pr_pos = self.observer.producer_pos().load(Ordering::Acquire);
+        let mut pos = self.consumer_pos_value;
+        // the discarded records are skipped, like the reader does
+        let lag = loop {
+            if pos >= pr_pos {
+                break 0;
+            }
+            let distance = pr_pos - pos;
+            if distance > self.mask + 1 {
+                // the reader reports it
+                return None;
+            }
+            let masked_pos = pos & self.mask;
+            let index_in_array = masked_pos / mem::size_of::<AtomicUsize>();
+            let header = self.observer.data()[index_in_array].load(Ordering::Acquire) & 0xffffffff;
+            if header & Self::BUSY_BIT != 0 {
+                return None;
+            }
+            let length = header & !Self::DISCARD_BIT;
+            let size = Self::HEADER_SIZE + (length + 7) / 8 * 8;
+            let data_offset = masked_pos + Self::HEADER_SIZE;
+            if length > self.mask + 1
+                || size > distance
+                || data_offset + length > self.observer.len()
+            {
+                return None;
+            }
+            if header & Self::DISCARD_BIT == 0 {
+                let slice = self.observer.slice(data_offset, length.min(Self::LAG_PEEK));
+                break now.saturating_sub(timestamp(slice)?);
+            }
+            pos += size;
+        };
+        self.stats.lag_ns = lag;
+        Some(lag)
+    }
+
+    pub fn config(&self) -> &RingBufferConfig {
+        &self.config
+    }
+
+    /// The handle to interrupt the wait of the blocking reads, the reads return
+    /// once they see the terminating flag. The wakeup fd is created by the first call.
+    pub fn wakeup_handle(&mut self) -> io::Result<WakeupHandle> {
+        if let Some(wakeup) = &self.wakeup {
+            return Ok(wakeup.clone());
+        }
+        let wakeup = WakeupHandle::new()?;
+        let event = epoll::Event::new(epoll::Events::EPOLLIN, Self::WAKEUP);
+        epoll::ctl(
+            self.observer.epfd,
+            epoll::ControlOptions::EPOLL_CTL_ADD,
+            wakeup.fd(),
+            event,
+        )?;
+        self.wakeup = Some(wakeup.clone());
+        Ok(wakeup)
+    }
+
+    /// Stops consuming, the position of the consumer stays where it is, so the kernel keeps
+    /// the unread events until `resume`, or drops the new ones once the buffer is full.
+    /// Meanwhile the reads return nothing, the blocking ones after the poll timeout,
+    /// but they still watch the fill level and report the overflow.
+    pub fn pause(&self) {
+        self.paused.pause();
+    }
+
+    /// Continues from where the consumer paused.
+    pub fn resume(&self) {
+        self.paused.resume();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.is_paused()
+    }
+
+    /// The handle to pause and resume from another thread, for example from the api.
+    pub fn pause_handle(&self) -> PauseHandle {
+        self.paused.clone()
+    }
+
+    /// Writes every slice which is not discarded to `w` before it is parsed or handed
+    /// to the callback, `TeeReader` reads them back. The first error of `w` stops the tee.
+    pub fn set_tee<W>(&mut self, w: W)
+    where
+        W: Write + Send + 'static,
+    {
+        self.tee = Some(Box::new(w));
+    }
+
+    /// Stops the tee and returns the writer, for example to flush it.
+    pub fn take_tee(&mut self) -> Option<Box<dyn Write + Send>> {
+        self.tee.take()
+    }
+
+    fn tee(&mut self, offset: usize, length: usize) {
+        if let Some(w) = &mut self.tee {
+            if let Err(err) = tee::write_record(w, self.observer.slice(offset, length)) {
+                log::error!("cannot write the tee, stop it: {err}");
+                self.tee = None;
+            }
+        }
+    }
+
+    /// The slices rejected by the parser since the last call, the latest
+    /// `MAX_PARSE_FAILURES` of them, `stats` counts all of them.
+    pub fn take_parse_failures(&mut self) -> Vec<ParseFailure> {
+        mem::take(&mut self.parse_failures)
+    }
+
+    fn on_parse_failure<E>(&mut self, offset: u64, length: usize, error: E)
+    where
+        E: fmt::Debug,
+    {
+        let failure = ParseFailure {
+            offset,
+            length,
+            error: format!("{error:?}"),
+        };
+        log::debug!("{failure}");
+        self.stats.parse_errors += 1;
+        if self.parse_failures.len() == Self::MAX_PARSE_FAILURES {
+            self.parse_failures.remove(0);
+        }
+        self.parse_failures.push(failure);
+    }
+
+    fn poll_timeout_ms(&self) -> i32 {
+        self.config
+            .poll_timeout
+            .as_millis()
+            .clamp(1, i32::MAX as u128) as i32
+    }
+
     fn read_value<D>(&mut self) -> Result<(Option<D>, usize), Error>
     where
         D: RingBufferData,
@@ -164,7 +621,7 @@ impl RingBuffer {
 
     fn read_finish(&mut self) {
         self.observer
-            .consumer_pos
+            .consumer_pos()
             .store(self.consumer_pos_value, Ordering::Release);
     }
 
@@ -172,16 +629,36 @@ impl RingBuffer {
     where
         D: RingBufferData,
     {
-        const HEADER_SIZE: usize = 8;
-        const BUSY_BIT: usize = 1 << 31;
-        const DISCARD_BIT: usize = 1 << 30;
+        let position = self.consumer_pos_value as u64;
+        let (slice, distance) = self.next_slice()?;
+        let (offset, length) = match slice {
+            Some(v) => v,
+            None => return Ok((None, distance)),
+        };
+        self.tee(offset, length);
+        match D::from_rb_slice(self.observer.slice(offset, length)) {
+            Err(err) => {
+                self.on_parse_failure(position, length, err);
+                Ok((None, distance))
+            }
+            Ok(None) => Ok((None, distance)),
+            Ok(Some(value)) => {
+                self.stats.events_consumed += 1;
+                Ok((Some(value), distance))
+            }
+        }
+    }
 
-        let pr_pos = self.observer.producer_pos.load(Ordering::Acquire);
+    /// Advances the position of the consumer in memory only, the caller stores it
+    /// once it is done with the slice. Returns the offset and the length of the slice,
+    /// `None` if the slice is discarded, and how many bytes are remaining after it.
+    fn next_slice(&mut self) -> Result<(Option<(usize, usize)>, usize), Error> {
+        let pr_pos = self.observer.producer_pos().load(Ordering::Acquire);
         if self.consumer_pos_value < pr_pos {
             // determine how far we are, how many unseen data is in the buffer
             let distance = pr_pos - self.consumer_pos_value;
             if distance > self.mask + 1 {
-                return Err(Error::Overflown);
+                return Err(Error::Overflown(distance));
             }
             if distance > self.previous_distance {
                 let percent = distance * 100 / (self.mask + 1);
@@ -191,21 +668,43 @@ impl RingBuffer {
                 }
             }
             self.previous_distance = distance;
-            // the first 8 bytes of the memory slice is a header (length and flags)
+            if self.is_paused() {
+                // consume nothing, only watch the fill level
+                self.stats.fill_percent = (distance * 100 / (self.mask + 1)) as u8;
+                return Err(Error::WouldBlock);
+            }
             let (header, data_offset) = {
                 let masked_pos = self.consumer_pos_value & self.mask;
                 let index_in_array = masked_pos / mem::size_of::<AtomicUsize>();
-                let header = self.observer.data[index_in_array].load(Ordering::Acquire);
+                let header = self.observer.data()[index_in_array].load(Ordering::Acquire);
                 // keep only 32 bits
-                (header & 0xffffffff, masked_pos + HEADER_SIZE)
+                (header & 0xffffffff, masked_pos + Self::HEADER_SIZE)
             };
 
-            if header & BUSY_BIT != 0 {
+            if header & Self::BUSY_BIT != 0 {
                 // nothing to read, kernel is writing to this slice right now
                 return Err(Error::WouldBlock);
             }
 
-            let (length, discard) = (header & !DISCARD_BIT, (header & DISCARD_BIT) != 0);
+            let (length, discard) = (
+                header & !Self::DISCARD_BIT,
+                (header & Self::DISCARD_BIT) != 0,
+            );
+
+            // the kernel advances the producer past the whole record before it clears
+            // the busy bit, so the record is never ahead of the producer
+            let size = Self::HEADER_SIZE + (length + 7) / 8 * 8;
+            if length > self.mask + 1
+                || size > distance
+                || data_offset + length > self.observer.len()
+            {
+                return Err(Error::Corrupted(RingBufferCorrupted {
+                    position: self.consumer_pos_value,
+                    length,
+                    producer_pos: pr_pos,
+                    capacity: self.mask + 1,
+                }));
+            }
 
             if !discard {
                 let c_pos = self.consumer_pos_value;
@@ -220,48 +719,45 @@ impl RingBuffer {
                 // }
             }
 
-            // align the length by 8, and advance our position
-            self.consumer_pos_value += HEADER_SIZE + (length + 7) / 8 * 8;
+            // advance our position by the length aligned by 8
+            self.consumer_pos_value += size;
             let distance = pr_pos - self.consumer_pos_value;
+            self.stats.bytes_consumed += size as u64;
+            self.stats.fill_percent = (distance * 100 / (self.mask + 1)) as u8;
 
-            if !discard {
-                // if not discard, yield the slice
-                let s = unsafe {
-                    slice::from_raw_parts(
-                        ((self.observer.data.as_ptr() as usize) + data_offset) as *mut u8,
-                        length,
-                    )
-                };
-                match D::from_rb_slice(s) {
-                    Err(err) => {
-                        log::error!("rb parse data: {:?}", err);
-                        Ok((None, distance))
-                    }
-                    Ok(None) => Ok((None, distance)),
-                    Ok(Some(value)) => Ok((Some(value), distance)),
-                }
-            } else {
-                Ok((None, distance))
-            }
+            // if not discard, yield the slice
+            Ok(((!discard).then(|| (data_offset, length)), distance))
         } else {
             Err(Error::WouldBlock)
         }
     }
 
     fn wait_epoll(&mut self, terminating: &AtomicBool) {
+        let timeout = self.poll_timeout_ms();
         while !terminating.load(Ordering::SeqCst) {
             self.observer.event[0].events = 0;
-            match epoll::wait(self.observer.epfd, 50, &mut self.observer.event) {
+            match epoll::wait(self.observer.epfd, timeout, &mut self.observer.event) {
                 Ok(0) => log::debug!("ringbuf wait timeout"),
-                Ok(1) => {
-                    let e = self.observer.event[0].events;
-                    if e & epoll::Events::EPOLLIN.bits() != 0 {
+                Ok(r @ (1 | 2)) => {
+                    let mut ready = false;
+                    for event in &self.observer.event[..r] {
+                        let (e, data) = (event.events, event.data);
+                        if data == Self::WAKEUP {
+                            if let Some(wakeup) = &self.wakeup {
+                                wakeup.reset();
+                            }
+                            ready = true;
+                        } else if e & epoll::Events::EPOLLIN.bits() != 0 {
+                            ready = true;
+                        } else {
+                            log::warn!("unexpected event {e}");
+                        }
+                    }
+                    if ready {
                         break;
-                    } else {
-                        log::warn!("unexpected event {e}");
                     }
                 }
-                // poll should not return bigger then number of fds, we have 1
+                // poll should not return bigger then number of fds, we have 2
                 Ok(r) => log::error!("ringbuf poll {}", r),
                 Err(error) => {
                     if io::ErrorKind::Interrupted != error.kind() {
@@ -274,18 +770,55 @@ impl RingBuffer {
         }
     }
 
+    // the unread events keep the buffer readable, so the paused consumer sleeps
+    // for the poll timeout instead, only the wakeup interrupts it
+    fn wait_paused(&self, terminating: &AtomicBool) {
+        if terminating.load(Ordering::SeqCst) {
+            return;
+        }
+        // the negative fd is ignored
+        let wakeup = self.wakeup.as_ref().map_or(-1, WakeupHandle::fd);
+        let mut fds = [libc::pollfd {
+            fd: wakeup,
+            events: libc::POLLIN,
+            revents: 0,
+        }];
+        unsafe { libc::poll(fds.as_mut_ptr(), 1, self.poll_timeout_ms()) };
+        if fds[0].revents & libc::POLLIN != 0 {
+            if let Some(wakeup) = &self.wakeup {
+                wakeup.reset();
+            }
+        }
+    }
+
+    // the bytes the consumer has not read yet
+    fn unread(&self) -> usize {
+        let pr_pos = self.observer.producer_pos().load(Ordering::Acquire);
+        pr_pos.saturating_sub(self.consumer_pos_value)
+    }
+
     #[allow(dead_code)]
     fn wait(&self, terminating: &AtomicBool) {
-        let mut fds = libc::pollfd {
-            fd: self.as_raw_fd(),
+        let pollfd = |fd| libc::pollfd {
+            fd,
             events: libc::POLLIN,
             revents: 0,
         };
+        // the negative fd is ignored
+        let wakeup = self.wakeup.as_ref().map_or(-1, WakeupHandle::fd);
+        let mut fds = [pollfd(self.as_raw_fd()), pollfd(wakeup)];
+        let timeout = self.poll_timeout_ms();
         while !terminating.load(Ordering::SeqCst) {
-            match unsafe { libc::poll(&mut fds, 1, 1_000) } {
+            match unsafe { libc::poll(fds.as_mut_ptr(), 2, timeout) } {
                 0 => log::debug!("ringbuf wait timeout"),
-                1 => {
-                    if fds.revents & libc::POLLIN != 0 {
+                1..=2 => {
+                    if fds[1].revents & libc::POLLIN != 0 {
+                        if let Some(wakeup) = &self.wakeup {
+                            wakeup.reset();
+                        }
+                        break;
+                    }
+                    if fds[0].revents & libc::POLLIN != 0 {
                         break;
                     }
                 }
@@ -297,13 +830,15 @@ impl RingBuffer {
                         log::error!("inerrupted: {error:?}");
                     }
                 }
-                // poll should not return bigger then number of fds, we have 1
-                r @ 2..=i32::MAX => log::error!("ringbuf poll {}", r),
+                // poll should not return bigger then number of fds, we have 2
+                r @ 3..=i32::MAX => log::error!("ringbuf poll {}", r),
             }
-            fds.revents = 0;
+            fds[0].revents = 0;
+            fds[1].revents = 0;
         }
     }
 
+    /// Returns nothing after the poll timeout if the consumer is paused.
     pub fn read_blocking<D>(&mut self, terminating: &AtomicBool) -> io::Result<(Option<D>, usize)>
     where
         D: RingBufferData,
@@ -313,34 +848,993 @@ impl RingBuffer {
             if tries > 10 {
                 log::debug!("cannot read ring buffer: {} attempts", tries);
             }
-            match self.read_value() {
-                Err(Error::WouldBlock) => {
-                    self.wait_epoll(terminating);
+            match self.try_read()? {
+                None if self.is_paused() => {
+                    self.wait_paused(terminating);
                     if terminating.load(Ordering::SeqCst) {
                         break Err(io::Error::new(io::ErrorKind::Other, "terminate"));
                     }
+                    return Ok((None, self.unread()));
                 }
-                Err(Error::Overflown) => {
-                    return Err(io::Error::new(io::ErrorKind::Other, "overflow"));
+                None => {
+                    self.wait_epoll(terminating);
+                    if terminating.load(Ordering::SeqCst) {
+                        break Err(io::Error::new(io::ErrorKind::Other, "terminate"));
+                    }
                 }
-                Ok(value) => return Ok(value),
+                Some(value) => return Ok(value),
             }
             tries += 1;
         }
     }
+
+    /// Wait for the values, then read them until nothing is left now or the budget
+    /// of the config is spent. Returns the values and how many bytes were remaining after
+    /// the last one. The overflow is reported once the values before it are taken.
+    /// The batch is empty if the consumer is paused.
+    pub fn read_batch_blocking<D>(
+        &mut self,
+        terminating: &AtomicBool,
+    ) -> io::Result<(Vec<D>, usize)>
+    where
+        D: RingBufferData,
+    {
+        loop {
+            let mut batch = vec![];
+            let (_, remaining) =
+                self.read_into(self.config.max_batch_events, |value| batch.push(value))?;
+            if !batch.is_empty() {
+                return Ok((batch, remaining));
+            }
+            if self.is_paused() {
+                self.wait_paused(terminating);
+                if terminating.load(Ordering::SeqCst) {
+                    return Err(io::Error::new(io::ErrorKind::Other, "terminate"));
+                }
+                return Ok((batch, self.unread()));
+            }
+            self.wait_epoll(terminating);
+            if terminating.load(Ordering::SeqCst) {
+                return Err(io::Error::new(io::ErrorKind::Other, "terminate"));
+            }
+        }
+    }
+
+    /// Read the values that are ready, at most `limit` and at most the budget of the config.
+    /// Returns how many values `push` got and how many bytes were remaining after the last one.
+    fn read_into<D, F>(&mut self, limit: usize, mut push: F) -> io::Result<(usize, usize)>
+    where
+        D: RingBufferData,
+        F: FnMut(D),
+    {
+        let mut count = 0;
+        let mut remaining = 0;
+        let start = self.stats.bytes_consumed;
+        while count < limit
+            && self.stats.bytes_consumed - start < self.config.read_budget_bytes as u64
+        {
+            match self.read_slice() {
+                Ok((value, r)) => {
+                    remaining = r;
+                    if let Some(value) = value {
+                        push(value);
+                        self.on_batch();
+                        count += 1;
+                        if count % self.config.commit_every.max(1) == 0 {
+                            self.read_finish();
+                        }
+                    }
+                }
+                Err(Error::WouldBlock) => {
+                    self.batch = 0;
+                    break;
+                }
+                // the overflow or the corruption stays, the next call reports it
+                Err(Error::Overflown(_) | Error::Corrupted(_)) if count != 0 => break,
+                Err(Error::Overflown(distance)) => return Err(self.overflow(distance)),
+                Err(Error::Corrupted(corrupted)) => return Err(corrupted.into_io()),
+            }
+        }
+        // the values are taken, release their space, and the discarded slices too
+        self.read_finish();
+        Ok((count, remaining))
+    }
+
+    /// Read without waiting, `None` if there is nothing to read now.
+    /// The consumer position is advanced before the value is returned.
+    pub fn try_read<D>(&mut self) -> io::Result<Option<(Option<D>, usize)>>
+    where
+        D: RingBufferData,
+    {
+        match self.read_value() {
+            Err(Error::WouldBlock) => {
+                self.batch = 0;
+                Ok(None)
+            }
+            Err(Error::Overflown(distance)) => Err(self.overflow(distance)),
+            Err(Error::Corrupted(corrupted)) => Err(corrupted.into_io()),
+            Ok(value) => {
+                if value.0.is_some() {
+                    self.on_batch();
+                }
+                Ok(Some(value))
+            }
+        }
+    }
+
+    /// Like `read_batch_blocking`, but hands the raw slices to `f` instead of parsing
+    /// and copying them. The slice is valid only in `f`, the consumer position is stored
+    /// for the kernel only after `f` returns, so the kernel does not overwrite the slice
+    /// meanwhile, once per `commit_every` slices and at the end of the batch.
+    /// The error of `f` is the parse failure, see `take_parse_failures`.
+    /// Returns how many slices `f` got and how many bytes were remaining after the last one,
+    /// none of them if the consumer is paused.
+    pub fn consume_with<F, E>(
+        &mut self,
+        terminating: &AtomicBool,
+        mut f: F,
+    ) -> io::Result<(usize, usize)>
+    where
+        F: FnMut(&[u8]) -> Result<(), E>,
+        E: fmt::Debug,
+    {
+        loop {
+            let (count, remaining) = self.try_consume_with(&mut f)?;
+            if count != 0 {
+                return Ok((count, remaining));
+            }
+            if self.is_paused() {
+                self.wait_paused(terminating);
+                if terminating.load(Ordering::SeqCst) {
+                    return Err(io::Error::new(io::ErrorKind::Other, "terminate"));
+                }
+                return Ok((0, self.unread()));
+            }
+            self.wait_epoll(terminating);
+            if terminating.load(Ordering::SeqCst) {
+                return Err(io::Error::new(io::ErrorKind::Other, "terminate"));
+            }
+        }
+    }
+
+    /// Like `consume_with`, but returns at once if nothing is ready, for example
+    /// to drain the ring buffer after the terminating flag is set.
+    pub fn try_consume_with<F, E>(&mut self, mut f: F) -> io::Result<(usize, usize)>
+    where
+        F: FnMut(&[u8]) -> Result<(), E>,
+        E: fmt::Debug,
+    {
+        let mut count = 0;
+        let mut remaining = 0;
+        let start = self.stats.bytes_consumed;
+        while count < self.config.max_batch_events
+            && self.stats.bytes_consumed - start < self.config.read_budget_bytes as u64
+        {
+            let position = self.consumer_pos_value as u64;
+            match self.next_slice() {
+                Ok((Some((offset, length)), r)) => {
+                    remaining = r;
+                    self.tee(offset, length);
+                    match f(self.observer.slice(offset, length)) {
+                        Ok(()) => self.stats.events_consumed += 1,
+                        Err(err) => self.on_parse_failure(position, length, err),
+                    }
+                    self.on_batch();
+                    count += 1;
+                    if count % self.config.commit_every.max(1) == 0 {
+                        self.read_finish();
+                    }
+                }
+                Ok((None, r)) => remaining = r,
+                Err(Error::WouldBlock) => {
+                    self.batch = 0;
+                    break;
+                }
+                // the overflow or the corruption stays, the next call reports it
+                Err(Error::Overflown(_) | Error::Corrupted(_)) if count != 0 => break,
+                Err(Error::Overflown(distance)) => return Err(self.overflow(distance)),
+                Err(Error::Corrupted(corrupted)) => return Err(corrupted.into_io()),
+            }
+        }
+        // `f` is done with the slices, release their space, and the discarded slices too
+        self.read_finish();
+        Ok((count, remaining))
+    }
+
+    fn on_batch(&mut self) {
+        self.batch += 1;
+        self.stats.largest_batch = self.stats.largest_batch.max(self.batch);
+    }
+
+    fn overflow(&self, distance: usize) -> io::Error {
+        let overflow = RingBufferOverflow {
+            distance,
+            capacity: self.mask + 1,
+        };
+        io::Error::new(io::ErrorKind::Other, overflow)
+    }
 }
 
+// the mappings unmap themselves
 impl Drop for RingBufferObserver {
     fn drop(&mut self) {
         epoll::close(self.epfd).unwrap_or_default();
-        let len = self.len();
-        let p = mem::replace(&mut self.consumer_pos, Box::new(AtomicUsize::new(0)));
-        let q = mem::replace(&mut self.producer_pos, Box::new(AtomicUsize::new(0)));
-        let data = mem::replace(&mut self.data, Box::new([]));
-        unsafe {
-            libc::munmap(Box::into_raw(p) as *mut _, self.page_size);
-            libc::munmap(Box::into_raw(q) as *mut _, self.page_size + len);
-        }
-        Box::leak(data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{self, Write},
+        ptr,
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::{
+        RingBuffer, RingBufferConfig, RingBufferCorrupted, RingBufferData, RingBufferOverflow,
+        RingBufferSet, TeeReader,
+    };
+
+    const MAX_LENGTH: usize = 0x1000;
+
+    #[derive(Debug)]
+    struct Seq(u32);
+
+    impl RingBufferData for Seq {
+        type Error = ();
+
+        fn from_rb_slice(slice: &[u8]) -> Result<Option<Self>, Self::Error> {
+            let bytes = slice.get(..4).ok_or(())?;
+            Ok(Some(Seq(u32::from_le_bytes(bytes.try_into().unwrap()))))
+        }
+    }
+
+    /// The kernel side of the ring buffer in the memory file. The kernel maps the data twice
+    /// in a row, so the record crossing the end is contiguous, the producer writes both copies.
+    struct Producer {
+        fd: i32,
+        eventfd: i32,
+        len: usize,
+        base: *mut u8,
+        page_size: usize,
+        pos: usize,
+        // the position of each record
+        positions: Vec<usize>,
+    }
+
+    impl Producer {
+        fn new() -> Self {
+            Self::with_name(b"ring_buffer\0")
+        }
+
+        /// The name of the memory file is seen in `/proc/self/maps`.
+        fn with_name(name: &[u8]) -> Self {
+            unsafe {
+                let page_size = libc::sysconf(libc::_SC_PAGESIZE) as usize;
+                let len = page_size * 2 + MAX_LENGTH * 2;
+                let fd = libc::memfd_create(name.as_ptr() as *const _, 0);
+                assert!(fd >= 0);
+                assert_eq!(libc::ftruncate(fd, len as i64), 0);
+                let base = libc::mmap(
+                    ptr::null_mut(),
+                    len,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_SHARED,
+                    fd,
+                    0,
+                );
+                assert_ne!(base, libc::MAP_FAILED);
+                // always readable, the memory file cannot be polled
+                let eventfd = libc::eventfd(1, 0);
+                assert!(eventfd >= 0);
+                Producer {
+                    fd,
+                    eventfd,
+                    len,
+                    base: base as *mut u8,
+                    page_size,
+                    pos: 0,
+                    positions: vec![],
+                }
+            }
+        }
+
+        fn ring_buffer(&self, config: RingBufferConfig) -> RingBuffer {
+            RingBuffer::open(self.fd, self.eventfd, MAX_LENGTH, config).unwrap()
+        }
+
+        fn position(&self, page: usize) -> &AtomicUsize {
+            unsafe { &*(self.base.add(self.page_size * page) as *const AtomicUsize) }
+        }
+
+        /// What the kernel sees.
+        fn consumer_pos(&self) -> usize {
+            self.position(0).load(Ordering::Acquire)
+        }
+
+        /// Where the consumer must be after reading the first `n` records.
+        fn expected_pos(&self, n: usize) -> usize {
+            self.positions.get(n).copied().unwrap_or(self.pos)
+        }
+
+        /// `false` if there is no space, the consumer is behind.
+        fn push(&mut self, seq: u32, length: usize) -> bool {
+            let size = 8 + (length + 7) / 8 * 8;
+            if self.pos + size - self.consumer_pos() > MAX_LENGTH {
+                return false;
+            }
+            self.overrun(seq, length);
+            true
+        }
+
+        /// Writes over the unread records, like the producer which does not see the consumer.
+        fn overrun(&mut self, seq: u32, length: usize) {
+            let size = 8 + (length + 7) / 8 * 8;
+            let mut record = vec![0; size];
+            record[..4].copy_from_slice(&(length as u32).to_le_bytes());
+            record[8..12].copy_from_slice(&seq.to_le_bytes());
+            let data = unsafe { self.base.add(self.page_size * 2) };
+            for (i, b) in record.into_iter().enumerate() {
+                let offset = (self.pos + i) % MAX_LENGTH;
+                unsafe {
+                    *data.add(offset) = b;
+                    *data.add(offset + MAX_LENGTH) = b;
+                }
+            }
+            self.positions.push(self.pos);
+            self.pos += size;
+            self.position(1).store(self.pos, Ordering::Release);
+        }
+
+        /// The record of 8 bytes, but its header claims `length`, like the kernel side
+        /// of the different layout would write.
+        fn push_bogus(&mut self, length: u32) {
+            let mut record = [0; 16];
+            record[..4].copy_from_slice(&length.to_le_bytes());
+            let data = unsafe { self.base.add(self.page_size * 2) };
+            for (i, b) in record.into_iter().enumerate() {
+                let offset = (self.pos + i) % MAX_LENGTH;
+                unsafe {
+                    *data.add(offset) = b;
+                    *data.add(offset + MAX_LENGTH) = b;
+                }
+            }
+            self.positions.push(self.pos);
+            self.pos += record.len();
+            self.position(1).store(self.pos, Ordering::Release);
+        }
+
+        /// The buffer is not readable for the poll anymore, only the wakeup interrupts the wait.
+        fn silence(&self) {
+            let mut value = [0u8; 8];
+            let r = unsafe { libc::read(self.eventfd, value.as_mut_ptr() as *mut _, 8) };
+            assert_eq!(r, 8);
+        }
+
+        /// Readable again, like the kernel notifies the consumer after the record.
+        fn signal(&self) {
+            let value = 1u64.to_ne_bytes();
+            let r = unsafe { libc::write(self.eventfd, value.as_ptr() as *const _, 8) };
+            assert_eq!(r, 8);
+        }
+
+        /// Fills the buffer with the records of random size, up to `total` records.
+        fn fill(&mut self, rng: &mut u64, total: u32) {
+            while (self.positions.len() as u32) < total {
+                *rng ^= *rng << 13;
+                *rng ^= *rng >> 7;
+                *rng ^= *rng << 17;
+                if !self.push(self.positions.len() as u32, 4 + (*rng % 300) as usize) {
+                    break;
+                }
+            }
+        }
+    }
+
+    // the memory is shared, the producer may write from another thread
+    unsafe impl Send for Producer {}
+
+    impl Drop for Producer {
+        fn drop(&mut self) {
+            unsafe {
+                libc::munmap(self.base as *mut _, self.len);
+                libc::close(self.eventfd);
+                libc::close(self.fd);
+            }
+        }
+    }
+
+    const TOTAL: u32 = 2_000;
+
+    fn config() -> RingBufferConfig {
+        RingBufferConfig {
+            max_batch_events: 10,
+            commit_every: 4,
+            ..RingBufferConfig::default()
+        }
+    }
+
+    #[test]
+    fn consume_with_batched_commit() {
+        let mut producer = Producer::new();
+        let mut rb = producer.ring_buffer(config());
+        let terminating = AtomicBool::new(false);
+        let mut rng = 1;
+        let mut expected = 0;
+        while expected < TOTAL {
+            producer.fill(&mut rng, TOTAL);
+            let (count, _) = rb
+                .consume_with(&terminating, |slice| {
+                    let Seq(seq) = Seq::from_rb_slice(slice)?.unwrap();
+                    assert_eq!(seq, expected);
+                    // the kernel never sees the position past the record being read
+                    assert!(producer.consumer_pos() <= producer.positions[seq as usize]);
+                    expected += 1;
+                    Ok::<_, ()>(())
+                })
+                .unwrap();
+            assert!((1..=10).contains(&count));
+            // the batch is committed before returning
+            assert_eq!(
+                producer.consumer_pos(),
+                producer.expected_pos(expected as usize)
+            );
+        }
+        assert_eq!(rb.stats().events_consumed, TOTAL as u64);
+    }
+
+    #[test]
+    fn read_batch_batched_commit() {
+        let mut producer = Producer::new();
+        let mut rb = producer.ring_buffer(config());
+        let terminating = AtomicBool::new(false);
+        let mut rng = 2;
+        let mut expected = 0;
+        while expected < TOTAL {
+            producer.fill(&mut rng, TOTAL);
+            let (batch, _) = rb.read_batch_blocking::<Seq>(&terminating).unwrap();
+            assert!((1..=10).contains(&batch.len()));
+            for Seq(seq) in batch {
+                assert_eq!(seq, expected);
+                expected += 1;
+            }
+            assert_eq!(
+                producer.consumer_pos(),
+                producer.expected_pos(expected as usize)
+            );
+        }
+        assert_eq!(rb.stats().events_consumed, TOTAL as u64);
+    }
+
+    #[test]
+    fn set_is_fair() {
+        let mut loud = Producer::new();
+        let mut quiet = Producer::new();
+        let mut set = RingBufferSet::new(vec![
+            loud.ring_buffer(RingBufferConfig::default()),
+            quiet.ring_buffer(RingBufferConfig::default()),
+        ])
+        .unwrap();
+        let terminating = AtomicBool::new(false);
+        let mut rng = 3;
+        let mut expected = [0, 0];
+        let mut turns = vec![];
+        while expected[0] < TOTAL || expected[1] < 20 {
+            loud.fill(&mut rng, TOTAL);
+            quiet.fill(&mut rng, 20);
+            let (i, batch) = set.read_blocking_any::<Seq>(&terminating).unwrap();
+            assert_eq!(set.current(), i);
+            assert!((1..=RingBufferSet::BATCH).contains(&batch.len()));
+            for Seq(seq) in batch {
+                assert_eq!(seq, expected[i]);
+                expected[i] += 1;
+            }
+            turns.push(i);
+        }
+        // the loud buffer is refilled before every turn, still the quiet one gets the second turn
+        assert_eq!(turns[..2], [0, 1]);
+        assert_eq!(expected[1], 20);
+        assert_eq!(set.buffers()[0].stats().events_consumed, TOTAL as u64);
+        assert_eq!(loud.consumer_pos(), loud.expected_pos(TOTAL as usize));
+        assert_eq!(quiet.consumer_pos(), quiet.expected_pos(20));
+    }
+
+    #[test]
+    fn set_paused() {
+        let mut paused = Producer::new();
+        let mut quiet = Producer::new();
+        quiet.silence();
+        let config = RingBufferConfig {
+            poll_timeout: Duration::from_secs(10),
+            ..RingBufferConfig::default()
+        };
+        let mut set =
+            RingBufferSet::new(vec![paused.ring_buffer(config), quiet.ring_buffer(config)])
+                .unwrap();
+        set.buffers()[0].pause();
+        paused.fill(&mut 9, 5);
+        let terminating = AtomicBool::new(false);
+
+        // the paused buffer stays readable, still the set waits for the other one
+        let handler = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            assert!(quiet.push(0, 4));
+            quiet.signal();
+            quiet
+        });
+        let start = Instant::now();
+        let (i, batch) = set.read_blocking_any::<Seq>(&terminating).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!((i, batch.len()), (1, 1));
+        let quiet = handler.join().unwrap();
+        assert_eq!(quiet.consumer_pos(), quiet.expected_pos(1));
+        assert_eq!(paused.consumer_pos(), 0);
+
+        // watched again after the resume
+        set.buffers()[0].resume();
+        let (i, batch) = set.read_blocking_any::<Seq>(&terminating).unwrap();
+        assert_eq!((i, batch.len()), (0, 5));
+        assert_eq!(paused.consumer_pos(), paused.expected_pos(5));
+    }
+
+    #[test]
+    fn wakeup() {
+        let mut producer = Producer::new();
+        producer.silence();
+        let mut rb = producer.ring_buffer(RingBufferConfig {
+            poll_timeout: Duration::from_secs(30),
+            ..RingBufferConfig::default()
+        });
+        let wakeup = rb.wakeup_handle().unwrap();
+        let terminating = Arc::new(AtomicBool::new(false));
+        let handler = thread::spawn({
+            let terminating = terminating.clone();
+            move || {
+                thread::sleep(Duration::from_millis(50));
+                terminating.store(true, Ordering::SeqCst);
+                wakeup.notify();
+            }
+        });
+        let start = Instant::now();
+        let err = rb
+            .consume_with(&terminating, |_| -> Result<(), ()> { panic!() })
+            .unwrap_err();
+        assert_eq!(err.to_string(), "terminate");
+        assert!(start.elapsed() < Duration::from_secs(10));
+        handler.join().unwrap();
+
+        // the last records are drained without waiting
+        assert_eq!(
+            rb.try_consume_with(|_| -> Result<(), ()> { panic!() })
+                .unwrap()
+                .0,
+            0
+        );
+        producer.fill(&mut 4, 5);
+        let mut expected = 0;
+        let (count, remaining) = rb
+            .try_consume_with(|slice| {
+                let Seq(seq) = Seq::from_rb_slice(slice)?.unwrap();
+                assert_eq!(seq, expected);
+                expected += 1;
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+        assert_eq!((count, remaining), (5, 0));
+        assert_eq!(producer.consumer_pos(), producer.expected_pos(5));
+    }
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn tee() {
+        let mut producer = Producer::new();
+        let mut rb = producer.ring_buffer(config());
+        let tee = Shared::default();
+        rb.set_tee(tee.clone());
+        let terminating = AtomicBool::new(false);
+        let mut rng = 5;
+        let mut slices = vec![];
+        while slices.len() < 20 {
+            producer.fill(&mut rng, 30);
+            rb.consume_with(&terminating, |slice| {
+                slices.push(slice.to_vec());
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+        }
+        let mut read = slices.len();
+        while read < 30 {
+            producer.fill(&mut rng, 30);
+            read += rb.read_batch_blocking::<Seq>(&terminating).unwrap().0.len();
+        }
+        assert!(rb.take_tee().is_some());
+        producer.fill(&mut rng, 31);
+        rb.read_batch_blocking::<Seq>(&terminating).unwrap();
+
+        let bytes = tee.0.lock().unwrap().clone();
+        let mut reader = TeeReader::new(bytes.as_slice());
+        let mut seq = 0;
+        while let Some(slice) = reader.next_slice().unwrap() {
+            if let Some(expected) = slices.get(seq) {
+                assert_eq!(slice, expected.as_slice());
+            }
+            let Seq(s) = Seq::from_rb_slice(slice).unwrap().unwrap();
+            assert_eq!(s, seq as u32);
+            seq += 1;
+        }
+        // the last record is not in the tee
+        assert_eq!(seq, 30);
+        let truncated = &bytes[..(bytes.len() - 1)];
+        let mut reader = TeeReader::new(truncated);
+        let mut count = 0;
+        while reader.next_slice().unwrap().is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 29);
+    }
+
+    #[test]
+    fn parse_failures() {
+        let mut producer = Producer::new();
+        let mut rb = producer.ring_buffer(config());
+        let terminating = AtomicBool::new(false);
+        // too short for the sequence number
+        for (seq, length) in [(0, 4), (1, 2), (2, 4), (3, 1)] {
+            assert!(producer.push(seq, length));
+        }
+        let (batch, _) = rb.read_batch_blocking::<Seq>(&terminating).unwrap();
+        assert_eq!(batch.iter().map(|Seq(s)| *s).collect::<Vec<_>>(), [0, 2]);
+        let failures = rb.take_parse_failures();
+        assert_eq!(failures.len(), 2);
+        assert_eq!(failures[0].offset, producer.positions[1] as u64);
+        assert_eq!((failures[0].length, failures[1].length), (2, 1));
+        assert!(rb.take_parse_failures().is_empty());
+
+        // the callback rejects the odd ones
+        // more than it keeps, each record takes 16 bytes
+        for seq in 4..(4 + RingBuffer::MAX_PARSE_FAILURES as u32 * 3) {
+            assert!(producer.push(seq, 4));
+        }
+        let mut count = 0;
+        loop {
+            let (n, _) = rb
+                .try_consume_with(|slice| match Seq::from_rb_slice(slice)?.unwrap() {
+                    Seq(seq) if seq % 2 == 1 => Err(()),
+                    _ => Ok(()),
+                })
+                .unwrap();
+            if n == 0 {
+                break;
+            }
+            count += n;
+        }
+        assert_eq!(count, RingBuffer::MAX_PARSE_FAILURES * 3);
+        let failures = rb.take_parse_failures();
+        assert_eq!(failures.len(), RingBuffer::MAX_PARSE_FAILURES);
+        // the latest are kept
+        let last = producer.positions.last().copied().unwrap() as u64;
+        assert_eq!(failures.last().unwrap().offset, last);
+        assert_eq!(rb.stats().parse_errors, 2 + count as u64 / 2);
+    }
+
+    #[test]
+    fn corrupted_header() {
+        let mut producer = Producer::new();
+        let mut rb = producer.ring_buffer(config());
+        assert!(producer.push(0, 4));
+        for length in [MAX_LENGTH as u32 * 2, 9, 0x3fff_ffff] {
+            producer.push_bogus(length);
+        }
+
+        // the record before the corrupted one is consumed, the next call reports it
+        let (count, _) = rb
+            .try_consume_with(|slice| {
+                assert_eq!(Seq::from_rb_slice(slice)?.unwrap().0, 0);
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(producer.consumer_pos(), producer.expected_pos(1));
+        let err = rb.try_consume_with(|_| Ok::<_, ()>(())).unwrap_err();
+        let corrupted = RingBufferCorrupted::from_io(&err).unwrap();
+        assert_eq!(corrupted.position, producer.expected_pos(1));
+        assert_eq!(corrupted.length, MAX_LENGTH * 2);
+
+        // the consumer stays at the record, whichever way it reads
+        let err = rb.try_read::<Seq>().unwrap_err();
+        let corrupted = RingBufferCorrupted::from_io(&err).unwrap();
+        assert_eq!(corrupted.position, producer.expected_pos(1));
+        assert!(rb.try_consume_with(|_| Ok::<_, ()>(())).is_err());
+        assert_eq!(producer.consumer_pos(), producer.expected_pos(1));
+        assert_eq!(rb.stats().events_consumed, 1);
+
+        // the length fits the buffer, but runs past the producer
+        rb.resync();
+        producer.push_bogus(8);
+        assert!(producer.push(1, 4));
+        producer.push_bogus(9);
+        let mut seen = vec![];
+        let (count, _) = rb
+            .try_consume_with(|slice| {
+                seen.push(slice.len());
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+        assert_eq!((count, seen), (2, vec![8, 4]));
+        let err = rb.try_consume_with(|_| Ok::<_, ()>(())).unwrap_err();
+        assert_eq!(RingBufferCorrupted::from_io(&err).unwrap().length, 9);
+    }
+
+    #[test]
+    fn pause() {
+        let mut producer = Producer::new();
+        let mut rb = producer.ring_buffer(RingBufferConfig {
+            poll_timeout: Duration::from_millis(10),
+            ..config()
+        });
+        let terminating = AtomicBool::new(false);
+        producer.fill(&mut 5, 5);
+        rb.pause_handle().pause();
+        assert!(rb.is_paused());
+
+        // nothing is consumed, but the fill level is watched
+        let (count, remaining) = rb
+            .consume_with(&terminating, |_| -> Result<(), ()> { panic!() })
+            .unwrap();
+        assert_eq!((count, remaining), (0, producer.expected_pos(5)));
+        assert!(rb.try_read::<Seq>().unwrap().is_none());
+        let (value, _) = rb.read_blocking::<Seq>(&terminating).unwrap();
+        assert!(value.is_none());
+        let (batch, _) = rb.read_batch_blocking::<Seq>(&terminating).unwrap();
+        assert!(batch.is_empty());
+        assert_eq!(producer.consumer_pos(), 0);
+        assert_ne!(rb.stats().fill_percent, 0);
+        assert_eq!(rb.stats().events_consumed, 0);
+
+        // the overflow is reported while paused
+        let pos = producer.pos;
+        producer
+            .position(1)
+            .store(pos + MAX_LENGTH * 2, Ordering::Release);
+        let err = rb.try_consume_with(|_| Ok::<_, ()>(())).unwrap_err();
+        assert!(RingBufferOverflow::from_io(&err).is_some());
+        producer.position(1).store(pos, Ordering::Release);
+
+        // the shutdown does not wait for the resume
+        terminating.store(true, Ordering::SeqCst);
+        let err = rb
+            .consume_with(&terminating, |_| Ok::<_, ()>(()))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "terminate");
+        terminating.store(false, Ordering::SeqCst);
+
+        // continues from where it paused
+        rb.resume();
+        let mut expected = 0;
+        let (count, remaining) = rb
+            .consume_with(&terminating, |slice| {
+                let Seq(seq) = Seq::from_rb_slice(slice)?.unwrap();
+                assert_eq!(seq, expected);
+                expected += 1;
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+        assert_eq!((count, remaining), (5, 0));
+        assert_eq!(producer.consumer_pos(), producer.expected_pos(5));
+    }
+
+    #[test]
+    fn overflow_resync() {
+        let mut producer = Producer::new();
+        let mut rb = producer.ring_buffer(config());
+        let terminating = AtomicBool::new(false);
+        producer.fill(&mut 8, 2);
+        let (batch, _) = rb.read_batch_blocking::<Seq>(&terminating).unwrap();
+        assert_eq!(batch.len(), 2);
+
+        // the producer laps the consumer, the oldest unread records are overwritten
+        let consumed = producer.consumer_pos();
+        let mut seq = 2;
+        while producer.pos - consumed <= MAX_LENGTH {
+            producer.overrun(seq, 60);
+            seq += 1;
+        }
+        let err = rb.try_consume_with(|_| Ok::<_, ()>(())).unwrap_err();
+        let overflow = RingBufferOverflow::from_io(&err).unwrap();
+        assert_eq!(overflow.distance, producer.pos - consumed);
+        assert_eq!(overflow.capacity, MAX_LENGTH);
+        // nothing is consumed, whichever way it reads
+        let err = rb.read_batch_blocking::<Seq>(&terminating).unwrap_err();
+        assert!(RingBufferOverflow::from_io(&err).is_some());
+        assert_eq!(producer.consumer_pos(), consumed);
+
+        // the consumer lands on the boundary of the record the producer writes next
+        assert_eq!(rb.resync(), producer.pos - consumed);
+        assert_eq!(rb.skipped(), (producer.pos - consumed) as u64);
+        assert_eq!(producer.consumer_pos(), producer.expected_pos(seq as usize));
+        assert!(rb.try_read::<Seq>().unwrap().is_none());
+        assert!(producer.push(seq, 4));
+        assert!(producer.push(seq + 1, 4));
+        let (batch, remaining) = rb.read_batch_blocking::<Seq>(&terminating).unwrap();
+        let seqs = batch.iter().map(|Seq(s)| *s).collect::<Vec<_>>();
+        assert_eq!((seqs, remaining), (vec![seq, seq + 1], 0));
+        assert_eq!(
+            producer.consumer_pos(),
+            producer.expected_pos(seq as usize + 2)
+        );
+    }
+
+    #[test]
+    fn lag() {
+        let mut producer = Producer::new();
+        let mut rb = producer.ring_buffer(config());
+        let timestamp = |slice: &[u8]| Seq::from_rb_slice(slice).ok()?.map(|Seq(t)| t as u64);
+        assert_eq!(rb.lag(1_000, timestamp), Some(0));
+
+        // the sequence number is the timestamp
+        assert!(producer.push(100, 64));
+        assert!(producer.push(400, 8));
+        assert_eq!(
+            rb.lag(1_000, |slice| {
+                assert_eq!(slice.len(), RingBuffer::LAG_PEEK);
+                timestamp(slice)
+            }),
+            Some(900)
+        );
+        // nothing is consumed
+        assert_eq!(rb.lag(1_000, timestamp), Some(900));
+        assert_eq!(rb.lag(1_000, |_| None), None);
+        assert_eq!(rb.stats().lag_ns, 900);
+
+        let (value, _) = rb.try_read::<Seq>().unwrap().unwrap();
+        assert_eq!(value.unwrap().0, 100);
+        assert_eq!(
+            rb.lag(1_000, |slice| {
+                assert_eq!(slice.len(), 8);
+                timestamp(slice)
+            }),
+            Some(600)
+        );
+        // the clock is behind the timestamp
+        assert_eq!(rb.lag(300, timestamp), Some(0));
+
+        rb.try_read::<Seq>().unwrap().unwrap();
+        assert_eq!(rb.lag(1_000, timestamp), Some(0));
+        assert_eq!(rb.stats().lag_ns, 0);
+    }
+
+    #[test]
+    fn attach() {
+        let mut producer = Producer::new();
+        producer.fill(&mut 3, 4);
+        let mut rb = producer.ring_buffer(config());
+        let (value, _) = rb.try_read::<Seq>().unwrap().unwrap();
+        assert_eq!(value.unwrap().0, 0);
+        drop(rb);
+
+        // the next consumer continues from the committed position
+        let mut rb = producer.ring_buffer(config()).continue_committed();
+        let mut seen = vec![];
+        let (count, remaining) = rb
+            .try_consume_with(|slice| {
+                seen.push(Seq::from_rb_slice(slice)?.unwrap().0);
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+        assert_eq!((count, remaining, seen), (3, 0, vec![1, 2, 3]));
+        drop(rb);
+
+        // the position the producer left far behind is skipped, not reported
+        let pos = producer.pos;
+        producer
+            .position(1)
+            .store(pos + MAX_LENGTH * 2, Ordering::Release);
+        let mut rb = producer.ring_buffer(config()).continue_committed();
+        assert_eq!(rb.skipped(), MAX_LENGTH as u64 * 2);
+        assert_eq!(producer.consumer_pos(), pos + MAX_LENGTH * 2);
+        assert!(rb.try_read::<Seq>().unwrap().is_none());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn nonblocking() {
+        use std::{future::poll_fn, pin::Pin};
+
+        use futures_core::Stream;
+        use tokio::time;
+
+        use super::AsyncRingBuffer;
+
+        let seqs = |batch: &[Seq]| batch.iter().map(|Seq(s)| *s).collect::<Vec<_>>();
+        let wait = Duration::from_millis(50);
+        let mut producer = Producer::new();
+        producer.silence();
+        let mut rb = AsyncRingBuffer::new(producer.ring_buffer(config())).unwrap();
+        assert!(time::timeout(wait, rb.next_batch::<Seq>()).await.is_err());
+
+        // the batch is delivered after the wakeup
+        producer.fill(&mut 7, 5);
+        producer.signal();
+        let batch = time::timeout(Duration::from_secs(10), rb.next_batch::<Seq>());
+        assert_eq!(seqs(&batch.await.unwrap().unwrap()), [0, 1, 2, 3, 4]);
+        assert_eq!(producer.consumer_pos(), producer.expected_pos(5));
+        producer.silence();
+
+        // the pending future is dropped, nothing written meanwhile is lost
+        assert!(time::timeout(wait, rb.next_batch::<Seq>()).await.is_err());
+        producer.fill(&mut 7, 8);
+        assert!(time::timeout(wait, rb.next_batch::<Seq>()).await.is_err());
+        assert_eq!(producer.consumer_pos(), producer.expected_pos(5));
+        producer.signal();
+        let batch = time::timeout(Duration::from_secs(10), rb.next_batch::<Seq>());
+        assert_eq!(seqs(&batch.await.unwrap().unwrap()), [5, 6, 7]);
+        assert_eq!(producer.consumer_pos(), producer.expected_pos(8));
+        producer.silence();
+
+        let wakeup = rb.get_mut().wakeup_handle().unwrap();
+        let terminating = AtomicBool::new(false);
+        let mut stream = rb.stream::<Seq>(&terminating);
+        producer.fill(&mut 7, 10);
+        producer.signal();
+        for expected in 8..10 {
+            let value = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await;
+            assert_eq!(value.unwrap().unwrap().0, expected);
+        }
+
+        // the termination interrupts the waiting stream, the record written by then is taken
+        let (value, ()) = tokio::join!(poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)), async {
+            time::sleep(wait).await;
+            assert!(producer.push(10, 4));
+            terminating.store(true, Ordering::SeqCst);
+            wakeup.notify();
+        });
+        assert_eq!(value.unwrap().unwrap().0, 10);
+        assert!(poll_fn(|cx| Pin::new(&mut stream).poll_next(cx))
+            .await
+            .is_none());
+        assert_eq!(producer.consumer_pos(), producer.expected_pos(11));
+    }
+
+    #[cfg(feature = "test-proc-maps")]
+    #[test]
+    fn unmapped_on_drop() {
+        // the other tests map their own memory files meanwhile
+        let mappings = || {
+            std::fs::read_to_string("/proc/self/maps")
+                .unwrap()
+                .lines()
+                .filter(|line| line.contains("ring_buffer_unmapped"))
+                .count()
+        };
+        let mut producer = Producer::with_name(b"ring_buffer_unmapped\0");
+        assert!(producer.push(7, 4));
+        let before = mappings();
+        for _ in 0..100 {
+            let mut rb = producer.ring_buffer(config());
+            assert!(mappings() > before);
+            // each buffer reads from the start
+            let (value, _) = rb.try_read::<Seq>().unwrap().unwrap();
+            assert_eq!(value.unwrap().0, 7);
+            drop(rb);
+            assert_eq!(mappings(), before);
+        }
     }
 }
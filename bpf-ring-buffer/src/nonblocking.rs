@@ -0,0 +1,147 @@
+use std::{
+    io,
+    marker::PhantomData,
+    os::unix::io::{AsRawFd, RawFd},
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll},
+};
+
+use futures_core::{ready, Stream};
+use smallvec::SmallVec;
+use tokio::io::{unix::AsyncFd, Interest};
+
+use super::{RingBuffer, RingBufferData};
+
+/// The ring buffer on the tokio runtime, waits for the kernel in the reactor
+/// instead of blocking the thread in `epoll_wait`.
+///
+/// The futures only wait before reading, the values are read synchronously once the buffer
+/// is readable, so dropping the future never loses a value nor the consumer position.
+/// The consumer position is advanced exactly like `RingBuffer::read_blocking` does.
+/// The paused buffer yields nothing, after `RingBuffer::resume` it is read again
+/// once the kernel writes the next event.
+pub struct AsyncRingBuffer {
+    fd: AsyncFd<Observed>,
+    remaining: usize,
+}
+
+/// The reactor polls the epoll of the ring buffer, not the map itself,
+/// so the `WakeupHandle` interrupts the wait as well.
+struct Observed(RingBuffer);
+
+impl AsRawFd for Observed {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.observer.epfd
+    }
+}
+
+impl AsyncRingBuffer {
+    /// The batch is at most this many values, the rest stays in the ring buffer.
+    pub const BATCH: usize = 64;
+
+    /// Must be called within the tokio runtime.
+    pub fn new(rb: RingBuffer) -> io::Result<Self> {
+        Ok(AsyncRingBuffer {
+            fd: AsyncFd::with_interest(Observed(rb), Interest::READABLE)?,
+            remaining: 0,
+        })
+    }
+
+    /// The ring buffer itself, for example to `resync` after the overflow.
+    pub fn get_mut(&mut self) -> &mut RingBuffer {
+        &mut self.fd.get_mut().0
+    }
+
+    pub fn into_inner(self) -> RingBuffer {
+        self.fd.into_inner().0
+    }
+
+    /// How many bytes were remaining in the ring buffer after the last value.
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    /// Wait until the ring buffer has values, read them up to `BATCH`.
+    /// The overflow is reported once the values before it are taken.
+    pub async fn next_batch<D>(&mut self) -> io::Result<SmallVec<[D; 64]>>
+    where
+        D: RingBufferData,
+    {
+        loop {
+            let mut guard = self.fd.readable_mut().await?;
+            let mut batch = SmallVec::new();
+            while batch.len() < Self::BATCH {
+                match read_one(&mut guard.get_inner_mut().0, &mut self.remaining) {
+                    Ok(Some(value)) => batch.push(value),
+                    Ok(None) => break,
+                    // the overflow stays, the next call reports it
+                    Err(_) if !batch.is_empty() => break,
+                    Err(err) => return Err(err),
+                }
+            }
+            if !batch.is_empty() {
+                return Ok(batch);
+            }
+            guard.clear_ready();
+        }
+    }
+
+    /// The values one by one, it yields the overflow as an error. The stream ends
+    /// once `terminating` is set and the values readable by then are taken,
+    /// the `WakeupHandle` of the ring buffer interrupts the wait.
+    pub fn stream<'a, D>(&'a mut self, terminating: &'a AtomicBool) -> RingBufferStream<'a, D>
+    where
+        D: RingBufferData,
+    {
+        RingBufferStream {
+            inner: self,
+            terminating,
+            phantom: PhantomData,
+        }
+    }
+}
+
+pub struct RingBufferStream<'a, D> {
+    inner: &'a mut AsyncRingBuffer,
+    terminating: &'a AtomicBool,
+    phantom: PhantomData<fn() -> D>,
+}
+
+impl<'a, D> Stream for RingBufferStream<'a, D>
+where
+    D: RingBufferData,
+{
+    type Item = io::Result<D>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let RingBufferStream {
+            inner, terminating, ..
+        } = self.get_mut();
+        let AsyncRingBuffer { fd, remaining } = &mut **inner;
+        loop {
+            if terminating.load(Ordering::SeqCst) {
+                return Poll::Ready(read_one(&mut fd.get_mut().0, remaining).transpose());
+            }
+            let mut guard = ready!(fd.poll_read_ready_mut(cx))?;
+            match read_one(&mut guard.get_inner_mut().0, remaining)? {
+                Some(value) => return Poll::Ready(Some(Ok(value))),
+                None => guard.clear_ready(),
+            }
+        }
+    }
+}
+
+/// Skips the discarded and the malformed slices, `None` if nothing is left to read now.
+fn read_one<D>(rb: &mut RingBuffer, remaining: &mut usize) -> io::Result<Option<D>>
+where
+    D: RingBufferData,
+{
+    while let Some((value, r)) = rb.try_read()? {
+        *remaining = r;
+        if value.is_some() {
+            return Ok(value);
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,171 @@
+use std::{
+    io,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+};
+
+use smallvec::SmallVec;
+
+use super::{RingBuffer, RingBufferData};
+
+/// Several ring buffers behind one epoll instance, for example the data events
+/// and the control events, each with its own budget.
+///
+/// The buffers take turns, each turn reads at most `BATCH` values from one buffer
+/// and the next turn starts from the buffer after it, so the loud buffer
+/// cannot starve the others.
+pub struct RingBufferSet {
+    buffers: Vec<RingBuffer>,
+    epfd: i32,
+    events: Vec<epoll::Event>,
+    // the buffer is in the epoll, the paused one is not, it would stay readable
+    watched: Vec<bool>,
+    // the buffer to read first on the next call
+    next: usize,
+    // the buffer of the last batch or the last error
+    current: usize,
+}
+
+impl RingBufferSet {
+    /// The batch is at most this many values, the rest stays in the ring buffer.
+    pub const BATCH: usize = 64;
+
+    pub fn new(buffers: Vec<RingBuffer>) -> io::Result<Self> {
+        if buffers.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no ring buffers",
+            ));
+        }
+        let epfd = epoll::create(true)?;
+        for (i, rb) in buffers.iter().enumerate() {
+            let event = epoll::Event::new(epoll::Events::EPOLLIN, i as u64);
+            if let Err(err) = epoll::ctl(
+                epfd,
+                epoll::ControlOptions::EPOLL_CTL_ADD,
+                rb.poll_fd,
+                event,
+            ) {
+                epoll::close(epfd).unwrap_or_default();
+                return Err(err);
+            }
+        }
+        Ok(RingBufferSet {
+            events: vec![epoll::Event::new(epoll::Events::empty(), 0); buffers.len()],
+            watched: vec![true; buffers.len()],
+            buffers,
+            epfd,
+            next: 0,
+            current: 0,
+        })
+    }
+
+    pub fn buffers(&self) -> &[RingBuffer] {
+        &self.buffers
+    }
+
+    /// The ring buffers themselves, for example to `resync` the one that overflowed.
+    pub fn buffers_mut(&mut self) -> &mut [RingBuffer] {
+        &mut self.buffers
+    }
+
+    /// The index of the buffer which produced the last batch or the last error.
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    // the shortest timeout of the buffers
+    fn poll_timeout_ms(&self) -> i32 {
+        self.buffers
+            .iter()
+            .map(RingBuffer::poll_timeout_ms)
+            .min()
+            .unwrap_or(50)
+    }
+
+    /// Wait until any of the buffers has values, read them up to `BATCH` from one buffer.
+    /// Returns the index of the buffer and the values. The overflow of the buffer is
+    /// reported once the values before it are taken, `current` tells which buffer it is.
+    pub fn read_blocking_any<D>(
+        &mut self,
+        terminating: &AtomicBool,
+    ) -> io::Result<(usize, SmallVec<[D; 64]>)>
+    where
+        D: RingBufferData,
+    {
+        let n = self.buffers.len();
+        loop {
+            for k in 0..n {
+                let i = (self.next + k) % n;
+                let rb = &mut self.buffers[i];
+                let limit = Self::BATCH.min(rb.config.max_batch_events);
+                let mut batch = SmallVec::new();
+                let result = rb.read_into(limit, |value| batch.push(value));
+                if matches!(result, Ok((0, _))) {
+                    continue;
+                }
+                // the buffer had its turn, whatever it produced
+                self.current = i;
+                self.next = (i + 1) % n;
+                return result.map(|_| (i, batch));
+            }
+            let paused = self.watch_unpaused()?;
+            if paused == n {
+                let timeout = self.poll_timeout_ms() as u64;
+                thread::sleep(Duration::from_millis(timeout));
+            } else {
+                // the paused buffer might be resumed meanwhile, check it after the timeout
+                self.wait_epoll(terminating, paused != 0);
+            }
+            if terminating.load(Ordering::SeqCst) {
+                return Err(io::Error::new(io::ErrorKind::Other, "terminate"));
+            }
+        }
+    }
+
+    /// Keeps only the buffers which are not paused in the epoll, returns how many are paused.
+    fn watch_unpaused(&mut self) -> io::Result<usize> {
+        let mut paused = 0;
+        for (i, rb) in self.buffers.iter().enumerate() {
+            let watch = !rb.is_paused();
+            paused += usize::from(!watch);
+            if self.watched[i] == watch {
+                continue;
+            }
+            let op = if watch {
+                epoll::ControlOptions::EPOLL_CTL_ADD
+            } else {
+                epoll::ControlOptions::EPOLL_CTL_DEL
+            };
+            let event = epoll::Event::new(epoll::Events::EPOLLIN, i as u64);
+            epoll::ctl(self.epfd, op, rb.poll_fd, event)?;
+            self.watched[i] = watch;
+        }
+        Ok(paused)
+    }
+
+    fn wait_epoll(&mut self, terminating: &AtomicBool, once: bool) {
+        let timeout = self.poll_timeout_ms();
+        while !terminating.load(Ordering::SeqCst) {
+            match epoll::wait(self.epfd, timeout, &mut self.events) {
+                Ok(0) if once => break,
+                Ok(0) => log::debug!("ringbuf set wait timeout"),
+                Ok(_) => break,
+                Err(error) => {
+                    if io::ErrorKind::Interrupted != error.kind() {
+                        log::error!("ringbuf set error: {:?}", error);
+                    } else {
+                        log::error!("interrupted: {error:?}");
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for RingBufferSet {
+    fn drop(&mut self) {
+        epoll::close(self.epfd).unwrap_or_default();
+    }
+}
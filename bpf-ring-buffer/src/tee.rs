@@ -0,0 +1,46 @@
+use std::io::{self, Read, Write};
+
+/// Appends the record, the slice prefixed by its length, little endian `u32`.
+pub(crate) fn write_record<W>(w: &mut W, slice: &[u8]) -> io::Result<()>
+where
+    W: Write + ?Sized,
+{
+    w.write_all(&(slice.len() as u32).to_le_bytes())?;
+    w.write_all(slice)
+}
+
+/// Reads back the slices the tee of the ring buffer wrote, see `RingBuffer::set_tee`,
+/// so the exact bytes of the kernel can be parsed again without the kernel.
+/// The truncated last record, for example if the consumer was killed, ends the file.
+pub struct TeeReader<R> {
+    inner: R,
+    buf: Vec<u8>,
+}
+
+impl<R> TeeReader<R>
+where
+    R: Read,
+{
+    pub fn new(inner: R) -> Self {
+        TeeReader { inner, buf: vec![] }
+    }
+
+    /// The next slice as the ring buffer had it, `None` at the end.
+    pub fn next_slice(&mut self) -> io::Result<Option<&[u8]>> {
+        let mut len = [0; 4];
+        match self.inner.read_exact(&mut len) {
+            Ok(()) => (),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        }
+        self.buf.resize(u32::from_le_bytes(len) as usize, 0);
+        match self.inner.read_exact(&mut self.buf) {
+            Ok(()) => Ok(Some(&self.buf)),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                log::warn!("the tee is truncated");
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
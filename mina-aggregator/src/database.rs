@@ -1,22 +1,43 @@
 use std::{
     sync::{Arc, Mutex},
     collections::BTreeMap,
-    time::SystemTime,
-    net::SocketAddr,
+    time::{SystemTime, Duration},
+    net::{SocketAddr, IpAddr},
     path::Path,
 };
 
 use radiation::{Absorb, Emit};
-use serde::Serialize;
+use serde::{Serialize, Deserialize};
 use libp2p_core::PeerId;
 
 use mina_recorder::{
     meshsub_stats::{Event, Hash},
     custom_coding,
+    grafana::{Window, Buckets},
+    slo::{SloConfig, SloReport, Sample},
+    policy::CapturePolicy,
 };
 
 use super::rocksdb::{DbInner, DbError};
 
+/// The event the debugger posts, the standby receives the same in the feed.
+#[derive(Serialize, Deserialize)]
+pub struct Ingest {
+    pub alias: String,
+    pub event: Event,
+    /// the address to `namespace/name` of the pod, if the debugger runs in kubernetes
+    #[serde(default)]
+    pub pods: BTreeMap<IpAddr, String>,
+}
+
+/// The item of the feed, the sequence number and the ingested event.
+#[derive(Serialize, Deserialize)]
+pub struct FeedEntry<T> {
+    pub seq: u64,
+    #[serde(flatten)]
+    pub body: T,
+}
+
 #[derive(Serialize, Clone, Absorb, Emit)]
 pub struct GlobalBlockState {
     hash: Hash,
@@ -42,6 +63,13 @@ pub struct GlobalEvent {
     pub node_addr: SocketAddr,
     pub destination_addr: Option<String>,
     pub node_id: u32,
+    /// `namespace/name` of the pods, empty if unknown.
+    #[custom_absorb(custom_coding::trailing_string_absorb)]
+    pub node_pod: String,
+    #[custom_absorb(custom_coding::trailing_string_absorb)]
+    pub source_pod: String,
+    #[custom_absorb(custom_coding::trailing_string_absorb)]
+    pub destination_pod: String,
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord)]
@@ -51,7 +79,14 @@ pub struct Key {
 }
 
 impl GlobalEvent {
-    pub fn new(event: Event, addr: SocketAddr, id: u32, debugger_name: String) -> Self {
+    pub fn new(
+        event: Event,
+        addr: SocketAddr,
+        id: u32,
+        debugger_name: String,
+        pods: &BTreeMap<IpAddr, String>,
+    ) -> Self {
+        let pod = |ip| pods.get(&ip).cloned().unwrap_or_default();
         let time = event
             .better_time
             .duration_since(SystemTime::UNIX_EPOCH)
@@ -72,6 +107,9 @@ impl GlobalEvent {
                 node_addr: addr,
                 destination_addr: None,
                 node_id: id,
+                node_pod: pod(addr.ip()),
+                source_pod: pod(event.sender_addr.ip()),
+                destination_pod: String::new(),
             }
         } else {
             GlobalEvent {
@@ -88,11 +126,14 @@ impl GlobalEvent {
                 node_addr: addr,
                 destination_addr: Some(event.receiver_addr.to_string()),
                 node_id: id,
+                node_pod: pod(addr.ip()),
+                source_pod: String::new(),
+                destination_pod: pod(event.receiver_addr.ip()),
             }
         }
     }
 
-    pub fn append(&mut self, event: Event) {
+    pub fn append(&mut self, event: Event, pods: &BTreeMap<IpAddr, String>) {
         if let Some(latency) = event.latency {
             if !event.incoming {
                 let time = (event.time + latency)
@@ -102,6 +143,10 @@ impl GlobalEvent {
                 self.sent_message_id = Some(event.message_id);
                 self.sending_time_microseconds = Some(time_microseconds);
                 self.destination_addr = Some(event.receiver_addr.to_string());
+                self.destination_pod = pods
+                    .get(&event.receiver_addr.ip())
+                    .cloned()
+                    .unwrap_or_default();
             }
         }
     }
@@ -116,8 +161,11 @@ pub struct State {
 
 #[derive(Clone)]
 pub struct Database {
+    // the sequence number of the next event in the feed
+    feed: Arc<Mutex<u64>>,
     cache: Arc<Mutex<State>>,
     db: Arc<DbInner>,
+    slo: Arc<SloConfig>,
 }
 
 impl Database {
@@ -125,18 +173,107 @@ impl Database {
     where
         P: AsRef<Path>,
     {
+        let db = DbInner::open(path)?;
+        let next = db.feed_bounds().map_or(0, |(_, last)| last + 1);
         Ok(Database {
+            feed: Arc::new(Mutex::new(next)),
             cache: Arc::new(Mutex::new(State {
                 height: 0,
                 last: BTreeMap::new(),
                 ids: BTreeMap::new(),
                 counter: 0,
             })),
-            db: Arc::new(DbInner::open(path)?),
+            db: Arc::new(db),
+            slo: Arc::new(SloConfig::from_env()),
         })
     }
 
-    pub fn post_data(&self, debugger_name: &str, event: Event) {
+    /// Append the event to the feed and apply it.
+    pub fn ingest(&self, ingest: Ingest) {
+        let mut feed = self.feed.lock().expect("poisoned");
+        self.append(*feed, &ingest);
+        *feed += 1;
+        self.post_data(&ingest.alias, ingest.event, &ingest.pods);
+    }
+
+    /// The event of the primary feed, it keeps the sequence number of the primary,
+    /// so the feed of the standby continues the same after the promotion.
+    pub fn replicate(&self, FeedEntry { seq, body }: FeedEntry<Ingest>) {
+        let mut feed = self.feed.lock().expect("poisoned");
+        self.append(seq, &body);
+        *feed = seq + 1;
+        self.post_data(&body.alias, body.event, &body.pods);
+    }
+
+    fn append(&self, seq: u64, ingest: &Ingest) {
+        let result = serde_json::to_vec(ingest)
+            .map_err(|err| err.to_string())
+            .and_then(|b| self.db.put_feed(seq, &b).map_err(|err| err.to_string()));
+        if let Err(err) = result {
+            log::error!("cannot append the event {seq} to the feed: {err}");
+        }
+    }
+
+    /// The last sequence number of the feed, `None` if the feed is empty.
+    pub fn feed_last(&self) -> Option<u64> {
+        self.feed.lock().expect("poisoned").checked_sub(1)
+    }
+
+    /// The first sequence number kept and the events after `after`.
+    pub fn feed(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> (Option<u64>, Vec<FeedEntry<serde_json::Value>>) {
+        let first = self.db.feed_bounds().map(|(first, _)| first);
+        let events = match self.db.fetch_feed(after, limit) {
+            Ok(v) => v,
+            Err(err) => {
+                log::error!("{err}");
+                vec![]
+            }
+        };
+        let events = events
+            .into_iter()
+            .filter_map(|(seq, b)| {
+                let body = serde_json::from_slice(&b).ok()?;
+                Some(FeedEntry { seq, body })
+            })
+            .collect();
+        (first, events)
+    }
+
+    /// The stored capture policies by the node name, the empty name is the default.
+    pub fn policies(&self) -> BTreeMap<String, CapturePolicy> {
+        let policies = match self.db.fetch_policies() {
+            Ok(v) => v,
+            Err(err) => {
+                log::error!("{err}");
+                vec![]
+            }
+        };
+        policies
+            .into_iter()
+            .filter_map(|(name, b)| match serde_json::from_slice(&b) {
+                Ok(policy) => Some((name, policy)),
+                Err(err) => {
+                    log::error!("bad stored policy of {name:?}: {err}");
+                    None
+                }
+            })
+            .collect()
+    }
+
+    pub fn put_policy(&self, name: &str, policy: &CapturePolicy) {
+        let result = serde_json::to_vec(policy)
+            .map_err(|err| err.to_string())
+            .and_then(|b| self.db.put_policy(name, &b).map_err(|err| err.to_string()));
+        if let Err(err) = result {
+            log::error!("cannot store the policy of {name:?}: {err}");
+        }
+    }
+
+    fn post_data(&self, debugger_name: &str, event: Event, pods: &BTreeMap<IpAddr, String>) {
         let addr = event.node_address();
 
         log::info!("got data from {debugger_name} at {addr}");
@@ -169,10 +306,10 @@ impl Database {
 
         if let Some(g_event) = block_storage.get_mut(&key) {
             if g_event.sent_message_id.is_none() {
-                g_event.append(event);
+                g_event.append(event, pods);
             }
         } else {
-            let g_event = GlobalEvent::new(event, addr, id, debugger_name.to_owned());
+            let g_event = GlobalEvent::new(event, addr, id, debugger_name.to_owned(), pods);
             block_storage.insert(key, g_event);
         }
 
@@ -222,4 +359,116 @@ impl Database {
 
         Some((lock.height, events))
     }
+
+    /// The time between the first node seen the block and the last node received it,
+    /// the maximal value in each bucket, in seconds.
+    pub fn propagation_latency(&self, window: &Window) -> Buckets {
+        let mut buckets = Buckets::new(window);
+        let from = window
+            .from
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        let to = window
+            .to
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+
+        let (mut height, _) = self.latest().unwrap_or_default();
+        while height > 0 {
+            let blocks = match self.by_height(height) {
+                Some(v) => v,
+                None => break,
+            };
+            let mut earlier = true;
+            for block in blocks {
+                let times = block.events.iter().flat_map(|event| {
+                    event
+                        .receiving_time_microseconds
+                        .into_iter()
+                        .chain(event.sending_time_microseconds)
+                });
+                let first = times.min();
+                let last = block
+                    .events
+                    .iter()
+                    .filter_map(|event| event.receiving_time_microseconds)
+                    .max();
+                if let (Some(first), Some(last)) = (first, last) {
+                    earlier &= first < from;
+                    if (from..to).contains(&first) {
+                        let time = SystemTime::UNIX_EPOCH + Duration::from_micros(first);
+                        let latency = last.saturating_sub(first) as f64 / 1_000_000.0;
+                        buckets.add_max(time, latency);
+                    }
+                }
+            }
+            if earlier {
+                break;
+            }
+            height -= 1;
+        }
+
+        buckets
+    }
+
+    /// Block propagation objectives.
+    pub fn slo(&self) -> &SloConfig {
+        &self.slo
+    }
+
+    /// How long each block first seen in the range took to reach the last node,
+    /// from the first observation in the network.
+    pub fn slo_report(&self, since: SystemTime, until: SystemTime) -> SloReport {
+        let from = since
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        let mut samples = vec![];
+
+        let (mut height, _) = self.latest().unwrap_or_default();
+        while height > 0 {
+            let blocks = match self.by_height(height) {
+                Some(v) => v,
+                None => break,
+            };
+            let mut earlier = true;
+            for block in blocks {
+                let times = block.events.iter().flat_map(|event| {
+                    event
+                        .receiving_time_microseconds
+                        .into_iter()
+                        .chain(event.sending_time_microseconds)
+                });
+                let first = times.min();
+                let last = block
+                    .events
+                    .iter()
+                    .filter_map(|event| Some((event.receiving_time_microseconds?, event)))
+                    .max_by_key(|(time, _)| *time);
+                if let (Some(first), Some((last, event))) = (first, last) {
+                    earlier &= first < from;
+                    let node = if event.node_pod.is_empty() {
+                        format!("{} {}", event.debugger_name, event.node_addr)
+                    } else {
+                        event.node_pod.clone()
+                    };
+                    samples.push(Sample {
+                        hash: block.hash,
+                        height: event.block_height,
+                        time: SystemTime::UNIX_EPOCH + Duration::from_micros(first),
+                        latency: Duration::from_micros(last.saturating_sub(first)),
+                        node,
+                    });
+                }
+            }
+            if earlier {
+                break;
+            }
+            height -= 1;
+        }
+
+        SloReport::build(&self.slo, samples, since, until)
+    }
 }
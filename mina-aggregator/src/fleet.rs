@@ -0,0 +1,233 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use mina_recorder::policy::{CapturePolicy, RegisterRequest};
+use serde::Serialize;
+
+use super::database::Database;
+
+/// The debugger registered itself, the aggregator reaches its control api at `url`.
+#[derive(Clone, Serialize)]
+pub struct Debugger {
+    pub name: String,
+    pub url: String,
+    pub last_seen: SystemTime,
+    /// the version of the policy the debugger reported it has
+    pub policy_version: u64,
+    /// the version of the policy the debugger must have
+    pub expected_version: u64,
+    pub pushed: Option<SystemTime>,
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct Policies {
+    pub default: CapturePolicy,
+    /// override the default for the node by its debugger name
+    pub nodes: BTreeMap<String, CapturePolicy>,
+}
+
+struct State {
+    version: u64,
+    policies: Policies,
+    debuggers: BTreeMap<String, Debugger>,
+}
+
+impl State {
+    fn policy(&self, name: &str) -> CapturePolicy {
+        self.policies
+            .nodes
+            .get(name)
+            .unwrap_or(&self.policies.default)
+            .clone()
+    }
+}
+
+/// The debuggers of the fleet and the capture policies the aggregator pushes to them.
+/// The policies are stored, the registrations are not, the debuggers register every minute.
+#[derive(Clone)]
+pub struct Fleet {
+    state: Arc<Mutex<State>>,
+    // `DEBUGGER_TOKEN`, the token with full access to the debuggers
+    token: Option<String>,
+    // `FLEET_SECRET`, the debuggers and the operators present it to register and to set
+    // the policies, without it anyone could register a url and receive the token
+    secret: Option<String>,
+}
+
+impl Fleet {
+    pub fn new(db: &Database) -> Self {
+        let token = env::var("DEBUGGER_TOKEN").ok();
+        let secret = env::var("FLEET_SECRET").ok();
+        if token.is_some() && secret.is_none() {
+            log::warn!("`DEBUGGER_TOKEN` is not sent to the debuggers without `FLEET_SECRET`");
+        }
+        Self::with_options(db, token, secret)
+    }
+
+    /// Like `new`, but does not read the environment.
+    pub fn with_options(db: &Database, token: Option<String>, secret: Option<String>) -> Self {
+        let mut stored = db.policies();
+        let default = stored.remove("").unwrap_or_default();
+        let version = stored
+            .values()
+            .map(|p| p.version)
+            .fold(default.version, u64::max);
+        let policies = Policies {
+            default,
+            nodes: stored,
+        };
+        Fleet {
+            state: Arc::new(Mutex::new(State {
+                version,
+                policies,
+                debuggers: BTreeMap::new(),
+            })),
+            token,
+            secret,
+        }
+    }
+
+    /// The value of the `Authorization` header must be `Bearer $FLEET_SECRET`,
+    /// anyone is authorized if the secret is not set.
+    pub fn authorized(&self, authorization: Option<&str>) -> bool {
+        match &self.secret {
+            Some(secret) => {
+                authorization.and_then(|v| v.strip_prefix("Bearer ")) == Some(secret.as_str())
+            }
+            None => true,
+        }
+    }
+
+    pub fn debuggers(&self) -> Vec<Debugger> {
+        let state = self.state.lock().expect("poisoned");
+        state.debuggers.values().cloned().collect()
+    }
+
+    pub fn policies(&self) -> Policies {
+        self.state.lock().expect("poisoned").policies.clone()
+    }
+
+    /// Remember the debugger, push the policy if the debugger has a different one.
+    pub fn register(&self, request: RegisterRequest) -> Debugger {
+        let mut state = self.state.lock().expect("poisoned");
+        let policy = state.policy(&request.name);
+        let debugger = state
+            .debuggers
+            .entry(request.name.clone())
+            .or_insert_with(|| Debugger {
+                name: request.name.clone(),
+                url: request.url.clone(),
+                last_seen: SystemTime::now(),
+                policy_version: request.policy_version,
+                expected_version: policy.version,
+                pushed: None,
+                error: None,
+            });
+        debugger.url = request.url;
+        debugger.last_seen = SystemTime::now();
+        debugger.policy_version = request.policy_version;
+        debugger.expected_version = policy.version;
+        let debugger = debugger.clone();
+        drop(state);
+
+        if debugger.policy_version != policy.version {
+            self.push(debugger.name.clone(), debugger.url.clone(), policy);
+        }
+        debugger
+    }
+
+    /// Set the policy of the node, or the default one if the name is `None`,
+    /// store it and push it to the debuggers it applies to.
+    pub fn set(
+        &self,
+        db: &Database,
+        name: Option<String>,
+        mut policy: CapturePolicy,
+    ) -> CapturePolicy {
+        let mut state = self.state.lock().expect("poisoned");
+        state.version += 1;
+        policy.version = state.version;
+        db.put_policy(name.as_deref().unwrap_or_default(), &policy);
+        match &name {
+            Some(name) => {
+                state.policies.nodes.insert(name.clone(), policy.clone());
+            }
+            None => state.policies.default = policy.clone(),
+        }
+        let State {
+            policies,
+            debuggers,
+            ..
+        } = &mut *state;
+        let targets = debuggers
+            .values_mut()
+            .filter(|d| match &name {
+                Some(name) => d.name == *name,
+                None => !policies.nodes.contains_key(&d.name),
+            })
+            .map(|d| {
+                d.expected_version = policy.version;
+                (d.name.clone(), d.url.clone())
+            })
+            .collect::<Vec<_>>();
+        drop(state);
+
+        for (name, url) in targets {
+            self.push(name, url, policy.clone());
+        }
+        policy
+    }
+
+    /// The blocking client must not run on the runtime of the server, push in a thread.
+    fn push(&self, name: String, url: String, policy: CapturePolicy) {
+        let this = self.clone();
+        thread::spawn(move || {
+            let result = this.send(&url, &policy);
+            if let Err(err) = &result {
+                log::warn!("cannot push the policy {} to {name}: {err}", policy.version);
+            }
+            let mut state = this.state.lock().expect("poisoned");
+            if let Some(debugger) = state.debuggers.get_mut(&name) {
+                match result {
+                    Ok(()) => {
+                        debugger.pushed = Some(SystemTime::now());
+                        debugger.policy_version = policy.version;
+                        debugger.error = None;
+                    }
+                    Err(err) => debugger.error = Some(err),
+                }
+            }
+        });
+    }
+
+    fn send(&self, url: &str, policy: &CapturePolicy) -> Result<(), String> {
+        let url = url
+            .parse::<reqwest::Url>()
+            .and_then(|url| url.join("capture/policy"))
+            .map_err(|err| err.to_string())?;
+        let client = reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .map_err(|err| err.to_string())?;
+        let body = serde_json::to_string(policy).map_err(|err| err.to_string())?;
+        let mut request = client
+            .post(url)
+            .header("Content-Type", "application/json")
+            .body(body);
+        // the url is trusted only if the debugger registered with the secret
+        if let (Some(token), Some(_)) = (&self.token, &self.secret) {
+            request = request.bearer_auth(token);
+        }
+        request
+            .send()
+            .and_then(|r| r.error_for_status())
+            .map_err(|err| err.to_string())?;
+        Ok(())
+    }
+}
@@ -1,12 +1,18 @@
 mod routes;
 mod database;
 mod rocksdb;
+mod replication;
+mod fleet;
 
 use std::{thread, env};
 
 use tokio::{sync::oneshot, runtime::Runtime};
 
-use self::database::Database;
+use self::{
+    database::Database,
+    replication::{Replication, StandbyConfig},
+    fleet::Fleet,
+};
 
 fn main() {
     env_logger::init();
@@ -27,11 +33,20 @@ fn main() {
     };
 
     let database = Database::open("/tmp/mina-aggregator-db").expect("open db");
+    let standby = StandbyConfig::from_env();
+    let replication = Replication::new(&database, standby.as_ref());
+    if let Some(config) = standby {
+        if let Err(err) = replication.spawn_standby(database.clone(), config) {
+            log::error!("fatal: {err}");
+            return;
+        }
+    }
 
     let _guard = rt.enter();
     let (tx, rx) = oneshot::channel();
     let addr = ([0, 0, 0, 0], port);
-    let routes = routes::routes(database.clone());
+    let fleet = Fleet::new(&database);
+    let routes = routes::routes(database.clone(), replication, fleet);
     let shutdown = async move {
         rx.await.expect("corresponding sender should exist");
         log::info!("terminating http server...");
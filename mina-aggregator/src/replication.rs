@@ -0,0 +1,298 @@
+use std::{
+    env,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use serde::{Serialize, Deserialize};
+
+use super::database::{Database, FeedEntry, Ingest};
+
+/// `AGGREGATOR_STANDBY=<url of the primary>[,promote:<seconds>]`, without `promote`
+/// the standby is promoted only by `POST /replication/promote`.
+pub struct StandbyConfig {
+    primary: reqwest::Url,
+    promote_after: Option<Duration>,
+}
+
+impl FromStr for StandbyConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut items = s.split(',').map(str::trim);
+        let primary = items.next().unwrap_or_default();
+        let mut primary = primary
+            .parse::<reqwest::Url>()
+            .map_err(|err| format!("bad url of the primary {primary}: {err}"))?;
+        // the feed is joined to the url, without the slash the last segment would be replaced
+        if !primary.path().ends_with('/') {
+            let path = format!("{}/", primary.path());
+            primary.set_path(&path);
+        }
+        let mut promote_after = None;
+        for item in items {
+            let secs = item
+                .strip_prefix("promote:")
+                .ok_or_else(|| format!("unknown option {item}"))?;
+            let secs = secs
+                .parse()
+                .map_err(|err| format!("bad promote seconds {secs}: {err}"))?;
+            promote_after = Some(Duration::from_secs(secs));
+        }
+        Ok(StandbyConfig {
+            primary,
+            promote_after,
+        })
+    }
+}
+
+impl StandbyConfig {
+    pub fn from_env() -> Option<Self> {
+        let s = env::var("AGGREGATOR_STANDBY").ok()?;
+        s.parse()
+            .map_err(|err| log::error!("AGGREGATOR_STANDBY: {err}"))
+            .ok()
+    }
+
+    fn feed_url(&self) -> reqwest::Url {
+        self.primary.join("replication/feed").expect("url is valid")
+    }
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Primary,
+    Standby,
+}
+
+#[derive(Clone, Serialize)]
+pub struct ReplicationStatus {
+    pub role: Role,
+    /// the primary the standby follows, or followed before the promotion
+    pub primary: Option<String>,
+    /// the last event in the own feed
+    pub applied: Option<u64>,
+    /// the last event in the feed of the primary, as of the last contact
+    pub primary_last: Option<u64>,
+    pub last_contact: Option<SystemTime>,
+    pub error: Option<String>,
+    /// the events the primary dropped from its feed before the standby fetched them
+    pub lost: u64,
+    pub promoted: Option<SystemTime>,
+}
+
+/// How many events the primary dropped from its feed between the last event
+/// the standby has and the page, the empty standby expects the feed from zero.
+fn lost<T>(after: Option<u64>, page: &FeedPage<T>) -> u64 {
+    let expected = after.map_or(0, |after| after + 1);
+    page.events
+        .first()
+        .map(|entry| entry.seq)
+        .or(page.first)
+        .map_or(0, |first| first.saturating_sub(expected))
+}
+
+/// The page of the feed, the primary returns it to the standby.
+#[derive(Serialize, Deserialize)]
+pub struct FeedPage<T> {
+    /// the first event the feed still keeps
+    pub first: Option<u64>,
+    pub last: Option<u64>,
+    pub events: Vec<FeedEntry<T>>,
+}
+
+#[derive(Clone)]
+pub struct Replication {
+    standby: Arc<AtomicBool>,
+    status: Arc<Mutex<ReplicationStatus>>,
+}
+
+impl Replication {
+    /// How many events the standby fetches at once.
+    const PAGE: usize = 0x400;
+
+    const POLL: Duration = Duration::from_secs(1);
+
+    pub fn new(db: &Database, config: Option<&StandbyConfig>) -> Self {
+        let status = ReplicationStatus {
+            role: if config.is_some() {
+                Role::Standby
+            } else {
+                Role::Primary
+            },
+            primary: config.map(|c| c.primary.to_string()),
+            applied: db.feed_last(),
+            primary_last: None,
+            last_contact: None,
+            error: None,
+            lost: 0,
+            promoted: None,
+        };
+        Replication {
+            standby: Arc::new(AtomicBool::new(config.is_some())),
+            status: Arc::new(Mutex::new(status)),
+        }
+    }
+
+    pub fn is_standby(&self) -> bool {
+        self.standby.load(Ordering::SeqCst)
+    }
+
+    pub fn status(&self, db: &Database) -> ReplicationStatus {
+        let mut status = self.status.lock().expect("poisoned").clone();
+        status.applied = db.feed_last();
+        status
+    }
+
+    /// Stop following the primary and accept the events of the debuggers,
+    /// `false` if it is the primary already.
+    pub fn promote(&self) -> bool {
+        if !self.standby.swap(false, Ordering::SeqCst) {
+            return false;
+        }
+        let mut status = self.status.lock().expect("poisoned");
+        status.role = Role::Primary;
+        status.promoted = Some(SystemTime::now());
+        log::warn!("promoted, the primary was {:?}", status.primary);
+        true
+    }
+
+    /// Replicate the feed of the primary until promoted.
+    pub fn spawn_standby(
+        &self,
+        db: Database,
+        config: StandbyConfig,
+    ) -> Result<thread::JoinHandle<()>, reqwest::Error> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()?;
+        let url = config.feed_url();
+        let this = self.clone();
+        let started = SystemTime::now();
+        let handle = thread::spawn(move || {
+            log::info!("standby of {}", config.primary);
+            while this.is_standby() {
+                match this.poll(&client, &url, &db) {
+                    Ok(true) => continue,
+                    Ok(false) => (),
+                    Err(err) => {
+                        log::warn!("cannot replicate from {url}: {err}");
+                        let mut status = this.status.lock().expect("poisoned");
+                        status.error = Some(err);
+                        let last = status.last_contact.unwrap_or(started);
+                        let silent = last.elapsed().unwrap_or_default();
+                        drop(status);
+                        if config.promote_after.map_or(false, |d| silent >= d) {
+                            log::error!("the primary is silent for {silent:?}");
+                            this.promote();
+                        }
+                    }
+                }
+                thread::sleep(Self::POLL);
+            }
+        });
+        Ok(handle)
+    }
+
+    /// Fetch and apply one page, `true` if there might be more.
+    fn poll(
+        &self,
+        client: &reqwest::blocking::Client,
+        url: &reqwest::Url,
+        db: &Database,
+    ) -> Result<bool, String> {
+        let after = db.feed_last();
+        let mut request = client.get(url.clone()).query(&[("limit", Self::PAGE)]);
+        if let Some(after) = after {
+            request = request.query(&[("after", after)]);
+        }
+        let response = request
+            .send()
+            .and_then(|r| r.error_for_status())
+            .map_err(|err| err.to_string())?;
+        let text = response.text().map_err(|err| err.to_string())?;
+        let page =
+            serde_json::from_str::<FeedPage<Ingest>>(&text).map_err(|err| err.to_string())?;
+
+        let full = page.events.len() == Self::PAGE;
+        let lost = lost(after, &page);
+        if lost != 0 {
+            log::error!("the primary dropped {lost} events before the standby got them");
+        }
+        for entry in page.events {
+            if !self.is_standby() {
+                break;
+            }
+            db.replicate(entry);
+        }
+
+        let mut status = self.status.lock().expect("poisoned");
+        status.primary_last = page.last;
+        status.last_contact = Some(SystemTime::now());
+        status.error = None;
+        status.lost += lost;
+        Ok(full)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn standby_config() {
+    let config = "http://host/agg, promote:60"
+        .parse::<StandbyConfig>()
+        .unwrap();
+    assert_eq!(config.primary.as_str(), "http://host/agg/");
+    assert_eq!(
+        config.feed_url().as_str(),
+        "http://host/agg/replication/feed"
+    );
+    assert_eq!(config.promote_after, Some(Duration::from_secs(60)));
+
+    let config = "http://host:8000".parse::<StandbyConfig>().unwrap();
+    assert_eq!(
+        config.feed_url().as_str(),
+        "http://host:8000/replication/feed"
+    );
+    assert_eq!(config.promote_after, None);
+    let config = "http://host/agg/".parse::<StandbyConfig>().unwrap();
+    assert_eq!(
+        config.feed_url().as_str(),
+        "http://host/agg/replication/feed"
+    );
+
+    assert!("http://host/,promote:soon"
+        .parse::<StandbyConfig>()
+        .is_err());
+    assert!("http://host/,demote:60".parse::<StandbyConfig>().is_err());
+    assert!("host".parse::<StandbyConfig>().is_err());
+}
+
+#[cfg(test)]
+#[test]
+fn feed_gap() {
+    let page = |first, seqs: &[u64]| FeedPage {
+        first,
+        last: seqs.last().copied(),
+        events: seqs
+            .iter()
+            .map(|&seq| FeedEntry { seq, body: () })
+            .collect(),
+    };
+
+    // continues the feed
+    assert_eq!(lost(Some(9), &page(Some(0), &[10, 11])), 0);
+    assert_eq!(lost(None, &page(Some(0), &[0, 1])), 0);
+    // nothing new
+    assert_eq!(lost(Some(11), &page(Some(0), &[])), 0);
+    assert_eq!(lost(None, &page(None, &[])), 0);
+    // the primary trimmed its feed meanwhile
+    assert_eq!(lost(Some(9), &page(Some(15), &[15, 16])), 5);
+    // the empty standby of the primary which has trimmed its feed already
+    assert_eq!(lost(None, &page(Some(100), &[100, 101])), 100);
+}
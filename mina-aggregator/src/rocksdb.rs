@@ -21,6 +21,9 @@ pub enum DbError {
 impl DbInner {
     const TTL: Duration = Duration::from_secs(0);
 
+    /// The feed keeps only this many last events.
+    const FEED_CAPACITY: u64 = 1 << 20;
+
     pub fn open<P>(path: P) -> Result<Self, DbError>
     where
         P: AsRef<Path>,
@@ -30,10 +33,11 @@ impl DbInner {
         let mut opts = rocksdb::Options::default();
         opts.create_if_missing(true);
         opts.create_missing_column_families(true);
-        let cfs = [rocksdb::ColumnFamilyDescriptor::new(
-            "block",
-            Default::default(),
-        )];
+        let cfs = [
+            rocksdb::ColumnFamilyDescriptor::new("block", Default::default()),
+            rocksdb::ColumnFamilyDescriptor::new("feed", Default::default()),
+            rocksdb::ColumnFamilyDescriptor::new("policy", Default::default()),
+        ];
 
         let inner =
             rocksdb::DB::open_cf_descriptors_with_ttl(&opts, path.join("rocksdb"), cfs, Self::TTL)?;
@@ -45,6 +49,78 @@ impl DbInner {
         self.0.cf_handle("block").expect("must exist")
     }
 
+    fn feed(&self) -> &rocksdb::ColumnFamily {
+        self.0.cf_handle("feed").expect("must exist")
+    }
+
+    fn policy(&self) -> &rocksdb::ColumnFamily {
+        self.0.cf_handle("policy").expect("must exist")
+    }
+
+    /// The capture policy of the node by its name, the empty name is the default policy.
+    pub fn put_policy(&self, name: &str, body: &[u8]) -> Result<(), DbError> {
+        self.0.put_cf(self.policy(), name.as_bytes(), body)?;
+
+        Ok(())
+    }
+
+    pub fn fetch_policies(&self) -> Result<Vec<(String, Vec<u8>)>, DbError> {
+        let mut policies = vec![];
+        for item in self
+            .0
+            .iterator_cf(self.policy(), rocksdb::IteratorMode::Start)
+        {
+            let (key, value) = item?;
+            let name = String::from_utf8_lossy(&key).into_owned();
+            policies.push((name, value.to_vec()));
+        }
+
+        Ok(policies)
+    }
+
+    /// The ingested event as it was posted, the standby replays the feed.
+    pub fn put_feed(&self, seq: u64, body: &[u8]) -> Result<(), DbError> {
+        self.0.put_cf(self.feed(), seq.to_be_bytes(), body)?;
+        if seq % 0x400 == 0 && seq >= Self::FEED_CAPACITY {
+            let end = seq - Self::FEED_CAPACITY;
+            self.0
+                .delete_range_cf(self.feed(), 0u64.to_be_bytes(), end.to_be_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// The first and the last sequence number in the feed.
+    pub fn feed_bounds(&self) -> Option<(u64, u64)> {
+        let seq = |mode| {
+            let (key, _) = self.0.iterator_cf(self.feed(), mode).next()?.ok()?;
+            Some(u64::from_be_bytes(key.as_ref().try_into().ok()?))
+        };
+        Some((
+            seq(rocksdb::IteratorMode::Start)?,
+            seq(rocksdb::IteratorMode::End)?,
+        ))
+    }
+
+    /// At most `limit` events after the sequence number, or from the beginning.
+    pub fn fetch_feed(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, Vec<u8>)>, DbError> {
+        let start = after.map_or(0, |seq| seq + 1).to_be_bytes();
+        let mode = rocksdb::IteratorMode::From(&start, rocksdb::Direction::Forward);
+        let mut events = vec![];
+        for item in self.0.iterator_cf(self.feed(), mode).take(limit) {
+            let (key, value) = item?;
+            if let Ok(key) = key.as_ref().try_into() {
+                events.push((u64::from_be_bytes(key), value.to_vec()));
+            }
+        }
+
+        Ok(events)
+    }
+
     pub fn put_block(
         &self,
         height: u32,
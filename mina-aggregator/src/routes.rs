@@ -1,4 +1,9 @@
-use mina_recorder::meshsub_stats::Event;
+use std::time::{Duration, SystemTime};
+
+use mina_recorder::{
+    grafana::{QueryRequest, SearchRequest, Target},
+    policy::{CapturePolicy, RegisterRequest},
+};
 use serde::Deserialize;
 use warp::{
     Filter, Rejection, Reply,
@@ -6,7 +11,11 @@ use warp::{
     http::StatusCode,
 };
 
-use super::database::Database;
+use super::{
+    database::{Database, Ingest},
+    replication::{Replication, FeedPage},
+    fleet::Fleet,
+};
 
 fn version(
 ) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
@@ -31,23 +40,159 @@ fn openapi(
 
 fn register(
     db: Database,
+    replication: Replication,
 ) -> impl Filter<Extract = (WithStatus<impl Reply>,), Error = Rejection> + Clone + Sync + Send + 'static
 {
-    #[derive(Deserialize)]
-    struct Body {
-        alias: String,
-        event: Event,
-    }
-
     warp::path!("new")
         .and(warp::post())
         .and(warp::body::json())
-        .map(move |Body { alias, event }| {
-            db.post_data(&alias, event);
+        .map(move |ingest: Ingest| {
+            // the debugger tries the next aggregator of its list
+            if replication.is_standby() {
+                return reply::with_status(reply::reply(), StatusCode::SERVICE_UNAVAILABLE);
+            }
+            db.ingest(ingest);
             reply::with_status(reply::reply(), StatusCode::OK)
         })
 }
 
+#[derive(Deserialize)]
+struct FeedParams {
+    // the last event the standby has, from the beginning if absent
+    after: Option<u64>,
+    // default is 1024
+    limit: Option<usize>,
+}
+
+fn replication_feed(
+    db: Database,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("replication" / "feed")
+        .and(warp::query::query())
+        .map(move |params: FeedParams| -> WithStatus<Json> {
+            let limit = params.limit.unwrap_or(0x400).min(0x1000);
+            let (first, events) = db.feed(params.after, limit);
+            let page = FeedPage {
+                first,
+                last: db.feed_last(),
+                events,
+            };
+            reply::with_status(reply::json(&page), StatusCode::OK)
+        })
+}
+
+fn replication_status(
+    db: Database,
+    replication: Replication,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("replication").map(move || -> WithStatus<Json> {
+        let v = replication.status(&db);
+        reply::with_status(reply::json(&v), StatusCode::OK)
+    })
+}
+
+fn replication_promote(
+    db: Database,
+    replication: Replication,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("replication" / "promote").map(move || -> WithStatus<Json> {
+        let status = if replication.promote() {
+            StatusCode::OK
+        } else {
+            StatusCode::CONFLICT
+        };
+        reply::with_status(reply::json(&replication.status(&db)), status)
+    })
+}
+
+fn debuggers_register(
+    fleet: Fleet,
+    replication: Replication,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("debuggers")
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::body::json())
+        .map(
+            move |authorization: Option<String>, request: RegisterRequest| -> WithStatus<Json> {
+                if !fleet.authorized(authorization.as_deref()) {
+                    return reply::with_status(reply::json(&()), StatusCode::UNAUTHORIZED);
+                }
+                // only the primary pushes the policies
+                if replication.is_standby() {
+                    return reply::with_status(reply::json(&()), StatusCode::SERVICE_UNAVAILABLE);
+                }
+                let v = fleet.register(request);
+                reply::with_status(reply::json(&v), StatusCode::OK)
+            },
+        )
+}
+
+fn debuggers(
+    fleet: Fleet,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("debuggers").map(move || -> WithStatus<Json> {
+        let v = fleet.debuggers();
+        reply::with_status(reply::json(&v), StatusCode::OK)
+    })
+}
+
+fn policies(
+    fleet: Fleet,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("policy").map(move || -> WithStatus<Json> {
+        let v = fleet.policies();
+        reply::with_status(reply::json(&v), StatusCode::OK)
+    })
+}
+
+fn set_policy(
+    db: Database,
+    fleet: Fleet,
+    authorization: Option<String>,
+    name: Option<String>,
+    policy: CapturePolicy,
+) -> WithStatus<Json> {
+    if !fleet.authorized(authorization.as_deref()) {
+        return reply::with_status(reply::json(&()), StatusCode::UNAUTHORIZED);
+    }
+    if let Err(err) = policy.validate() {
+        return reply::with_status(reply::json(&err), StatusCode::BAD_REQUEST);
+    }
+    let v = fleet.set(&db, name, policy);
+    reply::with_status(reply::json(&v), StatusCode::OK)
+}
+
+fn policy_default(
+    db: Database,
+    fleet: Fleet,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("policy")
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::body::json())
+        .map(
+            move |authorization: Option<String>, policy: CapturePolicy| -> WithStatus<Json> {
+                set_policy(db.clone(), fleet.clone(), authorization, None, policy)
+            },
+        )
+}
+
+fn policy_node(
+    db: Database,
+    fleet: Fleet,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("policy" / String)
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::body::json())
+        .map(
+            move |name: String,
+                  authorization: Option<String>,
+                  policy: CapturePolicy|
+                  -> WithStatus<Json> {
+                set_policy(db.clone(), fleet.clone(), authorization, Some(name), policy)
+            },
+        )
+}
+
 fn stats_latest(
     db: Database,
 ) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
@@ -66,8 +211,98 @@ fn stats(
     })
 }
 
+#[derive(Deserialize)]
+struct SloParams {
+    // unix time in seconds, default is one period before `until`
+    since: Option<u64>,
+    // unix time in seconds, default is now
+    until: Option<u64>,
+    // how many periods, default is 24
+    limit: Option<usize>,
+}
+
+fn slo(
+    db: Database,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("slo")
+        .and(warp::query::query())
+        .map(move |params: SloParams| -> WithStatus<Json> {
+            let until = params.until.map_or_else(SystemTime::now, |secs| {
+                SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+            });
+            let since = match params.since {
+                Some(secs) => SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+                None => until - db.slo().period,
+            };
+            let v = db.slo_report(since, until);
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
+}
+
+fn slo_reports(
+    db: Database,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("slo" / "reports")
+        .and(warp::query::query())
+        .map(move |params: SloParams| -> WithStatus<Json> {
+            let periods = db
+                .slo()
+                .periods(SystemTime::now(), params.limit.unwrap_or(24));
+            let v = periods
+                .into_iter()
+                .map(|(since, until)| db.slo_report(since, until))
+                .collect::<Vec<_>>();
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
+}
+
+fn grafana_health(
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("grafana")
+        .map(move || -> WithStatus<Json> { reply::with_status(reply::json(&()), StatusCode::OK) })
+}
+
+fn grafana_search(
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("grafana" / "search")
+        .and(warp::body::json())
+        .map(move |request: SearchRequest| -> WithStatus<Json> {
+            let v = ["propagation_latency"]
+                .into_iter()
+                .filter(|name| name.starts_with(&request.target))
+                .collect::<Vec<_>>();
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
+}
+
+fn grafana_query(
+    db: Database,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("grafana" / "query")
+        .and(warp::body::json())
+        .map(move |request: QueryRequest| -> WithStatus<Json> {
+            let window = match request.window() {
+                Ok(v) => v,
+                Err(err) => {
+                    return reply::with_status(reply::json(&err), StatusCode::BAD_REQUEST);
+                }
+            };
+            let v = request
+                .targets
+                .iter()
+                .filter(|Target { target, .. }| target == "propagation_latency")
+                .map(|Target { target, .. }| {
+                    db.propagation_latency(&window).into_series(target.clone())
+                })
+                .collect::<Vec<_>>();
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
+}
+
 pub fn routes(
     database: Database,
+    replication: Replication,
+    fleet: Fleet,
 ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone + Sync + Send + 'static {
     use warp::reply::with;
 
@@ -76,11 +311,26 @@ pub fn routes(
         .allow_methods(["OPTIONS", "GET", "POST", "DELETE", "PUT"])
         .build();
 
-    let post = warp::post().and(register(database.clone()));
+    let post = warp::post().and(
+        register(database.clone(), replication.clone())
+            .or(replication_promote(database.clone(), replication.clone()))
+            .or(debuggers_register(fleet.clone(), replication.clone()))
+            .or(policy_default(database.clone(), fleet.clone()))
+            .or(policy_node(database.clone(), fleet.clone()))
+            .or(grafana_search())
+            .or(grafana_query(database.clone())),
+    );
     let get = warp::get().and(
         version()
             .or(openapi())
+            .or(grafana_health())
             .or(stats_latest(database.clone()))
+            .or(slo(database.clone()))
+            .or(slo_reports(database.clone()))
+            .or(replication_feed(database.clone()))
+            .or(debuggers(fleet.clone()))
+            .or(policies(fleet))
+            .or(replication_status(database.clone(), replication))
             .or(stats(database)),
     );
 
@@ -89,3 +339,86 @@ pub fn routes(
         .with(with::header("Access-Control-Allow-Origin", "*"))
         .with(cors_filter)
 }
+
+#[cfg(test)]
+#[test]
+fn fleet_registration() {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+    };
+
+    let dir = temp_dir::TempDir::new().unwrap();
+    let db = Database::open(dir.path()).unwrap();
+    let replication = Replication::new(&db, None);
+    let fleet = Fleet::with_options(&db, Some("token".to_owned()), Some("secret".to_owned()));
+    let routes = routes(db, replication, fleet.clone());
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let body = |url: &str| {
+        serde_json::to_string(&RegisterRequest {
+            name: "node".to_owned(),
+            url: url.to_owned(),
+            policy_version: 1,
+        })
+        .unwrap()
+    };
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        for authorization in [None, Some("Bearer wrong")] {
+            let mut request = warp::test::request()
+                .method("POST")
+                .path("/debuggers")
+                .body(body("http://attacker/"));
+            if let Some(v) = authorization {
+                request = request.header("authorization", v);
+            }
+            let response = request.reply(&routes).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+        let response = warp::test::request()
+            .method("POST")
+            .path("/policy")
+            .body(r#"{"sampling":0.5}"#)
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(fleet.debuggers().is_empty());
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/debuggers")
+            .header("authorization", "Bearer secret")
+            .body(body(&url))
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+    });
+
+    // the debugger reported another version, the policy is pushed to the registered url
+    let mut stream = (0..100)
+        .find_map(|_| match listener.accept() {
+            Ok((stream, _)) => Some(stream),
+            Err(_) => {
+                std::thread::sleep(Duration::from_millis(100));
+                None
+            }
+        })
+        .expect("the policy must be pushed");
+    stream.set_nonblocking(false).unwrap();
+    let mut request = vec![0; 0x1000];
+    let len = stream.read(&mut request).unwrap();
+    let request = String::from_utf8_lossy(&request[..len]).to_lowercase();
+    assert!(request.starts_with("post /capture/policy "));
+    assert!(request.contains("authorization: bearer token\r\n"));
+    stream
+        .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+        .unwrap();
+
+    let debuggers = fleet.debuggers();
+    assert_eq!(debuggers.len(), 1);
+    assert_eq!(debuggers[0].url, url);
+}
@@ -0,0 +1,247 @@
+use std::{
+    net::SocketAddr,
+    time::{Duration, SystemTime},
+};
+
+use pyo3::{
+    prelude::*,
+    exceptions::{PyIOError, PyValueError},
+    types::PyBytes,
+};
+
+use mina_recorder::{
+    api::decode_message,
+    database::{
+        CaptureReader, ConnectionFilter, MessageFilter, DbError, ConnectionId, MessageId,
+        StreamKind,
+    },
+};
+
+fn db_err(err: DbError) -> PyErr {
+    PyIOError::new_err(err.to_string())
+}
+
+fn unix_time(time: SystemTime) -> f64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+fn system_time(secs: f64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs_f64(secs.max(0.0))
+}
+
+fn stream_kind(s: &str) -> PyResult<StreamKind> {
+    s.parse()
+        .map_err(|()| PyValueError::new_err(format!("unknown stream kind {s}")))
+}
+
+fn json_to_py(py: Python, value: &serde_json::Value) -> PyResult<PyObject> {
+    pythonize::pythonize(py, value).map_err(|err| PyValueError::new_err(err.to_string()))
+}
+
+/// Connection as it is stored, times are unix time in seconds.
+#[pyclass(get_all)]
+pub struct Connection {
+    id: u64,
+    addr: String,
+    alias: String,
+    pid: u32,
+    fd: u32,
+    incoming: bool,
+    timestamp: f64,
+    /// `None` while the connection is open
+    timestamp_close: Option<f64>,
+    bytes_in: u64,
+    bytes_out: u64,
+    /// the connection is discarded after simultaneous connect in favor of this one
+    superseded_by: Option<u64>,
+}
+
+#[pymethods]
+impl Connection {
+    fn __repr__(&self) -> String {
+        let direction = if self.incoming {
+            "incoming"
+        } else {
+            "outgoing"
+        };
+        format!(
+            "Connection(id={}, addr={}, alias={}, {direction})",
+            self.id, self.addr, self.alias
+        )
+    }
+}
+
+/// Message as it is stored, the body is fetched by `Capture.decode` or `Capture.bytes`.
+#[pyclass(get_all)]
+pub struct Message {
+    id: u64,
+    connection_id: u64,
+    /// position within the connection, consecutive
+    seq: u64,
+    stream_id: String,
+    stream_kind: String,
+    incoming: bool,
+    timestamp: f64,
+    size: u32,
+    /// comma separated types of the message, like `publish_new_state`
+    brief: String,
+}
+
+#[pymethods]
+impl Message {
+    fn __repr__(&self) -> String {
+        let direction = if self.incoming {
+            "incoming"
+        } else {
+            "outgoing"
+        };
+        format!(
+            "Message(id={}, connection_id={}, stream_kind={}, {direction}, brief={})",
+            self.id, self.connection_id, self.stream_kind, self.brief
+        )
+    }
+}
+
+/// The capture database, opened for reading.
+#[pyclass]
+pub struct Capture {
+    reader: CaptureReader,
+}
+
+#[pymethods]
+impl Capture {
+    #[new]
+    fn new(path: &str) -> PyResult<Self> {
+        let reader = CaptureReader::open(path).map_err(db_err)?;
+        Ok(Capture { reader })
+    }
+
+    /// The manifest of the capture as a dict.
+    fn manifest(&self, py: Python) -> PyResult<PyObject> {
+        let value = serde_json::to_value(self.reader.manifest())
+            .map_err(|err| PyValueError::new_err(err.to_string()))?;
+        json_to_py(py, &value)
+    }
+
+    #[pyo3(signature = (addr = None, alias = None, incoming = None, since = None, until = None))]
+    fn connections(
+        &self,
+        addr: Option<&str>,
+        alias: Option<String>,
+        incoming: Option<bool>,
+        since: Option<f64>,
+        until: Option<f64>,
+    ) -> PyResult<Vec<Connection>> {
+        let addr = addr
+            .map(|s| s.parse::<SocketAddr>())
+            .transpose()
+            .map_err(|err| PyValueError::new_err(err.to_string()))?;
+        let filter = ConnectionFilter {
+            addr,
+            alias,
+            incoming,
+            from: since.map(system_time),
+            to: until.map(system_time),
+        };
+        let connections = self
+            .reader
+            .connections(&filter)
+            .map(|(id, cn)| Connection {
+                id: id.0,
+                addr: cn.info.addr.to_string(),
+                alias: cn.alias,
+                pid: cn.info.pid,
+                fd: cn.info.fd,
+                incoming: cn.incoming,
+                timestamp: unix_time(cn.timestamp),
+                timestamp_close: Some(cn.timestamp_close)
+                    .filter(|t| *t != SystemTime::UNIX_EPOCH)
+                    .map(unix_time),
+                bytes_in: cn.stats_in.total_bytes,
+                bytes_out: cn.stats_out.total_bytes,
+                superseded_by: cn.superseded_by.map(|id| id.0),
+            })
+            .collect();
+        Ok(connections)
+    }
+
+    /// Stream kinds are protocol names, like `/meshsub/1.1.0` or `coda/rpcs/0.0.1`.
+    #[pyo3(signature = (
+        connection_id = None,
+        stream_kinds = None,
+        incoming = None,
+        since = None,
+        until = None,
+        limit = None,
+    ))]
+    fn messages(
+        &self,
+        connection_id: Option<u64>,
+        stream_kinds: Option<Vec<String>>,
+        incoming: Option<bool>,
+        since: Option<f64>,
+        until: Option<f64>,
+        limit: Option<usize>,
+    ) -> PyResult<Vec<Message>> {
+        let stream_kinds = stream_kinds
+            .unwrap_or_default()
+            .iter()
+            .map(|s| stream_kind(s))
+            .collect::<PyResult<Vec<_>>>()?;
+        let filter = MessageFilter {
+            connection_id: connection_id.map(ConnectionId),
+            stream_kinds,
+            incoming,
+            from: since.map(system_time),
+            to: until.map(system_time),
+        };
+        let messages = self
+            .reader
+            .messages(&filter)
+            .take(limit.unwrap_or(usize::MAX))
+            .map(|(id, msg)| Message {
+                id: id.0,
+                connection_id: msg.connection_id.0,
+                seq: msg.seq,
+                stream_id: msg.stream_id.to_string(),
+                stream_kind: msg.stream_kind.to_string(),
+                incoming: msg.incoming,
+                timestamp: unix_time(msg.timestamp),
+                size: msg.size,
+                brief: msg.brief,
+            })
+            .collect();
+        Ok(messages)
+    }
+
+    /// The message decoded into python objects, like `GET /message/{id}` returns it.
+    fn decode(&self, py: Python, id: u64) -> PyResult<PyObject> {
+        let msg = self.reader.decoded(MessageId(id)).map_err(db_err)?;
+        json_to_py(py, &msg.message)
+    }
+
+    /// The decrypted bytes of the message.
+    fn bytes<'py>(&self, py: Python<'py>, id: u64) -> PyResult<&'py PyBytes> {
+        let bytes = self.reader.bytes(MessageId(id)).map_err(db_err)?;
+        Ok(PyBytes::new(py, &bytes))
+    }
+}
+
+/// Decode the decrypted message of the stream of given kind, for blobs obtained elsewhere.
+#[pyfunction]
+fn decode_blob(py: Python, stream_kind: &str, data: &[u8]) -> PyResult<PyObject> {
+    let value = decode_message(self::stream_kind(stream_kind)?, data.to_vec())
+        .map_err(PyValueError::new_err)?;
+    json_to_py(py, &value)
+}
+
+#[pymodule]
+fn mina_capture(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<Capture>()?;
+    m.add_class::<Connection>()?;
+    m.add_class::<Message>()?;
+    m.add_function(wrap_pyfunction!(decode_blob, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,165 @@
+//! Measures how much of one second the decryption workers need to decrypt
+//! 1 Gbps of noise transport messages. The rest is the time left
+//! for draining the ring buffer.
+//!
+//! Then offers 1 Gbps to the whole userspace pipeline, the queue in front of
+//! the recorder is as large as the ring buffer of the kernel module. The headroom
+//! is the part of the ring buffer the recorder never needed.
+//!
+//! Run: `cargo bench -p mina-recorder --bench decrypt`
+
+use std::{
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use mina_recorder::{
+    bench::{self, BenchConfig},
+    database::DbFacade,
+};
+use chacha20poly1305::{
+    aead::{AeadInPlace, KeyInit},
+    ChaCha20Poly1305, Nonce, Tag,
+};
+
+// 1 Gbps
+const BYTES_PER_SECOND: usize = 1_000_000_000 / 8;
+// typical size of noise transport message in mina network
+const MESSAGE_SIZE: usize = 0x1000;
+const CONNECTIONS: usize = 64;
+// the size of `event_queue` in the kernel module
+const RING_BUFFER_SIZE: usize = 0x8000000;
+const PIPELINE_DURATION: Duration = Duration::from_secs(5);
+
+struct Chunk {
+    connection: usize,
+    nonce: u64,
+    data: Vec<u8>,
+    tag: Tag,
+}
+
+fn nonce(n: u64) -> Nonce {
+    let mut nonce = Nonce::default();
+    nonce[4..].clone_from_slice(&n.to_le_bytes());
+    nonce
+}
+
+fn prepare() -> (Vec<ChaCha20Poly1305>, Vec<Chunk>) {
+    let ciphers = (0..CONNECTIONS)
+        .map(|i| ChaCha20Poly1305::new(&[i as u8; 32].into()))
+        .collect::<Vec<_>>();
+    let mut nonces = vec![0; CONNECTIONS];
+    let chunks = (0..(BYTES_PER_SECOND / MESSAGE_SIZE))
+        .map(|i| {
+            let connection = i % CONNECTIONS;
+            let n = nonces[connection];
+            nonces[connection] += 1;
+            let mut data = vec![i as u8; MESSAGE_SIZE];
+            let tag = ciphers[connection]
+                .encrypt_in_place_detached(&nonce(n), &[], &mut data)
+                .expect("must encrypt");
+            Chunk {
+                connection,
+                nonce: n,
+                data,
+                tag,
+            }
+        })
+        .collect();
+    (ciphers, chunks)
+}
+
+fn run(workers: usize, ciphers: &[ChaCha20Poly1305], chunks: &[Chunk]) -> Duration {
+    // the same sharding as in the recorder, a connection always goes to the same worker
+    let (handles, txs): (Vec<_>, Vec<_>) = (0..workers)
+        .map(|_| {
+            let (tx, rx) = mpsc::channel::<Chunk>();
+            let ciphers = ciphers.to_vec();
+            let handle = thread::spawn(move || {
+                let mut expected = vec![0; CONNECTIONS];
+                while let Ok(mut chunk) = rx.recv() {
+                    assert_eq!(expected[chunk.connection], chunk.nonce, "order is broken");
+                    expected[chunk.connection] += 1;
+                    ciphers[chunk.connection]
+                        .decrypt_in_place_detached(
+                            &nonce(chunk.nonce),
+                            &[],
+                            &mut chunk.data,
+                            &chunk.tag,
+                        )
+                        .expect("must decrypt");
+                }
+            });
+            (handle, tx)
+        })
+        .unzip();
+
+    let chunks = chunks
+        .iter()
+        .map(|c| Chunk {
+            connection: c.connection,
+            nonce: c.nonce,
+            data: c.data.clone(),
+            tag: c.tag,
+        })
+        .collect::<Vec<_>>();
+
+    let start = Instant::now();
+    for chunk in chunks {
+        txs[chunk.connection % workers]
+            .send(chunk)
+            .expect("worker must be alive");
+    }
+    drop(txs);
+    for handle in handles {
+        handle.join().expect("worker must not panic");
+    }
+    start.elapsed()
+}
+
+fn pipeline(workers: usize) {
+    let dir = std::env::temp_dir().join(format!("mina-bench-decrypt-{}", std::process::id()));
+    let db = DbFacade::open_scratch(&dir).expect("must open scratch database");
+    let config = BenchConfig {
+        rate: (BYTES_PER_SECOND / MESSAGE_SIZE) as u64,
+        connections: CONNECTIONS as u32,
+        chunk_size: MESSAGE_SIZE,
+        duration: PIPELINE_DURATION,
+        queue: RING_BUFFER_SIZE / MESSAGE_SIZE,
+        workers,
+    };
+    let report = bench::run(db, &config, None).expect("must run the pipeline");
+    std::fs::remove_dir_all(&dir).unwrap_or_default();
+    // the longest a chunk waited in the queue, at 1 Gbps the ring buffer held that much
+    let waited = Duration::from_micros(report.queue_latency.max_us);
+    let occupied = waited.as_secs_f64() * BYTES_PER_SECOND as f64 / RING_BUFFER_SIZE as f64;
+    println!(
+        "workers: {workers:2}, pipeline {:>10.0} bytes/s, dropped {:>6} chunks, ring buffer headroom: {:>6.1}%",
+        report.bytes_per_sec,
+        report.dropped_chunks,
+        (1.0 - occupied).max(0.0) * 100.0,
+    );
+}
+
+fn main() {
+    let (ciphers, chunks) = prepare();
+    let max = thread::available_parallelism().map_or(4, |n| n.get());
+    let mut workers = 1;
+    while workers <= max {
+        let elapsed = run(workers, &ciphers, &chunks);
+        let headroom = 1.0 - elapsed.as_secs_f64();
+        println!(
+            "workers: {workers:2}, decrypt 1 Gbit in {:>8.3} ms, left for draining: {:>6.1}%",
+            elapsed.as_secs_f64() * 1000.0,
+            headroom * 100.0,
+        );
+        workers *= 2;
+    }
+
+    let mut workers = 0;
+    while workers <= max {
+        pipeline(workers);
+        workers = (workers * 2).max(1);
+    }
+}
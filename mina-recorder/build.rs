@@ -20,6 +20,8 @@ fn main() {
     )
     .unwrap();
 
+    tonic_build::compile_protos("proto/debugger.proto").unwrap();
+
     capnpc::CompilerCommand::new()
         .file("libp2p_ipc.capnp")
         .run()
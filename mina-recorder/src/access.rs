@@ -0,0 +1,136 @@
+use std::{collections::BTreeMap, env, fmt, str::FromStr};
+
+use serde::Serialize;
+
+use crate::database::Redaction;
+
+/// What the token of the client allows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    /// the sizes and the types of the messages, the payloads are stripped,
+    /// the routes which cannot be redacted are forbidden
+    Redacted,
+    /// everything, the payloads, the noise keys and the control of the debugger
+    Full,
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scope::Redacted => write!(f, "redacted"),
+            Scope::Full => write!(f, "full"),
+        }
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "redacted" => Ok(Scope::Redacted),
+            "full" => Ok(Scope::Full),
+            _ => Err(s.to_owned()),
+        }
+    }
+}
+
+impl Scope {
+    /// The redaction the client asks for, but at least the one the scope enforces.
+    pub fn redaction(&self, requested: Option<Redaction>) -> Redaction {
+        match self {
+            Scope::Full => requested.unwrap_or_default(),
+            Scope::Redacted => Redaction::Strip,
+        }
+    }
+}
+
+/// The tokens of the HTTP api. Without tokens the api is open and every client has full access.
+#[derive(Default)]
+pub struct AccessTokens {
+    tokens: BTreeMap<String, Scope>,
+}
+
+impl FromStr for AccessTokens {
+    type Err = String;
+
+    /// Comma separated `<scope>:<token>`, like `full:s3cret,redacted:dashboard`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = BTreeMap::new();
+        for item in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (scope, token) = item
+                .split_once(':')
+                .ok_or_else(|| "expected <scope>:<token>".to_owned())?;
+            let scope = scope
+                .parse::<Scope>()
+                .map_err(|s| format!("unknown scope {s}"))?;
+            if token.is_empty() {
+                return Err(format!("empty token of the scope {scope}"));
+            }
+            tokens.insert(token.to_owned(), scope);
+        }
+        Ok(AccessTokens { tokens })
+    }
+}
+
+impl AccessTokens {
+    pub fn from_env() -> Self {
+        match env::var("API_TOKENS") {
+            Ok(s) => s.parse().unwrap_or_else(|err| {
+                // fail closed, the api is not open because of the typo
+                log::error!("API_TOKENS: {err}, every request is rejected");
+                AccessTokens {
+                    tokens: [(String::new(), Scope::Redacted)].into_iter().collect(),
+                }
+            }),
+            Err(_) => AccessTokens::default(),
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// The scope of the `Authorization: Bearer <token>` header, `None` if the token is unknown.
+    pub fn scope(&self, authorization: Option<&str>) -> Option<Scope> {
+        if self.is_open() {
+            return Some(Scope::Full);
+        }
+        let token = authorization?.strip_prefix("Bearer ")?.trim();
+        if token.is_empty() {
+            return None;
+        }
+        self.tokens.get(token).copied()
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn access_tokens() {
+    let open = AccessTokens::default();
+    assert_eq!(open.scope(None), Some(Scope::Full));
+
+    let tokens = "full:s3cret, redacted:dashboard"
+        .parse::<AccessTokens>()
+        .unwrap();
+    assert_eq!(tokens.scope(Some("Bearer s3cret")), Some(Scope::Full));
+    assert_eq!(
+        tokens.scope(Some("Bearer dashboard")),
+        Some(Scope::Redacted)
+    );
+    assert_eq!(tokens.scope(Some("Bearer other")), None);
+    assert_eq!(tokens.scope(Some("s3cret")), None);
+    assert_eq!(tokens.scope(None), None);
+
+    assert_eq!(
+        Scope::Redacted.redaction(Some(Redaction::None)),
+        Redaction::Strip
+    );
+    assert_eq!(
+        Scope::Full.redaction(Some(Redaction::Hash)),
+        Redaction::Hash
+    );
+    assert!("admin:x".parse::<AccessTokens>().is_err());
+    assert!("full:".parse::<AccessTokens>().is_err());
+}
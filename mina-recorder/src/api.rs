@@ -0,0 +1,298 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    net::{Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::SystemTime,
+};
+
+use thiserror::Error;
+
+use crate::{
+    event::EventMetadata,
+    recorder::{P2pRecorder, Aggregator},
+    database::{DbFacade, DbError, StreamId, StreamKind},
+    sink::{Sink, SinkEvent, SinkConfig},
+    peer_names::PeerNamesConfig,
+    kube::KubeMetadata,
+};
+
+/// Input of the recorder, what the kernel module reports about the debuggee.
+#[derive(Debug)]
+pub enum CaptureEvent {
+    /// The process `pid` is the node named `alias`, like `mainnet-node1`,
+    /// the chain is determined by the prefix of the alias.
+    Alias {
+        pid: u32,
+        alias: String,
+    },
+    /// The process listens on the port, the first one is the p2p port of the node.
+    Port {
+        pid: u32,
+        port: u16,
+    },
+    Connect {
+        incoming: bool,
+        metadata: EventMetadata,
+        /// empty to determine by the alias
+        chain_id: String,
+    },
+    Disconnect {
+        metadata: EventMetadata,
+    },
+    /// Bytes as they go over the wire, encrypted.
+    Data {
+        incoming: bool,
+        metadata: EventMetadata,
+        bytes: Vec<u8>,
+    },
+    Randomness {
+        pid: u32,
+        bytes: Vec<u8>,
+        time: SystemTime,
+    },
+}
+
+/// Anything that yields capture events, the ring buffer of the kernel module, a file, a test.
+pub trait EventSource {
+    fn next_event(&mut self) -> Option<CaptureEvent>;
+}
+
+impl<I> EventSource for I
+where
+    I: Iterator<Item = CaptureEvent>,
+{
+    fn next_event(&mut self) -> Option<CaptureEvent> {
+        self.next()
+    }
+}
+
+impl P2pRecorder {
+    pub fn handle(&mut self, event: CaptureEvent) {
+        match event {
+            CaptureEvent::Alias { pid, alias } => self.on_alias(pid, alias),
+            CaptureEvent::Port { pid, port } => {
+                self.on_listen(pid, (Ipv4Addr::UNSPECIFIED, port).into());
+            }
+            CaptureEvent::Connect {
+                incoming,
+                metadata,
+                chain_id,
+            } => self.on_connect::<false>(incoming, metadata, 0, chain_id),
+            CaptureEvent::Disconnect { metadata } => self.on_disconnect(metadata, 0),
+            CaptureEvent::Data {
+                incoming,
+                metadata,
+                bytes,
+            } => self.on_data(incoming, metadata, 0, bytes),
+            CaptureEvent::Randomness { pid, bytes, time } => self.on_randomness(pid, bytes, time),
+        }
+    }
+
+    /// Handle all events of the source.
+    pub fn run<S>(&mut self, mut source: S)
+    where
+        S: EventSource,
+    {
+        while let Some(event) = source.next_event() {
+            self.handle(event);
+        }
+    }
+}
+
+/// The message decrypted and decoded to json, like `GET /message/{id}` returns it.
+#[derive(Debug)]
+pub struct DecodedMessage {
+    /// assigned by the recorder
+    pub connection_id: u64,
+    /// position within the connection, consecutive
+    pub seq: u64,
+    pub remote_addr: Option<SocketAddr>,
+    pub stream_id: StreamId,
+    pub stream_kind: StreamKind,
+    pub incoming: bool,
+    pub time: SystemTime,
+    pub message: Result<serde_json::Value, String>,
+}
+
+/// Decode the decrypted message of the stream of given kind into json.
+pub fn decode_message(
+    stream_kind: StreamKind,
+    bytes: Vec<u8>,
+) -> Result<serde_json::Value, String> {
+    crate::decode::parse(stream_kind, bytes, false).map_err(|err| err.to_string())
+}
+
+/// Decodes messages and passes them to the callback.
+pub struct CallbackSink<F> {
+    addresses: BTreeMap<u64, SocketAddr>,
+    callback: F,
+}
+
+impl<F> CallbackSink<F>
+where
+    F: FnMut(DecodedMessage) + Send + 'static,
+{
+    pub fn new(callback: F) -> Self {
+        CallbackSink {
+            addresses: BTreeMap::default(),
+            callback,
+        }
+    }
+}
+
+impl<F> Sink for CallbackSink<F>
+where
+    F: FnMut(DecodedMessage) + Send + 'static,
+{
+    fn send(&mut self, event: &SinkEvent) -> io::Result<()> {
+        match event {
+            SinkEvent::Connection(v) => {
+                self.addresses.insert(v.id, v.info.addr);
+            }
+            SinkEvent::Close(id) => {
+                self.addresses.remove(id);
+            }
+            SinkEvent::Message(v) => {
+                let message = decode_message(v.stream_kind, v.bytes.clone());
+                (self.callback)(DecodedMessage {
+                    connection_id: v.id,
+                    seq: v.seq,
+                    remote_addr: self.addresses.get(&v.id).cloned(),
+                    stream_id: v.stream_id,
+                    stream_kind: v.stream_kind,
+                    incoming: v.incoming,
+                    time: v.time,
+                    message,
+                });
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum BuildError {
+    #[error("{_0}")]
+    Db(#[from] DbError),
+    #[error("sink {_0}")]
+    Sink(#[from] io::Error),
+}
+
+/// Creates the recorder without reading the environment.
+///
+/// ```no_run
+/// use mina_recorder::api::RecorderBuilder;
+///
+/// let (builder, messages) = RecorderBuilder::new("/tmp/capture").message_stream();
+/// let mut recorder = builder.decrypt_workers(2).build().unwrap();
+/// // feed `recorder.handle(event)` from any source,
+/// // and read decoded messages in another thread
+/// std::thread::spawn(move || messages.into_iter().for_each(|msg| println!("{msg:?}")));
+/// # drop(recorder);
+/// ```
+pub struct RecorderBuilder {
+    db_path: PathBuf,
+    sinks: Option<Vec<SinkConfig>>,
+    custom_sinks: Vec<(String, Box<dyn Sink>)>,
+    decrypt_workers: usize,
+    aggregator: Option<Aggregator>,
+    peer_names: PeerNamesConfig,
+    kube_metadata: bool,
+}
+
+impl RecorderBuilder {
+    pub fn new<P>(db_path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        RecorderBuilder {
+            db_path: db_path.as_ref().to_owned(),
+            sinks: None,
+            custom_sinks: vec![],
+            decrypt_workers: 0,
+            aggregator: None,
+            peer_names: PeerNamesConfig::default(),
+            kube_metadata: false,
+        }
+    }
+
+    /// Like `SINKS` environment variable, by default only the database.
+    pub fn sinks(mut self, configs: Vec<SinkConfig>) -> Self {
+        self.sinks = Some(configs);
+        self
+    }
+
+    /// Add the custom sink in addition to the configured.
+    pub fn sink<S>(mut self, name: &str, sink: S) -> Self
+    where
+        S: Sink,
+    {
+        self.custom_sinks.push((name.to_owned(), Box::new(sink)));
+        self
+    }
+
+    /// Called in a dedicated thread for each decoded message.
+    pub fn on_message<F>(self, callback: F) -> Self
+    where
+        F: FnMut(DecodedMessage) + Send + 'static,
+    {
+        self.sink("callback", CallbackSink::new(callback))
+    }
+
+    /// Like `on_message`, but the messages are sent in the channel.
+    pub fn message_stream(self) -> (Self, mpsc::Receiver<DecodedMessage>) {
+        let (tx, rx) = mpsc::channel();
+        let builder = self.on_message(move |msg| tx.send(msg).unwrap_or_default());
+        (builder, rx)
+    }
+
+    /// Like `DECRYPT_WORKERS` environment variable.
+    pub fn decrypt_workers(mut self, number: usize) -> Self {
+        self.decrypt_workers = number;
+        self
+    }
+
+    pub fn aggregator(mut self, aggregator: Aggregator) -> Self {
+        self.aggregator = Some(aggregator);
+        self
+    }
+
+    /// Like `PEER_NAMES` and `PEER_REVERSE_DNS` environment variables.
+    pub fn peer_names(mut self, config: PeerNamesConfig) -> Self {
+        self.peer_names = config;
+        self
+    }
+
+    /// Like `K8S_METADATA` environment variable.
+    pub fn kube_metadata(mut self, enabled: bool) -> Self {
+        self.kube_metadata = enabled;
+        self
+    }
+
+    pub fn build(self) -> Result<P2pRecorder, BuildError> {
+        let db = DbFacade::open(&self.db_path)?;
+        let core = db.core();
+        let sinks = core.sinks();
+        if let Some(configs) = &self.sinks {
+            sinks.configure(configs)?;
+        }
+        for (name, sink) in self.custom_sinks {
+            sinks.add(name, sink);
+        }
+        Ok(P2pRecorder::with_options(
+            db,
+            false,
+            self.aggregator,
+            self.decrypt_workers,
+            self.peer_names,
+            if self.kube_metadata {
+                KubeMetadata::in_cluster()
+            } else {
+                KubeMetadata::default()
+            },
+        ))
+    }
+}
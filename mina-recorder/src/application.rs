@@ -1,10 +1,13 @@
 use std::{
-    env,
     sync::{mpsc, Mutex, Arc},
     collections::BTreeMap,
-    net::{IpAddr, Ipv6Addr, SocketAddr},
+    net::{IpAddr, SocketAddr},
 };
 
+#[cfg(feature = "bpf")]
+use std::{env, collections::BTreeSet, net::Ipv6Addr};
+
+#[cfg(feature = "bpf")]
 use ebpf_user::{
     kind::{AppItem, AppItemKind},
     HashMapRef,
@@ -12,6 +15,9 @@ use ebpf_user::{
 
 use serde::{Serialize, Deserialize};
 
+#[cfg(feature = "bpf")]
+use crate::event::canonical_ip;
+
 #[derive(Clone, Copy, Debug, Serialize)]
 pub struct StatsBlocked {
     pub packets: u32,
@@ -27,11 +33,15 @@ pub struct StatsItem {
 #[derive(Deserialize)]
 pub struct EnableWhitelist {
     pub ips: Vec<IpAddr>,
+    /// the ports the node listens on, learned from the captured `listen` if empty
+    #[serde(default)]
     pub ports: Vec<u16>,
 }
 
+#[cfg_attr(not(feature = "bpf"), allow(dead_code))]
 enum ApplicationCommand {
     EnableWhitelist(EnableWhitelist),
+    ListenPort(u16),
     DisableWhitelist,
     GetFirewallStats,
     Terminate,
@@ -44,12 +54,16 @@ pub struct Application {
 }
 
 /// It is !Send, so will block thread where created
+#[cfg(feature = "bpf")]
 pub struct ApplicationServer {
     whitelist: HashMapRef<16, 4>,
     whitelist_ports: HashMapRef<2, 4>,
     blocked: HashMapRef<36, 8>,
     crx: mpsc::Receiver<ApplicationCommand>,
     dtx: mpsc::Sender<BTreeMap<StatsItem, StatsBlocked>>,
+    listen_ports: BTreeSet<u16>,
+    // the whitelist is enabled without the ports, it filters the learned ports
+    learned_ports: bool,
 }
 
 impl Application {
@@ -59,6 +73,13 @@ impl Application {
             .unwrap_or_default();
     }
 
+    /// The tracked process listens on the port, the firewall enabled without ports filters it.
+    pub fn on_listen_port(&self, port: u16) {
+        self.ctx
+            .send(ApplicationCommand::ListenPort(port))
+            .unwrap_or_default();
+    }
+
     pub fn disable_firewall(&self) {
         self.ctx
             .send(ApplicationCommand::DisableWhitelist)
@@ -83,6 +104,7 @@ impl Application {
     }
 }
 
+#[cfg(feature = "bpf")]
 impl ApplicationServer {
     fn clear_whitelist(&self) {
         let fd = match self.whitelist.kind() {
@@ -127,17 +149,11 @@ impl ApplicationServer {
             let value = self.blocked.get(&next_key).unwrap();
 
             let src_ip = Ipv6Addr::from(<[u8; 16]>::try_from(&next_key[0..16]).unwrap());
-            let src_ip = src_ip
-                .to_ipv4_mapped()
-                .map(IpAddr::from)
-                .unwrap_or(src_ip.into());
+            let src_ip = canonical_ip(src_ip.into());
             let src_port = u16::from_be_bytes(next_key[16..18].try_into().unwrap());
 
             let dst_ip = Ipv6Addr::from(<[u8; 16]>::try_from(&next_key[18..34]).unwrap());
-            let dst_ip = dst_ip
-                .to_ipv4_mapped()
-                .map(IpAddr::from)
-                .unwrap_or(src_ip.into());
+            let dst_ip = canonical_ip(dst_ip.into());
             let dst_port = u16::from_be_bytes(next_key[34..36].try_into().unwrap());
 
             let key = StatsItem {
@@ -156,6 +172,12 @@ impl ApplicationServer {
         list
     }
 
+    fn insert_port(&self, port: u16) {
+        self.whitelist_ports
+            .insert(port.to_be_bytes(), [0, 0, 0, 1])
+            .unwrap();
+    }
+
     pub fn run(mut self) {
         while let Ok(command) = self.crx.recv() {
             match command {
@@ -177,15 +199,26 @@ impl ApplicationServer {
                         };
                         self.whitelist.insert(ipv6.octets(), [0, 0, 0, 1]).unwrap();
                     }
+                    self.learned_ports = ports.is_empty();
+                    let ports = if self.learned_ports {
+                        self.listen_ports.iter().copied().collect()
+                    } else {
+                        ports
+                    };
                     for &port in &ports {
-                        self.whitelist_ports
-                            .insert(port.to_be_bytes(), [0, 0, 0, 1])
-                            .unwrap();
+                        self.insert_port(port);
                     }
                     log::info!("firewall: whitelist {ips:?}, ports: {ports:?}");
                 }
+                ApplicationCommand::ListenPort(port) => {
+                    if self.listen_ports.insert(port) && self.learned_ports {
+                        self.insert_port(port);
+                        log::info!("firewall: filter the learned port {port}");
+                    }
+                }
                 ApplicationCommand::DisableWhitelist => {
                     self.clear_whitelist();
+                    self.learned_ports = false;
 
                     // insert mark that whitelist is disabled
                     self.whitelist.insert([0; 16], [0, 0, 0, 1]).unwrap();
@@ -201,6 +234,7 @@ impl ApplicationServer {
     }
 }
 
+#[cfg(feature = "bpf")]
 pub fn new(
     whitelist: HashMapRef<16, 4>,
     whitelist_ports: HashMapRef<2, 4>,
@@ -218,6 +252,8 @@ pub fn new(
             blocked,
             crx,
             dtx,
+            listen_ports: BTreeSet::new(),
+            learned_ports: false,
         },
     )
 }
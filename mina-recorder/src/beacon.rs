@@ -0,0 +1,342 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+
+use crate::database::{DbCore, TimeBeacon};
+
+/// Exchange time beacons with the debuggers of other hosts over UDP.
+/// Each debugger must list the others, only the sender of the beacon records the round trip.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BeaconConfig {
+    pub listen: SocketAddr,
+    pub peers: Vec<SocketAddr>,
+    pub interval: Duration,
+    /// `DEBUGGER_NAME`, tells the captures apart
+    pub name: String,
+}
+
+impl BeaconConfig {
+    /// `TIME_BEACON_LISTEN`, disabled if it is not set, `TIME_BEACON_PEERS` comma separated,
+    /// `TIME_BEACON_INTERVAL` seconds, the default is 10.
+    pub fn from_env() -> Option<Self> {
+        let listen = env::var("TIME_BEACON_LISTEN").ok()?;
+        let listen = match listen.parse() {
+            Ok(v) => v,
+            Err(err) => {
+                log::error!("cannot parse TIME_BEACON_LISTEN={listen}: {err}");
+                return None;
+            }
+        };
+        let peers = env::var("TIME_BEACON_PEERS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .filter_map(|s| match s.to_socket_addrs() {
+                Ok(mut addrs) => addrs.next(),
+                Err(err) => {
+                    log::error!("cannot resolve time beacon peer {s}: {err}");
+                    None
+                }
+            })
+            .collect();
+        let interval = env::var("TIME_BEACON_INTERVAL")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(10);
+        Some(BeaconConfig {
+            listen,
+            peers,
+            interval: Duration::from_secs(interval).max(Duration::from_secs(1)),
+            name: env::var("DEBUGGER_NAME").unwrap_or_else(|_| "noname".to_owned()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Packet {
+    Beacon {
+        seq: u64,
+        sent: SystemTime,
+    },
+    Echo {
+        seq: u64,
+        sent: SystemTime,
+        peer_received: SystemTime,
+        peer_sent: SystemTime,
+        name: String,
+    },
+}
+
+impl Packet {
+    const MAGIC: &'static [u8; 8] = b"minabcn1";
+    const BEACON: u8 = 0;
+    const ECHO: u8 = 1;
+
+    fn encode(&self) -> Vec<u8> {
+        let time = |t: &SystemTime| {
+            let d = t.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+            (d.as_nanos() as u64).to_be_bytes()
+        };
+        let mut buf = Self::MAGIC.to_vec();
+        match self {
+            Packet::Beacon { seq, sent } => {
+                buf.push(Self::BEACON);
+                buf.extend_from_slice(&seq.to_be_bytes());
+                buf.extend_from_slice(&time(sent));
+            }
+            Packet::Echo {
+                seq,
+                sent,
+                peer_received,
+                peer_sent,
+                name,
+            } => {
+                buf.push(Self::ECHO);
+                buf.extend_from_slice(&seq.to_be_bytes());
+                buf.extend_from_slice(&time(sent));
+                buf.extend_from_slice(&time(peer_received));
+                buf.extend_from_slice(&time(peer_sent));
+                buf.extend_from_slice(name.as_bytes());
+            }
+        }
+        buf
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let bytes = bytes.strip_prefix(Self::MAGIC.as_slice())?;
+        let (&kind, bytes) = bytes.split_first()?;
+        let u64_at = |i: usize| {
+            let b = bytes.get((i * 8)..((i + 1) * 8))?;
+            Some(u64::from_be_bytes(b.try_into().expect("cannot fail")))
+        };
+        let time_at = |i| Some(SystemTime::UNIX_EPOCH + Duration::from_nanos(u64_at(i)?));
+        match kind {
+            Self::BEACON => Some(Packet::Beacon {
+                seq: u64_at(0)?,
+                sent: time_at(1)?,
+            }),
+            Self::ECHO => Some(Packet::Echo {
+                seq: u64_at(0)?,
+                sent: time_at(1)?,
+                peer_received: time_at(2)?,
+                peer_sent: time_at(3)?,
+                name: String::from_utf8_lossy(bytes.get(32..)?).into_owned(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Sends the beacons every `interval`, echoes the beacons of the peers,
+/// stores the round trips of its own beacons.
+pub fn spawn(
+    config: BeaconConfig,
+    db: DbCore,
+    terminating: Arc<AtomicBool>,
+) -> Option<thread::JoinHandle<()>> {
+    let socket = match UdpSocket::bind(config.listen) {
+        Ok(v) => v,
+        Err(err) => {
+            log::error!("cannot bind time beacon socket {}: {err}", config.listen);
+            return None;
+        }
+    };
+    let timeout = Duration::from_millis(100);
+    if let Err(err) = socket.set_read_timeout(Some(timeout)) {
+        log::error!("cannot set time beacon socket timeout: {err}");
+        return None;
+    }
+    log::info!(
+        "time beacons on {}, peers {:?}",
+        config.listen,
+        config.peers
+    );
+
+    let handle = thread::spawn(move || {
+        let mut seq = 0;
+        let mut next = SystemTime::now();
+        let mut buf = [0; 0x100];
+        while !terminating.load(Ordering::SeqCst) {
+            if SystemTime::now() >= next {
+                next += config.interval;
+                for peer in &config.peers {
+                    let packet = Packet::Beacon {
+                        seq,
+                        sent: SystemTime::now(),
+                    };
+                    if let Err(err) = socket.send_to(&packet.encode(), peer) {
+                        log::debug!("cannot send time beacon to {peer}: {err}");
+                    }
+                }
+                seq += 1;
+            }
+
+            let (len, addr) = match socket.recv_from(&mut buf) {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+            let received = SystemTime::now();
+            match Packet::decode(&buf[..len]) {
+                Some(Packet::Beacon { seq, sent }) => {
+                    let echo = Packet::Echo {
+                        seq,
+                        sent,
+                        peer_received: received,
+                        peer_sent: SystemTime::now(),
+                        name: config.name.clone(),
+                    };
+                    if let Err(err) = socket.send_to(&echo.encode(), addr) {
+                        log::debug!("cannot echo time beacon to {addr}: {err}");
+                    }
+                }
+                Some(Packet::Echo {
+                    seq,
+                    sent,
+                    peer_received,
+                    peer_sent,
+                    name,
+                }) => {
+                    let beacon = TimeBeacon {
+                        peer: addr.to_string(),
+                        peer_name: name,
+                        seq,
+                        sent,
+                        peer_received,
+                        peer_sent,
+                        received,
+                    };
+                    if let Err(err) = db.put_time_beacon(&beacon) {
+                        log::error!("cannot store time beacon: {err}");
+                    }
+                }
+                None => log::debug!("bad time beacon from {addr}"),
+            }
+        }
+    });
+    Some(handle)
+}
+
+/// How the clock of the other debugger relates to the local one.
+#[derive(Debug, Serialize)]
+pub struct PeerAlignment {
+    pub peer_name: String,
+    pub samples: usize,
+    /// add to the local time to get the time of the peer,
+    /// estimated from the round trips with the least delay
+    pub offset_ns: i64,
+    /// the least delay observed, the offset is accurate within half of it
+    pub min_delay_ns: i64,
+    /// how fast the offset changes, the clocks are not disciplined, `None` if too few samples
+    pub drift_ppm: Option<f64>,
+}
+
+impl PeerAlignment {
+    /// The share of the samples with the least delay used for the offset.
+    const BEST: usize = 8;
+
+    /// By the address of the peer.
+    pub fn build<I>(beacons: I) -> BTreeMap<String, Self>
+    where
+        I: IntoIterator<Item = TimeBeacon>,
+    {
+        let mut peers = BTreeMap::<String, Vec<TimeBeacon>>::new();
+        for beacon in beacons {
+            peers.entry(beacon.peer.clone()).or_default().push(beacon);
+        }
+        peers
+            .into_iter()
+            .filter_map(|(peer, beacons)| Some((peer, Self::of(beacons)?)))
+            .collect()
+    }
+
+    fn of(mut beacons: Vec<TimeBeacon>) -> Option<Self> {
+        let samples = beacons.len();
+        let peer_name = beacons.last()?.peer_name.clone();
+        let drift_ppm = Self::drift_ppm(&beacons);
+
+        beacons.sort_by_key(TimeBeacon::delay_ns);
+        let best = &beacons[..(samples / Self::BEST).max(1)];
+        let mut offsets = best.iter().map(TimeBeacon::offset_ns).collect::<Vec<_>>();
+        offsets.sort();
+        Some(PeerAlignment {
+            peer_name,
+            samples,
+            offset_ns: offsets[offsets.len() / 2],
+            min_delay_ns: best[0].delay_ns(),
+            drift_ppm,
+        })
+    }
+
+    // least squares slope of the offset over the time
+    fn drift_ppm(beacons: &[TimeBeacon]) -> Option<f64> {
+        let first = beacons.first()?.sent;
+        let points = beacons
+            .iter()
+            .map(|b| {
+                let x = b
+                    .sent
+                    .duration_since(first)
+                    .unwrap_or_default()
+                    .as_secs_f64();
+                (x, b.offset_ns() as f64 / 1_000.0)
+            })
+            .collect::<Vec<_>>();
+        let n = points.len() as f64;
+        let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+        let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+        let cov = points
+            .iter()
+            .map(|(x, y)| (x - mean_x) * (y - mean_y))
+            .sum::<f64>();
+        let var = points
+            .iter()
+            .map(|(x, _)| (x - mean_x).powi(2))
+            .sum::<f64>();
+        // microseconds per second is ppm
+        (points.len() >= 3 && var > 0.0).then(|| cov / var)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn time_beacons() {
+    let t = |ms: u64| SystemTime::UNIX_EPOCH + Duration::from_millis(1_675_166_400_000 + ms);
+    let packet = Packet::Echo {
+        seq: 7,
+        sent: t(0),
+        peer_received: t(5),
+        peer_sent: t(6),
+        name: "host-b".to_owned(),
+    };
+    assert_eq!(Packet::decode(&packet.encode()), Some(packet));
+    assert_eq!(Packet::decode(b"minabcn1\x00"), None);
+
+    // the peer is 100 ms ahead, the path takes 2 ms one way, one sample is delayed
+    let beacon = |seq: u64, extra: u64| TimeBeacon {
+        peer: "10.0.0.2:9100".to_owned(),
+        peer_name: "host-b".to_owned(),
+        seq,
+        sent: t(seq * 10_000),
+        peer_received: t(seq * 10_000 + 102 + extra),
+        peer_sent: t(seq * 10_000 + 103 + extra),
+        received: t(seq * 10_000 + 5 + extra),
+    };
+    assert_eq!(beacon(0, 0).offset_ns(), 100_000_000);
+    assert_eq!(beacon(0, 0).delay_ns(), 4_000_000);
+    let aligned = PeerAlignment::build((0..9).map(|i| beacon(i, if i == 3 { 50 } else { 0 })));
+    let peer = &aligned["10.0.0.2:9100"];
+    assert_eq!((peer.samples, peer.offset_ns), (9, 100_000_000));
+    assert_eq!(peer.min_delay_ns, 4_000_000);
+    assert!(peer.drift_ppm.unwrap().abs() < 100.0);
+}
@@ -0,0 +1,420 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    net::SocketAddr,
+    sync::{atomic::Ordering, mpsc, Arc},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use serde::Serialize;
+
+use crate::{
+    database::{ConnectionId, DbCore, DbError, DbFacade, RandomnessDatabase},
+    kube::KubeMetadata,
+    peer_names::PeerNamesConfig,
+    ConnectionInfo, EncryptionStatus, EventMetadata, P2pRecorder,
+};
+
+/// The load offered to the pipeline.
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    /// chunks per second, zero is as fast as the pipeline takes them
+    pub rate: u64,
+    /// of the synthetic traffic, the profile has its own connections
+    pub connections: u32,
+    /// bytes of the synthetic chunk
+    pub chunk_size: usize,
+    pub duration: Duration,
+    /// the queue in front of the recorder, it stands for the ring buffer of the kernel module,
+    /// the chunk is dropped if the queue is full
+    pub queue: usize,
+    /// like `DECRYPT_WORKERS`
+    pub workers: usize,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        BenchConfig {
+            rate: 10_000,
+            connections: 64,
+            chunk_size: 0x1000,
+            duration: Duration::from_secs(10),
+            queue: 0x1000,
+            workers: 0,
+        }
+    }
+}
+
+struct ProfileConnection {
+    info: ConnectionInfo,
+    incoming: bool,
+    alias: String,
+    chunks: Vec<(bool, Vec<u8>)>,
+}
+
+/// The raw traffic of a recorded capture, it goes through the pipeline instead of the random bytes.
+/// The capture must keep the raw chunks and the randomness, the bench decrypts them as the recorder did.
+/// Everything is loaded in memory, so use a small capture.
+pub struct Profile {
+    connections: Vec<ProfileConnection>,
+    randomness: Vec<Box<[u8]>>,
+}
+
+impl Profile {
+    pub fn load(core: &DbCore) -> Self {
+        let connections = core
+            .fetch_all_connections()
+            .filter(|(_, cn)| cn.superseded_by.is_none())
+            .map(|(id, cn)| {
+                let chunks = core
+                    .fetch_chunks(ConnectionId(id))
+                    .filter(|(_, header, _)| {
+                        matches!(header.encryption_status, EncryptionStatus::Raw)
+                    })
+                    .map(|(_, header, bytes)| (header.incoming, bytes))
+                    .collect();
+                ProfileConnection {
+                    info: cn.info,
+                    incoming: cn.incoming,
+                    alias: cn.alias,
+                    chunks,
+                }
+            })
+            .filter(|cn| !cn.chunks.is_empty())
+            .collect();
+        Profile {
+            connections,
+            randomness: core.iterate_randomness().collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.connections.is_empty()
+    }
+}
+
+enum Action {
+    Connect { incoming: bool, alias: String },
+    Data { incoming: bool, bytes: Vec<u8> },
+    Disconnect,
+}
+
+struct Item {
+    info: ConnectionInfo,
+    action: Action,
+    offered: Instant,
+}
+
+/// The latency of the stage, microseconds.
+#[derive(Debug, Default, Serialize)]
+pub struct StageLatency {
+    pub p50_us: u64,
+    pub p99_us: u64,
+    pub max_us: u64,
+}
+
+impl StageLatency {
+    fn of(mut samples: Vec<Duration>) -> Self {
+        samples.sort();
+        let at = |percent: usize| {
+            let i = (samples.len() * percent / 100).min(samples.len().saturating_sub(1));
+            samples.get(i).map_or(0, |d| d.as_micros() as u64)
+        };
+        StageLatency {
+            p50_us: at(50),
+            p99_us: at(99),
+            max_us: samples.last().map_or(0, |d| d.as_micros() as u64),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct BenchReport {
+    pub offered_chunks: u64,
+    pub offered_bytes: u64,
+    /// the queue was full, in the real setup the ring buffer overflows
+    pub dropped_chunks: u64,
+    pub processed_chunks: u64,
+    pub elapsed_secs: f64,
+    /// after the generator stopped, until the recorder and its workers are done
+    pub drain_secs: f64,
+    pub chunks_per_sec: f64,
+    pub bytes_per_sec: f64,
+    /// from the generation to the recorder taking the chunk from the queue
+    pub queue_latency: StageLatency,
+    /// the recorder handles the chunk, with the workers it only dispatches
+    pub recorder_latency: StageLatency,
+    pub connections: u64,
+    /// stored, but not decrypted, the random bytes of the synthetic traffic are never decrypted
+    pub undecrypted_bytes: u64,
+    pub messages: u64,
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "offered {} chunks, {} bytes",
+            self.offered_chunks, self.offered_bytes
+        )?;
+        writeln!(
+            f,
+            "processed {} chunks in {:.3} s ({:.3} s drain): {:.0} chunks/s, {:.0} bytes/s",
+            self.processed_chunks,
+            self.elapsed_secs,
+            self.drain_secs,
+            self.chunks_per_sec,
+            self.bytes_per_sec
+        )?;
+        writeln!(f, "dropped at the queue: {}", self.dropped_chunks)?;
+        for (stage, l) in [
+            ("queue", &self.queue_latency),
+            ("recorder", &self.recorder_latency),
+        ] {
+            writeln!(
+                f,
+                "{stage} latency: p50 {} us, p99 {} us, max {} us",
+                l.p50_us, l.p99_us, l.max_us
+            )?;
+        }
+        writeln!(
+            f,
+            "stored {} connections, {} messages, {} bytes not decrypted",
+            self.connections, self.messages, self.undecrypted_bytes
+        )
+    }
+}
+
+// xorshift, the synthetic payload must not compress or repeat
+fn fill(state: &mut u64, bytes: &mut [u8]) {
+    for chunk in bytes.chunks_mut(8) {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        chunk.copy_from_slice(&state.to_ne_bytes()[..chunk.len()]);
+    }
+}
+
+fn synthetic(config: &BenchConfig) -> impl Iterator<Item = (ConnectionInfo, Action)> {
+    let connections = config.connections.max(1);
+    let chunk_size = config.chunk_size;
+    let info = move |i: u32| ConnectionInfo {
+        addr: SocketAddr::from(([10, 0, (i >> 8) as u8, i as u8], 8302)),
+        pid: 1,
+        fd: 100 + i,
+    };
+    let connect = (0..connections).map(move |i| {
+        let action = Action::Connect {
+            incoming: i % 2 == 0,
+            alias: "bench-0.0.0.0".to_owned(),
+        };
+        (info(i), action)
+    });
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let data = (0..).map(move |n: u64| {
+        let i = (n % connections as u64) as u32;
+        let mut bytes = vec![0; chunk_size];
+        fill(&mut state, &mut bytes);
+        let action = Action::Data {
+            incoming: (n / connections as u64) % 2 == 0,
+            bytes,
+        };
+        (info(i), action)
+    });
+    connect.chain(data)
+}
+
+/// The profile connections again and again, each pass as new connections.
+fn replayed(profile: Profile) -> impl Iterator<Item = (ConnectionInfo, Action)> {
+    let connections = Arc::new(profile.connections);
+    (0_u32..).flat_map(move |pass| {
+        let connections = connections.clone();
+        let info = move |cn: &ProfileConnection| ConnectionInfo {
+            fd: cn.info.fd.wrapping_add(pass.wrapping_mul(0x10000)),
+            ..cn.info.clone()
+        };
+        let connect = connections
+            .iter()
+            .map(|cn| {
+                let action = Action::Connect {
+                    incoming: cn.incoming,
+                    alias: cn.alias.clone(),
+                };
+                (info(cn), action)
+            })
+            .collect::<Vec<_>>();
+        let longest = connections.iter().map(|cn| cn.chunks.len()).max();
+        // interleave the connections chunk by chunk
+        let data = (0..longest.unwrap_or_default())
+            .flat_map(|n| {
+                connections.iter().filter_map(move |cn| {
+                    let (incoming, bytes) = cn.chunks.get(n)?;
+                    let action = Action::Data {
+                        incoming: *incoming,
+                        bytes: bytes.clone(),
+                    };
+                    Some((info(cn), action))
+                })
+            })
+            .collect::<Vec<_>>();
+        let disconnect = connections
+            .iter()
+            .map(|cn| (info(cn), Action::Disconnect))
+            .collect::<Vec<_>>();
+        connect.into_iter().chain(data).chain(disconnect)
+    })
+}
+
+/// Generates the load directly into the userspace pipeline, without the kernel module,
+/// and stores it in `db`, use a scratch directory.
+pub fn run(
+    db: DbFacade,
+    config: &BenchConfig,
+    profile: Option<Profile>,
+) -> Result<BenchReport, DbError> {
+    let core = db.core();
+    let messages = db.messages.clone();
+    let source: Box<dyn Iterator<Item = (ConnectionInfo, Action)> + Send> = match profile {
+        Some(profile) if profile.is_empty() => Box::new(std::iter::empty()),
+        Some(profile) => {
+            for bytes in &profile.randomness {
+                db.add_randomness(bytes.to_vec())?;
+            }
+            Box::new(replayed(profile))
+        }
+        None => Box::new(synthetic(config)),
+    };
+    let mut recorder = P2pRecorder::with_options(
+        db,
+        false,
+        None,
+        config.workers,
+        PeerNamesConfig::default(),
+        KubeMetadata::default(),
+    );
+
+    let (tx, rx) = mpsc::sync_channel::<Item>(config.queue.max(1));
+    let generator = thread::spawn({
+        let (rate, duration) = (config.rate, config.duration);
+        move || {
+            let mut report = BenchReport::default();
+            let start = Instant::now();
+            let mut open = vec![];
+            for (info, action) in source {
+                let now = Instant::now();
+                if now - start >= duration {
+                    break;
+                }
+                if let Action::Data { bytes, .. } = &action {
+                    report.offered_chunks += 1;
+                    report.offered_bytes += bytes.len() as u64;
+                    if rate != 0 {
+                        let due = start
+                            + Duration::from_secs_f64(report.offered_chunks as f64 / rate as f64);
+                        if due > now {
+                            thread::sleep(due - now);
+                        }
+                    }
+                }
+                let control = !matches!(action, Action::Data { .. });
+                match &action {
+                    Action::Connect { .. } => open.push(info.clone()),
+                    Action::Disconnect => open.retain(|i| *i != info),
+                    Action::Data { .. } => (),
+                }
+                let item = Item {
+                    info,
+                    action,
+                    offered: Instant::now(),
+                };
+                // the kernel module never drops the connect and the close
+                let sent = if control {
+                    tx.send(item).is_ok()
+                } else {
+                    match tx.try_send(item) {
+                        Ok(()) => true,
+                        Err(mpsc::TrySendError::Full(_)) => {
+                            report.dropped_chunks += 1;
+                            true
+                        }
+                        Err(mpsc::TrySendError::Disconnected(_)) => false,
+                    }
+                };
+                if !sent {
+                    break;
+                }
+            }
+            let stopped = Instant::now();
+            for info in open {
+                let item = Item {
+                    info,
+                    action: Action::Disconnect,
+                    offered: Instant::now(),
+                };
+                tx.send(item).unwrap_or_default();
+            }
+            (report, stopped)
+        }
+    });
+
+    let start = Instant::now();
+    let mut queue_latency = vec![];
+    let mut recorder_latency = vec![];
+    let mut processed_bytes = 0;
+    let mut aliases = BTreeMap::new();
+    while let Ok(Item {
+        info,
+        action,
+        offered,
+    }) = rx.recv()
+    {
+        let taken = Instant::now();
+        let metadata = EventMetadata {
+            id: info,
+            time: SystemTime::now(),
+            better_time: SystemTime::now(),
+            duration: Duration::ZERO,
+        };
+        match action {
+            Action::Connect { incoming, alias } => {
+                if aliases.get(&metadata.id.pid) != Some(&alias) {
+                    aliases.insert(metadata.id.pid, alias.clone());
+                    recorder.on_alias(metadata.id.pid, alias);
+                }
+                recorder.on_connect::<false>(incoming, metadata, 0, String::new());
+            }
+            Action::Data { incoming, bytes } => {
+                queue_latency.push(taken - offered);
+                processed_bytes += bytes.len() as u64;
+                recorder.on_data(incoming, metadata, 0, bytes);
+                recorder_latency.push(taken.elapsed());
+            }
+            Action::Disconnect => recorder.on_disconnect(metadata, 0),
+        }
+    }
+    let (mut report, stopped) = match generator.join() {
+        Ok(v) => v,
+        Err(_) => (BenchReport::default(), Instant::now()),
+    };
+    // waits for the workers
+    drop(recorder);
+    let elapsed = start.elapsed();
+
+    report.processed_chunks = queue_latency.len() as u64;
+    report.elapsed_secs = elapsed.as_secs_f64();
+    report.drain_secs = stopped.elapsed().as_secs_f64();
+    report.chunks_per_sec = report.processed_chunks as f64 / report.elapsed_secs;
+    report.bytes_per_sec = processed_bytes as f64 / report.elapsed_secs;
+    report.queue_latency = StageLatency::of(queue_latency);
+    report.recorder_latency = StageLatency::of(recorder_latency);
+    for (_, cn) in core.fetch_all_connections() {
+        report.connections += 1;
+        for stats in [&cn.stats_in, &cn.stats_out] {
+            report.undecrypted_bytes += stats.total_bytes.saturating_sub(stats.decrypted_bytes);
+        }
+    }
+    report.messages = messages.load(Ordering::SeqCst);
+
+    Ok(report)
+}
@@ -0,0 +1,236 @@
+use std::{
+    env,
+    fs::File,
+    io::BufWriter,
+    process,
+    time::{Duration, SystemTime},
+};
+
+use mina_recorder::{
+    bench::{self, BenchConfig, Profile},
+    bundle::{self, BundleOptions},
+    forward,
+    report::{self, ReportOptions},
+    seal::{Identity, Recipient},
+    database::{CaptureReader, CaptureSummary, CaptureDiff, ConnectionId, DbFacade, DecoderFilter},
+};
+
+fn usage() -> ! {
+    eprintln!("usage: mina-capture compare <capture dir before> <capture dir after>");
+    eprintln!(
+        "       mina-capture bundle [--keys] [--recipient <age1...>] <capture dir> <bundle file> \
+         <connection id>..."
+    );
+    eprintln!("       mina-capture import [--identity <key file>] <bundle file> <capture dir>");
+    eprintln!("       mina-capture unseal --identity <key file> <spool file> <capture dir>");
+    eprintln!("       mina-capture redecode <capture dir>");
+    eprintln!(
+        "       mina-capture report [--hours <n>] [--connections <n>] <capture dir> <report file>"
+    );
+    eprintln!("       mina-capture baseline [--hours <n>] <capture dir> <baseline file>");
+    eprintln!(
+        "       mina-capture bench [--rate <chunks/s>] [--connections <n>] [--chunk-size <bytes>]"
+    );
+    eprintln!("                          [--seconds <n>] [--queue <n>] [--workers <n>]");
+    eprintln!("                          [--profile <capture dir>] <scratch capture dir>");
+    process::exit(1);
+}
+
+fn fail<E>(what: &str, err: E) -> !
+where
+    E: std::fmt::Display,
+{
+    eprintln!("{what}: {err}");
+    process::exit(1);
+}
+
+fn reader(path: &str) -> CaptureReader {
+    CaptureReader::open(path)
+        .unwrap_or_else(|err| fail(&format!("cannot open capture {path}"), err))
+}
+
+fn identity(path: &str) -> Identity {
+    Identity::read(path).unwrap_or_else(|err| fail(&format!("cannot read identity {path}"), err))
+}
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    match args.as_slice() {
+        ["compare", before, after] => {
+            let before = CaptureSummary::collect(&reader(before));
+            let after = CaptureSummary::collect(&reader(after));
+            print!(
+                "{}",
+                CaptureDiff {
+                    before: &before,
+                    after: &after,
+                }
+            );
+        }
+        ["bundle", rest @ ..] => {
+            let (keys, rest) = match rest {
+                ["--keys", rest @ ..] => (true, rest),
+                _ => (false, rest),
+            };
+            let (recipient, rest) = match rest {
+                ["--recipient", key, rest @ ..] => {
+                    let recipient = key
+                        .parse::<Recipient>()
+                        .unwrap_or_else(|err| fail("bad recipient", err));
+                    (Some(recipient), rest)
+                }
+                _ => (Recipient::from_env(), rest),
+            };
+            let (path, output, ids) = match rest {
+                [path, output, ids @ ..] if !ids.is_empty() => (path, output, ids),
+                _ => usage(),
+            };
+            let ids = ids
+                .iter()
+                .map(|id| id.parse().map(ConnectionId))
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap_or_else(|err| fail("bad connection id", err));
+            let reader = reader(path);
+            let file = File::create(output)
+                .unwrap_or_else(|err| fail(&format!("cannot create {output}"), err));
+            let options = BundleOptions {
+                keys,
+                recipient,
+                ..Default::default()
+            };
+            let summary = bundle::export(reader.core(), &ids, &options, BufWriter::new(file))
+                .unwrap_or_else(|err| fail("cannot export", err));
+            println!("{summary:?}");
+        }
+        ["import", rest @ ..] => {
+            let (identity, input, path) = match rest {
+                ["--identity", key, input, path] => (Some(identity(key)), input, path),
+                [input, path] => (None, input, path),
+                _ => usage(),
+            };
+            let file =
+                File::open(input).unwrap_or_else(|err| fail(&format!("cannot open {input}"), err));
+            let db = DbFacade::open(path)
+                .unwrap_or_else(|err| fail(&format!("cannot open capture {path}"), err));
+            let summary = bundle::import(&db, file, identity.as_ref())
+                .unwrap_or_else(|err| fail("cannot import", err));
+            println!("{summary:?}");
+        }
+        ["unseal", "--identity", key, input, path] => {
+            let identity = identity(key);
+            let db = DbFacade::open(path)
+                .unwrap_or_else(|err| fail(&format!("cannot open capture {path}"), err));
+            forward::import_spool(input, &db, identity)
+                .unwrap_or_else(|err| fail("cannot import", err));
+        }
+        ["redecode", path] => {
+            let reader = reader(path);
+            let versions = reader.core().fetch_decoder_versions();
+            for (version, stats) in &versions.versions {
+                println!("decoder version {version}: {} messages", stats.messages);
+            }
+            let filter = DecoderFilter::Below(versions.current);
+            let summary = reader
+                .core()
+                .redecode(filter)
+                .unwrap_or_else(|err| fail("cannot decode", err));
+            println!("{summary:?}");
+        }
+        ["report", rest @ ..] => {
+            let mut options = ReportOptions::default();
+            let mut rest = rest;
+            let number = |s: &str| s.parse::<u64>().unwrap_or_else(|err| fail(s, err));
+            let (path, output) = loop {
+                match rest {
+                    ["--hours", v, tail @ ..] => {
+                        (options.window, rest) = (Duration::from_secs(number(v) * 3600), tail)
+                    }
+                    ["--connections", v, tail @ ..] => {
+                        (options.connections, rest) = (number(v) as usize, tail)
+                    }
+                    [path, output] => break (path, output),
+                    _ => usage(),
+                }
+            };
+            let reader = reader(path);
+            let file = File::create(output)
+                .unwrap_or_else(|err| fail(&format!("cannot create {output}"), err));
+            let summary = report::write(reader.core(), &options, BufWriter::new(file))
+                .unwrap_or_else(|err| fail("cannot write the report", err));
+            println!("{summary:?}");
+        }
+        ["baseline", rest @ ..] => {
+            let (hours, path, output) = match rest {
+                ["--hours", v, path, output] => {
+                    let hours = v.parse::<u64>().unwrap_or_else(|err| fail(v, err));
+                    (Some(hours), path, output)
+                }
+                [path, output] => (None, path, output),
+                _ => usage(),
+            };
+            let reader = reader(path);
+            let mut times = reader
+                .core()
+                .fetch_messages_since(SystemTime::UNIX_EPOCH)
+                .map(|(_, msg)| msg.timestamp);
+            let first = times
+                .next()
+                .unwrap_or_else(|| fail(path, "the capture has no messages"));
+            let until = times.last().unwrap_or(first) + Duration::from_nanos(1);
+            // the last hours of the capture, or the whole capture
+            let since = hours
+                .and_then(|h| until.checked_sub(Duration::from_secs(h * 3600)))
+                .map_or(first, |since| since.max(first));
+            let baseline = reader.core().fetch_baseline(since, until);
+            baseline
+                .store(output)
+                .unwrap_or_else(|err| fail(&format!("cannot write {output}"), err));
+            println!(
+                "{} peers, {} topics",
+                baseline.peers.len(),
+                baseline.topics.len()
+            );
+        }
+        ["bench", rest @ ..] => {
+            let mut config = BenchConfig::default();
+            let mut profile = None;
+            let mut rest = rest;
+            let number = |s: &str| s.parse::<u64>().unwrap_or_else(|err| fail(s, err));
+            let path = loop {
+                match rest {
+                    ["--rate", v, tail @ ..] => (config.rate, rest) = (number(v), tail),
+                    ["--connections", v, tail @ ..] => {
+                        (config.connections, rest) = (number(v) as u32, tail)
+                    }
+                    ["--chunk-size", v, tail @ ..] => {
+                        (config.chunk_size, rest) = (number(v) as usize, tail)
+                    }
+                    ["--seconds", v, tail @ ..] => {
+                        (config.duration, rest) = (Duration::from_secs(number(v)), tail)
+                    }
+                    ["--queue", v, tail @ ..] => (config.queue, rest) = (number(v) as usize, tail),
+                    ["--workers", v, tail @ ..] => {
+                        (config.workers, rest) = (number(v) as usize, tail)
+                    }
+                    ["--profile", v, tail @ ..] => (profile, rest) = (Some(*v), tail),
+                    [path] => break path,
+                    _ => usage(),
+                }
+            };
+            let profile = profile.map(|p| {
+                let profile = Profile::load(reader(p).core());
+                if profile.is_empty() {
+                    fail(p, "the capture has no raw chunks");
+                }
+                profile
+            });
+            let db = DbFacade::open(path)
+                .unwrap_or_else(|err| fail(&format!("cannot open capture {path}"), err));
+            let report = bench::run(db, &config, profile)
+                .unwrap_or_else(|err| fail("cannot run the bench", err));
+            print!("{report}");
+        }
+        _ => usage(),
+    }
+}
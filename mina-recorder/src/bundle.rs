@@ -0,0 +1,425 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{self, BufRead, BufReader, Read, Write},
+    time::SystemTime,
+};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use radiation::{Absorb, AbsorbExt, Emit};
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::{
+    chunk::EncryptionStatus,
+    custom_coding,
+    database::{
+        ConnectionId, DbCore, DbError, DbFacade, DbGroup, Manifest, NoiseHandshake, Redaction,
+    },
+    forward,
+    seal::{self, Identity, Recipient},
+    sink::{
+        ChunkEvent, ConnectionEvent, MessageEvent, PeerIdentityEvent, SinkEvent, SupersededEvent,
+        UpdateEvent,
+    },
+};
+
+/// The beginning of the decompressed bundle.
+const MAGIC: &[u8; 8] = b"minabndl";
+const VERSION: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum BundleError {
+    #[error("{_0}")]
+    Io(#[from] io::Error),
+    #[error("{_0}")]
+    Db(#[from] DbError),
+    #[error("not a bundle")]
+    Magic,
+    #[error("unsupported bundle version {_0}")]
+    Version(u32),
+    #[error("cannot decode bundle item: {_0}")]
+    Decode(String),
+    #[error("the bundle is sealed, the identity is needed")]
+    Sealed,
+}
+
+/// The bundle is the gzip compressed sequence of the items, each prefixed by its length,
+/// the events are the same the recorder sends to the sinks, see `forward`.
+#[derive(Absorb, Emit)]
+enum BundleItem {
+    /// `manifest.json` of the source capture
+    Manifest(String),
+    Event(SinkEvent),
+    NoiseHandshake(NoiseHandshake),
+    Close(CloseItem),
+}
+
+#[derive(Absorb, Emit)]
+struct CloseItem {
+    id: u64,
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    timestamp: SystemTime,
+}
+
+#[derive(Default, Clone)]
+pub struct BundleOptions {
+    /// include the Diffie-Hellman results of the noise handshakes, if the capture has them
+    pub keys: bool,
+    /// redact the payloads of the messages, the raw chunks are redacted entirely,
+    /// the importer cannot decode the redacted messages
+    pub redaction: Redaction,
+    /// seal the bundle, only the holder of the identity can import it
+    pub recipient: Option<Recipient>,
+}
+
+#[derive(Default, Debug, Serialize)]
+pub struct BundleSummary {
+    pub connections: u64,
+    pub messages: u64,
+    pub raw_chunks: u64,
+    pub noise_handshakes: u64,
+}
+
+struct Writer<W>
+where
+    W: Write,
+{
+    inner: GzEncoder<W>,
+}
+
+impl<W> Writer<W>
+where
+    W: Write,
+{
+    fn item(&mut self, item: BundleItem) -> io::Result<()> {
+        let frame = item.chain(vec![]);
+        self.inner.write_all(&(frame.len() as u32).to_be_bytes())?;
+        self.inner.write_all(&frame)
+    }
+}
+
+/// Writes the connections to the bundle, which `import` stores in another capture.
+pub fn export<W>(
+    db: &DbCore,
+    ids: &[ConnectionId],
+    options: &BundleOptions,
+    out: W,
+) -> Result<BundleSummary, BundleError>
+where
+    W: Write,
+{
+    match &options.recipient {
+        Some(recipient) => {
+            let (summary, sealed) = write(db, ids, options, recipient.seal(out)?)?;
+            sealed.finish()?.flush()?;
+            Ok(summary)
+        }
+        None => {
+            let (summary, mut out) = write(db, ids, options, out)?;
+            out.flush()?;
+            Ok(summary)
+        }
+    }
+}
+
+fn write<W>(
+    db: &DbCore,
+    ids: &[ConnectionId],
+    options: &BundleOptions,
+    out: W,
+) -> Result<(BundleSummary, W), BundleError>
+where
+    W: Write,
+{
+    let mut w = Writer {
+        inner: GzEncoder::new(out, Compression::default()),
+    };
+    w.inner.write_all(MAGIC)?;
+    w.inner.write_all(&VERSION.to_be_bytes())?;
+    let mut manifest = db.manifest().clone();
+    if !options.redaction.is_none() {
+        manifest.export_redaction = Some(options.redaction);
+    }
+    let manifest = serde_json::to_string(&manifest).map_err(io::Error::from)?;
+    w.item(BundleItem::Manifest(manifest))?;
+
+    let mut summary = BundleSummary::default();
+    let selected = ids.iter().map(|id| id.0).collect::<BTreeSet<_>>();
+    let mut superseded = vec![];
+    for &id in &selected {
+        let cn = db.fetch_connection(id)?;
+        let cn_id = ConnectionId(id);
+        w.item(BundleItem::Event(SinkEvent::Connection(ConnectionEvent {
+            id,
+            info: cn.info.clone(),
+            incoming: cn.incoming,
+            alias: cn.alias.clone(),
+            timestamp: cn.timestamp,
+        })))?;
+        summary.connections += 1;
+        if let Some((peer_id, timestamp)) = db.fetch_peer_identity(cn_id, cn.info.addr) {
+            let event = PeerIdentityEvent {
+                id,
+                peer_id,
+                timestamp,
+            };
+            w.item(BundleItem::Event(SinkEvent::PeerIdentity(event)))?;
+        }
+        if let Ok(mut handshake) = db.fetch_noise_handshake(cn_id) {
+            if !options.keys {
+                handshake.secrets.clear();
+            }
+            w.item(BundleItem::NoiseHandshake(handshake))?;
+            summary.noise_handshakes += 1;
+        }
+
+        let mut messages = db
+            .fetch_connection_messages(cn_id)
+            .map(|(_, msg)| (msg.offset, msg))
+            .collect::<BTreeMap<_, _>>();
+        for (offset, header, bytes) in db.fetch_chunks(cn_id) {
+            let event = match messages.remove(&offset) {
+                Some(msg) => {
+                    summary.messages += 1;
+                    SinkEvent::Message(MessageEvent {
+                        id,
+                        seq: msg.seq,
+                        stream_id: msg.stream_id,
+                        stream_kind: msg.stream_kind,
+                        incoming: msg.incoming,
+                        time: msg.timestamp,
+                        bytes: db.fetch_blob_redacted(&msg, options.redaction)?,
+                    })
+                }
+                None => {
+                    summary.raw_chunks += 1;
+                    let bytes = match header.encryption_status {
+                        EncryptionStatus::DecryptedNoise => bytes,
+                        _ if options.redaction.is_none() => bytes,
+                        _ => options.redaction.apply(&bytes),
+                    };
+                    SinkEvent::Raw(ChunkEvent {
+                        id,
+                        encryption_status: header.encryption_status,
+                        incoming: header.incoming,
+                        time: header.time,
+                        bytes,
+                    })
+                }
+            };
+            w.item(BundleItem::Event(event))?;
+        }
+
+        for (incoming, stats, layers) in [
+            (true, &cn.stats_in, &cn.layers_in),
+            (false, &cn.stats_out, &cn.layers_out),
+        ] {
+            w.item(BundleItem::Event(SinkEvent::Update(UpdateEvent {
+                id,
+                stats: stats.clone(),
+                incoming,
+                layers: layers.clone(),
+            })))?;
+        }
+        if let Some(by) = cn.superseded_by.filter(|by| selected.contains(&by.0)) {
+            superseded.push(SupersededEvent { id, by: by.0 });
+        }
+    }
+    for event in superseded {
+        w.item(BundleItem::Event(SinkEvent::Superseded(event)))?;
+    }
+    for &id in &selected {
+        let cn = db.fetch_connection(id)?;
+        if cn.timestamp_close != SystemTime::UNIX_EPOCH {
+            let timestamp = cn.timestamp_close;
+            w.item(BundleItem::Close(CloseItem { id, timestamp }))?;
+        }
+    }
+    let out = w.inner.finish()?;
+
+    Ok((summary, out))
+}
+
+/// Stores the connections of the bundle as new connections of the capture,
+/// the messages are decoded again, like the forwarded ones.
+/// The sealed bundle needs the identity of the recipient.
+pub fn import<R>(
+    db: &DbFacade,
+    input: R,
+    identity: Option<&Identity>,
+) -> Result<BundleSummary, BundleError>
+where
+    R: Read,
+{
+    let mut input = BufReader::new(input);
+    if !seal::is_sealed(input.fill_buf()?) {
+        return read(db, input);
+    }
+    match identity {
+        Some(identity) => read(db, identity.open(input)?),
+        None => Err(BundleError::Sealed),
+    }
+}
+
+fn read<R>(db: &DbFacade, input: R) -> Result<BundleSummary, BundleError>
+where
+    R: Read,
+{
+    let mut input = GzDecoder::new(input);
+    let mut magic = [0; 8];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(BundleError::Magic);
+    }
+    let mut version = [0; 4];
+    input.read_exact(&mut version)?;
+    let version = u32::from_be_bytes(version);
+    if version != VERSION {
+        return Err(BundleError::Version(version));
+    }
+
+    let mut summary = BundleSummary::default();
+    let mut groups = BTreeMap::<u64, DbGroup>::new();
+    let mut buf = vec![];
+    loop {
+        let mut len = [0; 4];
+        match input.read_exact(&mut len) {
+            Ok(()) => (),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err.into()),
+        }
+        buf.resize(u32::from_be_bytes(len) as usize, 0);
+        input.read_exact(&mut buf)?;
+        let item =
+            BundleItem::absorb_ext(&buf).map_err(|err| BundleError::Decode(err.to_string()))?;
+        match item {
+            BundleItem::Manifest(manifest) => match serde_json::from_str::<Manifest>(&manifest) {
+                Ok(m) => log::info!(
+                    "bundle of the capture {}, redaction {}, export redaction {}",
+                    m.version,
+                    m.capture_redaction,
+                    m.export_redaction.unwrap_or_default(),
+                ),
+                Err(err) => log::warn!("bundle manifest: {err}"),
+            },
+            BundleItem::NoiseHandshake(mut handshake) => {
+                let id = handshake.connection_id.0;
+                if let Some(group) = groups.get(&id) {
+                    handshake.connection_id = group.id();
+                    group.add_noise_handshake(handshake)?;
+                    summary.noise_handshakes += 1;
+                }
+            }
+            BundleItem::Close(CloseItem { id, timestamp }) => {
+                if let Some(group) = groups.remove(&id) {
+                    set_close_time(db, group, timestamp)?;
+                }
+            }
+            BundleItem::Event(event) => {
+                match &event {
+                    SinkEvent::Connection(_) => summary.connections += 1,
+                    SinkEvent::Message(_) => summary.messages += 1,
+                    SinkEvent::Raw(_) => summary.raw_chunks += 1,
+                    _ => (),
+                }
+                if let Err(err) = forward::apply(event, db, &mut groups) {
+                    log::error!("cannot store bundle event: {err}");
+                }
+            }
+        }
+    }
+    // still open when the bundle was exported
+    for group in groups.into_values() {
+        set_close_time(db, group, SystemTime::UNIX_EPOCH)?;
+    }
+
+    Ok(summary)
+}
+
+/// The group sets the close time when dropped, restore the original one.
+fn set_close_time(db: &DbFacade, group: DbGroup, timestamp: SystemTime) -> Result<(), DbError> {
+    let id = group.id();
+    drop(group);
+    let core = db.core();
+    let mut cn = core.fetch_connection(id.0)?;
+    cn.timestamp_close = timestamp;
+    core.put_cn(id, cn)
+}
+
+#[cfg(test)]
+#[test]
+fn bundle_round_trip() {
+    use std::time::Duration;
+
+    use crate::{
+        database::{CaptureReader, MessageFilter, StreamId, StreamKind},
+        event::ConnectionInfo,
+    };
+
+    let (source, target) = (
+        temp_dir::TempDir::new().unwrap(),
+        temp_dir::TempDir::new().unwrap(),
+    );
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_675_166_400);
+    let mut bundle = vec![];
+    {
+        let db = DbFacade::open(source.path()).unwrap();
+        let info = ConnectionInfo {
+            addr: "1.2.3.4:8302".parse().unwrap(),
+            pid: 1,
+            fd: 10,
+        };
+        let group = db.add(info, true, "node".to_owned(), time).unwrap();
+        group
+            .add_raw(EncryptionStatus::Raw, true, time, b"encrypted")
+            .unwrap();
+        let stream = group.get(StreamId::Forward(1));
+        stream
+            .add_at(true, time, StreamKind::Select, b"/coda/yamux/1.0.0\n")
+            .unwrap();
+        // another connection, not exported
+        let info = ConnectionInfo {
+            addr: "1.2.3.5:8302".parse().unwrap(),
+            pid: 1,
+            fd: 11,
+        };
+        db.add(info, false, "node".to_owned(), time).unwrap();
+        drop(group);
+
+        let summary = export(
+            &db.core(),
+            &[ConnectionId(0)],
+            &BundleOptions::default(),
+            &mut bundle,
+        )
+        .unwrap();
+        assert_eq!((summary.connections, summary.messages), (1, 1));
+        assert_eq!(summary.raw_chunks, 1);
+    }
+    {
+        let db = DbFacade::open(target.path()).unwrap();
+        let summary = import(&db, bundle.as_slice(), None).unwrap();
+        assert_eq!((summary.connections, summary.messages), (1, 1));
+        assert!(import(&db, b"not a bundle".as_slice(), None).is_err());
+    }
+
+    let reader = CaptureReader::open(target.path()).unwrap();
+    let cn = reader.connection(ConnectionId(0)).unwrap();
+    assert_eq!(cn.info.addr, "1.2.3.4:8302".parse().unwrap());
+    assert_eq!(cn.timestamp, time);
+    assert_ne!(cn.timestamp_close, SystemTime::UNIX_EPOCH);
+    assert!(reader.connection(ConnectionId(1)).is_err());
+    let messages = reader
+        .messages(&MessageFilter::default())
+        .collect::<Vec<_>>();
+    assert_eq!(messages.len(), 1);
+    let decoded = reader.decoded(messages[0].0).unwrap();
+    assert_eq!(decoded.message, serde_json::json!("/coda/yamux/1.0.0\n"));
+    let chunks = reader
+        .core()
+        .fetch_chunks(ConnectionId(0))
+        .collect::<Vec<_>>();
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(chunks[0].2, b"encrypted");
+}
@@ -1,6 +1,7 @@
 use std::{fmt, time::SystemTime, io};
 
 use radiation::{Emit, Absorb, AbsorbExt};
+use serde::Serialize;
 use thiserror::Error;
 
 use crate::custom_coding;
@@ -15,7 +16,7 @@ pub struct ChunkHeader {
     pub incoming: bool,
 }
 
-#[derive(Clone, Debug, Absorb, Emit)]
+#[derive(Clone, Debug, Absorb, Emit, Serialize)]
 #[tag(u8)]
 pub enum EncryptionStatus {
     #[tag(1)]
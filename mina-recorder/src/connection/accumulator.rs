@@ -1,65 +1,308 @@
-#[derive(Default)]
-pub struct State {
+use thiserror::Error;
+
+/// How the stream marks the boundaries of the messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    /// unsigned varint length prefix, multistream select and protobuf
+    Varint,
+    /// u16 big endian length prefix, noise
+    U16Be,
+    /// u32 big endian length prefix
+    U32Be,
+    /// u64 little endian length prefix, binprot rpc
+    U64Le,
+    /// no prefix, every message is this size
+    Fixed(usize),
+}
+
+#[derive(Debug, Error)]
+pub enum FrameError {
+    #[error("bad varint length prefix: {0}")]
+    Varint(unsigned_varint::decode::Error),
+    #[error("message of {0} bytes is bigger than the limit")]
+    TooBig(u64),
+}
+
+impl Framing {
+    /// The prefix claiming a bigger message is garbage, do not wait for such a message.
+    pub const MAX: usize = 0x4000_0000;
+
+    /// The size of the prefix and the size of the message after it,
+    /// `None` if the prefix is incomplete.
+    pub fn decode(&self, bytes: &[u8]) -> Result<Option<(usize, usize)>, FrameError> {
+        use unsigned_varint::decode;
+
+        fn prefix<const N: usize>(bytes: &[u8]) -> Option<[u8; N]> {
+            bytes.get(..N)?.try_into().ok()
+        }
+
+        let (l0, l1) = match *self {
+            Framing::Varint => match decode::u64(bytes) {
+                Ok((l1, remaining)) => (bytes.len() - remaining.len(), l1),
+                Err(decode::Error::Insufficient) => return Ok(None),
+                Err(err) => return Err(FrameError::Varint(err)),
+            },
+            Framing::U16Be => match prefix(bytes) {
+                Some(b) => (2, u16::from_be_bytes(b) as u64),
+                None => return Ok(None),
+            },
+            Framing::U32Be => match prefix(bytes) {
+                Some(b) => (4, u32::from_be_bytes(b) as u64),
+                None => return Ok(None),
+            },
+            Framing::U64Le => match prefix(bytes) {
+                Some(b) => (8, u64::from_le_bytes(b)),
+                None => return Ok(None),
+            },
+            Framing::Fixed(size) => (0, size as u64),
+        };
+        if l1 > Self::MAX as u64 {
+            return Err(FrameError::TooBig(l1));
+        }
+        Ok(Some((l0, l1 as usize)))
+    }
+
+    /// The message of the frame without the prefix.
+    pub fn body<'a>(&self, frame: &'a [u8]) -> &'a [u8] {
+        match self.decode(frame) {
+            Ok(Some((l0, _))) => &frame[l0..],
+            _ => frame,
+        }
+    }
+}
+
+/// Splits the stream of one direction into messages, the frame includes its prefix.
+pub struct Framer {
+    framing: Framing,
     pos: usize,
     acc: Vec<u8>,
 }
 
-impl State {
+impl Framer {
+    pub fn new(framing: Framing) -> Self {
+        Framer {
+            framing,
+            pos: 0,
+            acc: vec![],
+        }
+    }
+
     #[cfg(test)]
     pub fn pos(&self) -> usize {
         self.pos
     }
 
-    /// Try accept immediately, without accumulation
-    /// if returns false, the `bytes` contains full message, and accumulator is empty
-    pub fn extend<F>(&mut self, decode_size: F, bytes: &[u8]) -> bool
-    where
-        // try decode size of message from bytes,
-        // if bytes contains not enough information return `None`
-        // otherwise return separately size of size information and size of msg
-        F: Fn(&[u8]) -> Option<(usize, usize)>,
-    {
-        let original = <&[u8]>::clone(&bytes);
-        if self.acc.is_empty() {
-            if let Some((l0, l1)) = decode_size(bytes) {
-                if l0 + l1 == bytes.len() {
-                    return false;
-                }
-            }
+    /// Bytes of the incomplete message.
+    pub fn pending(&self) -> usize {
+        self.acc.len() - self.pos
+    }
+
+    /// The bytes not yet taken as frames.
+    pub fn buffered(&self) -> &[u8] {
+        &self.acc[self.pos..]
+    }
+
+    /// Nothing is buffered and the `bytes` is exactly one message,
+    /// the caller can use it in place, without accumulation.
+    pub fn is_whole(&self, bytes: &[u8]) -> bool {
+        self.pending() == 0
+            && matches!(
+                self.framing.decode(bytes),
+                Ok(Some((l0, l1))) if l0 + l1 == bytes.len()
+            )
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        if self.pending() == 0 {
+            self.acc.clear();
+            self.pos = 0;
         }
-        self.acc.extend_from_slice(original);
-        true
+        self.acc.extend_from_slice(bytes);
+    }
+
+    /// Drop the first `n` buffered bytes, the caller parsed them on its own.
+    pub fn skip(&mut self, n: usize) {
+        self.pos = (self.pos + n).min(self.acc.len());
     }
 
-    fn drop_buffer(&mut self) {
-        self.acc = self.acc[self.pos..].to_vec();
+    /// Take the buffered bytes, the stream is not framed anymore.
+    pub fn take(&mut self) -> Vec<u8> {
+        self.acc.drain(..self.pos);
         self.pos = 0;
+        std::mem::take(&mut self.acc)
     }
 
-    pub fn next_msg<F>(&mut self, decode_size: F) -> Option<&[u8]>
-    where
-        F: Fn(&[u8]) -> Option<(usize, usize)>,
-    {
-        if self.acc.is_empty() || self.acc.len() == self.pos {
-            self.drop_buffer();
-            return None;
+    /// The next whole message with its prefix, `None` if the buffered bytes are not enough.
+    /// The bad prefix loses the boundaries, the buffered bytes are returned with the error.
+    pub fn next_frame(&mut self) -> Result<Option<&mut [u8]>, (FrameError, Vec<u8>)> {
+        let bytes = &self.acc[self.pos..];
+        let size = match self.framing.decode(bytes) {
+            Err(err) => return Err((err, self.take())),
+            Ok(None) => None,
+            // zero sized frame would never advance
+            Ok(Some((l0, l1))) => Some(l0 + l1).filter(|s| *s != 0 && *s <= bytes.len()),
+        };
+        match size {
+            None => {
+                self.acc.drain(..self.pos);
+                self.pos = 0;
+                Ok(None)
+            }
+            Some(size) => {
+                let start = self.pos;
+                self.pos += size;
+                Ok(Some(&mut self.acc[start..self.pos]))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Framer, Framing, FrameError};
+
+    /// Xorshift, the fuzz tests must be reproducible.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    const FRAMINGS: [Framing; 5] = [
+        Framing::Varint,
+        Framing::U16Be,
+        Framing::U32Be,
+        Framing::U64Le,
+        Framing::Fixed(7),
+    ];
+
+    fn encode(framing: Framing, body: &[u8]) -> Vec<u8> {
+        use unsigned_varint::encode;
+
+        let mut bytes = match framing {
+            Framing::Varint => encode::usize(body.len(), &mut encode::usize_buffer()).to_vec(),
+            Framing::U16Be => (body.len() as u16).to_be_bytes().to_vec(),
+            Framing::U32Be => (body.len() as u32).to_be_bytes().to_vec(),
+            Framing::U64Le => (body.len() as u64).to_le_bytes().to_vec(),
+            Framing::Fixed(_) => vec![],
+        };
+        bytes.extend_from_slice(body);
+        bytes
+    }
+
+    #[test]
+    fn fuzz_split() {
+        for framing in FRAMINGS {
+            for seed in 1..=200 {
+                let mut rng = Rng(seed);
+                let bodies = (0..=rng.below(8))
+                    .map(|_| {
+                        let len = match framing {
+                            Framing::Fixed(size) => size,
+                            _ => rng.below(300),
+                        };
+                        (0..len).map(|_| rng.next() as u8).collect::<Vec<u8>>()
+                    })
+                    .collect::<Vec<_>>();
+                let expected = bodies
+                    .iter()
+                    .map(|body| encode(framing, body))
+                    .collect::<Vec<_>>();
+                let stream = expected.concat();
+
+                let mut framer = Framer::new(framing);
+                let mut frames = vec![];
+                let mut rest = stream.as_slice();
+                while !rest.is_empty() {
+                    let (chunk, r) = rest.split_at(rng.below(rest.len()) + 1);
+                    rest = r;
+                    if framer.is_whole(chunk) {
+                        frames.push(chunk.to_vec());
+                        continue;
+                    }
+                    framer.push(chunk);
+                    while let Some(frame) = framer.next_frame().unwrap() {
+                        frames.push(frame.to_vec());
+                    }
+                }
+                assert_eq!(framer.pending(), 0);
+                assert_eq!(frames, expected, "{framing:?}, seed {seed}");
+            }
         }
+    }
 
-        let bytes = &self.acc.as_slice()[self.pos..];
-        let (l0, l1) = decode_size(bytes)?;
-        if bytes.len() >= l0 + l1 {
-            let new_pos = self.acc.len() - bytes.len() + l0 + l1;
-            if self.pos == new_pos {
-                self.drop_buffer();
-                None
-            } else {
-                let s = &self.acc[self.pos..new_pos];
-                self.pos = new_pos;
-                Some(s)
+    #[test]
+    fn fuzz_garbage() {
+        for framing in FRAMINGS {
+            for seed in 1..=200 {
+                let mut rng = Rng(seed);
+                let stream = (0..rng.below(0x400))
+                    .map(|_| rng.next() as u8)
+                    .collect::<Vec<u8>>();
+
+                let mut framer = Framer::new(framing);
+                let mut total = 0;
+                for chunk in stream.chunks(rng.below(64) + 1) {
+                    framer.push(chunk);
+                    loop {
+                        match framer.next_frame() {
+                            Ok(Some(frame)) => total += frame.len(),
+                            Ok(None) => break,
+                            Err((_, dropped)) => total += dropped.len(),
+                        }
+                    }
+                }
+                assert_eq!(
+                    total + framer.pending(),
+                    stream.len(),
+                    "{framing:?}, seed {seed}"
+                );
             }
-        } else {
-            self.drop_buffer();
-            None
         }
     }
+
+    #[test]
+    fn boundaries() {
+        let mut framer = Framer::new(Framing::Varint);
+        // incomplete varint, wait
+        framer.push(&[0x80]);
+        assert!(framer.next_frame().unwrap().is_none());
+        assert_eq!(framer.pending(), 1);
+        framer.push(&[0x01]);
+        assert!(framer.next_frame().unwrap().is_none());
+        framer.push(&[0; 0x80]);
+        assert_eq!(framer.next_frame().unwrap().unwrap().len(), 0x82);
+
+        // not minimal varint
+        framer.push(&[0x81, 0x00, 0x01]);
+        let (err, dropped) = framer.next_frame().unwrap_err();
+        assert!(matches!(err, FrameError::Varint(_)));
+        assert_eq!(dropped, [0x81, 0x00, 0x01]);
+        assert_eq!(framer.pending(), 0);
+
+        let mut framer = Framer::new(Framing::U64Le);
+        framer.push(&u64::MAX.to_le_bytes());
+        let (err, _) = framer.next_frame().unwrap_err();
+        assert!(matches!(err, FrameError::TooBig(u64::MAX)));
+
+        let mut framer = Framer::new(Framing::U16Be);
+        assert!(framer.is_whole(&[0, 1, 0xff]));
+        assert!(!framer.is_whole(&[0, 1, 0xff, 0]));
+        framer.push(&[0, 1]);
+        assert!(!framer.is_whole(&[0, 1, 0xff]));
+        framer.skip(2);
+        assert_eq!(framer.pending(), 0);
+        framer.push(&[0, 2, 1]);
+        assert_eq!(framer.take(), [0, 2, 1]);
+        assert!(framer.buffered().is_empty());
+    }
 }
@@ -1,26 +1,31 @@
-use prost::decode_length_delimiter;
+use super::accumulator::{Framer, Framing, FrameError};
 
-use super::accumulator;
+pub struct State(Framer);
 
-#[derive(Default)]
-pub struct State(accumulator::State);
-
-impl State {
-    fn decode_size(mut bytes: &[u8]) -> Option<(usize, usize)> {
-        let original = <&[u8]>::clone(&bytes);
-        let l1 = decode_length_delimiter(&mut bytes).ok()?;
-        let l0 = original.len() - bytes.len();
-        Some((l0, l1))
+impl Default for State {
+    fn default() -> Self {
+        State(Framer::new(Framing::Varint))
     }
+}
 
+impl State {
     /// Try accept immediately, without accumulation
     /// if returns false, the `bytes` contains full message, and accumulator is empty
     pub fn extend(&mut self, bytes: &[u8]) -> bool {
-        self.0.extend(Self::decode_size, bytes)
+        if self.0.is_whole(bytes) {
+            false
+        } else {
+            self.0.push(bytes);
+            true
+        }
+    }
+
+    pub fn next_msg(&mut self) -> Result<Option<&[u8]>, (FrameError, Vec<u8>)> {
+        self.0.next_frame().map(|frame| frame.map(|frame| &*frame))
     }
 
-    pub fn next_msg(&mut self) -> Option<&[u8]> {
-        self.0.next_msg(Self::decode_size)
+    pub fn pending(&self) -> usize {
+        self.0.pending()
     }
 }
 
@@ -32,10 +37,10 @@ mod tests {
         let msg = hex::decode(msg).unwrap();
         let mut st = super::State::default();
         assert!(st.extend(&msg));
-        assert!(st.next_msg().is_some());
+        assert!(st.next_msg().unwrap().is_some());
         assert_ne!(st.0.pos(), 0);
-        assert!(st.next_msg().is_some());
-        assert!(st.next_msg().is_none());
+        assert!(st.next_msg().unwrap().is_some());
+        assert!(st.next_msg().unwrap().is_none());
         assert_eq!(st.0.pos(), 0);
     }
 }
@@ -1,25 +1,61 @@
+use std::{net::SocketAddr, time::SystemTime};
+
 use super::accumulator;
 
 mod meshsub;
 mod rpc;
 
 use crate::{
-    database::{StreamId, StreamKind, ConnectionStats, DbStream},
+    database::{StreamId, StreamKind, ConnectionStats, DbStream, Layer, MessageTiming},
     stats::update_block_stats,
 };
 
-use super::{HandleData, DirectedId, DynamicProtocol, Cx, Db, DbResult};
+use super::{HandleData, DirectedId, DynamicProtocol, Cx, Db, DbResult, DecoderError};
 
 pub struct State {
     stream_id: StreamId,
     kind: StreamKind,
     rpc_state: Option<rpc::State>,
     meshsub_state: Option<meshsub::State>,
+    timing: Timing,
+}
+
+/// The reads of the incomplete message of each direction, the first one and the last one.
+#[derive(Default)]
+struct Timing {
+    pending: [Option<(SystemTime, SystemTime, u32)>; 2],
+}
+
+impl Timing {
+    fn on_read(&mut self, incoming: bool, time: SystemTime) {
+        let pending = &mut self.pending[incoming as usize];
+        match pending {
+            None => *pending = Some((time, time, 1)),
+            // the frames of the same read
+            Some((_, last, _)) if *last == time => (),
+            Some((_, last, chunks)) => {
+                *last = time;
+                *chunks += 1;
+            }
+        }
+    }
+
+    /// The message is complete, the next one may begin in the same read.
+    fn complete(&mut self, incoming: bool, time: SystemTime) -> MessageTiming {
+        let pending = self.pending[incoming as usize].replace((time, time, 1));
+        let (first_byte, _, chunks) = pending.unwrap_or((time, time, 1));
+        MessageTiming { first_byte, chunks }
+    }
+
+    /// Nothing is left of the read, the next message begins in the next read.
+    fn clear(&mut self, incoming: bool) {
+        self.pending[incoming as usize] = None;
+    }
 }
 
 impl DynamicProtocol for State {
     fn from_name(name: &str, stream_id: StreamId) -> Self {
-        let kind = name.parse().expect("cannot fail");
+        let kind = name.parse().unwrap_or(StreamKind::Unknown);
         State {
             stream_id,
             kind,
@@ -37,6 +73,7 @@ impl DynamicProtocol for State {
                     None
                 }
             },
+            timing: Timing::default(),
         }
     }
 }
@@ -44,39 +81,68 @@ impl DynamicProtocol for State {
 impl HandleData for State {
     #[inline(never)]
     fn on_data(&mut self, id: DirectedId, bytes: &mut [u8], cx: &Cx, db: &Db) -> DbResult<()> {
+        db.count(Layer::Payload, id.incoming, bytes.len());
         let stream = db.get(self.stream_id);
-        if self.kind == StreamKind::Rpc {
-            let st = self.rpc_state.as_mut().expect("must exist");
+        let (incoming, time) = (id.incoming, id.metadata.time);
+        self.timing.on_read(incoming, time);
+        if let Some(st) = &mut self.rpc_state {
             match st.extend(bytes) {
-                Err(err) => log::error!("{id} {}: {err}", db.id()),
+                Err(err) => {
+                    let err = DecoderError::new(Layer::Payload, db.id(), &id, err);
+                    db.report(err.stream(self.stream_id).bytes(bytes));
+                }
                 Ok(None) => loop {
                     match st.next_msg() {
-                        Err(err) => log::error!("{id} {}: {err}", db.id()),
+                        Err(err) => {
+                            let err = DecoderError::new(Layer::Payload, db.id(), &id, err);
+                            db.report(err.stream(self.stream_id));
+                        }
                         Ok(None) => break,
                         Ok(Some(msg)) => {
-                            if let Err(err) = stream.add(&id, self.kind, &msg) {
+                            let timing = self.timing.complete(incoming, time);
+                            if let Err(err) = stream.add_timed(&id, self.kind, &msg, timing) {
                                 log::error!("{id} {}: {err}", db.id());
                             }
                         }
                     }
                 },
                 Ok(Some(msg)) => {
-                    if let Err(err) = stream.add(&id, self.kind, &msg) {
+                    let timing = self.timing.complete(incoming, time);
+                    if let Err(err) = stream.add_timed(&id, self.kind, &msg, timing) {
                         log::error!("{id} {}: {err}, {}", db.id(), hex::encode(bytes));
                     }
                 }
             }
-        } else if self.kind == StreamKind::Meshsub {
-            let st = self.meshsub_state.as_mut().expect("must exist");
+            if st.pending().0 == 0 {
+                self.timing.clear(incoming);
+            }
+        } else if let Some(st) = &mut self.meshsub_state {
             if !st.extend(bytes) {
-                meshsub_sink(&id, db, &stream, bytes, cx);
+                let timing = self.timing.complete(incoming, time);
+                meshsub_sink(&id, db, &stream, bytes, timing, cx);
             } else {
-                while let Some(slice) = st.next_msg() {
-                    meshsub_sink(&id, db, &stream, slice, cx);
+                loop {
+                    match st.next_msg() {
+                        Ok(Some(slice)) => {
+                            let timing = self.timing.complete(incoming, time);
+                            meshsub_sink(&id, db, &stream, slice, timing, cx);
+                        }
+                        Ok(None) => break,
+                        Err((err, dropped)) => {
+                            let err = DecoderError::new(Layer::Payload, db.id(), &id, err);
+                            db.report(err.stream(self.stream_id).bytes(&dropped));
+                            break;
+                        }
+                    }
                 }
             }
+            if st.pending() == 0 {
+                self.timing.clear(incoming);
+            }
         } else {
-            stream.add(&id, self.kind, bytes)?;
+            let timing = self.timing.complete(incoming, time);
+            self.timing.clear(incoming);
+            stream.add_timed(&id, self.kind, bytes, timing)?;
         }
 
         db.update(
@@ -89,17 +155,37 @@ impl HandleData for State {
             id.incoming,
         )
     }
+
+    fn snapshot(&self) -> serde_json::Value {
+        let (pending_bytes, pending_requests) = match (&self.rpc_state, &self.meshsub_state) {
+            (Some(st), _) => st.pending(),
+            (None, Some(st)) => (st.pending(), 0),
+            (None, None) => (0, 0),
+        };
+        serde_json::json!({
+            "layer": self.kind.to_string(),
+            "pending_bytes": pending_bytes,
+            "pending_requests": pending_requests,
+        })
+    }
 }
 
-fn meshsub_sink(id: &DirectedId, db: &Db, stream: &DbStream, msg: &[u8], cx: &Cx) {
+fn meshsub_sink(
+    id: &DirectedId,
+    db: &Db,
+    stream: &DbStream,
+    msg: &[u8],
+    timing: MessageTiming,
+    cx: &Cx,
+) {
     let node_address = {
         let lock = cx.apps.lock();
         lock.get(&id.metadata.id.pid)
             .map(|(_, p)| *p)
-            .unwrap_or("0.0.0.0:8302".parse().expect("valid constant socket addr"))
+            .unwrap_or(SocketAddr::from(([0, 0, 0, 0], 8302)))
     };
     let mut lock = cx.stats_state.lock();
-    match stream.add(id, StreamKind::Meshsub, msg) {
+    match stream.add_timed(id, StreamKind::Meshsub, msg, timing) {
         Ok(message_id) => {
             if let Err(err) = update_block_stats(
                 message_id.0,
@@ -129,20 +215,49 @@ fn meshsub_sink(id: &DirectedId, db: &Db, stream: &DbStream, msg: &[u8], cx: &Cx
             // perform io, after lock is dropped and mutex unlock
             if let Some(aggregator) = &cx.aggregator {
                 for event in events {
-                    aggregator.post_event(&event);
+                    let pods = cx.kube.references(
+                        id.metadata.id.pid,
+                        node_address.ip(),
+                        [event.sender_addr.ip(), event.receiver_addr.ip()],
+                    );
+                    aggregator.post_event(&event, &pods);
                 }
             }
             if b {
-                cx.db
-                    .stats(block_stat.height, node_address, &block_stat)
-                    .unwrap();
+                if let Err(err) = cx.db.stats(block_stat.height, node_address, &block_stat) {
+                    log::error!("{id} {}: {err}", db.id());
+                }
             }
             if t {
                 if let Some(stat) = tx_state {
-                    cx.db.stats_tx(block_stat.height, &stat).unwrap();
+                    if let Err(err) = cx.db.stats_tx(block_stat.height, &stat) {
+                        log::error!("{id} {}: {err}", db.id());
+                    }
                 }
             }
         }
         Err(err) => log::error!("{id} {}: {err}, {}", db.id(), hex::encode(msg)),
     }
 }
+
+#[cfg(test)]
+#[test]
+fn timing() {
+    use std::time::Duration;
+
+    let t = |ms| SystemTime::UNIX_EPOCH + Duration::from_millis(ms);
+    let mut timing = Timing::default();
+    timing.on_read(true, t(1));
+    timing.on_read(true, t(1));
+    timing.on_read(false, t(2));
+    timing.on_read(true, t(5));
+    let message = timing.complete(true, t(5));
+    assert_eq!((message.first_byte, message.chunks), (t(1), 2));
+    // the next message begins in the same read
+    let message = timing.complete(true, t(5));
+    assert_eq!((message.first_byte, message.chunks), (t(5), 1));
+    timing.clear(true);
+
+    let message = timing.complete(false, t(7));
+    assert_eq!((message.first_byte, message.chunks), (t(2), 1));
+}
@@ -5,21 +5,28 @@ use std::{
 };
 
 use mina_p2p_messages::{
-    binprot::{BinProtRead, BinProtWrite},
+    binprot::{self, BinProtRead, BinProtWrite},
     string::CharString as BString,
     rpc_kernel::{QueryHeader, MessageHeader, ResponseHeader},
-    utils,
 };
 use thiserror::Error;
 
-use super::accumulator;
+use super::accumulator::{Framer, Framing, FrameError};
 
-#[derive(Default)]
 pub struct State {
-    acc: accumulator::State,
+    acc: Framer,
     pending: BTreeMap<i64, Header>,
 }
 
+impl Default for State {
+    fn default() -> Self {
+        State {
+            acc: Framer::new(Framing::U64Le),
+            pending: BTreeMap::default(),
+        }
+    }
+}
+
 struct Header {
     tag: BString,
     version: i32,
@@ -31,29 +38,32 @@ pub enum Error {
     ResponseWithoutRequest { id: i64 },
     #[error("cannot decode size")]
     DecodeSize,
+    #[error("{0}")]
+    Frame(#[from] FrameError),
     #[error("write query header error: {0}")]
     WriteQueryHeader(#[from] io::Error),
+    #[error("read message header: {0}")]
+    ReadHeader(binprot::Error),
 }
 
 impl State {
-    // u64 in little endian
-    fn decode_size(bytes: &[u8]) -> Option<(usize, usize)> {
-        let mut s = Cursor::new(bytes);
-        let l1 = utils::stream_decode_size(&mut s).ok()?;
-        let l0 = s.position() as usize;
-        Some((l0, l1))
-    }
-
     pub fn extend<'a>(&mut self, bytes: &'a mut [u8]) -> Result<Option<Cow<'a, [u8]>>, Error> {
-        if self.acc.extend(Self::decode_size, bytes) {
-            Ok(None)
-        } else {
+        if self.acc.is_whole(bytes) {
             self.post_process(bytes)
+        } else {
+            self.acc.push(bytes);
+            Ok(None)
         }
     }
 
+    /// Bytes of the incomplete message and the requests without response.
+    pub fn pending(&self) -> (usize, usize) {
+        (self.acc.pending(), self.pending.len())
+    }
+
     pub fn next_msg(&mut self) -> Result<Option<Vec<u8>>, Error> {
-        let mut msg = match self.acc.next_msg(Self::decode_size) {
+        // the boundaries are lost, the dropped bytes cannot be decoded anyway
+        let mut msg = match self.acc.next_frame().map_err(|(err, _)| err)? {
             Some(v) => v.to_vec(),
             None => return Ok(None),
         };
@@ -61,13 +71,10 @@ impl State {
     }
 
     fn post_process<'a>(&mut self, bytes: &'a mut [u8]) -> Result<Option<Cow<'a, [u8]>>, Error> {
-        let (l0, _) = Self::decode_size(bytes).ok_or(Error::DecodeSize)?;
+        let (l0, _) = Framing::U64Le.decode(bytes)?.ok_or(Error::DecodeSize)?;
         let mut stream = Cursor::new(&mut bytes[l0..]);
         match MessageHeader::binprot_read(&mut stream) {
-            Err(err) => {
-                log::error!("{err}");
-                Ok(None)
-            }
+            Err(err) => Err(Error::ReadHeader(err)),
             Ok(MessageHeader::Heartbeat) => Ok(None),
             Ok(MessageHeader::Query(QueryHeader { tag, version, id })) => {
                 let header = Header { tag, version };
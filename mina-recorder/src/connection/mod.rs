@@ -1,16 +1,26 @@
 use super::{
     recorder::Cx,
     event::DirectedId,
-    database::{DbGroup as Db, DbResult, StreamId},
+    database::{DbGroup as Db, DbResult, DecoderError, StreamId},
 };
 
 pub trait DynamicProtocol {
     fn from_name(name: &str, stream_id: StreamId) -> Self;
+
+    /// Whether `from_name` accepts the protocol the peers agreed on.
+    fn supports(_name: &str) -> bool {
+        true
+    }
 }
 
 pub trait HandleData {
     // TODO: use Cow for bytes
     fn on_data(&mut self, id: DirectedId, bytes: &mut [u8], cx: &Cx, db: &Db) -> DbResult<()>;
+
+    /// State of the decoder and its inner layers, for diagnostics.
+    fn snapshot(&self) -> serde_json::Value {
+        serde_json::Value::Null
+    }
 }
 
 mod accumulator;
@@ -1,14 +1,16 @@
 use std::{collections::BTreeMap, borrow::Cow, task::Poll, fmt};
 
-use crate::database::StreamKind;
+use crate::database::{StreamKind, StreamEndKind, Layer};
 
-use super::{HandleData, DirectedId, DynamicProtocol, Cx, Db, DbResult, StreamId};
+use super::{HandleData, DirectedId, DynamicProtocol, Cx, Db, DbResult, DecoderError, StreamId};
 
 #[derive(Default)]
 pub struct State<Inner> {
     incoming: acc::State<true>,
     outgoing: acc::State<false>,
     inners: BTreeMap<StreamId, Status<Inner>>,
+    // headers and stream names since the last report
+    framing: usize,
 }
 
 impl<Inner> DynamicProtocol for State<Inner> {
@@ -18,6 +20,7 @@ impl<Inner> DynamicProtocol for State<Inner> {
             incoming: acc::State::default(),
             outgoing: acc::State::default(),
             inners: BTreeMap::default(),
+            framing: 0,
         }
     }
 }
@@ -28,6 +31,16 @@ pub enum Status<Inner> {
     OutgoingOnly(Inner),
 }
 
+impl<Inner> AsRef<Inner> for Status<Inner> {
+    fn as_ref(&self) -> &Inner {
+        match self {
+            Status::Duplex(inner) => inner,
+            Status::IncomingOnly(inner) => inner,
+            Status::OutgoingOnly(inner) => inner,
+        }
+    }
+}
+
 impl<Inner> AsMut<Inner> for Status<Inner> {
     fn as_mut(&mut self) -> &mut Inner {
         match self {
@@ -50,6 +63,8 @@ mod acc {
         Msg,
         Close,
         Reset,
+        /// the flag 7 is not defined, the frame is skipped
+        Invalid(u64),
     }
 
     #[derive(Default)]
@@ -60,10 +75,15 @@ mod acc {
     pub struct Output<'a> {
         pub tag: Tag,
         pub stream_id: StreamId,
+        pub header_len: usize,
         pub bytes: Cow<'a, [u8]>,
     }
 
     impl<const INCOMING: bool> State<INCOMING> {
+        pub fn pending(&self) -> usize {
+            self.acc.len()
+        }
+
         pub fn accumulate<'a>(&mut self, bytes: &'a [u8]) -> Poll<Output<'a>> {
             let r = |bytes: &[u8]| -> Option<(Tag, StreamId, usize, usize)> {
                 let (v, remaining) = decode::u64(bytes).ok()?;
@@ -75,8 +95,7 @@ mod acc {
                     1 | 2 => Tag::Msg,
                     3 | 4 => Tag::Close,
                     5 | 6 => Tag::Reset,
-                    7 => panic!("wrong header tag"),
-                    _ => unreachable!(),
+                    _ => Tag::Invalid(v),
                 };
 
                 let stream_id = if initiator == INCOMING {
@@ -101,6 +120,7 @@ mod acc {
                             return Poll::Ready(Output {
                                 tag,
                                 stream_id,
+                                header_len: offset,
                                 bytes: Cow::Borrowed(&bytes[offset..]),
                             });
                         }
@@ -110,6 +130,7 @@ mod acc {
                             return Poll::Ready(Output {
                                 tag,
                                 stream_id,
+                                header_len: offset,
                                 bytes: Cow::Borrowed(&bytes[offset..]),
                             });
                         }
@@ -130,6 +151,7 @@ mod acc {
                             return Poll::Ready(Output {
                                 tag,
                                 stream_id,
+                                header_len: offset,
                                 bytes: Cow::Owned(acc[offset..].to_vec()),
                             });
                         }
@@ -139,6 +161,7 @@ mod acc {
                             return Poll::Ready(Output {
                                 tag,
                                 stream_id,
+                                header_len: offset,
                                 bytes: Cow::Owned(bytes),
                             });
                         }
@@ -206,6 +229,10 @@ impl<'a> fmt::Display for Output<'a> {
             OutputVariant::Reset { header } => {
                 write!(f, "reset({header})")
             }
+            OutputVariant::Invalid { header, bytes } => {
+                let bytes = hex::encode(bytes);
+                write!(f, "invalid({header}, {bytes})")
+            }
         }
     }
 }
@@ -228,6 +255,10 @@ enum OutputVariant<'a> {
     Reset {
         header: u64,
     },
+    Invalid {
+        header: u64,
+        bytes: Cow<'a, [u8]>,
+    },
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -260,93 +291,105 @@ impl<Inner> State<Inner>
 where
     Inner: From<StreamId>,
 {
-    fn process<'a>(&mut self, incoming: bool, bytes: &'a [u8]) -> Vec<Output<'a>> {
-        let mut output = vec![];
-        let mut bytes = bytes;
-        loop {
-            let acc = if incoming {
-                self.incoming.accumulate(bytes)
-            } else {
-                self.outgoing.accumulate(bytes)
-            };
-            bytes = &[];
-            if let Poll::Ready(o) = acc {
-                let acc::Output {
-                    tag,
-                    stream_id,
-                    bytes,
-                } = o;
-                match tag {
-                    acc::Tag::New => {
-                        let name = String::from_utf8(bytes.to_vec())
-                            .unwrap_or_else(|_| hex::encode(bytes));
-                        let stream = Inner::from(stream_id);
-                        let already_exist = self
-                            .inners
-                            .insert(stream_id, Status::Duplex(stream))
-                            .is_some();
-                        let header = match stream_id {
-                            StreamId::Handshake => u64::MAX,
-                            StreamId::Forward(id) => id << 3,
-                            StreamId::Backward(id) => id << 3,
-                        };
-                        let variant = OutputVariant::New {
-                            header,
-                            name,
-                            already_exist,
-                        };
-                        output.push(Output { stream_id, variant })
-                    }
-                    acc::Tag::Msg => {
-                        let bad_stream = !matches!(
-                            (self.inners.get_mut(&stream_id), incoming),
-                            (Some(Status::Duplex(_)), _)
-                                | (Some(Status::IncomingOnly(_)), true)
-                                | (Some(Status::OutgoingOnly(_)), false)
-                        );
-                        let variant = OutputVariant::Msg { bytes, bad_stream };
-                        output.push(Output { stream_id, variant })
-                    }
-                    acc::Tag::Close => {
-                        let error = match (self.inners.remove(&stream_id), incoming) {
-                            (Some(Status::Duplex(stream)), true) => {
-                                self.inners.insert(stream_id, Status::OutgoingOnly(stream));
-                                None
-                            }
-                            (Some(Status::Duplex(stream)), false) => {
-                                self.inners.insert(stream_id, Status::IncomingOnly(stream));
-                                None
-                            }
-                            (Some(Status::IncomingOnly(_)), true) => None,
-                            (Some(Status::OutgoingOnly(_)), false) => None,
-                            (Some(Status::OutgoingOnly(_)), true) => Some(CloseError::OnlyOutgoing),
-                            (Some(Status::IncomingOnly(_)), false) => {
-                                Some(CloseError::OnlyIncoming)
-                            }
-                            (None, incoming) => Some(CloseError::DoesntExist { incoming }),
-                        };
-                        let header = match stream_id {
-                            StreamId::Handshake => u64::MAX,
-                            StreamId::Forward(id) => 4 + (id << 3),
-                            StreamId::Backward(id) => 3 + (id << 3),
-                        };
-                        let variant = OutputVariant::Close { header, error };
-                        output.push(Output { stream_id, variant })
+    /// Takes the next frame and applies it to the state of the streams immediately,
+    /// so the frames that follow in the same chunk see the streams as they are at that moment.
+    fn next<'a>(&mut self, incoming: bool, bytes: &'a [u8]) -> Option<Output<'a>> {
+        let acc = if incoming {
+            self.incoming.accumulate(bytes)
+        } else {
+            self.outgoing.accumulate(bytes)
+        };
+        let acc::Output {
+            tag,
+            stream_id,
+            header_len,
+            bytes,
+        } = match acc {
+            Poll::Ready(o) => o,
+            Poll::Pending => return None,
+        };
+        self.framing += header_len;
+        let output = match tag {
+            acc::Tag::New => {
+                self.framing += bytes.len();
+                let name = String::from_utf8(bytes.to_vec()).unwrap_or_else(|_| hex::encode(bytes));
+                let stream = Inner::from(stream_id);
+                let already_exist = self
+                    .inners
+                    .insert(stream_id, Status::Duplex(stream))
+                    .is_some();
+                let header = match stream_id {
+                    StreamId::Handshake => u64::MAX,
+                    StreamId::Forward(id) => id << 3,
+                    StreamId::Backward(id) => id << 3,
+                };
+                let variant = OutputVariant::New {
+                    header,
+                    name,
+                    already_exist,
+                };
+                Output { stream_id, variant }
+            }
+            acc::Tag::Msg => {
+                let bad_stream = !matches!(
+                    (self.inners.get_mut(&stream_id), incoming),
+                    (Some(Status::Duplex(_)), _)
+                        | (Some(Status::IncomingOnly(_)), true)
+                        | (Some(Status::OutgoingOnly(_)), false)
+                );
+                let variant = OutputVariant::Msg { bytes, bad_stream };
+                Output { stream_id, variant }
+            }
+            acc::Tag::Close => {
+                let error = match (self.inners.remove(&stream_id), incoming) {
+                    (Some(Status::Duplex(stream)), true) => {
+                        self.inners.insert(stream_id, Status::OutgoingOnly(stream));
+                        None
                     }
-                    acc::Tag::Reset => {
-                        self.inners.remove(&stream_id);
-                        let header = match stream_id {
-                            StreamId::Handshake => u64::MAX,
-                            StreamId::Forward(id) => 6 + (id << 3),
-                            StreamId::Backward(id) => 5 + (id << 3),
-                        };
-                        let variant = OutputVariant::Reset { header };
-                        output.push(Output { stream_id, variant })
+                    (Some(Status::Duplex(stream)), false) => {
+                        self.inners.insert(stream_id, Status::IncomingOnly(stream));
+                        None
                     }
+                    (Some(Status::IncomingOnly(_)), true) => None,
+                    (Some(Status::OutgoingOnly(_)), false) => None,
+                    (Some(Status::OutgoingOnly(_)), true) => Some(CloseError::OnlyOutgoing),
+                    (Some(Status::IncomingOnly(_)), false) => Some(CloseError::OnlyIncoming),
+                    (None, incoming) => Some(CloseError::DoesntExist { incoming }),
                 };
-            } else {
-                break;
+                let header = match stream_id {
+                    StreamId::Handshake => u64::MAX,
+                    StreamId::Forward(id) => 4 + (id << 3),
+                    StreamId::Backward(id) => 3 + (id << 3),
+                };
+                let variant = OutputVariant::Close { header, error };
+                Output { stream_id, variant }
+            }
+            acc::Tag::Reset => {
+                self.inners.remove(&stream_id);
+                let header = match stream_id {
+                    StreamId::Handshake => u64::MAX,
+                    StreamId::Forward(id) => 6 + (id << 3),
+                    StreamId::Backward(id) => 5 + (id << 3),
+                };
+                let variant = OutputVariant::Reset { header };
+                Output { stream_id, variant }
             }
+            acc::Tag::Invalid(header) => {
+                self.framing += bytes.len();
+                let variant = OutputVariant::Invalid { header, bytes };
+                Output { stream_id, variant }
+            }
+        };
+        Some(output)
+    }
+
+    #[cfg(test)]
+    fn process<'a>(&mut self, incoming: bool, bytes: &'a [u8]) -> Vec<Output<'a>> {
+        let mut output = vec![];
+        let mut bytes = bytes;
+        while let Some(o) = self.next(incoming, bytes) {
+            bytes = &[];
+            output.push(o);
         }
 
         output
@@ -359,7 +402,12 @@ where
 {
     #[inline(never)]
     fn on_data(&mut self, id: DirectedId, bytes: &mut [u8], cx: &Cx, db: &Db) -> DbResult<()> {
-        for Output { stream_id, variant } in self.process(id.incoming, bytes) {
+        // each frame is delivered before the next one is taken,
+        // the streams are interleaved on the connection
+        let mut data: &[u8] = bytes;
+        while let Some(Output { stream_id, variant }) = self.next(id.incoming, data) {
+            data = &[];
+            db.count(Layer::Mux, id.incoming, std::mem::take(&mut self.framing));
             let db_stream = db.get(stream_id);
 
             match variant {
@@ -369,7 +417,9 @@ where
                     already_exist,
                 } => {
                     if already_exist {
-                        log::warn!("{id}, {stream_id}: new stream \"{name}\", but already exist");
+                        let reason = format!("new stream \"{name}\", but already exist");
+                        let err = DecoderError::new(Layer::Mux, db.id(), &id, reason);
+                        db.report(err.stream(stream_id));
                     }
                     db_stream.add(&id, StreamKind::Mplex, &header.to_be_bytes())?;
                 }
@@ -386,26 +436,50 @@ where
                     mut bytes,
                     bad_stream: false,
                 } => {
-                    self.inners
-                        .get_mut(&stream_id)
-                        .expect("cannot fail, checked upper in stack")
-                        .as_mut()
-                        .on_data(id.clone(), bytes.to_mut(), cx, db)?;
+                    if let Some(stream) = self.inners.get_mut(&stream_id) {
+                        stream
+                            .as_mut()
+                            .on_data(id.clone(), bytes.to_mut(), cx, db)?;
+                    }
                 }
                 OutputVariant::Close { header, error } => {
+                    let reason = error.as_ref().map(ToString::to_string);
+                    db_stream.end(&id, StreamEndKind::Close, 0, reason.unwrap_or_default())?;
                     if let Some(error) = error {
-                        log::error!("{id} {error}");
+                        let err = DecoderError::new(Layer::Mux, db.id(), &id, error);
+                        db.report(err.stream(stream_id));
                     }
                     db_stream.add(&id, StreamKind::Mplex, &header.to_be_bytes())?;
                 }
                 OutputVariant::Reset { header } => {
+                    db_stream.end(&id, StreamEndKind::Reset, 0, String::new())?;
                     db_stream.add(&id, StreamKind::Mplex, &header.to_be_bytes())?;
                 }
+                OutputVariant::Invalid { header, bytes } => {
+                    let reason = format!("invalid header {header:x}, skip the frame");
+                    let err = DecoderError::new(Layer::Mux, db.id(), &id, reason);
+                    db.report(err.stream(stream_id).bytes(&bytes));
+                }
             }
         }
 
         Ok(())
     }
+
+    fn snapshot(&self) -> serde_json::Value {
+        let streams = self
+            .inners
+            .iter()
+            .map(|(stream_id, status)| (stream_id.to_string(), status.as_ref().snapshot()))
+            .collect::<serde_json::Map<_, _>>();
+        serde_json::json!({
+            "layer": "mplex",
+            "streams_open": self.inners.len(),
+            "pending_in": self.incoming.pending(),
+            "pending_out": self.outgoing.pending(),
+            "streams": streams,
+        })
+    }
 }
 
 #[cfg(test)]
@@ -1,25 +1,32 @@
-use crate::database::StreamKind;
+use crate::database::{StreamKind, Layer, Negotiation, NegotiationToken, NegotiationTokenKind};
 
-use super::{HandleData, DirectedId, DynamicProtocol, Cx, Db, DbResult, StreamId};
+use super::{HandleData, DirectedId, DynamicProtocol, Cx, Db, DbResult, DecoderError, StreamId};
 
 pub struct State<Inner> {
     stream_id: StreamId,
     error: bool,
     inner: Option<Inner>,
     hl: hl::State,
+    transcript: Vec<NegotiationToken>,
+    agreed: Option<String>,
+    simultaneous_connect: bool,
 }
 
 // high level state machine
 mod hl {
-    use std::{borrow::Cow, str::Utf8Error};
+    use std::borrow::Cow;
+
+    use crate::database::NegotiationTokenKind;
 
     use super::ll;
 
     #[derive(Debug, Default)]
     pub struct Output<'a> {
-        pub tokens: Vec<String>,
-        pub error: Option<(Utf8Error, Vec<u8>)>,
+        pub tokens: Vec<(NegotiationTokenKind, String)>,
+        pub error: Option<(ll::Error, Vec<u8>)>,
         pub agreed: Option<(String, Cow<'a, [u8]>)>,
+        /// the peers resolved `/libp2p/simultaneous-connect` by `select:` numbers
+        pub simultaneous_connect: bool,
     }
 
     #[derive(Default)]
@@ -57,34 +64,48 @@ mod hl {
                         break;
                     }
                     Ok(ll::Output::String(s)) => {
-                        output_.tokens.push(s.clone());
                         if s.starts_with("/multistream/") {
-                            //
+                            output_.tokens.push((NegotiationTokenKind::Header, s));
                         } else if s.starts_with("/libp2p/simultaneous-connect") {
+                            output_
+                                .tokens
+                                .push((NegotiationTokenKind::SimultaneousConnect, s));
                             this.simultaneous_connect = true;
                             if other.simultaneous_connect {
                                 other.done = None;
                             }
                         } else if s == "na" {
+                            output_.tokens.push((NegotiationTokenKind::Na, s));
                             if other.simultaneous_connect {
                                 other.simultaneous_connect = false;
                             } else {
                                 other.done = None;
                             }
                         } else if s.starts_with("select") {
-                            //
-                        } else if !(this.simultaneous_connect && other.simultaneous_connect) {
-                            this.done = Some(s);
-                            break;
+                            output_.tokens.push((NegotiationTokenKind::Select, s));
+                        } else {
+                            output_
+                                .tokens
+                                .push((NegotiationTokenKind::Protocol, s.clone()));
+                            if !(this.simultaneous_connect && other.simultaneous_connect) {
+                                this.done = Some(s);
+                                break;
+                            }
                         }
                     }
                     Ok(ll::Output::InitiatorToken) => {
                         this.simultaneous_connect = false;
-                        output_.tokens.push("initiator".to_string())
+                        output_.simultaneous_connect = true;
+                        output_
+                            .tokens
+                            .push((NegotiationTokenKind::Initiator, "initiator".to_string()))
                     }
                     Ok(ll::Output::ResponderToken) => {
                         this.simultaneous_connect = false;
-                        output_.tokens.push("responder".to_string())
+                        output_.simultaneous_connect = true;
+                        output_
+                            .tokens
+                            .push((NegotiationTokenKind::Responder, "responder".to_string()))
                     }
                 }
             }
@@ -96,7 +117,11 @@ mod hl {
 
 // low level parser
 mod ll {
-    use std::{borrow::Cow, mem, str, str::Utf8Error};
+    use std::{borrow::Cow, str, str::Utf8Error};
+
+    use thiserror::Error;
+
+    use super::super::accumulator::{Framer, Framing, FrameError};
 
     pub enum Output {
         String(String),
@@ -104,65 +129,77 @@ mod ll {
         ResponderToken,
     }
 
+    #[derive(Debug, Error)]
+    pub enum Error {
+        #[error("{0}")]
+        Utf8(#[from] Utf8Error),
+        #[error("{0}")]
+        Frame(#[from] FrameError),
+    }
+
     pub struct State {
-        acc: Vec<u8>,
+        framer: Framer,
     }
 
     impl Default for State {
         fn default() -> Self {
             State {
-                // enough for most multistream select packet
-                acc: Vec::with_capacity(128),
+                framer: Framer::new(Framing::Varint),
             }
         }
     }
 
     impl State {
         pub fn end<'a>(&mut self, bytes: &'a [u8]) -> Cow<'a, [u8]> {
-            if self.acc.is_empty() {
+            if self.framer.pending() == 0 {
                 Cow::Borrowed(bytes)
             } else {
-                let mut acc = mem::take(&mut self.acc);
+                let mut acc = self.framer.take();
                 acc.extend_from_slice(bytes);
                 Cow::Owned(acc)
             }
         }
 
         pub fn append(&mut self, bytes: &[u8]) {
-            self.acc.extend_from_slice(bytes);
+            self.framer.push(bytes);
         }
 
-        pub fn poll(&mut self) -> Option<Result<Output, (Utf8Error, Vec<u8>)>> {
-            use unsigned_varint::decode;
-
-            if self.acc.starts_with(b"\ninitiator\n") {
-                self.acc.drain(..11);
+        pub fn poll(&mut self) -> Option<Result<Output, (Error, Vec<u8>)>> {
+            let buffered = self.framer.buffered();
+            if buffered.starts_with(b"\ninitiator\n") {
+                self.framer.skip(11);
                 Some(Ok(Output::InitiatorToken))
-            } else if self.acc.starts_with(b"\nresponder\n") {
-                self.acc.drain(..11);
+            } else if buffered.starts_with(b"\nresponder\n") {
+                self.framer.skip(11);
                 Some(Ok(Output::ResponderToken))
             } else {
-                let (result, new) = {
-                    let (length, remaining) = decode::usize(&self.acc).ok()?;
-                    if remaining.len() < length {
-                        return None;
-                    }
-
-                    let (msg, remaining) = remaining.split_at(length);
-                    let result = str::from_utf8(msg)
-                        .map(|s| s.trim_end_matches('\n').to_string())
-                        .map(Output::String)
-                        .map_err(|err| (err, msg.to_vec()));
-
-                    (result, remaining.to_vec())
+                let frame = match self.framer.next_frame() {
+                    Ok(frame) => frame?,
+                    Err((err, bytes)) => return Some(Err((err.into(), bytes))),
                 };
-                self.acc = new;
+                let msg = Framing::Varint.body(frame);
+                let result = str::from_utf8(msg)
+                    .map(|s| s.trim_end_matches('\n').to_string())
+                    .map(Output::String)
+                    .map_err(|err| (err.into(), msg.to_vec()));
                 Some(result)
             }
         }
     }
 }
 
+/// Size of the token on the wire, the length prefix and the newline.
+fn token_size(token: &str) -> usize {
+    match token {
+        "initiator" | "responder" => 11,
+        _ => {
+            let len = token.len() + 1;
+            unsigned_varint::encode::usize(len, &mut unsigned_varint::encode::usize_buffer()).len()
+                + len
+        }
+    }
+}
+
 impl<Inner> From<StreamId> for State<Inner> {
     fn from(stream_id: StreamId) -> Self {
         State {
@@ -170,10 +207,26 @@ impl<Inner> From<StreamId> for State<Inner> {
             error: false,
             inner: None,
             hl: hl::State::default(),
+            transcript: vec![],
+            agreed: None,
+            simultaneous_connect: false,
         }
     }
 }
 
+impl<Inner> State<Inner> {
+    fn store_transcript(&self, db: &Db) -> DbResult<()> {
+        db.add_negotiation(Negotiation {
+            connection_id: db.id(),
+            stream_id: self.stream_id,
+            tokens: self.transcript.clone(),
+            agreed: self.agreed.clone().unwrap_or_default(),
+            simultaneous_connect: self.simultaneous_connect,
+            failed: self.error,
+        })
+    }
+}
+
 impl<Inner> HandleData for State<Inner>
 where
     Inner: HandleData + DynamicProtocol,
@@ -191,36 +244,96 @@ where
         }
 
         let output = self.hl.poll(id.incoming, bytes);
+        let time = id.metadata.time;
+        // the transcript changed and must be stored again
+        let mut changed = !output.tokens.is_empty();
 
         if !output.tokens.is_empty() {
             let stream = db.get(self.stream_id);
-            for token in output.tokens {
+            for (kind, token) in output.tokens {
+                db.count(Layer::Select, id.incoming, token_size(&token));
                 stream.add(&id, StreamKind::Select, token.as_bytes())?;
+                self.transcript.push(NegotiationToken {
+                    timestamp: time,
+                    incoming: id.incoming,
+                    kind,
+                    value: token,
+                });
             }
         }
 
+        if output.simultaneous_connect {
+            self.simultaneous_connect = true;
+            cx.on_simultaneous_connect(&id, db.id());
+        }
+
         if let Some((error, msg)) = output.error {
-            log::error!(
-                "{id}, {}, stream_id: {}, unparsed {}, {error}",
-                db.id(),
-                self.stream_id,
-                hex::encode(msg)
-            );
+            let err = DecoderError::new(Layer::Select, db.id(), &id, format!("unparsed {error}"));
+            db.report(err.stream(self.stream_id).bytes(&msg).fatal());
             self.error = true;
+            self.transcript.push(NegotiationToken {
+                timestamp: time,
+                incoming: id.incoming,
+                kind: NegotiationTokenKind::Unparsed,
+                value: hex::encode(msg),
+            });
+            changed = true;
         }
 
-        if let Some((protocol, mut data)) = output.agreed {
-            if let StreamKind::Unknown = protocol.parse().expect("cannot fail") {
-                log::error!("{id} {}, bad protocol name {protocol}", db.id());
+        if let Some((protocol, _)) = &output.agreed {
+            if self.agreed.is_none() {
+                self.agreed = Some(protocol.clone());
+                changed = true;
+                // the network renamed the protocol the decoders know
+                let protocol = db.profile().canonical(protocol);
+                if !Inner::supports(protocol) {
+                    let reason = format!("unsupported protocol {protocol}");
+                    let err = DecoderError::new(Layer::Select, db.id(), &id, reason);
+                    db.report(err.stream(self.stream_id).fatal());
+                    self.error = true;
+                } else if let Ok(StreamKind::Unknown) = protocol.parse() {
+                    let reason = format!("unknown protocol {protocol}, not decoded");
+                    let err = DecoderError::new(Layer::Select, db.id(), &id, reason);
+                    db.report(err.stream(self.stream_id));
+                }
             }
-            let inner = self
-                .inner
-                .get_or_insert_with(|| Inner::from_name(&protocol, self.stream_id));
+        }
+
+        if changed {
+            self.store_transcript(db)?;
+        }
+
+        if self.error {
+            return Ok(());
+        }
+
+        if let Some((protocol, mut data)) = output.agreed {
+            let inner = self.inner.get_or_insert_with(|| {
+                Inner::from_name(db.profile().canonical(&protocol), self.stream_id)
+            });
             inner.on_data(id, data.to_mut(), cx, db)?;
         }
 
         Ok(())
     }
+
+    fn snapshot(&self) -> serde_json::Value {
+        let phase = if self.error {
+            "failed"
+        } else if self.inner.is_some() {
+            "agreed"
+        } else {
+            "negotiating"
+        };
+        serde_json::json!({
+            "layer": "select",
+            "stream_id": self.stream_id.to_string(),
+            "phase": phase,
+            "protocol": self.agreed,
+            "error": self.error,
+            "inner": self.inner.as_ref().map(Inner::snapshot),
+        })
+    }
 }
 
 #[cfg(test)]
@@ -313,10 +426,12 @@ fn simultaneous_connect_test() {
 
     let mut data = hex::decode("1c73656c6563743a31343838333538303531393436383433383239370a0a726573706f6e6465720a").expect("valid constant");
     let result = state.hl.poll(false, &mut data);
+    assert!(result.simultaneous_connect);
     assert!(dbg!(result).agreed.is_none());
 
     let mut data = hex::decode("0a696e69746961746f720a072f6e6f6973650a").expect("valid constant");
     let result = state.hl.poll(true, &mut data);
+    assert!(result.simultaneous_connect);
     assert!(dbg!(result).agreed.is_none());
 
     let mut data = hex::decode("072f6e6f6973650a").expect("valid constant");
@@ -328,6 +443,25 @@ fn simultaneous_connect_test() {
     assert!(dbg!(result).agreed.is_some());
 }
 
+#[cfg(test)]
+#[test]
+#[rustfmt::skip]
+fn negotiation_transcript_test() {
+    use NegotiationTokenKind::*;
+
+    let mut state = State::<()>::from(StreamId::Handshake);
+    let mut poll = |incoming: bool, data: &str| {
+        let mut data = hex::decode(data).expect("valid constant");
+        let result = state.hl.poll(incoming, &mut data);
+        result.tokens.into_iter().map(|(kind, _)| kind).collect::<Vec<_>>()
+    };
+
+    assert_eq!(poll(false, "132f6d756c746973747265616d2f312e302e300a1d2f6c69627032702f73696d756c74616e656f75732d636f6e6e6563740a072f6e6f6973650a"), [Header, SimultaneousConnect, Protocol]);
+    assert_eq!(poll(true, "132f6d756c746973747265616d2f312e302e300a1d2f6c69627032702f73696d756c74616e656f75732d636f6e6e6563740a072f6e6f6973650a1c73656c6563743a31383333363733363237323438313935323033380a"), [Header, SimultaneousConnect, Protocol, Select]);
+    assert_eq!(poll(false, "1c73656c6563743a31343838333538303531393436383433383239370a0a726573706f6e6465720a"), [Select, Responder]);
+    assert_eq!(poll(true, "0a696e69746961746f720a072f6e6f6973650a"), [Initiator, Protocol]);
+}
+
 #[cfg(test)]
 #[test]
 #[rustfmt::skip]
@@ -476,3 +610,12 @@ fn simultaneous_connect_misordered_noise_test() {
     let result = state.hl.poll(true, &mut data);
     assert!(dbg!(result).agreed.is_some());
 }
+
+#[cfg(test)]
+#[test]
+fn token_size_test() {
+    // `072f6e6f6973650a`
+    assert_eq!(token_size("/noise"), 8);
+    assert_eq!(token_size("initiator"), 11);
+    assert_eq!(token_size(&"a".repeat(200)), 203);
+}
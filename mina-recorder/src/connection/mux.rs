@@ -1,5 +1,8 @@
 use super::{HandleData, DirectedId, DynamicProtocol, Cx, Db, DbResult, mplex, yamux, StreamId};
 
+const MPLEX: &str = "/coda/mplex/1.0.0";
+const YAMUX: &str = "/coda/yamux/1.0.0";
+
 pub enum State<Inner> {
     Mplex(mplex::State<Inner>),
     Yamux(yamux::State<Inner>),
@@ -8,11 +11,15 @@ pub enum State<Inner> {
 impl<Inner> DynamicProtocol for State<Inner> {
     fn from_name(name: &str, stream_id: StreamId) -> Self {
         match name {
-            "/coda/mplex/1.0.0" => State::Mplex(mplex::State::from_name(name, stream_id)),
-            "/coda/yamux/1.0.0" => State::Yamux(yamux::State::from_name(name, stream_id)),
-            n => panic!("unexpected mux protocol: {n}"),
+            MPLEX => State::Mplex(mplex::State::from_name(name, stream_id)),
+            YAMUX => State::Yamux(yamux::State::from_name(name, stream_id)),
+            n => unreachable!("unexpected mux protocol: {n}, must be checked by `supports`"),
         }
     }
+
+    fn supports(name: &str) -> bool {
+        matches!(name, MPLEX | YAMUX)
+    }
 }
 
 impl<Inner> HandleData for State<Inner>
@@ -25,4 +32,11 @@ where
             State::Yamux(state) => state.on_data(id, bytes, cx, db),
         }
     }
+
+    fn snapshot(&self) -> serde_json::Value {
+        match self {
+            State::Mplex(state) => state.snapshot(),
+            State::Yamux(state) => state.snapshot(),
+        }
+    }
 }
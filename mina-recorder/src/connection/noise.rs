@@ -1,9 +1,9 @@
-use std::{ops::Range, sync::atomic::Ordering};
+use std::{ops::Range, sync::atomic::Ordering, time::SystemTime};
 
 use curve25519_dalek::{
     scalar::Scalar, constants::ED25519_BASEPOINT_TABLE, montgomery::MontgomeryPoint,
 };
-use sha2::Sha256;
+use sha2::{Sha256, Digest};
 use chacha20poly1305::ChaCha20Poly1305;
 use vru_noise::{
     SymmetricState,
@@ -13,17 +13,22 @@ use vru_noise::{
 };
 use thiserror::Error;
 
-use crate::database::{StreamId, StreamKind, RandomnessDatabase, ConnectionStats};
+use crate::database::{
+    StreamId, StreamKind, RandomnessDatabase, ConnectionStats, Layer, ConnectionId, NoiseHandshake,
+};
 
-use super::{HandleData, DirectedId, DynamicProtocol, Cx, Db, DbResult};
+use super::{
+    accumulator::{Framer, Framing},
+    HandleData, DirectedId, DynamicProtocol, Cx, Db, DbResult, DecoderError,
+};
 
 type C = (Hmac<Sha256>, Sha256, typenum::B0, ChaCha20Poly1305);
 
 pub type State<Inner> = ChunkState<NoiseState<Inner>>;
 
 pub struct ChunkState<Inner> {
-    accumulator_incoming: Vec<u8>,
-    accumulator_outgoing: Vec<u8>,
+    accumulator_incoming: Framer,
+    accumulator_outgoing: Framer,
     inner: Inner,
 }
 
@@ -33,8 +38,8 @@ where
 {
     fn from_name(name: &str, stream_id: StreamId) -> Self {
         ChunkState {
-            accumulator_incoming: vec![],
-            accumulator_outgoing: vec![],
+            accumulator_incoming: Framer::new(Framing::U16Be),
+            accumulator_outgoing: Framer::new(Framing::U16Be),
             inner: Inner::from_name(name, stream_id),
         }
     }
@@ -52,32 +57,29 @@ where
             &mut self.accumulator_outgoing
         };
 
-        if accumulator.is_empty() && bytes.len() >= 2 {
-            let len = u16::from_be_bytes(bytes[..2].try_into().expect("checked above")) as usize;
-            if bytes.len() == 2 + len {
-                return self.inner.on_data(id, bytes, cx, db);
-            }
+        if accumulator.is_whole(bytes) {
+            return self.inner.on_data(id, bytes, cx, db);
         }
 
-        accumulator.extend_from_slice(bytes);
-        loop {
-            if accumulator.len() >= 2 {
-                let len = accumulator[..2].try_into().expect("checked above");
-                let len = u16::from_be_bytes(len) as usize;
-                if accumulator.len() >= 2 + len {
-                    let (chunk, remaining) = accumulator.split_at_mut(2 + len);
-                    if let Err(err) = self.inner.on_data(id.clone(), chunk, cx, db) {
-                        log::error!("{id} {}: {err}", db.id());
-                    }
-                    *accumulator = remaining.to_vec();
-                    continue;
-                }
+        accumulator.push(bytes);
+        // u16 prefix never exceeds the limit, the framer cannot fail
+        while let Ok(Some(chunk)) = accumulator.next_frame() {
+            if let Err(err) = self.inner.on_data(id.clone(), chunk, cx, db) {
+                log::error!("{id} {}: {err}", db.id());
             }
-            break;
         }
 
         Ok(())
     }
+
+    fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({
+            "layer": "noise_frames",
+            "pending_in": self.accumulator_incoming.pending(),
+            "pending_out": self.accumulator_outgoing.pending(),
+            "inner": self.inner.snapshot(),
+        })
+    }
 }
 
 pub struct NoiseState<Inner> {
@@ -87,6 +89,9 @@ pub struct NoiseState<Inner> {
     inner: Inner,
     decrypted: usize,
     failed_to_decrypt: usize,
+    artifacts: Artifacts,
+    // the number, the direction and the time of the last handshake message
+    last_message: (u64, bool, SystemTime),
 }
 
 impl<Inner> DynamicProtocol for NoiseState<Inner>
@@ -101,10 +106,23 @@ where
             inner: Inner::from(stream_id),
             decrypted: 0,
             failed_to_decrypt: 0,
+            artifacts: Artifacts::default(),
+            last_message: (0, false, SystemTime::UNIX_EPOCH),
         }
     }
 }
 
+/// Public keys and shared secrets observed during the handshake.
+#[derive(Default)]
+struct Artifacts {
+    i_epk: Option<MontgomeryPoint>,
+    r_epk: Option<MontgomeryPoint>,
+    r_spk: Option<MontgomeryPoint>,
+    i_spk: Option<MontgomeryPoint>,
+    // the results of Diffie-Hellman `ee`, `es`, `se`
+    dh: Vec<[u8; 32]>,
+}
+
 enum St {
     FirstMessage {
         st: SymmetricState<C, ChainingKey<C>>,
@@ -139,9 +157,22 @@ where
             Some(St::SecondMessage { .. }) => Msg::Third,
             Some(_) => Msg::Other,
         };
+        let in_handshake = !matches!(msg, Msg::Other);
         if !self.error {
-            match self.on_data_(id.incoming, bytes, &cx.db.core()) {
+            let result = self.on_data_(id.incoming, bytes, &cx.db.core());
+            if in_handshake {
+                let number = match msg {
+                    Msg::First => 1,
+                    Msg::Second => 2,
+                    _ => 3,
+                };
+                self.last_message = (number, id.incoming, id.metadata.time);
+                let error = result.as_ref().err().map(ToString::to_string);
+                db.add_noise_handshake(self.handshake(db.id(), error.unwrap_or_default()))?;
+            }
+            match result {
                 Ok(range) => {
+                    let frame = bytes.len();
                     let bytes = &mut bytes[range];
                     self.decrypted += bytes.len();
                     cx.stats.decrypted.fetch_add(bytes.len(), Ordering::Relaxed);
@@ -155,11 +186,16 @@ where
                         id.incoming,
                     )?;
                     match msg {
-                        Msg::First => (),
+                        Msg::First => db.count(Layer::Noise, id.incoming, frame),
                         Msg::Second => {
                             db.get(StreamId::Handshake)
                                 .add(&id, StreamKind::Handshake, bytes)?;
+                            if id.incoming {
+                                Self::on_remote_identity(&id, bytes, db)?;
+                            }
                             let mut payload = super::super::decode::noise::payload(bytes)?;
+                            let delivered = payload.len().saturating_sub(1);
+                            db.count(Layer::Noise, id.incoming, frame - delivered);
                             if !payload.is_empty() {
                                 self.inner.on_data(id, &mut payload[1..], cx, db)?;
                             }
@@ -167,27 +203,53 @@ where
                         Msg::Third => {
                             db.get(StreamId::Handshake)
                                 .add(&id, StreamKind::Handshake, bytes)?;
+                            if id.incoming {
+                                Self::on_remote_identity(&id, bytes, db)?;
+                            }
                             let mut payload = super::super::decode::noise::payload(bytes)?;
+                            let delivered = payload.len().saturating_sub(1);
+                            db.count(Layer::Noise, id.incoming, frame - delivered);
                             if !payload.is_empty() {
                                 self.inner.on_data(id, &mut payload[1..], cx, db)?;
                             }
                         }
                         Msg::Other => {
+                            db.count(Layer::Noise, id.incoming, frame - bytes.len());
+                            db.count_frame(id.incoming, frame, bytes.len());
                             self.inner.on_data(id, bytes, cx, db)?;
                         }
                     }
                 }
                 Err(err) => {
                     self.error = true;
-                    self.on_error(id, bytes, cx, db, err)?;
+                    self.on_error(id, bytes, cx, db, Some(err))?;
                 }
             }
         } else {
-            self.on_error(id, bytes, cx, db, NoiseError::CannotDecrypt)?;
+            // the protocol is not noise, nothing is decrypted
+            let err = (self.failed_to_decrypt == 0).then_some(NoiseError::CannotDecrypt);
+            self.on_error(id, bytes, cx, db, err)?;
         }
 
         Ok(())
     }
+
+    fn snapshot(&self) -> serde_json::Value {
+        let machine = match &self.machine {
+            None => "none",
+            Some(St::FirstMessage { .. }) => "first_message",
+            Some(St::SecondMessage { .. }) => "second_message",
+            Some(St::Transport { .. }) => "transport",
+        };
+        serde_json::json!({
+            "layer": "noise",
+            "machine": machine,
+            "error": self.error,
+            "decrypted": self.decrypted,
+            "failed_to_decrypt": self.failed_to_decrypt,
+            "inner": self.inner.snapshot(),
+        })
+    }
 }
 
 #[derive(Debug, Error)]
@@ -233,18 +295,74 @@ pub enum NoiseError {
 }
 
 impl<Inner> NoiseState<Inner> {
+    fn handshake(&self, connection_id: ConnectionId, error: String) -> NoiseHandshake {
+        let Artifacts {
+            i_epk,
+            r_epk,
+            r_spk,
+            i_spk,
+            dh,
+        } = &self.artifacts;
+        let (last_message, last_message_incoming, last_message_time) = self.last_message;
+        let key = |k: &Option<MontgomeryPoint>| {
+            k.as_ref()
+                .map(|k| hex::encode(k.as_bytes()))
+                .unwrap_or_default()
+        };
+        NoiseHandshake {
+            connection_id,
+            initiator_is_incoming: self.initiator_is_incoming,
+            initiator_ephemeral: key(i_epk),
+            responder_ephemeral: key(r_epk),
+            responder_static: key(r_spk),
+            initiator_static: key(i_spk),
+            key_ids: dh
+                .iter()
+                .map(|ss| hex::encode(Sha256::digest(ss)))
+                .collect(),
+            secrets: dh.iter().map(hex::encode).collect(),
+            complete: matches!(&self.machine, Some(St::Transport { .. })),
+            error,
+            last_message,
+            last_message_incoming,
+            last_message_time,
+        }
+    }
+
+    fn on_remote_identity(id: &DirectedId, bytes: &[u8], db: &Db) -> DbResult<()> {
+        match crate::decode::noise::peer_id(bytes) {
+            Ok(Some(peer_id)) => db.add_peer_identity(peer_id, id.metadata.time),
+            Ok(None) => Ok(()),
+            Err(err) => {
+                let reason = format!("cannot get peer id {err}");
+                db.report(DecoderError::new(Layer::Noise, db.id(), id, reason).bytes(bytes));
+                Ok(())
+            }
+        }
+    }
+
+    /// The error is reported for the first chunk the decoder failed on,
+    /// the chunks after it are only counted.
     fn on_error(
         &mut self,
         id: DirectedId,
         bytes: &mut [u8],
         cx: &Cx,
         db: &Db,
-        err: NoiseError,
+        err: Option<NoiseError>,
     ) -> DbResult<()> {
+        if let Some(err) = err {
+            db.report(
+                DecoderError::new(Layer::Noise, db.id(), &id, err)
+                    .bytes(bytes)
+                    .fatal(),
+            );
+        }
         cx.stats
             .failed_to_decrypt
             .fetch_add(bytes.len(), Ordering::Relaxed);
         self.failed_to_decrypt += bytes.len();
+        db.count(Layer::Unknown, id.incoming, bytes.len());
         db.update(
             ConnectionStats {
                 total_bytes: bytes.len() as u64,
@@ -255,13 +373,12 @@ impl<Inner> NoiseState<Inner> {
             id.incoming,
         )?;
 
-        log::error!(
-            "{id} {}, total failed {}, total decrypted {}, {err}: {} {}...",
+        log::debug!(
+            "{id} {}, total failed {}, total decrypted {}, not decrypted {}",
             db.id(),
             cx.stats.failed_to_decrypt.load(Ordering::Relaxed),
             cx.stats.decrypted.load(Ordering::Relaxed),
             bytes.len(),
-            hex::encode(&bytes[..32.min(bytes.len())])
         );
 
         let stream = db.get(StreamId::Handshake);
@@ -317,6 +434,13 @@ impl<Inner> NoiseState<Inner> {
                 .map(|ss| ss.to_bytes())
         }
 
+        // the length of the message is checked before
+        fn key_at(bytes: &[u8], offset: usize) -> [u8; 32] {
+            let mut key = [0; 32];
+            key.copy_from_slice(&bytes[offset..(offset + 32)]);
+            key
+        }
+
         let range;
         let len = bytes.len();
         self.machine = match self.machine.take() {
@@ -330,12 +454,12 @@ impl<Inner> NoiseState<Inner> {
                     return Err(NoiseError::FirstMessageTooBig);
                 }
 
-                let i_epk =
-                    MontgomeryPoint(bytes[2..34].try_into().expect("cannot fail, checked above"));
+                let i_epk = MontgomeryPoint(key_at(bytes, 2));
                 let st = SymmetricState::new("Noise_XX_25519_ChaChaPoly_SHA256")
                     .mix_hash(&[])
                     .mix_hash(i_epk.as_bytes())
                     .mix_hash(&[]);
+                self.artifacts.i_epk = Some(i_epk);
                 range = 34..len;
                 Some(St::FirstMessage { st, i_epk })
             }
@@ -344,29 +468,32 @@ impl<Inner> NoiseState<Inner> {
                     return Err(NoiseError::SecondMessageTooShort);
                 }
 
-                let r_epk =
-                    MontgomeryPoint(bytes[2..34].try_into().expect("cannot fail, checked above"));
+                let r_epk = MontgomeryPoint(key_at(bytes, 2));
 
-                let mut r_spk_bytes: [u8; 32] = bytes[34..66]
-                    .try_into()
-                    .expect("cannot fail, checked above");
+                let mut r_spk_bytes = key_at(bytes, 34);
                 let tag = *GenericArray::from_slice(&bytes[66..82]);
                 let r_spk;
                 let payload_tag = *GenericArray::from_slice(&bytes[(len - 16)..]);
+                self.artifacts.r_epk = Some(r_epk);
+                let ee = try_dh(&r_epk, &i_epk, cx).ok_or_else(|| {
+                    let i = self.initiator_is_incoming;
+                    NoiseError::EphemeralSecretKeyNotFound { i, r_epk, i_epk }
+                })?;
+                self.artifacts.dh.push(ee);
                 let st = st
                     .mix_hash(r_epk.as_bytes())
-                    .mix_shared_secret(try_dh(&r_epk, &i_epk, cx).ok_or_else(|| {
-                        let i = self.initiator_is_incoming;
-                        NoiseError::EphemeralSecretKeyNotFound { i, r_epk, i_epk }
-                    })?)
+                    .mix_shared_secret(ee)
                     .decrypt(&mut r_spk_bytes, &tag)
                     .map_err(|_| NoiseError::SecondMessageMacMismatch)?
                     .mix_shared_secret({
                         r_spk = MontgomeryPoint(r_spk_bytes);
-                        try_dh(&r_spk, &i_epk, cx).ok_or_else(|| {
+                        self.artifacts.r_spk = Some(r_spk);
+                        let es = try_dh(&r_spk, &i_epk, cx).ok_or_else(|| {
                             let i = self.initiator_is_incoming;
                             NoiseError::SecondSecretKeyNotFound { i, r_spk, i_epk }
-                        })?
+                        })?;
+                        self.artifacts.dh.push(es);
+                        es
                     })
                     .decrypt(&mut bytes[82..(len - 16)], &payload_tag)
                     .map_err(|_| NoiseError::SecondMessagePayloadMacMismatch)?;
@@ -379,8 +506,7 @@ impl<Inner> NoiseState<Inner> {
                     return Err(NoiseError::ThirdMessageTooShort);
                 }
 
-                let mut i_spk_bytes: [u8; 32] =
-                    bytes[2..34].try_into().expect("cannot fail, checked above");
+                let mut i_spk_bytes = key_at(bytes, 2);
                 let tag = *GenericArray::from_slice(&bytes[34..50]);
                 let i_spk;
                 let payload_tag = *GenericArray::from_slice(&bytes[(len - 16)..]);
@@ -391,10 +517,13 @@ impl<Inner> NoiseState<Inner> {
                     .map_err(|_| NoiseError::ThirdMessageMacMismatch)?
                     .mix_shared_secret({
                         i_spk = MontgomeryPoint(i_spk_bytes);
-                        try_dh(&i_spk, &r_epk, cx).ok_or_else(|| {
+                        self.artifacts.i_spk = Some(i_spk);
+                        let se = try_dh(&i_spk, &r_epk, cx).ok_or_else(|| {
                             let i = self.initiator_is_incoming;
                             NoiseError::ThirdSecretKeyNotFound { i, r_epk, i_spk }
-                        })?
+                        })?;
+                        self.artifacts.dh.push(se);
+                        se
                     })
                     .decrypt(&mut bytes[50..(len - 16)], &payload_tag)
                     .map_err(|_| NoiseError::ThirdMessagePayloadMacMismatch)?
@@ -465,6 +594,13 @@ fn noise() {
     noise.on_data_(id.incoming, &mut hex::decode("00a8e3cfaddd47cf48db1b70b83c15dbdb32bdba21cca65f9f80fb2e7f93d7a82b1b71d6241952e1205d510afad46f8d6d23de1be013618cd79d4e87eec4761292393532e7952bddaeb6709dcb266f861f92ef0eabe282d318f813d11426ac6916240bfead8994c63f10b03f6e241c2b92495a1f63d728fb63ba78e468945f7da081761102465308523dbf50064be4251468abb99db7af8afd71b99100a2fb7a37773a8062d33cc2e1d9").expect("valid constant"), &mut cx).expect("test");
     id.incoming = true;
     noise.on_data_(id.incoming, &mut hex::decode("00375cd2640426acf52810f89147cf5446f8b4bff334c9727c0a45abd220746b2e8b10d269ff28be87c8bb1d53e43e69922ff4b19760ef875d").expect("valid constant"), &mut cx).expect("test");
+
+    let handshake = noise.handshake(ConnectionId(0), String::new());
+    assert_eq!(handshake.initiator_ephemeral, "9844288f8c8f0337dff411d66e0378d950fb7590f9f44d6df969fd59a18ab849");
+    assert!(!handshake.responder_static.is_empty() && !handshake.initiator_static.is_empty());
+    assert_eq!(handshake.key_ids.len(), 3);
+    assert_eq!(handshake.key_ids[0], hex::encode(Sha256::digest(hex::decode(&handshake.secrets[0]).unwrap())));
+    assert!(handshake.complete);
 }
 
 #[cfg(test)]
@@ -492,3 +628,214 @@ fn key_() {
     let i = MontgomeryPoint(i_epk).eq(&pk);
     assert!(r | i);
 }
+
+/// Cross-checks the decryption of the handshake and the transport against snow,
+/// the sessions are generated with the secret keys the debugger would find in the randomness.
+#[cfg(test)]
+mod conformance {
+    use std::ops::Range;
+
+    use sha2::{Sha256, Digest};
+
+    use crate::database::{RandomnessDatabase, StreamId};
+
+    use super::{super::multistream_select, DynamicProtocol, NoiseError, NoiseState};
+
+    const PARAMS: &str = "Noise_XX_25519_ChaChaPoly_SHA256";
+
+    type Decoder = NoiseState<multistream_select::State<()>>;
+
+    struct Randomness(Vec<[u8; 32]>);
+
+    impl RandomnessDatabase for Randomness {
+        fn iterate_randomness<'a>(&'a self) -> Box<dyn Iterator<Item = Box<[u8]>> + 'a> {
+            Box::new(self.0.iter().map(|x| x.to_vec().into_boxed_slice()))
+        }
+    }
+
+    /// The secret keys of the session, derived from the seed.
+    struct Keys {
+        initiator_static: [u8; 32],
+        initiator_ephemeral: [u8; 32],
+        responder_static: [u8; 32],
+        responder_ephemeral: [u8; 32],
+    }
+
+    impl Keys {
+        fn new(seed: u64) -> Self {
+            let key = |role: &str| -> [u8; 32] {
+                Sha256::new()
+                    .chain_update(seed.to_be_bytes())
+                    .chain_update(role)
+                    .finalize()
+                    .into()
+            };
+            Keys {
+                initiator_static: key("initiator static"),
+                initiator_ephemeral: key("initiator ephemeral"),
+                responder_static: key("responder static"),
+                responder_ephemeral: key("responder ephemeral"),
+            }
+        }
+
+        /// The node generated the ephemeral keys, the debugger captured the randomness.
+        fn randomness(&self) -> Randomness {
+            Randomness(vec![self.initiator_ephemeral, self.responder_ephemeral])
+        }
+
+        fn peers(&self) -> (snow::HandshakeState, snow::HandshakeState) {
+            let params = PARAMS.parse::<snow::params::NoiseParams>().unwrap();
+            let initiator = snow::Builder::new(params.clone())
+                .local_private_key(&self.initiator_static)
+                .fixed_ephemeral_key_for_testing_only(&self.initiator_ephemeral)
+                .build_initiator()
+                .unwrap();
+            let responder = snow::Builder::new(params)
+                .local_private_key(&self.responder_static)
+                .fixed_ephemeral_key_for_testing_only(&self.responder_ephemeral)
+                .build_responder()
+                .unwrap();
+            (initiator, responder)
+        }
+    }
+
+    /// The message as it is on the wire, with the length prefix.
+    fn frame(message: &[u8]) -> Vec<u8> {
+        let mut bytes = (message.len() as u16).to_be_bytes().to_vec();
+        bytes.extend_from_slice(message);
+        bytes
+    }
+
+    /// The debugger is on the host of the responder, the messages of the initiator are incoming.
+    fn observe(
+        decoder: &mut Decoder,
+        randomness: &Randomness,
+        incoming: bool,
+        message: &[u8],
+    ) -> Result<Vec<u8>, NoiseError> {
+        let mut bytes = frame(message);
+        let Range { start, end } = decoder.on_data_(incoming, &mut bytes, randomness)?;
+        Ok(bytes[start..end].to_vec())
+    }
+
+    /// Runs the handshake with the payloads of the second and the third messages,
+    /// checks the decoder decrypts them.
+    fn handshake(
+        keys: &Keys,
+        decoder: &mut Decoder,
+        early_data: [&[u8]; 2],
+    ) -> (snow::TransportState, snow::TransportState) {
+        let randomness = keys.randomness();
+        let (mut initiator, mut responder) = keys.peers();
+        let mut buf = vec![0; 0x10000];
+        let mut payload = vec![0; 0x10000];
+
+        let len = initiator.write_message(&[], &mut buf).unwrap();
+        let decrypted = observe(decoder, &randomness, true, &buf[..len]).unwrap();
+        assert!(decrypted.is_empty());
+        responder.read_message(&buf[..len], &mut payload).unwrap();
+
+        let len = responder.write_message(early_data[0], &mut buf).unwrap();
+        let decrypted = observe(decoder, &randomness, false, &buf[..len]).unwrap();
+        assert_eq!(decrypted, early_data[0]);
+        initiator.read_message(&buf[..len], &mut payload).unwrap();
+
+        let len = initiator.write_message(early_data[1], &mut buf).unwrap();
+        let decrypted = observe(decoder, &randomness, true, &buf[..len]).unwrap();
+        assert_eq!(decrypted, early_data[1]);
+        responder.read_message(&buf[..len], &mut payload).unwrap();
+
+        (
+            initiator.into_transport_mode().unwrap(),
+            responder.into_transport_mode().unwrap(),
+        )
+    }
+
+    fn decoder() -> Decoder {
+        Decoder::from_name("/noise", StreamId::Handshake)
+    }
+
+    #[test]
+    fn xx_early_data() {
+        for seed in 0..8 {
+            let keys = Keys::new(seed);
+            let randomness = keys.randomness();
+            let mut decoder = decoder();
+            let responder_data = vec![seed as u8; 0x40 * seed as usize + 1];
+            let initiator_data = vec![!seed as u8; 0x100 * seed as usize + 1];
+            let (mut initiator, mut responder) =
+                handshake(&keys, &mut decoder, [&responder_data, &initiator_data]);
+
+            let mut buf = vec![0; 0x10000];
+            for i in 0..32_usize {
+                // the empty message as well
+                let plaintext = vec![i as u8; (i * 97 + seed as usize) % 0x800];
+                let incoming = i % 3 != 0;
+                let sender = if incoming {
+                    &mut initiator
+                } else {
+                    &mut responder
+                };
+                let len = sender.write_message(&plaintext, &mut buf).unwrap();
+                let decrypted = observe(&mut decoder, &randomness, incoming, &buf[..len]);
+                assert_eq!(decrypted.unwrap(), plaintext, "seed {seed}, message {i}");
+            }
+        }
+    }
+
+    /// Libp2p never rekeys, the debugger does not follow the rekey,
+    /// but it must reject the message rather than decrypt it into garbage.
+    #[test]
+    fn xx_rekey() {
+        let keys = Keys::new(100);
+        let randomness = keys.randomness();
+        let mut decoder = decoder();
+        let (mut initiator, mut responder) = handshake(&keys, &mut decoder, [b"r", b"i"]);
+
+        let mut buf = vec![0; 0x10000];
+        let len = initiator.write_message(b"before", &mut buf).unwrap();
+        let decrypted = observe(&mut decoder, &randomness, true, &buf[..len]).unwrap();
+        assert_eq!(decrypted, b"before");
+
+        initiator.rekey_outgoing();
+        responder.rekey_incoming();
+        let len = initiator.write_message(b"after", &mut buf).unwrap();
+        let mut payload = vec![0; 0x10000];
+        let read = responder.read_message(&buf[..len], &mut payload).unwrap();
+        assert_eq!(&payload[..read], b"after");
+        let result = observe(&mut decoder, &randomness, true, &buf[..len]);
+        assert!(matches!(result, Err(NoiseError::CannotDecrypt)));
+    }
+
+    /// The first message of libp2p carries only the ephemeral key.
+    #[test]
+    fn xx_first_message_payload() {
+        let keys = Keys::new(200);
+        let (mut initiator, _) = keys.peers();
+        let mut buf = vec![0; 0x10000];
+        let len = initiator.write_message(b"early", &mut buf).unwrap();
+        let result = observe(&mut decoder(), &keys.randomness(), true, &buf[..len]);
+        assert!(matches!(result, Err(NoiseError::FirstMessageTooBig)));
+    }
+
+    /// Without the ephemeral secret keys the debugger cannot decrypt the session.
+    #[test]
+    fn xx_unknown_keys() {
+        let keys = Keys::new(300);
+        let randomness = Randomness(vec![keys.initiator_static]);
+        let (mut initiator, mut responder) = keys.peers();
+        let mut decoder = decoder();
+        let mut buf = vec![0; 0x10000];
+        let mut payload = vec![0; 0x10000];
+
+        let len = initiator.write_message(&[], &mut buf).unwrap();
+        observe(&mut decoder, &randomness, true, &buf[..len]).unwrap();
+        responder.read_message(&buf[..len], &mut payload).unwrap();
+        let len = responder.write_message(b"r", &mut buf).unwrap();
+        let result = observe(&mut decoder, &randomness, false, &buf[..len]);
+        assert!(matches!(
+            result,
+            Err(NoiseError::EphemeralSecretKeyNotFound { .. })
+        ));
+    }
+}
@@ -4,9 +4,9 @@ use salsa20::{
     XSalsa20,
 };
 
-use crate::chunk::EncryptionStatus;
+use crate::{chunk::EncryptionStatus, database::Layer};
 
-use super::{HandleData, DirectedId, Cx, Db, DbResult, StreamId};
+use super::{HandleData, DirectedId, Cx, Db, DbResult, DecoderError, StreamId};
 
 pub struct State<Inner> {
     shared_secret: GenericArray<u8, typenum::U32>,
@@ -32,6 +32,11 @@ where
 }
 
 impl<Inner> State<Inner> {
+    /// The connection is not encrypted by the private network, it is ignored.
+    pub fn skipped(&self) -> bool {
+        self.skip
+    }
+
     pub fn shared_secret(chain_id: &[u8]) -> GenericArray<u8, typenum::U32> {
         use blake2::{
             digest::{Update, VariableOutput},
@@ -73,13 +78,16 @@ where
             )?;
             self.inner.on_data(id, bytes, cx, db)?;
         } else if bytes.len() != 24 {
+            db.count(Layer::Unknown, id.incoming, bytes.len());
             self.skip = true;
-            log::warn!(
-                "{id} {} skip connection, bytes: {}",
-                db.id(),
-                hex::encode(bytes)
+            let reason = "no nonce of the private network, skip the connection";
+            db.report(
+                DecoderError::new(Layer::Pnet, db.id(), &id, reason)
+                    .bytes(bytes)
+                    .fatal(),
             );
         } else {
+            db.count(Layer::Pnet, id.incoming, bytes.len());
             *cipher = Some(XSalsa20::new(
                 &self.shared_secret,
                 GenericArray::from_slice(bytes),
@@ -88,4 +96,13 @@ where
 
         Ok(())
     }
+
+    fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({
+            "layer": "pnet",
+            "skip": self.skip,
+            "established": self.cipher_in.is_some() && self.cipher_out.is_some(),
+            "inner": self.inner.snapshot(),
+        })
+    }
 }
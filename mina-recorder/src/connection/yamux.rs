@@ -3,9 +3,9 @@ use std::{
     task::Poll,
 };
 
-use crate::database::StreamKind;
+use crate::database::{StreamKind, StreamEndKind, Layer};
 
-use super::{HandleData, DirectedId, DynamicProtocol, Cx, Db, DbResult, StreamId};
+use super::{HandleData, DirectedId, DynamicProtocol, Cx, Db, DbResult, DecoderError, StreamId};
 
 pub struct State<Inner> {
     incoming: acc::State<true>,
@@ -13,6 +13,8 @@ pub struct State<Inner> {
     error: bool,
     inners: BTreeMap<StreamId, Status<Inner>>,
     recent_reset: VecDeque<StreamId>,
+    // the last `FIN` closed the part of the stream which was already closed or did not exist
+    bad_close: bool,
 }
 
 pub enum Status<Inner> {
@@ -21,6 +23,16 @@ pub enum Status<Inner> {
     OutgoingOnly(Inner),
 }
 
+impl<Inner> AsRef<Inner> for Status<Inner> {
+    fn as_ref(&self) -> &Inner {
+        match self {
+            Status::Duplex(inner) => inner,
+            Status::IncomingOnly(inner) => inner,
+            Status::OutgoingOnly(inner) => inner,
+        }
+    }
+}
+
 impl<Inner> AsMut<Inner> for Status<Inner> {
     fn as_mut(&mut self) -> &mut Inner {
         match self {
@@ -40,6 +52,7 @@ impl<Inner> DynamicProtocol for State<Inner> {
             error: false,
             inners: BTreeMap::new(),
             recent_reset: VecDeque::with_capacity(512),
+            bad_close: false,
         }
     }
 }
@@ -239,19 +252,32 @@ mod acc {
     #[derive(Debug, Error)]
     pub enum Error {
         #[error("header parse error: {0}")]
-        HeaderParse(HeaderParseError),
+        HeaderParse(HeaderParseError, [u8; 12]),
+    }
+
+    impl Error {
+        pub fn bytes(&self) -> &[u8] {
+            match self {
+                Error::HeaderParse(_, header) => header,
+            }
+        }
     }
 
     impl<const INCOMING: bool> State<INCOMING> {
+        pub fn pending(&self) -> usize {
+            self.acc.len()
+        }
+
         pub fn accumulate<'a>(&mut self, bytes: &'a [u8]) -> Poll<Result<Output<'a>, Error>> {
             let offset = 12;
 
             if self.acc.is_empty() {
                 if bytes.len() >= offset {
-                    let header_bytes = <[u8; 12]>::try_from(&bytes[..offset]).expect("cannot fail");
+                    let mut header_bytes = [0; 12];
+                    header_bytes.copy_from_slice(&bytes[..offset]);
                     let header = match Header::try_from(header_bytes) {
                         Ok(v) => v,
-                        Err(err) => return Poll::Ready(Err(Error::HeaderParse(err))),
+                        Err(err) => return Poll::Ready(Err(Error::HeaderParse(err, header_bytes))),
                     };
                     let end = offset + header.payload_length();
                     match bytes.len().cmp(&end) {
@@ -279,11 +305,11 @@ mod acc {
             } else {
                 self.acc.extend_from_slice(bytes);
                 if self.acc.len() >= offset {
-                    let header_bytes =
-                        <[u8; 12]>::try_from(&self.acc[..offset]).expect("cannot fail");
+                    let mut header_bytes = [0; 12];
+                    header_bytes.copy_from_slice(&self.acc[..offset]);
                     let header = match Header::try_from(header_bytes) {
                         Ok(v) => v,
-                        Err(err) => return Poll::Ready(Err(Error::HeaderParse(err))),
+                        Err(err) => return Poll::Ready(Err(Error::HeaderParse(err, header_bytes))),
                     };
                     let end = offset + header.payload_length();
                     match self.acc.len().cmp(&end) {
@@ -314,10 +340,86 @@ mod acc {
     }
 }
 
+/// How the side ends the stream by the frame, and the code of the go away.
+fn stream_end(header: &Header) -> Option<(StreamEndKind, u32)> {
+    match &header.ty {
+        HeaderType::GoAway(Ok(())) => Some((StreamEndKind::GoAway, 0)),
+        HeaderType::GoAway(Err(YamuxError::Protocol)) => Some((StreamEndKind::GoAway, 1)),
+        HeaderType::GoAway(Err(YamuxError::Internal)) => Some((StreamEndKind::GoAway, 2)),
+        _ if header.flags.contains(HeaderFlags::SYN) => None,
+        _ if header.flags.contains(HeaderFlags::FIN) => Some((StreamEndKind::Close, 0)),
+        _ if header.flags.contains(HeaderFlags::RST) => Some((StreamEndKind::Reset, 0)),
+        _ => None,
+    }
+}
+
+fn stream_id(header: &Header) -> StreamId {
+    if header.stream_id == 0 {
+        StreamId::Handshake
+    } else if header.stream_id % 2 == 0 {
+        StreamId::Forward((header.stream_id / 2) as u64)
+    } else {
+        StreamId::Backward((header.stream_id / 2) as u64)
+    }
+}
+
 impl<Inner> State<Inner>
 where
     Inner: From<StreamId>,
 {
+    /// Takes the next frame and applies its flags to the state of the streams immediately,
+    /// so the frames that follow in the same chunk see the streams as they are at that moment.
+    fn next<'a>(
+        &mut self,
+        incoming: bool,
+        bytes: &'a [u8],
+    ) -> Option<Result<acc::Output<'a>, acc::Error>> {
+        let acc = if incoming {
+            self.incoming.accumulate(bytes)
+        } else {
+            self.outgoing.accumulate(bytes)
+        };
+        let result = match acc {
+            Poll::Ready(result) => result,
+            Poll::Pending => return None,
+        };
+        if let Ok(out) = &result {
+            let header = &out.header;
+            let stream_id = stream_id(header);
+
+            if header.flags.contains(HeaderFlags::SYN) {
+                let stream = Inner::from(stream_id);
+                self.inners.insert(stream_id, Status::Duplex(stream));
+            } else if header.flags.contains(HeaderFlags::FIN) {
+                let error = match (self.inners.remove(&stream_id), incoming) {
+                    (Some(Status::Duplex(stream)), true) => {
+                        self.inners.insert(stream_id, Status::OutgoingOnly(stream));
+                        false
+                    }
+                    (Some(Status::Duplex(stream)), false) => {
+                        self.inners.insert(stream_id, Status::IncomingOnly(stream));
+                        false
+                    }
+                    (Some(Status::IncomingOnly(_)), true) => false,
+                    (Some(Status::OutgoingOnly(_)), false) => false,
+                    (Some(Status::OutgoingOnly(_)), true) => true,
+                    (Some(Status::IncomingOnly(_)), false) => true,
+                    (None, _) => true,
+                };
+                self.bad_close = error;
+            } else if header.flags.contains(HeaderFlags::RST) {
+                if self.recent_reset.len() == 512 {
+                    self.recent_reset.pop_front();
+                }
+                self.recent_reset.push_back(stream_id);
+                self.inners.remove(&stream_id);
+            }
+        }
+
+        Some(result)
+    }
+
+    #[cfg(test)]
     fn process<'a>(
         &mut self,
         incoming: bool,
@@ -325,57 +427,9 @@ where
     ) -> Vec<Result<acc::Output<'a>, acc::Error>> {
         let mut output = vec![];
         let mut bytes = bytes;
-        loop {
-            let acc = if incoming {
-                self.incoming.accumulate(bytes)
-            } else {
-                self.outgoing.accumulate(bytes)
-            };
+        while let Some(result) = self.next(incoming, bytes) {
             bytes = &[];
-            if let Poll::Ready(result) = acc {
-                if let Ok(out) = &result {
-                    let header = &out.header;
-                    let stream_id = if header.stream_id == 0 {
-                        StreamId::Handshake
-                    } else if header.stream_id % 2 == 0 {
-                        StreamId::Forward((header.stream_id / 2) as u64)
-                    } else {
-                        StreamId::Backward((header.stream_id / 2) as u64)
-                    };
-
-                    if header.flags.contains(HeaderFlags::SYN) {
-                        let stream = Inner::from(stream_id);
-                        self.inners.insert(stream_id, Status::Duplex(stream));
-                    } else if out.header.flags.contains(HeaderFlags::FIN) {
-                        let error = match (self.inners.remove(&stream_id), incoming) {
-                            (Some(Status::Duplex(stream)), true) => {
-                                self.inners.insert(stream_id, Status::OutgoingOnly(stream));
-                                false
-                            }
-                            (Some(Status::Duplex(stream)), false) => {
-                                self.inners.insert(stream_id, Status::IncomingOnly(stream));
-                                false
-                            }
-                            (Some(Status::IncomingOnly(_)), true) => false,
-                            (Some(Status::OutgoingOnly(_)), false) => false,
-                            (Some(Status::OutgoingOnly(_)), true) => true,
-                            (Some(Status::IncomingOnly(_)), false) => true,
-                            (None, _) => true,
-                        };
-                        // TODO: report
-                        let _ = error;
-                    } else if out.header.flags.contains(HeaderFlags::RST) {
-                        if self.recent_reset.len() == 512 {
-                            self.recent_reset.pop_front();
-                        }
-                        self.recent_reset.push_back(stream_id);
-                        self.inners.remove(&stream_id);
-                    }
-                }
-                output.push(result);
-            } else {
-                break;
-            }
+            output.push(result);
         }
 
         output
@@ -388,26 +442,24 @@ where
 {
     fn on_data(&mut self, id: DirectedId, bytes: &mut [u8], cx: &Cx, db: &Db) -> DbResult<()> {
         if self.error {
-            // TODO: report
             return Ok(());
         }
 
-        for result in self.process(id.incoming, bytes) {
+        // each frame is delivered before the next one is taken,
+        // the streams are interleaved on the connection
+        let mut data: &[u8] = bytes;
+        while let Some(result) = self.next(id.incoming, data) {
+            data = &[];
             match result {
                 Err(err) => {
                     self.error = true;
-                    // TODO: report
-                    log::error!("{id} {} {err}", db.id());
+                    let err = DecoderError::new(Layer::Mux, db.id(), &id, &err).bytes(err.bytes());
+                    db.report(err.fatal());
                     return Ok(());
                 }
                 Ok(acc::Output { header, mut bytes }) => {
-                    let stream_id = if header.stream_id == 0 {
-                        StreamId::Handshake
-                    } else if header.stream_id % 2 == 0 {
-                        StreamId::Forward((header.stream_id / 2) as u64)
-                    } else {
-                        StreamId::Backward((header.stream_id / 2) as u64)
-                    };
+                    db.count(Layer::Mux, id.incoming, 12);
+                    let stream_id = stream_id(&header);
                     let db_stream = db.get(stream_id);
                     if let HeaderType::Data { .. } = &header.ty {
                         if let Some(s) = self.inners.get_mut(&stream_id) {
@@ -417,18 +469,45 @@ where
                             stream.on_data(id.clone(), bytes.to_mut(), cx, db)?;
                             self.inners.insert(stream_id, Status::Duplex(stream));
 
-                            log::warn!("{id} {} doesn't exist {stream_id}", db.id());
+                            let reason = "data for the stream that doesn't exist";
+                            let err = DecoderError::new(Layer::Mux, db.id(), &id, reason);
+                            db.report(err.stream(stream_id));
                         }
                     } else {
                         let header_bytes = <[u8; 12]>::from(&header);
                         db_stream.add(&id, StreamKind::Yamux, &header_bytes)?;
                     }
+                    if let Some((kind, code)) = stream_end(&header) {
+                        let error = if std::mem::take(&mut self.bad_close) {
+                            "the part of the stream is already closed or doesn't exist"
+                        } else {
+                            ""
+                        };
+                        db_stream.end(&id, kind, code, error.to_owned())?;
+                    }
                 }
             }
         }
 
         Ok(())
     }
+
+    fn snapshot(&self) -> serde_json::Value {
+        let streams = self
+            .inners
+            .iter()
+            .map(|(stream_id, status)| (stream_id.to_string(), status.as_ref().snapshot()))
+            .collect::<serde_json::Map<_, _>>();
+        serde_json::json!({
+            "layer": "yamux",
+            "streams_open": self.inners.len(),
+            "error": self.error,
+            "recent_reset": self.recent_reset.len(),
+            "pending_in": self.incoming.pending(),
+            "pending_out": self.outgoing.pending(),
+            "streams": streams,
+        })
+    }
 }
 
 #[cfg(test)]
@@ -452,4 +531,168 @@ mod tests {
         assert_eq!(output.header.payload_length(), 16);
         assert_eq!(output.bytes.as_ref(), [0xff; 16]);
     }
+
+    #[test]
+    fn concurrent_streams() {
+        use std::time::SystemTime;
+
+        use crate::{
+            connection::{HandleData, multistream_select, mina_protocol},
+            database::{DbFacade, StreamKind, StreamEndKind},
+            event::{ConnectionInfo, DirectedId},
+            recorder::Cx,
+            peer_names::{PeerNames, PeerNamesConfig},
+        };
+
+        type Inner = multistream_select::State<mina_protocol::State>;
+
+        fn frame(flags: u16, stream_id: u32, payload: &[u8]) -> Vec<u8> {
+            let mut v = vec![0, 0];
+            v.extend_from_slice(&flags.to_be_bytes());
+            v.extend_from_slice(&stream_id.to_be_bytes());
+            v.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+            v.extend_from_slice(payload);
+            v
+        }
+
+        fn go_away(code: u32) -> Vec<u8> {
+            let mut v = vec![0, 3, 0, 0, 0, 0, 0, 0];
+            v.extend_from_slice(&code.to_be_bytes());
+            v
+        }
+
+        fn token(s: &str) -> Vec<u8> {
+            let mut v = vec![s.len() as u8 + 1];
+            v.extend_from_slice(s.as_bytes());
+            v.push(b'\n');
+            v
+        }
+
+        // size, query header (tag, version, id) and the unit query
+        fn query(id: u8) -> Vec<u8> {
+            let tag = b"get_some_initial_peers";
+            let mut payload = vec![1, tag.len() as u8];
+            payload.extend_from_slice(tag);
+            payload.extend_from_slice(&[1, id, 1, 0]);
+            let mut v = (payload.len() as u64).to_le_bytes().to_vec();
+            v.extend_from_slice(&payload);
+            v
+        }
+
+        let dir = temp_dir::TempDir::new().unwrap();
+        let db = DbFacade::open(dir.path()).unwrap();
+        let info = ConnectionInfo {
+            addr: "1.2.3.4:8302".parse().unwrap(),
+            pid: 1,
+            fd: 10,
+        };
+        let group = db
+            .add(info, false, "node".to_owned(), SystemTime::UNIX_EPOCH)
+            .unwrap();
+        let core = db.core();
+        let cx = Cx {
+            apps: Default::default(),
+            stats_state: Default::default(),
+            db,
+            stats: Default::default(),
+            aggregator: None,
+            simultaneous_connect: Default::default(),
+            local_pairs: Default::default(),
+            peer_names: PeerNames::new(PeerNamesConfig::default(), core.clone()),
+            kube: Default::default(),
+        };
+
+        let mut state = State::<Inner>::from_name("/coda/yamux/1.0.0", StreamId::Handshake);
+        let mut on_data = |incoming: bool, mut bytes: Vec<u8>| {
+            let id = DirectedId {
+                incoming,
+                ..DirectedId::default()
+            };
+            state.on_data(id, &mut bytes, &cx, &group).unwrap();
+        };
+
+        let negotiation = [token("/multistream/1.0.0"), token("coda/rpcs/0.0.1")].concat();
+        // the dialer opens three streams at once
+        on_data(false, [1, 3, 5].map(|s| frame(1, s, &negotiation)).concat());
+        // the listener confirms them in another order
+        on_data(true, [5, 1, 3].map(|s| frame(2, s, &negotiation)).concat());
+        // the query on the second stream is split between two frames,
+        // the third stream is reset right after its query
+        let (q0, q1, q2) = (query(1), query(2), query(3));
+        let chunk = [
+            frame(0, 3, &q1[..10]),
+            frame(0, 1, &q0),
+            frame(0, 5, &q2),
+            frame(0, 3, &q1[10..]),
+            frame(8, 5, &[]),
+        ];
+        on_data(false, chunk.concat());
+        // the listener closes the first stream by the window update, then the session
+        let mut fin = frame(4, 1, &[]);
+        fin[1] = 1;
+        on_data(true, [fin, go_away(2)].concat());
+
+        let messages = core
+            .fetch_messages_since(SystemTime::UNIX_EPOCH)
+            .map(|(_, msg)| msg)
+            .collect::<Vec<_>>();
+        let of_kind = |kind| {
+            messages
+                .iter()
+                .filter(|msg| msg.stream_kind == kind)
+                .map(|msg| msg.stream_id)
+                .collect::<Vec<_>>()
+        };
+        // each stream negotiated on its own, two tokens in each direction
+        let selects = of_kind(StreamKind::Select);
+        assert_eq!(selects.len(), 12);
+        for s in 0..3 {
+            let count = selects
+                .iter()
+                .filter(|id| **id == StreamId::Backward(s))
+                .count();
+            assert_eq!(count, 4);
+        }
+        assert_eq!(
+            of_kind(StreamKind::Rpc),
+            [
+                StreamId::Backward(0),
+                StreamId::Backward(2),
+                StreamId::Backward(1)
+            ]
+        );
+
+        let mut ends = core
+            .fetch_stream_ends(group.id())
+            .into_iter()
+            .map(|end| (end.stream_id, end.incoming, end.kind, end.code, end.error))
+            .collect::<Vec<_>>();
+        ends.sort_by_key(|end| end.0);
+        assert_eq!(
+            ends,
+            [
+                (
+                    StreamId::Handshake,
+                    true,
+                    StreamEndKind::GoAway,
+                    2,
+                    String::new()
+                ),
+                (
+                    StreamId::Backward(0),
+                    true,
+                    StreamEndKind::Close,
+                    0,
+                    String::new()
+                ),
+                (
+                    StreamId::Backward(2),
+                    false,
+                    StreamEndKind::Reset,
+                    0,
+                    String::new()
+                ),
+            ]
+        );
+    }
 }
@@ -1,5 +1,5 @@
 use std::{
-    net::{SocketAddr, IpAddr, Ipv6Addr, Ipv4Addr},
+    net::{SocketAddr, IpAddr, Ipv6Addr},
     time::{SystemTime, Duration},
     io,
 };
@@ -8,18 +8,17 @@ use mina_p2p_messages::binprot::{BinProtRead, BinProtWrite};
 use radiation::{Absorb, Emit, nom, ParseError, RadiationBuffer};
 use libp2p_core::PeerId;
 
+use crate::{
+    database::{ConnectionId, FramingStats, LayerStats, PeerGeo},
+    event::canonical_addr,
+    kube::PodMeta,
+    tcp_info::TcpParams,
+};
+
 pub fn addr_absorb(input: &[u8]) -> nom::IResult<&[u8], SocketAddr, ParseError<&[u8]>> {
     let pair = nom::sequence::pair(<[u8; 16]>::absorb::<()>, u16::absorb::<()>);
     nom::combinator::map(pair, |(ip, port)| {
-        let ipv6 = Ipv6Addr::from(ip);
-        if let [0, 0, 0, 0, 0, 0xFFFF, ..] = ipv6.segments() {
-            SocketAddr::new(
-                IpAddr::V4(Ipv4Addr::new(ip[12], ip[13], ip[14], ip[15])),
-                port,
-            )
-        } else {
-            SocketAddr::new(IpAddr::V6(ipv6), port)
-        }
+        canonical_addr(SocketAddr::new(IpAddr::V6(Ipv6Addr::from(ip)), port))
     })(input)
 }
 
@@ -69,10 +68,116 @@ where
     duration_emit(&value, buffer);
 }
 
+/// The field appended to the record later, absent in the records of older databases.
+pub fn trailing_u64_absorb(input: &[u8]) -> nom::IResult<&[u8], u64, ParseError<&[u8]>> {
+    if input.is_empty() {
+        Ok((input, 0))
+    } else {
+        u64::absorb::<()>(input)
+    }
+}
+
+pub fn trailing_u64_emit<W>(value: &u64, buffer: &mut W)
+where
+    W: for<'a> Extend<&'a u8>,
+{
+    value.emit(buffer);
+}
+
+/// Like `trailing_u64_absorb`, but `None` if absent, the value is shifted by one.
+pub fn trailing_cn_opt_absorb(
+    input: &[u8],
+) -> nom::IResult<&[u8], Option<ConnectionId>, ParseError<&[u8]>> {
+    let (rest, v) = trailing_u64_absorb(input)?;
+    Ok((rest, v.checked_sub(1).map(ConnectionId)))
+}
+
+pub fn trailing_cn_opt_emit<W>(value: &Option<ConnectionId>, buffer: &mut W)
+where
+    W: for<'a> Extend<&'a u8>,
+{
+    value.map_or(0, |ConnectionId(id)| id + 1).emit(buffer);
+}
+
+pub fn trailing_u32_absorb(input: &[u8]) -> nom::IResult<&[u8], u32, ParseError<&[u8]>> {
+    if input.is_empty() {
+        Ok((input, 0))
+    } else {
+        u32::absorb::<()>(input)
+    }
+}
+
+pub fn trailing_bool_absorb(input: &[u8]) -> nom::IResult<&[u8], bool, ParseError<&[u8]>> {
+    if input.is_empty() {
+        Ok((input, false))
+    } else {
+        bool::absorb::<()>(input)
+    }
+}
+
+pub fn trailing_string_absorb(input: &[u8]) -> nom::IResult<&[u8], String, ParseError<&[u8]>> {
+    if input.is_empty() {
+        Ok((input, String::new()))
+    } else {
+        String::absorb::<()>(input)
+    }
+}
+
+pub fn trailing_layers_absorb(input: &[u8]) -> nom::IResult<&[u8], LayerStats, ParseError<&[u8]>> {
+    if input.is_empty() {
+        Ok((input, LayerStats::default()))
+    } else {
+        LayerStats::absorb::<()>(input)
+    }
+}
+
+pub fn trailing_framing_absorb(
+    input: &[u8],
+) -> nom::IResult<&[u8], FramingStats, ParseError<&[u8]>> {
+    if input.is_empty() {
+        Ok((input, FramingStats::default()))
+    } else {
+        FramingStats::absorb::<()>(input)
+    }
+}
+
+pub fn trailing_geo_absorb(input: &[u8]) -> nom::IResult<&[u8], PeerGeo, ParseError<&[u8]>> {
+    if input.is_empty() {
+        Ok((input, PeerGeo::default()))
+    } else {
+        PeerGeo::absorb::<()>(input)
+    }
+}
+
+pub fn trailing_pod_absorb(input: &[u8]) -> nom::IResult<&[u8], PodMeta, ParseError<&[u8]>> {
+    if input.is_empty() {
+        Ok((input, PodMeta::default()))
+    } else {
+        PodMeta::absorb::<()>(input)
+    }
+}
+
+pub fn trailing_tcp_absorb(input: &[u8]) -> nom::IResult<&[u8], TcpParams, ParseError<&[u8]>> {
+    if input.is_empty() {
+        Ok((input, TcpParams::default()))
+    } else {
+        TcpParams::absorb::<()>(input)
+    }
+}
+
 pub fn time_absorb(input: &[u8]) -> nom::IResult<&[u8], SystemTime, ParseError<&[u8]>> {
     nom::combinator::map(duration_absorb, |d| SystemTime::UNIX_EPOCH + d)(input)
 }
 
+/// Like `trailing_u64_absorb`, the unix epoch if absent.
+pub fn trailing_time_absorb(input: &[u8]) -> nom::IResult<&[u8], SystemTime, ParseError<&[u8]>> {
+    if input.is_empty() {
+        Ok((input, SystemTime::UNIX_EPOCH))
+    } else {
+        time_absorb(input)
+    }
+}
+
 pub fn time_emit<W>(value: &SystemTime, buffer: &mut W)
 where
     W: for<'a> Extend<&'a u8>,
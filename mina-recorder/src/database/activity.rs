@@ -0,0 +1,227 @@
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+
+use super::types::{Connection, ConnectionId, Message, StreamKind};
+
+/// How the connection was used during the window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActivityClass {
+    /// exchanged application messages
+    Active,
+    /// exchanged nothing but pings and muxer control frames
+    KeepAlive,
+    /// exchanged nothing at all
+    Idle,
+}
+
+#[derive(Serialize)]
+pub struct ConnectionActivity {
+    pub connection_id: ConnectionId,
+    pub addr: SocketAddr,
+    pub alias: String,
+    pub incoming: bool,
+    pub class: ActivityClass,
+    pub messages: u64,
+    pub keep_alive_messages: u64,
+    pub last_message: Option<SystemTime>,
+    /// the longest time between two messages within the window
+    pub max_gap_secs: f64,
+    pub mean_gap_secs: f64,
+    /// from the last message (or the start of the window) until the end of the window
+    /// or the connection is closed
+    pub silent_secs: f64,
+    #[serde(skip)]
+    gaps: Duration,
+    #[serde(skip)]
+    end: SystemTime,
+}
+
+/// Yamux and mplex control frames (pings and window updates) and kademlia pings
+/// keep the connection alive, but carry nothing.
+fn is_keep_alive(msg: &Message) -> bool {
+    matches!(msg.stream_kind, StreamKind::Yamux | StreamKind::Mplex) || msg.brief == "ping"
+}
+
+impl ConnectionActivity {
+    fn new(id: ConnectionId, cn: Connection, since: SystemTime, until: SystemTime) -> Self {
+        let end = if cn.timestamp_close == SystemTime::UNIX_EPOCH {
+            until
+        } else {
+            cn.timestamp_close.min(until)
+        };
+        ConnectionActivity {
+            connection_id: id,
+            addr: cn.info.addr,
+            alias: cn.alias,
+            incoming: cn.incoming,
+            class: ActivityClass::Idle,
+            messages: 0,
+            keep_alive_messages: 0,
+            last_message: None,
+            max_gap_secs: 0.0,
+            mean_gap_secs: 0.0,
+            silent_secs: secs(end, cn.timestamp.max(since)),
+            gaps: Duration::ZERO,
+            end,
+        }
+    }
+
+    fn observe(&mut self, msg: &Message) {
+        if let Some(last) = self.last_message {
+            let gap = msg.timestamp.duration_since(last).unwrap_or_default();
+            self.gaps += gap;
+            self.max_gap_secs = self.max_gap_secs.max(gap.as_secs_f64());
+        }
+        self.messages += 1;
+        if is_keep_alive(msg) {
+            self.keep_alive_messages += 1;
+            if self.class == ActivityClass::Idle {
+                self.class = ActivityClass::KeepAlive;
+            }
+        } else {
+            self.class = ActivityClass::Active;
+        }
+        self.last_message = Some(msg.timestamp);
+        self.silent_secs = secs(self.end, msg.timestamp);
+        if self.messages > 1 {
+            self.mean_gap_secs = self.gaps.as_secs_f64() / (self.messages - 1) as f64;
+        }
+    }
+}
+
+fn secs(end: SystemTime, start: SystemTime) -> f64 {
+    end.duration_since(start).unwrap_or_default().as_secs_f64()
+}
+
+/// The connections open during the window, classified by their messages.
+#[derive(Serialize)]
+pub struct ActivityReport {
+    pub since: SystemTime,
+    pub until: SystemTime,
+    pub total: usize,
+    pub active: usize,
+    pub keep_alive: usize,
+    pub idle: usize,
+    pub connections: Vec<ConnectionActivity>,
+}
+
+impl ActivityReport {
+    pub fn build<C, M>(connections: C, messages: M, since: SystemTime, until: SystemTime) -> Self
+    where
+        C: IntoIterator<Item = (u64, Connection)>,
+        M: IntoIterator<Item = (u64, Message)>,
+    {
+        let mut activity = connections
+            .into_iter()
+            .filter(|(_, cn)| {
+                cn.timestamp < until
+                    && (cn.timestamp_close == SystemTime::UNIX_EPOCH || cn.timestamp_close >= since)
+            })
+            .map(|(id, cn)| {
+                (
+                    id,
+                    ConnectionActivity::new(ConnectionId(id), cn, since, until),
+                )
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        messages
+            .into_iter()
+            .map(|(_, msg)| msg)
+            .filter(|msg| msg.timestamp >= since)
+            .take_while(|msg| msg.timestamp < until)
+            .for_each(|msg| {
+                if let Some(cn) = activity.get_mut(&msg.connection_id.0) {
+                    cn.observe(&msg);
+                }
+            });
+
+        let connections = activity.into_values().collect::<Vec<_>>();
+        let count = |class| connections.iter().filter(|cn| cn.class == class).count();
+        ActivityReport {
+            since,
+            until,
+            total: connections.len(),
+            active: count(ActivityClass::Active),
+            keep_alive: count(ActivityClass::KeepAlive),
+            idle: count(ActivityClass::Idle),
+            connections,
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn activity_classes() {
+    use super::StreamId;
+    use crate::event::ConnectionInfo;
+
+    let since = SystemTime::UNIX_EPOCH + Duration::from_secs(1_675_166_400);
+    let until = since + Duration::from_secs(3600);
+    let cn = |fd, timestamp_close| {
+        let info = ConnectionInfo {
+            addr: "1.2.3.4:8302".parse().unwrap(),
+            pid: 1,
+            fd,
+        };
+        let timestamp = since - Duration::from_secs(60);
+        Connection {
+            timestamp_close,
+            ..Connection::new(info, false, "node".to_owned(), timestamp)
+        }
+    };
+    let msg = |id, secs, stream_kind, brief: &str| Message {
+        connection_id: ConnectionId(id),
+        stream_id: StreamId::Forward(1),
+        stream_kind,
+        incoming: true,
+        timestamp: since + Duration::from_secs(secs),
+        offset: 0,
+        size: 12,
+        brief: brief.to_owned(),
+        seq: 0,
+        unredacted: false,
+        decoder_version: 0,
+        transfer_ns: 0,
+        chunks: 0,
+    };
+
+    let connections = vec![
+        (0, cn(10, SystemTime::UNIX_EPOCH)),
+        (1, cn(11, SystemTime::UNIX_EPOCH)),
+        (2, cn(12, SystemTime::UNIX_EPOCH)),
+        // closed before the window
+        (3, cn(13, since - Duration::from_secs(1))),
+    ];
+    // before the window
+    let mut early = msg(2, 0, StreamKind::Rpc, "get_best_tip");
+    early.timestamp = since - Duration::from_secs(1);
+    let messages = [
+        early,
+        msg(0, 10, StreamKind::Yamux, "yamux"),
+        msg(1, 20, StreamKind::Yamux, "yamux"),
+        msg(0, 30, StreamKind::Meshsub, "publish_new_state"),
+        msg(1, 50, StreamKind::Kad, "ping"),
+        msg(1, 140, StreamKind::Yamux, "yamux"),
+    ]
+    .into_iter()
+    .enumerate()
+    .map(|(i, msg)| (i as u64, msg));
+
+    let report = ActivityReport::build(connections, messages, since, until);
+    assert_eq!(report.total, 3);
+    assert_eq!((report.active, report.keep_alive, report.idle), (1, 1, 1));
+    let keep_alive = &report.connections[1];
+    assert_eq!(keep_alive.class, ActivityClass::KeepAlive);
+    assert_eq!(keep_alive.messages, 3);
+    assert_eq!(keep_alive.max_gap_secs, 90.0);
+    assert_eq!(keep_alive.mean_gap_secs, 60.0);
+    assert_eq!(keep_alive.silent_secs, 3460.0);
+    assert_eq!(report.connections[2].class, ActivityClass::Idle);
+}
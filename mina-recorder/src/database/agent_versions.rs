@@ -0,0 +1,162 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+
+use super::types::AgentVersionSighting;
+
+#[derive(Serialize)]
+pub struct AgentVersionShare {
+    pub agent_version: String,
+    /// the distinct peers which announced the version during the window
+    pub peers: usize,
+    /// the peers whose last announcement in the window is this version
+    pub current: usize,
+    /// `current` of all the peers
+    pub share: f64,
+    pub first_seen: SystemTime,
+    pub last_seen: SystemTime,
+    /// the distinct peers which announced the version in each interval of the window
+    pub timeline: Vec<usize>,
+}
+
+/// The agent versions the peers announced by identify, how the upgrade spreads over the network.
+#[derive(Serialize)]
+pub struct AgentVersionReport {
+    pub since: SystemTime,
+    pub until: SystemTime,
+    pub interval_secs: u64,
+    /// the distinct peers which announced any version
+    pub peers: usize,
+    /// the most widespread first
+    pub versions: Vec<AgentVersionShare>,
+}
+
+struct Accumulator {
+    peers: BTreeSet<String>,
+    first_seen: SystemTime,
+    last_seen: SystemTime,
+    timeline: Vec<BTreeSet<String>>,
+}
+
+impl AgentVersionReport {
+    /// The timeline has at most this many intervals, the longer window gets the longer interval.
+    const MAX_INTERVALS: u64 = 1000;
+
+    pub fn build<I>(sightings: I, since: SystemTime, until: SystemTime, interval: Duration) -> Self
+    where
+        I: IntoIterator<Item = AgentVersionSighting>,
+    {
+        let window = until.duration_since(since).unwrap_or_default().as_secs();
+        let interval_secs = interval
+            .as_secs()
+            .max(1)
+            .max((window + Self::MAX_INTERVALS - 1) / Self::MAX_INTERVALS);
+        let intervals = (window / interval_secs + 1) as usize;
+
+        let mut versions = BTreeMap::<String, Accumulator>::new();
+        // the peer to its last announcement
+        let mut latest = BTreeMap::<String, (SystemTime, String)>::new();
+        for sighting in sightings {
+            let time = sighting.timestamp;
+            if time < since || time > until {
+                continue;
+            }
+            // the peer id is unknown if the handshake is not decrypted
+            let peer = if sighting.peer_id.is_empty() {
+                sighting.addr.ip().to_string()
+            } else {
+                sighting.peer_id
+            };
+            let version = sighting.agent_version;
+            if latest.get(&peer).map_or(true, |(t, _)| *t <= time) {
+                latest.insert(peer.clone(), (time, version.clone()));
+            }
+            let acc = versions.entry(version).or_insert_with(|| Accumulator {
+                peers: BTreeSet::new(),
+                first_seen: time,
+                last_seen: time,
+                timeline: vec![BTreeSet::new(); intervals],
+            });
+            acc.first_seen = acc.first_seen.min(time);
+            acc.last_seen = acc.last_seen.max(time);
+            let offset = time.duration_since(since).unwrap_or_default().as_secs();
+            let i = ((offset / interval_secs) as usize).min(intervals - 1);
+            acc.timeline[i].insert(peer.clone());
+            acc.peers.insert(peer);
+        }
+
+        let peers = latest.len();
+        let mut current = BTreeMap::<String, usize>::new();
+        for (_, version) in latest.into_values() {
+            *current.entry(version).or_default() += 1;
+        }
+        let mut versions = versions
+            .into_iter()
+            .map(|(agent_version, acc)| {
+                let current = current.get(&agent_version).copied().unwrap_or_default();
+                AgentVersionShare {
+                    peers: acc.peers.len(),
+                    current,
+                    share: if peers == 0 {
+                        0.0
+                    } else {
+                        current as f64 / peers as f64
+                    },
+                    first_seen: acc.first_seen,
+                    last_seen: acc.last_seen,
+                    timeline: acc.timeline.iter().map(BTreeSet::len).collect(),
+                    agent_version,
+                }
+            })
+            .collect::<Vec<_>>();
+        versions.sort_by(|a, b| b.current.cmp(&a.current).then(b.peers.cmp(&a.peers)));
+
+        AgentVersionReport {
+            since,
+            until,
+            interval_secs,
+            peers,
+            versions,
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn agent_version_report() {
+    use super::types::ConnectionId;
+
+    let t = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+    let sighting = |secs, peer_id: &str, agent_version: &str| AgentVersionSighting {
+        timestamp: t(secs),
+        connection_id: ConnectionId(0),
+        addr: "1.2.3.4:8302".parse().unwrap(),
+        peer_id: peer_id.to_owned(),
+        agent_version: agent_version.to_owned(),
+    };
+    let sightings = vec![
+        sighting(100, "a", "mina/1.3.0"),
+        sighting(150, "b", "mina/1.3.0"),
+        sighting(1200, "a", "mina/1.4.0"),
+        sighting(1300, "", "mina/1.3.0"),
+        // out of the window
+        sighting(5000, "b", "mina/1.4.0"),
+    ];
+    let report = AgentVersionReport::build(sightings, t(0), t(1800), Duration::from_secs(600));
+    assert_eq!(report.peers, 3);
+    assert_eq!(report.versions.len(), 2);
+
+    let old = &report.versions[0];
+    assert_eq!(old.agent_version, "mina/1.3.0");
+    assert_eq!((old.peers, old.current), (3, 2));
+    assert_eq!((old.first_seen, old.last_seen), (t(100), t(1300)));
+    assert_eq!(old.timeline, [2, 0, 1, 0]);
+
+    let new = &report.versions[1];
+    assert_eq!((new.peers, new.current), (1, 1));
+    assert_eq!(new.timeline, [0, 0, 1, 0]);
+    assert!((new.share - 1.0 / 3.0).abs() < 1e-9);
+}
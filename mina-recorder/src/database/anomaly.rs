@@ -0,0 +1,280 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    net::IpAddr,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use parking_lot::Mutex;
+use radiation::{Absorb, Emit};
+use serde::Serialize;
+
+use crate::{custom_coding, decode::MessageType};
+
+/// Compares the number of messages in each window with the moving average
+/// of the previous windows, per peer address and per gossip topic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectorConfig {
+    pub enabled: bool,
+    pub window: Duration,
+    /// the window holds this many times more messages than usual
+    pub factor: u64,
+    /// and at least this many messages
+    pub min: u64,
+    /// no anomaly until the average is known for this many windows
+    pub warmup: u32,
+}
+
+impl Default for DetectorConfig {
+    fn default() -> Self {
+        DetectorConfig {
+            enabled: true,
+            window: Duration::from_secs(10),
+            factor: 10,
+            min: 20,
+            warmup: 6,
+        }
+    }
+}
+
+impl FromStr for DetectorConfig {
+    type Err = String;
+
+    /// `off`, or comma separated parameters, like `window:10,factor:10,min:20,warmup:6`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = DetectorConfig::default();
+        for item in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (name, value) = item.split_once(':').unwrap_or((item, ""));
+            let number = || {
+                value
+                    .parse::<u64>()
+                    .ok()
+                    .filter(|v| *v != 0)
+                    .ok_or_else(|| item.to_owned())
+            };
+            match name {
+                "off" => config.enabled = false,
+                "window" => config.window = Duration::from_secs(number()?),
+                "factor" => config.factor = number()?,
+                "min" => config.min = number()?,
+                "warmup" => config.warmup = number()? as u32,
+                _ => return Err(item.to_owned()),
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// The rate of the peer or of the topic jumped, stored in the database.
+#[derive(Clone, Debug, Absorb, Emit, Serialize)]
+pub struct Anomaly {
+    /// the beginning of the window
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub timestamp: SystemTime,
+    /// `peer` or `topic`
+    pub subject: String,
+    /// the address of the peer or the name of the topic
+    pub key: String,
+    /// messages in the window
+    pub count: u64,
+    /// usual number of messages in the window, at least one
+    pub baseline: u64,
+    pub window_secs: u64,
+    pub description: String,
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Subject {
+    Peer(IpAddr),
+    Topic(String),
+}
+
+/// Exponentially weighted moving average of the number of messages per window.
+struct Ewma {
+    start: SystemTime,
+    count: u64,
+    mean: f64,
+    windows: u32,
+}
+
+impl Ewma {
+    const ALPHA: f64 = 0.2;
+
+    fn new(start: SystemTime) -> Self {
+        Ewma {
+            start,
+            count: 0,
+            mean: 0.0,
+            windows: 0,
+        }
+    }
+
+    /// Counts the message, returns the previous window if the message begins the new one,
+    /// together with the average and the number of windows before it.
+    fn observe(
+        &mut self,
+        time: SystemTime,
+        window: Duration,
+    ) -> Option<(SystemTime, u64, f64, u32)> {
+        let elapsed = time.duration_since(self.start).unwrap_or_default();
+        let passed = (elapsed.as_nanos() / window.as_nanos()) as u32;
+        let finished = if passed == 0 {
+            None
+        } else {
+            let finished = (self.start, self.count, self.mean, self.windows);
+            self.mean = if self.windows == 0 {
+                self.count as f64
+            } else {
+                Self::ALPHA * self.count as f64 + (1.0 - Self::ALPHA) * self.mean
+            };
+            // the windows without messages
+            self.mean *= (1.0 - Self::ALPHA).powi(passed as i32 - 1);
+            self.windows = self.windows.saturating_add(passed);
+            self.start += window * passed;
+            self.count = 0;
+            Some(finished)
+        };
+        self.count += 1;
+        finished
+    }
+}
+
+#[derive(Default)]
+pub struct AnomalyDetector {
+    config: DetectorConfig,
+    state: Mutex<BTreeMap<Subject, Ewma>>,
+}
+
+impl AnomalyDetector {
+    pub fn new(config: DetectorConfig) -> Self {
+        AnomalyDetector {
+            config,
+            state: Mutex::default(),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let config = match env::var("ANOMALY_DETECTOR") {
+            Ok(s) => s.parse().unwrap_or_else(|item| {
+                log::error!("bad anomaly detector parameter {item}, use default");
+                DetectorConfig::default()
+            }),
+            Err(_) => DetectorConfig::default(),
+        };
+        Self::new(config)
+    }
+
+    pub fn config(&self) -> &DetectorConfig {
+        &self.config
+    }
+
+    /// The message of the peer, the topic is the kind of the gossip it publishes.
+    pub fn observe(&self, ip: IpAddr, tys: &[MessageType], time: SystemTime) -> Vec<Anomaly> {
+        if !self.config.enabled {
+            return vec![];
+        }
+        let mut subjects = vec![Subject::Peer(ip)];
+        for ty in tys {
+            if matches!(
+                ty,
+                MessageType::PublishNewState
+                    | MessageType::PublishSnarkPoolDiff
+                    | MessageType::PublishTransactionPoolDiff
+            ) {
+                let topic = Subject::Topic(ty.to_string());
+                if !subjects.contains(&topic) {
+                    subjects.push(topic);
+                }
+            }
+        }
+
+        let mut state = self.state.lock();
+        subjects
+            .into_iter()
+            .filter_map(|subject| {
+                let ewma = state
+                    .entry(subject.clone())
+                    .or_insert_with(|| Ewma::new(time));
+                let (start, count, mean, windows) = ewma.observe(time, self.config.window)?;
+                self.check(subject, start, count, mean, windows)
+            })
+            .collect()
+    }
+
+    fn check(
+        &self,
+        subject: Subject,
+        start: SystemTime,
+        count: u64,
+        mean: f64,
+        windows: u32,
+    ) -> Option<Anomaly> {
+        let DetectorConfig {
+            window,
+            factor,
+            min,
+            warmup,
+            ..
+        } = self.config;
+        let usual = mean.max(1.0);
+        if windows < warmup || count < min || (count as f64) < usual * factor as f64 {
+            return None;
+        }
+        let (subject, key) = match subject {
+            Subject::Peer(ip) => ("peer", ip.to_string()),
+            Subject::Topic(topic) => ("topic", topic),
+        };
+        let ratio = (count as f64 / usual).round() as u64;
+        let description = format!(
+            "{subject} {key} message rate {ratio}x baseline, {count} messages in {} seconds",
+            window.as_secs(),
+        );
+        log::info!("anomaly: {description}");
+        Some(Anomaly {
+            timestamp: start,
+            subject: subject.to_owned(),
+            key,
+            count,
+            baseline: usual.round() as u64,
+            window_secs: window.as_secs(),
+            description,
+        })
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn anomaly_detector() {
+    let config = "window:1,factor:5,min:10,warmup:3"
+        .parse::<DetectorConfig>()
+        .unwrap();
+    assert_eq!(config.window, Duration::from_secs(1));
+    assert!(!"off".parse::<DetectorConfig>().unwrap().enabled);
+    assert!("factor:0".parse::<DetectorConfig>().is_err());
+
+    let detector = AnomalyDetector::new(config);
+    let ip = "1.2.3.4".parse().unwrap();
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_675_166_400);
+    let mut anomalies = vec![];
+    // two messages per second for ten seconds, then a burst of fifty
+    for i in 0..20 {
+        let t = time + Duration::from_millis(i * 500);
+        anomalies.extend(detector.observe(ip, &[], t));
+    }
+    for i in 0..50 {
+        let t = time + Duration::from_secs(10) + Duration::from_millis(i * 10);
+        let tys = [MessageType::PublishNewState];
+        anomalies.extend(detector.observe(ip, &tys, t));
+    }
+    assert!(anomalies.is_empty());
+    // the window is finished by the next message
+    anomalies.extend(detector.observe(ip, &[], time + Duration::from_secs(11)));
+    assert_eq!(anomalies.len(), 1);
+    let anomaly = &anomalies[0];
+    assert_eq!(anomaly.subject, "peer");
+    assert_eq!(anomaly.timestamp, time + Duration::from_secs(10));
+    assert_eq!((anomaly.count, anomaly.baseline), (50, 2));
+    assert!(anomaly.description.contains("25x baseline"));
+}
@@ -0,0 +1,379 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs,
+    net::IpAddr,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use serde::{Serialize, Deserialize};
+
+use super::{
+    types::{Connection, ConnectionId, Message},
+    validation::Publish,
+};
+
+/// How the peers and the gossip topics behaved in the healthy capture, the rates are per hour.
+/// Computed by `mina-capture baseline`, the debugger loads it from `BASELINE`
+/// and compares the current capture with it, see `Baseline::compare`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Baseline {
+    pub since: SystemTime,
+    pub until: SystemTime,
+    pub peers: BTreeMap<IpAddr, PeerProfile>,
+    pub topics: BTreeMap<String, TopicProfile>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PeerProfile {
+    pub connections_per_hour: f64,
+    pub messages_per_hour: f64,
+    pub bytes_per_hour: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TopicProfile {
+    /// the duplicates are counted too
+    pub received_per_hour: f64,
+    pub sent_per_hour: f64,
+    /// the distinct peers which delivered the messages of the topic
+    pub peers: u64,
+}
+
+/// The current capture against the baseline.
+#[derive(Serialize)]
+pub struct BaselineDeviations {
+    pub since: SystemTime,
+    pub until: SystemTime,
+    pub baseline_since: SystemTime,
+    pub baseline_until: SystemTime,
+    /// the figure deviates if it is this many times bigger or smaller than in the baseline
+    pub threshold: f64,
+    pub peers_new: Vec<IpAddr>,
+    pub peers_gone: Vec<IpAddr>,
+    pub topics_new: Vec<String>,
+    pub topics_gone: Vec<String>,
+    /// the peers and the topics present in both, the most deviating first
+    pub deviations: Vec<Deviation>,
+}
+
+#[derive(Serialize)]
+pub struct Deviation {
+    /// the address of the peer or the name of the topic
+    pub subject: String,
+    pub metric: &'static str,
+    pub baseline: f64,
+    pub current: f64,
+    /// `current / baseline`, `None` if it is zero in the baseline
+    pub ratio: Option<f64>,
+}
+
+#[derive(Debug)]
+pub enum BaselineError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for BaselineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BaselineError::Io(err) => write!(f, "{err}"),
+            BaselineError::Json(err) => write!(f, "bad baseline: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for BaselineError {}
+
+const HOUR: Duration = Duration::from_secs(3600);
+
+impl Baseline {
+    /// The rates below this many per hour both in the baseline and now are the noise.
+    pub const MIN_RATE: f64 = 1.0;
+
+    /// The default of `threshold`.
+    pub const THRESHOLD: f64 = 2.0;
+
+    /// `BASELINE` is the path to the json of the baseline, `None` if it is not set.
+    pub fn from_env() -> Option<Self> {
+        let path = std::env::var("BASELINE").ok()?;
+        match Self::load(&path) {
+            Ok(v) => {
+                log::info!("compare the capture with the baseline {path}");
+                Some(v)
+            }
+            Err(err) => {
+                log::error!("cannot load the baseline {path}: {err}");
+                None
+            }
+        }
+    }
+
+    pub fn load<P>(path: P) -> Result<Self, BaselineError>
+    where
+        P: AsRef<Path>,
+    {
+        let bytes = fs::read(path).map_err(BaselineError::Io)?;
+        serde_json::from_slice(&bytes).map_err(BaselineError::Json)
+    }
+
+    pub fn store<P>(&self, path: P) -> Result<(), BaselineError>
+    where
+        P: AsRef<Path>,
+    {
+        let bytes = serde_json::to_vec_pretty(self).map_err(BaselineError::Json)?;
+        fs::write(path, bytes).map_err(BaselineError::Io)
+    }
+
+    /// The `connections` give the addresses of the peers, those opened within the range
+    /// are counted. The `messages` and the `publishes` must be within the range.
+    pub fn build<C, M, P>(
+        connections: C,
+        messages: M,
+        publishes: P,
+        since: SystemTime,
+        until: SystemTime,
+    ) -> Self
+    where
+        C: IntoIterator<Item = (u64, Connection)>,
+        M: IntoIterator<Item = Message>,
+        P: IntoIterator<Item = Publish>,
+    {
+        let hours = until
+            .duration_since(since)
+            .unwrap_or_default()
+            .as_secs_f64()
+            / HOUR.as_secs_f64();
+        let per_hour = |count: u64| {
+            if hours == 0.0 {
+                count as f64
+            } else {
+                count as f64 / hours
+            }
+        };
+
+        let mut addresses = BTreeMap::<ConnectionId, IpAddr>::new();
+        let mut peers = BTreeMap::<IpAddr, [u64; 3]>::new();
+        for (id, cn) in connections {
+            let ip = cn.info.addr.ip();
+            addresses.insert(ConnectionId(id), ip);
+            if cn.timestamp >= since && cn.timestamp < until {
+                peers.entry(ip).or_default()[0] += 1;
+            }
+        }
+        for msg in messages {
+            if let Some(ip) = addresses.get(&msg.connection_id) {
+                let counts = peers.entry(*ip).or_default();
+                counts[1] += 1;
+                counts[2] += u64::from(msg.size);
+            }
+        }
+
+        let mut topics = BTreeMap::<String, ([u64; 2], BTreeSet<IpAddr>)>::new();
+        for publish in publishes {
+            let (counts, delivered_by) = topics.entry(publish.topic).or_default();
+            if publish.incoming {
+                counts[0] += 1;
+                delivered_by.extend(addresses.get(&publish.connection_id));
+            } else {
+                counts[1] += 1;
+            }
+        }
+
+        Baseline {
+            since,
+            until,
+            peers: peers
+                .into_iter()
+                .map(|(ip, [connections, messages, bytes])| {
+                    let profile = PeerProfile {
+                        connections_per_hour: per_hour(connections),
+                        messages_per_hour: per_hour(messages),
+                        bytes_per_hour: per_hour(bytes),
+                    };
+                    (ip, profile)
+                })
+                .collect(),
+            topics: topics
+                .into_iter()
+                .map(|(topic, ([received, sent], delivered_by))| {
+                    let profile = TopicProfile {
+                        received_per_hour: per_hour(received),
+                        sent_per_hour: per_hour(sent),
+                        peers: delivered_by.len() as u64,
+                    };
+                    (topic, profile)
+                })
+                .collect(),
+        }
+    }
+
+    /// The figures of `current` which are `threshold` times bigger or smaller than here.
+    pub fn compare(&self, current: &Baseline, threshold: f64) -> BaselineDeviations {
+        let mut deviations = vec![];
+        let mut check = |subject: &dyn fmt::Display, metric, baseline: f64, current: f64| {
+            if baseline < Self::MIN_RATE && current < Self::MIN_RATE {
+                return;
+            }
+            let ratio = (baseline != 0.0).then(|| current / baseline);
+            if ratio.map_or(true, |r| r >= threshold || r <= 1.0 / threshold) {
+                deviations.push(Deviation {
+                    subject: subject.to_string(),
+                    metric,
+                    baseline,
+                    current,
+                    ratio,
+                });
+            }
+        };
+        for (ip, b) in &self.peers {
+            if let Some(c) = current.peers.get(ip) {
+                for (metric, b, c) in [
+                    (
+                        "connections_per_hour",
+                        b.connections_per_hour,
+                        c.connections_per_hour,
+                    ),
+                    (
+                        "messages_per_hour",
+                        b.messages_per_hour,
+                        c.messages_per_hour,
+                    ),
+                    ("bytes_per_hour", b.bytes_per_hour, c.bytes_per_hour),
+                ] {
+                    check(ip, metric, b, c);
+                }
+            }
+        }
+        for (topic, b) in &self.topics {
+            if let Some(c) = current.topics.get(topic) {
+                for (metric, b, c) in [
+                    (
+                        "received_per_hour",
+                        b.received_per_hour,
+                        c.received_per_hour,
+                    ),
+                    ("sent_per_hour", b.sent_per_hour, c.sent_per_hour),
+                    ("peers", b.peers as f64, c.peers as f64),
+                ] {
+                    check(topic, metric, b, c);
+                }
+            }
+        }
+        // the appeared figures first, then by how many times it changed
+        let distance = |d: &Deviation| d.ratio.map_or(f64::INFINITY, |r| r.max(1.0 / r));
+        deviations.sort_by(|a, b| distance(b).total_cmp(&distance(a)));
+
+        fn missing<K, V>(a: &BTreeMap<K, V>, b: &BTreeMap<K, V>) -> Vec<K>
+        where
+            K: Ord + Clone,
+        {
+            a.keys().filter(|k| !b.contains_key(k)).cloned().collect()
+        }
+        BaselineDeviations {
+            since: current.since,
+            until: current.until,
+            baseline_since: self.since,
+            baseline_until: self.until,
+            threshold,
+            peers_new: missing(&current.peers, &self.peers),
+            peers_gone: missing(&self.peers, &current.peers),
+            topics_new: missing(&current.topics, &self.topics),
+            topics_gone: missing(&self.topics, &current.topics),
+            deviations,
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn baseline_deviations() {
+    use super::types::{StreamId, StreamKind};
+    use crate::event::ConnectionInfo;
+
+    let since = SystemTime::UNIX_EPOCH + Duration::from_secs(1_675_166_400);
+    let until = since + Duration::from_secs(7200);
+    let connection = |addr: &str| {
+        let info = ConnectionInfo {
+            addr: addr.parse().unwrap(),
+            pid: 1,
+            fd: 10,
+        };
+        Connection::new(
+            info,
+            true,
+            "node".to_owned(),
+            since + Duration::from_secs(10),
+        )
+    };
+    let message = |cn: u64, size| Message {
+        connection_id: ConnectionId(cn),
+        stream_id: StreamId::Forward(1),
+        stream_kind: StreamKind::Meshsub,
+        incoming: true,
+        timestamp: since,
+        offset: 0,
+        size,
+        brief: String::new(),
+        seq: 0,
+        unredacted: false,
+        decoder_version: 0,
+        transfer_ns: 0,
+        chunks: 0,
+    };
+    let publish = |cn: u64, incoming| Publish {
+        time: since,
+        connection_id: ConnectionId(cn),
+        incoming,
+        topic: "coda/consensus-messages/0.0.1".to_owned(),
+        hash: [0; 16],
+    };
+    let capture = |messages: usize, publishes: usize| {
+        Baseline::build(
+            [
+                (0, connection("1.2.3.4:8302")),
+                (1, connection("1.2.3.5:8302")),
+            ],
+            (0..messages).map(|_| message(0, 1000)),
+            (0..publishes).map(|i| publish(i as u64 % 2, i % 4 != 0)),
+            since,
+            until,
+        )
+    };
+
+    let baseline = capture(20, 40);
+    let a = &baseline.peers[&"1.2.3.4".parse::<IpAddr>().unwrap()];
+    assert_eq!((a.connections_per_hour, a.messages_per_hour), (0.5, 10.0));
+    assert_eq!(a.bytes_per_hour, 10_000.0);
+    let topic = &baseline.topics["coda/consensus-messages/0.0.1"];
+    assert_eq!((topic.received_per_hour, topic.sent_per_hour), (15.0, 5.0));
+    assert_eq!(topic.peers, 2);
+
+    // survives the file
+    let dir = temp_dir::TempDir::new().unwrap();
+    let path = dir.path().join("baseline.json");
+    baseline.store(&path).unwrap();
+    let baseline = Baseline::load(&path).unwrap();
+    assert_eq!(baseline.peers.len(), 2);
+
+    // the same behavior, nothing deviates
+    let same = baseline.compare(&capture(20, 40), Baseline::THRESHOLD);
+    assert!(same.deviations.is_empty());
+    assert!(same.peers_new.is_empty() && same.peers_gone.is_empty());
+
+    // the peer sends five times more, the gossip stops
+    let report = baseline.compare(&capture(100, 0), Baseline::THRESHOLD);
+    let metrics = report
+        .deviations
+        .iter()
+        .map(|d| (d.subject.as_str(), d.metric, d.ratio))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        metrics,
+        [
+            ("1.2.3.4", "messages_per_hour", Some(5.0)),
+            ("1.2.3.4", "bytes_per_hour", Some(5.0)),
+        ]
+    );
+    assert_eq!(report.topics_gone, ["coda/consensus-messages/0.0.1"]);
+}
@@ -0,0 +1,194 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use super::{core::DbError, redaction::Redaction, types::FullMessage};
+
+/// Keeps at most `capacity` least recently used values, each expires after `ttl`.
+pub struct LruCache<K, V> {
+    capacity: usize,
+    ttl: Duration,
+    tick: u64,
+    entries: BTreeMap<K, Entry<V>>,
+    // tick of the last use -> key, the first is the least recently used
+    order: BTreeMap<u64, K>,
+}
+
+struct Entry<V> {
+    value: V,
+    inserted: Instant,
+    tick: u64,
+}
+
+impl<K, V> LruCache<K, V>
+where
+    K: Ord + Clone,
+{
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        LruCache {
+            capacity,
+            ttl,
+            tick: 0,
+            entries: BTreeMap::default(),
+            order: BTreeMap::default(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let entry = self.entries.get_mut(key)?;
+        self.order.remove(&entry.tick);
+        if entry.inserted.elapsed() >= self.ttl {
+            self.entries.remove(key);
+            return None;
+        }
+        self.tick += 1;
+        entry.tick = self.tick;
+        self.order.insert(self.tick, key.clone());
+        Some(&entry.value)
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        if let Some(entry) = self.entries.remove(&key) {
+            self.order.remove(&entry.tick);
+        }
+        while self.entries.len() >= self.capacity {
+            match self.order.pop_first() {
+                Some((_, lru)) => self.entries.remove(&lru),
+                None => break,
+            };
+        }
+        self.tick += 1;
+        self.order.insert(self.tick, key.clone());
+        let entry = Entry {
+            value,
+            inserted: Instant::now(),
+            tick: self.tick,
+        };
+        self.entries.insert(key, entry);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+/// Decoded message bodies and aggregations the HTTP server returns.
+/// The messages never change once stored, the aggregations are invalidated
+/// when new data arrives.
+pub struct HttpCache {
+    messages: Mutex<LruCache<(u64, Redaction), FullMessage>>,
+    aggregations: Mutex<LruCache<String, (u64, serde_json::Value)>>,
+    generation: AtomicU64,
+}
+
+impl Default for HttpCache {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CAPACITY)
+    }
+}
+
+impl HttpCache {
+    const DEFAULT_CAPACITY: usize = 0x400;
+    const TTL: Duration = Duration::from_secs(60);
+
+    pub fn new(capacity: usize) -> Self {
+        HttpCache {
+            messages: Mutex::new(LruCache::new(capacity, Self::TTL)),
+            aggregations: Mutex::new(LruCache::new(capacity, Self::TTL)),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let capacity = env::var("HTTP_CACHE_SIZE")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(Self::DEFAULT_CAPACITY);
+        Self::new(capacity)
+    }
+
+    /// New data arrived, the aggregations are stale.
+    pub fn invalidate(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn message<F>(
+        &self,
+        id: u64,
+        redaction: Redaction,
+        fetch: F,
+    ) -> Result<FullMessage, DbError>
+    where
+        F: FnOnce() -> Result<FullMessage, DbError>,
+    {
+        if let Some(v) = self.messages.lock().get(&(id, redaction)) {
+            return Ok(v.clone());
+        }
+        let v = fetch()?;
+        self.messages.lock().insert((id, redaction), v.clone());
+        Ok(v)
+    }
+
+    pub fn aggregation<F, T>(&self, key: &str, compute: F) -> serde_json::Value
+    where
+        F: FnOnce() -> T,
+        T: Serialize,
+    {
+        let generation = self.generation.load(Ordering::Relaxed);
+        let key = key.to_owned();
+        if let Some((g, v)) = self.aggregations.lock().get(&key) {
+            if *g == generation {
+                return v.clone();
+            }
+        }
+        let v = serde_json::to_value(compute()).unwrap_or_default();
+        self.aggregations
+            .lock()
+            .insert(key, (generation, v.clone()));
+        v
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn lru_cache() {
+    let mut cache = LruCache::new(2, Duration::from_secs(60));
+    cache.insert(1, "a");
+    cache.insert(2, "b");
+    assert_eq!(cache.get(&1), Some(&"a"));
+    // `2` is the least recently used
+    cache.insert(3, "c");
+    assert_eq!(cache.get(&2), None);
+    assert_eq!(cache.get(&1), Some(&"a"));
+    assert_eq!(cache.get(&3), Some(&"c"));
+    assert_eq!(cache.len(), 2);
+
+    let mut cache = LruCache::new(2, Duration::ZERO);
+    cache.insert(1, "a");
+    assert_eq!(cache.get(&1), None);
+    assert!(cache.is_empty());
+
+    let cache = HttpCache::new(4);
+    assert_eq!(cache.aggregation("x", || 1), 1);
+    assert_eq!(cache.aggregation("x", || 2), 1);
+    cache.invalidate();
+    assert_eq!(cache.aggregation("x", || 3), 3);
+}
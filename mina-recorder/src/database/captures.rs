@@ -0,0 +1,198 @@
+use std::{cmp::Reverse, env, path::PathBuf, thread};
+
+use serde::Serialize;
+
+use super::{
+    core::{DbCore, DbError},
+    params::{Direction, ValidParams},
+    types::FullMessage,
+};
+
+/// The captures copied from other hosts, served by this server under `/capture/{name}`.
+#[derive(Default)]
+pub struct Captures {
+    mounted: Vec<(String, PathBuf, DbCore)>,
+}
+
+/// The capture mounted under `/capture/{name}`.
+#[derive(Serialize)]
+pub struct MountedCapture {
+    pub name: String,
+    pub path: PathBuf,
+    pub connections: u64,
+    pub messages: u64,
+}
+
+/// The message found in one of the mounted captures, the id is of that capture.
+#[derive(Serialize)]
+pub struct CaptureMessage {
+    pub capture: String,
+    pub id: u64,
+    #[serde(flatten)]
+    pub message: FullMessage,
+}
+
+impl Captures {
+    /// `CAPTURES`, comma separated `<name>=<path>`, like `node-a=/data/a,node-b=/data/b`.
+    /// The capture which cannot be opened is logged and not mounted.
+    pub fn from_env() -> Self {
+        let mut captures = Captures::default();
+        let Ok(s) = env::var("CAPTURES") else {
+            return captures;
+        };
+        for item in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let Some((name, path)) = item.split_once('=') else {
+                log::error!("the capture {item} must be <name>=<path>");
+                continue;
+            };
+            let name = name.trim();
+            if name.is_empty() || name.contains('/') || captures.get(name).is_some() {
+                log::error!("the capture name {name:?} is empty, has a slash or is not unique");
+                continue;
+            }
+            if let Err(err) = captures.mount(name, path.trim()) {
+                log::error!("cannot mount the capture {name} at {path}: {err}");
+            }
+        }
+        captures
+    }
+
+    /// Opened read only, the database must not be in use by the running debugger.
+    pub fn mount<P>(&mut self, name: &str, path: P) -> Result<(), DbError>
+    where
+        P: Into<PathBuf>,
+    {
+        let path = path.into();
+        let core = DbCore::open_read_only(&path)?;
+        log::info!("mount the capture {name} at {}", path.display());
+        self.mounted.push((name.to_owned(), path, core));
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&DbCore> {
+        self.mounted
+            .iter()
+            .find(|(n, ..)| n == name)
+            .map(|(.., core)| core)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &DbCore)> {
+        self.mounted
+            .iter()
+            .map(|(name, _, core)| (name.as_str(), core))
+    }
+
+    pub fn summary(&self) -> Vec<MountedCapture> {
+        self.mounted
+            .iter()
+            .map(|(name, path, core)| MountedCapture {
+                name: name.clone(),
+                path: path.clone(),
+                connections: core
+                    .total::<{ DbCore::CONNECTIONS_CNT }>()
+                    .unwrap_or_default(),
+                messages: core.total::<{ DbCore::MESSAGES_CNT }>().unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    /// Searches every capture at once, the results are merged by the time. The limit
+    /// applies to the result, not to each capture. The ids in the params are of each
+    /// capture, so the search by the time is meaningful across the captures.
+    pub fn search(&self, params: &ValidParams) -> Vec<CaptureMessage> {
+        let mut found = thread::scope(|s| {
+            let searches = self
+                .mounted
+                .iter()
+                .map(|(name, _, core)| {
+                    s.spawn(move || {
+                        core.fetch_messages(params)
+                            .map(|(id, message)| CaptureMessage {
+                                capture: name.clone(),
+                                id,
+                                message,
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+            searches
+                .into_iter()
+                .flat_map(|search| search.join().unwrap_or_default())
+                .collect::<Vec<_>>()
+        });
+        // stable, the captures keep their order at the same time
+        match params.coordinate.direction {
+            Direction::Forward => found.sort_by_key(|v| v.message.timestamp),
+            Direction::Reverse => found.sort_by_key(|v| Reverse(v.message.timestamp)),
+        }
+        found.truncate(params.coordinate.limit);
+        found
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn search_merged() {
+    use std::time::{Duration, SystemTime};
+
+    use super::{
+        params::Params,
+        rocksdb::DbFacade,
+        types::{StreamId, StreamKind},
+    };
+    use crate::event::{ConnectionInfo, DirectedId, EventMetadata};
+
+    let dirs = [(); 2].map(|()| temp_dir::TempDir::new().unwrap());
+    let mut captures = Captures::default();
+    let mut groups = vec![];
+    for (i, dir) in dirs.iter().enumerate() {
+        let db = DbFacade::open(dir.path()).unwrap();
+        let info = ConnectionInfo {
+            addr: "1.2.3.4:8302".parse().unwrap(),
+            pid: 1,
+            fd: 10,
+        };
+        let group = db
+            .add(info, false, "node".to_owned(), SystemTime::UNIX_EPOCH)
+            .unwrap();
+        // the messages of both captures interleave by the time
+        for t in [i as u64, i as u64 + 2] {
+            let id = DirectedId {
+                metadata: EventMetadata {
+                    time: SystemTime::UNIX_EPOCH + Duration::from_secs(t),
+                    ..EventMetadata::default()
+                },
+                incoming: true,
+                ..DirectedId::default()
+            };
+            group
+                .get(StreamId::Forward(0))
+                .add(&id, StreamKind::Select, b"/multistream/1.0.0\n")
+                .unwrap();
+        }
+        let name = format!("node-{i}");
+        captures
+            .mounted
+            .push((name, dir.path().to_owned(), db.core()));
+        groups.push(group);
+    }
+
+    let names = captures.summary().into_iter().map(|c| c.name);
+    assert_eq!(names.collect::<Vec<_>>(), ["node-0", "node-1"]);
+
+    let params = Params::default().with_limit(3).validate().unwrap();
+    let found = captures
+        .search(&params)
+        .into_iter()
+        .map(|v| (v.capture, v.id))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        found,
+        [
+            ("node-0".to_owned(), 0),
+            ("node-1".to_owned(), 0),
+            ("node-0".to_owned(), 1),
+        ]
+    );
+}
@@ -0,0 +1,371 @@
+use std::{
+    collections::BTreeMap,
+    net::{IpAddr, SocketAddr},
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+
+use super::types::{Connection, ConnectionId, NoiseHandshake};
+
+/// Why the connection did not reach the application protocols.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HandshakeFailure {
+    /// no handshake is recorded, the connection closed before the first message
+    /// or failed at the private network layer
+    NoHandshake,
+    /// the connection closed in the middle of the handshake
+    Incomplete,
+    /// a message of the handshake is too short or too big
+    Malformed,
+    /// the message authentication failed, the peer might use another key
+    MacMismatch,
+    /// the debugger did not observe the secret keys of the node
+    KeyNotFound,
+    CannotDecrypt,
+    /// discarded in favor of another connection after simultaneous connect
+    SimultaneousConnect,
+    /// the multistream select negotiation cannot be parsed
+    NegotiationFailed,
+}
+
+impl HandshakeFailure {
+    /// Categorizes the connection by its noise handshake and multistream negotiations.
+    pub fn of(
+        cn: &Connection,
+        handshake: Option<&NoiseHandshake>,
+        negotiation_failed: bool,
+    ) -> Option<Self> {
+        if cn.superseded_by.is_some() {
+            return Some(HandshakeFailure::SimultaneousConnect);
+        }
+        let handshake = match handshake {
+            Some(v) => v,
+            None => return Some(HandshakeFailure::NoHandshake),
+        };
+        let error = handshake.error.as_str();
+        if error.is_empty() {
+            if !handshake.complete {
+                Some(HandshakeFailure::Incomplete)
+            } else if negotiation_failed {
+                Some(HandshakeFailure::NegotiationFailed)
+            } else {
+                None
+            }
+        } else if error.contains("too short") || error.contains("too big") {
+            Some(HandshakeFailure::Malformed)
+        } else if error.contains("mac mismatch") {
+            Some(HandshakeFailure::MacMismatch)
+        } else if error.contains("key not found") {
+            Some(HandshakeFailure::KeyNotFound)
+        } else {
+            Some(HandshakeFailure::CannotDecrypt)
+        }
+    }
+}
+
+/// The connection closed before the noise handshake completed, how far it got.
+#[derive(Clone, Debug, Serialize)]
+pub struct PartialHandshake {
+    pub connection_id: ConnectionId,
+    pub addr: SocketAddr,
+    pub incoming: bool,
+    pub failure: HandshakeFailure,
+    /// the number of the last handshake message observed, from 1 to 3,
+    /// zero if none or if the capture is older than the field
+    pub last_message: u64,
+    /// the peer sent the last handshake message
+    pub last_message_incoming: Option<bool>,
+    /// from the connection open until the last handshake message
+    pub last_message_after_secs: Option<f64>,
+    /// from the connection open until the close
+    pub elapsed_secs: f64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub error: String,
+}
+
+impl PartialHandshake {
+    /// `None` if the handshake completed or the connection is still open.
+    pub fn of(
+        connection_id: ConnectionId,
+        cn: &Connection,
+        handshake: Option<&NoiseHandshake>,
+        failure: Option<HandshakeFailure>,
+    ) -> Option<Self> {
+        if handshake.map_or(false, |h| h.complete) || cn.timestamp_close == SystemTime::UNIX_EPOCH {
+            return None;
+        }
+        let seen = handshake.filter(|h| h.last_message != 0);
+        let secs = |time: SystemTime| {
+            time.duration_since(cn.timestamp)
+                .ok()
+                .map(|d| d.as_secs_f64())
+        };
+        Some(PartialHandshake {
+            connection_id,
+            addr: cn.info.addr,
+            incoming: cn.incoming,
+            failure: failure?,
+            last_message: seen.map_or(0, |h| h.last_message),
+            last_message_incoming: seen.map(|h| h.last_message_incoming),
+            last_message_after_secs: seen.and_then(|h| secs(h.last_message_time)),
+            elapsed_secs: secs(cn.timestamp_close).unwrap_or_default(),
+            bytes_in: cn.layers_in.total(),
+            bytes_out: cn.layers_out.total(),
+            error: handshake.map(|h| h.error.clone()).unwrap_or_default(),
+        })
+    }
+}
+
+/// The connection as the churn report sees it.
+pub struct ConnectionOutcome {
+    pub peer: IpAddr,
+    pub opened: SystemTime,
+    /// `None` if the connection is still open
+    pub closed: Option<SystemTime>,
+    pub failure: Option<HandshakeFailure>,
+    pub partial: Option<PartialHandshake>,
+}
+
+#[derive(Serialize)]
+pub struct ChurnReport {
+    pub since: SystemTime,
+    pub until: SystemTime,
+    pub connects: u64,
+    pub disconnects: u64,
+    /// of the connections opened and closed within the range
+    pub median_lifetime_secs: Option<f64>,
+    pub failures: BTreeMap<HandshakeFailure, u64>,
+    /// of the connections opened within the range
+    pub partial_handshakes: PartialHandshakes,
+    pub hours: Vec<ChurnHour>,
+    /// the peers which reconnect the most, at most `TOP_PEERS`
+    pub top_peers: Vec<PeerChurn>,
+}
+
+/// The connections closed in the middle of the handshake or before it,
+/// they did not exchange a message, but still tell how far they got.
+#[derive(Default, Serialize)]
+pub struct PartialHandshakes {
+    pub connections: u64,
+    /// by the number of the last handshake message observed, zero if none
+    pub last_message: BTreeMap<u64, u64>,
+    /// closed without a single byte exchanged
+    pub silent: u64,
+    pub median_elapsed_secs: Option<f64>,
+    pub median_bytes: Option<f64>,
+}
+
+#[derive(Serialize)]
+pub struct ChurnHour {
+    pub start: SystemTime,
+    pub connects: u64,
+    pub disconnects: u64,
+    /// of the connections opened during the hour
+    pub failures: BTreeMap<HandshakeFailure, u64>,
+}
+
+#[derive(Serialize)]
+pub struct PeerChurn {
+    pub peer: IpAddr,
+    pub connects: u64,
+    /// the connects after the first one
+    pub reconnects: u64,
+    pub median_lifetime_secs: Option<f64>,
+    pub failures: BTreeMap<HandshakeFailure, u64>,
+}
+
+const HOUR: Duration = Duration::from_secs(3600);
+
+fn median(mut values: Vec<f64>) -> Option<f64> {
+    values.sort_by(f64::total_cmp);
+    let n = values.len();
+    match n {
+        0 => None,
+        _ if n % 2 == 1 => Some(values[n / 2]),
+        _ => Some((values[n / 2 - 1] + values[n / 2]) / 2.0),
+    }
+}
+
+impl ChurnReport {
+    pub const TOP_PEERS: usize = 20;
+
+    pub fn build<I>(connections: I, since: SystemTime, until: SystemTime) -> Self
+    where
+        I: IntoIterator<Item = ConnectionOutcome>,
+    {
+        let range = until.duration_since(since).unwrap_or_default();
+        let hours_number = (range.as_secs() + HOUR.as_secs() - 1) / HOUR.as_secs();
+        let mut hours = (0..hours_number as u32)
+            .map(|i| ChurnHour {
+                start: since + HOUR * i,
+                connects: 0,
+                disconnects: 0,
+                failures: BTreeMap::default(),
+            })
+            .collect::<Vec<_>>();
+        let hour_of = |time: SystemTime| {
+            let offset = time.duration_since(since).ok()?;
+            Some((offset.as_secs() / HOUR.as_secs()) as usize).filter(|_| time < until)
+        };
+
+        let mut connects = 0;
+        let mut disconnects = 0;
+        let mut lifetimes = vec![];
+        let mut failures = BTreeMap::<HandshakeFailure, u64>::new();
+        let mut partial_handshakes = PartialHandshakes::default();
+        let (mut partial_elapsed, mut partial_bytes) = (vec![], vec![]);
+        let mut peers = BTreeMap::<IpAddr, (PeerChurn, Vec<f64>)>::new();
+        for outcome in connections {
+            let lifetime = outcome
+                .closed
+                .and_then(|closed| closed.duration_since(outcome.opened).ok())
+                .map(|lifetime| lifetime.as_secs_f64());
+            if let Some(i) = hour_of(outcome.opened) {
+                connects += 1;
+                let hour = &mut hours[i];
+                hour.connects += 1;
+                if let Some(failure) = outcome.failure {
+                    *failures.entry(failure).or_default() += 1;
+                    *hour.failures.entry(failure).or_default() += 1;
+                }
+                if let Some(partial) = &outcome.partial {
+                    partial_handshakes.connections += 1;
+                    *partial_handshakes
+                        .last_message
+                        .entry(partial.last_message)
+                        .or_default() += 1;
+                    let bytes = partial.bytes_in + partial.bytes_out;
+                    partial_handshakes.silent += (bytes == 0) as u64;
+                    partial_elapsed.push(partial.elapsed_secs);
+                    partial_bytes.push(bytes as f64);
+                }
+
+                let (peer, peer_lifetimes) = peers.entry(outcome.peer).or_insert_with(|| {
+                    let peer = PeerChurn {
+                        peer: outcome.peer,
+                        connects: 0,
+                        reconnects: 0,
+                        median_lifetime_secs: None,
+                        failures: BTreeMap::default(),
+                    };
+                    (peer, vec![])
+                });
+                peer.connects += 1;
+                if let Some(failure) = outcome.failure {
+                    *peer.failures.entry(failure).or_default() += 1;
+                }
+                if let (Some(lifetime), Some(_)) = (lifetime, outcome.closed.and_then(hour_of)) {
+                    lifetimes.push(lifetime);
+                    peer_lifetimes.push(lifetime);
+                }
+            }
+            if let Some(i) = outcome.closed.and_then(hour_of) {
+                disconnects += 1;
+                hours[i].disconnects += 1;
+            }
+        }
+
+        let mut top_peers = peers
+            .into_values()
+            .map(|(mut peer, lifetimes)| {
+                peer.reconnects = peer.connects - 1;
+                peer.median_lifetime_secs = median(lifetimes);
+                peer
+            })
+            .collect::<Vec<_>>();
+        top_peers.sort_by(|a, b| b.connects.cmp(&a.connects).then(a.peer.cmp(&b.peer)));
+        top_peers.truncate(Self::TOP_PEERS);
+
+        partial_handshakes.median_elapsed_secs = median(partial_elapsed);
+        partial_handshakes.median_bytes = median(partial_bytes);
+
+        ChurnReport {
+            since,
+            until,
+            connects,
+            disconnects,
+            median_lifetime_secs: median(lifetimes),
+            failures,
+            partial_handshakes,
+            hours,
+            top_peers,
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn churn_report() {
+    let since = SystemTime::UNIX_EPOCH + Duration::from_secs(1_675_166_400);
+    let until = since + Duration::from_secs(7200);
+    let at = |secs: u64| since + Duration::from_secs(secs);
+    let a = "1.2.3.4".parse().unwrap();
+    let b = "1.2.3.5".parse().unwrap();
+    let outcome = |peer, opened, closed: Option<u64>, failure| ConnectionOutcome {
+        peer,
+        opened: at(opened),
+        closed: closed.map(at),
+        failure,
+        partial: None,
+    };
+    let partial = |last_message, elapsed_secs, bytes_in| PartialHandshake {
+        connection_id: ConnectionId(0),
+        addr: SocketAddr::new(a, 8302),
+        incoming: true,
+        failure: HandshakeFailure::Incomplete,
+        last_message,
+        last_message_incoming: Some(true),
+        last_message_after_secs: Some(elapsed_secs),
+        elapsed_secs,
+        bytes_in,
+        bytes_out: 0,
+        error: String::new(),
+    };
+    let report = ChurnReport::build(
+        [
+            // opened before the range, only the disconnect is counted
+            ConnectionOutcome {
+                peer: b,
+                opened: since - Duration::from_secs(60),
+                closed: Some(at(10)),
+                failure: None,
+                partial: None,
+            },
+            outcome(a, 100, Some(110), Some(HandshakeFailure::MacMismatch)),
+            outcome(a, 200, Some(230), Some(HandshakeFailure::MacMismatch)),
+            outcome(a, 3700, Some(3800), None),
+            outcome(b, 4000, None, None),
+            ConnectionOutcome {
+                partial: Some(partial(1, 2.0, 32)),
+                ..outcome(b, 4100, Some(4102), Some(HandshakeFailure::Incomplete))
+            },
+            ConnectionOutcome {
+                partial: Some(partial(2, 4.0, 80)),
+                ..outcome(b, 4200, Some(4204), Some(HandshakeFailure::Incomplete))
+            },
+        ],
+        since,
+        until,
+    );
+    assert_eq!((report.connects, report.disconnects), (6, 6));
+    assert_eq!(report.median_lifetime_secs, Some(10.0));
+    assert_eq!(report.failures[&HandshakeFailure::MacMismatch], 2);
+    assert_eq!(report.hours.len(), 2);
+    assert_eq!(
+        (report.hours[0].connects, report.hours[0].disconnects),
+        (2, 3)
+    );
+    assert_eq!(report.hours[1].failures[&HandshakeFailure::Incomplete], 2);
+    let top = &report.top_peers[0];
+    assert_eq!((top.peer, top.connects, top.reconnects), (a, 3, 2));
+    assert_eq!(top.median_lifetime_secs, Some(30.0));
+    assert_eq!(report.top_peers[1].median_lifetime_secs, Some(3.0));
+    let partial = &report.partial_handshakes;
+    assert_eq!((partial.connections, partial.silent), (2, 0));
+    assert_eq!(partial.last_message.get(&2), Some(&1));
+    assert_eq!(partial.median_elapsed_secs, Some(3.0));
+    assert_eq!(partial.median_bytes, Some(56.0));
+}
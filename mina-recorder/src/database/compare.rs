@@ -0,0 +1,236 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    net::IpAddr,
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+
+use super::{
+    core::DbError,
+    reader::{CaptureReader, ConnectionFilter, MessageFilter},
+    types::{ConnectionId, StreamKind},
+};
+
+/// Figures of a capture which tell whether the node behaves differently,
+/// for example after the version upgrade.
+#[derive(Default, Serialize)]
+pub struct CaptureSummary {
+    pub connections: usize,
+    pub peers: BTreeSet<IpAddr>,
+    /// the connections which exchanged bytes, but no message after the handshake
+    pub handshake_failures: usize,
+    /// number of messages per stream kind
+    pub messages: BTreeMap<String, u64>,
+    /// from the first message to the last
+    pub duration: Duration,
+    /// number of messages per stream kind which cannot be decoded
+    pub decode_errors: BTreeMap<String, u64>,
+}
+
+impl CaptureSummary {
+    /// Decodes every message, takes a while for a big capture.
+    pub fn collect(reader: &CaptureReader) -> Self {
+        let mut summary = CaptureSummary::default();
+
+        let mut exchanged_bytes = BTreeSet::new();
+        for (id, cn) in reader.connections(&ConnectionFilter::default()) {
+            summary.connections += 1;
+            summary.peers.insert(cn.info.addr.ip());
+            if cn.stats_in.total_bytes + cn.stats_out.total_bytes != 0 {
+                exchanged_bytes.insert(id);
+            }
+        }
+
+        let mut established = BTreeSet::<ConnectionId>::new();
+        let mut range = None::<(SystemTime, SystemTime)>;
+        for (id, msg) in reader.messages(&MessageFilter::default()) {
+            let kind = msg.stream_kind.to_string();
+            *summary.messages.entry(kind.clone()).or_default() += 1;
+            if !matches!(msg.stream_kind, StreamKind::Select | StreamKind::Handshake) {
+                established.insert(msg.connection_id);
+            }
+            range = Some(match range {
+                None => (msg.timestamp, msg.timestamp),
+                Some((first, last)) => (first.min(msg.timestamp), last.max(msg.timestamp)),
+            });
+            if let Err(DbError::Decode(_)) = reader.decoded(id) {
+                *summary.decode_errors.entry(kind).or_default() += 1;
+            }
+        }
+        summary.handshake_failures = exchanged_bytes.difference(&established).count();
+        summary.duration = range
+            .and_then(|(first, last)| last.duration_since(first).ok())
+            .unwrap_or_default();
+
+        summary
+    }
+
+    pub fn handshake_failure_rate(&self) -> f64 {
+        if self.connections == 0 {
+            0.0
+        } else {
+            self.handshake_failures as f64 / self.connections as f64
+        }
+    }
+
+    /// Messages per second of the stream kind.
+    pub fn rate(&self, kind: &str) -> f64 {
+        let count = self.messages.get(kind).copied().unwrap_or_default();
+        let secs = self.duration.as_secs_f64();
+        if secs == 0.0 {
+            count as f64
+        } else {
+            count as f64 / secs
+        }
+    }
+}
+
+/// Prints two summaries side by side.
+pub struct CaptureDiff<'a> {
+    pub before: &'a CaptureSummary,
+    pub after: &'a CaptureSummary,
+}
+
+impl CaptureDiff<'_> {
+    pub fn peers_gone(&self) -> impl Iterator<Item = &IpAddr> {
+        self.before.peers.difference(&self.after.peers)
+    }
+
+    pub fn peers_new(&self) -> impl Iterator<Item = &IpAddr> {
+        self.after.peers.difference(&self.before.peers)
+    }
+}
+
+impl fmt::Display for CaptureDiff<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let CaptureDiff { before, after } = self;
+        let kinds = before
+            .messages
+            .keys()
+            .chain(after.messages.keys())
+            .collect::<BTreeSet<_>>();
+
+        writeln!(f, "{:<32}{:>16}{:>16}", "", "before", "after")?;
+        writeln!(
+            f,
+            "{:<32}{:>16}{:>16}",
+            "connections", before.connections, after.connections
+        )?;
+        writeln!(
+            f,
+            "{:<32}{:>16}{:>16}",
+            "peers",
+            before.peers.len(),
+            after.peers.len()
+        )?;
+        writeln!(
+            f,
+            "{:<32}{:>15.2}%{:>15.2}%",
+            "handshake failures",
+            before.handshake_failure_rate() * 100.0,
+            after.handshake_failure_rate() * 100.0
+        )?;
+        writeln!(
+            f,
+            "{:<32}{:>16}{:>16}",
+            "duration, seconds",
+            before.duration.as_secs(),
+            after.duration.as_secs()
+        )?;
+        for kind in &kinds {
+            writeln!(
+                f,
+                "{:<32}{:>16.3}{:>16.3}",
+                format!("{kind}, per second"),
+                before.rate(kind),
+                after.rate(kind)
+            )?;
+        }
+        for kind in &kinds {
+            let before = before.decode_errors.get(*kind).copied().unwrap_or_default();
+            let after = after.decode_errors.get(*kind).copied().unwrap_or_default();
+            if before != 0 || after != 0 {
+                writeln!(
+                    f,
+                    "{:<32}{:>16}{:>16}",
+                    format!("{kind}, decode errors"),
+                    before,
+                    after
+                )?;
+            }
+        }
+
+        let list = |peers: Vec<&IpAddr>| {
+            peers
+                .into_iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        writeln!(f, "peers gone: {}", list(self.peers_gone().collect()))?;
+        writeln!(f, "peers new: {}", list(self.peers_new().collect()))
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn compare_captures() {
+    use super::{DbFacade, StreamId, ConnectionStats};
+    use crate::event::ConnectionInfo;
+
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_675_166_400);
+    let capture = |peers: &[&str], failed: &str| {
+        let dir = temp_dir::TempDir::new().unwrap();
+        let db = DbFacade::open(dir.path()).unwrap();
+        for (fd, peer) in peers.iter().enumerate() {
+            let info = ConnectionInfo {
+                addr: peer.parse().unwrap(),
+                pid: 1,
+                fd: fd as u32,
+            };
+            let group = db.add(info, false, "node".to_owned(), time).unwrap();
+            let stats = ConnectionStats {
+                total_bytes: 100,
+                ..Default::default()
+            };
+            group.update(stats, true).unwrap();
+            let stream = group.get(StreamId::Forward(1));
+            stream
+                .add_at(true, time, StreamKind::Select, b"/coda/yamux/1.0.0\n")
+                .unwrap();
+            if *peer != failed {
+                let time = time + Duration::from_secs(10);
+                stream
+                    .add_at(
+                        true,
+                        time,
+                        StreamKind::Rpc,
+                        b"\x07\x00\x00\x00\x00\x00\x00\x00\x02\xfd\x52\x50\x43\x00\x01",
+                    )
+                    .unwrap();
+            }
+        }
+        drop(db);
+        let summary = CaptureSummary::collect(&CaptureReader::open(dir.path()).unwrap());
+        (dir, summary)
+    };
+
+    let (_a, before) = capture(&["1.2.3.4:8302", "1.2.3.5:8302"], "1.2.3.5:8302");
+    let (_b, after) = capture(&["1.2.3.4:8302", "1.2.3.6:8302"], "");
+    assert_eq!(before.handshake_failures, 1);
+    assert_eq!(after.handshake_failures, 0);
+    assert_eq!(before.duration, Duration::from_secs(10));
+    assert_eq!(after.rate("coda/rpcs/0.0.1"), 0.2);
+
+    let diff = CaptureDiff {
+        before: &before,
+        after: &after,
+    };
+    let gone = diff.peers_gone().collect::<Vec<_>>();
+    let new = diff.peers_new().collect::<Vec<_>>();
+    assert_eq!(gone, [&"1.2.3.5".parse::<IpAddr>().unwrap()]);
+    assert_eq!(new, [&"1.2.3.6".parse::<IpAddr>().unwrap()]);
+    assert!(diff.to_string().contains("handshake failures"));
+}
@@ -4,38 +4,81 @@ use std::{
     cmp::Ordering,
     sync::{Arc, Mutex},
     collections::{BTreeMap, HashSet, BTreeSet},
-    io,
+    io, env,
     convert::TryInto,
-    net::SocketAddr,
+    ops::Range,
+    net::{SocketAddr, IpAddr, Ipv4Addr},
 };
 
+use libp2p_core::PeerId;
 use mina_p2p_messages::gossip::GossipNetMessageV2;
 use radiation::{AbsorbExt, nom, ParseError, Emit};
 
-use serde::Serialize;
 use thiserror::Error;
 
 use super::{
     types::{
         Connection, ConnectionId, StreamFullId, Message, StreamKind, FullMessage, MessageId,
         Timestamp, StatsDbKey, StatsV2DbKey, CapnpEventWithMetadata, CapnpEventWithMetadataKey,
-        CapnpTableRow, CapnpEventDecoded,
+        CapnpTableRow, CapnpEventDecoded, IdentityHistory, IdentityAppearance, SharedIp,
+        SyscallErrorKey, SyscallErrorStat, Session, NodeLogLine, NodeStatus, LayerReport,
+        SubscriptionChange, Negotiation, NoiseHandshake, DecoderVersions, DecoderVersionStats,
+        RedecodeSummary, TimeBeacon, HealthSample, GossipFirstSeen, GossipDuplicate,
+        AgentVersionSighting, Verification, StreamEnd,
+    },
+    params::{
+        ValidParams, Coordinate, StreamFilter, Direction, KindFilter, ValidParamsConnection,
+        DecoderFilter,
     },
-    params::{ValidParams, Coordinate, StreamFilter, Direction, KindFilter, ValidParamsConnection},
     index::{
         ConnectionIdx, StreamIdx, StreamByKindIdx, MessageKindIdx, AddressIdx, LedgerHash,
-        LedgerHashIdx,
+        LedgerHashIdx, PeerIdIdx, AddrPeerIdIdx,
     },
     sorted_intersect::sorted_intersect,
+    tuning::{DbTuning, DbStatistics},
+    redaction::Redaction,
+    trigger::CaptureTriggers,
+    cache::HttpCache,
+    watchdog::Watchdog,
+    pipelines::Pipelines,
+    key_injection::KeyInjections,
+    freeze::{Freezes, Freeze},
+    peer_consistency::{ReportedPeers, PeerConsistency, WirePeer},
+    geoip::{GeoIp, GeoReport},
+    nat::NatReport,
+    framing::FramingReport,
+    agent_versions::AgentVersionReport,
+    ports::{PortReport, PortUse},
+    anomaly::{AnomalyDetector, Anomaly},
+    manifest::Manifest,
+    activity::ActivityReport,
+    peer_directory::PeerDirectory,
+    churn::{ChurnReport, ConnectionOutcome, HandshakeFailure, PartialHandshake},
+    duplication::{DuplicationHeatmap, Delivery},
+    validation::{ValidationReport, Publish},
+    baseline::{Baseline, BaselineDeviations},
+    leaderboard::Leaderboard,
+    kademlia::{KademliaReport, KadMessage, LearnedPeer},
+    subscriptions::{SubscriptionPeer, SubscriptionTimeline},
 };
 
 use crate::{
     decode::{
-        DecodeError, MessageType,
+        DecodeError, MessageType, DECODER_VERSION,
         meshsub_stats::{self, BlockStat, TxStat, Hash},
+        preview::PreviewLimits,
     },
     strace::StraceLine,
     meshsub::{SnarkByHash, Event, SnarkWithHash},
+    custom_coding,
+    kube::PodMeta,
+    tcp_info::TcpParams,
+    slo::{SloConfig, SloReport, Sample},
+    policy::ActivePolicy,
+    profile::{Profiles, ProtocolChecklist},
+    health::Health,
+    export::ExportJobs,
+    sink::Sinks,
     ChunkHeader,
 };
 
@@ -90,11 +133,39 @@ impl<'pa> From<nom::Err<ParseError<&'pa [u8]>>> for DbError {
 #[derive(Clone)]
 pub struct DbCore {
     cache: Arc<Mutex<BTreeMap<ConnectionId, u64>>>,
+    // keep options, they hold rocksdb statistics
+    opts: Arc<rocksdb::Options>,
+    tuning: Arc<DbTuning>,
+    manifest: Arc<Manifest>,
+    sinks: Arc<Sinks>,
+    triggers: Arc<CaptureTriggers>,
+    http_cache: Arc<HttpCache>,
+    watchdog: Arc<Watchdog>,
+    pipelines: Arc<Pipelines>,
+    reported_peers: Arc<ReportedPeers>,
+    key_injections: Arc<KeyInjections>,
+    freezes: Arc<Freezes>,
+    capture_policy: Arc<ActivePolicy>,
+    profiles: Arc<Profiles>,
+    health: Arc<Health>,
+    geoip: Arc<GeoIp>,
+    // the healthy capture to compare with, from `BASELINE`
+    baseline: Arc<Option<Baseline>>,
+    anomalies: Arc<AnomalyDetector>,
+    slo: Arc<SloConfig>,
+    peer_directory: Arc<PeerDirectory>,
+    export_jobs: Arc<ExportJobs>,
+    // the check of the first observation of the gossip and its store are atomic
+    gossip_lock: Arc<parking_lot::Mutex<()>>,
+    // store the Diffie-Hellman results of the noise handshakes
+    export_noise_secrets: bool,
+    // store the gossip data delivered again as the references to the first message
+    gossip_dedup: bool,
     inner: Arc<rocksdb::DB>,
 }
 
 impl DbCore {
-    const CFS: [&'static str; 15] = [
+    const CFS: [&'static str; 33] = [
         Self::CONNECTIONS,
         Self::MESSAGES,
         Self::RANDOMNESS,
@@ -104,12 +175,30 @@ impl DbCore {
         Self::CAPNP,
         Self::STATS_BLOCK_V2,
         Self::BLOBS,
+        Self::SYSCALL_ERRORS,
+        Self::SESSIONS,
+        Self::NODE_LOG,
+        Self::NODE_STATUS,
+        Self::SUBSCRIPTIONS,
+        Self::NEGOTIATIONS,
+        Self::NOISE_HANDSHAKES,
+        Self::ANOMALIES,
+        Self::TIME_BEACONS,
+        Self::HEALTH,
+        Self::GOSSIP_FIRST_SEEN,
+        Self::GOSSIP_DUPLICATES,
+        Self::AGENT_VERSIONS,
+        Self::VERIFICATIONS,
+        Self::STREAM_ENDS,
         Self::CONNECTION_ID_INDEX,
         Self::STREAM_ID_INDEX,
         Self::STREAM_KIND_INDEX,
         Self::MESSAGE_KIND_INDEX,
         Self::ADDR_INDEX,
         Self::LEDGER_HASH_INDEX,
+        Self::PEER_ID_INDEX,
+        Self::ADDR_PEER_ID_INDEX,
+        Self::GOSSIP_HASH_INDEX,
     ];
 
     const TTL: Duration = Duration::from_secs(0);
@@ -140,6 +229,38 @@ impl DbCore {
 
     const BLOBS: &'static str = "blobs";
 
+    const SYSCALL_ERRORS: &'static str = "syscall_errors";
+
+    const SESSIONS: &'static str = "sessions";
+
+    pub const SESSIONS_CNT: u8 = 4;
+
+    const NODE_LOG: &'static str = "node_log";
+
+    const NODE_STATUS: &'static str = "node_status";
+
+    const SUBSCRIPTIONS: &'static str = "subscriptions";
+
+    const NEGOTIATIONS: &'static str = "negotiations";
+
+    const NOISE_HANDSHAKES: &'static str = "noise_handshakes";
+
+    const ANOMALIES: &'static str = "anomalies";
+
+    const TIME_BEACONS: &'static str = "time_beacons";
+
+    const HEALTH: &'static str = "health";
+
+    const GOSSIP_FIRST_SEEN: &'static str = "gossip_first_seen";
+
+    const GOSSIP_DUPLICATES: &'static str = "gossip_duplicates";
+
+    const AGENT_VERSIONS: &'static str = "agent_versions";
+
+    const VERIFICATIONS: &'static str = "verifications";
+
+    const STREAM_ENDS: &'static str = "stream_ends";
+
     // indexes
 
     const CONNECTION_ID_INDEX: &'static str = "connection_id_index";
@@ -154,51 +275,296 @@ impl DbCore {
 
     const LEDGER_HASH_INDEX: &'static str = "ledger_hash_index";
 
+    const PEER_ID_INDEX: &'static str = "peer_id_index";
+
+    const ADDR_PEER_ID_INDEX: &'static str = "addr_peer_id_index";
+
+    const GOSSIP_HASH_INDEX: &'static str = "gossip_hash_index";
+
+    /// The peer id appeared from this number of ip addresses considered roaming.
+    const ROAMING_THRESHOLD: usize = 4;
+
     pub fn open<P>(path: P) -> Result<Self, DbError>
+    where
+        P: AsRef<Path>,
+    {
+        Self::open_with_tuning(path, DbTuning::from_env())
+    }
+
+    pub fn open_with_tuning<P>(path: P, tuning: DbTuning) -> Result<Self, DbError>
+    where
+        P: AsRef<Path>,
+    {
+        Self::open_inner(path, tuning, PeerDirectory::from_env(), false)
+    }
+
+    /// The capture copied from another host, nothing in `path` is changed.
+    /// Only the reads make sense, the writes fail.
+    pub fn open_read_only<P>(path: P) -> Result<Self, DbError>
+    where
+        P: AsRef<Path>,
+    {
+        Self::open_inner(path, DbTuning::default(), PeerDirectory::default(), true)
+    }
+
+    /// The database in memory for the data derived from the capture again and thrown away.
+    /// Nothing of the capture is shared, not even the peer directory, only the exports
+    /// would be in `path`.
+    pub fn open_scratch<P>(path: P) -> Result<Self, DbError>
+    where
+        P: AsRef<Path>,
+    {
+        let tuning = DbTuning {
+            in_memory: Some(u64::MAX),
+            ..DbTuning::default()
+        };
+        Self::open_inner(path, tuning, PeerDirectory::default(), false)
+    }
+
+    fn open_inner<P>(
+        path: P,
+        tuning: DbTuning,
+        peer_directory: PeerDirectory,
+        read_only: bool,
+    ) -> Result<Self, DbError>
     where
         P: AsRef<Path>,
     {
         let path = PathBuf::from(path.as_ref());
 
         let mut opts = rocksdb::Options::default();
-        opts.create_if_missing(true);
-        opts.create_missing_column_families(true);
+        opts.create_if_missing(!read_only);
+        opts.create_missing_column_families(!read_only);
+        tuning.apply(&mut opts);
+
+        // the path only names the database, nothing is on the disk
+        let manifest = if tuning.in_memory.is_some() {
+            opts.set_env(&rocksdb::Env::mem_env()?);
+            Manifest::new(Redaction::from_env())
+        } else if read_only {
+            Manifest::load(&path, Redaction::from_env()).map_err(DbError::CreateDirError)?
+        } else {
+            Manifest::load_or_create(&path, Redaction::from_env())
+                .map_err(DbError::CreateDirError)?
+        };
 
-        let opts_with_prefix_extractor = |prefix_len| {
+        let cache = tuning.block_cache();
+        let default_opts = || {
             let mut opts = rocksdb::Options::default();
+            tuning.apply_cf(&mut opts, cache.as_ref());
+            opts
+        };
+        let opts_with_prefix_extractor = |prefix_len| {
+            let mut opts = default_opts();
             opts.set_prefix_extractor(rocksdb::SliceTransform::create_fixed_prefix(prefix_len));
             opts
         };
         let cfs = [
-            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[0], Default::default()),
-            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[1], Default::default()),
-            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[2], Default::default()),
-            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[3], Default::default()),
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[0], default_opts()),
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[1], default_opts()),
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[2], default_opts()),
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[3], default_opts()),
             // STATS
             rocksdb::ColumnFamilyDescriptor::new(Self::CFS[4], opts_with_prefix_extractor(4)),
-            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[5], Default::default()),
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[5], default_opts()),
             // CAPNP
-            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[6], Default::default()),
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[6], default_opts()),
             rocksdb::ColumnFamilyDescriptor::new(Self::CFS[7], opts_with_prefix_extractor(4)),
             // BLOBS
-            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[8], Default::default()),
-            // INDEXES
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[8], default_opts()),
+            // SYSCALL ERRORS
             rocksdb::ColumnFamilyDescriptor::new(Self::CFS[9], opts_with_prefix_extractor(8)),
-            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[10], opts_with_prefix_extractor(16)),
-            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[11], opts_with_prefix_extractor(2)),
-            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[12], opts_with_prefix_extractor(2)),
-            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[13], opts_with_prefix_extractor(18)),
-            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[14], opts_with_prefix_extractor(32)),
+            // SESSIONS
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[10], default_opts()),
+            // NODE LOG
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[11], default_opts()),
+            // NODE STATUS
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[12], default_opts()),
+            // SUBSCRIPTIONS
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[13], default_opts()),
+            // NEGOTIATIONS
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[14], opts_with_prefix_extractor(8)),
+            // NOISE HANDSHAKES
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[15], default_opts()),
+            // ANOMALIES
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[16], default_opts()),
+            // TIME BEACONS
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[17], default_opts()),
+            // HEALTH
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[18], default_opts()),
+            // GOSSIP FIRST SEEN
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[19], default_opts()),
+            // GOSSIP DUPLICATES
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[20], default_opts()),
+            // AGENT VERSIONS
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[21], default_opts()),
+            // VERIFICATIONS
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[22], default_opts()),
+            // STREAM ENDS
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[23], opts_with_prefix_extractor(8)),
+            // INDEXES
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[24], opts_with_prefix_extractor(8)),
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[25], opts_with_prefix_extractor(16)),
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[26], opts_with_prefix_extractor(2)),
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[27], opts_with_prefix_extractor(2)),
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[28], opts_with_prefix_extractor(18)),
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[29], opts_with_prefix_extractor(32)),
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[30], default_opts()),
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[31], opts_with_prefix_extractor(16)),
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[32], default_opts()),
         ];
-        let inner =
-            rocksdb::DB::open_cf_descriptors_with_ttl(&opts, path.join("rocksdb"), cfs, Self::TTL)?;
+        let inner = if read_only {
+            rocksdb::DB::open_cf_descriptors_read_only(&opts, path.join("rocksdb"), cfs, false)?
+        } else {
+            rocksdb::DB::open_cf_descriptors_with_ttl(&opts, path.join("rocksdb"), cfs, Self::TTL)?
+        };
 
         Ok(DbCore {
             cache: Arc::new(Mutex::new(BTreeMap::default())),
+            opts: Arc::new(opts),
+            tuning: Arc::new(tuning),
+            manifest: Arc::new(manifest),
+            sinks: Arc::new(Sinks::default()),
+            triggers: Arc::new(CaptureTriggers::from_env()),
+            http_cache: Arc::new(HttpCache::from_env()),
+            watchdog: Arc::new(Watchdog::from_env()),
+            pipelines: Arc::new(Pipelines::default()),
+            reported_peers: Arc::new(ReportedPeers::default()),
+            key_injections: Arc::new(KeyInjections::default()),
+            freezes: Arc::new(Freezes::default()),
+            capture_policy: Arc::new(ActivePolicy::default()),
+            profiles: Arc::new(Profiles::from_env()),
+            health: Arc::new(Health::default()),
+            geoip: Arc::new(GeoIp::from_env()),
+            baseline: Arc::new(Baseline::from_env()),
+            anomalies: Arc::new(AnomalyDetector::from_env()),
+            slo: Arc::new(SloConfig::from_env()),
+            peer_directory: Arc::new(peer_directory),
+            export_jobs: Arc::new(if read_only {
+                ExportJobs::load(path.join("exports"))
+            } else {
+                ExportJobs::open(path.join("exports"))
+            }),
+            gossip_lock: Arc::new(parking_lot::Mutex::new(())),
+            export_noise_secrets: env::var("NOISE_EXPORT_SECRETS").as_deref() == Ok("1"),
+            gossip_dedup: env::var("GOSSIP_DEDUP").as_deref() == Ok("1"),
             inner: Arc::new(inner),
         })
     }
 
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    /// Outputs of the recorder besides this database.
+    pub fn sinks(&self) -> &Sinks {
+        &self.sinks
+    }
+
+    /// Rules to store the payloads in full when the capture is redacted.
+    pub fn triggers(&self) -> &CaptureTriggers {
+        &self.triggers
+    }
+
+    /// Connections whose decoders stalled.
+    pub fn watchdog(&self) -> &Watchdog {
+        &self.watchdog
+    }
+
+    /// The decoders of the open connections.
+    pub fn pipelines(&self) -> &Pipelines {
+        &self.pipelines
+    }
+
+    /// The peers the node claims via graphql.
+    pub fn reported_peers(&self) -> &ReportedPeers {
+        &self.reported_peers
+    }
+
+    /// The keys injected to decrypt the connections again.
+    pub fn key_injections(&self) -> &KeyInjections {
+        &self.key_injections
+    }
+
+    /// Databases to look up the location of the peer addresses.
+    pub fn freezes(&self) -> &Freezes {
+        &self.freezes
+    }
+
+    /// Freeze the view, the queries with the freeze see only what is stored so far.
+    pub fn freeze(&self, time: SystemTime) -> Freeze {
+        let end = |cf| {
+            self.inner
+                .iterator_cf(cf, rocksdb::IteratorMode::End)
+                .next()
+                .and_then(Self::decode_index::<u64>)
+                .map_or(0, |id| id + 1)
+        };
+        let connections = end(self.connections());
+        let messages = end(self.messages());
+        self.freezes.freeze(time, connections, messages)
+    }
+
+    /// Sampling, port filter and retention, the aggregator sets it.
+    pub fn capture_policy(&self) -> &ActivePolicy {
+        &self.capture_policy
+    }
+
+    /// The profiles of the networks, the connection gets one by its chain id.
+    pub fn profiles(&self) -> &Profiles {
+        &self.profiles
+    }
+
+    pub fn health(&self) -> &Health {
+        &self.health
+    }
+
+    /// The exports running in the background, `export::spawn` runs them.
+    pub fn export_jobs(&self) -> &ExportJobs {
+        &self.export_jobs
+    }
+
+    pub fn gossip_dedup(&self) -> bool {
+        self.gossip_dedup
+    }
+
+    pub fn geoip(&self) -> &GeoIp {
+        &self.geoip
+    }
+
+    pub fn baseline(&self) -> Option<&Baseline> {
+        self.baseline.as_ref().as_ref()
+    }
+
+    /// Detects jumps of the message rate of the peers and the topics.
+    pub fn anomaly_detector(&self) -> &AnomalyDetector {
+        &self.anomalies
+    }
+
+    /// Block propagation objectives.
+    pub fn slo(&self) -> &SloConfig {
+        &self.slo
+    }
+
+    /// The peers known from the previous captures too.
+    pub fn peer_directory(&self) -> &PeerDirectory {
+        &self.peer_directory
+    }
+
+    /// Decoded messages and aggregations the server returned recently.
+    pub fn http_cache(&self) -> &HttpCache {
+        &self.http_cache
+    }
+
+    pub fn tuning(&self) -> &DbTuning {
+        &self.tuning
+    }
+
+    pub fn fetch_db_statistics(&self) -> DbStatistics {
+        let s = self.opts.get_statistics().unwrap_or_default();
+        DbStatistics::parse(&s, (*self.tuning).clone())
+    }
+
     fn connections(&self) -> &rocksdb::ColumnFamily {
         self.inner.cf_handle(Self::CONNECTIONS).expect("must exist")
     }
@@ -239,6 +605,90 @@ impl DbCore {
         self.inner.cf_handle(Self::BLOBS).expect("must exist")
     }
 
+    fn syscall_errors(&self) -> &rocksdb::ColumnFamily {
+        self.inner
+            .cf_handle(Self::SYSCALL_ERRORS)
+            .expect("must exist")
+    }
+
+    fn sessions(&self) -> &rocksdb::ColumnFamily {
+        self.inner.cf_handle(Self::SESSIONS).expect("must exist")
+    }
+
+    fn node_log(&self) -> &rocksdb::ColumnFamily {
+        self.inner.cf_handle(Self::NODE_LOG).expect("must exist")
+    }
+
+    fn node_status(&self) -> &rocksdb::ColumnFamily {
+        self.inner.cf_handle(Self::NODE_STATUS).expect("must exist")
+    }
+
+    fn subscriptions(&self) -> &rocksdb::ColumnFamily {
+        self.inner
+            .cf_handle(Self::SUBSCRIPTIONS)
+            .expect("must exist")
+    }
+
+    fn negotiations(&self) -> &rocksdb::ColumnFamily {
+        self.inner
+            .cf_handle(Self::NEGOTIATIONS)
+            .expect("must exist")
+    }
+
+    fn noise_handshakes(&self) -> &rocksdb::ColumnFamily {
+        self.inner
+            .cf_handle(Self::NOISE_HANDSHAKES)
+            .expect("must exist")
+    }
+
+    fn anomalies(&self) -> &rocksdb::ColumnFamily {
+        self.inner.cf_handle(Self::ANOMALIES).expect("must exist")
+    }
+
+    fn time_beacons(&self) -> &rocksdb::ColumnFamily {
+        self.inner
+            .cf_handle(Self::TIME_BEACONS)
+            .expect("must exist")
+    }
+
+    fn health_samples(&self) -> &rocksdb::ColumnFamily {
+        self.inner.cf_handle(Self::HEALTH).expect("must exist")
+    }
+
+    fn gossip_first_seen(&self) -> &rocksdb::ColumnFamily {
+        self.inner
+            .cf_handle(Self::GOSSIP_FIRST_SEEN)
+            .expect("must exist")
+    }
+
+    fn gossip_duplicates(&self) -> &rocksdb::ColumnFamily {
+        self.inner
+            .cf_handle(Self::GOSSIP_DUPLICATES)
+            .expect("must exist")
+    }
+
+    fn agent_versions(&self) -> &rocksdb::ColumnFamily {
+        self.inner
+            .cf_handle(Self::AGENT_VERSIONS)
+            .expect("must exist")
+    }
+
+    fn verifications(&self) -> &rocksdb::ColumnFamily {
+        self.inner
+            .cf_handle(Self::VERIFICATIONS)
+            .expect("must exist")
+    }
+
+    fn stream_ends(&self) -> &rocksdb::ColumnFamily {
+        self.inner.cf_handle(Self::STREAM_ENDS).expect("must exist")
+    }
+
+    fn gossip_hash_index(&self) -> &rocksdb::ColumnFamily {
+        self.inner
+            .cf_handle(Self::GOSSIP_HASH_INDEX)
+            .expect("must exist")
+    }
+
     fn connection_id_index(&self) -> &rocksdb::ColumnFamily {
         self.inner
             .cf_handle(Self::CONNECTION_ID_INDEX)
@@ -273,13 +723,83 @@ impl DbCore {
             .expect("must exist")
     }
 
+    fn peer_id_index(&self) -> &rocksdb::ColumnFamily {
+        self.inner
+            .cf_handle(Self::PEER_ID_INDEX)
+            .expect("must exist")
+    }
+
+    fn addr_peer_id_index(&self) -> &rocksdb::ColumnFamily {
+        self.inner
+            .cf_handle(Self::ADDR_PEER_ID_INDEX)
+            .expect("must exist")
+    }
+
     pub fn put_cn(&self, id: ConnectionId, v: Connection) -> Result<(), DbError> {
         self.inner
             .put_cf(self.connections(), id.chain(vec![]), v.chain(vec![]))?;
+        self.http_cache.invalidate();
 
         Ok(())
     }
 
+    /// The name of the remote address, from the hosts mapping or the reverse lookup.
+    pub fn set_peer_name(&self, id: ConnectionId, name: String) -> Result<(), DbError> {
+        if !self.sinks.database() {
+            return Ok(());
+        }
+        let mut cn = self.fetch_connection(id.0)?;
+        cn.peer_name = name;
+        self.put_cn(id, cn)
+    }
+
+    /// The local address of the socket, read from `/proc` when the connection is recorded.
+    pub fn set_local_addr(&self, id: ConnectionId, addr: SocketAddr) -> Result<(), DbError> {
+        if !self.sinks.database() {
+            return Ok(());
+        }
+        let mut cn = self.fetch_connection(id.0)?;
+        cn.local_addr = addr.to_string();
+        self.put_cn(id, cn)
+    }
+
+    pub fn set_tcp_params(&self, id: ConnectionId, params: TcpParams) -> Result<(), DbError> {
+        if !self.sinks.database() {
+            return Ok(());
+        }
+        let mut cn = self.fetch_connection(id.0)?;
+        cn.tcp = params;
+        self.put_cn(id, cn)
+    }
+
+    /// The address of the node the peer observes, it tells it by identify.
+    pub fn set_observed_addr(&self, id: ConnectionId, addr: String) -> Result<(), DbError> {
+        if !self.sinks.database() {
+            return Ok(());
+        }
+        let mut cn = self.fetch_connection(id.0)?;
+        if cn.observed_addr == addr {
+            return Ok(());
+        }
+        cn.observed_addr = addr;
+        self.put_cn(id, cn)
+    }
+
+    pub fn set_pods(
+        &self,
+        id: ConnectionId,
+        local: PodMeta,
+        remote: PodMeta,
+    ) -> Result<(), DbError> {
+        if !self.sinks.database() {
+            return Ok(());
+        }
+        let mut cn = self.fetch_connection(id.0)?;
+        cn.local_pod = local;
+        cn.remote_pod = remote;
+        self.put_cn(id, cn)
+    }
+
     pub fn put_message(
         &self,
         addr: &SocketAddr,
@@ -315,10 +835,6 @@ impl DbCore {
         self.inner
             .put_cf(self.stream_kind_index(), index.chain(vec![]), vec![])?;
         for ty in tys {
-            if matches!(&ty, &MessageType::HandshakePayload) {
-                // peer id index
-            }
-
             let index = MessageKindIdx { ty, id };
             self.inner
                 .put_cf(self.message_kind_index(), index.chain(vec![]), vec![])?;
@@ -338,6 +854,258 @@ impl DbCore {
             self.inner
                 .put_cf(self.ledger_hash_index(), index.chain(vec![]), vec![])?;
         }
+        self.http_cache.invalidate();
+        Ok(())
+    }
+
+    pub fn put_session(&self, id: u64, v: Session) -> Result<(), DbError> {
+        self.inner
+            .put_cf(self.sessions(), id.to_be_bytes(), v.chain(vec![]))?;
+
+        Ok(())
+    }
+
+    /// The key is the timestamp followed by the hash of the line,
+    /// so storing the same line again does nothing.
+    pub fn put_node_log(&self, v: &NodeLogLine) -> Result<(), DbError> {
+        use blake2::digest::{Update, FixedOutput, typenum};
+
+        let value = v.clone().chain(vec![]);
+        let hash = blake2::Blake2b::<typenum::U8>::default()
+            .chain(&value)
+            .finalize_fixed();
+        let mut key = vec![];
+        custom_coding::time_emit(&v.timestamp, &mut key);
+        key.extend_from_slice(&hash);
+        self.inner.put_cf(self.node_log(), key, value)?;
+
+        Ok(())
+    }
+
+    pub fn put_node_status(&self, v: &NodeStatus) -> Result<(), DbError> {
+        let mut key = vec![];
+        custom_coding::time_emit(&v.timestamp, &mut key);
+        self.inner
+            .put_cf(self.node_status(), key, v.clone().chain(vec![]))?;
+
+        Ok(())
+    }
+
+    /// The key is the timestamp followed by the message id and the topic,
+    /// the message may announce several topics.
+    pub fn put_subscription(&self, v: &SubscriptionChange) -> Result<(), DbError> {
+        let mut key = vec![];
+        custom_coding::time_emit(&v.timestamp, &mut key);
+        key.extend_from_slice(&v.message_id.0.to_be_bytes());
+        key.extend_from_slice(v.topic.as_bytes());
+        self.inner
+            .put_cf(self.subscriptions(), key, v.clone().chain(vec![]))?;
+        self.http_cache.invalidate();
+
+        Ok(())
+    }
+
+    /// Stores the observation only if the `data` was never observed before, the hash index
+    /// maps the hash of the data to the key of its first observation.
+    /// Returns whether the observation is the first one.
+    pub fn put_gossip_first_seen(
+        &self,
+        mut v: GossipFirstSeen,
+        data: &[u8],
+    ) -> Result<bool, DbError> {
+        use blake2::digest::{Update, FixedOutput, typenum};
+
+        let hash = blake2::Blake2b::<typenum::U16>::default()
+            .chain(data)
+            .finalize_fixed();
+
+        let _guard = self.gossip_lock.lock();
+        if self.inner.get_cf(self.gossip_hash_index(), hash)?.is_some() {
+            return Ok(false);
+        }
+        v.hash = hex::encode(hash);
+        let mut key = vec![];
+        custom_coding::time_emit(&v.timestamp, &mut key);
+        key.extend_from_slice(&v.message_id.0.to_be_bytes());
+        key.extend_from_slice(&hash);
+        self.inner
+            .put_cf(self.gossip_first_seen(), &key, v.chain(vec![]))?;
+        self.inner.put_cf(self.gossip_hash_index(), hash, key)?;
+
+        Ok(true)
+    }
+
+    /// The first observation of the `data`, `None` if the data was never observed.
+    pub fn fetch_gossip_first_seen_of(
+        &self,
+        data: &[u8],
+    ) -> Result<Option<GossipFirstSeen>, DbError> {
+        use blake2::digest::{Update, FixedOutput, typenum};
+
+        let hash = blake2::Blake2b::<typenum::U16>::default()
+            .chain(data)
+            .finalize_fixed();
+        match self.inner.get_cf(self.gossip_hash_index(), hash)? {
+            Some(key) => self.get(self.gossip_first_seen(), key).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// The key is the timestamp followed by the connection id and the hash of the data.
+    pub fn put_gossip_duplicate(&self, v: &GossipDuplicate) -> Result<(), DbError> {
+        let mut key = vec![];
+        custom_coding::time_emit(&v.timestamp, &mut key);
+        key.extend_from_slice(&v.connection_id.0.to_be_bytes());
+        key.extend_from_slice(v.hash.as_bytes());
+        self.inner
+            .put_cf(self.gossip_duplicates(), key, v.clone().chain(vec![]))?;
+
+        Ok(())
+    }
+
+    pub fn put_agent_version(&self, v: &AgentVersionSighting) -> Result<(), DbError> {
+        let mut key = vec![];
+        custom_coding::time_emit(&v.timestamp, &mut key);
+        key.extend_from_slice(&v.connection_id.0.to_be_bytes());
+        self.inner
+            .put_cf(self.agent_versions(), key, v.clone().chain(vec![]))?;
+
+        Ok(())
+    }
+
+    pub fn put_verification(&self, v: &Verification) -> Result<(), DbError> {
+        let mut key = vec![];
+        custom_coding::time_emit(&v.timestamp, &mut key);
+        key.extend_from_slice(&v.connection_id.0.to_be_bytes());
+        self.inner
+            .put_cf(self.verifications(), key, v.clone().chain(vec![]))?;
+
+        Ok(())
+    }
+
+    /// Both sides may end the stream, and more than once if the stream id is reused.
+    pub fn put_stream_end(&self, v: &StreamEnd) -> Result<(), DbError> {
+        let mut key = StreamFullId {
+            cn: v.connection_id,
+            id: v.stream_id,
+        }
+        .chain(vec![]);
+        custom_coding::time_emit(&v.timestamp, &mut key);
+        key.push(v.incoming as u8);
+        self.inner
+            .put_cf(self.stream_ends(), key, v.clone().chain(vec![]))?;
+
+        Ok(())
+    }
+
+    /// Overwrites the previous transcript of the stream.
+    pub fn put_negotiation(&self, v: &Negotiation) -> Result<(), DbError> {
+        let key = StreamFullId {
+            cn: v.connection_id,
+            id: v.stream_id,
+        }
+        .chain(vec![]);
+        self.inner
+            .put_cf(self.negotiations(), key, v.clone().chain(vec![]))?;
+
+        Ok(())
+    }
+
+    /// Overwrites the previous state of the handshake of the connection.
+    pub fn put_noise_handshake(&self, v: &NoiseHandshake) -> Result<(), DbError> {
+        let mut v = v.clone();
+        if !self.export_noise_secrets {
+            v.secrets.clear();
+        }
+        self.inner.put_cf(
+            self.noise_handshakes(),
+            v.connection_id.chain(vec![]),
+            v.chain(vec![]),
+        )?;
+
+        Ok(())
+    }
+
+    /// The key is the beginning of the window followed by the subject.
+    pub fn put_anomaly(&self, v: &Anomaly) -> Result<(), DbError> {
+        let mut key = vec![];
+        custom_coding::time_emit(&v.timestamp, &mut key);
+        key.extend_from_slice(v.subject.as_bytes());
+        key.extend_from_slice(v.key.as_bytes());
+        self.inner
+            .put_cf(self.anomalies(), key, v.clone().chain(vec![]))?;
+
+        Ok(())
+    }
+
+    /// The key is the time the echo arrived followed by the sequence number and the peer.
+    pub fn put_time_beacon(&self, v: &TimeBeacon) -> Result<(), DbError> {
+        let mut key = vec![];
+        custom_coding::time_emit(&v.received, &mut key);
+        key.extend_from_slice(&v.seq.to_be_bytes());
+        key.extend_from_slice(v.peer.as_bytes());
+        self.inner
+            .put_cf(self.time_beacons(), key, v.clone().chain(vec![]))?;
+
+        Ok(())
+    }
+
+    pub fn put_health(&self, v: &HealthSample) -> Result<(), DbError> {
+        let mut key = vec![];
+        custom_coding::time_emit(&v.until, &mut key);
+        self.inner
+            .put_cf(self.health_samples(), key, v.clone().chain(vec![]))?;
+
+        Ok(())
+    }
+
+    /// Returns how many times the error happened in the connection.
+    pub fn add_syscall_error(
+        &self,
+        connection_id: ConnectionId,
+        syscall: String,
+        errno: u32,
+    ) -> Result<u64, DbError> {
+        let key = SyscallErrorKey {
+            connection_id,
+            syscall,
+            errno,
+        }
+        .chain(vec![]);
+        let count = match self.inner.get_cf(self.syscall_errors(), &key)? {
+            Some(v) => u64::absorb_ext(&v)? + 1,
+            None => 1,
+        };
+        self.inner
+            .put_cf(self.syscall_errors(), key, count.chain(vec![]))?;
+
+        Ok(count)
+    }
+
+    pub fn put_peer_identity(
+        &self,
+        peer_id: PeerId,
+        addr: SocketAddr,
+        connection_id: ConnectionId,
+        timestamp: SystemTime,
+    ) -> Result<(), DbError> {
+        let index = PeerIdIdx {
+            peer_id,
+            timestamp,
+            connection_id,
+            addr,
+        };
+        self.inner
+            .put_cf(self.peer_id_index(), index.chain(vec![]), vec![])?;
+        let index = AddrPeerIdIdx {
+            addr,
+            timestamp,
+            connection_id,
+            peer_id,
+        };
+        self.inner
+            .put_cf(self.addr_peer_id_index(), index.chain(vec![]), vec![])?;
+
         Ok(())
     }
 
@@ -366,6 +1134,7 @@ impl DbCore {
         };
 
         self.inner.put_cf(self.stats(), key.chain(vec![]), bytes)?;
+        self.http_cache.invalidate();
 
         Ok(())
     }
@@ -381,6 +1150,7 @@ impl DbCore {
             key.chain(vec![]),
             event.chain(vec![]),
         )?;
+        self.http_cache.invalidate();
 
         Ok(())
     }
@@ -388,6 +1158,7 @@ impl DbCore {
     pub fn put_stats_tx(&self, height: u32, bytes: Vec<u8>) -> Result<(), DbError> {
         self.inner
             .put_cf(self.stats_tx(), height.to_be_bytes(), bytes)?;
+        self.http_cache.invalidate();
 
         Ok(())
     }
@@ -442,7 +1213,50 @@ impl DbCore {
         Ok(data[ChunkHeader::SIZE..].to_vec())
     }
 
+    /// The redaction applied to the message when it was stored.
+    fn stored_redaction(&self, msg: &Message) -> Redaction {
+        if msg.unredacted {
+            Redaction::None
+        } else {
+            self.manifest.capture_redaction
+        }
+    }
+
+    /// Redact the payload for export, does nothing if it is already redacted at capture time.
+    pub(crate) fn fetch_blob_redacted(
+        &self,
+        msg: &Message,
+        redaction: Redaction,
+    ) -> Result<Vec<u8>, DbError> {
+        let buf = self.fetch_blob(msg.connection_id, msg.offset)?;
+        if redaction.covers(msg.stream_kind) && !self.stored_redaction(msg).covers(msg.stream_kind)
+        {
+            Ok(redaction.apply(&buf))
+        } else {
+            Ok(buf)
+        }
+    }
+
     #[allow(clippy::type_complexity)]
+    fn decode_value<T>(item: Result<(Box<[u8]>, Box<[u8]>), rocksdb::Error>) -> Option<T>
+    where
+        T: for<'pa> AbsorbExt<'pa>,
+    {
+        match item {
+            Ok((_, value)) => match T::absorb_ext(&value) {
+                Ok(v) => Some(v),
+                Err(err) => {
+                    log::error!("{err}");
+                    None
+                }
+            },
+            Err(err) => {
+                log::error!("{err}");
+                None
+            }
+        }
+    }
+
     fn decode<K, T>(item: Result<(Box<[u8]>, Box<[u8]>), rocksdb::Error>) -> Option<(K, T)>
     where
         K: for<'pa> AbsorbExt<'pa> + std::fmt::Display,
@@ -467,7 +1281,6 @@ impl DbCore {
         }
     }
 
-    #[allow(dead_code)]
     #[allow(clippy::type_complexity)]
     fn decode_index<T>(item: Result<(Box<[u8]>, Box<[u8]>), rocksdb::Error>) -> Option<T>
     where
@@ -517,10 +1330,22 @@ impl DbCore {
         let mut pos = total / 2;
         let mut r = pos;
         while r > 0 {
-            let v = self.get::<T, _>(cf, pos.to_be_bytes())?;
+            // the retention deletes the records, take the first one kept at or after the position
+            let key = pos.to_be_bytes();
+            let mode = rocksdb::IteratorMode::From(&key, rocksdb::Direction::Forward);
+            let v = self
+                .inner
+                .iterator_cf(cf, mode)
+                .next()
+                .map(Self::decode_value::<T>);
 
             r /= 2;
-            match v.timestamp().cmp(&timestamp) {
+            let ord = match v {
+                Some(Some(v)) => v.timestamp().cmp(&timestamp),
+                Some(None) => return Err(DbError::NoItemAtCursor(hex::encode(key))),
+                None => Ordering::Greater,
+            };
+            match ord {
                 Ordering::Less => pos += r,
                 Ordering::Equal => r = 0,
                 Ordering::Greater => pos -= r,
@@ -529,19 +1354,454 @@ impl DbCore {
         Ok(pos)
     }
 
-    pub fn total<const K: u8>(&self) -> Result<u64, DbError> {
-        match self.inner.get([K])? {
-            None => Ok(0),
-            Some(b) => Ok(u64::absorb_ext(&b)?),
-        }
+    pub fn total<const K: u8>(&self) -> Result<u64, DbError> {
+        match self.inner.get([K])? {
+            None => Ok(0),
+            Some(b) => Ok(u64::absorb_ext(&b)?),
+        }
+    }
+
+    pub fn set_total<const K: u8>(&self, v: u64) -> Result<(), DbError> {
+        Ok(self.inner.put([K], v.chain(vec![]))?)
+    }
+
+    pub fn fetch_connection(&self, id: u64) -> Result<Connection, DbError> {
+        self.get(self.connections(), id.to_be_bytes())
+    }
+
+    /// Stored chunks of the connection with their offsets, the raw ones and the messages,
+    /// in the order they were observed.
+    pub fn fetch_chunks(
+        &self,
+        cn: ConnectionId,
+    ) -> impl Iterator<Item = (u64, ChunkHeader, Vec<u8>)> + '_ {
+        use rocksdb::{IteratorMode, Direction};
+
+        let key = (cn, 0u64).chain(vec![]);
+        self.inner
+            .iterator_cf(self.blobs(), IteratorMode::From(&key, Direction::Forward))
+            .map_while(move |item| {
+                let (key, value) = match item {
+                    Ok(v) => v,
+                    Err(err) => {
+                        log::error!("{err}");
+                        return None;
+                    }
+                };
+                let (cn_key, offset) = <(ConnectionId, u64)>::absorb_ext(&key).ok()?;
+                if cn_key != cn {
+                    return None;
+                }
+                let header = ChunkHeader::absorb_ext(value.get(..ChunkHeader::SIZE)?).ok()?;
+                Some((offset, header, value[ChunkHeader::SIZE..].to_vec()))
+            })
+    }
+
+    /// Messages of the connection, ordered by id.
+    pub fn fetch_connection_messages(
+        &self,
+        cn: ConnectionId,
+    ) -> impl Iterator<Item = (u64, Message)> + '_ {
+        use rocksdb::{IteratorMode, Direction};
+
+        let key = ConnectionIdx {
+            connection_id: cn,
+            id: MessageId(0),
+        }
+        .chain(vec![]);
+        self.inner
+            .iterator_cf(
+                self.connection_id_index(),
+                IteratorMode::From(&key, Direction::Forward),
+            )
+            .filter_map(Self::decode_index::<ConnectionIdx>)
+            .take_while(move |index| index.connection_id == cn)
+            .filter_map(|ConnectionIdx { id, .. }| Some((id.0, self.fetch_message(id.0).ok()?)))
+    }
+
+    /// The peer identified by the noise handshake of the connection.
+    pub fn fetch_peer_identity(
+        &self,
+        cn: ConnectionId,
+        addr: SocketAddr,
+    ) -> Option<(PeerId, SystemTime)> {
+        use rocksdb::{IteratorMode, Direction};
+
+        let mut key = vec![];
+        custom_coding::addr_emit(&addr, &mut key);
+        custom_coding::time_emit(&SystemTime::UNIX_EPOCH, &mut key);
+        self.inner
+            .iterator_cf(
+                self.addr_peer_id_index(),
+                IteratorMode::From(&key, Direction::Forward),
+            )
+            .filter_map(Self::decode_index::<AddrPeerIdIdx>)
+            .take_while(|index| index.addr == addr)
+            .find(|index| index.connection_id == cn)
+            .map(|index| (index.peer_id, index.timestamp))
+    }
+
+    pub fn fetch_message(&self, id: u64) -> Result<Message, DbError> {
+        self.get(self.messages(), id.to_be_bytes())
+    }
+
+    pub fn fetch_session(&self, id: u64) -> Result<Session, DbError> {
+        self.get(self.sessions(), id.to_be_bytes())
+    }
+
+    pub fn fetch_sessions(&self) -> impl Iterator<Item = (u64, Session)> + '_ {
+        self.inner
+            .iterator_cf(self.sessions(), rocksdb::IteratorMode::Start)
+            .filter_map(Self::decode)
+    }
+
+    /// Node log lines starting from `from`, ordered by time.
+    pub fn fetch_node_log(&self, from: SystemTime) -> impl Iterator<Item = NodeLogLine> + '_ {
+        use rocksdb::{IteratorMode, Direction};
+
+        let mut key = vec![];
+        custom_coding::time_emit(&from, &mut key);
+        self.inner
+            .iterator_cf(
+                self.node_log(),
+                IteratorMode::From(&key, Direction::Forward),
+            )
+            .filter_map(Self::decode_value)
+    }
+
+    /// Anomalies starting from `from`, ordered by time.
+    pub fn fetch_anomalies(&self, from: SystemTime) -> impl Iterator<Item = Anomaly> + '_ {
+        use rocksdb::{IteratorMode, Direction};
+
+        let mut key = vec![];
+        custom_coding::time_emit(&from, &mut key);
+        self.inner
+            .iterator_cf(
+                self.anomalies(),
+                IteratorMode::From(&key, Direction::Forward),
+            )
+            .filter_map(Self::decode_value)
+    }
+
+    /// Time beacons whose echo arrived starting from `from`, ordered by time.
+    pub fn fetch_time_beacons(&self, from: SystemTime) -> impl Iterator<Item = TimeBeacon> + '_ {
+        use rocksdb::{IteratorMode, Direction};
+
+        let mut key = vec![];
+        custom_coding::time_emit(&from, &mut key);
+        self.inner
+            .iterator_cf(
+                self.time_beacons(),
+                IteratorMode::From(&key, Direction::Forward),
+            )
+            .filter_map(Self::decode_value)
+    }
+
+    /// The health samples of the intervals ending from `from` to `to`, ordered by time.
+    pub fn fetch_health(
+        &self,
+        from: SystemTime,
+        to: SystemTime,
+    ) -> impl Iterator<Item = HealthSample> + '_ {
+        use rocksdb::{IteratorMode, Direction};
+
+        let mut key = vec![];
+        custom_coding::time_emit(&from, &mut key);
+        self.inner
+            .iterator_cf(
+                self.health_samples(),
+                IteratorMode::From(&key, Direction::Forward),
+            )
+            .filter_map(Self::decode_value::<HealthSample>)
+            .take_while(move |v| v.until <= to)
+    }
+
+    /// Node status snapshots starting from `from`, ordered by time.
+    pub fn fetch_node_status(&self, from: SystemTime) -> impl Iterator<Item = NodeStatus> + '_ {
+        use rocksdb::{IteratorMode, Direction};
+
+        let mut key = vec![];
+        custom_coding::time_emit(&from, &mut key);
+        self.inner
+            .iterator_cf(
+                self.node_status(),
+                IteratorMode::From(&key, Direction::Forward),
+            )
+            .filter_map(Self::decode_value)
+    }
+
+    /// Topic subscription announcements starting from `from`, ordered by time.
+    pub fn fetch_subscriptions(
+        &self,
+        from: SystemTime,
+    ) -> impl Iterator<Item = SubscriptionChange> + '_ {
+        use rocksdb::{IteratorMode, Direction};
+
+        let mut key = vec![];
+        custom_coding::time_emit(&from, &mut key);
+        self.inner
+            .iterator_cf(
+                self.subscriptions(),
+                IteratorMode::From(&key, Direction::Forward),
+            )
+            .filter_map(Self::decode_value)
+    }
+
+    /// The gossip data new to the node in the range, in the order it was first observed.
+    pub fn fetch_gossip_first_seen(
+        &self,
+        since: SystemTime,
+        until: SystemTime,
+    ) -> impl Iterator<Item = GossipFirstSeen> + '_ {
+        use rocksdb::{IteratorMode, Direction};
+
+        let mut key = vec![];
+        custom_coding::time_emit(&since, &mut key);
+        self.inner
+            .iterator_cf(
+                self.gossip_first_seen(),
+                IteratorMode::From(&key, Direction::Forward),
+            )
+            .filter_map(Self::decode_value::<GossipFirstSeen>)
+            .take_while(move |v| v.timestamp <= until)
+    }
+
+    /// The gossip delivered again in the range, only if stored with `GOSSIP_DEDUP`, ordered by time.
+    pub fn fetch_gossip_duplicates(
+        &self,
+        since: SystemTime,
+        until: SystemTime,
+    ) -> impl Iterator<Item = GossipDuplicate> + '_ {
+        use rocksdb::{IteratorMode, Direction};
+
+        let mut key = vec![];
+        custom_coding::time_emit(&since, &mut key);
+        self.inner
+            .iterator_cf(
+                self.gossip_duplicates(),
+                IteratorMode::From(&key, Direction::Forward),
+            )
+            .filter_map(Self::decode_value::<GossipDuplicate>)
+            .take_while(move |v| v.timestamp <= until)
+    }
+
+    /// The connections verified in the range, see `verify::spawn`.
+    pub fn fetch_verifications(
+        &self,
+        since: SystemTime,
+        until: SystemTime,
+    ) -> impl Iterator<Item = Verification> + '_ {
+        use rocksdb::{IteratorMode, Direction};
+
+        let mut key = vec![];
+        custom_coding::time_emit(&since, &mut key);
+        self.inner
+            .iterator_cf(
+                self.verifications(),
+                IteratorMode::From(&key, Direction::Forward),
+            )
+            .filter_map(Self::decode_value::<Verification>)
+            .take_while(move |v| v.timestamp <= until)
+    }
+
+    /// The agent versions the peers announced in the range, how many peers run each version.
+    pub fn fetch_agent_versions(
+        &self,
+        since: SystemTime,
+        until: SystemTime,
+        interval: Duration,
+    ) -> AgentVersionReport {
+        use rocksdb::{IteratorMode, Direction};
+
+        let mut key = vec![];
+        custom_coding::time_emit(&since, &mut key);
+        let sightings = self
+            .inner
+            .iterator_cf(
+                self.agent_versions(),
+                IteratorMode::From(&key, Direction::Forward),
+            )
+            .filter_map(Self::decode_value::<AgentVersionSighting>)
+            .take_while(move |v| v.timestamp <= until);
+        AgentVersionReport::build(sightings, since, until, interval)
+    }
+
+    /// Changes of the topic subscriptions of the peer, or of the local node, in the range.
+    pub fn fetch_subscription_timeline(
+        &self,
+        peer: SubscriptionPeer,
+        since: SystemTime,
+        until: SystemTime,
+    ) -> SubscriptionTimeline {
+        let connections = match &peer {
+            SubscriptionPeer::PeerId(peer_id) => self
+                .fetch_identity_history(*peer_id)
+                .appearances
+                .into_iter()
+                .map(|a| a.connection_id)
+                .collect(),
+            _ => BTreeSet::new(),
+        };
+        // the state at `since` depends on the announcements before it
+        let changes = self
+            .fetch_subscriptions(SystemTime::UNIX_EPOCH)
+            .filter(|v| match &peer {
+                SubscriptionPeer::Local => !v.incoming,
+                SubscriptionPeer::Ip(ip) => v.incoming && v.addr.ip() == *ip,
+                SubscriptionPeer::PeerId(_) => v.incoming && connections.contains(&v.connection_id),
+            });
+        SubscriptionTimeline::build(changes, since, until)
+    }
+
+    /// The latest node status snapshot taken before `time`.
+    pub fn fetch_node_status_at(&self, time: SystemTime) -> Option<NodeStatus> {
+        use rocksdb::{IteratorMode, Direction};
+
+        let mut key = vec![];
+        custom_coding::time_emit(&time, &mut key);
+        self.inner
+            .iterator_cf(
+                self.node_status(),
+                IteratorMode::From(&key, Direction::Reverse),
+            )
+            .filter_map(Self::decode_value)
+            .next()
+    }
+
+    /// Messages selected by `params` with node log lines and node status snapshots
+    /// that happened between the first and the last message, ordered by time.
+    /// The first item is the node status at the time of the first message, if known.
+    pub fn fetch_timeline(&self, params: &ValidParams) -> Vec<serde_json::Value> {
+        let messages = self.fetch_messages(params).collect::<Vec<_>>();
+        let (from, to) = match (
+            messages.iter().map(|(_, msg)| msg.timestamp).min(),
+            messages.iter().map(|(_, msg)| msg.timestamp).max(),
+        ) {
+            (Some(from), Some(to)) => (from, to + Duration::from_nanos(1)),
+            _ => return vec![],
+        };
+
+        let mut items = messages
+            .into_iter()
+            .map(|(id, msg)| {
+                let timestamp = msg.timestamp;
+                let v = serde_json::json!({ "type": "message", "id": id, "message": msg });
+                (timestamp, v)
+            })
+            .chain(
+                self.fetch_node_log(from)
+                    .take_while(|line| line.timestamp < to)
+                    .map(|line| {
+                        let timestamp = line.timestamp;
+                        let v =
+                            serde_json::json!({ "type": "node_log", "line": line.post_process() });
+                        (timestamp, v)
+                    }),
+            )
+            .chain(
+                self.fetch_node_status_at(from)
+                    .into_iter()
+                    .chain(
+                        self.fetch_node_status(from)
+                            .skip_while(|status| status.timestamp <= from),
+                    )
+                    .take_while(|status| status.timestamp < to)
+                    .map(|status| {
+                        let timestamp = status.timestamp.max(from);
+                        let v = serde_json::json!({ "type": "node_status", "status": status });
+                        (timestamp, v)
+                    }),
+            )
+            .collect::<Vec<_>>();
+        // stable sort, messages with the same timestamp keep the order
+        items.sort_by_key(|(timestamp, _)| *timestamp);
+        if let Direction::Reverse = params.coordinate.direction {
+            items.reverse();
+        }
+        items.into_iter().map(|(_, v)| v).collect()
+    }
+
+    /// Multistream select transcripts of the streams of the connection.
+    pub fn fetch_negotiations(&self, connection_id: ConnectionId) -> Vec<Negotiation> {
+        use rocksdb::{IteratorMode, Direction};
+
+        let key = connection_id.chain(vec![]);
+        self.inner
+            .iterator_cf(
+                self.negotiations(),
+                IteratorMode::From(&key, Direction::Forward),
+            )
+            .filter_map(Self::decode_value::<Negotiation>)
+            .take_while(|v| v.connection_id == connection_id)
+            .collect()
+    }
+
+    /// The closes and the resets of the streams of the connection, by the stream.
+    pub fn fetch_stream_ends(&self, connection_id: ConnectionId) -> Vec<StreamEnd> {
+        use rocksdb::{IteratorMode, Direction};
+
+        let key = connection_id.chain(vec![]);
+        self.inner
+            .iterator_cf(
+                self.stream_ends(),
+                IteratorMode::From(&key, Direction::Forward),
+            )
+            .filter_map(Self::decode_value::<StreamEnd>)
+            .take_while(|v| v.connection_id == connection_id)
+            .collect()
+    }
+
+    /// The protocols the connection negotiated against the profile of its network.
+    pub fn fetch_protocol_checklist(
+        &self,
+        connection_id: ConnectionId,
+    ) -> Result<ProtocolChecklist, DbError> {
+        let cn = self.fetch_connection(connection_id.0)?;
+        let profile = self.profiles.select(&self.profiles.chain_id(&cn.alias));
+        let negotiations = self.fetch_negotiations(connection_id);
+        Ok(profile.checklist(negotiations.iter().map(|n| n.agreed.as_str())))
     }
 
-    pub fn set_total<const K: u8>(&self, v: u64) -> Result<(), DbError> {
-        Ok(self.inner.put([K], v.chain(vec![]))?)
+    pub fn fetch_noise_handshake(
+        &self,
+        connection_id: ConnectionId,
+    ) -> Result<NoiseHandshake, DbError> {
+        self.get(self.noise_handshakes(), connection_id.chain(vec![]))
     }
 
-    pub fn fetch_connection(&self, id: u64) -> Result<Connection, DbError> {
-        self.get(self.connections(), id.to_be_bytes())
+    pub fn fetch_syscall_errors(&self, connection_id: ConnectionId) -> Vec<SyscallErrorStat> {
+        use rocksdb::{IteratorMode, Direction};
+
+        let key = connection_id.chain(vec![]);
+        self.inner
+            .iterator_cf(
+                self.syscall_errors(),
+                IteratorMode::From(&key, Direction::Forward),
+            )
+            .filter_map(Self::decode_syscall_error)
+            .take_while(|(key, _)| key.connection_id == connection_id)
+            .map(|(key, count)| SyscallErrorStat::new(key, count))
+            .collect()
+    }
+
+    fn decode_syscall_error(
+        item: Result<(Box<[u8]>, Box<[u8]>), rocksdb::Error>,
+    ) -> Option<(SyscallErrorKey, u64)> {
+        match item {
+            Ok((key, value)) => {
+                match (SyscallErrorKey::absorb_ext(&key), u64::absorb_ext(&value)) {
+                    (Ok(key), Ok(count)) => Some((key, count)),
+                    _ => {
+                        log::error!("cannot decode syscall error {}", hex::encode(&key));
+                        None
+                    }
+                }
+            }
+            Err(err) => {
+                log::error!("{err}");
+                None
+            }
+        }
     }
 
     fn fetch_details(&self, (key, msg): (u64, Message)) -> Option<(u64, FullMessage)> {
@@ -565,67 +1825,33 @@ impl DbCore {
                 stream_kind: msg.stream_kind,
                 message: serde_json::Value::String(msg.brief),
                 size: msg.size,
+                seq: msg.seq,
+                decoder_version: msg.decoder_version,
+                first_byte: msg.first_byte(),
+                chunks: msg.chunks,
             },
         ))
     }
 
     // TODO: preview is useless
     fn fetch_details_inner(&self, msg: Message, preview: bool) -> Result<FullMessage, DbError> {
+        self.fetch_details_redacted(msg, preview, Redaction::None)
+    }
+
+    fn fetch_details_redacted(
+        &self,
+        msg: Message,
+        preview: bool,
+        redaction: Redaction,
+    ) -> Result<FullMessage, DbError> {
         let connection =
             self.get::<Connection, _>(self.connections(), msg.connection_id.0.to_be_bytes())?;
-        let buf = self.fetch_blob(msg.connection_id, msg.offset)?;
+        let buf = self.fetch_blob_redacted(&msg, redaction)?;
+        let capture_redaction = self.stored_redaction(&msg);
         let message = match msg.stream_kind {
-            StreamKind::Kad => crate::decode::kademlia::parse(buf, preview)?,
-            StreamKind::Meshsub => crate::decode::meshsub::parse(buf, preview)?,
-            StreamKind::Handshake => crate::decode::noise::parse(buf, preview)?,
-            StreamKind::Rpc => crate::decode::rpc::parse(buf, preview)?,
-            StreamKind::IpfsId => crate::decode::identify::parse(buf, preview, msg.stream_kind)?,
-            StreamKind::IpfsPush => crate::decode::identify::parse(buf, preview, msg.stream_kind)?,
-            // TODO: proper decode
-            StreamKind::IpfsDelta => serde_json::Value::String(hex::encode(&buf)),
-            StreamKind::PeerExchange => crate::decode::json_string::parse(buf, preview)?,
-            // TODO: proper decode
-            StreamKind::BitswapExchange => serde_json::Value::String(hex::encode(&buf)),
-            // TODO: proper decode
-            StreamKind::NodeStatus => serde_json::Value::String(hex::encode(&buf)),
-            StreamKind::Select => {
-                let s = String::from_utf8(buf)
-                    .map_err(|err| DbError::Decode(DecodeError::Utf8(err)))?;
-                serde_json::Value::String(s)
-            }
-            StreamKind::Mplex => {
-                let v = buf.as_slice().try_into().map_err(|_| {
-                    DbError::Decode(DecodeError::UnexpectedSize {
-                        actual: buf.len(),
-                        expected: 8,
-                    })
-                })?;
-                let v = u64::from_be_bytes(v);
-                let stream = v >> 3;
-                let header = v & 7;
-                let action = match header {
-                    0 => "create stream",
-                    3 => "close receiver",
-                    4 => "close initiator",
-                    5 => "reset receiver",
-                    6 => "reset initiator",
-                    1 | 2 | 7 => panic!("unexpected header {header}"),
-                    _ => unreachable!(),
-                };
-
-                #[derive(Serialize)]
-                struct MplexMessage {
-                    action: &'static str,
-                    stream: u64,
-                }
-
-                let msg = MplexMessage { action, stream };
-
-                serde_json::to_value(&msg)
-                    .map_err(|err| DbError::Decode(DecodeError::Serde(err)))?
-            }
-            StreamKind::Yamux => crate::decode::yamux::parse(buf, preview)?,
-            StreamKind::Unknown => serde_json::Value::String(hex::encode(&buf)),
+            _ if capture_redaction.covers(msg.stream_kind) => capture_redaction.placeholder(&buf),
+            _ if redaction.covers(msg.stream_kind) => redaction.placeholder(&buf),
+            _ => crate::decode::parse(msg.stream_kind, buf, preview)?,
         };
         Ok(FullMessage {
             connection_id: msg.connection_id,
@@ -636,6 +1862,10 @@ impl DbCore {
             stream_kind: msg.stream_kind,
             message,
             size: msg.size,
+            first_byte: msg.first_byte(),
+            chunks: msg.chunks,
+            seq: msg.seq,
+            decoder_version: msg.decoder_version,
         })
     }
 
@@ -680,6 +1910,8 @@ impl DbCore {
     {
         let it = it.filter_map(|id| match self.get(self.messages(), id.0.to_be_bytes()) {
             Ok(v) => Some((id.0, v)),
+            // the retention deleted the message, but not every index
+            Err(DbError::NoItemAtCursor(_)) => None,
             Err(err) => {
                 log::error!("{err}");
                 None
@@ -697,6 +1929,14 @@ impl DbCore {
         let coordinate = &params.coordinate;
         let direction = coordinate.direction;
 
+        let reverse = matches!(direction, Direction::Reverse);
+        let freeze = coordinate.freeze.and_then(|id| self.freezes.get(id));
+        let end = freeze.as_ref().map_or(u64::MAX, |f| f.connections);
+        let (present, id) = match &freeze {
+            Some(freeze) => freeze.start(reverse, present, id, end),
+            None => (present, id),
+        };
+
         let id = id.to_be_bytes();
         let mode = if present {
             rocksdb::IteratorMode::From(&id, direction.into())
@@ -709,6 +1949,7 @@ impl DbCore {
             .iterator_cf(self.connections(), mode)
             .filter_map(Self::decode);
         let it = Box::new(it) as Box<dyn Iterator<Item = (u64, Connection)>>;
+        let it = it.take_while(move |(id, _)| *id < end);
         let now = SystemTime::now();
         params.limit(it.filter_map(move |(id, cn)| {
             if cn.stats_in.total_bytes == 0 && cn.stats_out.total_bytes == 0 {
@@ -727,6 +1968,14 @@ impl DbCore {
         let coordinate = &params.coordinate;
         let direction = coordinate.direction;
 
+        let reverse = matches!(direction, Direction::Reverse);
+        let freeze = coordinate.freeze.and_then(|id| self.freezes.get(id));
+        let end = freeze.as_ref().map_or(u64::MAX, |f| f.messages);
+        let (present, id) = match &freeze {
+            Some(freeze) => freeze.start(reverse, present, id, end),
+            None => (present, id),
+        };
+
         let it = if params.stream_filter.is_some() || params.kind_filter.is_some() {
             let stream_indexes = match &params.stream_filter {
                 Some(StreamFilter::AnyStreamByAddr(addr)) => {
@@ -801,7 +2050,6 @@ impl DbCore {
                             .map(|StreamByKindIdx { id, .. }| id)
                     });
 
-                    let reverse = matches!(direction, Direction::Reverse);
                     let predicate = move |a: &MessageId, b: &MessageId| (*a < *b) ^ reverse;
                     let it = itertools::kmerge_by(its, predicate);
 
@@ -824,7 +2072,6 @@ impl DbCore {
                             .map(|MessageKindIdx { id, .. }| id)
                     });
 
-                    let reverse = matches!(direction, Direction::Reverse);
                     let predicate = move |a: &MessageId, b: &MessageId| (*a < *b) ^ reverse;
                     let it = itertools::kmerge_by(its, predicate);
 
@@ -856,22 +2103,771 @@ impl DbCore {
                 .filter_map(Self::decode);
             Box::new(it) as Box<dyn Iterator<Item = (u64, Message)>>
         };
+        // going forward the ids only grow, stop at the first one after the freeze
+        let it = it.take_while(move |(id, _)| *id < end);
+        let decoder_filter = params.decoder_filter;
+        let it = it.filter(move |(_, msg)| decoder_filter.map_or(true, |f| f.matches(msg)));
         params.limit(it.filter_map(|v| self.fetch_details(v)))
     }
 
-    pub fn fetch_full_message(&self, id: u64) -> Result<FullMessage, DbError> {
+    pub fn fetch_decoder_versions(&self) -> DecoderVersions {
+        let mut versions = BTreeMap::<u32, DecoderVersionStats>::new();
+        let it = self
+            .inner
+            .iterator_cf(self.messages(), rocksdb::IteratorMode::Start)
+            .filter_map(Self::decode::<u64, Message>);
+        for (id, msg) in it {
+            let stats =
+                versions
+                    .entry(msg.decoder_version)
+                    .or_insert_with(|| DecoderVersionStats {
+                        messages: 0,
+                        first_id: id,
+                        last_id: id,
+                        since: msg.timestamp,
+                        until: msg.timestamp,
+                    });
+            stats.messages += 1;
+            stats.last_id = id;
+            stats.since = stats.since.min(msg.timestamp);
+            stats.until = stats.until.max(msg.timestamp);
+        }
+        DecoderVersions {
+            current: DECODER_VERSION,
+            versions,
+        }
+    }
+
+    /// Decodes again the messages selected by the filter with the current version
+    /// of the decoders, updates their types and the message kind index.
+    /// The ledger hash index is not updated.
+    pub fn redecode(&self, filter: DecoderFilter) -> Result<RedecodeSummary, DbError> {
+        let mut summary = RedecodeSummary::default();
+        let it = self
+            .inner
+            .iterator_cf(self.messages(), rocksdb::IteratorMode::Start)
+            .filter_map(Self::decode::<u64, Message>)
+            .filter(|(_, msg)| filter.matches(msg) && msg.decoder_version != DECODER_VERSION);
+        for (id, mut msg) in it {
+            if self.stored_redaction(&msg).covers(msg.stream_kind) {
+                summary.skipped += 1;
+                continue;
+            }
+            let bytes = self.fetch_blob(msg.connection_id, msg.offset)?;
+            let tys = match crate::decode::parse_types(msg.stream_kind, &bytes, false) {
+                Ok((tys, _)) => tys,
+                Err(err) => {
+                    log::warn!("cannot decode message {id} again: {err}");
+                    summary.failed += 1;
+                    continue;
+                }
+            };
+            let id = MessageId(id);
+            let stale = msg
+                .brief
+                .split(',')
+                .filter_map(|s| s.parse::<MessageType>().ok());
+            for ty in stale {
+                let index = MessageKindIdx { ty, id };
+                self.inner
+                    .delete_cf(self.message_kind_index(), index.chain(vec![]))?;
+            }
+            for ty in &tys {
+                let index = MessageKindIdx { ty: ty.clone(), id };
+                self.inner
+                    .put_cf(self.message_kind_index(), index.chain(vec![]), vec![])?;
+            }
+            msg.brief = tys
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(",");
+            msg.decoder_version = DECODER_VERSION;
+            self.inner
+                .put_cf(self.messages(), id.0.to_be_bytes(), msg.chain(vec![]))?;
+            summary.messages += 1;
+        }
+        self.http_cache.invalidate();
+        Ok(summary)
+    }
+
+    /// Deletes the connections closed before the moment, their messages, payloads
+    /// and indexes. The ledger hash index and the peer id index keep the dangling entries,
+    /// the queries skip them. Returns the number of the connections and the messages deleted.
+    pub fn prune_before(&self, before: SystemTime) -> Result<(u64, u64), DbError> {
+        let closed = self
+            .inner
+            .iterator_cf(self.connections(), rocksdb::IteratorMode::Start)
+            .filter_map(Self::decode::<u64, Connection>)
+            .take_while(|(_, cn)| cn.timestamp < before)
+            .filter(|(_, cn)| {
+                cn.timestamp_close != SystemTime::UNIX_EPOCH && cn.timestamp_close < before
+            })
+            .collect::<Vec<_>>();
+
+        let (mut cns, mut msgs) = (0, 0);
+        for (id, cn) in closed {
+            let from = ConnectionId(id).chain(vec![]);
+            let to = ConnectionId(id + 1).chain(vec![]);
+            let mode = rocksdb::IteratorMode::From(&from, rocksdb::Direction::Forward);
+            let ids = self
+                .inner
+                .iterator_cf(self.connection_id_index(), mode)
+                .filter_map(Self::decode_index::<ConnectionIdx>)
+                .take_while(|index| index.connection_id.0 == id)
+                .map(|index| index.id)
+                .collect::<Vec<_>>();
+            for id in ids {
+                let msg = match self.get::<Message, _>(self.messages(), id.0.to_be_bytes()) {
+                    Ok(v) => v,
+                    Err(DbError::NoItemAtCursor(_)) => continue,
+                    Err(err) => return Err(err),
+                };
+                let index = AddressIdx {
+                    addr: cn.info.addr,
+                    id,
+                };
+                self.inner
+                    .delete_cf(self.addr_index(), index.chain(vec![]))?;
+                let index = StreamByKindIdx {
+                    stream_kind: msg.stream_kind,
+                    id,
+                };
+                self.inner
+                    .delete_cf(self.stream_kind_index(), index.chain(vec![]))?;
+                let tys = msg
+                    .brief
+                    .split(',')
+                    .filter_map(|s| s.parse::<MessageType>().ok());
+                for ty in tys {
+                    let index = MessageKindIdx { ty, id };
+                    self.inner
+                        .delete_cf(self.message_kind_index(), index.chain(vec![]))?;
+                }
+                self.inner.delete_cf(self.messages(), id.0.to_be_bytes())?;
+                msgs += 1;
+            }
+            self.inner
+                .delete_range_cf(self.connection_id_index(), &from, &to)?;
+            self.inner
+                .delete_range_cf(self.stream_id_index(), &from, &to)?;
+            self.inner.delete_range_cf(self.blobs(), &from, &to)?;
+            self.inner.delete_cf(self.connections(), &from)?;
+            self.cache
+                .lock()
+                .expect("must be ok")
+                .remove(&ConnectionId(id));
+            cns += 1;
+        }
+        if cns != 0 {
+            self.http_cache.invalidate();
+        }
+        Ok((cns, msgs))
+    }
+
+    /// Deletes the messages below `first`, their indexes, their payloads and the chunks
+    /// of their connections stored before them, the connections stay. So the capture keeps
+    /// only the latest messages. Returns the number of the messages deleted.
+    pub fn prune_messages_below(&self, first: MessageId) -> Result<u64, DbError> {
+        let messages = self
+            .inner
+            .iterator_cf(self.messages(), rocksdb::IteratorMode::Start)
+            .filter_map(Self::decode::<u64, Message>)
+            .take_while(|(id, _)| *id < first.0)
+            .collect::<Vec<_>>();
+        if messages.is_empty() {
+            return Ok(0);
+        }
+
+        let mut addresses = BTreeMap::new();
+        // the end of the payload of the last deleted message of each connection
+        let mut blobs = BTreeMap::<ConnectionId, u64>::new();
+        for (id, msg) in &messages {
+            let id = MessageId(*id);
+            let cn = msg.connection_id;
+            let addr = *addresses
+                .entry(cn)
+                .or_insert_with(|| self.fetch_connection(cn.0).ok().map(|cn| cn.info.addr));
+            if let Some(addr) = addr {
+                let index = AddressIdx { addr, id };
+                self.inner
+                    .delete_cf(self.addr_index(), index.chain(vec![]))?;
+            }
+            let index = ConnectionIdx {
+                connection_id: cn,
+                id,
+            };
+            self.inner
+                .delete_cf(self.connection_id_index(), index.chain(vec![]))?;
+            let index = StreamIdx {
+                stream_full_id: StreamFullId {
+                    cn,
+                    id: msg.stream_id,
+                },
+                id,
+            };
+            self.inner
+                .delete_cf(self.stream_id_index(), index.chain(vec![]))?;
+            let index = StreamByKindIdx {
+                stream_kind: msg.stream_kind,
+                id,
+            };
+            self.inner
+                .delete_cf(self.stream_kind_index(), index.chain(vec![]))?;
+            let tys = msg
+                .brief
+                .split(',')
+                .filter_map(|s| s.parse::<MessageType>().ok());
+            for ty in tys {
+                let index = MessageKindIdx { ty, id };
+                self.inner
+                    .delete_cf(self.message_kind_index(), index.chain(vec![]))?;
+            }
+            let end = blobs.entry(cn).or_default();
+            *end = (*end).max(msg.offset + 1);
+        }
+        for (cn, end) in blobs {
+            let from = (cn, 0_u64).chain(vec![]);
+            let to = (cn, end).chain(vec![]);
+            self.inner.delete_range_cf(self.blobs(), from, to)?;
+        }
+        self.inner
+            .delete_range_cf(self.messages(), 0_u64.to_be_bytes(), first.0.to_be_bytes())?;
+        self.http_cache.invalidate();
+        Ok(messages.len() as u64)
+    }
+
+    pub fn fetch_identity_history(&self, peer_id: PeerId) -> IdentityHistory {
+        use rocksdb::{IteratorMode, Direction};
+
+        let unspecified = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0);
+
+        let start = PeerIdIdx {
+            peer_id,
+            timestamp: SystemTime::UNIX_EPOCH,
+            connection_id: ConnectionId(0),
+            addr: unspecified,
+        }
+        .chain(vec![]);
+        let appearances = self
+            .inner
+            .iterator_cf(
+                self.peer_id_index(),
+                IteratorMode::From(&start, Direction::Forward),
+            )
+            .filter_map(Self::decode_index::<PeerIdIdx>)
+            .take_while(|index| index.peer_id == peer_id)
+            .map(|index| IdentityAppearance {
+                addr: index.addr,
+                connection_id: index.connection_id,
+                timestamp: index.timestamp,
+            })
+            .collect::<Vec<_>>();
+
+        let ips = appearances
+            .iter()
+            .map(|a| a.addr.ip())
+            .collect::<BTreeSet<_>>();
+        let shared_ips = ips
+            .iter()
+            .filter_map(|ip| {
+                let start = AddrPeerIdIdx {
+                    addr: SocketAddr::new(*ip, 0),
+                    timestamp: SystemTime::UNIX_EPOCH,
+                    connection_id: ConnectionId(0),
+                    peer_id,
+                }
+                .chain(vec![]);
+                let peer_ids = self
+                    .inner
+                    .iterator_cf(
+                        self.addr_peer_id_index(),
+                        IteratorMode::From(&start, Direction::Forward),
+                    )
+                    .filter_map(Self::decode_index::<AddrPeerIdIdx>)
+                    .take_while(|index| index.addr.ip() == *ip)
+                    .filter(|index| index.peer_id != peer_id)
+                    .map(|index| index.peer_id.to_base58())
+                    .collect::<BTreeSet<_>>();
+                if peer_ids.is_empty() {
+                    None
+                } else {
+                    Some(SharedIp {
+                        ip: *ip,
+                        peer_ids: peer_ids.into_iter().collect(),
+                    })
+                }
+            })
+            .collect();
+
+        IdentityHistory {
+            peer_id: peer_id.to_base58(),
+            appearances,
+            roaming: ips.len() >= Self::ROAMING_THRESHOLD,
+            shared_ips,
+        }
+    }
+
+    pub fn fetch_full_message(
+        &self,
+        id: u64,
+        redaction: Redaction,
+    ) -> Result<FullMessage, DbError> {
+        self.http_cache.message(id, redaction, || {
+            let msg = self.get::<Message, _>(self.messages(), id.to_be_bytes())?;
+            self.fetch_details_redacted(msg, false, redaction)
+        })
+    }
+
+    /// The message decoded and cut to the limits, to inline in the list.
+    pub fn fetch_preview(
+        &self,
+        id: u64,
+        limits: PreviewLimits,
+        redaction: Redaction,
+    ) -> Result<serde_json::Value, DbError> {
+        let msg = self.get::<Message, _>(self.messages(), id.to_be_bytes())?;
+        let buf = self.fetch_blob_redacted(&msg, redaction)?;
+        let capture_redaction = self.stored_redaction(&msg);
+        if capture_redaction.covers(msg.stream_kind) {
+            return Ok(capture_redaction.placeholder(&buf));
+        }
+        if redaction.covers(msg.stream_kind) {
+            return Ok(redaction.placeholder(&buf));
+        }
+        Ok(crate::decode::preview::render(
+            msg.stream_kind,
+            buf,
+            limits,
+        )?)
+    }
+
+    /// All connections, ordered by id.
+    pub fn fetch_all_connections(&self) -> impl Iterator<Item = (u64, Connection)> + '_ {
+        self.inner
+            .iterator_cf(self.connections(), rocksdb::IteratorMode::Start)
+            .filter_map(Self::decode)
+    }
+
+    /// Bytes of all connections attributed to the protocol layers.
+    pub fn fetch_layer_stats(&self) -> LayerReport {
+        self.fetch_all_connections().map(|(_, cn)| cn).collect()
+    }
+
+    /// How much the noise frames of all connections reveal the lengths of the messages.
+    pub fn fetch_framing_stats(&self) -> FramingReport {
+        self.fetch_all_connections().map(|(_, cn)| cn).collect()
+    }
+
+    /// Distinct peer addresses by country and autonomous system.
+    pub fn fetch_peer_geo(&self) -> GeoReport {
+        self.fetch_all_connections().map(|(_, cn)| cn).collect()
+    }
+
+    /// The addresses of the node, local and as the peers observe them, of all connections.
+    pub fn fetch_nat(&self) -> NatReport {
+        self.fetch_all_connections().map(|(_, cn)| cn).collect()
+    }
+
+    /// The ephemeral ports of the outgoing connections opened in the range, how quickly they are reused.
+    pub fn fetch_port_usage(&self, since: SystemTime, until: SystemTime) -> PortReport {
+        let uses = self.fetch_all_connections().filter_map(|(id, cn)| {
+            if cn.incoming {
+                return None;
+            }
+            let local = cn.local_addr.parse::<SocketAddr>().ok()?;
+            Some(PortUse {
+                connection_id: ConnectionId(id),
+                pid: cn.info.pid,
+                alias: cn.alias,
+                fd: cn.info.fd,
+                port: local.port(),
+                opened: cn.timestamp,
+                closed: (cn.timestamp_close != SystemTime::UNIX_EPOCH)
+                    .then_some(cn.timestamp_close),
+            })
+        });
+        PortReport::build(uses, since, until)
+    }
+
+    /// The peers the node reports versus the peers it exchanged messages with during the last `window`.
+    pub fn fetch_peer_consistency(&self, window: Duration) -> PeerConsistency {
+        let activity = self.fetch_activity(window);
+        let mut wire = BTreeMap::<(IpAddr, Option<String>), WirePeer>::new();
+        for cn in activity
+            .connections
+            .into_iter()
+            .filter(|cn| cn.messages != 0)
+        {
+            let peer_id = self
+                .fetch_peer_identity(cn.connection_id, cn.addr)
+                .map(|(id, _)| id.to_base58());
+            let peer = wire
+                .entry((cn.addr.ip(), peer_id.clone()))
+                .or_insert_with(|| WirePeer {
+                    addr: cn.addr,
+                    peer_id,
+                    connection_ids: vec![],
+                    messages: 0,
+                });
+            peer.connection_ids.push(cn.connection_id);
+            peer.messages += cn.messages;
+        }
+        let wire = wire.into_values().collect();
+        PeerConsistency::build(
+            self.reported_peers.latest(),
+            wire,
+            activity.since,
+            activity.until,
+        )
+    }
+
+    /// The connections open during the last `window` classified as active, keep-alive or idle.
+    pub fn fetch_activity(&self, window: Duration) -> ActivityReport {
+        let until = SystemTime::now();
+        let since = until - window;
+        let messages = self.fetch_messages_since(since);
+        ActivityReport::build(self.fetch_all_connections(), messages, since, until)
+    }
+
+    /// Connects and disconnects during the range, hour by hour, and the peers reconnecting most.
+    pub fn fetch_churn(&self, since: SystemTime, until: SystemTime) -> ChurnReport {
+        let connections = self.fetch_all_connections().filter_map(|(id, cn)| {
+            let closed =
+                (cn.timestamp_close != SystemTime::UNIX_EPOCH).then_some(cn.timestamp_close);
+            if cn.timestamp >= until || closed.map_or(false, |closed| closed < since) {
+                return None;
+            }
+            // the failure is only needed for the connections opened in the range
+            let (failure, partial) = if cn.timestamp >= since {
+                self.fetch_handshake_outcome(ConnectionId(id), &cn)
+            } else {
+                (None, None)
+            };
+            Some(ConnectionOutcome {
+                peer: cn.info.addr.ip(),
+                opened: cn.timestamp,
+                closed,
+                failure,
+                partial,
+            })
+        });
+        ChurnReport::build(connections, since, until)
+    }
+
+    fn fetch_handshake_outcome(
+        &self,
+        id: ConnectionId,
+        cn: &Connection,
+    ) -> (Option<HandshakeFailure>, Option<PartialHandshake>) {
+        let handshake = self.fetch_noise_handshake(id).ok();
+        let negotiation_failed = self.fetch_negotiations(id).iter().any(|n| n.failed);
+        let failure = HandshakeFailure::of(cn, handshake.as_ref(), negotiation_failed);
+        let partial = PartialHandshake::of(id, cn, handshake.as_ref(), failure);
+        (failure, partial)
+    }
+
+    /// The connections opened in the range and closed before the noise handshake completed,
+    /// the latest first.
+    pub fn fetch_partial_handshakes(
+        &self,
+        since: SystemTime,
+        until: SystemTime,
+        limit: usize,
+    ) -> Vec<PartialHandshake> {
+        let mut partial = self
+            .fetch_all_connections()
+            .filter(|(_, cn)| (since..until).contains(&cn.timestamp))
+            .filter_map(|(id, cn)| self.fetch_handshake_outcome(ConnectionId(id), &cn).1)
+            .collect::<Vec<_>>();
+        partial.sort_by(|a, b| b.connection_id.cmp(&a.connection_id));
+        partial.truncate(limit);
+        partial
+    }
+
+    /// The connections opened in the range which did not reach the application protocols,
+    /// the latest first.
+    pub fn fetch_failed_handshakes(
+        &self,
+        since: SystemTime,
+        until: SystemTime,
+        limit: usize,
+    ) -> Vec<(ConnectionId, HandshakeFailure)> {
+        let mut failed = self
+            .fetch_all_connections()
+            .filter(|(_, cn)| (since..until).contains(&cn.timestamp))
+            .filter_map(|(id, cn)| {
+                let id = ConnectionId(id);
+                Some((id, self.fetch_handshake_outcome(id, &cn).0?))
+            })
+            .collect::<Vec<_>>();
+        failed.sort_by(|a, b| b.0.cmp(&a.0));
+        failed.truncate(limit);
+        failed
+    }
+
+    /// Duplicate deliveries of the gossip by each peer in buckets of `interval`.
+    pub fn fetch_gossip_duplication(
+        &self,
+        since: SystemTime,
+        until: SystemTime,
+        interval: Option<Duration>,
+    ) -> DuplicationHeatmap {
+        let interval = DuplicationHeatmap::interval(since, until, interval);
+        let mut addresses = BTreeMap::<ConnectionId, Option<IpAddr>>::new();
+        let deliveries = self
+            .fetch_publishes(since - DuplicationHeatmap::LOOKBACK, until)
+            .filter(|publish| publish.incoming)
+            .filter_map(|publish| {
+                let peer = *addresses.entry(publish.connection_id).or_insert_with(|| {
+                    let cn = self.fetch_connection(publish.connection_id.0).ok();
+                    cn.map(|cn| cn.info.addr.ip())
+                });
+                Some(Delivery {
+                    time: publish.time,
+                    peer: peer?,
+                    hash: publish.hash,
+                })
+            });
+        DuplicationHeatmap::build(deliveries, since, until, interval)
+    }
+
+    /// The largest messages, streams and connections of the range, at most `limit` of each.
+    pub fn fetch_leaderboard(
+        &self,
+        since: SystemTime,
+        until: SystemTime,
+        limit: usize,
+    ) -> Leaderboard {
+        let messages = self
+            .fetch_messages_since(since)
+            .take_while(|(_, msg)| msg.timestamp < until);
+        Leaderboard::build(messages, since, until, limit, |id| {
+            self.fetch_connection(id.0).ok().map(|cn| cn.info.addr)
+        })
+    }
+
+    /// Outcomes of the kademlia queries of the node, per peer.
+    pub fn fetch_kademlia_report(&self, since: SystemTime, until: SystemTime) -> KademliaReport {
+        let messages = self.fetch_kad_messages(since, until + KademliaReport::TIMEOUT);
+        KademliaReport::build(messages, since, until, |id| {
+            self.fetch_connection(id.0).ok().map(|cn| cn.info.addr)
+        })
+    }
+
+    /// The peers learned from the kademlia responses in the range.
+    pub fn fetch_learned_peers(&self, since: SystemTime, until: SystemTime) -> Vec<LearnedPeer> {
+        let messages = self.fetch_kad_messages(since, until);
+        LearnedPeer::collect(messages, |id| {
+            self.fetch_connection(id.0).ok().map(|cn| cn.info.addr)
+        })
+    }
+
+    /// The kademlia messages in the range with the peers they tell about, ordered by time.
+    fn fetch_kad_messages(
+        &self,
+        since: SystemTime,
+        until: SystemTime,
+    ) -> impl Iterator<Item = KadMessage> + '_ {
+        self.fetch_messages_in_range(since, until)
+            .filter(|msg| msg.stream_kind == StreamKind::Kad)
+            .map(move |msg| {
+                // the peers of the message redacted at capture time are lost
+                let redacted = self.stored_redaction(&msg).covers(msg.stream_kind);
+                let closer_peers = (!redacted)
+                    .then(|| self.fetch_blob(msg.connection_id, msg.offset).ok())
+                    .flatten()
+                    .and_then(|bytes| crate::decode::kademlia::parse_closer_peers(&bytes).ok())
+                    .unwrap_or_default();
+                KadMessage {
+                    time: msg.timestamp,
+                    connection_id: msg.connection_id,
+                    stream_id: msg.stream_id,
+                    incoming: msg.incoming,
+                    closer_peers,
+                }
+            })
+    }
+
+    /// Time from the first receipt of the gossip to the first forward of it, per topic.
+    pub fn fetch_validation_latency(
+        &self,
+        since: SystemTime,
+        until: SystemTime,
+    ) -> ValidationReport {
+        let window = ValidationReport::FORWARD_WINDOW;
+        let publishes = self.fetch_publishes(since - window, until + window);
+        ValidationReport::build(publishes, since, until)
+    }
+
+    /// The peers and the topics of the range, what `mina-capture baseline` stores.
+    pub fn fetch_baseline(&self, since: SystemTime, until: SystemTime) -> Baseline {
+        let messages = self.fetch_messages_in_range(since, until);
+        let publishes = self.fetch_publishes(since, until);
+        Baseline::build(
+            self.fetch_all_connections(),
+            messages,
+            publishes,
+            since,
+            until,
+        )
+    }
+
+    /// The range against the baseline, `None` if there is no baseline.
+    pub fn fetch_baseline_deviations(
+        &self,
+        since: SystemTime,
+        until: SystemTime,
+        threshold: f64,
+    ) -> Option<BaselineDeviations> {
+        let baseline = self.baseline()?;
+        Some(baseline.compare(&self.fetch_baseline(since, until), threshold))
+    }
+
+    /// The gossip received and sent in the range, each data of the meshsub message, ordered by time.
+    /// The duplicates stored as the references are counted as if they were the messages.
+    fn fetch_publishes(
+        &self,
+        since: SystemTime,
+        until: SystemTime,
+    ) -> impl Iterator<Item = Publish> + '_ {
+        use blake2::digest::{Update, FixedOutput, typenum};
+        use itertools::Itertools;
+
+        let duplicates = self.fetch_gossip_duplicates(since, until).filter_map(|v| {
+            let hash = hex::decode(&v.hash).ok()?.try_into().ok()?;
+            Some(Publish {
+                time: v.timestamp,
+                connection_id: v.connection_id,
+                incoming: v.incoming,
+                topic: v.topic,
+                hash,
+            })
+        });
+        self.fetch_messages_in_range(since, until)
+            .filter(|msg| msg.stream_kind == StreamKind::Meshsub && msg.brief.contains("publish"))
+            .flat_map(move |msg| {
+                // the data of the message redacted at capture time is lost, skip it
+                let redacted = self.stored_redaction(&msg).covers(msg.stream_kind);
+                let data = (!redacted)
+                    .then(|| self.fetch_blob(msg.connection_id, msg.offset).ok())
+                    .flatten()
+                    .and_then(|bytes| {
+                        crate::decode::meshsub::parse_protobuf_publish_topics(&bytes).ok()
+                    })
+                    .into_iter()
+                    .flatten();
+                data.map(move |(topic, data)| {
+                    let hash = blake2::Blake2b::<typenum::U16>::default()
+                        .chain(&data)
+                        .finalize_fixed();
+                    Publish {
+                        time: msg.timestamp,
+                        connection_id: msg.connection_id,
+                        incoming: msg.incoming,
+                        topic,
+                        hash: hash.into(),
+                    }
+                })
+            })
+            .merge_by(duplicates, |a, b| a.time <= b.time)
+    }
+
+    /// Messages starting at the first one observed not before `from`, ordered by id.
+    pub fn fetch_messages_since(
+        &self,
+        from: SystemTime,
+    ) -> impl Iterator<Item = (u64, Message)> + '_ {
+        use rocksdb::{IteratorMode, Direction};
+
+        let total = self.total::<{ Self::MESSAGES_CNT }>().unwrap_or(0);
+        let secs = from
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let id = if secs == 0 {
+            0
+        } else {
+            self.search_timestamp::<Message>(self.messages(), total, secs)
+                .unwrap_or(0)
+        };
+        let key = id.to_be_bytes();
+        self.inner
+            .iterator_cf(
+                self.messages(),
+                IteratorMode::From(&key, Direction::Forward),
+            )
+            .filter_map(Self::decode::<u64, Message>)
+            .skip_while(move |(_, msg)| msg.timestamp < from)
+    }
+
+    /// Messages in the time range `from..to`, ordered by id.
+    pub fn fetch_messages_in_range(
+        &self,
+        from: SystemTime,
+        to: SystemTime,
+    ) -> impl Iterator<Item = Message> + '_ {
+        self.fetch_messages_since(from)
+            .map(|(_, msg)| msg)
+            .take_while(move |msg| msg.timestamp < to)
+    }
+
+    /// The ids of the messages observed in the time range `from..to`.
+    pub fn message_ids_in_range(&self, from: SystemTime, to: SystemTime) -> Range<u64> {
+        let total = self.total::<{ Self::MESSAGES_CNT }>().unwrap_or(0);
+        let first = |time| {
+            self.fetch_messages_since(time)
+                .next()
+                .map_or(total, |(id, _)| id)
+        };
+        let start = first(from);
+        start..first(to).max(start)
+    }
+
+    /// The messages decoded, ordered by id, the cache of the server is not involved.
+    pub fn fetch_full_messages(
+        &self,
+        ids: Range<u64>,
+        redaction: Redaction,
+    ) -> impl Iterator<Item = (u64, Result<FullMessage, DbError>)> + '_ {
+        use rocksdb::{IteratorMode, Direction};
+
+        let key = ids.start.to_be_bytes();
+        self.inner
+            .iterator_cf(
+                self.messages(),
+                IteratorMode::From(&key, Direction::Forward),
+            )
+            .filter_map(Self::decode::<u64, Message>)
+            .take_while(move |(id, _)| *id < ids.end)
+            .map(move |(id, msg)| (id, self.fetch_details_redacted(msg, false, redaction)))
+    }
+
+    /// The node status when the message was observed and the next change of the status.
+    pub fn fetch_message_node_status(
+        &self,
+        id: u64,
+    ) -> Result<(Option<NodeStatus>, Option<NodeStatus>), DbError> {
         let msg = self.get::<Message, _>(self.messages(), id.to_be_bytes())?;
-        self.fetch_details_inner(msg, false)
+        let before = self.fetch_node_status_at(msg.timestamp);
+        let after = self
+            .fetch_node_status(msg.timestamp)
+            .find(|status| status.timestamp > msg.timestamp);
+        Ok((before, after))
     }
 
-    pub fn fetch_full_message_bin(&self, id: u64) -> Result<Vec<u8>, DbError> {
+    pub fn fetch_full_message_bin(
+        &self,
+        id: u64,
+        redaction: Redaction,
+    ) -> Result<Vec<u8>, DbError> {
         let msg = self.get::<Message, _>(self.messages(), id.to_be_bytes())?;
 
-        self.fetch_blob(msg.connection_id, msg.offset)
+        self.fetch_blob_redacted(&msg, redaction)
     }
 
-    pub fn fetch_full_message_hex(&self, id: u64) -> Result<String, DbError> {
-        let buf = self.fetch_full_message_bin(id)?;
+    pub fn fetch_full_message_hex(&self, id: u64, redaction: Redaction) -> Result<String, DbError> {
+        let buf = self.fetch_full_message_bin(id, redaction)?;
         Ok(hex::encode(&buf))
     }
 
@@ -918,6 +2914,38 @@ impl DbCore {
             .map(|(k, _)| (k.height, self.fetch_stats_block_v2(k.height)))
     }
 
+    /// How long the node forwarded each block observed in the range, from the first local
+    /// observation to the last send of the block, the blocks never sent are not counted.
+    pub fn fetch_slo_report(&self, since: SystemTime, until: SystemTime) -> SloReport {
+        let mut samples = BTreeMap::<Hash, Sample>::new();
+        let mut height = self.fetch_last_stat_block_v2().map(|(h, _)| h).unwrap_or(0);
+        while height > 0 {
+            let events = self.fetch_stats_block_v2(height);
+            if events.iter().all(|event| event.time < since) {
+                break;
+            }
+            for event in events {
+                let latency = match event.latency {
+                    Some(v) if !event.incoming => v,
+                    _ => continue,
+                };
+                let sample = samples.entry(event.hash).or_insert_with(|| Sample {
+                    hash: event.hash,
+                    height: event.block_height,
+                    time: event.time - latency,
+                    latency,
+                    node: event.receiver_addr.to_string(),
+                });
+                if latency > sample.latency {
+                    sample.latency = latency;
+                    sample.node = event.receiver_addr.to_string();
+                }
+            }
+            height -= 1;
+        }
+        SloReport::build(&self.slo, samples.into_values(), since, until)
+    }
+
     pub fn fetch_stats(&self, id: u32) -> Option<(StatsDbKey, BlockStat)> {
         let id_bytes = id.to_be_bytes();
         let mode = rocksdb::IteratorMode::From(&id_bytes, rocksdb::Direction::Forward);
@@ -1239,3 +3267,85 @@ fn duplicates_removed() {
     assert_eq!(result.next().unwrap().events.len(), 1);
     assert!(result.next().is_none());
 }
+
+#[cfg(test)]
+#[test]
+fn stream_ends_served() {
+    use super::{
+        rocksdb::DbFacade,
+        types::{StreamEndKind, StreamId},
+    };
+    use crate::event::{ConnectionInfo, DirectedId, EventMetadata};
+
+    let dir = temp_dir::TempDir::new().unwrap();
+    let db = DbFacade::open(dir.path()).unwrap();
+    let info = ConnectionInfo {
+        addr: "1.2.3.4:8302".parse().unwrap(),
+        pid: 1,
+        fd: 10,
+    };
+    let yamux = db
+        .add(
+            info.clone(),
+            false,
+            "node".to_owned(),
+            SystemTime::UNIX_EPOCH,
+        )
+        .unwrap();
+    let mplex = db
+        .add(info, true, "node".to_owned(), SystemTime::UNIX_EPOCH)
+        .unwrap();
+    let did = |secs, incoming| DirectedId {
+        metadata: EventMetadata {
+            time: SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+            ..EventMetadata::default()
+        },
+        incoming,
+        ..DirectedId::default()
+    };
+
+    // the yamux FIN of the local node, then the RST of the remote peer
+    let stream = yamux.get(StreamId::Forward(1));
+    stream
+        .end(&did(1, false), StreamEndKind::Close, 0, String::new())
+        .unwrap();
+    stream
+        .end(&did(2, true), StreamEndKind::Reset, 0, String::new())
+        .unwrap();
+    // the mplex reset of the stream which was already reset
+    let stream = mplex.get(StreamId::Backward(3));
+    stream
+        .end(&did(3, true), StreamEndKind::Reset, 0, String::new())
+        .unwrap();
+    stream
+        .end(
+            &did(4, false),
+            StreamEndKind::Reset,
+            0,
+            "already reset".to_owned(),
+        )
+        .unwrap();
+
+    let core = db.core();
+    let ends = core.fetch_stream_ends(yamux.id());
+    let summary = ends
+        .iter()
+        .map(|v| (v.stream_id, v.incoming, v.kind, v.error.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        [
+            (StreamId::Forward(1), false, StreamEndKind::Close, ""),
+            (StreamId::Forward(1), true, StreamEndKind::Reset, ""),
+        ]
+    );
+    let ends = core.fetch_stream_ends(mplex.id());
+    assert_eq!(ends.len(), 2);
+    assert!(ends.iter().all(|v| v.connection_id == mplex.id()));
+
+    // as `/connection/{id}/stream_ends` serves them
+    let json = serde_json::to_value(&ends).unwrap();
+    assert_eq!(json[0]["kind"], "reset");
+    assert_eq!(json[0]["incoming"], true);
+    assert_eq!(json[1]["error"], "already reset");
+}
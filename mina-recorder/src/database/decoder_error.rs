@@ -0,0 +1,137 @@
+use std::{fmt, net::SocketAddr, time::SystemTime};
+
+use crate::event::DirectedId;
+
+use super::{
+    anomaly::Anomaly,
+    types::{ConnectionId, Layer, StreamId},
+};
+
+/// The decoder of the layer cannot make sense of the bytes of the connection.
+#[derive(Debug, Clone)]
+pub struct DecoderError {
+    pub layer: Layer,
+    pub connection_id: ConnectionId,
+    pub addr: SocketAddr,
+    /// the stream of the multiplexed connection, `None` below the multiplexer
+    pub stream_id: Option<StreamId>,
+    pub incoming: bool,
+    pub time: SystemTime,
+    pub reason: String,
+    /// the beginning of the bytes the decoder failed on, at most `CONTEXT` bytes
+    pub context: Vec<u8>,
+    /// the layer skipped the bytes and goes on, otherwise it does not decode the connection anymore
+    pub recoverable: bool,
+}
+
+impl DecoderError {
+    pub const CONTEXT: usize = 32;
+
+    pub fn new<E>(layer: Layer, connection_id: ConnectionId, id: &DirectedId, reason: E) -> Self
+    where
+        E: fmt::Display,
+    {
+        DecoderError {
+            layer,
+            connection_id,
+            addr: id.metadata.id.addr,
+            stream_id: None,
+            incoming: id.incoming,
+            time: id.metadata.time,
+            reason: reason.to_string(),
+            context: vec![],
+            recoverable: true,
+        }
+    }
+
+    pub fn stream(mut self, stream_id: StreamId) -> Self {
+        self.stream_id = Some(stream_id);
+        self
+    }
+
+    pub fn bytes(mut self, bytes: &[u8]) -> Self {
+        self.context = bytes[..bytes.len().min(Self::CONTEXT)].to_vec();
+        self
+    }
+
+    pub fn fatal(mut self) -> Self {
+        self.recoverable = false;
+        self
+    }
+
+    /// The record of the anomalies store, the subject is `decoder`, the key is the peer.
+    pub fn anomaly(&self) -> Anomaly {
+        Anomaly {
+            timestamp: self.time,
+            subject: "decoder".to_owned(),
+            key: self.addr.to_string(),
+            count: 1,
+            baseline: 0,
+            window_secs: 0,
+            description: self.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for DecoderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let direction = if self.incoming {
+            "incoming"
+        } else {
+            "outgoing"
+        };
+        write!(f, "{} {} {}", self.layer, self.connection_id, self.addr)?;
+        if let Some(stream_id) = self.stream_id {
+            write!(f, " {stream_id}")?;
+        }
+        write!(f, " {direction}: {}", self.reason)?;
+        if !self.recoverable {
+            write!(f, ", stopped decoding")?;
+        }
+        if !self.context.is_empty() {
+            write!(f, ", bytes: {}", hex::encode(&self.context))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for DecoderError {}
+
+#[cfg(test)]
+#[test]
+fn decoder_error() {
+    use std::time::Duration;
+
+    use crate::event::{ConnectionInfo, EventMetadata};
+
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_675_166_400);
+    let id = DirectedId {
+        metadata: EventMetadata {
+            id: ConnectionInfo {
+                addr: "1.2.3.4:8302".parse().unwrap(),
+                pid: 1,
+                fd: 10,
+            },
+            time,
+            better_time: time,
+            duration: Duration::ZERO,
+        },
+        alias: "node".to_owned(),
+        incoming: true,
+        buffered: 0,
+    };
+    let err = DecoderError::new(Layer::Mux, ConnectionId(7), &id, "bad header")
+        .stream(StreamId::Forward(3))
+        .bytes(&[0xff; 100])
+        .fatal();
+    assert_eq!(err.context.len(), DecoderError::CONTEXT);
+    let anomaly = err.anomaly();
+    assert_eq!(
+        (anomaly.subject.as_str(), anomaly.key.as_str()),
+        ("decoder", "1.2.3.4:8302")
+    );
+    assert!(anomaly.description.starts_with("mux "));
+    assert!(anomaly
+        .description
+        .contains("incoming: bad header, stopped decoding, bytes: ffff"));
+}
@@ -0,0 +1,181 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::IpAddr,
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+
+/// The gossip message delivered to the node by the peer, identified by the hash of its data.
+pub struct Delivery {
+    pub time: SystemTime,
+    pub peer: IpAddr,
+    pub hash: [u8; 16],
+}
+
+/// Peer × time bucket matrix of duplicate deliveries of the gossip.
+/// The delivery is a duplicate if the node already received the same data,
+/// from this peer or from another one.
+#[derive(Serialize)]
+pub struct DuplicationHeatmap {
+    pub since: SystemTime,
+    pub until: SystemTime,
+    pub interval_secs: u64,
+    pub buckets: usize,
+    /// delivered / distinct messages in each bucket, the mesh as a whole,
+    /// `None` if nothing was delivered
+    pub factor: Vec<Option<f64>>,
+    /// the most duplicating peers first
+    pub peers: Vec<PeerDuplication>,
+}
+
+#[derive(Serialize)]
+pub struct PeerDuplication {
+    pub peer: IpAddr,
+    pub delivered: u64,
+    pub duplicates: u64,
+    /// duplicates / delivered in each bucket, `None` if the peer delivered nothing
+    pub ratio: Vec<Option<f64>>,
+}
+
+#[derive(Default, Clone, Copy)]
+struct Cell {
+    delivered: u64,
+    duplicates: u64,
+}
+
+impl Cell {
+    fn ratio(&self) -> Option<f64> {
+        (self.delivered != 0).then(|| self.duplicates as f64 / self.delivered as f64)
+    }
+}
+
+impl DuplicationHeatmap {
+    pub const MAX_BUCKETS: usize = 0x200;
+    /// The deliveries before the range which tell what the node already has,
+    /// the gossip is forwarded for about this long.
+    pub const LOOKBACK: Duration = Duration::from_secs(120);
+
+    /// The bucket width, the range is split in at most `MAX_BUCKETS` buckets.
+    pub fn interval(since: SystemTime, until: SystemTime, requested: Option<Duration>) -> Duration {
+        let range = until.duration_since(since).unwrap_or_default();
+        let min = range / Self::MAX_BUCKETS as u32;
+        requested
+            .unwrap_or(range / 60)
+            .max(min)
+            .max(Duration::from_secs(1))
+    }
+
+    /// The `deliveries` must be ordered by time, the first delivery of the data is not a duplicate,
+    /// so the deliveries before `since` should be included to know what the node already has.
+    pub fn build<I>(deliveries: I, since: SystemTime, until: SystemTime, interval: Duration) -> Self
+    where
+        I: IntoIterator<Item = Delivery>,
+    {
+        let range = until.duration_since(since).unwrap_or_default();
+        let buckets = (range.as_nanos() / interval.as_nanos()) as usize + 1;
+
+        let mut seen = BTreeSet::new();
+        let mut total = vec![Cell::default(); buckets];
+        let mut distinct = vec![0u64; buckets];
+        let mut rows = BTreeMap::<IpAddr, Vec<Cell>>::new();
+        for Delivery { time, peer, hash } in deliveries {
+            if time >= until {
+                break;
+            }
+            let duplicate = !seen.insert(hash);
+            let i = match time.duration_since(since) {
+                Ok(offset) => (offset.as_nanos() / interval.as_nanos()) as usize,
+                Err(_) => continue,
+            };
+            let row = rows
+                .entry(peer)
+                .or_insert_with(|| vec![Cell::default(); buckets]);
+            for cell in [&mut row[i], &mut total[i]] {
+                cell.delivered += 1;
+                cell.duplicates += duplicate as u64;
+            }
+            if !duplicate {
+                distinct[i] += 1;
+            }
+        }
+
+        let factor = total
+            .iter()
+            .zip(&distinct)
+            .map(|(cell, distinct)| match (cell.delivered, *distinct) {
+                (0, _) => None,
+                // all the data was received before the bucket
+                (delivered, 0) => Some(delivered as f64),
+                (delivered, distinct) => Some(delivered as f64 / distinct as f64),
+            })
+            .collect();
+        let mut peers = rows
+            .into_iter()
+            .map(|(peer, row)| PeerDuplication {
+                peer,
+                delivered: row.iter().map(|c| c.delivered).sum(),
+                duplicates: row.iter().map(|c| c.duplicates).sum(),
+                ratio: row.iter().map(Cell::ratio).collect(),
+            })
+            .collect::<Vec<_>>();
+        peers.sort_by(|a, b| b.duplicates.cmp(&a.duplicates).then(a.peer.cmp(&b.peer)));
+
+        DuplicationHeatmap {
+            since,
+            until,
+            interval_secs: interval.as_secs(),
+            buckets,
+            factor,
+            peers,
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn duplication_heatmap() {
+    let since = SystemTime::UNIX_EPOCH + Duration::from_secs(1_675_166_400);
+    let until = since + Duration::from_secs(20);
+    let interval = DuplicationHeatmap::interval(since, until, Some(Duration::from_secs(10)));
+    assert_eq!(interval, Duration::from_secs(10));
+    assert_eq!(
+        DuplicationHeatmap::interval(since, since + Duration::from_secs(3600), None),
+        Duration::from_secs(60)
+    );
+
+    let a = "1.2.3.4".parse().unwrap();
+    let b = "1.2.3.5".parse().unwrap();
+    let d = |secs: i64, peer, hash: u8| Delivery {
+        time: if secs < 0 {
+            since - Duration::from_secs(-secs as u64)
+        } else {
+            since + Duration::from_secs(secs as u64)
+        },
+        peer,
+        hash: [hash; 16],
+    };
+    let heatmap = DuplicationHeatmap::build(
+        [
+            // known before the range
+            d(-5, a, 0),
+            d(1, a, 1),
+            d(2, b, 1),
+            d(3, b, 0),
+            d(12, b, 2),
+            d(13, a, 2),
+            d(14, a, 2),
+            // after the range
+            d(25, a, 3),
+        ],
+        since,
+        until,
+        interval,
+    );
+    assert_eq!(heatmap.buckets, 3);
+    assert_eq!(heatmap.factor, [Some(3.0), Some(3.0), None]);
+    let first = &heatmap.peers[0];
+    assert_eq!((first.peer, first.delivered, first.duplicates), (a, 3, 2));
+    assert_eq!(first.ratio, [Some(0.0), Some(1.0), None]);
+    assert_eq!(heatmap.peers[1].ratio, [Some(1.0), Some(0.0), None]);
+}
@@ -0,0 +1,153 @@
+use radiation::{Absorb, Emit};
+use serde::Serialize;
+
+use super::types::Connection;
+
+/// The noise frames of the transport phase against the plaintext they carry, how much
+/// the length of the frame tells about the length of the message.
+#[derive(Default, Clone, Debug, PartialEq, Eq, Absorb, Emit, Serialize)]
+pub struct FramingStats {
+    pub frames: u64,
+    /// the frames with the length prefix
+    pub ciphertext: u64,
+    pub plaintext: u64,
+    /// the frames which have more than the length prefix and the authentication tag
+    pub padded_frames: u64,
+    /// bytes beyond the length prefix and the authentication tag
+    pub padding: u64,
+    // the sums for the correlation of the lengths, saturating
+    plaintext_sq: u64,
+    ciphertext_sq: u64,
+    product: u64,
+}
+
+impl FramingStats {
+    /// The length prefix and the authentication tag of the frame.
+    pub const OVERHEAD: u64 = 2 + 16;
+
+    /// The `frame` of this many bytes with the prefix decrypts into `plaintext` bytes.
+    pub fn add(&mut self, frame: usize, plaintext: usize) {
+        let (c, p) = (frame as u64, plaintext as u64);
+        let padding = c.saturating_sub(p + Self::OVERHEAD);
+        self.frames += 1;
+        self.ciphertext += c;
+        self.plaintext += p;
+        self.padded_frames += (padding != 0) as u64;
+        self.padding += padding;
+        self.plaintext_sq = self.plaintext_sq.saturating_add(p * p);
+        self.ciphertext_sq = self.ciphertext_sq.saturating_add(c * c);
+        self.product = self.product.saturating_add(c * p);
+    }
+
+    /// Pearson correlation of the frame length and the plaintext length, one means the frame
+    /// reveals the length of the message, `None` if the lengths never vary.
+    pub fn correlation(&self) -> Option<f64> {
+        let n = self.frames as f64;
+        let (sc, sp) = (self.ciphertext as f64, self.plaintext as f64);
+        let cov = n * self.product as f64 - sc * sp;
+        let var_c = n * self.ciphertext_sq as f64 - sc * sc;
+        let var_p = n * self.plaintext_sq as f64 - sp * sp;
+        let d = (var_c * var_p).sqrt();
+        (d > 0.0).then(|| (cov / d).clamp(-1.0, 1.0))
+    }
+}
+
+impl std::ops::AddAssign<&FramingStats> for FramingStats {
+    fn add_assign(&mut self, rhs: &FramingStats) {
+        self.frames += rhs.frames;
+        self.ciphertext += rhs.ciphertext;
+        self.plaintext += rhs.plaintext;
+        self.padded_frames += rhs.padded_frames;
+        self.padding += rhs.padding;
+        self.plaintext_sq = self.plaintext_sq.saturating_add(rhs.plaintext_sq);
+        self.ciphertext_sq = self.ciphertext_sq.saturating_add(rhs.ciphertext_sq);
+        self.product = self.product.saturating_add(rhs.product);
+    }
+}
+
+/// The framing of one direction summed over the connections.
+#[derive(Default, Serialize)]
+pub struct FramingSummary {
+    #[serde(flatten)]
+    pub stats: FramingStats,
+    /// bytes of the ciphertext per byte of the plaintext
+    pub expansion: f64,
+    /// the share of the frames whose length is the plaintext length plus the fixed overhead
+    pub exact_share: f64,
+    pub correlation: Option<f64>,
+}
+
+impl From<FramingStats> for FramingSummary {
+    fn from(stats: FramingStats) -> Self {
+        let mut summary = FramingSummary {
+            correlation: stats.correlation(),
+            stats,
+            ..Default::default()
+        };
+        if summary.stats.plaintext != 0 {
+            summary.expansion = summary.stats.ciphertext as f64 / summary.stats.plaintext as f64;
+        }
+        if summary.stats.frames != 0 {
+            let exact = summary.stats.frames - summary.stats.padded_frames;
+            summary.exact_share = exact as f64 / summary.stats.frames as f64;
+        }
+        summary
+    }
+}
+
+/// How much the noise framing of all connections leaks the lengths of the messages.
+#[derive(Default, Serialize)]
+pub struct FramingReport {
+    pub connections: usize,
+    pub incoming: FramingSummary,
+    pub outgoing: FramingSummary,
+}
+
+impl FromIterator<Connection> for FramingReport {
+    fn from_iter<T: IntoIterator<Item = Connection>>(iter: T) -> Self {
+        let (connections, incoming, outgoing) = iter
+            .into_iter()
+            .filter(|cn| cn.framing_in.frames + cn.framing_out.frames != 0)
+            .fold(
+                (0, FramingStats::default(), FramingStats::default()),
+                |(n, mut incoming, mut outgoing), cn| {
+                    incoming += &cn.framing_in;
+                    outgoing += &cn.framing_out;
+                    (n + 1, incoming, outgoing)
+                },
+            );
+        FramingReport {
+            connections,
+            incoming: incoming.into(),
+            outgoing: outgoing.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn framing_report() {
+    let mut unpadded = FramingStats::default();
+    for len in [10, 200, 31, 4000] {
+        unpadded.add(len + 18, len);
+    }
+    assert_eq!(unpadded.padded_frames, 0);
+    assert_eq!(unpadded.ciphertext - unpadded.plaintext, 4 * 18);
+    assert!((unpadded.correlation().unwrap() - 1.0).abs() < 1e-9);
+
+    // every frame is padded to the same size, the length tells nothing
+    let mut padded = FramingStats::default();
+    for len in [10, 200, 31, 4000] {
+        padded.add(4096 + 18, len);
+    }
+    assert_eq!(padded.padded_frames, 4);
+    assert_eq!(padded.padding, 4 * 4096 - (10 + 200 + 31 + 4000));
+    assert_eq!(padded.correlation(), None);
+
+    let mut total = unpadded.clone();
+    total += &padded;
+    let summary = FramingSummary::from(total);
+    assert_eq!(summary.stats.frames, 8);
+    assert_eq!(summary.exact_share, 0.5);
+    assert!(summary.expansion > 1.0);
+}
@@ -0,0 +1,93 @@
+use std::time::SystemTime;
+
+use parking_lot::Mutex;
+use serde::Serialize;
+
+/// The view of the live capture at a moment, the capture goes on, but the queries
+/// with the freeze see only the connections and the messages recorded before it.
+#[derive(Clone, Serialize)]
+pub struct Freeze {
+    pub id: u64,
+    pub time: SystemTime,
+    /// the ids of the visible connections are below this
+    pub connections: u64,
+    /// the ids of the visible messages are below this
+    pub messages: u64,
+}
+
+impl Freeze {
+    /// Where the query starts inside the frozen view. Going forward the start is kept,
+    /// going back it is moved to the last visible record.
+    pub fn start(&self, reverse: bool, present: bool, id: u64, end: u64) -> (bool, u64) {
+        if reverse && (!present || id >= end) {
+            (true, end.saturating_sub(1))
+        } else {
+            (present, id)
+        }
+    }
+}
+
+/// The freezes the clients hold, released explicitly.
+#[derive(Default)]
+pub struct Freezes {
+    inner: Mutex<(u64, Vec<Freeze>)>,
+}
+
+impl Freezes {
+    /// Only this many freezes are kept, the oldest is released first.
+    const CAPACITY: usize = 64;
+
+    pub fn freeze(&self, time: SystemTime, connections: u64, messages: u64) -> Freeze {
+        let mut lock = self.inner.lock();
+        let (next, freezes) = &mut *lock;
+        let freeze = Freeze {
+            id: *next,
+            time,
+            connections,
+            messages,
+        };
+        *next += 1;
+        if freezes.len() >= Self::CAPACITY {
+            freezes.remove(0);
+        }
+        freezes.push(freeze.clone());
+        freeze
+    }
+
+    pub fn get(&self, id: u64) -> Option<Freeze> {
+        self.inner.lock().1.iter().find(|f| f.id == id).cloned()
+    }
+
+    pub fn all(&self) -> Vec<Freeze> {
+        self.inner.lock().1.clone()
+    }
+
+    pub fn release(&self, id: u64) -> Option<Freeze> {
+        let mut lock = self.inner.lock();
+        let i = lock.1.iter().position(|f| f.id == id)?;
+        Some(lock.1.remove(i))
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn freeze() {
+    let freezes = Freezes::default();
+    let a = freezes.freeze(SystemTime::UNIX_EPOCH, 3, 10);
+    let b = freezes.freeze(SystemTime::UNIX_EPOCH, 4, 20);
+    assert_eq!((a.id, b.id), (0, 1));
+
+    assert_eq!(a.start(false, false, 0, a.messages), (false, 0));
+    assert_eq!(a.start(true, false, u64::MAX, a.messages), (true, 9));
+    assert_eq!(a.start(true, true, 15, a.messages), (true, 9));
+    assert_eq!(a.start(true, true, 5, a.messages), (true, 5));
+
+    assert!(freezes.release(a.id).is_some());
+    assert!(freezes.get(a.id).is_none());
+    assert_eq!(freezes.all().len(), 1);
+
+    for _ in 0..Freezes::CAPACITY {
+        freezes.freeze(SystemTime::UNIX_EPOCH, 0, 0);
+    }
+    assert!(freezes.get(b.id).is_none());
+}
@@ -0,0 +1,248 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env,
+    net::IpAddr,
+    path::Path,
+};
+
+#[cfg(feature = "geoip")]
+use maxminddb::{geoip2, Reader};
+use radiation::{Absorb, Emit};
+use serde::Serialize;
+
+use super::types::Connection;
+
+/// Location and the autonomous system of the peer address,
+/// the fields are empty if unknown.
+#[derive(Default, Clone, Debug, PartialEq, Eq, Absorb, Emit, Serialize)]
+pub struct PeerGeo {
+    /// ISO 3166 code
+    pub country: String,
+    pub city: String,
+    /// zero if unknown
+    pub asn: u32,
+    pub as_org: String,
+}
+
+impl PeerGeo {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// MaxMind databases to enrich the peer addresses, both are optional.
+#[cfg(feature = "geoip")]
+#[derive(Default)]
+pub struct GeoIp {
+    // city or country database
+    location: Option<Reader<Vec<u8>>>,
+    asn: Option<Reader<Vec<u8>>>,
+}
+
+/// Built without the `geoip` feature, the addresses are not enriched.
+#[cfg(not(feature = "geoip"))]
+#[derive(Default)]
+pub struct GeoIp {}
+
+impl GeoIp {
+    pub fn from_env() -> Self {
+        let location = env::var("GEOIP_DB").ok();
+        let asn = env::var("GEOIP_ASN_DB").ok();
+        Self::open(
+            location.as_ref().map(Path::new),
+            asn.as_ref().map(Path::new),
+        )
+    }
+}
+
+#[cfg(not(feature = "geoip"))]
+impl GeoIp {
+    pub fn open(location: Option<&Path>, asn: Option<&Path>) -> Self {
+        if location.is_some() || asn.is_some() {
+            log::error!("cannot open geoip database, built without the `geoip` feature");
+        }
+        GeoIp {}
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        false
+    }
+
+    pub fn lookup(&self, _: IpAddr) -> PeerGeo {
+        PeerGeo::default()
+    }
+}
+
+#[cfg(feature = "geoip")]
+impl GeoIp {
+    pub fn open(location: Option<&Path>, asn: Option<&Path>) -> Self {
+        let open = |path: Option<&Path>| {
+            let path = path?;
+            match Reader::open_readfile(path) {
+                Ok(reader) => Some(reader),
+                Err(err) => {
+                    log::error!("cannot open geoip database {}: {err}", path.display());
+                    None
+                }
+            }
+        };
+        GeoIp {
+            location: open(location),
+            asn: open(asn),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.location.is_some() || self.asn.is_some()
+    }
+
+    /// Private and unknown addresses give the empty value.
+    pub fn lookup(&self, ip: IpAddr) -> PeerGeo {
+        let mut geo = PeerGeo::default();
+        if let Some(city) = self
+            .location
+            .as_ref()
+            .and_then(|r| r.lookup::<geoip2::City>(ip).ok())
+        {
+            if let Some(code) = city.country.and_then(|c| c.iso_code) {
+                geo.country = code.to_owned();
+            }
+            if let Some(name) = city
+                .city
+                .and_then(|c| c.names)
+                .and_then(|names| names.get("en").copied())
+            {
+                geo.city = name.to_owned();
+            }
+        }
+        if let Some(asn) = self
+            .asn
+            .as_ref()
+            .and_then(|r| r.lookup::<geoip2::Asn>(ip).ok())
+        {
+            geo.asn = asn.autonomous_system_number.unwrap_or_default();
+            geo.as_org = asn
+                .autonomous_system_organization
+                .unwrap_or_default()
+                .to_owned();
+        }
+        geo
+    }
+}
+
+/// Distinct peer addresses by country and by autonomous system.
+#[derive(Default, Serialize)]
+pub struct GeoReport {
+    pub peers: usize,
+    /// the addresses without the data, private or not in the database
+    pub unknown: usize,
+    pub countries: BTreeMap<String, usize>,
+    /// the largest first
+    pub autonomous_systems: Vec<AsShare>,
+}
+
+#[derive(Serialize)]
+pub struct AsShare {
+    pub asn: u32,
+    pub org: String,
+    pub peers: usize,
+    pub share: f64,
+    /// the autonomous system holds at least half of the known peers
+    pub clustered: bool,
+}
+
+impl GeoReport {
+    const CLUSTERED_SHARE: f64 = 0.5;
+}
+
+impl FromIterator<Connection> for GeoReport {
+    fn from_iter<T: IntoIterator<Item = Connection>>(iter: T) -> Self {
+        let mut seen = BTreeSet::new();
+        let mut report = GeoReport::default();
+        let mut systems = BTreeMap::<u32, (String, usize)>::new();
+        for cn in iter {
+            if !seen.insert(cn.info.addr.ip()) {
+                continue;
+            }
+            report.peers += 1;
+            if cn.geo.is_empty() {
+                report.unknown += 1;
+                continue;
+            }
+            if !cn.geo.country.is_empty() {
+                *report.countries.entry(cn.geo.country).or_default() += 1;
+            }
+            if cn.geo.asn != 0 {
+                let entry = systems.entry(cn.geo.asn).or_default();
+                entry.0 = cn.geo.as_org;
+                entry.1 += 1;
+            }
+        }
+        let known = (report.peers - report.unknown) as f64;
+        report.autonomous_systems = systems
+            .into_iter()
+            .map(|(asn, (org, peers))| {
+                let share = peers as f64 / known;
+                AsShare {
+                    asn,
+                    org,
+                    peers,
+                    share,
+                    clustered: peers > 1 && share >= Self::CLUSTERED_SHARE,
+                }
+            })
+            .collect();
+        report
+            .autonomous_systems
+            .sort_by(|a, b| b.peers.cmp(&a.peers).then(a.asn.cmp(&b.asn)));
+        report
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn geo_report() {
+    use std::time::SystemTime;
+
+    use crate::event::ConnectionInfo;
+
+    let cn = |addr: &str, country: &str, asn: u32| {
+        let info = ConnectionInfo {
+            addr: addr.parse().unwrap(),
+            pid: 1,
+            fd: 10,
+        };
+        Connection {
+            geo: PeerGeo {
+                country: country.to_owned(),
+                city: String::new(),
+                asn,
+                as_org: if asn == 0 { "" } else { "Hosting" }.to_owned(),
+            },
+            ..Connection::new(info, false, "node".to_owned(), SystemTime::UNIX_EPOCH)
+        }
+    };
+
+    let report = [
+        cn("1.2.3.4:8302", "DE", 24940),
+        // the same peer reconnected
+        cn("1.2.3.4:8302", "DE", 24940),
+        cn("1.2.3.5:8302", "FI", 24940),
+        cn("5.6.7.8:8302", "US", 16509),
+        cn("10.0.0.1:8302", "", 0),
+    ]
+    .into_iter()
+    .collect::<GeoReport>();
+    assert_eq!(report.peers, 4);
+    assert_eq!(report.unknown, 1);
+    assert_eq!(report.countries.get("DE"), Some(&1));
+    assert_eq!(report.autonomous_systems.len(), 2);
+    let largest = &report.autonomous_systems[0];
+    assert_eq!((largest.asn, largest.peers), (24940, 2));
+    assert!(largest.clustered);
+    assert!(!report.autonomous_systems[1].clustered);
+
+    assert!(GeoIp::default()
+        .lookup("1.2.3.4".parse().unwrap())
+        .is_empty());
+}
@@ -1,6 +1,7 @@
-use std::net::SocketAddr;
+use std::{net::SocketAddr, time::SystemTime};
 
 use radiation::{Absorb, Emit};
+use libp2p_core::PeerId;
 
 use crate::{decode::MessageType, custom_coding};
 use super::types::{ConnectionId, MessageId, StreamFullId, StreamKind};
@@ -37,6 +38,34 @@ pub struct MessageKindIdx {
     pub id: MessageId,
 }
 
+#[derive(Absorb, Emit)]
+pub struct PeerIdIdx {
+    #[custom_absorb(custom_coding::peer_id_absorb)]
+    #[custom_emit(custom_coding::peer_id_emit)]
+    pub peer_id: PeerId,
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub timestamp: SystemTime,
+    pub connection_id: ConnectionId,
+    #[custom_absorb(custom_coding::addr_absorb)]
+    #[custom_emit(custom_coding::addr_emit)]
+    pub addr: SocketAddr,
+}
+
+#[derive(Absorb, Emit)]
+pub struct AddrPeerIdIdx {
+    #[custom_absorb(custom_coding::addr_absorb)]
+    #[custom_emit(custom_coding::addr_emit)]
+    pub addr: SocketAddr,
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub timestamp: SystemTime,
+    pub connection_id: ConnectionId,
+    #[custom_absorb(custom_coding::peer_id_absorb)]
+    #[custom_emit(custom_coding::peer_id_emit)]
+    pub peer_id: PeerId,
+}
+
 #[derive(Absorb, Emit)]
 pub struct LedgerHashIdx {
     pub hash: LedgerHash,
@@ -0,0 +1,250 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::SocketAddr,
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+
+use super::types::{ConnectionId, StreamId};
+
+/// The message of the kademlia stream, each request opens a new stream.
+pub struct KadMessage {
+    pub time: SystemTime,
+    pub connection_id: ConnectionId,
+    pub stream_id: StreamId,
+    pub incoming: bool,
+    /// the peers the message tells about, the peer id and its addresses
+    pub closer_peers: Vec<(String, Vec<String>)>,
+}
+
+/// How the DHT peers answer the queries of the node.
+#[derive(Serialize)]
+pub struct KademliaReport {
+    pub since: SystemTime,
+    pub until: SystemTime,
+    /// sent by the node within the range
+    pub queries: u64,
+    pub responded: u64,
+    /// no response within `TIMEOUT`
+    pub timed_out: u64,
+    /// the range ends too soon to tell
+    pub pending: u64,
+    /// the least responsive peers first
+    pub peers: Vec<PeerResponsiveness>,
+}
+
+#[derive(Serialize)]
+pub struct PeerResponsiveness {
+    /// `None` if the connection is missing in the database
+    pub addr: Option<SocketAddr>,
+    pub queries: u64,
+    pub responded: u64,
+    pub timed_out: u64,
+    /// responded of the queries with a known outcome, `None` if there are no such queries
+    pub success_rate: Option<f64>,
+    pub median_latency_secs: Option<f64>,
+    pub max_latency_secs: Option<f64>,
+}
+
+/// The peer announced in the kademlia responses, the dataset of the DHT view of the node.
+#[derive(Serialize)]
+pub struct LearnedPeer {
+    pub peer_id: String,
+    /// all the addresses announced for the peer
+    pub addrs: BTreeSet<String>,
+    pub first_learned: SystemTime,
+    pub last_learned: SystemTime,
+    /// how many responses announced the peer
+    pub announcements: u64,
+    /// the remote addresses of the connections the responses came from
+    pub sources: BTreeSet<SocketAddr>,
+}
+
+struct Query {
+    connection_id: ConnectionId,
+    time: SystemTime,
+    response: Option<SystemTime>,
+}
+
+impl KademliaReport {
+    /// libp2p gives up waiting for the response after this time.
+    pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// The `messages` must be ordered by time and include `TIMEOUT` after `until`,
+    /// `addr` looks up the remote address of the connection.
+    pub fn build<I, F>(messages: I, since: SystemTime, until: SystemTime, mut addr: F) -> Self
+    where
+        I: IntoIterator<Item = KadMessage>,
+        F: FnMut(ConnectionId) -> Option<SocketAddr>,
+    {
+        // the stream is the query of the node if its first message is outgoing
+        let mut streams = BTreeMap::<(ConnectionId, StreamId), Option<Query>>::new();
+        let mut end = since;
+        for msg in messages {
+            end = end.max(msg.time);
+            let key = (msg.connection_id, msg.stream_id);
+            match streams.get_mut(&key) {
+                None => {
+                    let query =
+                        (!msg.incoming && (since..until).contains(&msg.time)).then_some(Query {
+                            connection_id: msg.connection_id,
+                            time: msg.time,
+                            response: None,
+                        });
+                    streams.insert(key, query);
+                }
+                Some(Some(query)) if msg.incoming && query.response.is_none() => {
+                    query.response = Some(msg.time);
+                }
+                Some(_) => {}
+            }
+        }
+
+        let mut report = KademliaReport {
+            since,
+            until,
+            queries: 0,
+            responded: 0,
+            timed_out: 0,
+            pending: 0,
+            peers: vec![],
+        };
+        let mut peers = BTreeMap::<ConnectionId, (u64, u64, u64, Vec<Duration>)>::new();
+        for query in streams.into_values().flatten() {
+            report.queries += 1;
+            let peer = peers.entry(query.connection_id).or_default();
+            peer.0 += 1;
+            let latency = query
+                .response
+                .and_then(|response| response.duration_since(query.time).ok())
+                .filter(|latency| *latency <= Self::TIMEOUT);
+            if let Some(latency) = latency {
+                report.responded += 1;
+                peer.1 += 1;
+                peer.3.push(latency);
+            } else if end.duration_since(query.time).unwrap_or_default() > Self::TIMEOUT {
+                report.timed_out += 1;
+                peer.2 += 1;
+            } else {
+                report.pending += 1;
+            }
+        }
+
+        // the same peer might be reached through several connections
+        let mut by_addr = BTreeMap::<Option<SocketAddr>, (u64, u64, u64, Vec<Duration>)>::new();
+        for (connection_id, (queries, responded, timed_out, latencies)) in peers {
+            let peer = by_addr.entry(addr(connection_id)).or_default();
+            peer.0 += queries;
+            peer.1 += responded;
+            peer.2 += timed_out;
+            peer.3.extend(latencies);
+        }
+        report.peers = by_addr
+            .into_iter()
+            .map(|(addr, (queries, responded, timed_out, mut latencies))| {
+                latencies.sort();
+                let known = responded + timed_out;
+                PeerResponsiveness {
+                    addr,
+                    queries,
+                    responded,
+                    timed_out,
+                    success_rate: (known != 0).then(|| responded as f64 / known as f64),
+                    median_latency_secs: latencies
+                        .get(latencies.len() / 2)
+                        .map(Duration::as_secs_f64),
+                    max_latency_secs: latencies.last().map(Duration::as_secs_f64),
+                }
+            })
+            .collect();
+        report.peers.sort_by(|a, b| {
+            let rate = |p: &PeerResponsiveness| p.success_rate.unwrap_or(1.0);
+            rate(a).total_cmp(&rate(b)).then(b.queries.cmp(&a.queries))
+        });
+
+        report
+    }
+}
+
+impl LearnedPeer {
+    /// The peers announced by the incoming messages, the first learned first.
+    pub fn collect<I, F>(messages: I, mut addr: F) -> Vec<Self>
+    where
+        I: IntoIterator<Item = KadMessage>,
+        F: FnMut(ConnectionId) -> Option<SocketAddr>,
+    {
+        let mut peers = BTreeMap::<String, LearnedPeer>::new();
+        for msg in messages.into_iter().filter(|msg| msg.incoming) {
+            let source = addr(msg.connection_id);
+            for (peer_id, addrs) in msg.closer_peers {
+                let peer = peers.entry(peer_id.clone()).or_insert_with(|| LearnedPeer {
+                    peer_id,
+                    addrs: BTreeSet::new(),
+                    first_learned: msg.time,
+                    last_learned: msg.time,
+                    announcements: 0,
+                    sources: BTreeSet::new(),
+                });
+                peer.addrs.extend(addrs);
+                peer.first_learned = peer.first_learned.min(msg.time);
+                peer.last_learned = peer.last_learned.max(msg.time);
+                peer.announcements += 1;
+                peer.sources.extend(source);
+            }
+        }
+        let mut peers = peers.into_values().collect::<Vec<_>>();
+        peers.sort_by(|a, b| a.first_learned.cmp(&b.first_learned));
+        peers
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn kademlia_outcomes() {
+    let since = SystemTime::UNIX_EPOCH + Duration::from_secs(1_675_166_400);
+    let until = since + Duration::from_secs(60);
+    let m = |secs: u64, cn: u64, stream: u64, incoming| KadMessage {
+        time: since + Duration::from_secs(secs),
+        connection_id: ConnectionId(cn),
+        stream_id: StreamId::Forward(stream),
+        incoming,
+        closer_peers: vec![],
+    };
+    let announce = |mut msg: KadMessage, addr: &str| {
+        msg.closer_peers = vec![("12D3KooW".to_owned(), vec![addr.to_owned()])];
+        msg
+    };
+    let a = "1.2.3.4:8302".parse().unwrap();
+    let b = "1.2.3.5:8302".parse().unwrap();
+    let addr = |id: ConnectionId| Some(if id.0 == 1 { a } else { b });
+    let messages = || {
+        [
+            m(1, 1, 1, false),
+            m(2, 1, 1, true),
+            // the query of the peer, not counted
+            m(3, 1, 2, true),
+            m(3, 1, 2, false),
+            m(5, 2, 1, false),
+            m(30, 2, 2, false),
+            announce(m(33, 2, 2, true), "/ip4/1.2.3.6/tcp/8302"),
+            // too late to tell
+            m(55, 2, 3, false),
+            announce(m(64, 2, 4, true), "/ip4/1.2.3.7/tcp/8302"),
+        ]
+    };
+    let report = KademliaReport::build(messages(), since, until, addr);
+    assert_eq!((report.queries, report.responded), (4, 2));
+    assert_eq!((report.timed_out, report.pending), (1, 1));
+    let worst = &report.peers[0];
+    assert_eq!((worst.addr, worst.success_rate), (Some(b), Some(0.5)));
+    assert_eq!(worst.max_latency_secs, Some(3.0));
+    assert_eq!(report.peers[1].success_rate, Some(1.0));
+
+    let learned = LearnedPeer::collect(messages(), addr);
+    assert_eq!(learned.len(), 1);
+    assert_eq!(learned[0].addrs.len(), 2);
+    assert_eq!(learned[0].first_learned, since + Duration::from_secs(33));
+    assert_eq!(learned[0].announcements, 2);
+    assert_eq!(learned[0].sources.iter().collect::<Vec<_>>(), [&b]);
+}
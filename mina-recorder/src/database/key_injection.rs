@@ -0,0 +1,153 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::SystemTime,
+};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use super::types::ConnectionId;
+
+/// The keys of the connection recovered after the fact, e.g. from the debug output of the node.
+/// The recorder decrypts the raw chunks of the connection again with them.
+#[derive(Clone, Serialize)]
+pub struct KeyInjection {
+    pub id: u64,
+    pub connection_id: ConnectionId,
+    /// the curve25519 secret keys, static or ephemeral, not exposed
+    #[serde(skip)]
+    pub keys: Vec<[u8; 32]>,
+    /// the chain id of the private network, derived from the alias if not given
+    pub chain_id: Option<String>,
+    pub requested: SystemTime,
+    pub status: InjectionStatus,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "snake_case", tag = "state")]
+pub enum InjectionStatus {
+    Pending,
+    Running,
+    /// the connection decrypted again is stored as a new connection,
+    /// the original one is superseded by it
+    Done {
+        connection_id: ConnectionId,
+        chunks: u64,
+        messages: u64,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+/// The recent key injections, the recorder takes the pending ones.
+#[derive(Default)]
+pub struct KeyInjections {
+    pending: AtomicBool,
+    inner: Mutex<(u64, Vec<KeyInjection>)>,
+}
+
+impl KeyInjections {
+    /// Only this many recent injections are kept.
+    const CAPACITY: usize = 256;
+
+    /// Parse the hex of the 32 bytes secret key.
+    pub fn parse_key(s: &str) -> Result<[u8; 32], String> {
+        let bytes = hex::decode(s.trim()).map_err(|err| format!("{s}: {err}"))?;
+        bytes
+            .try_into()
+            .map_err(|bytes: Vec<u8>| format!("{s}: {} bytes, must be 32", bytes.len()))
+    }
+
+    pub fn request(
+        &self,
+        connection_id: ConnectionId,
+        keys: Vec<[u8; 32]>,
+        chain_id: Option<String>,
+        time: SystemTime,
+    ) -> KeyInjection {
+        let mut lock = self.inner.lock();
+        let (next, injections) = &mut *lock;
+        let injection = KeyInjection {
+            id: *next,
+            connection_id,
+            keys,
+            chain_id,
+            requested: time,
+            status: InjectionStatus::Pending,
+        };
+        *next += 1;
+        if injections.len() >= Self::CAPACITY {
+            if let Some(i) = injections
+                .iter()
+                .position(|i| !matches!(i.status, InjectionStatus::Pending))
+            {
+                injections.remove(i);
+            }
+        }
+        injections.push(injection.clone());
+        self.pending.store(true, Ordering::SeqCst);
+        injection
+    }
+
+    /// The pending injections become running, cheap if there are none.
+    pub fn take_pending(&self) -> Vec<KeyInjection> {
+        if !self.pending.swap(false, Ordering::SeqCst) {
+            return vec![];
+        }
+        let mut lock = self.inner.lock();
+        lock.1
+            .iter_mut()
+            .filter(|i| matches!(i.status, InjectionStatus::Pending))
+            .map(|i| {
+                i.status = InjectionStatus::Running;
+                i.clone()
+            })
+            .collect()
+    }
+
+    pub fn finish(&self, id: u64, status: InjectionStatus) {
+        let mut lock = self.inner.lock();
+        if let Some(injection) = lock.1.iter_mut().find(|i| i.id == id) {
+            injection.status = status;
+        }
+    }
+
+    /// The injections of the connection, the oldest first.
+    pub fn of(&self, connection_id: ConnectionId) -> Vec<KeyInjection> {
+        let lock = self.inner.lock();
+        lock.1
+            .iter()
+            .filter(|i| i.connection_id == connection_id)
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn key_injections() {
+    let key = "d1f3bca173136dd555dd97262336ce644a76ec31d521d2befe87caec8678c1a7";
+    let key = KeyInjections::parse_key(key).unwrap();
+    assert!(KeyInjections::parse_key("d1f3").is_err());
+    assert!(KeyInjections::parse_key("xyz").is_err());
+
+    let injections = KeyInjections::default();
+    assert!(injections.take_pending().is_empty());
+    let injection = injections.request(ConnectionId(3), vec![key], None, SystemTime::UNIX_EPOCH);
+    let taken = injections.take_pending();
+    assert_eq!(taken.len(), 1);
+    assert_eq!(taken[0].keys, [key]);
+    assert!(injections.take_pending().is_empty());
+
+    let status = InjectionStatus::Done {
+        connection_id: ConnectionId(7),
+        chunks: 10,
+        messages: 4,
+    };
+    injections.finish(injection.id, status);
+    let v = serde_json::to_value(&injections.of(ConnectionId(3))[0]).unwrap();
+    assert_eq!(v["status"]["state"], "done");
+    assert!(v.get("keys").is_none());
+    assert!(injections.of(ConnectionId(4)).is_empty());
+}
@@ -0,0 +1,247 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap},
+    net::SocketAddr,
+    time::SystemTime,
+};
+
+use serde::Serialize;
+
+use super::types::{ConnectionId, Message, StreamId, StreamKind};
+
+/// The heaviest messages, streams and connections of the range, by the bytes of the messages.
+#[derive(Serialize)]
+pub struct Leaderboard {
+    pub since: SystemTime,
+    pub until: SystemTime,
+    pub messages: Vec<LargeMessage>,
+    pub streams: Vec<LargeStream>,
+    pub connections: Vec<LargeConnection>,
+}
+
+#[derive(Serialize)]
+pub struct LargeMessage {
+    pub id: u64,
+    pub connection_id: ConnectionId,
+    pub stream_id: String,
+    pub stream_kind: StreamKind,
+    pub incoming: bool,
+    pub timestamp: SystemTime,
+    pub size: u32,
+    pub brief: String,
+    /// the message in the browser
+    pub link: String,
+}
+
+#[derive(Serialize)]
+pub struct LargeStream {
+    pub connection_id: ConnectionId,
+    pub stream_id: String,
+    pub stream_kind: StreamKind,
+    pub messages: u64,
+    pub bytes: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    /// the messages of the stream in the browser
+    pub link: String,
+}
+
+#[derive(Serialize)]
+pub struct LargeConnection {
+    pub connection_id: ConnectionId,
+    /// `None` if the connection is missing in the database
+    pub addr: Option<SocketAddr>,
+    pub messages: u64,
+    pub bytes: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    /// the messages of the connection in the browser
+    pub link: String,
+}
+
+#[derive(Default)]
+struct Counter {
+    messages: u64,
+    bytes_in: u64,
+    bytes_out: u64,
+}
+
+impl Counter {
+    fn count(&mut self, msg: &Message) {
+        self.messages += 1;
+        if msg.incoming {
+            self.bytes_in += msg.size as u64;
+        } else {
+            self.bytes_out += msg.size as u64;
+        }
+    }
+
+    fn bytes(&self) -> u64 {
+        self.bytes_in + self.bytes_out
+    }
+}
+
+impl Leaderboard {
+    pub const MAX_LIMIT: usize = 1000;
+
+    /// The `messages` must be within the range, `addr` looks up the remote address.
+    pub fn build<I, F>(
+        messages: I,
+        since: SystemTime,
+        until: SystemTime,
+        limit: usize,
+        mut addr: F,
+    ) -> Self
+    where
+        I: IntoIterator<Item = (u64, Message)>,
+        F: FnMut(ConnectionId) -> Option<SocketAddr>,
+    {
+        let limit = limit.min(Self::MAX_LIMIT);
+
+        // the smallest of the largest on top, ties broken by the id
+        let mut largest = BinaryHeap::<Reverse<(u32, Reverse<u64>)>>::new();
+        let mut selected = BTreeMap::<u64, Message>::new();
+        let mut streams = BTreeMap::<(ConnectionId, StreamId), (StreamKind, Counter)>::new();
+        let mut connections = BTreeMap::<ConnectionId, Counter>::new();
+        for (id, msg) in messages {
+            streams
+                .entry((msg.connection_id, msg.stream_id))
+                .or_insert_with(|| (msg.stream_kind, Counter::default()))
+                .1
+                .count(&msg);
+            connections
+                .entry(msg.connection_id)
+                .or_default()
+                .count(&msg);
+
+            if limit == 0 {
+                continue;
+            }
+            largest.push(Reverse((msg.size, Reverse(id))));
+            selected.insert(id, msg);
+            if largest.len() > limit {
+                if let Some(Reverse((_, Reverse(id)))) = largest.pop() {
+                    selected.remove(&id);
+                }
+            }
+        }
+
+        let mut messages = selected
+            .into_iter()
+            .map(|(id, msg)| LargeMessage {
+                id,
+                connection_id: msg.connection_id,
+                stream_id: msg.stream_id.to_string(),
+                stream_kind: msg.stream_kind,
+                incoming: msg.incoming,
+                timestamp: msg.timestamp,
+                size: msg.size,
+                brief: msg.brief,
+                link: format!("/message/{id}"),
+            })
+            .collect::<Vec<_>>();
+        messages.sort_by(|a, b| b.size.cmp(&a.size).then(a.id.cmp(&b.id)));
+
+        let mut streams = streams
+            .into_iter()
+            .map(
+                |((connection_id, stream_id), (stream_kind, c))| LargeStream {
+                    connection_id,
+                    stream_id: stream_id.to_string(),
+                    stream_kind,
+                    messages: c.messages,
+                    bytes: c.bytes(),
+                    bytes_in: c.bytes_in,
+                    bytes_out: c.bytes_out,
+                    link: format!(
+                        "/messages?connection_id={}&stream_id={stream_id}",
+                        connection_id.0
+                    ),
+                },
+            )
+            .collect::<Vec<_>>();
+        streams.sort_by(|a, b| b.bytes.cmp(&a.bytes));
+        streams.truncate(limit);
+
+        let mut connections = connections.into_iter().collect::<Vec<_>>();
+        connections.sort_by(|(_, a), (_, b)| b.bytes().cmp(&a.bytes()));
+        connections.truncate(limit);
+        let connections = connections
+            .into_iter()
+            .map(|(connection_id, c)| LargeConnection {
+                connection_id,
+                addr: addr(connection_id),
+                messages: c.messages,
+                bytes: c.bytes(),
+                bytes_in: c.bytes_in,
+                bytes_out: c.bytes_out,
+                link: format!("/messages?connection_id={}", connection_id.0),
+            })
+            .collect();
+
+        Leaderboard {
+            since,
+            until,
+            messages,
+            streams,
+            connections,
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn leaderboard() {
+    use std::time::Duration;
+
+    let since = SystemTime::UNIX_EPOCH + Duration::from_secs(1_675_166_400);
+    let until = since + Duration::from_secs(60);
+    let msg = |cn: u64, stream: u64, incoming, size| Message {
+        connection_id: ConnectionId(cn),
+        stream_id: StreamId::Forward(stream),
+        stream_kind: StreamKind::Meshsub,
+        incoming,
+        timestamp: since,
+        offset: 0,
+        size,
+        brief: String::new(),
+        seq: 0,
+        unredacted: false,
+        decoder_version: 0,
+        transfer_ns: 0,
+        chunks: 0,
+    };
+    let messages = [
+        msg(1, 1, true, 100),
+        msg(1, 1, false, 500),
+        msg(1, 3, true, 200),
+        msg(2, 1, true, 700),
+        msg(2, 1, true, 200),
+    ];
+    let board = Leaderboard::build(
+        messages.into_iter().enumerate().map(|(i, m)| (i as u64, m)),
+        since,
+        until,
+        2,
+        |_| None,
+    );
+    let sizes = board
+        .messages
+        .iter()
+        .map(|m| (m.id, m.size))
+        .collect::<Vec<_>>();
+    assert_eq!(sizes, [(3, 700), (1, 500)]);
+    assert_eq!(board.messages[0].link, "/message/3");
+    assert_eq!((board.streams[0].bytes, board.streams[1].bytes), (900, 600));
+    assert_eq!(board.streams.len(), 2);
+    let top = &board.connections[0];
+    assert_eq!(
+        (top.connection_id, top.bytes, top.bytes_in),
+        (ConnectionId(2), 900, 900)
+    );
+    assert_eq!(board.connections[1].bytes_out, 500);
+    assert_eq!(
+        board.streams[1].link,
+        "/messages?connection_id=1&stream_id=forward_00000001"
+    );
+}
@@ -0,0 +1,72 @@
+use std::{fs, io, path::Path, time::SystemTime};
+
+use serde::{Serialize, Deserialize};
+
+use super::redaction::Redaction;
+
+/// Describes the capture, stored as `manifest.json` next to the database.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: String,
+    pub created: SystemTime,
+    /// the payloads are redacted at capture time
+    pub capture_redaction: Redaction,
+    /// the payloads are redacted when exported, not stored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub export_redaction: Option<Redaction>,
+}
+
+impl Manifest {
+    const FILENAME: &'static str = "manifest.json";
+
+    /// The manifest of the new capture, not stored anywhere yet.
+    pub fn new(capture_redaction: Redaction) -> Self {
+        Manifest {
+            version: env!("GIT_HASH").trim().to_owned(),
+            created: SystemTime::now(),
+            capture_redaction,
+            export_redaction: None,
+        }
+    }
+
+    /// The redaction is fixed when the capture is created,
+    /// if the requested one is different, the one from the manifest is used.
+    pub fn load_or_create<P>(path: P, capture_redaction: Redaction) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        fs::create_dir_all(path.as_ref())?;
+        let manifest = Self::load(&path, capture_redaction)?;
+        fs::write(
+            path.as_ref().join(Self::FILENAME),
+            serde_json::to_vec_pretty(&manifest)?,
+        )?;
+
+        Ok(manifest)
+    }
+
+    /// Same, but nothing is written, the manifest of the capture without one is not stored.
+    pub fn load<P>(path: P, capture_redaction: Redaction) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref().join(Self::FILENAME);
+        let manifest = match fs::read(&path) {
+            Ok(bytes) => {
+                let manifest = serde_json::from_slice::<Self>(&bytes)?;
+                if manifest.capture_redaction != capture_redaction {
+                    log::error!(
+                        "the capture is created with redaction {}, requested {capture_redaction}, \
+                        use another database path to change redaction",
+                        manifest.capture_redaction,
+                    );
+                }
+                manifest
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Self::new(capture_redaction),
+            Err(err) => return Err(err),
+        };
+
+        Ok(manifest)
+    }
+}
@@ -1,21 +1,116 @@
 mod types;
 pub use self::types::{
     StreamKind, StreamId, ConnectionId, ConnectionStats, FullMessage, CapnpEventWithMetadata,
-    CapnpEventWithMetadataKey,
+    CapnpEventWithMetadataKey, MessageId, Session, NodeLogLine, NodeStatus, Connection, Message,
+    Layer, LayerStats, SubscriptionChange, Negotiation, NegotiationToken, NegotiationTokenKind,
+    NoiseHandshake, DecoderVersions, DecoderVersionStats, RedecodeSummary, TimeBeacon,
+    HealthSample, MessageTiming, GossipFirstSeen, GossipDuplicate, AgentVersionSighting,
+    Verification, StreamEnd, StreamEndKind,
 };
 
 mod rocksdb;
 pub use self::rocksdb::{DbFacade, DbGroup, DbStream, DbStrace};
 
 mod params;
-pub use self::params::Params;
+pub use self::params::{Params, DecoderFilter};
 
 mod index;
 pub use self::index::LedgerHash;
 
 mod sorted_intersect;
 
+mod redaction;
+pub use self::redaction::Redaction;
+
+mod trigger;
+pub use self::trigger::{CaptureTriggers, TriggerConfig, TriggerRule, Fired};
+
+mod watchdog;
+pub use self::watchdog::{Watchdog, WatchdogConfig, Stall, DecoderProgress};
+
+mod pipelines;
+pub use self::pipelines::{Pipeline, Pipelines};
+
+mod key_injection;
+pub use self::key_injection::{KeyInjections, KeyInjection, InjectionStatus};
+
+mod freeze;
+pub use self::freeze::{Freezes, Freeze};
+
+mod peer_consistency;
+pub use self::peer_consistency::{ReportedPeer, ReportedPeers, PeerConsistency, WirePeer};
+
+mod anomaly;
+pub use self::anomaly::{AnomalyDetector, DetectorConfig, Anomaly};
+
+mod decoder_error;
+pub use self::decoder_error::DecoderError;
+
+mod geoip;
+pub use self::geoip::{GeoIp, PeerGeo, GeoReport, AsShare};
+
+mod framing;
+pub use self::framing::{FramingStats, FramingSummary, FramingReport};
+
+mod agent_versions;
+pub use self::agent_versions::{AgentVersionReport, AgentVersionShare};
+
+mod nat;
+pub use self::nat::{NatMapping, NatReport, ExternalAddr};
+
+mod ports;
+pub use self::ports::{PortReport, PidPorts, RapidReuse, PortUse};
+
+mod cache;
+pub use self::cache::{HttpCache, LruCache};
+
+mod manifest;
+pub use self::manifest::Manifest;
+
+mod tuning;
+pub use self::tuning::{DbTuning, DbStatistics, CompactionStyle};
+
 mod core;
 pub use self::core::{DbError, DbCore, RandomnessDatabase};
 
+mod activity;
+pub use self::activity::{ActivityReport, ActivityClass, ConnectionActivity};
+
+mod peer_directory;
+pub use self::peer_directory::{PeerDirectory, PeerRecord};
+
+mod churn;
+pub use self::churn::{
+    ChurnReport, ChurnHour, PeerChurn, HandshakeFailure, PartialHandshake, PartialHandshakes,
+};
+
+mod duplication;
+pub use self::duplication::{DuplicationHeatmap, PeerDuplication, Delivery};
+
+mod leaderboard;
+pub use self::leaderboard::{Leaderboard, LargeMessage, LargeStream, LargeConnection};
+
+mod kademlia;
+pub use self::kademlia::{KademliaReport, PeerResponsiveness, LearnedPeer, KadMessage};
+
+mod validation;
+pub use self::validation::{ValidationReport, TopicValidation, Publish};
+
+mod baseline;
+pub use self::baseline::{
+    Baseline, BaselineDeviations, BaselineError, Deviation, PeerProfile, TopicProfile,
+};
+
+mod subscriptions;
+pub use self::subscriptions::{SubscriptionPeer, SubscriptionTimeline};
+
+mod reader;
+pub use self::reader::{CaptureReader, ConnectionFilter, MessageFilter};
+
+mod compare;
+pub use self::compare::{CaptureSummary, CaptureDiff};
+
+mod captures;
+pub use self::captures::{Captures, MountedCapture, CaptureMessage};
+
 pub type DbResult<T> = Result<T, DbError>;
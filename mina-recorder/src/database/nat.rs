@@ -0,0 +1,209 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::{IpAddr, SocketAddr},
+};
+
+use multiaddr::{Multiaddr, Protocol};
+use serde::Serialize;
+
+use crate::event::canonical_addr;
+
+use super::types::Connection;
+
+/// The address translation in effect for the connection, the local address of the socket
+/// versus the address of the node as the peer observes it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NatMapping {
+    pub local: Option<SocketAddr>,
+    pub external: Option<SocketAddr>,
+    /// `None` if either address is unknown
+    pub translated: Option<bool>,
+}
+
+impl NatMapping {
+    pub fn of(cn: &Connection) -> Self {
+        let local = cn.local_addr.parse().ok().map(canonical_addr);
+        let external = socket_addr(&cn.observed_addr);
+        let translated = local
+            .zip(external)
+            .map(|(local, external)| local.ip() != external.ip());
+        NatMapping {
+            local,
+            external,
+            translated,
+        }
+    }
+}
+
+/// The tcp address of the multiaddr, `None` if it is not ip and tcp.
+fn socket_addr(s: &str) -> Option<SocketAddr> {
+    let addr = s.parse::<Multiaddr>().ok()?;
+    let mut ip = None;
+    for protocol in addr.iter() {
+        match protocol {
+            Protocol::Ip4(v) => ip = Some(IpAddr::V4(v)),
+            Protocol::Ip6(v) => ip = Some(IpAddr::V6(v)),
+            Protocol::Tcp(port) => return ip.map(|ip| canonical_addr(SocketAddr::new(ip, port))),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// How the node is seen from outside, the answer to "why does nobody dial me back".
+#[derive(Default, Serialize)]
+pub struct NatReport {
+    /// the local ip addresses of the sockets, by the number of connections
+    pub local_ips: BTreeMap<IpAddr, u64>,
+    /// the addresses of the node the peers observe, the most reported first
+    pub external: Vec<ExternalAddr>,
+    /// the local addresses the peers dialed, by the number of incoming connections
+    pub dialed: BTreeMap<SocketAddr, u64>,
+    pub incoming: u64,
+    pub outgoing: u64,
+    /// the peers observe the ip the node has not, `None` if no peer told its observation
+    pub behind_nat: Option<bool>,
+    /// the connections the peer observes on another port than the local one
+    pub ports_rewritten: u64,
+    pub summary: String,
+}
+
+#[derive(Serialize)]
+pub struct ExternalAddr {
+    pub ip: IpAddr,
+    pub ports: BTreeSet<u16>,
+    /// distinct remote addresses which observed it
+    pub reporters: usize,
+}
+
+impl FromIterator<Connection> for NatReport {
+    fn from_iter<T: IntoIterator<Item = Connection>>(iter: T) -> Self {
+        let mut report = NatReport::default();
+        let mut external = BTreeMap::<IpAddr, (BTreeSet<u16>, BTreeSet<IpAddr>)>::new();
+        for cn in iter {
+            if cn.incoming {
+                report.incoming += 1;
+            } else {
+                report.outgoing += 1;
+            }
+            let mapping = NatMapping::of(&cn);
+            if let Some(local) = mapping.local {
+                *report.local_ips.entry(local.ip()).or_default() += 1;
+                if cn.incoming {
+                    *report.dialed.entry(local).or_default() += 1;
+                }
+            }
+            if let Some(addr) = mapping.external {
+                let entry = external.entry(addr.ip()).or_default();
+                entry.0.insert(addr.port());
+                entry.1.insert(cn.info.addr.ip());
+            }
+            let rewritten = mapping
+                .local
+                .zip(mapping.external)
+                .map_or(false, |(local, external)| local.port() != external.port());
+            // only the incoming connection tells, the outgoing one has the ephemeral port anyway
+            if rewritten && cn.incoming {
+                report.ports_rewritten += 1;
+            }
+        }
+        report.external = external
+            .into_iter()
+            .map(|(ip, (ports, reporters))| ExternalAddr {
+                ip,
+                ports,
+                reporters: reporters.len(),
+            })
+            .collect();
+        report
+            .external
+            .sort_by(|a, b| b.reporters.cmp(&a.reporters).then(a.ip.cmp(&b.ip)));
+
+        report.behind_nat = (!report.external.is_empty()).then(|| {
+            report
+                .external
+                .iter()
+                .any(|e| !report.local_ips.contains_key(&e.ip))
+        });
+        report.summary = match (report.behind_nat, report.external.first()) {
+            (Some(true), Some(e)) => {
+                let local = report
+                    .local_ips
+                    .keys()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("behind nat, peers see {}, the node has {local}", e.ip)
+            }
+            (Some(false), _) => "no nat, peers see the local address".to_owned(),
+            _ => "unknown, no peer told the address it observes".to_owned(),
+        };
+        if report.incoming == 0 && report.outgoing != 0 {
+            report.summary += ", no incoming connections, peers might not reach the node";
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn nat_report() {
+    use std::time::SystemTime;
+
+    use crate::event::ConnectionInfo;
+
+    let cn = |addr: &str, incoming, local: &str, observed: &str| {
+        let info = ConnectionInfo {
+            addr: addr.parse().unwrap(),
+            pid: 1,
+            fd: 10,
+        };
+        Connection {
+            local_addr: local.to_owned(),
+            observed_addr: observed.to_owned(),
+            ..Connection::new(info, incoming, "node".to_owned(), SystemTime::UNIX_EPOCH)
+        }
+    };
+    let a = cn(
+        "1.2.3.4:8302",
+        false,
+        "10.0.0.5:40112",
+        "/ip4/5.6.7.8/tcp/61000",
+    );
+    let mapping = NatMapping::of(&a);
+    assert_eq!(mapping.local, Some("10.0.0.5:40112".parse().unwrap()));
+    assert_eq!(mapping.translated, Some(true));
+
+    let report = [
+        a,
+        cn(
+            "1.2.3.5:8302",
+            false,
+            "10.0.0.5:40114",
+            "/ip4/5.6.7.8/tcp/61002",
+        ),
+        cn("1.2.3.6:8302", false, "10.0.0.5:40116", ""),
+    ]
+    .into_iter()
+    .collect::<NatReport>();
+    assert_eq!(report.behind_nat, Some(true));
+    assert_eq!(report.external[0].reporters, 2);
+    assert_eq!(report.local_ips.values().sum::<u64>(), 3);
+    assert_eq!(
+        report.summary,
+        "behind nat, peers see 5.6.7.8, the node has 10.0.0.5, \
+         no incoming connections, peers might not reach the node"
+    );
+
+    let report = [cn(
+        "1.2.3.4:40001",
+        true,
+        "1.1.1.1:8302",
+        "/ip4/1.1.1.1/tcp/8302",
+    )]
+    .into_iter()
+    .collect::<NatReport>();
+    assert_eq!(report.behind_nat, Some(false));
+    assert_eq!(report.dialed.values().sum::<u64>(), 1);
+    assert_eq!(report.ports_rewritten, 0);
+}
@@ -4,9 +4,9 @@ use serde::Deserialize;
 
 use thiserror::Error;
 
-use crate::decode::MessageType;
+use crate::{decode::MessageType, event::canonical_addr};
 
-use super::types::{ConnectionId, StreamFullId, StreamKind, Timestamp};
+use super::types::{ConnectionId, Message, StreamFullId, StreamKind, Timestamp};
 
 #[derive(Debug, Error)]
 pub enum ParamsCoordinateValidateError {
@@ -26,6 +26,8 @@ pub enum ParamsValidateError {
     ParseStreamId(String),
     #[error("cannot parse message kind")]
     ParseMessageKind,
+    #[error("cannot use together decoder_version and decoder_version_below")]
+    DecoderVersionAmbiguous,
 }
 
 pub struct ValidParamsCoordinate {
@@ -33,12 +35,14 @@ pub struct ValidParamsCoordinate {
     pub limit: usize,
     limit_timestamp: Option<u64>,
     pub direction: Direction,
+    pub freeze: Option<u64>,
 }
 
 pub struct ValidParams {
     pub coordinate: ValidParamsCoordinate,
     pub stream_filter: Option<StreamFilter>,
     pub kind_filter: Option<KindFilter>,
+    pub decoder_filter: Option<DecoderFilter>,
 }
 
 pub struct ValidParamsConnection {
@@ -61,6 +65,23 @@ pub enum KindFilter {
     Message(Vec<MessageType>),
 }
 
+/// Which version of the decoders produced the message, see `decode::DECODER_VERSION`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecoderFilter {
+    Version(u32),
+    /// decoded by an older version
+    Below(u32),
+}
+
+impl DecoderFilter {
+    pub fn matches(&self, msg: &Message) -> bool {
+        match *self {
+            DecoderFilter::Version(version) => msg.decoder_version == version,
+            DecoderFilter::Below(version) => msg.decoder_version < version,
+        }
+    }
+}
+
 #[derive(Default, Deserialize)]
 pub struct Params {
     // the start of the list, either id of record ...
@@ -82,6 +103,12 @@ pub struct Params {
     stream_id: Option<String>,
     stream_kind: Option<String>,
     message_kind: Option<String>,
+    // filter by the version of the decoders, either exact ...
+    decoder_version: Option<u32>,
+    // ... or older
+    decoder_version_below: Option<u32>,
+    // see only the records stored before the freeze, the id `POST /freeze` returned
+    freeze: Option<u64>,
 }
 
 #[derive(Default, Clone, Copy, Deserialize)]
@@ -149,6 +176,7 @@ impl Params {
             limit,
             limit_timestamp: self.limit_timestamp,
             direction: self.direction,
+            freeze: self.freeze,
         })
     }
 
@@ -164,7 +192,7 @@ impl Params {
         let stream_filter = match (self.addr, self.connection_id, self.stream_id) {
             (Some(addr), _, _) => {
                 let addr = addr.parse().map_err(ParamsValidateError::ParseSocketAddr)?;
-                Some(StreamFilter::AnyStreamByAddr(addr))
+                Some(StreamFilter::AnyStreamByAddr(canonical_addr(addr)))
             }
             (None, None, None) => None,
             (None, Some(id), None) => Some(StreamFilter::AnyStreamInConnection(ConnectionId(id))),
@@ -197,10 +225,17 @@ impl Params {
                 Some(KindFilter::Message(kinds))
             }
         };
+        let decoder_filter = match (self.decoder_version, self.decoder_version_below) {
+            (None, None) => None,
+            (Some(version), None) => Some(DecoderFilter::Version(version)),
+            (None, Some(version)) => Some(DecoderFilter::Below(version)),
+            (Some(_), Some(_)) => return Err(ParamsValidateError::DecoderVersionAmbiguous),
+        };
         Ok(ValidParams {
             coordinate,
             stream_filter,
             kind_filter,
+            decoder_filter,
         })
     }
 }
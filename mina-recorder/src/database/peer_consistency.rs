@@ -0,0 +1,183 @@
+use std::{collections::BTreeSet, net::SocketAddr, time::SystemTime};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use crate::event::canonical_ip;
+
+use super::types::ConnectionId;
+
+/// The peer as the node reports it via graphql.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReportedPeer {
+    pub peer_id: String,
+    pub host: String,
+    pub libp2p_port: u16,
+}
+
+/// The latest peer list the node reported, the poller of the node status updates it.
+#[derive(Default)]
+pub struct ReportedPeers {
+    inner: Mutex<Option<(SystemTime, Vec<ReportedPeer>)>>,
+}
+
+impl ReportedPeers {
+    pub fn update(&self, time: SystemTime, peers: Vec<ReportedPeer>) {
+        *self.inner.lock() = Some((time, peers));
+    }
+
+    pub fn latest(&self) -> Option<(SystemTime, Vec<ReportedPeer>)> {
+        self.inner.lock().clone()
+    }
+}
+
+/// The peer the node exchanged messages with, as seen on the wire.
+#[derive(Debug, Clone, Serialize)]
+pub struct WirePeer {
+    pub addr: SocketAddr,
+    /// known if the handshake was decoded
+    pub peer_id: Option<String>,
+    pub connection_ids: Vec<ConnectionId>,
+    pub messages: u64,
+}
+
+/// The peers the node claims versus the peers it talks to.
+#[derive(Serialize)]
+pub struct PeerConsistency {
+    pub since: SystemTime,
+    pub until: SystemTime,
+    /// when the node reported its peers, `None` if it never did, the node status is not polled
+    pub reported_at: Option<SystemTime>,
+    pub reported: usize,
+    pub on_wire: usize,
+    pub matched: usize,
+    pub consistent: bool,
+    /// "node claims 30 peers, wire shows traffic with 24"
+    pub summary: String,
+    /// the node claims the peer, but exchanged nothing with it within the window
+    pub only_reported: Vec<ReportedPeer>,
+    /// the node exchanged messages with the peer, but does not claim it
+    pub only_on_wire: Vec<WirePeer>,
+}
+
+impl PeerConsistency {
+    /// The reported peer matches the wire peer by the peer id,
+    /// or by the ip address if the peer id of the connection is unknown.
+    pub fn build(
+        reported: Option<(SystemTime, Vec<ReportedPeer>)>,
+        wire: Vec<WirePeer>,
+        since: SystemTime,
+        until: SystemTime,
+    ) -> Self {
+        let (reported_at, reported) = match reported {
+            Some((time, peers)) => (Some(time), peers),
+            None => (None, vec![]),
+        };
+        let ids = reported
+            .iter()
+            .map(|p| p.peer_id.as_str())
+            .collect::<BTreeSet<_>>();
+        let hosts = reported
+            .iter()
+            .filter_map(|p| p.host.parse().ok().map(canonical_ip))
+            .collect::<BTreeSet<_>>();
+        let matches = |w: &WirePeer| match &w.peer_id {
+            Some(id) => ids.contains(id.as_str()),
+            None => hosts.contains(&w.addr.ip()),
+        };
+        let (matched, only_on_wire) = wire.iter().cloned().partition::<Vec<_>, _>(matches);
+
+        let wire_ids = wire
+            .iter()
+            .filter_map(|w| w.peer_id.as_deref())
+            .collect::<BTreeSet<_>>();
+        let wire_ips = wire
+            .iter()
+            .filter(|w| w.peer_id.is_none())
+            .map(|w| w.addr.ip())
+            .collect::<BTreeSet<_>>();
+        let on_wire = |p: &ReportedPeer| {
+            wire_ids.contains(p.peer_id.as_str())
+                || p.host
+                    .parse()
+                    .map_or(false, |ip| wire_ips.contains(&canonical_ip(ip)))
+        };
+        let only_reported = reported
+            .iter()
+            .filter(|p| !on_wire(p))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let summary = match reported_at {
+            Some(_) => format!(
+                "node claims {} peers, wire shows traffic with {}",
+                reported.len(),
+                wire.len()
+            ),
+            None => format!(
+                "node does not report its peers, wire shows traffic with {}",
+                wire.len()
+            ),
+        };
+        PeerConsistency {
+            since,
+            until,
+            reported_at,
+            reported: reported.len(),
+            on_wire: wire.len(),
+            matched: matched.len(),
+            consistent: reported_at.is_some()
+                && only_reported.is_empty()
+                && only_on_wire.is_empty(),
+            summary,
+            only_reported,
+            only_on_wire,
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn peer_consistency() {
+    let time = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_675_166_400);
+    let reported = |id: &str, host: &str| ReportedPeer {
+        peer_id: id.to_owned(),
+        host: host.to_owned(),
+        libp2p_port: 8302,
+    };
+    let wire = |addr: &str, id: Option<&str>| WirePeer {
+        addr: addr.parse().unwrap(),
+        peer_id: id.map(str::to_owned),
+        connection_ids: vec![ConnectionId(0)],
+        messages: 1,
+    };
+    let report = PeerConsistency::build(
+        Some((
+            time,
+            vec![
+                reported("a", "1.2.3.4"),
+                reported("b", "1.2.3.5"),
+                reported("c", "1.2.3.6"),
+            ],
+        )),
+        vec![
+            wire("1.2.3.4:8302", Some("a")),
+            // the handshake is not decoded, the same host
+            wire("1.2.3.5:40112", None),
+            wire("1.2.3.7:8302", Some("d")),
+        ],
+        time,
+        time,
+    );
+    assert_eq!((report.reported, report.on_wire, report.matched), (3, 3, 2));
+    assert_eq!(report.only_reported, [reported("c", "1.2.3.6")]);
+    assert_eq!(report.only_on_wire[0].peer_id.as_deref(), Some("d"));
+    assert_eq!(
+        report.summary,
+        "node claims 3 peers, wire shows traffic with 3"
+    );
+    assert!(!report.consistent);
+
+    let report = PeerConsistency::build(None, vec![], time, time);
+    assert!(!report.consistent);
+}
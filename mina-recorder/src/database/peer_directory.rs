@@ -0,0 +1,205 @@
+use std::{env, path::Path, time::SystemTime};
+
+use libp2p_core::PeerId;
+use parking_lot::Mutex;
+use radiation::{Absorb, AbsorbExt, Emit};
+use serde::Serialize;
+
+use crate::custom_coding;
+
+use super::core::DbError;
+
+/// What the debugger knows about the peer from all the captures so far.
+#[derive(Clone, Debug, Absorb, Emit, Serialize)]
+pub struct PeerRecord {
+    pub peer_id: String,
+    /// the remote addresses of the connections and the listen addresses from identify,
+    /// the most recent last
+    pub addresses: Vec<String>,
+    /// from identify, the most recent last
+    pub agent_versions: Vec<String>,
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub first_seen: SystemTime,
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub last_seen: SystemTime,
+    /// connections with the peer
+    pub connections: u64,
+}
+
+impl PeerRecord {
+    const MAX_ADDRESSES: usize = 32;
+    const MAX_AGENT_VERSIONS: usize = 8;
+
+    fn new(peer_id: PeerId, time: SystemTime) -> Self {
+        PeerRecord {
+            peer_id: peer_id.to_base58(),
+            addresses: vec![],
+            agent_versions: vec![],
+            first_seen: time,
+            last_seen: time,
+            connections: 0,
+        }
+    }
+
+    fn see(&mut self, time: SystemTime) {
+        self.first_seen = self.first_seen.min(time);
+        self.last_seen = self.last_seen.max(time);
+    }
+
+    // moves the item to the end, the oldest is forgotten
+    fn remember(list: &mut Vec<String>, item: String, max: usize) {
+        list.retain(|v| *v != item);
+        list.push(item);
+        if list.len() > max {
+            list.remove(0);
+        }
+    }
+}
+
+/// Small database of the peers which outlives the capture, the path is independent of
+/// the capture, so the debugging sessions on the same network accumulate the context.
+#[derive(Default)]
+pub struct PeerDirectory {
+    // read, modify, write
+    inner: Option<Mutex<rocksdb::DB>>,
+}
+
+impl PeerDirectory {
+    pub fn open<P>(path: P) -> Result<Self, DbError>
+    where
+        P: AsRef<Path>,
+    {
+        let mut opts = rocksdb::Options::default();
+        opts.create_if_missing(true);
+        let inner = rocksdb::DB::open(&opts, path)?;
+        Ok(PeerDirectory {
+            inner: Some(Mutex::new(inner)),
+        })
+    }
+
+    /// The path is `PEER_DIRECTORY`, disabled if it is not set.
+    pub fn from_env() -> Self {
+        match env::var("PEER_DIRECTORY") {
+            Ok(path) => Self::open(&path).unwrap_or_else(|err| {
+                log::error!("cannot open peer directory {path}: {err}");
+                PeerDirectory::default()
+            }),
+            Err(_) => PeerDirectory::default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    fn update<F>(&self, peer_id: PeerId, time: SystemTime, f: F)
+    where
+        F: FnOnce(&mut PeerRecord),
+    {
+        let inner = match &self.inner {
+            Some(v) => v.lock(),
+            None => return,
+        };
+        let key = peer_id.to_bytes();
+        let result = inner.get(&key).map_err(DbError::from).and_then(|v| {
+            let mut record = match v {
+                Some(bytes) => PeerRecord::absorb_ext(&bytes)?,
+                None => PeerRecord::new(peer_id, time),
+            };
+            record.see(time);
+            f(&mut record);
+            inner.put(&key, record.chain(vec![]))?;
+            Ok(())
+        });
+        if let Err(err) = result {
+            log::error!("peer directory {peer_id}: {err}");
+        }
+    }
+
+    /// The noise handshake revealed the peer at the remote address.
+    pub fn on_connection(&self, peer_id: PeerId, addr: String, time: SystemTime) {
+        self.update(peer_id, time, |record| {
+            record.connections += 1;
+            PeerRecord::remember(&mut record.addresses, addr, PeerRecord::MAX_ADDRESSES);
+        });
+    }
+
+    /// The peer announced itself in the identify message.
+    pub fn on_identify(
+        &self,
+        peer_id: PeerId,
+        agent_version: Option<String>,
+        listen_addrs: Vec<String>,
+        time: SystemTime,
+    ) {
+        self.update(peer_id, time, |record| {
+            for addr in listen_addrs {
+                PeerRecord::remember(&mut record.addresses, addr, PeerRecord::MAX_ADDRESSES);
+            }
+            if let Some(agent_version) = agent_version.filter(|v| !v.is_empty()) {
+                let max = PeerRecord::MAX_AGENT_VERSIONS;
+                PeerRecord::remember(&mut record.agent_versions, agent_version, max);
+            }
+        });
+    }
+
+    pub fn fetch(&self, peer_id: PeerId) -> Result<Option<PeerRecord>, DbError> {
+        let inner = match &self.inner {
+            Some(v) => v.lock(),
+            None => return Ok(None),
+        };
+        match inner.get(peer_id.to_bytes())? {
+            Some(bytes) => Ok(Some(PeerRecord::absorb_ext(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// All the peers, the most recently seen first.
+    pub fn fetch_all(&self) -> Vec<PeerRecord> {
+        let inner = match &self.inner {
+            Some(v) => v.lock(),
+            None => return vec![],
+        };
+        let mut records = inner
+            .iterator(rocksdb::IteratorMode::Start)
+            .filter_map(|item| match item {
+                Ok((_, value)) => PeerRecord::absorb_ext(&value).ok(),
+                Err(err) => {
+                    log::error!("{err}");
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        records.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
+        records
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn peer_directory() {
+    use std::time::Duration;
+
+    let dir = temp_dir::TempDir::new().unwrap();
+    let peer_id = PeerId::random();
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_675_166_400);
+    {
+        let directory = PeerDirectory::open(dir.path()).unwrap();
+        directory.on_connection(peer_id, "1.2.3.4:8302".to_owned(), time);
+        let addrs = vec!["/ip4/1.2.3.4/tcp/8302".to_owned()];
+        let agent = Some("mina/1.4.0".to_owned());
+        directory.on_identify(peer_id, agent, addrs, time + Duration::from_secs(1));
+    }
+    // the next capture
+    let directory = PeerDirectory::open(dir.path()).unwrap();
+    let later = time + Duration::from_secs(3600);
+    directory.on_connection(peer_id, "1.2.3.4:8302".to_owned(), later);
+    let record = directory.fetch(peer_id).unwrap().unwrap();
+    assert_eq!(record.connections, 2);
+    assert_eq!(record.addresses, ["/ip4/1.2.3.4/tcp/8302", "1.2.3.4:8302"]);
+    assert_eq!(record.agent_versions, ["mina/1.4.0"]);
+    assert_eq!((record.first_seen, record.last_seen), (time, later));
+    assert_eq!(directory.fetch_all().len(), 1);
+}
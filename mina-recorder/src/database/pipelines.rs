@@ -0,0 +1,133 @@
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    time::{Duration, SystemTime},
+};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use super::types::ConnectionId;
+
+/// The decoder of the open connection, layer by layer, to see where a silent connection is stuck.
+#[derive(Debug, Clone, Serialize)]
+pub struct Pipeline {
+    pub connection_id: ConnectionId,
+    pub addr: SocketAddr,
+    /// the time of the chunk the snapshot was taken after
+    pub updated: SystemTime,
+    /// the chunks decoded so far
+    pub chunks: u64,
+    /// the watchdog stopped the decoder, only raw bytes are stored
+    pub raw: bool,
+    /// the names of the layers, the outermost first, the streams of the muxer are not included
+    pub composition: Vec<String>,
+    /// the state of each layer, the next layer is `inner`, the muxer holds `streams`
+    pub layers: serde_json::Value,
+}
+
+impl Pipeline {
+    pub fn new(
+        connection_id: ConnectionId,
+        addr: SocketAddr,
+        updated: SystemTime,
+        chunks: u64,
+        raw: bool,
+        layers: serde_json::Value,
+    ) -> Self {
+        let mut composition = vec![];
+        let mut layer = &layers;
+        while let Some(name) = layer.get("layer").and_then(serde_json::Value::as_str) {
+            composition.push(name.to_owned());
+            layer = &layer["inner"];
+        }
+        Pipeline {
+            connection_id,
+            addr,
+            updated,
+            chunks,
+            raw,
+            composition,
+            layers,
+        }
+    }
+}
+
+/// The latest pipeline of each open connection, the recorder updates it as the data arrives.
+#[derive(Default)]
+pub struct Pipelines {
+    inner: Mutex<BTreeMap<ConnectionId, Pipeline>>,
+}
+
+impl Pipelines {
+    /// The snapshot is taken after each of the first chunks,
+    /// the connection that gets stuck usually does it early.
+    const EVERY_CHUNK: u64 = 64;
+    /// After that, at most once in this time.
+    const INTERVAL: Duration = Duration::from_secs(1);
+
+    /// Whether the pipeline of the connection needs a new snapshot.
+    pub fn outdated(chunks: u64, updated: SystemTime, time: SystemTime) -> bool {
+        chunks <= Self::EVERY_CHUNK
+            || time.duration_since(updated).unwrap_or_default() >= Self::INTERVAL
+    }
+
+    pub fn update(&self, pipeline: Pipeline) {
+        self.inner.lock().insert(pipeline.connection_id, pipeline);
+    }
+
+    pub fn remove(&self, id: ConnectionId) {
+        self.inner.lock().remove(&id);
+    }
+
+    pub fn get(&self, id: ConnectionId) -> Option<Pipeline> {
+        self.inner.lock().get(&id).cloned()
+    }
+
+    /// All the open connections, the oldest first.
+    pub fn all(&self) -> Vec<Pipeline> {
+        self.inner.lock().values().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn pipeline_composition() {
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_675_166_400);
+    let layers = serde_json::json!({
+        "layer": "pnet",
+        "established": true,
+        "inner": {
+            "layer": "select",
+            "phase": "agreed",
+            "inner": {
+                "layer": "noise_frames",
+                "inner": { "layer": "noise", "machine": "second_message", "inner": null },
+            },
+        },
+    });
+    let addr = "1.2.3.4:8302".parse().unwrap();
+    let pipeline = Pipeline::new(ConnectionId(1), addr, time, 3, false, layers);
+    assert_eq!(
+        pipeline.composition,
+        ["pnet", "select", "noise_frames", "noise"]
+    );
+
+    let pipelines = Pipelines::default();
+    pipelines.update(pipeline);
+    assert_eq!(pipelines.get(ConnectionId(1)).unwrap().chunks, 3);
+    pipelines.remove(ConnectionId(1));
+    assert!(pipelines.all().is_empty());
+
+    assert!(Pipelines::outdated(10, time, time));
+    assert!(!Pipelines::outdated(
+        100,
+        time,
+        time + Duration::from_millis(500)
+    ));
+    assert!(Pipelines::outdated(
+        100,
+        time,
+        time + Duration::from_secs(1)
+    ));
+}
@@ -0,0 +1,166 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+
+use super::types::ConnectionId;
+
+/// The local end of the outgoing connection, the port is allocated by the kernel.
+pub struct PortUse {
+    pub connection_id: ConnectionId,
+    pub pid: u32,
+    pub alias: String,
+    pub fd: u32,
+    pub port: u16,
+    pub opened: SystemTime,
+    pub closed: Option<SystemTime>,
+}
+
+/// How the processes use the ephemeral ports. The connection is identified by the pid and the fd,
+/// if both the fd and the port are reused quickly, the traffic is easy to misattribute.
+#[derive(Serialize)]
+pub struct PortReport {
+    pub since: SystemTime,
+    pub until: SystemTime,
+    pub pids: Vec<PidPorts>,
+}
+
+#[derive(Serialize)]
+pub struct PidPorts {
+    pub pid: u32,
+    pub alias: String,
+    /// the outgoing connections with the known local port
+    pub connections: u64,
+    pub distinct_ports: usize,
+    pub lowest_port: u16,
+    pub highest_port: u16,
+    /// the connection took the port some earlier connection had
+    pub reuses: u64,
+    /// from the close of the earlier connection to the open of the next one
+    pub min_reuse_interval_secs: Option<f64>,
+    pub median_reuse_interval_secs: Option<f64>,
+    /// the same fd and the same port again within `RAPID_REUSE`, at most `MAX_LISTED`
+    pub rapid_reuses: Vec<RapidReuse>,
+    pub rapid_reuses_total: u64,
+}
+
+#[derive(Serialize)]
+pub struct RapidReuse {
+    pub port: u16,
+    pub fd: u32,
+    pub previous: ConnectionId,
+    pub next: ConnectionId,
+    pub interval_secs: f64,
+}
+
+impl PortReport {
+    pub const RAPID_REUSE: Duration = Duration::from_secs(1);
+    pub const MAX_LISTED: usize = 100;
+
+    /// The `uses` opened within the range are counted.
+    pub fn build<I>(uses: I, since: SystemTime, until: SystemTime) -> Self
+    where
+        I: IntoIterator<Item = PortUse>,
+    {
+        let mut by_pid = BTreeMap::<u32, Vec<PortUse>>::new();
+        for u in uses {
+            if (since..until).contains(&u.opened) {
+                by_pid.entry(u.pid).or_default().push(u);
+            }
+        }
+
+        let pids = by_pid
+            .into_iter()
+            .map(|(pid, mut uses)| {
+                uses.sort_by(|a, b| a.opened.cmp(&b.opened));
+                // the last connection on the port
+                let mut last = BTreeMap::<u16, (ConnectionId, u32, SystemTime)>::new();
+                let mut intervals = vec![];
+                let mut rapid_reuses = vec![];
+                let mut rapid_reuses_total = 0;
+                for u in &uses {
+                    let end = u.closed.unwrap_or(u.opened);
+                    if let Some((previous, fd, previous_end)) =
+                        last.insert(u.port, (u.connection_id, u.fd, end))
+                    {
+                        let interval = u.opened.duration_since(previous_end).unwrap_or_default();
+                        intervals.push(interval);
+                        if fd == u.fd && interval <= Self::RAPID_REUSE {
+                            rapid_reuses_total += 1;
+                            if rapid_reuses.len() < Self::MAX_LISTED {
+                                rapid_reuses.push(RapidReuse {
+                                    port: u.port,
+                                    fd,
+                                    previous,
+                                    next: u.connection_id,
+                                    interval_secs: interval.as_secs_f64(),
+                                });
+                            }
+                        }
+                    }
+                }
+                intervals.sort();
+                PidPorts {
+                    pid,
+                    alias: uses.last().map(|u| u.alias.clone()).unwrap_or_default(),
+                    connections: uses.len() as u64,
+                    distinct_ports: last.len(),
+                    lowest_port: last.keys().next().copied().unwrap_or_default(),
+                    highest_port: last.keys().last().copied().unwrap_or_default(),
+                    reuses: intervals.len() as u64,
+                    min_reuse_interval_secs: intervals.first().map(Duration::as_secs_f64),
+                    median_reuse_interval_secs: intervals
+                        .get(intervals.len() / 2)
+                        .map(Duration::as_secs_f64),
+                    rapid_reuses,
+                    rapid_reuses_total,
+                }
+            })
+            .collect();
+
+        PortReport { since, until, pids }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn port_reuse() {
+    let since = SystemTime::UNIX_EPOCH + Duration::from_secs(1_675_166_400);
+    let until = since + Duration::from_secs(3600);
+    let u = |id: u64, pid, fd, port, opened_ms: u64, closed_ms: Option<u64>| PortUse {
+        connection_id: ConnectionId(id),
+        pid,
+        alias: "node".to_owned(),
+        fd,
+        port,
+        opened: since + Duration::from_millis(opened_ms),
+        closed: closed_ms.map(|ms| since + Duration::from_millis(ms)),
+    };
+    let report = PortReport::build(
+        [
+            u(0, 10, 40, 40112, 0, Some(1_000)),
+            // the same fd and the same port right after the close
+            u(1, 10, 40, 40112, 1_200, Some(5_000)),
+            // the port is reused much later by another fd
+            u(2, 10, 41, 40112, 65_000, None),
+            u(3, 10, 42, 40200, 2_000, None),
+            u(4, 11, 40, 40112, 3_000, None),
+        ],
+        since,
+        until,
+    );
+    assert_eq!(report.pids.len(), 2);
+    let p = &report.pids[0];
+    assert_eq!((p.connections, p.distinct_ports, p.reuses), (4, 2, 2));
+    assert_eq!((p.lowest_port, p.highest_port), (40112, 40200));
+    assert_eq!(p.min_reuse_interval_secs, Some(0.2));
+    assert_eq!(p.median_reuse_interval_secs, Some(60.0));
+    assert_eq!(p.rapid_reuses_total, 1);
+    assert_eq!(
+        (p.rapid_reuses[0].previous, p.rapid_reuses[0].next),
+        (ConnectionId(0), ConnectionId(1))
+    );
+    assert_eq!(report.pids[1].reuses, 0);
+}
@@ -0,0 +1,233 @@
+use std::{net::SocketAddr, path::Path, time::SystemTime};
+
+use super::{
+    core::{DbCore, DbError},
+    manifest::Manifest,
+    params::DecoderFilter,
+    redaction::Redaction,
+    tuning::DbTuning,
+    types::{Connection, ConnectionId, FullMessage, Message, MessageId, StreamKind},
+};
+
+/// Which connections to yield, empty filter yields all of them.
+#[derive(Default, Clone)]
+pub struct ConnectionFilter {
+    pub addr: Option<SocketAddr>,
+    pub alias: Option<String>,
+    pub incoming: Option<bool>,
+    /// opened not before
+    pub from: Option<SystemTime>,
+    /// opened before
+    pub to: Option<SystemTime>,
+}
+
+impl ConnectionFilter {
+    pub fn matches(&self, cn: &Connection) -> bool {
+        self.addr.map_or(true, |addr| cn.info.addr == addr)
+            && self.alias.as_ref().map_or(true, |alias| &cn.alias == alias)
+            && self
+                .incoming
+                .map_or(true, |incoming| cn.incoming == incoming)
+            && self.from.map_or(true, |from| cn.timestamp >= from)
+            && self.to.map_or(true, |to| cn.timestamp < to)
+    }
+}
+
+/// Which messages to yield, empty filter yields all of them.
+#[derive(Default, Clone)]
+pub struct MessageFilter {
+    pub connection_id: Option<ConnectionId>,
+    /// any of these, all if empty
+    pub stream_kinds: Vec<StreamKind>,
+    pub incoming: Option<bool>,
+    pub from: Option<SystemTime>,
+    pub to: Option<SystemTime>,
+    /// the version of the decoders which produced the message
+    pub decoder: Option<DecoderFilter>,
+}
+
+impl MessageFilter {
+    pub fn matches(&self, msg: &Message) -> bool {
+        self.connection_id
+            .map_or(true, |id| msg.connection_id == id)
+            && (self.stream_kinds.is_empty() || self.stream_kinds.contains(&msg.stream_kind))
+            && self
+                .incoming
+                .map_or(true, |incoming| msg.incoming == incoming)
+            && self.decoder.map_or(true, |decoder| decoder.matches(msg))
+    }
+}
+
+/// Reads the capture database without the HTTP server, for tests and custom analyzers.
+/// The database must not be in use by the running debugger.
+#[derive(Clone)]
+pub struct CaptureReader {
+    db: DbCore,
+}
+
+impl CaptureReader {
+    pub fn open<P>(path: P) -> Result<Self, DbError>
+    where
+        P: AsRef<Path>,
+    {
+        let db = DbCore::open_with_tuning(path, DbTuning::default())?;
+        Ok(CaptureReader { db })
+    }
+
+    /// Over the database already open by the debugger.
+    pub fn new(db: DbCore) -> Self {
+        CaptureReader { db }
+    }
+
+    pub fn manifest(&self) -> &Manifest {
+        self.db.manifest()
+    }
+
+    /// Everything the HTTP server can query.
+    pub fn core(&self) -> &DbCore {
+        &self.db
+    }
+
+    pub fn connection(&self, id: ConnectionId) -> Result<Connection, DbError> {
+        self.db.fetch_connection(id.0)
+    }
+
+    pub fn connections<'a>(
+        &'a self,
+        filter: &'a ConnectionFilter,
+    ) -> impl Iterator<Item = (ConnectionId, Connection)> + 'a {
+        self.db
+            .fetch_all_connections()
+            .filter(move |(_, cn)| filter.matches(cn))
+            .map(|(id, cn)| (ConnectionId(id), cn))
+    }
+
+    pub fn message(&self, id: MessageId) -> Result<Message, DbError> {
+        self.db.fetch_message(id.0)
+    }
+
+    /// Ordered by id, that is the order the messages are observed.
+    pub fn messages<'a>(
+        &'a self,
+        filter: &'a MessageFilter,
+    ) -> impl Iterator<Item = (MessageId, Message)> + 'a {
+        let from = filter.from.unwrap_or(SystemTime::UNIX_EPOCH);
+        self.db
+            .fetch_messages_since(from)
+            .take_while(move |(_, msg)| filter.to.map_or(true, |to| msg.timestamp < to))
+            .filter(move |(_, msg)| filter.matches(msg))
+            .map(|(id, msg)| (MessageId(id), msg))
+    }
+
+    /// The message decoded to json, like `GET /message/{id}` returns it.
+    pub fn decoded(&self, id: MessageId) -> Result<FullMessage, DbError> {
+        self.db.fetch_full_message(id.0, Redaction::None)
+    }
+
+    /// The decrypted bytes of the message.
+    pub fn bytes(&self, id: MessageId) -> Result<Vec<u8>, DbError> {
+        self.db.fetch_full_message_bin(id.0, Redaction::None)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn capture_reader() {
+    use super::{DbFacade, StreamId};
+    use crate::event::ConnectionInfo;
+
+    let dir = temp_dir::TempDir::new().unwrap();
+    let time = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_675_166_400);
+    {
+        let db = DbFacade::open(dir.path()).unwrap();
+        let info = ConnectionInfo {
+            addr: "1.2.3.4:8302".parse().unwrap(),
+            pid: 1,
+            fd: 10,
+        };
+        let group = db.add(info, true, "node".to_owned(), time).unwrap();
+        let stream = group.get(StreamId::Forward(1));
+        stream
+            .add_at(true, time, StreamKind::Select, b"/coda/yamux/1.0.0\n")
+            .unwrap();
+        stream
+            .add_at(false, time, StreamKind::Select, b"/coda/yamux/1.0.0\n")
+            .unwrap();
+    }
+
+    let reader = CaptureReader::open(dir.path()).unwrap();
+    let filter = ConnectionFilter {
+        addr: Some("1.2.3.4:8302".parse().unwrap()),
+        ..Default::default()
+    };
+    let connections = reader.connections(&filter).collect::<Vec<_>>();
+    assert_eq!(connections.len(), 1);
+    assert_eq!(connections[0].1.alias, "node");
+
+    let filter = MessageFilter {
+        connection_id: Some(connections[0].0),
+        incoming: Some(false),
+        ..Default::default()
+    };
+    let messages = reader.messages(&filter).collect::<Vec<_>>();
+    assert_eq!(messages.len(), 1);
+    let decoded = reader.decoded(messages[0].0).unwrap();
+    assert_eq!(decoded.message, serde_json::json!("/coda/yamux/1.0.0\n"));
+}
+
+#[cfg(test)]
+#[test]
+fn redecode_outdated() {
+    use super::{DbFacade, StreamId};
+    use crate::{decode::DECODER_VERSION, event::ConnectionInfo};
+
+    let dir = temp_dir::TempDir::new().unwrap();
+    let time = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_675_166_400);
+    let addr = "1.2.3.4:8302".parse().unwrap();
+    {
+        let db = DbFacade::open(dir.path()).unwrap();
+        let info = ConnectionInfo {
+            addr,
+            pid: 1,
+            fd: 10,
+        };
+        let group = db.add(info, true, "node".to_owned(), time).unwrap();
+        let stream = group.get(StreamId::Forward(1));
+        for incoming in [true, false] {
+            stream
+                .add_at(incoming, time, StreamKind::Select, b"/coda/yamux/1.0.0\n")
+                .unwrap();
+        }
+    }
+
+    let reader = CaptureReader::open(dir.path()).unwrap();
+    // pretend the first message was decoded by a buggy version, which found no types
+    let mut msg = reader.message(MessageId(0)).unwrap();
+    assert_eq!(msg.decoder_version, DECODER_VERSION);
+    msg.brief = String::new();
+    msg.decoder_version = 0;
+    reader
+        .core()
+        .put_message(&addr, MessageId(0), msg, vec![], vec![])
+        .unwrap();
+
+    let outdated = MessageFilter {
+        decoder: Some(DecoderFilter::Below(DECODER_VERSION)),
+        ..Default::default()
+    };
+    assert_eq!(reader.messages(&outdated).count(), 1);
+    let versions = reader.core().fetch_decoder_versions();
+    assert_eq!(versions.versions[&0].messages, 1);
+    assert_eq!(versions.versions[&DECODER_VERSION].first_id, 1);
+
+    let summary = reader
+        .core()
+        .redecode(DecoderFilter::Below(DECODER_VERSION))
+        .unwrap();
+    assert_eq!(
+        (summary.messages, summary.skipped, summary.failed),
+        (1, 0, 0)
+    );
+    assert_eq!(reader.messages(&outdated).count(), 0);
+    assert_eq!(reader.message(MessageId(0)).unwrap().brief, "select");
+}
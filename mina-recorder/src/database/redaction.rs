@@ -0,0 +1,126 @@
+use std::{env, fmt, str::FromStr};
+
+use serde::{Serialize, Deserialize};
+
+use super::types::StreamKind;
+
+/// Hides the payload of the messages, keeps the size, the type, the time
+/// and the peer identity. Handshake and protocol negotiation are never redacted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Redaction {
+    #[default]
+    None,
+    /// replace the payload with a hash, the rest of the bytes are zero
+    Hash,
+    /// replace the payload with zeros
+    Strip,
+}
+
+impl fmt::Display for Redaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Redaction::None => write!(f, "none"),
+            Redaction::Hash => write!(f, "hash"),
+            Redaction::Strip => write!(f, "strip"),
+        }
+    }
+}
+
+impl FromStr for Redaction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Redaction::None),
+            "hash" => Ok(Redaction::Hash),
+            "strip" => Ok(Redaction::Strip),
+            _ => Err(s.to_owned()),
+        }
+    }
+}
+
+impl Redaction {
+    pub const HASH_SIZE: usize = 32;
+
+    pub fn from_env() -> Self {
+        match env::var("REDACTION") {
+            Ok(s) => s.parse().unwrap_or_else(|s| {
+                log::error!("unknown redaction {s}, use `strip`");
+                Redaction::Strip
+            }),
+            Err(_) => Redaction::None,
+        }
+    }
+
+    pub fn is_none(&self) -> bool {
+        matches!(self, Redaction::None)
+    }
+
+    pub fn covers(&self, stream_kind: StreamKind) -> bool {
+        match stream_kind {
+            StreamKind::Handshake | StreamKind::Select | StreamKind::Mplex | StreamKind::Yamux => {
+                false
+            }
+            _ => !self.is_none(),
+        }
+    }
+
+    /// The output has the same length as the input.
+    pub fn apply(&self, bytes: &[u8]) -> Vec<u8> {
+        use blake2::digest::{Update, FixedOutput, typenum};
+
+        match self {
+            Redaction::None => bytes.to_vec(),
+            Redaction::Hash => {
+                let hash = blake2::Blake2b::<typenum::U32>::default()
+                    .chain(bytes)
+                    .finalize_fixed();
+                let mut v = vec![0; bytes.len()];
+                let len = bytes.len().min(Self::HASH_SIZE);
+                v[..len].clone_from_slice(&hash[..len]);
+                v
+            }
+            Redaction::Strip => vec![0; bytes.len()],
+        }
+    }
+
+    /// What api shows instead of the decoded message, `bytes` are already redacted.
+    pub fn placeholder(&self, bytes: &[u8]) -> serde_json::Value {
+        #[derive(Serialize)]
+        struct Redacted {
+            redacted: Redaction,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            hash: Option<String>,
+        }
+
+        let hash = match self {
+            Redaction::Hash => Some(hex::encode(&bytes[..bytes.len().min(Self::HASH_SIZE)])),
+            _ => None,
+        };
+        serde_json::to_value(Redacted {
+            redacted: *self,
+            hash,
+        })
+        .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn redaction_preserves_size() {
+    let bytes = (0..100).collect::<Vec<u8>>();
+    for redaction in [Redaction::None, Redaction::Hash, Redaction::Strip] {
+        assert_eq!(redaction.apply(&bytes).len(), bytes.len());
+        assert_eq!(redaction.apply(&bytes[..10]).len(), 10);
+    }
+    assert_eq!(Redaction::Hash.apply(&bytes), Redaction::Hash.apply(&bytes));
+    assert_ne!(
+        Redaction::Hash.apply(&bytes),
+        Redaction::Hash.apply(&bytes[1..])
+    );
+    assert!(Redaction::Strip.apply(&bytes).iter().all(|b| *b == 0));
+    assert!(!Redaction::Hash.covers(StreamKind::Handshake));
+    assert!(Redaction::Hash.covers(StreamKind::Rpc));
+    assert!(!Redaction::None.covers(StreamKind::Rpc));
+}
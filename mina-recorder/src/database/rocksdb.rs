@@ -1,4 +1,5 @@
 use std::{
+    collections::BTreeSet,
     path::Path,
     time::SystemTime,
     sync::{
@@ -12,24 +13,39 @@ use std::{
 };
 
 use itertools::Itertools;
+use parking_lot::Mutex;
 use radiation::Emit;
+use libp2p_core::PeerId;
 
 use crate::{
     event::{ConnectionInfo, DirectedId},
     chunk::{ChunkHeader, EncryptionStatus},
     decode::{
-        MessageType,
         meshsub_stats::{BlockStat, TxStat},
+        MessageType, DecodeError,
     },
     strace::StraceLine,
     meshsub_stats::Event,
+    policy::CapturePolicy,
+    profile::{NetworkProfile, Profiles},
+    sink::{
+        SinkEvent, ConnectionEvent, UpdateEvent, PeerIdentityEvent, ChunkEvent, MessageEvent,
+        StatsEvent, StatsTxEvent, SupersededEvent,
+    },
 };
 
 use super::{
     core::{DbCore, DbError},
+    trigger::RateMeter,
+    decoder_error::DecoderError,
+    watchdog::{Stall, Watchdog},
+    pipelines::Pipeline,
+    framing::FramingStats,
     types::{
         Connection, ConnectionId, Message, MessageId, StreamId, StreamKind,
-        ConnectionStats,
+        ConnectionStats, Session, Layer, LayerStats, SubscriptionChange, Negotiation,
+        NoiseHandshake, MessageTiming, GossipFirstSeen, GossipDuplicate, AgentVersionSighting,
+        StreamEnd, StreamEndKind,
     },
 };
 
@@ -37,6 +53,7 @@ pub struct DbFacade {
     cns: AtomicU64,
     pub messages: Arc<AtomicU64>,
     rnd_cnt: AtomicU64,
+    sessions: AtomicU64,
     inner: DbCore,
 }
 
@@ -45,12 +62,23 @@ impl DbFacade {
     where
         P: AsRef<Path>,
     {
-        let inner = DbCore::open(path)?;
+        Self::with_core(DbCore::open(path)?)
+    }
+
+    /// See `DbCore::open_scratch`.
+    pub fn open_scratch<P>(path: P) -> Result<Self, DbError>
+    where
+        P: AsRef<Path>,
+    {
+        Self::with_core(DbCore::open_scratch(path)?)
+    }
 
+    fn with_core(inner: DbCore) -> Result<Self, DbError> {
         Ok(DbFacade {
             cns: AtomicU64::new(inner.total::<{ DbCore::CONNECTIONS_CNT }>()?),
             messages: Arc::new(AtomicU64::new(inner.total::<{ DbCore::MESSAGES_CNT }>()?)),
             rnd_cnt: AtomicU64::new(inner.total::<{ DbCore::RANDOMNESS_CNT }>()?),
+            sessions: AtomicU64::new(inner.total::<{ DbCore::SESSIONS_CNT }>()?),
             inner,
         })
     }
@@ -61,15 +89,30 @@ impl DbFacade {
         node_address: SocketAddr,
         value: &BlockStat,
     ) -> Result<(), DbError> {
+        let sinks = self.inner.sinks();
+        sinks.send(|| SinkEvent::Stats(StatsEvent { height, node_address, value: value.chain(vec![]) }));
+        if !sinks.database() {
+            return Ok(());
+        }
         self.inner
             .put_stats(height, node_address, value.chain(vec![]))
     }
 
     pub fn stats_block_v2(&self, event: Event) -> Result<(), DbError> {
+        let sinks = self.inner.sinks();
+        sinks.send(|| SinkEvent::StatsBlockV2(event.clone()));
+        if !sinks.database() {
+            return Ok(());
+        }
         self.inner.put_stats_block_v2(event)
     }
 
     pub fn stats_tx(&self, height: u32, value: &TxStat) -> Result<(), DbError> {
+        let sinks = self.inner.sinks();
+        sinks.send(|| SinkEvent::StatsTx(StatsTxEvent { height, value: value.chain(vec![]) }));
+        if !sinks.database() {
+            return Ok(());
+        }
         self.inner.put_stats_tx(height, value.chain(vec![]))
     }
 
@@ -87,26 +130,74 @@ impl DbFacade {
         alias: String,
         timestamp: SystemTime,
     ) -> Result<DbGroup, DbError> {
+        // the forwarded and imported connections are not normalized yet
+        let info = info.canonical();
         let id = ConnectionId(self.cns.fetch_add(1, SeqCst));
         let addr = info.addr;
+        let group = DbGroup {
+            addr,
+            id,
+            messages: self.messages.clone(),
+            seq: Arc::new(AtomicU64::new(0)),
+            layers: Arc::default(),
+            framing: Arc::default(),
+            rate: Arc::default(),
+            alive: Arc::new(()),
+            peer_id: Arc::default(),
+            reported: Arc::default(),
+            profile: self.inner.profiles().fallback(),
+            inner: self.inner.clone(),
+        };
+        let sinks = self.inner.sinks();
+        sinks.send(|| {
+            let (info, alias) = (info.clone(), alias.clone());
+            SinkEvent::Connection(ConnectionEvent { id: id.0, info, incoming, alias, timestamp })
+        });
+        if !sinks.database() {
+            return Ok(group);
+        }
+        let geo = self.inner.geoip().lookup(info.addr.ip());
         let v = Connection {
-            info,
-            incoming,
-            timestamp,
-            stats_in: ConnectionStats::default(),
-            stats_out: ConnectionStats::default(),
-            timestamp_close: SystemTime::UNIX_EPOCH,
-            alias,
+            geo,
+            ..Connection::new(info, incoming, alias, timestamp)
         };
         self.inner.put_cn(id, v)?;
         self.inner.set_total::<{ DbCore::CONNECTIONS_CNT }>(id.0)?;
 
-        Ok(DbGroup {
-            addr,
-            id,
-            messages: self.messages.clone(),
-            inner: self.inner.clone(),
-        })
+        Ok(group)
+    }
+
+    pub fn begin_session(
+        &self,
+        alias: String,
+        pid: u32,
+        timestamp: SystemTime,
+    ) -> Result<u64, DbError> {
+        let id = self.sessions.fetch_add(1, SeqCst);
+        let first_connection_id = ConnectionId(self.cns.load(SeqCst));
+        let first_message_id = MessageId(self.messages.load(SeqCst));
+        let v = Session {
+            alias,
+            pid,
+            start: timestamp,
+            end: SystemTime::UNIX_EPOCH,
+            first_connection_id,
+            first_message_id,
+            end_connection_id: first_connection_id,
+            end_message_id: first_message_id,
+        };
+        self.inner.put_session(id, v)?;
+        self.inner.set_total::<{ DbCore::SESSIONS_CNT }>(id + 1)?;
+
+        Ok(id)
+    }
+
+    pub fn end_session(&self, id: u64, timestamp: SystemTime) -> Result<(), DbError> {
+        let mut v = self.inner.fetch_session(id)?;
+        v.end = timestamp;
+        v.end_connection_id = ConnectionId(self.cns.load(SeqCst));
+        v.end_message_id = MessageId(self.messages.load(SeqCst));
+        self.inner.put_session(id, v)
     }
 
     pub fn add_randomness(&self, bytes: Vec<u8>) -> Result<(), DbError> {
@@ -116,6 +207,31 @@ impl DbFacade {
         Ok(())
     }
 
+    /// The other end of the connection is a tracked process on this host.
+    pub fn set_paired(
+        &self,
+        id: ConnectionId,
+        other: ConnectionId,
+        peer_alias: String,
+    ) -> Result<(), DbError> {
+        if !self.inner.sinks().database() {
+            return Ok(());
+        }
+        let mut cn = self.inner.fetch_connection(id.0)?;
+        cn.paired_with = Some(other);
+        cn.peer_alias = peer_alias;
+        self.inner.put_cn(id, cn)
+    }
+
+    /// The policy the aggregator pushed, or the default one.
+    pub fn capture_policy(&self) -> CapturePolicy {
+        self.inner.capture_policy().get()
+    }
+
+    pub fn profiles(&self) -> &Profiles {
+        self.inner.profiles()
+    }
+
     pub fn core(&self) -> DbCore {
         self.inner.clone()
     }
@@ -147,6 +263,23 @@ pub struct DbGroup {
     addr: SocketAddr,
     id: ConnectionId,
     messages: Arc<AtomicU64>,
+    // per connection message counter, the connection is handled by single worker,
+    // so the sequence follows the order of the data on the wire
+    seq: Arc<AtomicU64>,
+    // outgoing and incoming, written to the database with the stats
+    layers: Arc<Mutex<[LayerStats; 2]>>,
+    // the noise frames of the transport phase, like the layers
+    framing: Arc<Mutex<[FramingStats; 2]>>,
+    // messages per second, for the capture trigger
+    rate: Arc<Mutex<RateMeter>>,
+    // the connection is closed when the last clone is dropped
+    alive: Arc<()>,
+    // known after the noise handshake
+    peer_id: Arc<Mutex<Option<PeerId>>>,
+    // the layer and whether the error is recoverable, already in the anomalies
+    reported: Arc<Mutex<BTreeSet<(Layer, bool)>>>,
+    // selected by the chain id, the default accepts any name
+    profile: Arc<NetworkProfile>,
     inner: DbCore,
 }
 
@@ -162,16 +295,166 @@ impl DbGroup {
         self.id
     }
 
+    pub fn profile(&self) -> &NetworkProfile {
+        &self.profile
+    }
+
+    /// Must be set before the decoders start, the streams keep the profile they got.
+    pub fn set_profile(&mut self, profile: Arc<NetworkProfile>) {
+        self.profile = profile;
+    }
+
     pub fn update(&self, stats: ConnectionStats, incoming: bool) -> Result<(), DbError> {
+        let layers = self.layers.lock()[incoming as usize].clone();
+        let sinks = self.inner.sinks();
+        sinks.send(|| {
+            let (stats, layers) = (stats.clone(), layers.clone());
+            SinkEvent::Update(UpdateEvent { id: self.id.0, stats, incoming, layers })
+        });
+        if !sinks.database() {
+            return Ok(());
+        }
+        let framing = self.framing.lock()[incoming as usize].clone();
         let mut cn = self.inner.fetch_connection(self.id.0)?;
         if incoming {
             cn.stats_in += stats;
+            cn.layers_in = layers;
+            cn.framing_in = framing;
         } else {
             cn.stats_out += stats;
+            cn.layers_out = layers;
+            cn.framing_out = framing;
+        }
+        self.inner.put_cn(self.id, cn)
+    }
+
+    /// Multistream select transcript of the stream, overwrites the previous one.
+    pub fn add_negotiation(&self, v: Negotiation) -> Result<(), DbError> {
+        self.inner.put_negotiation(&v)
+    }
+
+    /// Public artifacts of the noise handshake, overwrites the previous state.
+    pub fn add_noise_handshake(&self, v: NoiseHandshake) -> Result<(), DbError> {
+        self.inner.put_noise_handshake(&v)
+    }
+
+    /// Decryption or parsing failed, might fire the capture trigger.
+    pub fn on_anomaly(&self, reason: &str, time: SystemTime) {
+        self.inner
+            .triggers()
+            .on_anomaly(self.addr.ip(), reason, time);
+    }
+
+    /// The decoder failed, might fire the capture trigger. The first recoverable and the first
+    /// fatal error of each layer of the connection are stored in the anomalies, the next ones
+    /// are only logged.
+    pub fn report(&self, err: DecoderError) {
+        if err.recoverable {
+            log::warn!("{err}");
+        } else {
+            log::error!("{err}");
+        }
+        self.inner.health().on_decoder_error();
+        self.on_anomaly(&err.layer.to_string(), err.time);
+        if !self.reported.lock().insert((err.layer, err.recoverable)) {
+            return;
+        }
+        if let Err(db_err) = self.inner.put_anomaly(&err.anomaly()) {
+            log::error!("{} cannot store the decoder error: {db_err}", self.id);
+        }
+    }
+
+    /// The decoder of the connection produced nothing for a while.
+    pub fn on_stall(
+        &self,
+        time: SystemTime,
+        since: SystemTime,
+        bytes: u64,
+        reset: bool,
+        snapshot: serde_json::Value,
+    ) {
+        let stall = Stall {
+            connection_id: self.id,
+            addr: self.addr,
+            time,
+            since,
+            bytes,
+            reset,
+            snapshot,
+        };
+        self.inner.watchdog().record(stall);
+        self.inner.health().on_stall();
+        self.on_anomaly("stall", time);
+    }
+
+    /// Detects stalled decoders.
+    pub fn watchdog(&self) -> &Watchdog {
+        self.inner.watchdog()
+    }
+
+    /// The state of the decoder after the chunk, replaces the previous one.
+    pub fn update_pipeline(
+        &self,
+        time: SystemTime,
+        chunks: u64,
+        raw: bool,
+        snapshot: serde_json::Value,
+    ) {
+        let pipeline = Pipeline::new(self.id, self.addr, time, chunks, raw, snapshot);
+        self.inner.pipelines().update(pipeline);
+    }
+
+    /// The connection is closed, its decoder is gone.
+    pub fn remove_pipeline(&self) {
+        self.inner.pipelines().remove(self.id);
+    }
+
+    /// Number of messages of the connection so far.
+    pub fn seq(&self) -> u64 {
+        self.seq.load(SeqCst)
+    }
+
+    /// Attribute the bytes to the layer, stored with the next update.
+    pub fn count(&self, layer: Layer, incoming: bool, bytes: usize) {
+        self.layers.lock()[incoming as usize].add(layer, bytes as u64);
+    }
+
+    /// The noise frame of `frame` bytes with the prefix carried `plaintext` bytes,
+    /// stored with the next update.
+    pub fn count_frame(&self, incoming: bool, frame: usize, plaintext: usize) {
+        self.framing.lock()[incoming as usize].add(frame, plaintext);
+    }
+
+    /// Replace the counters, when the attribution is done elsewhere.
+    pub fn set_layers(&self, incoming: bool, layers: LayerStats) {
+        self.layers.lock()[incoming as usize] = layers;
+    }
+
+    pub fn set_superseded(&self, survivor: ConnectionId) -> Result<(), DbError> {
+        let sinks = self.inner.sinks();
+        sinks.send(|| SinkEvent::Superseded(SupersededEvent { id: self.id.0, by: survivor.0 }));
+        if !sinks.database() {
+            return Ok(());
         }
+        let mut cn = self.inner.fetch_connection(self.id.0)?;
+        cn.superseded_by = Some(survivor);
         self.inner.put_cn(self.id, cn)
     }
 
+    pub fn add_peer_identity(&self, peer_id: PeerId, timestamp: SystemTime) -> Result<(), DbError> {
+        *self.peer_id.lock() = Some(peer_id);
+        self.inner
+            .peer_directory()
+            .on_connection(peer_id, self.addr.to_string(), timestamp);
+        let sinks = self.inner.sinks();
+        sinks.send(|| SinkEvent::PeerIdentity(PeerIdentityEvent { id: self.id.0, peer_id, timestamp }));
+        if !sinks.database() {
+            return Ok(());
+        }
+        self.inner
+            .put_peer_identity(peer_id, self.addr, self.id, timestamp)
+    }
+
     pub fn add_raw(
         &self,
         encryption_status: EncryptionStatus,
@@ -179,6 +462,27 @@ impl DbGroup {
         time: SystemTime,
         bytes: &[u8],
     ) -> Result<u64, DbError> {
+        let sinks = self.inner.sinks();
+        sinks.send(|| {
+            let (encryption_status, bytes) = (encryption_status.clone(), bytes.to_vec());
+            SinkEvent::Raw(ChunkEvent { id: self.id.0, encryption_status, incoming, time, bytes })
+        });
+        if !sinks.database() {
+            return Ok(0);
+        }
+
+        // raw chunks carry the payload of every stream, redact them entirely
+        let redaction = self.inner.manifest().capture_redaction;
+        let redacted;
+        let bytes = match encryption_status {
+            EncryptionStatus::DecryptedNoise => bytes,
+            _ if redaction.is_none() => bytes,
+            _ => {
+                redacted = redaction.apply(bytes);
+                &redacted
+            }
+        };
+
         let header = ChunkHeader {
             size: bytes.len() as u32,
             time,
@@ -197,9 +501,23 @@ impl DbGroup {
 
 impl Drop for DbGroup {
     fn drop(&mut self) {
+        if Arc::strong_count(&self.alive) > 1 {
+            return;
+        }
         let id = self.id;
+        let sinks = self.inner.sinks();
+        sinks.send(|| SinkEvent::Close(id.0));
+        if !sinks.database() {
+            return;
+        }
         if let Ok(mut cn) = self.inner.fetch_connection(id.0) {
             cn.timestamp_close = SystemTime::now();
+            let [layers_out, layers_in] = self.layers.lock().clone();
+            cn.layers_in = layers_in;
+            cn.layers_out = layers_out;
+            let [framing_out, framing_in] = self.framing.lock().clone();
+            cn.framing_in = framing_in;
+            cn.framing_out = framing_out;
             if let Err(err) = self.inner.put_cn(id, cn) {
                 log::error!("connection {id}, error: {err}")
             }
@@ -214,49 +532,163 @@ pub struct DbStream {
 }
 
 impl DbStream {
+    /// The side of `did` closed or reset the stream, `error` if it does not match
+    /// the state of the stream.
+    pub fn end(
+        &self,
+        did: &DirectedId,
+        kind: StreamEndKind,
+        code: u32,
+        error: String,
+    ) -> Result<(), DbError> {
+        self.group.inner.put_stream_end(&StreamEnd {
+            connection_id: self.group.id,
+            stream_id: self.s_id,
+            timestamp: did.metadata.time,
+            incoming: did.incoming,
+            kind,
+            code,
+            error,
+        })
+    }
+
     pub fn add(
         &self,
         did: &DirectedId,
         stream_kind: StreamKind,
         bytes: &[u8],
     ) -> Result<MessageId, DbError> {
-        let index_ledger_hash = std::env::var("DEBUGGER_INDEX_LEDGER_HASH").is_ok();
+        let timing = MessageTiming::single(did.metadata.time);
+        self.add_timed(did, stream_kind, bytes, timing)
+    }
+
+    /// The message is reassembled from several reads of the socket.
+    pub fn add_timed(
+        &self,
+        did: &DirectedId,
+        stream_kind: StreamKind,
+        bytes: &[u8],
+        timing: MessageTiming,
+    ) -> Result<MessageId, DbError> {
+        self.add_inner(did.incoming, did.metadata.time, stream_kind, bytes, Some(timing))
+    }
+
+    /// The reads of the socket are unknown, the message is imported or forwarded.
+    pub fn add_at(
+        &self,
+        incoming: bool,
+        time: SystemTime,
+        stream_kind: StreamKind,
+        bytes: &[u8],
+    ) -> Result<MessageId, DbError> {
+        self.add_inner(incoming, time, stream_kind, bytes, None)
+    }
 
-        let offset = self.group.add_raw(EncryptionStatus::DecryptedNoise, did.incoming, did.metadata.time, bytes)?;
+    fn add_inner(
+        &self,
+        incoming: bool,
+        time: SystemTime,
+        stream_kind: StreamKind,
+        bytes: &[u8],
+        timing: Option<MessageTiming>,
+    ) -> Result<MessageId, DbError> {
+        let seq = self.group.seq.fetch_add(1, SeqCst);
+        let sinks = self.group.inner.sinks();
+        sinks.send(|| SinkEvent::Message(MessageEvent {
+            id: self.group.id.0,
+            seq,
+            stream_id: self.s_id,
+            stream_kind,
+            incoming,
+            time,
+            bytes: bytes.to_vec(),
+        }));
+        if !sinks.database() {
+            return Ok(MessageId(self.group.messages.fetch_add(1, SeqCst)));
+        }
+
+        if incoming && matches!(stream_kind, StreamKind::IpfsId | StreamKind::IpfsPush) {
+            self.add_announcement(time, bytes);
+        }
+
+        if stream_kind == StreamKind::Meshsub && self.group.inner.gossip_dedup() {
+            if let Some(first) = self.add_duplicate(incoming, time, bytes)? {
+                return Ok(first);
+            }
+        }
 
-        let mut ledger_hashes = vec![];
-        let tys = match stream_kind {
-            StreamKind::Unknown => vec![],
-            StreamKind::Meshsub => {
-                let (tys, hashes) = crate::decode::meshsub::parse_types(bytes, index_ledger_hash)?;
-                ledger_hashes = hashes;
-                tys
+        let index_ledger_hash = std::env::var("DEBUGGER_INDEX_LEDGER_HASH").is_ok();
+
+        let redaction = self.group.inner.manifest().capture_redaction;
+        let triggers = self.group.inner.triggers();
+        if let Some(rate) = triggers.rate() {
+            if self.group.rate.lock().observe(time) > rate {
+                triggers.fire(self.group.addr.ip(), "rate", time);
             }
-            StreamKind::Kad => crate::decode::kademlia::parse_types(bytes)?,
-            StreamKind::Handshake => crate::decode::noise::parse_types(bytes)?,
-            StreamKind::Rpc => crate::decode::rpc::parse_types(bytes)?,
-            StreamKind::IpfsId => vec![MessageType::Identify],
-            StreamKind::IpfsPush => vec![MessageType::IdentifyPush],
-            // TODO: message type (types)
-            StreamKind::IpfsDelta => vec![],
-            StreamKind::PeerExchange => vec![MessageType::PeerExchange],
-            StreamKind::BitswapExchange => vec![MessageType::BitswapExchange],
-            StreamKind::NodeStatus => vec![MessageType::NodeStatus],
-            StreamKind::Select => vec![MessageType::Select],
-            StreamKind::Mplex => vec![MessageType::Mplex],
-            StreamKind::Yamux => vec![MessageType::Yamux],
+        }
+        let unredacted =
+            redaction.covers(stream_kind) && triggers.is_zoomed(self.group.addr.ip(), time);
+        let offset = if redaction.covers(stream_kind) && !unredacted {
+            let redacted = redaction.apply(bytes);
+            self.group.add_raw(EncryptionStatus::DecryptedNoise, incoming, time, &redacted)?
+        } else {
+            self.group.add_raw(EncryptionStatus::DecryptedNoise, incoming, time, bytes)?
         };
 
+        let (tys, ledger_hashes) =
+            match crate::decode::parse_types(stream_kind, bytes, index_ledger_hash) {
+                Ok(v) => v,
+                Err(err) => {
+                    self.group.report(DecoderError {
+                        layer: Layer::Payload,
+                        connection_id: self.group.id,
+                        addr: self.group.addr,
+                        stream_id: Some(self.s_id),
+                        incoming,
+                        time,
+                        reason: format!("{stream_kind}: {err}"),
+                        context: bytes[..bytes.len().min(DecoderError::CONTEXT)].to_vec(),
+                        recoverable: true,
+                    });
+                    return Err(err.into());
+                }
+            };
+
+        self.observe_anomalies(&tys, time)?;
+
         let id = MessageId(self.group.messages.fetch_add(1, SeqCst));
+        if tys
+            .iter()
+            .any(|ty| matches!(ty, MessageType::Subscribe | MessageType::Unsubscribe))
+        {
+            self.add_subscriptions(id, incoming, time, bytes)?;
+        }
+        if tys.iter().any(|ty| {
+            matches!(
+                ty,
+                MessageType::PublishNewState
+                    | MessageType::PublishSnarkPoolDiff
+                    | MessageType::PublishTransactionPoolDiff
+            )
+        }) {
+            self.add_gossip(id, incoming, time, bytes)?;
+        }
         let v = Message {
             connection_id: self.group.id,
             stream_id: self.s_id,
             stream_kind,
-            incoming: did.incoming,
-            timestamp: did.metadata.time,
+            incoming,
+            timestamp: time,
             offset,
             size: bytes.len() as u32,
             brief: tys.iter().map(|ty| ty.to_string()).join(","),
+            seq,
+            unredacted,
+            decoder_version: crate::decode::DECODER_VERSION,
+            transfer_ns: timing.map_or(0, |t| {
+                time.duration_since(t.first_byte).unwrap_or_default().as_nanos() as u64
+            }),
+            chunks: timing.map_or(0, |t| t.chunks),
         };
         self.group.inner
             .put_message(&self.group.addr, id, v, tys, ledger_hashes)?;
@@ -264,4 +696,148 @@ impl DbStream {
 
         Ok(id)
     }
+
+    fn add_announcement(&self, time: SystemTime, bytes: &[u8]) {
+        let announcement = match crate::decode::identify::announcement(bytes) {
+            Ok(v) => v,
+            Err(err) => {
+                log::debug!("{}: cannot parse identify: {err}", self.group.id);
+                return;
+            }
+        };
+        if let Some(addr) = announcement.observed_addr {
+            if let Err(err) = self.group.inner.set_observed_addr(self.group.id, addr) {
+                log::error!("{}: {err}", self.group.id);
+            }
+        }
+        let peer_id = *self.group.peer_id.lock();
+        if let Some(agent_version) = announcement.agent_version.clone().filter(|v| !v.is_empty()) {
+            let sighting = AgentVersionSighting {
+                timestamp: time,
+                connection_id: self.group.id,
+                addr: self.group.addr,
+                peer_id: peer_id.map(|v| v.to_base58()).unwrap_or_default(),
+                agent_version,
+            };
+            if let Err(err) = self.group.inner.put_agent_version(&sighting) {
+                log::error!("{}: {err}", self.group.id);
+            }
+        }
+        let directory = self.group.inner.peer_directory();
+        let peer_id = match peer_id {
+            Some(v) if directory.is_enabled() => v,
+            _ => return,
+        };
+        directory.on_identify(
+            peer_id,
+            announcement.agent_version,
+            announcement.listen_addrs,
+            time,
+        );
+    }
+
+    fn add_subscriptions(
+        &self,
+        id: MessageId,
+        incoming: bool,
+        time: SystemTime,
+        bytes: &[u8],
+    ) -> Result<(), DbError> {
+        for (topic, subscribe) in crate::decode::meshsub::parse_subscriptions(bytes)? {
+            if !self.group.profile.knows_topic(&topic) {
+                log::warn!(
+                    "{}, topic {topic} is not in the profile {}",
+                    self.group.id,
+                    self.group.profile.name
+                );
+            }
+            let v = SubscriptionChange {
+                timestamp: time,
+                message_id: id,
+                connection_id: self.group.id,
+                addr: self.group.addr,
+                incoming,
+                topic,
+                subscribe,
+            };
+            self.group.inner.put_subscription(&v)?;
+        }
+
+        Ok(())
+    }
+
+    fn observe_anomalies(&self, tys: &[MessageType], time: SystemTime) -> Result<(), DbError> {
+        let anomalies = self.group.inner.anomaly_detector()
+            .observe(self.group.addr.ip(), tys, time);
+        for anomaly in anomalies {
+            self.group.inner.put_anomaly(&anomaly)?;
+        }
+
+        Ok(())
+    }
+
+    /// If every data of the message was observed before, store the references
+    /// instead of the message. Returns the id of the message which brought the data first.
+    fn add_duplicate(
+        &self,
+        incoming: bool,
+        time: SystemTime,
+        bytes: &[u8],
+    ) -> Result<Option<MessageId>, DbError> {
+        let data = match crate::decode::meshsub::parse_publish_only(bytes) {
+            Ok(Some(v)) => v,
+            // let the regular path report the error
+            Ok(None) | Err(_) => return Ok(None),
+        };
+        let mut duplicates = Vec::with_capacity(data.len());
+        for (topic, data) in data {
+            let first = match self.group.inner.fetch_gossip_first_seen_of(&data)? {
+                Some(v) => v,
+                None => return Ok(None),
+            };
+            duplicates.push(GossipDuplicate {
+                timestamp: time,
+                connection_id: self.group.id,
+                addr: self.group.addr,
+                incoming,
+                topic,
+                hash: first.hash,
+                size: data.len() as u32,
+                first: first.message_id,
+            });
+        }
+        let (tys, _) = crate::decode::parse_types(StreamKind::Meshsub, bytes, false)?;
+        self.observe_anomalies(&tys, time)?;
+        for v in &duplicates {
+            self.group.inner.put_gossip_duplicate(v)?;
+        }
+
+        Ok(duplicates.first().map(|v| v.first))
+    }
+
+    fn add_gossip(
+        &self,
+        id: MessageId,
+        incoming: bool,
+        time: SystemTime,
+        bytes: &[u8],
+    ) -> Result<(), DbError> {
+        for (topic, data) in crate::decode::meshsub::parse_protobuf_publish_topics(bytes)
+            .map_err(DecodeError::Protobuf)?
+        {
+            let v = GossipFirstSeen {
+                timestamp: time,
+                message_id: id,
+                connection_id: self.group.id,
+                addr: self.group.addr,
+                incoming,
+                topic,
+                hash: String::new(),
+                size: data.len() as u32,
+            };
+            self.group.inner.put_gossip_first_seen(v, &data)?;
+        }
+
+        Ok(())
+    }
 }
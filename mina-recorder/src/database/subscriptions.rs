@@ -0,0 +1,122 @@
+use std::{collections::BTreeMap, net::IpAddr, time::SystemTime};
+
+use libp2p_core::PeerId;
+use serde::Serialize;
+
+use super::types::SubscriptionChange;
+
+/// Whose announcements the timeline shows.
+pub enum SubscriptionPeer {
+    /// the local node sends the same announcement to every peer,
+    /// only the changes of its state count
+    Local,
+    Ip(IpAddr),
+    PeerId(PeerId),
+}
+
+/// Topic subscriptions of a peer, or of the local node, during the range.
+/// The peers announce all their subscriptions on each new connection,
+/// only the announcements which change the state are listed.
+#[derive(Serialize)]
+pub struct SubscriptionTimeline {
+    pub since: SystemTime,
+    pub until: SystemTime,
+    /// topics subscribed at `since`
+    pub initial: Vec<String>,
+    pub changes: Vec<SubscriptionChange>,
+    /// topics subscribed at `until`
+    pub subscribed: Vec<String>,
+    /// including the announcements which change nothing
+    pub announcements: usize,
+}
+
+fn subscribed(state: &BTreeMap<String, bool>) -> Vec<String> {
+    state
+        .iter()
+        .filter(|(_, subscribed)| **subscribed)
+        .map(|(topic, _)| topic.clone())
+        .collect()
+}
+
+impl SubscriptionTimeline {
+    /// The `changes` must be ordered by time and contain only the announcements of one side.
+    pub fn build<I>(changes: I, since: SystemTime, until: SystemTime) -> Self
+    where
+        I: IntoIterator<Item = SubscriptionChange>,
+    {
+        let mut state = BTreeMap::<String, bool>::new();
+        let mut initial = None;
+        let mut timeline = vec![];
+        let mut announcements = 0;
+        for change in changes
+            .into_iter()
+            .take_while(|change| change.timestamp < until)
+        {
+            let in_range = change.timestamp >= since;
+            if in_range && initial.is_none() {
+                initial = Some(subscribed(&state));
+            }
+            let previous = state.insert(change.topic.clone(), change.subscribe);
+            if in_range {
+                announcements += 1;
+                if previous != Some(change.subscribe) {
+                    timeline.push(change);
+                }
+            }
+        }
+
+        SubscriptionTimeline {
+            since,
+            until,
+            initial: initial.unwrap_or_else(|| subscribed(&state)),
+            changes: timeline,
+            subscribed: subscribed(&state),
+            announcements,
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn subscription_timeline() {
+    use std::time::Duration;
+
+    use super::types::{ConnectionId, MessageId};
+
+    let since = SystemTime::UNIX_EPOCH + Duration::from_secs(1_675_166_400);
+    let until = since + Duration::from_secs(3600);
+    let change = |secs: i64, connection_id, topic: &str, subscribe| SubscriptionChange {
+        timestamp: if secs < 0 {
+            since - Duration::from_secs(-secs as u64)
+        } else {
+            since + Duration::from_secs(secs as u64)
+        },
+        message_id: MessageId(0),
+        connection_id: ConnectionId(connection_id),
+        addr: "1.2.3.4:8302".parse().unwrap(),
+        incoming: true,
+        topic: topic.to_owned(),
+        subscribe,
+    };
+    let changes = vec![
+        change(-10, 0, "coda/consensus-messages/0.0.1", true),
+        change(-10, 0, "mina/block/1.0.0", true),
+        // reconnected, announces the same
+        change(10, 1, "coda/consensus-messages/0.0.1", true),
+        change(10, 1, "mina/block/1.0.0", true),
+        change(20, 1, "mina/block/1.0.0", false),
+        // after the range
+        change(4000, 1, "mina/block/1.0.0", true),
+    ];
+
+    let timeline = SubscriptionTimeline::build(changes, since, until);
+    assert_eq!(
+        timeline.initial,
+        ["coda/consensus-messages/0.0.1", "mina/block/1.0.0"]
+    );
+    assert_eq!(timeline.announcements, 3);
+    assert_eq!(timeline.changes.len(), 1);
+    assert_eq!(timeline.changes[0].topic, "mina/block/1.0.0");
+    assert!(!timeline.changes[0].subscribe);
+    assert_eq!(timeline.subscribed, ["coda/consensus-messages/0.0.1"]);
+}
@@ -0,0 +1,203 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    env,
+    net::IpAddr,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+
+/// When the capture is redacted, store the payloads of a peer in full
+/// for some time after something interesting happens.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TriggerRule {
+    /// the connection exchanges more messages per second than this
+    Rate(u64),
+    /// decryption or parsing of the connection failed
+    Anomaly,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TriggerConfig {
+    pub rules: Vec<TriggerRule>,
+    /// how long the payloads are stored in full after the trigger fired
+    pub duration: Duration,
+}
+
+impl Default for TriggerConfig {
+    fn default() -> Self {
+        TriggerConfig {
+            rules: vec![],
+            duration: Duration::from_secs(300),
+        }
+    }
+}
+
+impl FromStr for TriggerConfig {
+    type Err = String;
+
+    /// Comma separated rules, like `rate:100,anomaly,for:600`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = TriggerConfig::default();
+        for item in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (name, value) = item.split_once(':').unwrap_or((item, ""));
+            let number = || value.parse::<u64>().map_err(|_| item.to_owned());
+            match name {
+                "rate" => config.rules.push(TriggerRule::Rate(number()?)),
+                "anomaly" => config.rules.push(TriggerRule::Anomaly),
+                "for" => config.duration = Duration::from_secs(number()?),
+                _ => return Err(item.to_owned()),
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// The trigger fired, the payloads of the peer are stored in full until `until`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Fired {
+    pub ip: IpAddr,
+    pub reason: String,
+    pub time: SystemTime,
+    pub until: SystemTime,
+}
+
+#[derive(Default)]
+pub struct CaptureTriggers {
+    config: TriggerConfig,
+    state: Mutex<TriggersState>,
+}
+
+#[derive(Default)]
+struct TriggersState {
+    zoomed: BTreeMap<IpAddr, SystemTime>,
+    fired: VecDeque<Fired>,
+}
+
+impl CaptureTriggers {
+    const HISTORY: usize = 0x400;
+
+    pub fn new(config: TriggerConfig) -> Self {
+        CaptureTriggers {
+            config,
+            state: Mutex::default(),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let config = match env::var("CAPTURE_TRIGGERS") {
+            Ok(s) => s.parse().unwrap_or_else(|item| {
+                log::error!("unknown capture trigger {item}, ignore triggers");
+                TriggerConfig::default()
+            }),
+            Err(_) => TriggerConfig::default(),
+        };
+        Self::new(config)
+    }
+
+    pub fn config(&self) -> &TriggerConfig {
+        &self.config
+    }
+
+    pub fn rate(&self) -> Option<u64> {
+        self.config.rules.iter().find_map(|rule| match rule {
+            TriggerRule::Rate(rate) => Some(*rate),
+            _ => None,
+        })
+    }
+
+    /// The payloads of the peer should be stored in full.
+    pub fn is_zoomed(&self, ip: IpAddr, time: SystemTime) -> bool {
+        if self.config.rules.is_empty() {
+            return false;
+        }
+        self.state
+            .lock()
+            .zoomed
+            .get(&ip)
+            .map_or(false, |until| time < *until)
+    }
+
+    pub fn fire(&self, ip: IpAddr, reason: &str, time: SystemTime) {
+        let until = time + self.config.duration;
+        let mut state = self.state.lock();
+        let previous = state.zoomed.insert(ip, until);
+        // already zoomed, just extend
+        if previous.map_or(false, |previous| time < previous) {
+            return;
+        }
+        log::info!("capture trigger {reason} fired for {ip}");
+        if state.fired.len() == Self::HISTORY {
+            state.fired.pop_front();
+        }
+        state.fired.push_back(Fired {
+            ip,
+            reason: reason.to_owned(),
+            time,
+            until,
+        });
+    }
+
+    pub fn on_anomaly(&self, ip: IpAddr, reason: &str, time: SystemTime) {
+        if self.config.rules.contains(&TriggerRule::Anomaly) {
+            self.fire(ip, reason, time);
+        }
+    }
+
+    /// Recent firings, the last is the latest.
+    pub fn fired(&self) -> Vec<Fired> {
+        self.state.lock().fired.iter().cloned().collect()
+    }
+}
+
+/// Counts the messages of the connection in one second windows.
+#[derive(Default)]
+pub struct RateMeter {
+    start: Option<SystemTime>,
+    count: u64,
+}
+
+impl RateMeter {
+    /// Returns the number of messages in the current window.
+    pub fn observe(&mut self, time: SystemTime) -> u64 {
+        let fresh = self.start.map_or(true, |start| {
+            time.duration_since(start).unwrap_or_default() >= Duration::from_secs(1)
+        });
+        if fresh {
+            self.start = Some(time);
+            self.count = 0;
+        }
+        self.count += 1;
+        self.count
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn capture_triggers() {
+    let config = "rate:2,anomaly,for:60".parse::<TriggerConfig>().unwrap();
+    assert_eq!(config.rules, [TriggerRule::Rate(2), TriggerRule::Anomaly]);
+    assert!("rate:x".parse::<TriggerConfig>().is_err());
+
+    let triggers = CaptureTriggers::new(config);
+    let ip = "1.2.3.4".parse().unwrap();
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_675_166_400);
+    let mut meter = RateMeter::default();
+    for i in 0..3 {
+        let count = meter.observe(time + Duration::from_millis(i * 100));
+        if count > triggers.rate().unwrap() {
+            triggers.fire(ip, "rate", time);
+        }
+    }
+    assert_eq!(meter.observe(time + Duration::from_secs(2)), 1);
+    assert!(triggers.is_zoomed(ip, time + Duration::from_secs(59)));
+    assert!(!triggers.is_zoomed(ip, time + Duration::from_secs(60)));
+    assert!(!triggers.is_zoomed("1.2.3.5".parse().unwrap(), time));
+
+    // extends the window, but does not record another firing
+    triggers.on_anomaly(ip, "noise", time + Duration::from_secs(30));
+    assert!(triggers.is_zoomed(ip, time + Duration::from_secs(60)));
+    assert_eq!(triggers.fired().len(), 1);
+}
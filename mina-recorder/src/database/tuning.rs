@@ -0,0 +1,184 @@
+use std::{collections::BTreeMap, env, str::FromStr};
+
+use serde::Serialize;
+
+/// RocksDB options configurable through environment variables,
+/// the default values are the same as RocksDB defaults.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DbTuning {
+    pub compaction_style: CompactionStyle,
+    pub block_cache_size: Option<usize>,
+    pub max_total_wal_size: Option<u64>,
+    pub wal_ttl_seconds: Option<u64>,
+    pub wal_size_limit_mb: Option<u64>,
+    pub manual_wal_flush: bool,
+    /// nothing is written to the disk, only the latest this many messages are kept in memory
+    pub in_memory: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompactionStyle {
+    #[default]
+    Level,
+    Universal,
+    Fifo,
+}
+
+impl FromStr for CompactionStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "level" => Ok(CompactionStyle::Level),
+            "universal" => Ok(CompactionStyle::Universal),
+            "fifo" => Ok(CompactionStyle::Fifo),
+            _ => Err(s.to_owned()),
+        }
+    }
+}
+
+impl From<CompactionStyle> for rocksdb::DBCompactionStyle {
+    fn from(v: CompactionStyle) -> Self {
+        match v {
+            CompactionStyle::Level => rocksdb::DBCompactionStyle::Level,
+            CompactionStyle::Universal => rocksdb::DBCompactionStyle::Universal,
+            CompactionStyle::Fifo => rocksdb::DBCompactionStyle::Fifo,
+        }
+    }
+}
+
+impl DbTuning {
+    pub fn from_env() -> Self {
+        fn var<T: FromStr>(name: &str) -> Option<T> {
+            let s = env::var(name).ok()?;
+            match s.parse() {
+                Ok(v) => Some(v),
+                Err(_) => {
+                    log::error!("cannot parse {name}={s}, ignore");
+                    None
+                }
+            }
+        }
+
+        DbTuning {
+            compaction_style: var("DB_COMPACTION_STYLE").unwrap_or_default(),
+            block_cache_size: var("DB_BLOCK_CACHE_SIZE"),
+            max_total_wal_size: var("DB_MAX_TOTAL_WAL_SIZE"),
+            wal_ttl_seconds: var("DB_WAL_TTL_SECONDS"),
+            wal_size_limit_mb: var("DB_WAL_SIZE_LIMIT_MB"),
+            manual_wal_flush: env::var("DB_MANUAL_WAL_FLUSH").is_ok(),
+            in_memory: var("DB_IN_MEMORY"),
+        }
+    }
+
+    pub fn block_cache(&self) -> Option<rocksdb::Cache> {
+        self.block_cache_size.map(rocksdb::Cache::new_lru_cache)
+    }
+
+    /// Options of the whole database, statistics are always enabled.
+    pub fn apply(&self, opts: &mut rocksdb::Options) {
+        opts.enable_statistics();
+        if let Some(size) = self.max_total_wal_size {
+            opts.set_max_total_wal_size(size);
+        }
+        if let Some(secs) = self.wal_ttl_seconds {
+            opts.set_wal_ttl_seconds(secs);
+        }
+        if let Some(size) = self.wal_size_limit_mb {
+            opts.set_wal_size_limit_mb(size);
+        }
+        opts.set_manual_wal_flush(self.manual_wal_flush);
+    }
+
+    /// Options of a column family, the block cache is shared between column families.
+    pub fn apply_cf(&self, opts: &mut rocksdb::Options, cache: Option<&rocksdb::Cache>) {
+        opts.set_compaction_style(self.compaction_style.into());
+        if let Some(cache) = cache {
+            let mut table_opts = rocksdb::BlockBasedOptions::default();
+            table_opts.set_block_cache(cache);
+            opts.set_block_based_table_factory(&table_opts);
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct DbStatistics {
+    pub write_amplification: Option<f64>,
+    pub bytes_written: u64,
+    pub flush_write_bytes: u64,
+    pub compact_read_bytes: u64,
+    pub compact_write_bytes: u64,
+    pub compaction_count: u64,
+    pub compaction_time_micros: u64,
+    pub stall_count: u64,
+    pub stall_micros: u64,
+    pub tuning: DbTuning,
+}
+
+impl DbStatistics {
+    /// Parse the text dump of `rocksdb::Options::get_statistics`.
+    /// Ticker lines look like `rocksdb.bytes.written COUNT : 123`,
+    /// histogram lines look like `rocksdb.db.write.stall P50 : 0.0 ... COUNT : 1 SUM : 2`.
+    pub fn parse(s: &str, tuning: DbTuning) -> Self {
+        let mut values = BTreeMap::<(&str, &str), u64>::new();
+        for line in s.lines() {
+            let mut words = line.split_whitespace();
+            let name = match words.next() {
+                Some(v) => v,
+                None => continue,
+            };
+            while let (Some(key), Some(":"), Some(value)) =
+                (words.next(), words.next(), words.next())
+            {
+                if let Ok(value) = value.parse::<f64>() {
+                    values.insert((name, key), value as u64);
+                }
+            }
+        }
+        let get = |name, key| values.get(&(name, key)).cloned().unwrap_or_default();
+
+        let bytes_written = get("rocksdb.bytes.written", "COUNT");
+        let flush_write_bytes = get("rocksdb.flush.write.bytes", "COUNT");
+        let compact_write_bytes = get("rocksdb.compact.write.bytes", "COUNT");
+        let write_amplification = if bytes_written != 0 {
+            Some((flush_write_bytes + compact_write_bytes) as f64 / bytes_written as f64)
+        } else {
+            None
+        };
+
+        DbStatistics {
+            write_amplification,
+            bytes_written,
+            flush_write_bytes,
+            compact_read_bytes: get("rocksdb.compact.read.bytes", "COUNT"),
+            compact_write_bytes,
+            compaction_count: get("rocksdb.compaction.times.micros", "COUNT"),
+            compaction_time_micros: get("rocksdb.compaction.times.micros", "SUM"),
+            stall_count: get("rocksdb.db.write.stall", "COUNT"),
+            stall_micros: get("rocksdb.stall.micros", "COUNT"),
+            tuning,
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn parse_statistics() {
+    let s = "\
+rocksdb.bytes.written COUNT : 1000
+rocksdb.flush.write.bytes COUNT : 1200
+rocksdb.compact.read.bytes COUNT : 600
+rocksdb.compact.write.bytes COUNT : 800
+rocksdb.stall.micros COUNT : 42
+rocksdb.compaction.times.micros P50 : 10.000000 P95 : 20.000000 P99 : 30.000000 P100 : 40.000000 COUNT : 3 SUM : 75
+rocksdb.db.write.stall P50 : 0.000000 P95 : 0.000000 P99 : 0.000000 P100 : 0.000000 COUNT : 2 SUM : 5
+";
+    let stats = DbStatistics::parse(s, DbTuning::default());
+    assert_eq!(stats.write_amplification, Some(2.0));
+    assert_eq!(stats.compact_read_bytes, 600);
+    assert_eq!(stats.compaction_count, 3);
+    assert_eq!(stats.compaction_time_micros, 75);
+    assert_eq!(stats.stall_count, 2);
+    assert_eq!(stats.stall_micros, 42);
+}
@@ -2,8 +2,9 @@ use std::{
     time::{SystemTime, Duration, UNIX_EPOCH},
     fmt,
     str::FromStr,
-    net::SocketAddr,
+    net::{SocketAddr, IpAddr},
     ops::AddAssign,
+    collections::BTreeMap,
 };
 
 use mina_p2p_messages::{binprot::BinProtRead, v2, gossip::GossipNetMessageV2};
@@ -11,9 +12,11 @@ use radiation::{Absorb, Emit};
 
 use serde::{Serialize, Deserialize};
 
+use super::{framing::FramingStats, geoip::PeerGeo, nat::NatMapping};
+
 use crate::{
-    event::ConnectionInfo, custom_coding, strace::StraceLine, libp2p_helper::CapnpEvent,
-    meshsub_stats::Hash,
+    event::ConnectionInfo, custom_coding, kube::PodMeta, strace::StraceLine,
+    libp2p_helper::CapnpEvent, meshsub_stats::Hash, tcp_info::TcpParams,
 };
 
 #[derive(
@@ -46,9 +49,89 @@ pub struct Connection {
     pub timestamp_close: SystemTime,
 
     pub alias: String,
+
+    /// The connection is discarded after simultaneous connect in favor of this one.
+    #[custom_absorb(custom_coding::trailing_cn_opt_absorb)]
+    #[custom_emit(custom_coding::trailing_cn_opt_emit)]
+    pub superseded_by: Option<ConnectionId>,
+
+    #[custom_absorb(custom_coding::trailing_layers_absorb)]
+    pub layers_in: LayerStats,
+    #[custom_absorb(custom_coding::trailing_layers_absorb)]
+    pub layers_out: LayerStats,
+
+    /// The other end of the connection is a tracked process on the same host.
+    #[custom_absorb(custom_coding::trailing_cn_opt_absorb)]
+    #[custom_emit(custom_coding::trailing_cn_opt_emit)]
+    pub paired_with: Option<ConnectionId>,
+    /// Alias of the process at the other end, if it is tracked.
+    #[custom_absorb(custom_coding::trailing_string_absorb)]
+    pub peer_alias: String,
+
+    /// Country and autonomous system of the remote address, if `GEOIP_DB` is set.
+    #[custom_absorb(custom_coding::trailing_geo_absorb)]
+    #[serde(skip_serializing_if = "PeerGeo::is_empty")]
+    pub geo: PeerGeo,
+    /// From `PEER_NAMES` or the reverse lookup.
+    #[custom_absorb(custom_coding::trailing_string_absorb)]
+    pub peer_name: String,
+
+    /// The pod of the debuggee process, if `K8S_METADATA` is set.
+    #[custom_absorb(custom_coding::trailing_pod_absorb)]
+    #[serde(skip_serializing_if = "PodMeta::is_empty")]
+    pub local_pod: PodMeta,
+    /// The pod which owns the remote address.
+    #[custom_absorb(custom_coding::trailing_pod_absorb)]
+    #[serde(skip_serializing_if = "PodMeta::is_empty")]
+    pub remote_pod: PodMeta,
+
+    /// The local address of the socket, empty if unknown.
+    #[custom_absorb(custom_coding::trailing_string_absorb)]
+    pub local_addr: String,
+    /// The multiaddr of the node as the peer observes it, the peer tells it by identify.
+    #[custom_absorb(custom_coding::trailing_string_absorb)]
+    pub observed_addr: String,
+
+    /// The noise frames of the transport phase against the plaintext they carry.
+    #[custom_absorb(custom_coding::trailing_framing_absorb)]
+    pub framing_in: FramingStats,
+    #[custom_absorb(custom_coding::trailing_framing_absorb)]
+    pub framing_out: FramingStats,
+
+    /// The parameters the kernel negotiated for the socket, read at connect or accept.
+    #[custom_absorb(custom_coding::trailing_tcp_absorb)]
+    #[serde(skip_serializing_if = "TcpParams::is_empty")]
+    pub tcp: TcpParams,
 }
 
 impl Connection {
+    /// The connection just opened, nothing else is known about it yet.
+    pub fn new(info: ConnectionInfo, incoming: bool, alias: String, timestamp: SystemTime) -> Self {
+        Connection {
+            info,
+            incoming,
+            timestamp,
+            stats_in: ConnectionStats::default(),
+            stats_out: ConnectionStats::default(),
+            timestamp_close: UNIX_EPOCH,
+            alias,
+            superseded_by: None,
+            layers_in: LayerStats::default(),
+            layers_out: LayerStats::default(),
+            paired_with: None,
+            peer_alias: String::new(),
+            geo: PeerGeo::default(),
+            peer_name: String::new(),
+            local_pod: PodMeta::default(),
+            remote_pod: PodMeta::default(),
+            local_addr: String::new(),
+            observed_addr: String::new(),
+            framing_in: FramingStats::default(),
+            framing_out: FramingStats::default(),
+            tcp: TcpParams::default(),
+        }
+    }
+
     pub fn post_process(&self, now: Option<SystemTime>) -> serde_json::Value {
         let end = if self.timestamp_close == UNIX_EPOCH {
             now.unwrap_or_else(SystemTime::now)
@@ -65,6 +148,10 @@ impl Connection {
         v.as_object_mut()
             .expect("self must be a structure")
             .insert("stats_out".to_owned(), stats_out);
+        let nat = serde_json::to_value(NatMapping::of(self)).expect("must not fail");
+        v.as_object_mut()
+            .expect("self must be a structure")
+            .insert("nat".to_owned(), nat);
 
         v
     }
@@ -90,6 +177,105 @@ impl ConnectionStats {
     }
 }
 
+/// Protocol layer the bytes on the wire belong to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Layer {
+    Pnet,
+    Select,
+    Noise,
+    Mux,
+    Payload,
+    Unknown,
+}
+
+impl fmt::Display for Layer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Layer::Pnet => write!(f, "pnet"),
+            Layer::Select => write!(f, "select"),
+            Layer::Noise => write!(f, "noise"),
+            Layer::Mux => write!(f, "mux"),
+            Layer::Payload => write!(f, "payload"),
+            Layer::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// Bytes on the wire attributed to the protocol layers, the sum is the total traffic.
+#[derive(Default, Clone, Debug, Absorb, Emit, Serialize)]
+pub struct LayerStats {
+    /// the nonce of the private network
+    pub pnet: u64,
+    /// multistream select negotiation of the connection and of each stream
+    pub select: u64,
+    /// the handshake, the length and the authentication tag of each frame
+    pub noise: u64,
+    /// headers and control frames of yamux or mplex
+    pub mux: u64,
+    /// messages of the application protocols
+    pub payload: u64,
+    /// not decrypted, so cannot be attributed
+    pub unknown: u64,
+}
+
+impl LayerStats {
+    pub fn add(&mut self, layer: Layer, bytes: u64) {
+        let counter = match layer {
+            Layer::Pnet => &mut self.pnet,
+            Layer::Select => &mut self.select,
+            Layer::Noise => &mut self.noise,
+            Layer::Mux => &mut self.mux,
+            Layer::Payload => &mut self.payload,
+            Layer::Unknown => &mut self.unknown,
+        };
+        *counter += bytes;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.pnet + self.select + self.noise + self.mux + self.payload + self.unknown
+    }
+}
+
+impl AddAssign<&LayerStats> for LayerStats {
+    fn add_assign(&mut self, rhs: &LayerStats) {
+        self.pnet += rhs.pnet;
+        self.select += rhs.select;
+        self.noise += rhs.noise;
+        self.mux += rhs.mux;
+        self.payload += rhs.payload;
+        self.unknown += rhs.unknown;
+    }
+}
+
+/// The attribution summed over the connections.
+#[derive(Default, Serialize)]
+pub struct LayerReport {
+    pub connections: usize,
+    pub incoming: LayerStats,
+    pub outgoing: LayerStats,
+    /// the share of the traffic which is not the payload
+    pub overhead: f64,
+}
+
+impl FromIterator<Connection> for LayerReport {
+    fn from_iter<T: IntoIterator<Item = Connection>>(iter: T) -> Self {
+        let mut report = iter
+            .into_iter()
+            .fold(LayerReport::default(), |mut report, cn| {
+                report.connections += 1;
+                report.incoming += &cn.layers_in;
+                report.outgoing += &cn.layers_out;
+                report
+            });
+        let total = report.incoming.total() + report.outgoing.total();
+        let payload = report.incoming.payload + report.outgoing.payload;
+        if total != 0 {
+            report.overhead = 1.0 - payload as f64 / total as f64;
+        }
+        report
+    }
+}
+
 impl AddAssign<ConnectionStats> for ConnectionStats {
     fn add_assign(&mut self, rhs: ConnectionStats) {
         self.total_bytes += rhs.total_bytes;
@@ -280,9 +466,57 @@ pub struct Message {
     pub offset: u64,
     pub size: u32,
     pub brief: String,
+    /// Position of the message within its connection, starting from 0 and without gaps.
+    /// Zero for every message of the databases recorded before it was introduced.
+    #[custom_absorb(custom_coding::trailing_u64_absorb)]
+    #[custom_emit(custom_coding::trailing_u64_emit)]
+    pub seq: u64,
+    /// The capture is redacted, but the payload is stored in full, because a trigger fired.
+    #[custom_absorb(custom_coding::trailing_bool_absorb)]
+    pub unredacted: bool,
+    /// `decode::DECODER_VERSION` which produced `brief` and the indexes of the message.
+    /// Zero for every message of the databases recorded before it was introduced.
+    #[custom_absorb(custom_coding::trailing_u32_absorb)]
+    pub decoder_version: u32,
+    /// Nanoseconds from the first byte of the message to the last one, which arrived at
+    /// `timestamp`. Zero for every message of the databases recorded before it was introduced.
+    #[custom_absorb(custom_coding::trailing_u64_absorb)]
+    #[custom_emit(custom_coding::trailing_u64_emit)]
+    pub transfer_ns: u64,
+    /// How many reads of the socket carried the bytes of the message, zero if it is unknown.
+    #[custom_absorb(custom_coding::trailing_u32_absorb)]
+    pub chunks: u32,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl Message {
+    /// The first byte of the message arrived, `None` if it is unknown.
+    pub fn first_byte(&self) -> Option<SystemTime> {
+        if self.chunks == 0 {
+            None
+        } else {
+            Some(self.timestamp - Duration::from_nanos(self.transfer_ns))
+        }
+    }
+}
+
+/// The reads of the socket the message is reassembled from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MessageTiming {
+    pub first_byte: SystemTime,
+    pub chunks: u32,
+}
+
+impl MessageTiming {
+    /// The message arrived in a single read.
+    pub fn single(time: SystemTime) -> Self {
+        MessageTiming {
+            first_byte: time,
+            chunks: 1,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FullMessage {
     pub connection_id: ConnectionId,
     pub remote_addr: SocketAddr,
@@ -293,6 +527,461 @@ pub struct FullMessage {
     // dynamic type, the type is depend on `stream_kind`
     pub message: serde_json::Value,
     pub size: u32,
+    #[serde(default)]
+    pub seq: u64,
+    #[serde(default)]
+    pub decoder_version: u32,
+    /// the last byte arrived at `timestamp`
+    #[serde(default)]
+    pub first_byte: Option<SystemTime>,
+    #[serde(default)]
+    pub chunks: u32,
+}
+
+/// Which versions of the decoders produced the messages of the capture.
+#[derive(Serialize)]
+pub struct DecoderVersions {
+    /// `decode::DECODER_VERSION` of this build
+    pub current: u32,
+    pub versions: BTreeMap<u32, DecoderVersionStats>,
+}
+
+/// The messages produced by one version of the decoders.
+#[derive(Serialize)]
+pub struct DecoderVersionStats {
+    pub messages: u64,
+    pub first_id: u64,
+    pub last_id: u64,
+    pub since: SystemTime,
+    pub until: SystemTime,
+}
+
+#[derive(Default, Debug, Serialize)]
+pub struct RedecodeSummary {
+    /// decoded again by the current version
+    pub messages: u64,
+    /// the payload is redacted at capture time, nothing to decode
+    pub skipped: u64,
+    /// the current version cannot decode the message
+    pub failed: u64,
+}
+
+/// One run of the node, from exec until exit.
+#[derive(Clone, Absorb, Emit, Serialize)]
+pub struct Session {
+    pub alias: String,
+    pub pid: u32,
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub start: SystemTime,
+    /// `UNIX_EPOCH` means the session is not finished
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub end: SystemTime,
+    pub first_connection_id: ConnectionId,
+    pub first_message_id: MessageId,
+    /// exclusive, the session is in range `first_connection_id..end_connection_id`
+    pub end_connection_id: ConnectionId,
+    /// exclusive, the session is in range `first_message_id..end_message_id`
+    pub end_message_id: MessageId,
+}
+
+/// One line of the structured log of the node.
+#[derive(Clone, Absorb, Emit)]
+pub struct NodeLogLine {
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub timestamp: SystemTime,
+    pub level: String,
+    pub message: String,
+    /// json encoded
+    pub metadata: String,
+}
+
+impl NodeLogLine {
+    pub fn post_process(self) -> serde_json::Value {
+        let metadata = serde_json::from_str::<serde_json::Value>(&self.metadata)
+            .unwrap_or(serde_json::Value::Null);
+        serde_json::json!({
+            "timestamp": self.timestamp,
+            "level": self.level,
+            "message": self.message,
+            "metadata": metadata,
+        })
+    }
+}
+
+/// Snapshot of the node state taken from its graphql endpoint.
+#[derive(Clone, Absorb, Emit, Serialize)]
+pub struct NodeStatus {
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub timestamp: SystemTime,
+    /// `SYNCED`, `CATCHUP`, `BOOTSTRAP`, ...
+    pub sync_status: String,
+    pub epoch: u32,
+    pub slot: u32,
+    pub global_slot: u32,
+    /// empty if the node has no best tip yet
+    pub best_tip_hash: String,
+    pub best_tip_height: u32,
+    pub best_tip_slot: u32,
+}
+
+impl NodeStatus {
+    /// Equal except the timestamp.
+    pub fn same(&self, other: &Self) -> bool {
+        self.sync_status == other.sync_status
+            && self.epoch == other.epoch
+            && self.slot == other.slot
+            && self.global_slot == other.global_slot
+            && self.best_tip_hash == other.best_tip_hash
+    }
+}
+
+/// The round trip of the time beacon to another debugger, aligns the captures of different hosts
+/// the way NTP does, but the clocks are not adjusted.
+#[derive(Clone, Debug, Absorb, Emit, Serialize)]
+pub struct TimeBeacon {
+    /// the address of the other debugger
+    pub peer: String,
+    /// `DEBUGGER_NAME` of the other debugger
+    pub peer_name: String,
+    pub seq: u64,
+    /// sent, by the local clock
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub sent: SystemTime,
+    /// received by the peer, by its clock
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub peer_received: SystemTime,
+    /// echoed by the peer, by its clock
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub peer_sent: SystemTime,
+    /// the echo received, by the local clock
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub received: SystemTime,
+}
+
+impl TimeBeacon {
+    fn nanos(time: SystemTime) -> i128 {
+        time.duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as i128
+    }
+
+    /// The clock of the peer minus the local clock, in nanoseconds,
+    /// assuming the path takes equal time in both directions.
+    pub fn offset_ns(&self) -> i64 {
+        let there = Self::nanos(self.peer_received) - Self::nanos(self.sent);
+        let back = Self::nanos(self.peer_sent) - Self::nanos(self.received);
+        ((there + back) / 2) as i64
+    }
+
+    /// The round trip without the time the peer held the beacon, in nanoseconds.
+    pub fn delay_ns(&self) -> i64 {
+        let round_trip = Self::nanos(self.received) - Self::nanos(self.sent);
+        let held = Self::nanos(self.peer_sent) - Self::nanos(self.peer_received);
+        (round_trip - held) as i64
+    }
+}
+
+/// The health of the recorder itself over the interval, stored with the capture,
+/// tells whether the capture of the moment is complete.
+#[derive(Clone, Debug, Absorb, Emit, Serialize)]
+pub struct HealthSample {
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub since: SystemTime,
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub until: SystemTime,
+    /// read from the ring buffer
+    pub events: u64,
+    /// the most bytes waiting in the ring buffer
+    pub buffered_max: u64,
+    /// the most the events waited between the kernel and the recorder
+    pub lag_max_us: u64,
+    /// the events came earlier than the previous event of the thread
+    pub unordered: u64,
+    pub decoder_errors: u64,
+    pub stalls: u64,
+    /// the events the sinks dropped, their queues were full
+    pub sink_dropped: u64,
+    pub sink_failed: u64,
+    /// the ring buffer overflowed, the events of these bytes are lost
+    #[custom_absorb(custom_coding::trailing_u64_absorb)]
+    #[custom_emit(custom_coding::trailing_u64_emit)]
+    pub ring_buffer_skipped: u64,
+}
+
+/// Gossipsub topic subscription announced on the connection.
+#[derive(Clone, Debug, Absorb, Emit, Serialize)]
+pub struct SubscriptionChange {
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub timestamp: SystemTime,
+    pub message_id: MessageId,
+    pub connection_id: ConnectionId,
+    #[custom_absorb(custom_coding::addr_absorb)]
+    #[custom_emit(custom_coding::addr_emit)]
+    pub addr: SocketAddr,
+    /// announced by the peer, otherwise by the local node
+    pub incoming: bool,
+    pub topic: String,
+    /// `false` means unsubscribe
+    pub subscribe: bool,
+}
+
+/// The first observation of the gossip data, the duplicates arriving later are not stored.
+#[derive(Clone, Debug, Absorb, Emit, Serialize)]
+pub struct GossipFirstSeen {
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub timestamp: SystemTime,
+    pub message_id: MessageId,
+    pub connection_id: ConnectionId,
+    #[custom_absorb(custom_coding::addr_absorb)]
+    #[custom_emit(custom_coding::addr_emit)]
+    pub addr: SocketAddr,
+    /// received from the peer, otherwise the node published it first
+    pub incoming: bool,
+    pub topic: String,
+    /// hex of the blake2b hash of the data
+    pub hash: String,
+    pub size: u32,
+}
+
+/// The gossip data delivered again, stored instead of the message when `GOSSIP_DEDUP` is set.
+#[derive(Clone, Debug, Absorb, Emit, Serialize)]
+pub struct GossipDuplicate {
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub timestamp: SystemTime,
+    pub connection_id: ConnectionId,
+    #[custom_absorb(custom_coding::addr_absorb)]
+    #[custom_emit(custom_coding::addr_emit)]
+    pub addr: SocketAddr,
+    pub incoming: bool,
+    pub topic: String,
+    /// hex of the blake2b hash of the data
+    pub hash: String,
+    pub size: u32,
+    /// the stored message which brought the data first
+    pub first: MessageId,
+}
+
+/// The connection decrypted and decoded again from its raw chunks, compared with
+/// the stored messages, see `verify::verify_connection`.
+#[derive(Clone, Debug, Absorb, Emit, Serialize)]
+pub struct Verification {
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub timestamp: SystemTime,
+    pub connection_id: ConnectionId,
+    pub chunks: u64,
+    /// the stored messages compared
+    pub messages: u64,
+    /// the messages the pipeline produced again
+    pub rederived: u64,
+    /// the messages which differ, are missing or are extra
+    pub divergent: u64,
+    /// the first difference, empty if there is none
+    pub detail: String,
+}
+
+/// The agent version the peer announced by identify.
+#[derive(Clone, Debug, Absorb, Emit, Serialize)]
+pub struct AgentVersionSighting {
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub timestamp: SystemTime,
+    pub connection_id: ConnectionId,
+    #[custom_absorb(custom_coding::addr_absorb)]
+    #[custom_emit(custom_coding::addr_emit)]
+    pub addr: SocketAddr,
+    /// empty if the handshake is not decrypted
+    pub peer_id: String,
+    pub agent_version: String,
+}
+
+/// What the token of the multistream select negotiation means.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Absorb, Emit, Serialize)]
+#[tag(u8)]
+#[serde(rename_all = "snake_case")]
+pub enum NegotiationTokenKind {
+    /// `/multistream/1.0.0`
+    Header,
+    /// proposed or accepted protocol
+    Protocol,
+    Na,
+    /// `/libp2p/simultaneous-connect`
+    SimultaneousConnect,
+    /// `select:<random number>`, resolves the simultaneous connect
+    Select,
+    Initiator,
+    Responder,
+    /// not a valid utf-8, the value is hex
+    Unparsed,
+}
+
+#[derive(Clone, Debug, Absorb, Emit, Serialize)]
+pub struct NegotiationToken {
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub timestamp: SystemTime,
+    pub incoming: bool,
+    pub kind: NegotiationTokenKind,
+    /// the token as it is on the wire, without the length prefix and the newline
+    pub value: String,
+}
+
+/// Full transcript of the multistream select negotiation of the stream,
+/// the tokens of both sides in the order they were observed.
+#[derive(Clone, Debug, Absorb, Emit, Serialize)]
+pub struct Negotiation {
+    pub connection_id: ConnectionId,
+    pub stream_id: StreamId,
+    pub tokens: Vec<NegotiationToken>,
+    /// empty until the negotiation is done
+    pub agreed: String,
+    pub simultaneous_connect: bool,
+    /// the negotiation cannot be parsed further
+    pub failed: bool,
+}
+
+/// How the side ended the stream, as the muxer tells.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Absorb, Emit, Serialize)]
+#[tag(u8)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamEndKind {
+    /// the side sends nothing more, the stream is closed cleanly once both sides did it
+    Close,
+    /// the side abandons the stream at once, in both directions
+    Reset,
+    /// yamux go away, the side terminates the whole session, the stream is the handshake
+    GoAway,
+}
+
+/// The close or the reset of the stream by one side, in the order they were observed.
+#[derive(Clone, Debug, Absorb, Emit, Serialize)]
+pub struct StreamEnd {
+    pub connection_id: ConnectionId,
+    pub stream_id: StreamId,
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub timestamp: SystemTime,
+    /// the remote peer ended it, otherwise the local node
+    pub incoming: bool,
+    pub kind: StreamEndKind,
+    /// the code of the yamux go away: 0 normal, 1 protocol error, 2 internal error
+    pub code: u32,
+    /// the end does not match the state of the stream, empty if it does
+    pub error: String,
+}
+
+/// Public artifacts of the noise handshake of the connection,
+/// to verify the key schedule against an independent implementation.
+/// The keys are hex encoded.
+#[derive(Clone, Debug, Absorb, Emit, Serialize)]
+pub struct NoiseHandshake {
+    pub connection_id: ConnectionId,
+    /// the remote peer is the initiator
+    pub initiator_is_incoming: bool,
+    pub initiator_ephemeral: String,
+    pub responder_ephemeral: String,
+    pub responder_static: String,
+    pub initiator_static: String,
+    /// sha256 of the Diffie-Hellman results `ee`, `es`, `se`,
+    /// identifies the shared secret without revealing it
+    pub key_ids: Vec<String>,
+    /// the Diffie-Hellman results themselves,
+    /// empty unless the capture allows exporting the secrets
+    pub secrets: Vec<String>,
+    /// the third message is decrypted, the transport keys are derived
+    pub complete: bool,
+    /// why the handshake failed
+    pub error: String,
+    /// the number of the last handshake message observed, from 1 to 3,
+    /// zero in the records of older databases
+    #[custom_absorb(custom_coding::trailing_u64_absorb)]
+    #[custom_emit(custom_coding::trailing_u64_emit)]
+    pub last_message: u64,
+    /// the peer sent the last handshake message
+    #[custom_absorb(custom_coding::trailing_bool_absorb)]
+    pub last_message_incoming: bool,
+    #[custom_absorb(custom_coding::trailing_time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub last_message_time: SystemTime,
+}
+
+#[derive(Absorb, Emit)]
+pub struct SyscallErrorKey {
+    pub connection_id: ConnectionId,
+    pub syscall: String,
+    pub errno: u32,
+}
+
+#[derive(Serialize)]
+pub struct SyscallErrorStat {
+    pub syscall: String,
+    pub errno: u32,
+    pub name: Option<&'static str>,
+    pub description: String,
+    pub count: u64,
+}
+
+impl SyscallErrorStat {
+    pub fn new(key: SyscallErrorKey, count: u64) -> Self {
+        let name = match key.errno {
+            4 => Some("EINTR"),
+            9 => Some("EBADF"),
+            11 => Some("EAGAIN"),
+            32 => Some("EPIPE"),
+            101 => Some("ENETUNREACH"),
+            103 => Some("ECONNABORTED"),
+            104 => Some("ECONNRESET"),
+            107 => Some("ENOTCONN"),
+            110 => Some("ETIMEDOUT"),
+            111 => Some("ECONNREFUSED"),
+            113 => Some("EHOSTUNREACH"),
+            115 => Some("EINPROGRESS"),
+            _ => None,
+        };
+        SyscallErrorStat {
+            description: std::io::Error::from_raw_os_error(key.errno as i32).to_string(),
+            syscall: key.syscall,
+            errno: key.errno,
+            name,
+            count,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct IdentityHistory {
+    pub peer_id: String,
+    pub appearances: Vec<IdentityAppearance>,
+    /// the peer id appears from many ip addresses
+    pub roaming: bool,
+    /// the ip addresses which presented another peer id, key rotation or NAT
+    pub shared_ips: Vec<SharedIp>,
+}
+
+#[derive(Serialize)]
+pub struct IdentityAppearance {
+    pub addr: SocketAddr,
+    pub connection_id: ConnectionId,
+    pub timestamp: SystemTime,
+}
+
+#[derive(Serialize)]
+pub struct SharedIp {
+    pub ip: IpAddr,
+    pub peer_ids: Vec<String>,
 }
 
 pub trait Timestamp {
@@ -657,3 +1346,45 @@ impl CapnpTableRow {
         }
     }
 }
+
+#[cfg(test)]
+#[test]
+fn message_seq_compatible() {
+    let msg = Message {
+        connection_id: ConnectionId(1),
+        stream_id: StreamId::Forward(1),
+        stream_kind: StreamKind::Rpc,
+        incoming: true,
+        timestamp: SystemTime::UNIX_EPOCH + Duration::from_secs(1_675_166_400),
+        offset: 0,
+        size: 10,
+        brief: "get_best_tip".to_owned(),
+        seq: 7,
+        unredacted: false,
+        decoder_version: 3,
+        transfer_ns: 2_000_000,
+        chunks: 3,
+    };
+    let bytes = msg.chain(vec![]);
+    let decoded = Message::absorb_ext(&bytes).unwrap();
+    assert_eq!(decoded.seq, 7);
+    assert_eq!(decoded.decoder_version, 3);
+    assert_eq!(
+        decoded.first_byte(),
+        Some(msg.timestamp - Duration::from_millis(2))
+    );
+
+    // the record written before the timing was introduced
+    let decoded = Message::absorb_ext(&bytes[..bytes.len() - 12]).unwrap();
+    assert_eq!((decoded.decoder_version, decoded.chunks), (3, 0));
+    assert_eq!(decoded.first_byte(), None);
+
+    // the record written before the decoder version was introduced
+    let decoded = Message::absorb_ext(&bytes[..bytes.len() - 16]).unwrap();
+    assert_eq!((decoded.seq, decoded.decoder_version), (7, 0));
+
+    // the record written before the sequence number was introduced
+    let decoded = Message::absorb_ext(&bytes[..bytes.len() - 25]).unwrap();
+    assert_eq!(decoded.seq, 0);
+    assert_eq!(decoded.brief, "get_best_tip");
+}
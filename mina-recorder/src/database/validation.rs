@@ -0,0 +1,174 @@
+use std::{
+    collections::{BTreeMap, btree_map::Entry},
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+
+use super::types::ConnectionId;
+
+/// The gossip message received or sent by the node, identified by the hash of its data.
+pub struct Publish {
+    pub time: SystemTime,
+    pub connection_id: ConnectionId,
+    pub incoming: bool,
+    pub topic: String,
+    pub hash: [u8; 16],
+}
+
+/// How long the node validates the gossip before forwarding it to the mesh.
+/// The node forwards the message only after it is validated, so the time from the first
+/// receipt to the first forward is the validation latency plus the queueing.
+#[derive(Serialize)]
+pub struct ValidationReport {
+    pub since: SystemTime,
+    pub until: SystemTime,
+    pub topics: BTreeMap<String, TopicValidation>,
+}
+
+#[derive(Default, Serialize)]
+pub struct TopicValidation {
+    /// distinct messages first received within the range
+    pub received: u64,
+    pub forwarded: u64,
+    /// received, but never forwarded within `FORWARD_WINDOW`,
+    /// rejected or ignored by the validator, or there was no peer to forward to
+    pub not_forwarded: u64,
+    /// the node sent them before receiving, produced by the node itself
+    pub local: u64,
+    pub p50_secs: Option<f64>,
+    pub p90_secs: Option<f64>,
+    pub p99_secs: Option<f64>,
+    pub max_secs: Option<f64>,
+}
+
+impl TopicValidation {
+    fn percentile(latencies: &[Duration], percent: usize) -> Option<f64> {
+        let n = latencies.len();
+        let rank = (percent * n + 99) / 100;
+        (n != 0).then(|| latencies[rank.clamp(1, n) - 1].as_secs_f64())
+    }
+}
+
+impl ValidationReport {
+    /// The message received before the end of the range is forwarded within this time,
+    /// or it is not forwarded at all.
+    pub const FORWARD_WINDOW: Duration = Duration::from_secs(60);
+
+    /// The `publishes` must be ordered by time and include `FORWARD_WINDOW` before `since`
+    /// and after `until`, the messages received before the range and forwarded in it are ignored.
+    pub fn build<I>(publishes: I, since: SystemTime, until: SystemTime) -> Self
+    where
+        I: IntoIterator<Item = Publish>,
+    {
+        // the first receipt, `None` if the node sent it first
+        let mut first = BTreeMap::<[u8; 16], (String, Option<SystemTime>)>::new();
+        let mut latencies = BTreeMap::<String, Vec<Duration>>::new();
+        let mut topics = BTreeMap::<String, TopicValidation>::new();
+        for Publish {
+            time,
+            incoming,
+            topic,
+            hash,
+            ..
+        } in publishes
+        {
+            let (topic, received) = match first.entry(hash) {
+                Entry::Vacant(entry) => {
+                    if time < since {
+                        entry.insert((topic, None));
+                        continue;
+                    }
+                    if time >= until {
+                        continue;
+                    }
+                    let stats = topics.entry(topic.clone()).or_default();
+                    if incoming {
+                        stats.received += 1;
+                        entry.insert((topic, Some(time)));
+                    } else {
+                        stats.local += 1;
+                        entry.insert((topic, None));
+                    }
+                    continue;
+                }
+                Entry::Occupied(entry) => entry.into_mut(),
+            };
+            if incoming {
+                continue;
+            }
+            // forwarded, only the first forward counts
+            if let Some(received) = received.take() {
+                if let Ok(latency) = time.duration_since(received) {
+                    if latency <= Self::FORWARD_WINDOW {
+                        latencies.entry(topic.clone()).or_default().push(latency);
+                    }
+                }
+            }
+        }
+
+        for (topic, stats) in &mut topics {
+            let mut latencies = latencies.remove(topic).unwrap_or_default();
+            latencies.sort();
+            stats.forwarded = latencies.len() as u64;
+            stats.not_forwarded = stats.received - stats.forwarded;
+            stats.p50_secs = TopicValidation::percentile(&latencies, 50);
+            stats.p90_secs = TopicValidation::percentile(&latencies, 90);
+            stats.p99_secs = TopicValidation::percentile(&latencies, 99);
+            stats.max_secs = latencies.last().map(Duration::as_secs_f64);
+        }
+
+        ValidationReport {
+            since,
+            until,
+            topics,
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn validation_latency() {
+    let since = SystemTime::UNIX_EPOCH + Duration::from_secs(1_675_166_400);
+    let until = since + Duration::from_secs(60);
+    let p = |ms: u64, incoming, hash: u8| Publish {
+        time: since + Duration::from_millis(ms),
+        connection_id: ConnectionId(0),
+        incoming,
+        topic: "coda/consensus-messages/0.0.1".to_owned(),
+        hash: [hash; 16],
+    };
+    let report = ValidationReport::build(
+        [
+            // received before the range
+            Publish {
+                time: since - Duration::from_secs(1),
+                ..p(0, true, 0)
+            },
+            p(1_000, true, 1),
+            p(1_200, false, 0),
+            // the duplicate receipt does not matter
+            p(1_100, true, 1),
+            p(1_300, false, 1),
+            // forwarded to another peer later
+            p(1_500, false, 1),
+            p(2_000, true, 2),
+            p(2_100, false, 2),
+            // produced by the node
+            p(3_000, false, 3),
+            p(3_100, true, 3),
+            // never forwarded
+            p(4_000, true, 4),
+            // forwarded after the range
+            p(59_000, true, 5),
+            p(61_000, false, 5),
+        ],
+        since,
+        until,
+    );
+    let topic = &report.topics["coda/consensus-messages/0.0.1"];
+    assert_eq!((topic.received, topic.forwarded), (4, 3));
+    assert_eq!((topic.not_forwarded, topic.local), (1, 1));
+    assert_eq!(topic.p50_secs, Some(0.3));
+    assert_eq!(topic.max_secs, Some(2.0));
+}
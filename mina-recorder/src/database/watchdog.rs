@@ -0,0 +1,178 @@
+use std::{
+    collections::VecDeque,
+    env,
+    net::SocketAddr,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use super::types::ConnectionId;
+
+/// Flags the connections whose bytes keep arriving,
+/// but the decoders produce no message, most likely the decoder lost sync.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WatchdogConfig {
+    /// `None` means the watchdog is disabled
+    pub timeout: Option<Duration>,
+    /// stop decoding the stalled connection, store only its raw bytes
+    pub reset: bool,
+}
+
+impl FromStr for WatchdogConfig {
+    type Err = String;
+
+    /// Seconds, optionally followed by `,reset`, like `120,reset`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (secs, reset) = match s.split_once(',') {
+            Some((secs, "reset")) => (secs, true),
+            Some(_) => return Err(s.to_owned()),
+            None => (s, false),
+        };
+        let secs = secs.trim().parse::<u64>().map_err(|_| s.to_owned())?;
+        Ok(WatchdogConfig {
+            timeout: Some(Duration::from_secs(secs)),
+            reset,
+        })
+    }
+}
+
+/// The decoder of the connection produced nothing for a while.
+#[derive(Debug, Clone, Serialize)]
+pub struct Stall {
+    pub connection_id: ConnectionId,
+    pub addr: SocketAddr,
+    pub time: SystemTime,
+    /// the last message, or the first byte if there was no message yet
+    pub since: SystemTime,
+    /// arrived since then
+    pub bytes: u64,
+    /// the decoding is stopped, only raw bytes are stored from now on
+    pub reset: bool,
+    /// state of the decoder, accumulators of each layer and stream
+    pub snapshot: serde_json::Value,
+}
+
+#[derive(Default)]
+pub struct Watchdog {
+    config: WatchdogConfig,
+    stalls: Mutex<VecDeque<Stall>>,
+}
+
+impl Watchdog {
+    const HISTORY: usize = 0x400;
+
+    pub fn new(config: WatchdogConfig) -> Self {
+        Watchdog {
+            config,
+            stalls: Mutex::default(),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let config = match env::var("DECODER_WATCHDOG") {
+            Ok(s) => s.parse().unwrap_or_else(|s| {
+                log::error!("cannot parse decoder watchdog {s}, disable the watchdog");
+                WatchdogConfig::default()
+            }),
+            Err(_) => WatchdogConfig::default(),
+        };
+        Self::new(config)
+    }
+
+    pub fn config(&self) -> &WatchdogConfig {
+        &self.config
+    }
+
+    pub fn record(&self, stall: Stall) {
+        log::warn!(
+            "{} {} decoder stalled, {} bytes without a message since {:?}",
+            stall.addr,
+            stall.connection_id,
+            stall.bytes,
+            stall.since,
+        );
+        let mut stalls = self.stalls.lock();
+        if stalls.len() == Self::HISTORY {
+            stalls.pop_front();
+        }
+        stalls.push_back(stall);
+    }
+
+    /// Recent stalls, the last is the latest.
+    pub fn stalls(&self) -> Vec<Stall> {
+        self.stalls.lock().iter().cloned().collect()
+    }
+}
+
+/// Tracks the progress of the decoder of one connection.
+#[derive(Default)]
+pub struct DecoderProgress {
+    // the time and the sequence number of the last message
+    last: Option<(SystemTime, u64)>,
+    bytes: u64,
+    stalled: bool,
+}
+
+impl DecoderProgress {
+    /// Call after the chunk is decoded, `seq` is the number of messages of the connection.
+    /// Returns the time since the decoder made progress and the bytes arrived since then,
+    /// once per stall.
+    pub fn observe(
+        &mut self,
+        time: SystemTime,
+        seq: u64,
+        bytes: usize,
+        timeout: Duration,
+    ) -> Option<(SystemTime, u64)> {
+        match self.last {
+            Some((since, last_seq)) if last_seq == seq => {
+                self.bytes += bytes as u64;
+                let silent = time.duration_since(since).unwrap_or_default();
+                if !self.stalled && silent >= timeout {
+                    self.stalled = true;
+                    return Some((since, self.bytes));
+                }
+            }
+            _ => {
+                self.last = Some((time, seq));
+                self.bytes = 0;
+                self.stalled = false;
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn decoder_watchdog() {
+    let config = "120,reset".parse::<WatchdogConfig>().unwrap();
+    assert_eq!(config.timeout, Some(Duration::from_secs(120)));
+    assert!(config.reset);
+    assert!("120,x".parse::<WatchdogConfig>().is_err());
+
+    let timeout = Duration::from_secs(60);
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_675_166_400);
+    let at = |secs| time + Duration::from_secs(secs);
+    let mut progress = DecoderProgress::default();
+    assert_eq!(progress.observe(at(0), 1, 100, timeout), None);
+    // the decoder produces messages
+    assert_eq!(progress.observe(at(70), 2, 100, timeout), None);
+    // the bytes arrive, but no message
+    assert_eq!(progress.observe(at(100), 2, 100, timeout), None);
+    assert_eq!(
+        progress.observe(at(130), 2, 50, timeout),
+        Some((at(70), 150))
+    );
+    // reported once
+    assert_eq!(progress.observe(at(200), 2, 50, timeout), None);
+    // recovered
+    assert_eq!(progress.observe(at(210), 3, 50, timeout), None);
+    assert_eq!(
+        progress.observe(at(280), 3, 50, timeout),
+        Some((at(210), 50))
+    );
+}
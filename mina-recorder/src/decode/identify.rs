@@ -58,6 +58,32 @@ pub fn parse(
     }
 }
 
+/// What the peer tells about itself and about the node.
+pub struct Announcement {
+    pub agent_version: Option<String>,
+    pub listen_addrs: Vec<String>,
+    /// the address of the node as the peer observes it
+    pub observed_addr: Option<String>,
+}
+
+pub fn announcement(bytes: &[u8]) -> Result<Announcement, DecodeError> {
+    let pb::Identify {
+        agent_version,
+        listen_addrs,
+        observed_addr,
+        ..
+    } = pb::Identify::decode_length_delimited(bytes).map_err(DecodeError::Protobuf)?;
+    let listen_addrs = listen_addrs
+        .into_iter()
+        .map(|v| utils::parse_addr(&v))
+        .collect();
+    Ok(Announcement {
+        agent_version,
+        listen_addrs,
+        observed_addr: observed_addr.map(|v| utils::parse_addr(&v)),
+    })
+}
+
 #[cfg(test)]
 #[test]
 fn decode_identify() {
@@ -26,6 +26,48 @@ pub fn parse_types(bytes: &[u8]) -> Result<Vec<MessageType>, DecodeError> {
     Ok(vec![ty])
 }
 
+fn multiaddr_string(addr: &[u8]) -> String {
+    let mut acc = String::new();
+    let mut input = addr;
+    while !input.is_empty() {
+        match multiaddr::Protocol::from_bytes(input) {
+            Ok((p, i)) => {
+                input = i;
+                acc = format!("{acc}{p}");
+            }
+            Err(err) => {
+                input = &[];
+                acc = format!("{acc}{err}");
+            }
+        }
+    }
+    acc
+}
+
+/// The peers the message tells about, the peer id in base58 and its addresses.
+pub fn parse_closer_peers(bytes: &[u8]) -> Result<Vec<(String, Vec<String>)>, DecodeError> {
+    let buf = Bytes::from(bytes.to_vec());
+    let msg =
+        <pb::Message as Message>::decode_length_delimited(buf).map_err(DecodeError::Protobuf)?;
+
+    let peers = msg
+        .closer_peers
+        .into_iter()
+        .map(|peer| {
+            let id = libp2p_core::PeerId::from_bytes(&peer.id)
+                .map(|id| id.to_base58())
+                .unwrap_or_else(|_| hex::encode(&peer.id));
+            let addrs = peer
+                .addrs
+                .iter()
+                .map(|addr| multiaddr_string(addr))
+                .collect();
+            (id, addrs)
+        })
+        .collect();
+    Ok(peers)
+}
+
 pub fn parse(bytes: Vec<u8>, preview: bool) -> Result<serde_json::Value, DecodeError> {
     #[derive(Serialize)]
     #[serde(rename_all = "snake_case")]
@@ -82,27 +124,7 @@ pub fn parse(bytes: Vec<u8>, preview: bool) -> Result<serde_json::Value, DecodeE
         fn from(v: pb::message::Peer) -> Self {
             Peer {
                 id: hex::encode(&v.id),
-                addrs: v
-                    .addrs
-                    .iter()
-                    .map(|addr| {
-                        let mut acc = String::new();
-                        let mut input = addr.as_slice();
-                        while !input.is_empty() {
-                            match multiaddr::Protocol::from_bytes(input) {
-                                Ok((p, i)) => {
-                                    input = i;
-                                    acc = format!("{acc}{p}");
-                                }
-                                Err(err) => {
-                                    input = &[];
-                                    acc = format!("{acc}{err}");
-                                }
-                            }
-                        }
-                        acc
-                    })
-                    .collect(),
+                addrs: v.addrs.iter().map(|addr| multiaddr_string(addr)).collect(),
                 connection: match v.connection() {
                     pb::message::ConnectionType::NotConnected => ConnectionType::NotConnected,
                     pb::message::ConnectionType::Connected => ConnectionType::Connected,
@@ -361,6 +361,19 @@ pub fn parse(bytes: Vec<u8>, preview: bool) -> Result<serde_json::Value, DecodeE
     serde_json::to_value(&t).map_err(DecodeError::Serde)
 }
 
+/// Topics the message announces, `true` means subscribe.
+pub fn parse_subscriptions(bytes: &[u8]) -> Result<Vec<(String, bool)>, DecodeError> {
+    let pb::Rpc { subscriptions, .. } =
+        Message::decode_length_delimited(bytes).map_err(DecodeError::Protobuf)?;
+    Ok(subscriptions
+        .into_iter()
+        .map(|v| {
+            let subscribe = v.subscribe();
+            (v.topic_id.unwrap_or_default(), subscribe)
+        })
+        .collect())
+}
+
 pub fn parse_protobuf_publish(
     bytes: &[u8],
 ) -> Result<impl Iterator<Item = Vec<u8>>, prost::DecodeError> {
@@ -369,6 +382,33 @@ pub fn parse_protobuf_publish(
     Ok(publish.into_iter().filter_map(|m| m.data))
 }
 
+/// Like `parse_protobuf_publish`, each data with its topic.
+pub fn parse_protobuf_publish_topics(
+    bytes: &[u8],
+) -> Result<impl Iterator<Item = (String, Vec<u8>)>, prost::DecodeError> {
+    let pb::Rpc { publish, .. } = Message::decode_length_delimited(bytes)?;
+
+    Ok(publish.into_iter().filter_map(|m| Some((m.topic, m.data?))))
+}
+
+/// Each data with its topic, `None` if the message carries anything else,
+/// the subscriptions, the control or a publish without the data.
+pub fn parse_publish_only(bytes: &[u8]) -> Result<Option<Vec<(String, Vec<u8>)>>, DecodeError> {
+    let pb::Rpc {
+        subscriptions,
+        publish,
+        control,
+    } = Message::decode_length_delimited(bytes).map_err(DecodeError::Protobuf)?;
+    if !subscriptions.is_empty() || control.is_some() || publish.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(publish
+        .into_iter()
+        .map(|m| Some((m.topic, m.data?)))
+        .collect())
+}
+
 pub fn parse_it(
     bytes: &[u8],
     preview: bool,
@@ -6,6 +6,8 @@ pub mod identify;
 pub mod json_string;
 pub mod yamux;
 pub mod meshsub_stats;
+pub mod preview;
+pub mod schema;
 
 mod utils;
 
@@ -18,7 +20,10 @@ use thiserror::Error;
 
 use mina_p2p_messages::{binprot, rpc_kernel::JSONinifyError};
 
-use super::{connection::yamux as yamux_parser, database::LedgerHash};
+use super::{
+    connection::yamux as yamux_parser,
+    database::{LedgerHash, StreamKind},
+};
 
 #[derive(Debug, Error)]
 pub enum DecodeError {
@@ -63,6 +68,101 @@ impl From<JSONinifyError> for DecodeError {
     }
 }
 
+/// Version of the decoders, stored with each message. Bump it whenever a fix changes
+/// the types or the indexes the decoders produce for the same bytes, so the messages
+/// decoded by the buggy version can be found and decoded again.
+pub const DECODER_VERSION: u32 = 1;
+
+/// Types of the message of the stream of given kind,
+/// and the ledger hashes it mentions if `index_ledger_hash` is set.
+pub fn parse_types(
+    stream_kind: StreamKind,
+    bytes: &[u8],
+    index_ledger_hash: bool,
+) -> Result<(Vec<MessageType>, Vec<LedgerHash>), DecodeError> {
+    let tys = match stream_kind {
+        StreamKind::Unknown => vec![],
+        StreamKind::Meshsub => return meshsub::parse_types(bytes, index_ledger_hash),
+        StreamKind::Kad => kademlia::parse_types(bytes)?,
+        StreamKind::Handshake => noise::parse_types(bytes)?,
+        StreamKind::Rpc => rpc::parse_types(bytes)?,
+        StreamKind::IpfsId => vec![MessageType::Identify],
+        StreamKind::IpfsPush => vec![MessageType::IdentifyPush],
+        // TODO: message type (types)
+        StreamKind::IpfsDelta => vec![],
+        StreamKind::PeerExchange => vec![MessageType::PeerExchange],
+        StreamKind::BitswapExchange => vec![MessageType::BitswapExchange],
+        StreamKind::NodeStatus => vec![MessageType::NodeStatus],
+        StreamKind::Select => vec![MessageType::Select],
+        StreamKind::Mplex => vec![MessageType::Mplex],
+        StreamKind::Yamux => vec![MessageType::Yamux],
+    };
+
+    Ok((tys, vec![]))
+}
+
+/// Decode the message of the stream of given kind into json.
+pub fn parse(
+    stream_kind: StreamKind,
+    buf: Vec<u8>,
+    preview: bool,
+) -> Result<serde_json::Value, DecodeError> {
+    let message = match stream_kind {
+        StreamKind::Kad => kademlia::parse(buf, preview)?,
+        StreamKind::Meshsub => meshsub::parse(buf, preview)?,
+        StreamKind::Handshake => noise::parse(buf, preview)?,
+        StreamKind::Rpc => rpc::parse(buf, preview)?,
+        StreamKind::IpfsId => identify::parse(buf, preview, stream_kind)?,
+        StreamKind::IpfsPush => identify::parse(buf, preview, stream_kind)?,
+        // TODO: proper decode
+        StreamKind::IpfsDelta => serde_json::Value::String(hex::encode(&buf)),
+        StreamKind::PeerExchange => json_string::parse(buf, preview)?,
+        // TODO: proper decode
+        StreamKind::BitswapExchange => serde_json::Value::String(hex::encode(&buf)),
+        // TODO: proper decode
+        StreamKind::NodeStatus => serde_json::Value::String(hex::encode(&buf)),
+        StreamKind::Select => {
+            let s = String::from_utf8(buf).map_err(DecodeError::Utf8)?;
+            serde_json::Value::String(s)
+        }
+        StreamKind::Mplex => {
+            let v = buf
+                .as_slice()
+                .try_into()
+                .map_err(|_| DecodeError::UnexpectedSize {
+                    actual: buf.len(),
+                    expected: 8,
+                })?;
+            let v = u64::from_be_bytes(v);
+            let stream = v >> 3;
+            let header = v & 7;
+            let action = match header {
+                0 => "create stream",
+                3 => "close receiver",
+                4 => "close initiator",
+                5 => "reset receiver",
+                6 => "reset initiator",
+                1 | 2 | 7 => panic!("unexpected header {header}"),
+                _ => unreachable!(),
+            };
+
+            #[derive(Serialize)]
+            struct MplexMessage {
+                action: &'static str,
+                stream: u64,
+            }
+
+            let msg = MplexMessage { action, stream };
+
+            serde_json::to_value(&msg).map_err(DecodeError::Serde)?
+        }
+        StreamKind::Yamux => yamux::parse(buf, preview)?,
+        StreamKind::Unknown => serde_json::Value::String(hex::encode(&buf)),
+    };
+
+    Ok(message)
+}
+
 #[derive(Clone, Absorb, Emit, PartialEq, Eq, PartialOrd, Ord)]
 #[tag(u16)]
 pub enum MessageType {
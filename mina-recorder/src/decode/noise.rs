@@ -33,6 +33,31 @@ pub fn payload(bytes: &[u8]) -> Result<Vec<u8>, DecodeError> {
     Ok(msg.payload)
 }
 
+fn public_key(pk: &keys_proto::PublicKey) -> Result<PublicKey, DecodeError> {
+    Ok(match pk.r#type() {
+        keys_proto::KeyType::Rsa => return Err(DecodeError::Rsa),
+        keys_proto::KeyType::Ed25519 => PublicKey::Ed25519(ed25519::PublicKey::decode(&pk.data)?),
+        keys_proto::KeyType::Secp256k1 => {
+            PublicKey::Secp256k1(secp256k1::PublicKey::decode(&pk.data)?)
+        }
+        keys_proto::KeyType::Ecdsa => PublicKey::Ecdsa(ecdsa::PublicKey::from_bytes(&pk.data)?),
+    })
+}
+
+/// The peer id of the party which sent this handshake payload.
+pub fn peer_id(bytes: &[u8]) -> Result<Option<PeerId>, DecodeError> {
+    if bytes.starts_with(b"mac_mismatch\x00\x00\x00\x00") {
+        return Ok(None);
+    }
+
+    let buf = Bytes::from(bytes.to_vec());
+    let msg = pb::Envelope::decode(buf).map_err(DecodeError::Protobuf)?;
+    match msg.public_key {
+        None => Ok(None),
+        Some(pk) => Ok(Some(PeerId::from_public_key(&public_key(&pk)?))),
+    }
+}
+
 pub fn parse(bytes: Vec<u8>, _: bool) -> Result<serde_json::Value, DecodeError> {
     #[derive(Serialize)]
     struct T {
@@ -65,19 +90,7 @@ pub fn parse(bytes: Vec<u8>, _: bool) -> Result<serde_json::Value, DecodeError>
     let (r#type, public_key, peer_id) = match msg.public_key {
         None => ("".to_string(), "".to_string(), "".to_string()),
         Some(pk) => {
-            let libp2p_pk = match pk.r#type() {
-                keys_proto::KeyType::Rsa => return Err(DecodeError::Rsa),
-                keys_proto::KeyType::Ed25519 => {
-                    PublicKey::Ed25519(ed25519::PublicKey::decode(&pk.data)?)
-                }
-                keys_proto::KeyType::Secp256k1 => {
-                    PublicKey::Secp256k1(secp256k1::PublicKey::decode(&pk.data)?)
-                }
-                keys_proto::KeyType::Ecdsa => {
-                    PublicKey::Ecdsa(ecdsa::PublicKey::from_bytes(&pk.data)?)
-                }
-            };
-            let id = PeerId::from_public_key(&libp2p_pk);
+            let id = PeerId::from_public_key(&public_key(&pk)?);
             (
                 pk.r#type().as_str_name().to_string(),
                 hex::encode(pk.data),
@@ -0,0 +1,162 @@
+use serde_json::{Map, Value};
+
+use crate::database::StreamKind;
+
+use super::DecodeError;
+
+/// How much of the decoded message goes to the preview.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PreviewLimits {
+    /// fields of each object, items of each array
+    pub fields: usize,
+    /// bytes of all strings and numbers together
+    pub bytes: usize,
+}
+
+impl PreviewLimits {
+    pub const DEFAULT_FIELDS: usize = 8;
+    pub const DEFAULT_BYTES: usize = 0x100;
+    pub const MAX_FIELDS: usize = 0x40;
+    pub const MAX_BYTES: usize = 0x1000;
+
+    /// `None` if the preview is not requested, the limits are clamped to the maximum.
+    pub fn new(fields: Option<usize>, bytes: Option<usize>) -> Option<Self> {
+        if fields.is_none() && bytes.is_none() {
+            return None;
+        }
+        Some(PreviewLimits {
+            fields: fields.unwrap_or(Self::DEFAULT_FIELDS).min(Self::MAX_FIELDS),
+            bytes: bytes.unwrap_or(Self::DEFAULT_BYTES).min(Self::MAX_BYTES),
+        })
+    }
+}
+
+/// Decode the message and cut it to the limits.
+pub fn render(
+    stream_kind: StreamKind,
+    mut buf: Vec<u8>,
+    limits: PreviewLimits,
+) -> Result<Value, DecodeError> {
+    match stream_kind {
+        // not decoded, the hex of the first bytes
+        StreamKind::IpfsDelta
+        | StreamKind::BitswapExchange
+        | StreamKind::NodeStatus
+        | StreamKind::Unknown => {
+            let len = buf.len();
+            buf.truncate(limits.bytes / 2);
+            let mut s = hex::encode(&buf);
+            if buf.len() < len {
+                s.push_str("...");
+            }
+            Ok(Value::String(s))
+        }
+        _ => {
+            let value = super::parse(stream_kind, buf, false)?;
+            let mut cut = Cut {
+                fields: limits.fields,
+                remaining: limits.bytes,
+            };
+            Ok(cut.value(value))
+        }
+    }
+}
+
+struct Cut {
+    fields: usize,
+    remaining: usize,
+}
+
+impl Cut {
+    fn value(&mut self, value: Value) -> Value {
+        match value {
+            Value::Object(map) => {
+                let total = map.len();
+                let mut out = Map::new();
+                for (key, value) in map {
+                    if out.len() == self.fields || self.remaining == 0 {
+                        break;
+                    }
+                    self.consume(key.len());
+                    let value = self.value(value);
+                    out.insert(key, value);
+                }
+                if out.len() < total {
+                    out.insert("...".to_owned(), Value::from(total - out.len()));
+                }
+                Value::Object(out)
+            }
+            Value::Array(items) => {
+                let total = items.len();
+                let mut out = Vec::new();
+                for item in items {
+                    if out.len() == self.fields || self.remaining == 0 {
+                        break;
+                    }
+                    out.push(self.value(item));
+                }
+                if out.len() < total {
+                    out.push(Value::String(format!("... {} more", total - out.len())));
+                }
+                Value::Array(out)
+            }
+            Value::String(mut s) => {
+                if s.len() > self.remaining {
+                    let mut end = self.remaining;
+                    while !s.is_char_boundary(end) {
+                        end -= 1;
+                    }
+                    s.truncate(end);
+                    s.push_str("...");
+                    self.remaining = 0;
+                } else {
+                    self.remaining -= s.len();
+                }
+                Value::String(s)
+            }
+            value => {
+                self.consume(value.to_string().len());
+                value
+            }
+        }
+    }
+
+    fn consume(&mut self, len: usize) {
+        self.remaining = self.remaining.saturating_sub(len);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn preview() {
+    let limits = PreviewLimits::new(Some(2), None).unwrap();
+    assert_eq!(limits.bytes, PreviewLimits::DEFAULT_BYTES);
+    assert_eq!(PreviewLimits::new(None, None), None);
+    assert_eq!(
+        PreviewLimits::new(None, Some(usize::MAX)).unwrap().bytes,
+        PreviewLimits::MAX_BYTES
+    );
+
+    let value = serde_json::json!({
+        "a": [1, 2, 3, 4],
+        "b": "abcdefghijklmnopqrstuvwxyz",
+        "c": {"d": 1},
+    });
+    let mut cut = Cut {
+        fields: 2,
+        remaining: 12,
+    };
+    assert_eq!(
+        cut.value(value),
+        serde_json::json!({
+            "a": [1, 2, "... 2 more"],
+            "b": "abcdefgh...",
+            "...": 1,
+        })
+    );
+
+    let v = render(StreamKind::Unknown, vec![0xab; 0x100], limits).unwrap();
+    assert_eq!(v, Value::String(format!("{}...", "ab".repeat(0x80))));
+    let v = render(StreamKind::Select, b"/noise\n".to_vec(), limits).unwrap();
+    assert_eq!(v, Value::String("/noise\n".to_owned()));
+}
@@ -0,0 +1,344 @@
+use serde_json::{json, Map, Value};
+
+use crate::database::StreamKind;
+
+use super::DECODER_VERSION;
+
+/// Every stream kind, the order of the `/schemas` output.
+pub const STREAM_KINDS: [StreamKind; 14] = [
+    StreamKind::Handshake,
+    StreamKind::Kad,
+    StreamKind::IpfsId,
+    StreamKind::IpfsPush,
+    StreamKind::IpfsDelta,
+    StreamKind::PeerExchange,
+    StreamKind::BitswapExchange,
+    StreamKind::NodeStatus,
+    StreamKind::Meshsub,
+    StreamKind::Rpc,
+    StreamKind::Select,
+    StreamKind::Mplex,
+    StreamKind::Yamux,
+    StreamKind::Unknown,
+];
+
+fn string() -> Value {
+    json!({ "type": "string" })
+}
+
+fn hex() -> Value {
+    json!({ "type": "string", "contentEncoding": "base16" })
+}
+
+fn multiaddr() -> Value {
+    json!({ "type": "string", "format": "multiaddr" })
+}
+
+fn peer_id() -> Value {
+    json!({ "type": "string", "format": "peer_id" })
+}
+
+fn integer() -> Value {
+    json!({ "type": "integer" })
+}
+
+fn nullable(mut schema: Value) -> Value {
+    if let Some(ty) = schema.get("type").cloned() {
+        schema["type"] = json!([ty, "null"]);
+    }
+    schema
+}
+
+fn array(items: Value) -> Value {
+    json!({ "type": "array", "items": items })
+}
+
+fn enumeration(values: &[&str]) -> Value {
+    json!({ "type": "string", "enum": values })
+}
+
+/// The decoder does not look inside, any json.
+fn any(description: &str) -> Value {
+    json!({ "description": description })
+}
+
+/// All fields are required, the optional ones are nullable.
+fn object(fields: &[(&str, Value)]) -> Value {
+    let properties = fields
+        .iter()
+        .map(|(name, schema)| (name.to_string(), schema.clone()))
+        .collect::<Map<_, _>>();
+    let required = fields.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+    })
+}
+
+/// The variant of the enum tagged by the `type` field.
+fn variant(tag: &str, fields: &[(&str, Value)]) -> Value {
+    let mut fields = fields.to_vec();
+    fields.insert(0, ("type", json!({ "const": tag })));
+    object(&fields)
+}
+
+fn one_of(variants: Vec<Value>) -> Value {
+    json!({ "oneOf": variants })
+}
+
+fn with_title(mut schema: Value, title: &str) -> Value {
+    schema["title"] = json!(title);
+    schema
+}
+
+fn described(mut schema: Value, description: &str) -> Value {
+    schema["description"] = json!(description);
+    schema
+}
+
+/// The structure of the json the decoder produces for the message of the stream kind,
+/// the json schema, the frontend builds the generic renderers and filters from it.
+pub fn schema(stream_kind: StreamKind) -> Value {
+    match stream_kind {
+        StreamKind::Handshake => one_of(vec![
+            with_title(
+                object(&[
+                    (
+                        "type",
+                        enumeration(&["RSA", "Ed25519", "Secp256k1", "ECDSA", ""]),
+                    ),
+                    ("public_key", hex()),
+                    ("peer_id", string()),
+                    ("signature", hex()),
+                    ("payload_type", hex()),
+                    ("payload", hex()),
+                ]),
+                "handshake_payload",
+            ),
+            with_title(
+                object(&[
+                    ("this_decrypted", integer()),
+                    ("this_failed", integer()),
+                    ("total_decrypted", integer()),
+                    ("total_failed", integer()),
+                ]),
+                "failed_to_decrypt",
+            ),
+        ]),
+        StreamKind::Kad => {
+            let peer = object(&[
+                ("id", hex()),
+                ("addrs", array(multiaddr())),
+                (
+                    "connection",
+                    enumeration(&[
+                        "not_connected",
+                        "connected",
+                        "can_connect",
+                        "cannot_connect",
+                    ]),
+                ),
+            ]);
+            let record = object(&[
+                ("key", hex()),
+                ("value", hex()),
+                (
+                    "time_received",
+                    json!({ "type": "string", "format": "date-time" }),
+                ),
+            ]);
+            object(&[
+                (
+                    "type",
+                    enumeration(&[
+                        "put_value",
+                        "get_value",
+                        "add_provider",
+                        "get_providers",
+                        "find_node",
+                        "ping",
+                    ]),
+                ),
+                ("key", hex()),
+                ("record", nullable(record)),
+                ("closer_peers", array(peer.clone())),
+                ("provider_peers", array(peer)),
+            ])
+        }
+        StreamKind::IpfsId | StreamKind::IpfsPush => object(&[
+            ("protocol_version", nullable(string())),
+            ("agent_version", nullable(string())),
+            ("public_key", nullable(hex())),
+            ("listen_addrs", array(multiaddr())),
+            ("observed_addr", nullable(multiaddr())),
+            ("protocols", array(string())),
+        ]),
+        StreamKind::PeerExchange => any("the json the node sends, the list of the peers"),
+        StreamKind::Meshsub => {
+            let message_ids = ("message_ids", array(hex()));
+            let control = variant(
+                "control",
+                &[
+                    (
+                        "ihave",
+                        array(object(&[
+                            ("topic_id", nullable(string())),
+                            message_ids.clone(),
+                        ])),
+                    ),
+                    ("iwant", array(object(&[message_ids]))),
+                    ("graft", array(object(&[("topic_id", nullable(string()))]))),
+                    (
+                        "prune",
+                        array(object(&[
+                            ("topic_id", nullable(string())),
+                            (
+                                "peers",
+                                array(object(&[
+                                    ("peer_id", nullable(hex())),
+                                    ("signed_peer_record", nullable(hex())),
+                                ])),
+                            ),
+                            ("backoff", nullable(integer())),
+                        ])),
+                    ),
+                ],
+            );
+            let gossip = any("`GossipNetMessageV2` of mina-p2p-messages, tagged by the variant");
+            array(one_of(vec![
+                variant("subscribe", &[("topic", string())]),
+                variant("unsubscribe", &[("topic", string())]),
+                variant(
+                    "publish_v2",
+                    &[
+                        ("from", nullable(peer_id())),
+                        ("seqno", nullable(hex())),
+                        ("signature", nullable(hex())),
+                        ("key", nullable(hex())),
+                        ("topic", string()),
+                        ("message", gossip),
+                    ],
+                ),
+                variant(
+                    "publish_testing_message",
+                    &[
+                        ("from", peer_id()),
+                        ("topic", string()),
+                        ("message", string()),
+                        ("hash", array(integer())),
+                    ],
+                ),
+                control,
+            ]))
+        }
+        StreamKind::Rpc => {
+            fn rpc(tag: &str, payload: &str) -> Value {
+                let payload_schema =
+                    any("the json of the rpc, the hex string if the rpc is unknown");
+                variant(
+                    tag,
+                    &[
+                        ("tag", string()),
+                        ("version", integer()),
+                        ("id", integer()),
+                        (payload, payload_schema),
+                    ],
+                )
+            }
+
+            one_of(vec![
+                rpc("request", "query"),
+                rpc("response", "value"),
+                json!({ "type": "null" }),
+            ])
+        }
+        StreamKind::Select => described(string(), "the protocol name, the newline included"),
+        StreamKind::Mplex => object(&[
+            (
+                "action",
+                enumeration(&[
+                    "create stream",
+                    "close receiver",
+                    "close initiator",
+                    "reset receiver",
+                    "reset initiator",
+                ]),
+            ),
+            ("stream", integer()),
+        ]),
+        StreamKind::Yamux => object(&[
+            ("version", integer()),
+            (
+                "ty",
+                one_of(vec![
+                    variant("data", &[("length", integer())]),
+                    variant("window_update", &[("delta", integer())]),
+                    variant("ping", &[("opaque", integer())]),
+                    variant("go_away", &[]),
+                ]),
+            ),
+            ("flags", object(&[("bits", integer())])),
+            ("stream_id", integer()),
+        ]),
+        StreamKind::IpfsDelta
+        | StreamKind::BitswapExchange
+        | StreamKind::NodeStatus
+        | StreamKind::Unknown => described(hex(), "not decoded"),
+    }
+}
+
+/// Schemas of all stream kinds by the protocol name, and the placeholder the api shows
+/// instead of the redacted message of any kind.
+pub fn schemas() -> Value {
+    let kinds = STREAM_KINDS
+        .iter()
+        .map(|kind| (kind.to_string(), schema(*kind)))
+        .collect::<Map<_, _>>();
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "decoder_version": DECODER_VERSION,
+        "redacted": object(&[
+            ("redacted", enumeration(&["hash", "strip"])),
+        ]),
+        "stream_kinds": kinds,
+    })
+}
+
+#[cfg(test)]
+#[test]
+fn schema_matches_decoded() {
+    let all = schemas();
+    for kind in STREAM_KINDS {
+        let s = &all["stream_kinds"][kind.to_string()];
+        assert!(s.is_object(), "{kind}");
+    }
+
+    let required = |s: &Value| {
+        s["required"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| v.as_str().unwrap().to_owned())
+            .collect::<Vec<_>>()
+    };
+    let keys = |v: &Value| v.as_object().unwrap().keys().cloned().collect::<Vec<_>>();
+
+    let header = [0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 4].to_vec();
+    let decoded = super::parse(StreamKind::Yamux, header, false).unwrap();
+    let s = schema(StreamKind::Yamux);
+    let mut expected = required(&s);
+    let mut actual = keys(&decoded);
+    expected.sort();
+    actual.sort();
+    assert_eq!(expected, actual);
+    let data = &s["properties"]["ty"]["oneOf"][0];
+    let mut expected = required(data);
+    let mut actual = keys(&decoded["ty"]);
+    expected.sort();
+    actual.sort();
+    assert_eq!(expected, actual);
+
+    let decoded = super::parse(StreamKind::Mplex, 8u64.to_be_bytes().to_vec(), false).unwrap();
+    assert_eq!(required(&schema(StreamKind::Mplex)), keys(&decoded));
+}
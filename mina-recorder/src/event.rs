@@ -1,5 +1,5 @@
 use std::{
-    net::SocketAddr,
+    net::{IpAddr, SocketAddr},
     fmt,
     time::{SystemTime, Duration},
 };
@@ -48,6 +48,34 @@ impl Default for ConnectionInfo {
     }
 }
 
+/// The dual stack socket reports ipv4 peers as `::ffff:a.b.c.d`, the plain ipv4 address
+/// is canonical, so the same peer has the same address in the indexes, queries and views.
+pub fn canonical_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        IpAddr::V4(_) => ip,
+    }
+}
+
+pub fn canonical_addr(addr: SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V6(v6) => match v6.ip().to_ipv4_mapped() {
+            Some(ip) => SocketAddr::new(IpAddr::V4(ip), v6.port()),
+            None => addr,
+        },
+        SocketAddr::V4(_) => addr,
+    }
+}
+
+impl ConnectionInfo {
+    pub fn canonical(self) -> Self {
+        ConnectionInfo {
+            addr: canonical_addr(self.addr),
+            ..self
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct DirectedId {
     pub metadata: EventMetadata,
@@ -101,3 +129,14 @@ impl fmt::Display for DirectedId {
         write!(f, "{hour:02}:{minute:02}:{second:02}.{nano:09} {duration:010?} {buffered} {addr} {fd} {arrow} {alias}_{pid}")
     }
 }
+
+#[cfg(test)]
+#[test]
+fn canonical_addresses() {
+    let mapped = "[::ffff:1.2.3.4]:8302".parse().unwrap();
+    assert_eq!(canonical_addr(mapped), "1.2.3.4:8302".parse().unwrap());
+    let v6 = "[2001:db8::1]:8302".parse().unwrap();
+    assert_eq!(canonical_addr(v6), v6);
+    let ip = "::ffff:1.2.3.4".parse().unwrap();
+    assert_eq!(canonical_ip(ip), "1.2.3.4".parse::<IpAddr>().unwrap());
+}
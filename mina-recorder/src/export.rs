@@ -0,0 +1,593 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    env,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use parking_lot::{Condvar, Mutex};
+use serde::{Serialize, Deserialize};
+
+use crate::{
+    bundle::{self, BundleOptions},
+    database::{ConnectionId, DbCore, FullMessage, Redaction},
+    seal::Recipient,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    /// the decoded messages of the time range, one json per line
+    Ndjson,
+    /// the connections as `GET /export/bundle` writes them, one segment,
+    /// the bundle is a single compressed stream
+    Bundle,
+}
+
+impl ExportFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Bundle => "bundle",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportRequest {
+    pub format: ExportFormat,
+    /// seconds, the messages observed from `since` until `until`, the end of the capture by default
+    #[serde(default)]
+    pub since: u64,
+    #[serde(default)]
+    pub until: Option<u64>,
+    /// the connections of the bundle
+    #[serde(default)]
+    pub connections: Vec<u64>,
+    /// the server sets it, at least the one the scope of the client enforces
+    #[serde(default)]
+    pub redaction: Redaction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+/// The part of the job one worker does, the segments are written to separate files
+/// and concatenated in order when all of them are done.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Segment {
+    /// the message ids, or the indexes of the connections of the bundle
+    pub ids: Range<u64>,
+    pub done: bool,
+    pub items: u64,
+}
+
+/// Stored as `job.json` in the directory of the job, the unfinished job is resumed
+/// after the restart, the segments which are not done are written again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportJob {
+    pub id: u64,
+    pub request: ExportRequest,
+    pub state: JobState,
+    pub created: SystemTime,
+    pub finished: Option<SystemTime>,
+    pub segments: Vec<Segment>,
+    /// bytes of the result
+    pub size: u64,
+    pub error: Option<String>,
+}
+
+impl ExportJob {
+    pub fn progress(&self) -> f64 {
+        if self.segments.is_empty() {
+            return 1.0;
+        }
+        let done = self.segments.iter().filter(|s| s.done).count();
+        done as f64 / self.segments.len() as f64
+    }
+}
+
+#[derive(Serialize)]
+pub struct ExportJobStatus {
+    #[serde(flatten)]
+    pub job: ExportJob,
+    pub progress: f64,
+}
+
+impl From<ExportJob> for ExportJobStatus {
+    fn from(job: ExportJob) -> Self {
+        ExportJobStatus {
+            progress: job.progress(),
+            job,
+        }
+    }
+}
+
+struct Job {
+    inner: ExportJob,
+    cancel: Arc<AtomicBool>,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    jobs: BTreeMap<u64, Job>,
+    // the job and the index of the segment
+    queue: VecDeque<(u64, usize)>,
+}
+
+/// The export jobs, in the `exports` directory of the capture.
+pub struct ExportJobs {
+    dir: PathBuf,
+    // the messages of a segment of the new job
+    segment: u64,
+    state: Mutex<State>,
+    cv: Condvar,
+}
+
+impl ExportJobs {
+    /// The messages of a segment, the workers take the segments in parallel.
+    const SEGMENT: u64 = 100_000;
+
+    /// Loads the jobs of the previous run, the unfinished ones are queued again.
+    pub fn open<P>(dir: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        if let Err(err) = fs::create_dir_all(dir.as_ref()) {
+            log::error!(
+                "cannot create the export jobs {}: {err}",
+                dir.as_ref().display()
+            );
+        }
+        Self::load(dir)
+    }
+
+    /// Same, but the directory is not created.
+    pub fn load<P>(dir: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let dir = dir.as_ref().to_owned();
+        let mut state = State::default();
+        let entries = match fs::read_dir(&dir) {
+            Ok(v) => v.filter_map(Result::ok).collect(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => vec![],
+            Err(err) => {
+                log::error!("cannot open the export jobs {}: {err}", dir.display());
+                vec![]
+            }
+        };
+        for entry in entries {
+            let path = entry.path().join("job.json");
+            let job = match fs::read(&path).map(|b| serde_json::from_slice::<ExportJob>(&b)) {
+                Ok(Ok(v)) => v,
+                Ok(Err(err)) => {
+                    log::error!("bad export job {}: {err}", path.display());
+                    continue;
+                }
+                Err(_) => continue,
+            };
+            state.next_id = state.next_id.max(job.id + 1);
+            if job.state == JobState::Running {
+                log::info!("resume the export job {}", job.id);
+                for (n, segment) in job.segments.iter().enumerate() {
+                    if !segment.done {
+                        state.queue.push_back((job.id, n));
+                    }
+                }
+            }
+            let cancel = Arc::new(AtomicBool::new(false));
+            state.jobs.insert(job.id, Job { inner: job, cancel });
+        }
+        ExportJobs {
+            dir,
+            segment: Self::SEGMENT,
+            state: Mutex::new(state),
+            cv: Condvar::new(),
+        }
+    }
+
+    pub fn create(&self, db: &DbCore, request: ExportRequest) -> Result<ExportJob, String> {
+        let secs = |s| SystemTime::UNIX_EPOCH + Duration::from_secs(s);
+        let until = request.until.map_or(SystemTime::now(), secs);
+        if secs(request.since) > until {
+            return Err("`since` is after `until`".to_owned());
+        }
+        let segments = match request.format {
+            ExportFormat::Ndjson => {
+                let ids = db.message_ids_in_range(secs(request.since), until);
+                (ids.start..ids.end)
+                    .step_by(self.segment as usize)
+                    .map(|start| start..(start + self.segment).min(ids.end))
+                    .collect()
+            }
+            ExportFormat::Bundle if request.connections.is_empty() => {
+                return Err("the bundle needs `connections`".to_owned());
+            }
+            ExportFormat::Bundle => vec![0..request.connections.len() as u64],
+        };
+
+        let mut state = self.state.lock();
+        let id = state.next_id;
+        state.next_id += 1;
+        let job = ExportJob {
+            id,
+            request,
+            state: JobState::Running,
+            created: SystemTime::now(),
+            finished: None,
+            segments: segments
+                .into_iter()
+                .map(|ids| Segment {
+                    ids,
+                    done: false,
+                    items: 0,
+                })
+                .collect(),
+            size: 0,
+            error: None,
+        };
+        fs::create_dir_all(self.job_dir(id)).map_err(|err| err.to_string())?;
+        self.save(&job);
+        state.queue.extend((0..job.segments.len()).map(|n| (id, n)));
+        let cancel = Arc::new(AtomicBool::new(false));
+        state.jobs.insert(
+            id,
+            Job {
+                inner: job.clone(),
+                cancel,
+            },
+        );
+        drop(state);
+        self.cv.notify_all();
+
+        // nothing to export, the result is empty
+        if job.segments.is_empty() {
+            self.finish(id);
+        }
+        Ok(job)
+    }
+
+    pub fn list(&self) -> Vec<ExportJob> {
+        let state = self.state.lock();
+        state.jobs.values().map(|job| job.inner.clone()).collect()
+    }
+
+    pub fn get(&self, id: u64) -> Option<ExportJob> {
+        self.state.lock().jobs.get(&id).map(|job| job.inner.clone())
+    }
+
+    /// Stops the workers of the job and deletes its files.
+    pub fn cancel(&self, id: u64) -> Option<ExportJob> {
+        let mut state = self.state.lock();
+        let job = state.jobs.get_mut(&id)?;
+        if job.inner.state == JobState::Running {
+            job.cancel.store(true, Ordering::Relaxed);
+            job.inner.state = JobState::Cancelled;
+            job.inner.finished = Some(SystemTime::now());
+            self.save(&job.inner);
+            self.remove_files(id);
+        }
+        let job = job.inner.clone();
+        state.queue.retain(|(job_id, _)| *job_id != id);
+        Some(job)
+    }
+
+    /// The path of the result, when the job is done.
+    pub fn result(&self, id: u64) -> Option<(ExportJob, PathBuf)> {
+        let job = self.get(id).filter(|job| job.state == JobState::Done)?;
+        let path = self.result_path(&job);
+        Some((job, path))
+    }
+
+    fn job_dir(&self, id: u64) -> PathBuf {
+        self.dir.join(id.to_string())
+    }
+
+    fn result_path(&self, job: &ExportJob) -> PathBuf {
+        let name = format!("result.{}", job.request.format.extension());
+        self.job_dir(job.id).join(name)
+    }
+
+    fn save(&self, job: &ExportJob) {
+        let path = self.job_dir(job.id).join("job.json");
+        let result = serde_json::to_vec(job)
+            .map_err(io::Error::from)
+            .and_then(|b| fs::write(&path, b));
+        if let Err(err) = result {
+            log::error!("cannot store the export job {}: {err}", path.display());
+        }
+    }
+
+    fn remove_files(&self, id: u64) {
+        let entries = fs::read_dir(self.job_dir(id))
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok);
+        for entry in entries {
+            if entry.file_name() != "job.json" {
+                fs::remove_file(entry.path()).unwrap_or_default();
+            }
+        }
+    }
+
+    fn next_task(&self, timeout: Duration) -> Option<(u64, usize)> {
+        let mut state = self.state.lock();
+        if state.queue.is_empty() {
+            self.cv.wait_for(&mut state, timeout);
+        }
+        state.queue.pop_front()
+    }
+
+    fn run(&self, db: &DbCore, id: u64, n: usize) {
+        let (request, ids, cancel) = {
+            let state = self.state.lock();
+            match state.jobs.get(&id) {
+                Some(job) if job.inner.state == JobState::Running => (
+                    job.inner.request.clone(),
+                    job.inner.segments[n].ids.clone(),
+                    job.cancel.clone(),
+                ),
+                _ => return,
+            }
+        };
+        let dir = self.job_dir(id);
+        let part = dir.join(format!("{n}.part"));
+        let result = write_segment(db, &request, ids, &cancel, &part);
+
+        // rename under the lock, `cancel` could remove the files before the rename otherwise
+        let mut state = self.state.lock();
+        let job = match state.jobs.get_mut(&id) {
+            Some(job) if job.inner.state == JobState::Running => job,
+            _ => {
+                fs::remove_file(&part).unwrap_or_default();
+                return;
+            }
+        };
+        let result =
+            result.and_then(|items| fs::rename(&part, dir.join(n.to_string())).map(|()| items));
+        match result {
+            Ok(items) => {
+                job.inner.segments[n].done = true;
+                job.inner.segments[n].items = items;
+            }
+            Err(err) => {
+                log::error!("export job {id}, segment {n}: {err}");
+                fs::remove_file(&part).unwrap_or_default();
+                job.cancel.store(true, Ordering::Relaxed);
+                job.inner.state = JobState::Failed;
+                job.inner.finished = Some(SystemTime::now());
+                job.inner.error = Some(err.to_string());
+            }
+        }
+        self.save(&job.inner);
+        let all_done = job.inner.segments.iter().all(|s| s.done);
+        drop(state);
+
+        if all_done {
+            self.finish(id);
+        }
+    }
+
+    /// Concatenates the segments into the result.
+    fn finish(&self, id: u64) {
+        let job = match self.get(id) {
+            Some(v) => v,
+            None => return,
+        };
+        let path = self.result_path(&job);
+        let dir = self.job_dir(id);
+        let result = File::create(&path).and_then(|mut out| {
+            for n in 0..job.segments.len() {
+                let segment = dir.join(n.to_string());
+                io::copy(&mut File::open(&segment)?, &mut out)?;
+                fs::remove_file(segment)?;
+            }
+            out.sync_all()?;
+            out.metadata().map(|m| m.len())
+        });
+
+        let mut state = self.state.lock();
+        let job = match state.jobs.get_mut(&id) {
+            Some(job) if job.inner.state == JobState::Running => job,
+            _ => return,
+        };
+        job.inner.finished = Some(SystemTime::now());
+        match result {
+            Ok(size) => {
+                job.inner.state = JobState::Done;
+                job.inner.size = size;
+            }
+            Err(err) => {
+                log::error!("export job {id}: {err}");
+                job.inner.state = JobState::Failed;
+                job.inner.error = Some(err.to_string());
+            }
+        }
+        self.save(&job.inner);
+    }
+}
+
+#[derive(Serialize)]
+struct NdjsonLine<'a> {
+    id: u64,
+    #[serde(flatten)]
+    message: &'a FullMessage,
+}
+
+/// Returns how many items are written.
+fn write_segment(
+    db: &DbCore,
+    request: &ExportRequest,
+    ids: Range<u64>,
+    cancel: &AtomicBool,
+    path: &Path,
+) -> io::Result<u64> {
+    let mut out = BufWriter::new(File::create(path)?);
+    let mut items = 0;
+    match request.format {
+        ExportFormat::Ndjson => {
+            for (id, message) in db.fetch_full_messages(ids, request.redaction) {
+                if cancel.load(Ordering::Relaxed) {
+                    break;
+                }
+                match message {
+                    Ok(message) => {
+                        serde_json::to_writer(
+                            &mut out,
+                            &NdjsonLine {
+                                id,
+                                message: &message,
+                            },
+                        )?;
+                        out.write_all(b"\n")?;
+                        items += 1;
+                    }
+                    Err(err) => log::warn!("export message {id}: {err}"),
+                }
+            }
+        }
+        ExportFormat::Bundle => {
+            let connections = request.connections[ids.start as usize..ids.end as usize]
+                .iter()
+                .map(|id| ConnectionId(*id))
+                .collect::<Vec<_>>();
+            let options = BundleOptions {
+                keys: false,
+                redaction: request.redaction,
+                recipient: Recipient::from_env(),
+            };
+            let summary = bundle::export(db, &connections, &options, &mut out)
+                .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+            items = summary.connections;
+        }
+    }
+    out.into_inner()?.sync_all()?;
+    Ok(items)
+}
+
+/// Runs the export jobs in `EXPORT_WORKERS` threads, the default is 4.
+pub fn spawn(db: DbCore, terminating: Arc<AtomicBool>) -> thread::JoinHandle<()> {
+    let workers = env::var("EXPORT_WORKERS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(4usize)
+        .max(1);
+
+    thread::spawn(move || {
+        let handles = (0..workers)
+            .map(|_| {
+                let db = db.clone();
+                let terminating = terminating.clone();
+                thread::spawn(move || {
+                    while !terminating.load(Ordering::SeqCst) {
+                        let jobs = db.export_jobs();
+                        if let Some((id, n)) = jobs.next_task(Duration::from_secs(1)) {
+                            jobs.run(&db, id, n);
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap_or_default();
+        }
+    })
+}
+
+#[cfg(test)]
+#[test]
+fn export_resumed() {
+    use std::io::{BufRead, BufReader};
+
+    use crate::{
+        database::{DbFacade, StreamId, StreamKind},
+        event::ConnectionInfo,
+    };
+
+    let dir = temp_dir::TempDir::new().unwrap();
+    let db = DbFacade::open(dir.path().join("db")).unwrap();
+    let info = ConnectionInfo {
+        addr: "1.2.3.4:8302".parse().unwrap(),
+        pid: 1,
+        fd: 10,
+    };
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_675_166_400);
+    let group = db.add(info, true, "node".to_owned(), time).unwrap();
+    let stream = group.get(StreamId::Forward(1));
+    for i in 0..5 {
+        let time = time + Duration::from_secs(i);
+        stream
+            .add_at(i % 2 == 0, time, StreamKind::Select, b"/coda/yamux/1.0.0\n")
+            .unwrap();
+    }
+    let core = db.core();
+
+    let path = dir.path().join("exports");
+    let mut jobs = ExportJobs::open(&path);
+    jobs.segment = 2;
+    let request = || ExportRequest {
+        format: ExportFormat::Ndjson,
+        since: 0,
+        until: None,
+        connections: vec![],
+        redaction: Redaction::default(),
+    };
+    let job = jobs.create(&core, request()).unwrap();
+    let ranges = job
+        .segments
+        .iter()
+        .map(|s| s.ids.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(ranges, [0..2, 2..4, 4..5]);
+
+    // the second segment is done, then the restart
+    let (id, _) = jobs.next_task(Duration::ZERO).unwrap();
+    let (_, n) = jobs.next_task(Duration::ZERO).unwrap();
+    assert_eq!(n, 1);
+    jobs.run(&core, id, n);
+    assert!(jobs.get(id).unwrap().segments[1].done);
+    drop(jobs);
+
+    let jobs = ExportJobs::load(&path);
+    let job = jobs.get(id).unwrap();
+    assert_eq!(job.state, JobState::Running);
+    assert_eq!(job.progress(), 1.0 / 3.0);
+    while let Some((id, n)) = jobs.next_task(Duration::ZERO) {
+        assert_ne!(n, 1, "the done segment is not written again");
+        jobs.run(&core, id, n);
+    }
+
+    let (job, result) = jobs.result(id).unwrap();
+    assert_eq!(job.segments.iter().map(|s| s.items).sum::<u64>(), 5);
+    let ids = BufReader::new(File::open(result).unwrap())
+        .lines()
+        .map(|line| {
+            serde_json::from_str::<serde_json::Value>(&line.unwrap()).unwrap()["id"].clone()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(ids, (0..5).map(serde_json::Value::from).collect::<Vec<_>>());
+    // only the job and its result are left
+    assert_eq!(fs::read_dir(jobs.job_dir(id)).unwrap().count(), 2);
+
+    // the cancelled job leaves no segment behind
+    let job = jobs.create(&core, request()).unwrap();
+    jobs.cancel(job.id).unwrap();
+    jobs.run(&core, job.id, 0);
+    assert_eq!(fs::read_dir(jobs.job_dir(job.id)).unwrap().count(), 1);
+}
@@ -0,0 +1,264 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, Write, BufWriter},
+    net::SocketAddr,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use libp2p_core::PeerId;
+use radiation::{Absorb, Emit, AbsorbExt};
+use time::OffsetDateTime;
+
+use crate::{
+    custom_coding,
+    database::{StreamId, StreamKind},
+    sink::{Sink, SinkEvent, MessageEvent},
+};
+
+/// Header of each record in the flow file, followed by `size` bytes of the decrypted message.
+#[derive(Absorb, Emit)]
+pub struct FlowHeader {
+    pub size: u32,
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub time: SystemTime,
+    pub incoming: bool,
+    pub stream_id: StreamId,
+    pub stream_kind: StreamKind,
+}
+
+impl FlowHeader {
+    pub const SIZE: usize = 27; // size 4 + time 12 + incoming 1 + stream id 8 + stream kind 2
+}
+
+/// Reads records of the flow file.
+pub struct FlowParser<R>(R);
+
+impl<R> FlowParser<R>
+where
+    R: io::Read,
+{
+    pub fn new(inner: R) -> Self {
+        FlowParser(inner)
+    }
+}
+
+impl<R> Iterator for FlowParser<R>
+where
+    R: io::Read,
+{
+    type Item = (FlowHeader, Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut header_bytes = vec![0; FlowHeader::SIZE];
+        self.0.read_exact(&mut header_bytes).ok()?;
+        let header = FlowHeader::absorb_ext(&header_bytes).ok()?;
+        let mut data = vec![0; header.size as usize];
+        self.0.read_exact(&mut data).ok()?;
+        Some((header, data))
+    }
+}
+
+/// When to start the next file of the connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlowRotation {
+    pub max_size: u64,
+    pub max_age: Duration,
+}
+
+impl Default for FlowRotation {
+    fn default() -> Self {
+        FlowRotation {
+            max_size: 64 * 1024 * 1024,
+            max_age: Duration::from_secs(3600),
+        }
+    }
+}
+
+struct FlowFile {
+    writer: BufWriter<File>,
+    size: u64,
+    opened: SystemTime,
+}
+
+struct Flow {
+    addr: SocketAddr,
+    alias: String,
+    peer_id: Option<PeerId>,
+    file: Option<FlowFile>,
+}
+
+impl Flow {
+    /// Like `<alias>_<peer>_<connection id>_<timestamp>.flow`,
+    /// the peer is its peer id if known, otherwise its address.
+    fn file_name(&self, id: u64, time: SystemTime) -> String {
+        let peer = match &self.peer_id {
+            Some(peer_id) => peer_id.to_string(),
+            None => format!("{}-{}", self.addr.ip(), self.addr.port()),
+        };
+        let t = OffsetDateTime::from(time);
+        let (year, month, day) = (t.year(), t.month() as u8, t.day());
+        let (hour, minute, second, milli) = t.time().as_hms_milli();
+        let alias = &self.alias;
+        format!(
+            "{alias}_{peer}_{id}_{year:04}{month:02}{day:02}T{hour:02}{minute:02}{second:02}.{milli:03}.flow"
+        )
+    }
+}
+
+/// Writes decrypted messages of each connection into its own files, without the database.
+/// The file is rotated when it exceeds the size or the age.
+pub struct FlowSink {
+    dir: PathBuf,
+    rotation: FlowRotation,
+    flows: BTreeMap<u64, Flow>,
+}
+
+impl FlowSink {
+    pub fn create(dir: PathBuf, rotation: FlowRotation) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(FlowSink {
+            dir,
+            rotation,
+            flows: BTreeMap::default(),
+        })
+    }
+
+    fn write(&mut self, msg: &MessageEvent) -> io::Result<()> {
+        let flow = match self.flows.get_mut(&msg.id) {
+            Some(v) => v,
+            // the connection was opened before the sink
+            None => return Ok(()),
+        };
+        let record_size = (FlowHeader::SIZE + msg.bytes.len()) as u64;
+        if let Some(file) = &mut flow.file {
+            let age = msg.time.duration_since(file.opened).unwrap_or_default();
+            let too_big = file.size != 0 && file.size + record_size > self.rotation.max_size;
+            if too_big || age >= self.rotation.max_age {
+                file.writer.flush()?;
+                flow.file = None;
+            }
+        }
+        if flow.file.is_none() {
+            let path = self.dir.join(flow.file_name(msg.id, msg.time));
+            flow.file = Some(FlowFile {
+                writer: BufWriter::new(File::create(path)?),
+                size: 0,
+                opened: msg.time,
+            });
+        }
+        let file = flow.file.as_mut().expect("just created");
+
+        let header = FlowHeader {
+            size: msg.bytes.len() as u32,
+            time: msg.time,
+            incoming: msg.incoming,
+            stream_id: msg.stream_id,
+            stream_kind: msg.stream_kind,
+        };
+        file.writer.write_all(&header.chain(vec![]))?;
+        file.writer.write_all(&msg.bytes)?;
+        file.size += record_size;
+
+        Ok(())
+    }
+}
+
+impl Sink for FlowSink {
+    fn send(&mut self, event: &SinkEvent) -> io::Result<()> {
+        match event {
+            SinkEvent::Connection(v) => {
+                let flow = Flow {
+                    addr: v.info.addr,
+                    alias: v.alias.clone(),
+                    peer_id: None,
+                    file: None,
+                };
+                self.flows.insert(v.id, flow);
+                Ok(())
+            }
+            SinkEvent::PeerIdentity(v) => {
+                if let Some(flow) = self.flows.get_mut(&v.id) {
+                    flow.peer_id = Some(v.peer_id);
+                }
+                Ok(())
+            }
+            SinkEvent::Message(v) => self.write(v),
+            SinkEvent::Close(id) => match self.flows.remove(id).and_then(|flow| flow.file) {
+                Some(mut file) => file.writer.flush(),
+                None => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flows
+            .values_mut()
+            .filter_map(|flow| flow.file.as_mut())
+            .try_for_each(|file| file.writer.flush())
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn flow_rotation() {
+    use crate::{event::ConnectionInfo, sink::ConnectionEvent};
+
+    let dir = temp_dir::TempDir::new().unwrap();
+    let rotation = FlowRotation {
+        max_size: 2 * (FlowHeader::SIZE as u64 + 10),
+        max_age: Duration::from_secs(60),
+    };
+    let mut sink = FlowSink::create(dir.path().to_owned(), rotation).unwrap();
+
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_675_166_400);
+    let connection = ConnectionEvent {
+        id: 3,
+        info: ConnectionInfo {
+            addr: "1.2.3.4:8302".parse().unwrap(),
+            pid: 1,
+            fd: 10,
+        },
+        incoming: true,
+        alias: "node".to_owned(),
+        timestamp: time,
+    };
+    sink.send(&SinkEvent::Connection(connection)).unwrap();
+    // two records per file, then the third record is one minute later
+    for (i, secs) in [0, 1, 2, 3, 64].into_iter().enumerate() {
+        let msg = MessageEvent {
+            id: 3,
+            seq: i as u64,
+            stream_id: StreamId::Forward(1),
+            stream_kind: StreamKind::Rpc,
+            incoming: i % 2 == 0,
+            time: time + Duration::from_secs(secs),
+            bytes: vec![i as u8; 10],
+        };
+        sink.send(&SinkEvent::Message(msg)).unwrap();
+    }
+    sink.send(&SinkEvent::Close(3)).unwrap();
+
+    let mut names = fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(
+        names,
+        [
+            "node_1.2.3.4-8302_3_20230131T120000.000.flow",
+            "node_1.2.3.4-8302_3_20230131T120002.000.flow",
+            "node_1.2.3.4-8302_3_20230131T120104.000.flow",
+        ]
+    );
+
+    let file = File::open(dir.path().join(&names[1])).unwrap();
+    let records = FlowParser::new(file).collect::<Vec<_>>();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[1].1, vec![3; 10]);
+    assert!(!records[1].0.incoming);
+}
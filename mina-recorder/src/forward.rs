@@ -0,0 +1,341 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write, BufWriter, BufReader},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use radiation::{Emit, AbsorbExt};
+
+use crate::{
+    database::{DbFacade, DbGroup, DbError},
+    seal::{Identity, Recipient},
+    sink::{Sink, SinkEvent},
+};
+
+/// The top bit of the length marks the sealed batch of frames.
+const SEALED: u32 = 0x8000_0000;
+
+/// The largest event the forwarder sends, the messages of the node are far smaller.
+const MAX_EVENT: usize = 0x2000000;
+
+/// The length comes from the peer before anything is authenticated, a larger frame is rejected.
+/// The sealed batch is at most `Forwarder::MAX_BATCH` plus one event plus the overhead of the seal.
+const MAX_FRAME: u32 = 0x4000000;
+
+/// Sends events to the remote instance over TCP, each event is prefixed by its length
+/// (4 bytes big endian). Reconnects if the connection is lost.
+/// With the recipient, the frames are collected and sent as sealed batches.
+pub struct Forwarder {
+    remote: String,
+    stream: Option<BufWriter<TcpStream>>,
+    last_attempt: Option<Instant>,
+    recipient: Option<Recipient>,
+    batch: Vec<u8>,
+}
+
+impl Forwarder {
+    const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+    const MAX_BATCH: usize = 0x100000;
+
+    pub fn new(remote: String) -> Self {
+        Forwarder {
+            remote,
+            stream: None,
+            last_attempt: None,
+            recipient: None,
+            batch: vec![],
+        }
+    }
+
+    /// Seal the events to the recipient, the remote instance cannot read them
+    /// unless it has the identity, see `Unseal`.
+    pub fn sealed(mut self, recipient: Option<Recipient>) -> Self {
+        self.recipient = recipient;
+        self
+    }
+
+    fn connect(&mut self) -> io::Result<&mut BufWriter<TcpStream>> {
+        if self.stream.is_none() {
+            if let Some(last) = self.last_attempt {
+                if last.elapsed() < Self::RECONNECT_INTERVAL {
+                    return Err(io::ErrorKind::NotConnected.into());
+                }
+            }
+            self.last_attempt = Some(Instant::now());
+            let stream = TcpStream::connect(&self.remote)?;
+            log::info!("forwarding to {}", self.remote);
+            self.stream = Some(BufWriter::new(stream));
+        }
+        Ok(self.stream.as_mut().expect("connected"))
+    }
+
+    fn write_frame(&mut self, len: u32, frame: &[u8]) -> io::Result<()> {
+        let s = self.connect()?;
+        let r = s
+            .write_all(&len.to_be_bytes())
+            .and_then(|()| s.write_all(frame));
+        if r.is_err() {
+            self.stream = None;
+        }
+        r
+    }
+
+    /// The batch is dropped if it cannot be sent, like a single event.
+    fn send_batch(&mut self) -> io::Result<()> {
+        let Some(recipient) = &self.recipient else {
+            return Ok(());
+        };
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        let sealed = recipient.seal_bytes(&self.batch);
+        self.batch.clear();
+        let sealed = sealed?;
+        self.write_frame(sealed.len() as u32 | SEALED, &sealed)
+    }
+}
+
+impl Sink for Forwarder {
+    fn send(&mut self, event: &SinkEvent) -> io::Result<()> {
+        let frame = event.chain(vec![]);
+        if frame.len() > MAX_EVENT {
+            let msg = format!("the event of {} bytes is too large to forward", frame.len());
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        }
+        if self.recipient.is_some() {
+            self.batch
+                .extend_from_slice(&(frame.len() as u32).to_be_bytes());
+            self.batch.extend_from_slice(&frame);
+            if self.batch.len() >= Self::MAX_BATCH {
+                self.send_batch()?;
+            }
+            return Ok(());
+        }
+        self.write_frame(frame.len() as u32, &frame)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send_batch()?;
+        let r = match &mut self.stream {
+            Some(s) => s.flush(),
+            None => Ok(()),
+        };
+        if r.is_err() {
+            self.stream = None;
+        }
+        r
+    }
+}
+
+/// What the remote instance does with the sealed batches.
+pub enum Unseal {
+    /// open and store them, like the plain events
+    Identity(Identity),
+    /// the remote instance cannot read them, append them to a file per edge recorder
+    /// in the directory, `import_spool` stores them later
+    Spool(PathBuf),
+}
+
+impl Unseal {
+    /// The identity file from `FORWARD_IDENTITY`, otherwise the spool directory
+    /// `FORWARD_SPOOL`, by default `sealed` in the capture directory.
+    pub fn from_env<P>(db_path: P) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        if let Ok(path) = std::env::var("FORWARD_IDENTITY") {
+            return Identity::read(path).map(Unseal::Identity);
+        }
+        let dir = std::env::var("FORWARD_SPOOL")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| db_path.as_ref().join("sealed"));
+        fs::create_dir_all(&dir)?;
+        Ok(Unseal::Spool(dir))
+    }
+}
+
+/// Accept edge recorders and store what they send.
+pub fn spawn_receiver(
+    listener: TcpListener,
+    db: Arc<DbFacade>,
+    unseal: Arc<Unseal>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(v) => v,
+                Err(err) => {
+                    log::error!("forwarding receiver: {err}");
+                    continue;
+                }
+            };
+            let db = db.clone();
+            let unseal = unseal.clone();
+            thread::spawn(move || {
+                let peer = stream
+                    .peer_addr()
+                    .map(|a| a.to_string())
+                    .unwrap_or_default();
+                log::info!("edge recorder {peer} connected");
+                let spool = match &*unseal {
+                    Unseal::Identity(_) => None,
+                    Unseal::Spool(dir) => Some(dir.join(format!("{peer}.sealed"))),
+                };
+                match receive(BufReader::new(stream), &db, &unseal, spool.as_deref()) {
+                    Ok(()) => log::info!("edge recorder {peer} disconnected"),
+                    Err(err) => log::error!("edge recorder {peer}: {err}"),
+                }
+            });
+        }
+    })
+}
+
+/// Stores the sealed batches the remote instance spooled, the events of one edge recorder
+/// are in one file.
+pub fn import_spool<P>(path: P, db: &DbFacade, identity: Identity) -> io::Result<()>
+where
+    P: AsRef<Path>,
+{
+    let file = BufReader::new(File::open(path)?);
+    receive(file, db, &Unseal::Identity(identity), None)
+}
+
+fn read_frame<R>(stream: &mut R, buf: &mut Vec<u8>) -> io::Result<Option<u32>>
+where
+    R: Read,
+{
+    let mut len = [0; 4];
+    match stream.read_exact(&mut len) {
+        Ok(()) => (),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    let len = u32::from_be_bytes(len);
+    if len & !SEALED > MAX_FRAME {
+        let msg = format!("the frame of {} bytes is too large", len & !SEALED);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+    }
+    buf.resize((len & !SEALED) as usize, 0);
+    stream.read_exact(buf)?;
+    Ok(Some(len))
+}
+
+fn receive<R>(mut stream: R, db: &DbFacade, unseal: &Unseal, spool: Option<&Path>) -> io::Result<()>
+where
+    R: Read,
+{
+    let mut groups = BTreeMap::<u64, DbGroup>::new();
+    let mut spool = spool
+        .map(|path| OpenOptions::new().create(true).append(true).open(path))
+        .transpose()?;
+    let mut buf = vec![];
+    while let Some(len) = read_frame(&mut stream, &mut buf)? {
+        if len & SEALED == 0 {
+            store(&buf, db, &mut groups)?;
+            continue;
+        }
+        match (unseal, &mut spool) {
+            (Unseal::Identity(identity), _) => {
+                let batch = identity.open_bytes(&buf)?;
+                let mut batch = batch.as_slice();
+                let mut frame = vec![];
+                while read_frame(&mut batch, &mut frame)?.is_some() {
+                    store(&frame, db, &mut groups)?;
+                }
+            }
+            (Unseal::Spool(_), Some(file)) => {
+                file.write_all(&len.to_be_bytes())?;
+                file.write_all(&buf)?;
+            }
+            (Unseal::Spool(_), None) => {
+                log::warn!("sealed batch of {} bytes is dropped", buf.len());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn store(frame: &[u8], db: &DbFacade, groups: &mut BTreeMap<u64, DbGroup>) -> io::Result<()> {
+    let event = match SinkEvent::absorb_ext(frame) {
+        Ok(v) => v,
+        Err(err) => {
+            let err = format!("cannot decode forwarded event: {err}");
+            return Err(io::Error::new(io::ErrorKind::InvalidData, err));
+        }
+    };
+    if let Err(err) = apply(event, db, groups) {
+        log::error!("cannot store forwarded event: {err}");
+    }
+    Ok(())
+}
+
+pub(crate) fn apply(
+    event: SinkEvent,
+    db: &DbFacade,
+    groups: &mut BTreeMap<u64, DbGroup>,
+) -> Result<(), DbError> {
+    fn group(groups: &BTreeMap<u64, DbGroup>, id: u64) -> Result<&DbGroup, DbError> {
+        groups
+            .get(&id)
+            .ok_or_else(|| DbError::NoItemAtCursor(format!("forwarded connection {id}")))
+    }
+
+    match event {
+        SinkEvent::Connection(v) => {
+            let group = db.add(v.info, v.incoming, v.alias, v.timestamp)?;
+            groups.insert(v.id, group);
+        }
+        SinkEvent::Close(id) => {
+            groups.remove(&id);
+        }
+        SinkEvent::Update(v) => {
+            let group = group(groups, v.id)?;
+            group.set_layers(v.incoming, v.layers);
+            group.update(v.stats, v.incoming)?
+        }
+        SinkEvent::PeerIdentity(v) => {
+            group(groups, v.id)?.add_peer_identity(v.peer_id, v.timestamp)?
+        }
+        SinkEvent::Superseded(v) => {
+            let by = group(groups, v.by)?.id();
+            group(groups, v.id)?.set_superseded(by)?
+        }
+        SinkEvent::Raw(v) => {
+            group(groups, v.id)?.add_raw(v.encryption_status, v.incoming, v.time, &v.bytes)?;
+        }
+        SinkEvent::Message(v) => {
+            group(groups, v.id)?.get(v.stream_id).add_at(
+                v.incoming,
+                v.time,
+                v.stream_kind,
+                &v.bytes,
+            )?;
+        }
+        SinkEvent::Stats(v) => db.core().put_stats(v.height, v.node_address, v.value)?,
+        SinkEvent::StatsBlockV2(v) => db.core().put_stats_block_v2(v)?,
+        SinkEvent::StatsTx(v) => db.core().put_stats_tx(v.height, v.value)?,
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+#[test]
+fn frame_too_large() {
+    let mut buf = vec![];
+    let mut stream = &[0, 0, 0, 3, 1, 2, 3][..];
+    assert_eq!(read_frame(&mut stream, &mut buf).unwrap(), Some(3));
+    assert_eq!(buf, [1, 2, 3]);
+    assert_eq!(read_frame(&mut stream, &mut buf).unwrap(), None);
+
+    for len in [0x7fff_ffff, MAX_FRAME + 1, (MAX_FRAME + 1) | SEALED] {
+        let mut stream = &len.to_be_bytes()[..];
+        let err = read_frame(&mut stream, &mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
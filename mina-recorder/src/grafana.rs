@@ -0,0 +1,307 @@
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+
+use crate::database::{DbCore, ConnectionId};
+
+/// The body of `POST /search`.
+#[derive(Default, Deserialize)]
+pub struct SearchRequest {
+    #[serde(default)]
+    pub target: String,
+}
+
+/// The body of `POST /query`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryRequest {
+    pub range: Range,
+    pub interval_ms: Option<u64>,
+    pub max_data_points: Option<u64>,
+    pub targets: Vec<Target>,
+}
+
+#[derive(Deserialize)]
+pub struct Range {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Target {
+    pub target: String,
+    pub ref_id: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct TimeSeries {
+    pub target: String,
+    /// pairs of value and unix time in milliseconds
+    pub datapoints: Vec<(f64, u64)>,
+}
+
+#[derive(Debug)]
+pub struct Window {
+    pub from: SystemTime,
+    pub to: SystemTime,
+    pub interval: Duration,
+}
+
+impl Window {
+    /// The query comes from the client, the interval is widened
+    /// so the buckets are at most this many.
+    pub const MAX_BUCKETS: u32 = 0x10000;
+}
+
+impl QueryRequest {
+    /// Time range of the query and the width of the bucket.
+    pub fn window(&self) -> Result<Window, String> {
+        let parse = |s: &str| {
+            OffsetDateTime::parse(s, &Rfc3339)
+                .map(SystemTime::from)
+                .map_err(|err| format!("{s}: {err}"))
+        };
+        let from = parse(&self.range.from)?;
+        let to = parse(&self.range.to)?;
+        let range = to.duration_since(from).map_err(|err| err.to_string())?;
+        let by_points = self
+            .max_data_points
+            .filter(|n| *n != 0)
+            .map(|n| {
+                let n = u32::try_from(n).unwrap_or(u32::MAX);
+                range / n.clamp(1, Window::MAX_BUCKETS)
+            })
+            .unwrap_or_default();
+        let interval = Duration::from_millis(self.interval_ms.unwrap_or(1_000))
+            .max(by_points)
+            .max(range / Window::MAX_BUCKETS)
+            .max(Duration::from_millis(1));
+        Ok(Window { from, to, interval })
+    }
+}
+
+/// Values accumulated in buckets of equal width.
+pub struct Buckets {
+    from: SystemTime,
+    interval: Duration,
+    values: Vec<f64>,
+}
+
+impl Buckets {
+    pub fn new(window: &Window) -> Self {
+        let range = window.to.duration_since(window.from).unwrap_or_default();
+        let interval = window.interval.max(Duration::from_nanos(1));
+        let len =
+            (range.as_nanos() / interval.as_nanos()).min(Window::MAX_BUCKETS.into()) as usize + 1;
+        Buckets {
+            from: window.from,
+            interval,
+            values: vec![0.0; len],
+        }
+    }
+
+    fn bucket(&mut self, time: SystemTime) -> Option<&mut f64> {
+        let offset = time.duration_since(self.from).ok()?;
+        let i = (offset.as_nanos() / self.interval.as_nanos()) as usize;
+        self.values.get_mut(i)
+    }
+
+    pub fn add(&mut self, time: SystemTime, value: f64) {
+        if let Some(v) = self.bucket(time) {
+            *v += value;
+        }
+    }
+
+    pub fn add_max(&mut self, time: SystemTime, value: f64) {
+        if let Some(v) = self.bucket(time) {
+            *v = v.max(value);
+        }
+    }
+
+    /// Per second, instead of per bucket.
+    pub fn into_rate(mut self) -> Self {
+        let secs = self.interval.as_secs_f64();
+        self.values.iter_mut().for_each(|v| *v /= secs);
+        self
+    }
+
+    pub fn into_series(self, target: String) -> TimeSeries {
+        let from = self
+            .from
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let datapoints = self
+            .values
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| (v, (from + self.interval * i as u32).as_millis() as u64))
+            .collect();
+        TimeSeries { target, datapoints }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Metric {
+    BandwidthIn,
+    BandwidthOut,
+    MessageRate,
+    BlockLatency,
+}
+
+impl Metric {
+    const ALL: [(&'static str, Self); 4] = [
+        ("bandwidth_in", Metric::BandwidthIn),
+        ("bandwidth_out", Metric::BandwidthOut),
+        ("message_rate", Metric::MessageRate),
+        ("block_latency", Metric::BlockLatency),
+    ];
+
+    /// The target is the name of the metric,
+    /// optionally followed by `/` and the address of the peer, like `bandwidth_in/1.2.3.4:8302`.
+    fn parse(target: &str) -> Option<(Self, Option<SocketAddr>)> {
+        let (name, peer) = match target.split_once('/') {
+            Some((name, addr)) => (name, Some(addr.parse().ok()?)),
+            None => (target, None),
+        };
+        let (_, metric) = Self::ALL.iter().find(|(n, _)| *n == name)?;
+        Some((*metric, peer))
+    }
+}
+
+/// Names of the metrics available in `/query`.
+pub fn search(request: &SearchRequest) -> Vec<&'static str> {
+    Metric::ALL
+        .iter()
+        .map(|(name, _)| *name)
+        .filter(|name| name.starts_with(&request.target))
+        .collect()
+}
+
+pub fn query(db: &DbCore, request: &QueryRequest) -> Result<Vec<TimeSeries>, String> {
+    let window = request.window()?;
+    let mut addresses = BTreeMap::<ConnectionId, Option<SocketAddr>>::new();
+    let mut addr_of = |id: ConnectionId| {
+        *addresses
+            .entry(id)
+            .or_insert_with(|| db.fetch_connection(id.0).ok().map(|cn| cn.info.addr))
+    };
+
+    let mut series = vec![];
+    for Target { target, .. } in &request.targets {
+        let (metric, peer) = match Metric::parse(target) {
+            Some(v) => v,
+            None => {
+                log::warn!("grafana, unknown target {target}");
+                continue;
+            }
+        };
+        let mut buckets = Buckets::new(&window);
+        match metric {
+            Metric::BandwidthIn | Metric::BandwidthOut | Metric::MessageRate => {
+                for msg in db.fetch_messages_in_range(window.from, window.to) {
+                    let matches = match metric {
+                        Metric::BandwidthIn => msg.incoming,
+                        Metric::BandwidthOut => !msg.incoming,
+                        _ => true,
+                    };
+                    if !matches || (peer.is_some() && addr_of(msg.connection_id) != peer) {
+                        continue;
+                    }
+                    let value = match metric {
+                        Metric::MessageRate => 1.0,
+                        _ => msg.size as f64,
+                    };
+                    buckets.add(msg.timestamp, value);
+                }
+                buckets = buckets.into_rate();
+            }
+            Metric::BlockLatency => {
+                // maximal latency of the block sent by the node in each bucket, in seconds
+                let mut height = db.fetch_last_stat_block_v2().map(|(h, _)| h).unwrap_or(0);
+                while height > 0 {
+                    let events = db.fetch_stats_block_v2(height);
+                    if events.iter().all(|event| event.time < window.from) {
+                        break;
+                    }
+                    for event in events {
+                        let latency = match event.latency {
+                            Some(v) => v,
+                            None => continue,
+                        };
+                        if peer.is_some() && Some(event.receiver_addr) != peer {
+                            continue;
+                        }
+                        if event.time < window.to {
+                            buckets.add_max(event.time, latency.as_secs_f64());
+                        }
+                    }
+                    height -= 1;
+                }
+            }
+        }
+        series.push(buckets.into_series(target.clone()));
+    }
+
+    Ok(series)
+}
+
+#[cfg(test)]
+#[test]
+fn grafana_query() {
+    let s = r#"{"range":{"from":"2023-01-31T12:00:00.000Z","to":"2023-01-31T12:00:10.000Z"},"intervalMs":2000,"maxDataPoints":100,"targets":[{"target":"bandwidth_in/1.2.3.4:8302","refId":"A"}]}"#;
+    let request = serde_json::from_str::<QueryRequest>(s).unwrap();
+    let window = request.window().unwrap();
+    assert_eq!(window.interval, Duration::from_secs(2));
+    assert!(matches!(
+        Metric::parse(&request.targets[0].target),
+        Some((Metric::BandwidthIn, Some(_)))
+    ));
+
+    let mut buckets = Buckets::new(&window);
+    buckets.add(window.from + Duration::from_millis(500), 100.0);
+    buckets.add(window.from + Duration::from_millis(1500), 300.0);
+    buckets.add(window.from + Duration::from_secs(9), 50.0);
+    buckets.add(window.from - Duration::from_secs(1), 1000.0);
+    let series = buckets.into_rate().into_series("x".to_owned());
+    assert_eq!(series.datapoints.len(), 6);
+    assert_eq!(series.datapoints[0], (200.0, 1675166400000));
+    assert_eq!(series.datapoints[4], (25.0, 1675166408000));
+}
+
+#[cfg(test)]
+#[test]
+fn grafana_window_bounded() {
+    let request = |interval_ms: &str, max_data_points: &str| {
+        let s = format!(
+            r#"{{"range":{{"from":"2022-01-31T12:00:00.000Z","to":"2023-01-31T12:00:00.000Z"}},"intervalMs":{interval_ms},"maxDataPoints":{max_data_points},"targets":[]}}"#
+        );
+        serde_json::from_str::<QueryRequest>(&s).unwrap()
+    };
+
+    for (interval_ms, max_data_points) in [
+        ("1", "4294967296"),
+        ("1", "18446744073709551615"),
+        ("1", "0"),
+        ("1", "null"),
+        ("0", "1"),
+        ("18446744073709551615", "0"),
+    ] {
+        let window = request(interval_ms, max_data_points).window().unwrap();
+        assert!(window.interval >= Duration::from_millis(1));
+        let buckets = Buckets::new(&window).into_series(String::new());
+        assert!(buckets.datapoints.len() <= Window::MAX_BUCKETS as usize + 1);
+    }
+
+    // a year in one millisecond buckets would be 3*10^10 of them
+    let window = request("1", "null").window().unwrap();
+    let year = window.to.duration_since(window.from).unwrap();
+    assert_eq!(window.interval, year / Window::MAX_BUCKETS);
+    let window = request("1", "1").window().unwrap();
+    assert_eq!(window.interval, year);
+}
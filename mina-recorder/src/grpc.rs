@@ -0,0 +1,380 @@
+use std::{
+    io,
+    net::{AddrParseError, SocketAddr},
+    pin::Pin,
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use tokio::sync::broadcast;
+use tokio_stream::{Stream, StreamExt, wrappers::BroadcastStream};
+use tonic::{Request, Response, Status};
+
+use crate::{
+    database::{
+        CaptureReader, ConnectionFilter, MessageFilter, DbCore, DbError, Connection, Message,
+        ConnectionId, MessageId, StreamKind,
+    },
+    sink::{Sink, SinkEvent},
+};
+
+/// Generated from `proto/debugger.proto`.
+pub mod pb {
+    tonic::include_proto!("mina.debugger.v1");
+}
+
+fn nanos(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64
+}
+
+fn time(nanos: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_nanos(nanos)
+}
+
+fn db_status(err: DbError) -> Status {
+    match err {
+        DbError::NoItemAtCursor(_) => Status::not_found(err.to_string()),
+        err => Status::internal(err.to_string()),
+    }
+}
+
+fn stream_kinds(v: &[String]) -> Result<Vec<StreamKind>, Status> {
+    v.iter()
+        .map(|s| {
+            s.parse()
+                .map_err(|()| Status::invalid_argument(format!("unknown stream kind {s}")))
+        })
+        .collect()
+}
+
+/// The kinds as `Display` writes them, so they compare with `pb::Message::stream_kind`.
+fn stream_kind_names(v: &[String]) -> Result<Vec<String>, Status> {
+    Ok(stream_kinds(v)?.iter().map(ToString::to_string).collect())
+}
+
+/// The event passes the filter of `subscribe`.
+fn subscribed(kinds: &[String], event: &pb::Event) -> bool {
+    match &event.event {
+        Some(pb::event::Event::Message(msg)) => {
+            kinds.is_empty() || kinds.contains(&msg.stream_kind)
+        }
+        _ => true,
+    }
+}
+
+fn join_status(err: tokio::task::JoinError) -> Status {
+    Status::internal(err.to_string())
+}
+
+impl pb::Connection {
+    fn new(id: u64, cn: Connection) -> Self {
+        pb::Connection {
+            id,
+            addr: cn.info.addr.to_string(),
+            alias: cn.alias,
+            pid: cn.info.pid,
+            fd: cn.info.fd,
+            incoming: cn.incoming,
+            timestamp: nanos(cn.timestamp),
+            timestamp_close: nanos(cn.timestamp_close),
+            bytes_in: cn.stats_in.total_bytes,
+            bytes_out: cn.stats_out.total_bytes,
+            superseded_by: cn.superseded_by.map(|id| id.0),
+        }
+    }
+}
+
+impl pb::Message {
+    fn new(id: u64, msg: Message) -> Self {
+        pb::Message {
+            id,
+            connection_id: msg.connection_id.0,
+            stream_id: msg.stream_id.to_string(),
+            stream_kind: msg.stream_kind.to_string(),
+            incoming: msg.incoming,
+            timestamp: nanos(msg.timestamp),
+            size: msg.size,
+            types: msg
+                .brief
+                .split(',')
+                .filter(|s| !s.is_empty())
+                .map(ToOwned::to_owned)
+                .collect(),
+            seq: msg.seq,
+        }
+    }
+}
+
+/// Broadcasts live events to the subscribers.
+struct GrpcSink(broadcast::Sender<pb::Event>);
+
+impl Sink for GrpcSink {
+    fn send(&mut self, event: &SinkEvent) -> io::Result<()> {
+        use self::pb::event::Event;
+
+        if self.0.receiver_count() == 0 {
+            return Ok(());
+        }
+        let event = match event {
+            SinkEvent::Connection(v) => Event::Connection(pb::Connection {
+                id: v.id,
+                addr: v.info.addr.to_string(),
+                alias: v.alias.clone(),
+                pid: v.info.pid,
+                fd: v.info.fd,
+                incoming: v.incoming,
+                timestamp: nanos(v.timestamp),
+                ..Default::default()
+            }),
+            SinkEvent::Close(id) => Event::Closed(pb::ConnectionClosed {
+                id: *id,
+                timestamp: nanos(SystemTime::now()),
+            }),
+            SinkEvent::Message(v) => {
+                let types = crate::decode::parse_types(v.stream_kind, &v.bytes, false)
+                    .map(|(types, _)| types)
+                    .unwrap_or_default();
+                Event::Message(pb::Message {
+                    id: 0,
+                    connection_id: v.id,
+                    stream_id: v.stream_id.to_string(),
+                    stream_kind: v.stream_kind.to_string(),
+                    incoming: v.incoming,
+                    timestamp: nanos(v.time),
+                    size: v.bytes.len() as u32,
+                    types: types.iter().map(ToString::to_string).collect(),
+                    seq: v.seq,
+                })
+            }
+            _ => return Ok(()),
+        };
+        // the subscribers might be gone since the check
+        self.0
+            .send(pb::Event { event: Some(event) })
+            .unwrap_or_default();
+        Ok(())
+    }
+}
+
+type ResponseStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+struct Service {
+    reader: CaptureReader,
+    events: broadcast::Sender<pb::Event>,
+}
+
+impl Service {
+    const DEFAULT_LIMIT: u32 = 1000;
+    const MAX_LIMIT: u32 = 10_000;
+
+    fn limit(limit: Option<u32>) -> usize {
+        limit.unwrap_or(Self::DEFAULT_LIMIT).min(Self::MAX_LIMIT) as usize
+    }
+}
+
+#[tonic::async_trait]
+impl pb::debugger_server::Debugger for Service {
+    async fn get_connection(
+        &self,
+        request: Request<pb::GetConnectionRequest>,
+    ) -> Result<Response<pb::Connection>, Status> {
+        let id = request.into_inner().id;
+        let cn = self
+            .reader
+            .connection(ConnectionId(id))
+            .map_err(db_status)?;
+        Ok(Response::new(pb::Connection::new(id, cn)))
+    }
+
+    type ListConnectionsStream = ResponseStream<pb::Connection>;
+
+    async fn list_connections(
+        &self,
+        request: Request<pb::ListConnectionsRequest>,
+    ) -> Result<Response<Self::ListConnectionsStream>, Status> {
+        let request = request.into_inner();
+        let addr = request
+            .addr
+            .map(|s| s.parse())
+            .transpose()
+            .map_err(|err: AddrParseError| Status::invalid_argument(err.to_string()))?;
+        let filter = ConnectionFilter {
+            addr,
+            alias: request.alias,
+            incoming: request.incoming,
+            from: request.since.map(time),
+            to: request.until.map(time),
+        };
+        let limit = Self::limit(request.limit);
+        // the iteration blocks, it must not stall the runtime which serves `subscribe`
+        let reader = self.reader.clone();
+        let connections = tokio::task::spawn_blocking(move || {
+            reader
+                .connections(&filter)
+                .take(limit)
+                .map(|(id, cn)| Ok(pb::Connection::new(id.0, cn)))
+                .collect::<Vec<_>>()
+        })
+        .await
+        .map_err(join_status)?;
+        Ok(Response::new(Box::pin(tokio_stream::iter(connections))))
+    }
+
+    type ListMessagesStream = ResponseStream<pb::Message>;
+
+    async fn list_messages(
+        &self,
+        request: Request<pb::ListMessagesRequest>,
+    ) -> Result<Response<Self::ListMessagesStream>, Status> {
+        let request = request.into_inner();
+        let filter = MessageFilter {
+            connection_id: request.connection_id.map(ConnectionId),
+            stream_kinds: stream_kinds(&request.stream_kinds)?,
+            incoming: request.incoming,
+            from: request.since.map(time),
+            to: request.until.map(time),
+        };
+        let limit = Self::limit(request.limit);
+        let reader = self.reader.clone();
+        let messages = tokio::task::spawn_blocking(move || {
+            reader
+                .messages(&filter)
+                .take(limit)
+                .map(|(id, msg)| Ok(pb::Message::new(id.0, msg)))
+                .collect::<Vec<_>>()
+        })
+        .await
+        .map_err(join_status)?;
+        Ok(Response::new(Box::pin(tokio_stream::iter(messages))))
+    }
+
+    async fn get_message(
+        &self,
+        request: Request<pb::GetMessageRequest>,
+    ) -> Result<Response<pb::DecodedMessage>, Status> {
+        let id = MessageId(request.into_inner().id);
+        let msg = self.reader.message(id).map_err(db_status)?;
+        let decoded = self.reader.decoded(id).map_err(db_status)?;
+        let raw = self.reader.bytes(id).map_err(db_status)?;
+        Ok(Response::new(pb::DecodedMessage {
+            message: Some(pb::Message::new(id.0, msg)),
+            json: decoded.message.to_string(),
+            raw,
+        }))
+    }
+
+    type SubscribeStream = ResponseStream<pb::Event>;
+
+    async fn subscribe(
+        &self,
+        request: Request<pb::SubscribeRequest>,
+    ) -> Result<Response<Self::SubscribeStream>, Status> {
+        let kinds = stream_kind_names(&request.into_inner().stream_kinds)?;
+        let events = BroadcastStream::new(self.events.subscribe()).filter_map(move |event| {
+            // the subscriber is too slow and lost some events, skip them
+            let event = event.ok()?;
+            subscribed(&kinds, &event).then_some(Ok(event))
+        });
+        Ok(Response::new(Box::pin(events)))
+    }
+}
+
+/// Serve gRPC in a dedicated thread. Live events come through the sink,
+/// so it must be spawned after the sinks are configured.
+pub fn spawn(addr: SocketAddr, db: DbCore) -> thread::JoinHandle<()> {
+    let (tx, _) = broadcast::channel(0x1000);
+    db.sinks().add("grpc".to_owned(), GrpcSink(tx.clone()));
+    let service = Service {
+        reader: CaptureReader::new(db),
+        events: tx,
+    };
+    thread::spawn(move || {
+        let rt = match tokio::runtime::Runtime::new() {
+            Ok(v) => v,
+            Err(err) => {
+                log::error!("cannot start grpc server: {err}");
+                return;
+            }
+        };
+        log::info!("grpc server on {addr}");
+        let server = tonic::transport::Server::builder()
+            .add_service(pb::debugger_server::DebuggerServer::new(service))
+            .serve(addr);
+        if let Err(err) = rt.block_on(server) {
+            log::error!("grpc server: {err}");
+        }
+    })
+}
+
+#[cfg(test)]
+#[test]
+fn grpc_conversions() {
+    use crate::{database::StreamId, event::ConnectionInfo};
+
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_675_166_400);
+    let info = ConnectionInfo {
+        addr: "1.2.3.4:8302".parse().unwrap(),
+        pid: 1,
+        fd: 10,
+    };
+    let cn = Connection {
+        superseded_by: Some(ConnectionId(7)),
+        ..Connection::new(info, true, "node".to_owned(), time)
+    };
+    let v = pb::Connection::new(3, cn);
+    assert_eq!(
+        (v.id, v.addr.as_str(), v.alias.as_str()),
+        (3, "1.2.3.4:8302", "node")
+    );
+    assert_eq!((v.pid, v.fd, v.incoming), (1, 10, true));
+    assert_eq!(v.timestamp, 1_675_166_400_000_000_000);
+    assert_eq!((v.timestamp_close, v.superseded_by), (0, Some(7)));
+
+    let msg = |brief: &str| Message {
+        connection_id: ConnectionId(3),
+        stream_id: StreamId::Forward(1),
+        stream_kind: StreamKind::Meshsub,
+        incoming: false,
+        timestamp: time,
+        offset: 0,
+        size: 100,
+        brief: brief.to_owned(),
+        seq: 5,
+        unredacted: false,
+        decoder_version: 0,
+        transfer_ns: 0,
+        chunks: 1,
+    };
+    let v = pb::Message::new(9, msg("publish_new_state,publish_snark_pool_diff"));
+    assert_eq!((v.id, v.connection_id, v.size, v.seq), (9, 3, 100, 5));
+    assert_eq!(v.stream_kind, "/meshsub/1.1.0");
+    assert_eq!(v.types, ["publish_new_state", "publish_snark_pool_diff"]);
+    assert!(pb::Message::new(9, msg("")).types.is_empty());
+
+    // the filter of `subscribe` compares the names `pb::Message::new` writes
+    for kind in StreamKind::iter() {
+        assert_eq!(kind.to_string().parse::<StreamKind>(), Ok(kind));
+        let kinds = stream_kind_names(&[kind.to_string()]).unwrap();
+        let event = |stream_kind: StreamKind| pb::Event {
+            event: Some(pb::event::Event::Message(pb::Message {
+                stream_kind: stream_kind.to_string(),
+                ..Default::default()
+            })),
+        };
+        assert!(subscribed(&kinds, &event(kind)));
+        let other = StreamKind::iter().find(|k| *k != kind).unwrap();
+        assert!(!subscribed(&kinds, &event(other)));
+        assert!(subscribed(&[], &event(other)));
+    }
+    // an unknown name is the unknown kind, like in `list_messages`
+    assert_eq!(
+        stream_kind_names(&["/foo".to_owned()]).unwrap(),
+        ["unknown"]
+    );
+    let closed = pb::Event {
+        event: Some(pb::event::Event::Closed(pb::ConnectionClosed::default())),
+    };
+    assert!(subscribed(&["unknown".to_owned()], &closed));
+}
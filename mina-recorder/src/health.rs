@@ -0,0 +1,380 @@
+use std::{
+    env,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use crate::{
+    database::{DbCore, HealthSample},
+    sink::SinkStats,
+};
+
+/// The counters of the current interval, the sampler takes and resets them.
+pub struct Health {
+    events: AtomicU64,
+    buffered_max: AtomicU64,
+    lag_max_us: AtomicU64,
+    unordered: AtomicU64,
+    decoder_errors: AtomicU64,
+    stalls: AtomicU64,
+    ring_buffer_skipped: AtomicU64,
+    // the start of the interval and the totals of the sinks at the start
+    last: Mutex<(SystemTime, u64, u64)>,
+    ring_buffer: Mutex<Option<RingBufferStatus>>,
+    // pauses and resumes the reader of the ring buffer
+    pause: Mutex<Option<Box<dyn Fn(bool) + Send>>>,
+}
+
+/// The counters of the ring buffer, the thread reading it publishes them every few seconds.
+#[derive(Debug, Clone, Serialize)]
+pub struct RingBufferStatus {
+    pub time: SystemTime,
+    /// how full the buffer was after the last read
+    pub fill_percent: u8,
+    pub bytes_consumed: u64,
+    pub events_consumed: u64,
+    pub parse_errors: u64,
+    /// the latest events which cannot be parsed, a spike of them means the layout
+    /// of the event in the kernel module and in the recorder differ
+    pub parse_failures: Vec<ParseFailure>,
+    /// the most events read in a row without waiting for the kernel
+    pub largest_batch: usize,
+    /// skipped after the overflows
+    pub bytes_skipped: u64,
+    /// the reader consumes nothing, the kernel drops the events once the buffer is full
+    pub paused: bool,
+    /// the oldest unread event was this old at most since the previous status,
+    /// by the timestamp the kernel module gave it
+    pub lag_ns: u64,
+    /// since the previous status
+    pub bytes_per_second: f64,
+    pub events_per_second: f64,
+}
+
+/// The event in the ring buffer the recorder cannot parse.
+#[derive(Debug, Clone, Serialize)]
+pub struct ParseFailure {
+    /// the position in the ring buffer since the start
+    pub offset: u64,
+    pub length: usize,
+    pub error: String,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Health {
+            events: AtomicU64::new(0),
+            buffered_max: AtomicU64::new(0),
+            lag_max_us: AtomicU64::new(0),
+            unordered: AtomicU64::new(0),
+            decoder_errors: AtomicU64::new(0),
+            stalls: AtomicU64::new(0),
+            ring_buffer_skipped: AtomicU64::new(0),
+            last: Mutex::new((SystemTime::now(), 0, 0)),
+            ring_buffer: Mutex::new(None),
+            pause: Mutex::new(None),
+        }
+    }
+}
+
+impl Health {
+    /// Keeps this many parse failures of the previous statuses.
+    const PARSE_FAILURES: usize = 64;
+
+    /// The event is read from the ring buffer, `buffered` bytes are still there.
+    pub fn on_event(&self, buffered: usize, lag: Duration) {
+        self.events.fetch_add(1, Ordering::Relaxed);
+        self.buffered_max
+            .fetch_max(buffered as u64, Ordering::Relaxed);
+        self.lag_max_us
+            .fetch_max(lag.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn on_unordered(&self) {
+        self.unordered.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn on_decoder_error(&self) {
+        self.decoder_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn on_stall(&self) {
+        self.stalls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The ring buffer overflowed, `skipped` bytes of events are lost.
+    pub fn on_gap(&self, skipped: u64) {
+        self.ring_buffer_skipped
+            .fetch_add(skipped, Ordering::Relaxed);
+    }
+
+    /// The throughput is computed against the previous status.
+    pub fn set_ring_buffer(&self, mut status: RingBufferStatus) {
+        let mut ring_buffer = self.ring_buffer.lock();
+        if let Some(previous) = &mut *ring_buffer {
+            let mut failures = std::mem::take(&mut previous.parse_failures);
+            failures.append(&mut status.parse_failures);
+            let excess = failures.len().saturating_sub(Self::PARSE_FAILURES);
+            failures.drain(..excess);
+            status.parse_failures = failures;
+
+            let secs = status
+                .time
+                .duration_since(previous.time)
+                .unwrap_or_default()
+                .as_secs_f64();
+            if secs > 0.0 {
+                let bytes = status
+                    .bytes_consumed
+                    .saturating_sub(previous.bytes_consumed);
+                let events = status
+                    .events_consumed
+                    .saturating_sub(previous.events_consumed);
+                status.bytes_per_second = bytes as f64 / secs;
+                status.events_per_second = events as f64 / secs;
+            }
+        }
+        *ring_buffer = Some(status);
+    }
+
+    /// `None` if the events do not come from the ring buffer, for example on replay.
+    pub fn ring_buffer(&self) -> Option<RingBufferStatus> {
+        self.ring_buffer.lock().clone()
+    }
+
+    /// How to pause the reader of the ring buffer, `f(true)` pauses it, `f(false)` resumes.
+    pub fn set_pause<F>(&self, f: F)
+    where
+        F: Fn(bool) + Send + 'static,
+    {
+        *self.pause.lock() = Some(Box::new(f));
+    }
+
+    /// `false` if there is nothing to pause, for example on replay.
+    pub fn pause(&self, paused: bool) -> bool {
+        match &*self.pause.lock() {
+            Some(f) => {
+                f(paused);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The interval ends now, the next one starts.
+    pub fn sample(&self, now: SystemTime, sinks: &[SinkStats]) -> HealthSample {
+        let dropped = sinks.iter().map(|s| s.dropped).sum::<u64>();
+        let failed = sinks.iter().map(|s| s.failed).sum::<u64>();
+        let mut last = self.last.lock();
+        let (since, last_dropped, last_failed) = *last;
+        *last = (now, dropped, failed);
+        drop(last);
+
+        HealthSample {
+            since,
+            until: now,
+            events: self.events.swap(0, Ordering::Relaxed),
+            buffered_max: self.buffered_max.swap(0, Ordering::Relaxed),
+            lag_max_us: self.lag_max_us.swap(0, Ordering::Relaxed),
+            unordered: self.unordered.swap(0, Ordering::Relaxed),
+            decoder_errors: self.decoder_errors.swap(0, Ordering::Relaxed),
+            stalls: self.stalls.swap(0, Ordering::Relaxed),
+            // the sinks are replaced on reconfiguration, their counters start again
+            sink_dropped: dropped.saturating_sub(last_dropped),
+            sink_failed: failed.saturating_sub(last_failed),
+            ring_buffer_skipped: self.ring_buffer_skipped.swap(0, Ordering::Relaxed),
+        }
+    }
+}
+
+/// The health of the recorder over the time window, from the samples stored with the capture.
+#[derive(Serialize)]
+pub struct HealthReport {
+    pub since: SystemTime,
+    pub until: SystemTime,
+    pub events: u64,
+    pub buffered_max: u64,
+    pub lag_max_us: u64,
+    pub unordered: u64,
+    pub decoder_errors: u64,
+    pub stalls: u64,
+    pub sink_dropped: u64,
+    pub sink_failed: u64,
+    pub ring_buffer_skipped: u64,
+    /// the recorder did not run, no samples cover these intervals
+    pub gaps: Vec<(SystemTime, SystemTime)>,
+    /// the recorder ran the whole window, lost no events and the sinks dropped nothing
+    pub complete: bool,
+    pub samples: Vec<HealthSample>,
+}
+
+impl HealthReport {
+    /// The sampler wakes up a bit late, do not count it as a gap.
+    const TOLERANCE: Duration = Duration::from_secs(1);
+
+    pub fn new(since: SystemTime, until: SystemTime, samples: Vec<HealthSample>) -> Self {
+        let mut report = HealthReport {
+            since,
+            until,
+            events: 0,
+            buffered_max: 0,
+            lag_max_us: 0,
+            unordered: 0,
+            decoder_errors: 0,
+            stalls: 0,
+            sink_dropped: 0,
+            sink_failed: 0,
+            ring_buffer_skipped: 0,
+            gaps: vec![],
+            complete: false,
+            samples: vec![],
+        };
+        let mut covered = since;
+        for sample in &samples {
+            if sample.since > covered + Self::TOLERANCE {
+                report.gaps.push((covered, sample.since));
+            }
+            covered = covered.max(sample.until);
+            report.events += sample.events;
+            report.buffered_max = report.buffered_max.max(sample.buffered_max);
+            report.lag_max_us = report.lag_max_us.max(sample.lag_max_us);
+            report.unordered += sample.unordered;
+            report.decoder_errors += sample.decoder_errors;
+            report.stalls += sample.stalls;
+            report.sink_dropped += sample.sink_dropped;
+            report.sink_failed += sample.sink_failed;
+            report.ring_buffer_skipped += sample.ring_buffer_skipped;
+        }
+        // the last sample may be still to come
+        if until > covered + Self::TOLERANCE && until < SystemTime::now() {
+            report.gaps.push((covered, until));
+        }
+        report.complete =
+            report.gaps.is_empty() && report.sink_dropped == 0 && report.ring_buffer_skipped == 0;
+        report.samples = samples;
+        report
+    }
+}
+
+/// Stores the health sample every `HEALTH_INTERVAL` seconds, the default is 10.
+pub fn spawn(db: DbCore, terminating: Arc<AtomicBool>) -> thread::JoinHandle<()> {
+    let interval = env::var("HEALTH_INTERVAL")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(10);
+    let interval = Duration::from_secs(interval).max(Duration::from_secs(1));
+
+    thread::spawn(move || {
+        let mut next = SystemTime::now() + interval;
+        while !terminating.load(Ordering::SeqCst) {
+            let now = SystemTime::now();
+            if now < next {
+                thread::sleep(Duration::from_millis(100));
+                continue;
+            }
+            next += interval;
+
+            let sample = db.health().sample(now, &db.sinks().stats());
+            if let Err(err) = db.put_health(&sample) {
+                log::error!("cannot store the health sample: {err}");
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+#[test]
+fn health_sample() {
+    let health = Health::default();
+    health.on_event(100, Duration::from_millis(2));
+    health.on_event(50, Duration::from_millis(5));
+    health.on_decoder_error();
+    health.on_gap(0x1000);
+    let sinks = [SinkStats {
+        name: "null".to_owned(),
+        queued: 0,
+        sent: 0,
+        dropped: 3,
+        failed: 0,
+    }];
+    let sample = health.sample(SystemTime::now(), &sinks);
+    assert_eq!(
+        (sample.events, sample.buffered_max, sample.lag_max_us),
+        (2, 100, 5_000)
+    );
+    assert_eq!((sample.decoder_errors, sample.sink_dropped), (1, 3));
+    assert_eq!(sample.ring_buffer_skipped, 0x1000);
+
+    let next = health.sample(SystemTime::now(), &sinks);
+    assert_eq!((next.events, next.sink_dropped), (0, 0));
+    assert_eq!(next.ring_buffer_skipped, 0);
+    assert_eq!(next.since, sample.until);
+
+    let t = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+    let sample = |since, until, dropped| HealthSample {
+        since: t(since),
+        until: t(until),
+        sink_dropped: dropped,
+        ..next.clone()
+    };
+    let report = HealthReport::new(
+        t(100),
+        t(130),
+        vec![
+            sample(100, 110, 0),
+            sample(110, 120, 0),
+            sample(120, 130, 0),
+        ],
+    );
+    assert!(report.complete);
+    let report = HealthReport::new(
+        t(100),
+        t(130),
+        vec![sample(100, 110, 2), sample(120, 130, 0)],
+    );
+    assert_eq!(report.gaps, [(t(110), t(120))]);
+    assert_eq!(report.sink_dropped, 2);
+    assert!(!report.complete);
+
+    let status = |secs, bytes_consumed| RingBufferStatus {
+        time: t(secs),
+        fill_percent: 10,
+        bytes_consumed,
+        events_consumed: bytes_consumed / 100,
+        parse_errors: 0,
+        parse_failures: vec![ParseFailure {
+            offset: bytes_consumed,
+            length: 16,
+            error: "ErrorSliceTooShort".to_owned(),
+        }],
+        largest_batch: 64,
+        bytes_skipped: 0,
+        paused: false,
+        lag_ns: 0,
+        bytes_per_second: 0.0,
+        events_per_second: 0.0,
+    };
+    assert!(health.ring_buffer().is_none());
+    assert!(!health.pause(true));
+    let paused = Arc::new(AtomicBool::new(false));
+    health.set_pause({
+        let paused = paused.clone();
+        move |v| paused.store(v, Ordering::SeqCst)
+    });
+    assert!(health.pause(true));
+    assert!(paused.load(Ordering::SeqCst));
+    health.set_ring_buffer(status(100, 1_000));
+    health.set_ring_buffer(status(105, 51_000));
+    let ring_buffer = health.ring_buffer().unwrap();
+    assert_eq!(ring_buffer.bytes_per_second, 10_000.0);
+    assert_eq!(ring_buffer.events_per_second, 100.0);
+    let offsets = ring_buffer.parse_failures.iter().map(|f| f.offset);
+    assert_eq!(offsets.collect::<Vec<_>>(), [1_000, 51_000]);
+}
@@ -0,0 +1,261 @@
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    net::IpAddr,
+    sync::{Arc, Weak},
+    thread,
+    time::Duration,
+};
+
+use parking_lot::Mutex;
+use radiation::{Absorb, Emit};
+use serde::Serialize;
+
+/// The pod of the peer address or of the debuggee process.
+#[derive(Default, Clone, Debug, PartialEq, Eq, Absorb, Emit, Serialize)]
+pub struct PodMeta {
+    pub namespace: String,
+    pub name: String,
+    /// `key=value`
+    pub labels: Vec<String>,
+}
+
+impl PodMeta {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_empty()
+    }
+
+    /// `namespace/name`, the way `kubectl` refers to the pod.
+    pub fn reference(&self) -> String {
+        format!("{}/{}", self.namespace, self.name)
+    }
+}
+
+#[derive(Default)]
+struct Pods {
+    by_ip: BTreeMap<IpAddr, PodMeta>,
+    by_uid: BTreeMap<String, PodMeta>,
+}
+
+/// Pods of the cluster, the list is refreshed periodically from the API server.
+#[derive(Default)]
+pub struct KubeMetadata {
+    pods: Arc<Mutex<Pods>>,
+    // pid -> pod uid, the cgroup of the process does not change
+    uids: Mutex<BTreeMap<u32, Option<String>>>,
+}
+
+impl KubeMetadata {
+    const SERVICE_ACCOUNT: &'static str = "/var/run/secrets/kubernetes.io/serviceaccount";
+    const REFRESH: Duration = Duration::from_secs(30);
+
+    /// Enabled by `K8S_METADATA=1`.
+    pub fn from_env() -> Self {
+        if env::var("K8S_METADATA").as_deref() == Ok("1") {
+            Self::in_cluster()
+        } else {
+            KubeMetadata::default()
+        }
+    }
+
+    /// The service account of the debugger must be allowed to list pods.
+    pub fn in_cluster() -> Self {
+        let this = KubeMetadata::default();
+        let (host, port) = match (
+            env::var("KUBERNETES_SERVICE_HOST"),
+            env::var("KUBERNETES_SERVICE_PORT"),
+        ) {
+            (Ok(host), Ok(port)) => (host, port),
+            _ => {
+                log::error!(
+                    "kubernetes metadata is enabled, but the debugger is not in the cluster"
+                );
+                return this;
+            }
+        };
+        let client = match Self::client() {
+            Ok(v) => v,
+            Err(err) => {
+                log::error!("cannot create kubernetes client: {err}");
+                return this;
+            }
+        };
+        let host = if host.contains(':') {
+            format!("[{host}]")
+        } else {
+            host
+        };
+        let url = format!("https://{host}:{port}/api/v1/pods");
+        let pods = Arc::downgrade(&this.pods);
+        thread::Builder::new()
+            .name("k8s-metadata".to_owned())
+            .spawn(move || Self::refresh(client, url, pods))
+            .expect("cannot spawn thread");
+        this
+    }
+
+    fn client() -> Result<reqwest::blocking::Client, Box<dyn std::error::Error>> {
+        let ca = fs::read(format!("{}/ca.crt", Self::SERVICE_ACCOUNT))?;
+        let token = fs::read_to_string(format!("{}/token", Self::SERVICE_ACCOUNT))?;
+        let mut headers = reqwest::header::HeaderMap::new();
+        let mut auth = reqwest::header::HeaderValue::from_str(&format!("Bearer {}", token.trim()))?;
+        auth.set_sensitive(true);
+        headers.insert(reqwest::header::AUTHORIZATION, auth);
+        let client = reqwest::blocking::Client::builder()
+            .add_root_certificate(reqwest::Certificate::from_pem(&ca)?)
+            .default_headers(headers)
+            .build()?;
+        Ok(client)
+    }
+
+    // exits when the metadata is dropped
+    fn refresh(client: reqwest::blocking::Client, url: String, pods: Weak<Mutex<Pods>>) {
+        loop {
+            let list = client
+                .get(&url)
+                .send()
+                .and_then(|r| r.error_for_status())
+                .and_then(|r| r.json::<serde_json::Value>());
+            let pods = match pods.upgrade() {
+                Some(v) => v,
+                None => break,
+            };
+            match list {
+                Ok(list) => *pods.lock() = parse_pods(&list),
+                Err(err) => log::error!("cannot list kubernetes pods: {err}"),
+            }
+            drop(pods);
+            thread::sleep(Self::REFRESH);
+        }
+    }
+
+    pub fn pod_of_ip(&self, ip: IpAddr) -> Option<PodMeta> {
+        self.pods.lock().by_ip.get(&ip).cloned()
+    }
+
+    /// The pod of the process, found by the pod uid in its cgroup.
+    pub fn pod_of_pid(&self, pid: u32) -> Option<PodMeta> {
+        let uid = self
+            .uids
+            .lock()
+            .entry(pid)
+            .or_insert_with(|| {
+                let cgroup = fs::read_to_string(format!("/proc/{pid}/cgroup")).ok()?;
+                pod_uid(&cgroup)
+            })
+            .clone()?;
+        self.pods.lock().by_uid.get(&uid).cloned()
+    }
+
+    /// `namespace/name` of the pods for the aggregator, the address of the node
+    /// is mapped to the pod of the process, it might be in the host network.
+    pub fn references<I>(&self, pid: u32, node: IpAddr, peers: I) -> BTreeMap<IpAddr, String>
+    where
+        I: IntoIterator<Item = IpAddr>,
+    {
+        let mut references = peers
+            .into_iter()
+            .filter_map(|ip| Some((ip, self.pod_of_ip(ip)?.reference())))
+            .collect::<BTreeMap<_, _>>();
+        if let Some(pod) = self.pod_of_pid(pid) {
+            references.insert(node, pod.reference());
+        }
+        references
+    }
+}
+
+/// The cgroup path contains `pod<uid>`, the cgroupfs driver keeps the dashes,
+/// the systemd driver replaces them by underscores.
+fn pod_uid(cgroup: &str) -> Option<String> {
+    let candidates = cgroup.lines().flat_map(|line| line.split("pod").skip(1));
+    candidates.find_map(|rest| {
+        let uid = rest
+            .split(|c: char| !(c.is_ascii_hexdigit() || c == '-' || c == '_'))
+            .next()?;
+        (uid.len() == 36).then(|| uid.replace('_', "-"))
+    })
+}
+
+/// The response of `GET /api/v1/pods`.
+fn parse_pods(list: &serde_json::Value) -> Pods {
+    let mut pods = Pods::default();
+    let items = list["items"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    for item in items {
+        let metadata = &item["metadata"];
+        let meta = PodMeta {
+            namespace: metadata["namespace"]
+                .as_str()
+                .unwrap_or_default()
+                .to_owned(),
+            name: metadata["name"].as_str().unwrap_or_default().to_owned(),
+            labels: metadata["labels"]
+                .as_object()
+                .map(|labels| {
+                    labels
+                        .iter()
+                        .map(|(k, v)| format!("{k}={}", v.as_str().unwrap_or_default()))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        };
+        // pods in the host network share the address of the node
+        if !item["spec"]["hostNetwork"].as_bool().unwrap_or_default() {
+            let ips = item["status"]["podIPs"]
+                .as_array()
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            for ip in ips.iter().filter_map(|ip| ip["ip"].as_str()?.parse().ok()) {
+                pods.by_ip.insert(ip, meta.clone());
+            }
+        }
+        if let Some(uid) = metadata["uid"].as_str() {
+            pods.by_uid.insert(uid.to_owned(), meta);
+        }
+    }
+    pods
+}
+
+#[cfg(test)]
+#[test]
+fn kube_metadata() {
+    let cgroup = "0::/kubepods.slice/kubepods-besteffort.slice/kubepods-besteffort-pod3f4c5a2e_1b2c_4d5e_8f90_a1b2c3d4e5f6.slice/cri-containerd-0123.scope\n";
+    assert_eq!(
+        pod_uid(cgroup).as_deref(),
+        Some("3f4c5a2e-1b2c-4d5e-8f90-a1b2c3d4e5f6")
+    );
+    let cgroup = "12:memory:/kubepods/burstable/pod3f4c5a2e-1b2c-4d5e-8f90-a1b2c3d4e5f6/0123\n";
+    assert_eq!(
+        pod_uid(cgroup).as_deref(),
+        Some("3f4c5a2e-1b2c-4d5e-8f90-a1b2c3d4e5f6")
+    );
+    assert_eq!(pod_uid("0::/user.slice\n"), None);
+
+    let list = serde_json::json!({
+        "items": [
+            {
+                "metadata": {
+                    "name": "seed-1",
+                    "namespace": "testnet",
+                    "uid": "3f4c5a2e-1b2c-4d5e-8f90-a1b2c3d4e5f6",
+                    "labels": { "app": "seed" },
+                },
+                "spec": {},
+                "status": { "podIPs": [{ "ip": "10.1.0.5" }] },
+            },
+            {
+                "metadata": { "name": "debugger", "namespace": "testnet", "uid": "1" },
+                "spec": { "hostNetwork": true },
+                "status": { "podIPs": [{ "ip": "192.168.0.2" }] },
+            },
+        ],
+    });
+    let pods = parse_pods(&list);
+    let seed = &pods.by_ip[&"10.1.0.5".parse().unwrap()];
+    assert_eq!(seed.reference(), "testnet/seed-1");
+    assert_eq!(seed.labels, ["app=seed"]);
+    assert_eq!(pods.by_ip.len(), 1);
+    assert_eq!(pods.by_uid.len(), 2);
+}
@@ -1,6 +1,6 @@
 /// Contains header for kernel events.
 mod event;
-pub use self::event::{EventMetadata, ConnectionInfo, DirectedId};
+pub use self::event::{EventMetadata, ConnectionInfo, DirectedId, canonical_addr, canonical_ip};
 
 /// Represents chunk of raw data flown in TCP connection.
 mod chunk;
@@ -8,7 +8,19 @@ pub use self::chunk::{ChunkHeader, EncryptionStatus, ChunkParser};
 
 /// State machine that manages debuggee processes and their TCP connections.
 mod recorder;
-pub use self::recorder::P2pRecorder;
+pub use self::recorder::{P2pRecorder, Aggregator};
+
+/// Links the connections between two tracked processes on the same host.
+mod local_pair;
+
+/// The negotiated parameters of the tcp sockets, read from the tracked processes.
+pub mod tcp_info;
+
+/// Names of the peer addresses from the hosts mapping or the reverse lookup.
+pub mod peer_names;
+
+/// Pods of the cluster, the debugger might run as a DaemonSet.
+pub mod kube;
 
 /// State machine that manages snark worker processes.
 mod snark_worker;
@@ -25,6 +37,21 @@ pub use self::connection::yamux;
 mod decode;
 pub use self::decode::{meshsub, meshsub_stats};
 
+/// Encryption of the bundles and the forwarded events to the key of the analysis team.
+pub mod seal;
+
+/// Sends captured connections and messages to the remote instance that stores them.
+pub mod forward;
+
+/// Outputs of the recorder: database, json lines file, remote instance.
+pub mod sink;
+
+/// Decrypted messages of each connection in rotating files.
+pub mod flows;
+
+/// The log filter which can be changed at runtime via the HTTP interface.
+pub mod log_filter;
+
 /// Helps encode/decode data for database.
 pub mod custom_coding;
 
@@ -32,12 +59,25 @@ pub mod custom_coding;
 pub mod database;
 
 /// HTTP or HTTPS server. The interface to the whole debugger.
+#[cfg(feature = "server")]
 pub mod server;
 
+/// Tokens of the HTTP interface, the lower privileged ones see the redacted payloads.
+#[cfg(feature = "server")]
+pub mod access;
+
+/// Keyed pseudonyms of the addresses and the peer ids for the public demo of the debugger.
+pub mod pseudonym;
+
+/// gRPC interface, the schema is `proto/debugger.proto`.
+#[cfg(feature = "server")]
+pub mod grpc;
+
 /// Obsolete. Attempt to store all strace log in database.
 pub mod strace;
 
 /// Obsolete. Pausing the node if ring buffer between kernel and userspace is about to overflow.
+#[cfg(feature = "bpf")]
 pub mod ptrace;
 
 /// Observers blocks, snarks and transactions and store metadata about it.
@@ -48,6 +88,50 @@ mod stats;
 #[cfg(test)]
 mod stats_test;
 
+/// Reads the structured log of the node and stores it next to the messages.
+pub mod node_log;
+
+/// Polls graphql endpoint of the node and stores snapshots of its state.
+pub mod node_status;
+
+/// Time beacons exchanged with the debuggers of other hosts to align their captures.
+pub mod beacon;
+
+/// Query contract of Grafana JSON datasource, time series of bandwidth, message rate and latency.
+pub mod grafana;
+
+/// Block propagation objectives and the reports of their violations, shared with the aggregator.
+pub mod slo;
+
+/// The capture without the database on the disk, the latest messages in memory.
+pub mod memory;
+
+/// Decrypts and decodes again the sampled connections of the capture, compares the messages.
+pub mod verify;
+
+/// Drops, fill of the ring buffer and decoder errors of the recorder itself, stored with the capture.
+pub mod health;
+
+/// Sampling, port filter and retention of the capture, the aggregator pushes them to the fleet.
+pub mod policy;
+
+/// The protocol names and the gossip topics of the networks, selected by the chain id.
+pub mod profile;
+
+/// Selected connections in a single compressed file, to attach to a bug report
+/// and import in another capture.
+pub mod bundle;
+
+/// The diagnostic archive for the bug against the debugger itself.
+#[cfg(feature = "report")]
+pub mod report;
+
+/// Large exports as background jobs, the workers write the segments in parallel.
+pub mod export;
+
+/// Synthetic or recorded load through the userspace pipeline, to size the hardware.
+pub mod bench;
+
 /// Decodes capnp encoded IPC between mina deamon and libp2p_helper.
 pub mod libp2p_helper;
 
@@ -56,4 +140,9 @@ pub mod libp2p_ipc_capnp {
     include!(concat!(env!("OUT_DIR"), "/libp2p_ipc_capnp.rs"));
 }
 
+/// The firewall of the kernel module, controlled by the server.
+/// Without `bpf` nothing creates it, the firewall routes are `404`.
 pub mod application;
+
+/// Embedding the recorder in other tools: builder, input events, decoded messages.
+pub mod api;
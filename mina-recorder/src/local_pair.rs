@@ -0,0 +1,127 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+
+use super::{database::ConnectionId, event::canonical_addr};
+
+/// Local address of the tcp socket `fd` of the process,
+/// found by the inode of the socket in `/proc/{pid}/net/tcp` or `tcp6`.
+pub fn local_addr(pid: u32, fd: u32) -> Option<SocketAddr> {
+    let link = fs::read_link(format!("/proc/{pid}/fd/{fd}")).ok()?;
+    let inode = link
+        .to_str()?
+        .strip_prefix("socket:[")?
+        .strip_suffix(']')?
+        .parse::<u64>()
+        .ok()?;
+    ["tcp", "tcp6"].into_iter().find_map(|table| {
+        let s = fs::read_to_string(format!("/proc/{pid}/net/{table}")).ok()?;
+        find_local_addr(&s, inode)
+    })
+}
+
+fn find_local_addr(table: &str, inode: u64) -> Option<SocketAddr> {
+    // sl local_address rem_address st tx_queue:rx_queue tr:tm->when retrnsmt uid timeout inode
+    table.lines().skip(1).find_map(|line| {
+        let columns = line.split_whitespace().collect::<Vec<_>>();
+        if columns.get(9)?.parse::<u64>().ok()? != inode {
+            return None;
+        }
+        parse_addr(columns.get(1)?)
+    })
+}
+
+// the address is in the host byte order, the port is big endian
+fn parse_addr(s: &str) -> Option<SocketAddr> {
+    let (ip, port) = s.split_once(':')?;
+    let port = u16::from_str_radix(port, 16).ok()?;
+    let mut words = (0..ip.len()).step_by(8).map(|i| {
+        Some(
+            u32::from_str_radix(ip.get(i..(i + 8))?, 16)
+                .ok()?
+                .to_ne_bytes(),
+        )
+    });
+    let ip = match ip.len() {
+        8 => IpAddr::V4(Ipv4Addr::from(words.next()??)),
+        32 => {
+            let mut octets = [0; 16];
+            for chunk in octets.chunks_mut(4) {
+                chunk.copy_from_slice(&words.next()??);
+            }
+            IpAddr::V6(Ipv6Addr::from(octets))
+        }
+        _ => return None,
+    };
+    Some(SocketAddr::new(ip, port))
+}
+
+/// Two tracked processes on the same host talk to each other.
+/// Each side records its own connection, the local address of one side
+/// is the remote address of the other side.
+#[derive(Default)]
+pub struct LocalPairs {
+    // local and remote address -> the connection and the process
+    open: BTreeMap<(SocketAddr, SocketAddr), (ConnectionId, u32)>,
+}
+
+impl LocalPairs {
+    /// Returns the connection at the other end and its process, if it is tracked.
+    pub fn connected(
+        &mut self,
+        id: ConnectionId,
+        pid: u32,
+        local: SocketAddr,
+        remote: SocketAddr,
+    ) -> Option<(ConnectionId, u32)> {
+        let (local, remote) = (canonical_addr(local), canonical_addr(remote));
+        self.open.insert((local, remote), (id, pid));
+        self.open.get(&(remote, local)).cloned()
+    }
+
+    pub fn closed(&mut self, id: ConnectionId, local: SocketAddr, remote: SocketAddr) {
+        let key = (canonical_addr(local), canonical_addr(remote));
+        if self.open.get(&key).map_or(false, |(c, _)| *c == id) {
+            self.open.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn local_pair() {
+    let table = "  sl  local_address                         remote_address                        st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 0100007F:206E 0100007F:A2C4 01 00000000:00000000 00:00000000 00000000     0        0 418552 1 0000000000000000 20 4 30 10 -1
+   1: 00000000000000000000000001000000:A2C4 00000000000000000000000001000000:206E 01 00000000:00000000 00:00000000 00000000     0        0 418553 1 0000000000000000 20 4 30 10 -1
+";
+    let a = find_local_addr(table, 418552).unwrap();
+    let b = find_local_addr(table, 418553).unwrap();
+    assert_eq!(a, "127.0.0.1:8302".parse().unwrap());
+    assert_eq!(b, "[::1]:41668".parse().unwrap());
+    assert_eq!(find_local_addr(table, 1), None);
+
+    let listener = "127.0.0.1:8302".parse().unwrap();
+    let ephemeral = "127.0.0.1:41668".parse().unwrap();
+    let mut pairs = LocalPairs::default();
+    assert_eq!(
+        pairs.connected(ConnectionId(0), 10, ephemeral, listener),
+        None
+    );
+    assert_eq!(
+        pairs.connected(ConnectionId(1), 20, listener, ephemeral),
+        Some((ConnectionId(0), 10))
+    );
+    // the listener is dual stack
+    let mapped = "[::ffff:127.0.0.1]:41668".parse().unwrap();
+    assert_eq!(
+        pairs.connected(ConnectionId(2), 20, listener, mapped),
+        Some((ConnectionId(0), 10))
+    );
+    pairs.closed(ConnectionId(0), ephemeral, listener);
+    assert_eq!(
+        pairs.connected(ConnectionId(3), 20, listener, ephemeral),
+        None
+    );
+}
@@ -0,0 +1,110 @@
+use std::env;
+
+use log::{Log, Metadata, Record, LevelFilter};
+use parking_lot::RwLock;
+
+/// The filter has the `RUST_LOG` syntax, like `info,mina_recorder::connection::noise=trace`,
+/// comma separated directives, each is a level, a module path or `module=level`.
+pub fn validate(spec: &str) -> Result<(), String> {
+    for directive in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let level = match directive.split_once('=') {
+            Some((module, level)) if !module.is_empty() => level,
+            Some(_) => return Err(format!("no module in `{directive}`")),
+            // a bare level or a module path, the latter means all levels
+            None => continue,
+        };
+        if level.parse::<LevelFilter>().is_err() {
+            return Err(format!("bad level in `{directive}`"));
+        }
+    }
+    Ok(())
+}
+
+struct Filtered {
+    spec: String,
+    logger: env_logger::Logger,
+}
+
+impl Filtered {
+    fn new(spec: &str) -> Self {
+        let logger = env_logger::Builder::new().parse_filters(spec).build();
+        Filtered {
+            spec: spec.to_owned(),
+            logger,
+        }
+    }
+}
+
+/// The `env_logger` whose filter can be replaced while the debugger runs,
+/// the state of the debugger survives getting more verbose logs.
+struct ReloadableLogger {
+    inner: RwLock<Option<Filtered>>,
+}
+
+static LOGGER: ReloadableLogger = ReloadableLogger {
+    inner: parking_lot::const_rwlock(None),
+};
+
+impl Log for ReloadableLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let inner = self.inner.read();
+        inner.as_ref().map_or(false, |f| f.logger.enabled(metadata))
+    }
+
+    fn log(&self, record: &Record) {
+        if let Some(f) = &*self.inner.read() {
+            f.logger.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(f) = &*self.inner.read() {
+            f.logger.flush();
+        }
+    }
+}
+
+/// Replaces `env_logger::init`, the initial filter is `RUST_LOG`, `error` if it is not set.
+pub fn init() {
+    let spec = env::var("RUST_LOG").unwrap_or_else(|_| "error".to_owned());
+    let filtered = Filtered::new(&spec);
+    log::set_max_level(filtered.logger.filter());
+    *LOGGER.inner.write() = Some(filtered);
+    if let Err(err) = log::set_logger(&LOGGER) {
+        eprintln!("cannot setup logging: {err}");
+    }
+}
+
+/// The effective filter, `None` if the logger is not initialized by `init`.
+pub fn current() -> Option<String> {
+    LOGGER.inner.read().as_ref().map(|f| f.spec.clone())
+}
+
+/// Applies the new filter immediately, the messages already filtered out are lost.
+pub fn set(spec: &str) -> Result<(), String> {
+    validate(spec)?;
+    {
+        let mut inner = LOGGER.inner.write();
+        if inner.is_none() {
+            return Err("the logger is not reloadable".to_owned());
+        }
+        let filtered = Filtered::new(spec);
+        log::set_max_level(filtered.logger.filter());
+        *inner = Some(filtered);
+    }
+    // the logger is locked while replacing
+    log::info!("log filter {spec}");
+    Ok(())
+}
+
+#[cfg(test)]
+#[test]
+fn validate_filter() {
+    assert!(validate("info").is_ok());
+    assert!(validate("warn, mina_recorder::connection::noise=trace").is_ok());
+    assert!(validate("mina_recorder::database").is_ok());
+    assert!(validate("").is_ok());
+    assert!(validate("mina_recorder=loud").is_err());
+    assert!(validate("=debug").is_err());
+    assert!(set("debug").is_err());
+}
@@ -0,0 +1,83 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::database::{DbCore, DbError, MessageId};
+
+/// Deletes the messages but the latest `limit`, returns how many are deleted.
+pub fn trim(db: &DbCore, limit: u64) -> Result<u64, DbError> {
+    let total = db.total::<{ DbCore::MESSAGES_CNT }>()?;
+    db.prune_messages_below(MessageId(total.saturating_sub(limit)))
+}
+
+/// Keeps the latest `DB_IN_MEMORY` messages of the capture which is only in memory,
+/// the older ones are deleted every second. Nothing to do if the capture is on the disk.
+pub fn spawn(db: DbCore, terminating: Arc<AtomicBool>) -> Option<thread::JoinHandle<()>> {
+    let limit = db.tuning().in_memory?;
+    log::info!("the capture is in memory, keep the latest {limit} messages");
+
+    Some(thread::spawn(move || {
+        while !terminating.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_secs(1));
+            match trim(&db, limit) {
+                Ok(0) => (),
+                Ok(n) => log::debug!("deleted {n} messages from memory"),
+                Err(err) => log::error!("cannot delete the old messages: {err}"),
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+#[test]
+fn trim_messages() {
+    use std::time::SystemTime;
+
+    use crate::{
+        database::{CaptureReader, DbFacade, DbTuning, StreamId, StreamKind},
+        event::ConnectionInfo,
+    };
+
+    let dir = temp_dir::TempDir::new().unwrap();
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_675_166_400);
+    {
+        let db = DbFacade::open(dir.path()).unwrap();
+        let info = ConnectionInfo {
+            addr: "1.2.3.4:8302".parse().unwrap(),
+            pid: 1,
+            fd: 10,
+        };
+        let group = db.add(info, true, "node".to_owned(), time).unwrap();
+        let stream = group.get(StreamId::Forward(1));
+        for _ in 0..3 {
+            stream
+                .add_at(true, time, StreamKind::Select, b"/coda/yamux/1.0.0\n")
+                .unwrap();
+        }
+        drop((stream, group));
+        assert_eq!(trim(&db.core(), 1).unwrap(), 2);
+        assert_eq!(trim(&db.core(), 1).unwrap(), 0);
+    }
+
+    let reader = CaptureReader::open(dir.path()).unwrap();
+    assert!(reader.message(MessageId(1)).is_err());
+    let decoded = reader.decoded(MessageId(2)).unwrap();
+    assert_eq!(decoded.message, serde_json::json!("/coda/yamux/1.0.0\n"));
+
+    // no database on the disk, only the directory of the export jobs
+    let path = dir.path().join("in_memory");
+    let tuning = DbTuning {
+        in_memory: Some(10),
+        ..DbTuning::default()
+    };
+    let db = DbCore::open_with_tuning(&path, tuning).unwrap();
+    db.set_total::<{ DbCore::MESSAGES_CNT }>(5).unwrap();
+    assert_eq!(db.total::<{ DbCore::MESSAGES_CNT }>().unwrap(), 5);
+    assert!(!path.join("rocksdb").exists());
+    assert!(!path.join("manifest.json").exists());
+}
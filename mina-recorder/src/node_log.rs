@@ -0,0 +1,201 @@
+use std::{
+    fs,
+    io::{self, BufRead},
+    os::unix::fs::MetadataExt,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+
+use crate::database::{DbCore, NodeLogLine};
+
+/// Parse one line of the structured (json) log of the mina node.
+/// The node writes timestamp like `2023-01-31 12:00:00.123456Z`.
+pub fn parse_line(line: &str) -> Option<NodeLogLine> {
+    let mut value = serde_json::from_str::<serde_json::Value>(line.trim()).ok()?;
+    let obj = value.as_object_mut()?;
+    let timestamp = obj.remove("timestamp")?;
+    let timestamp = OffsetDateTime::parse(&timestamp.as_str()?.replacen(' ', "T", 1), &Rfc3339)
+        .ok()?
+        .into();
+    let level = obj
+        .remove("level")
+        .and_then(|v| v.as_str().map(ToOwned::to_owned))
+        .unwrap_or_default();
+    let message = obj
+        .remove("message")
+        .and_then(|v| v.as_str().map(ToOwned::to_owned))
+        .unwrap_or_default();
+    let metadata = obj
+        .remove("metadata")
+        .map(|v| v.to_string())
+        .unwrap_or_default();
+
+    Some(NodeLogLine {
+        timestamp,
+        level,
+        message,
+        metadata,
+    })
+}
+
+/// Store each line, returns how many lines are stored.
+pub fn ingest(db: &DbCore, lines: &str) -> usize {
+    lines
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match parse_line(line) {
+            Some(v) => Some(v),
+            None => {
+                log::warn!("cannot parse node log line: {line}");
+                None
+            }
+        })
+        .filter(|line| match db.put_node_log(line) {
+            Ok(()) => true,
+            Err(err) => {
+                log::error!("cannot store node log line: {err}");
+                false
+            }
+        })
+        .count()
+}
+
+/// Follow the log file like `tail -F`, the file may be rotated or truncated.
+/// Storing is idempotent, so the file is read from the beginning.
+pub fn spawn_tail(
+    path: PathBuf,
+    db: DbCore,
+    terminating: Arc<AtomicBool>,
+) -> thread::JoinHandle<()> {
+    const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+    thread::spawn(move || {
+        let mut reader = None::<io::BufReader<fs::File>>;
+        let mut position = 0;
+        let mut line = String::new();
+        while !terminating.load(Ordering::SeqCst) {
+            let r = match &mut reader {
+                Some(r) => r,
+                None => match fs::File::open(&path) {
+                    Ok(file) => {
+                        log::info!("tail node log {}", path.display());
+                        position = 0;
+                        reader.get_or_insert(io::BufReader::new(file))
+                    }
+                    Err(err) => {
+                        log::debug!("cannot open node log {}: {err}", path.display());
+                        thread::sleep(POLL_INTERVAL);
+                        continue;
+                    }
+                },
+            };
+            match r.read_line(&mut line) {
+                Ok(0) => {
+                    // reopen the file if it was replaced or truncated,
+                    // keep the old one while nothing is at the path
+                    if let Ok(current) = fs::metadata(&path) {
+                        let replaced = r.get_ref().metadata().map_or(true, |m| {
+                            (m.dev(), m.ino()) != (current.dev(), current.ino())
+                        });
+                        if replaced || current.len() < position {
+                            reader = None;
+                            line.clear();
+                            continue;
+                        }
+                    }
+                    thread::sleep(POLL_INTERVAL);
+                }
+                Ok(n) => {
+                    position += n as u64;
+                    // the line is incomplete, wait for the rest of it
+                    if line.ends_with('\n') {
+                        ingest(&db, &line);
+                        line.clear();
+                    }
+                }
+                Err(err) => {
+                    log::error!("cannot read node log {}: {err}", path.display());
+                    reader = None;
+                    line.clear();
+                    thread::sleep(POLL_INTERVAL);
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+#[test]
+fn parse_node_log_line() {
+    use std::time::SystemTime;
+
+    let line = r#"{"timestamp":"2023-01-31 12:00:00.123456Z","level":"Info","source":{"module":"Mina_lib","location":"File \"src/lib/mina_lib/mina_lib.ml\", line 1, characters 1-2"},"message":"Best tip changed","metadata":{"height":42}}"#;
+    let v = parse_line(line).unwrap();
+    assert_eq!(v.level, "Info");
+    assert_eq!(v.message, "Best tip changed");
+    assert_eq!(v.metadata, r#"{"height":42}"#);
+    let d = v.timestamp.duration_since(SystemTime::UNIX_EPOCH).unwrap();
+    assert_eq!(d.as_secs(), 1675166400);
+    assert_eq!(d.subsec_micros(), 123456);
+
+    assert!(parse_line("not a json").is_none());
+}
+
+#[cfg(test)]
+#[test]
+fn tail_rotated() {
+    use std::{io::Write, time::SystemTime};
+
+    let dir = temp_dir::TempDir::new().unwrap();
+    let db = DbCore::open(dir.path().join("db")).unwrap();
+    let path = dir.path().join("mina.log");
+    let write = |message: &str| {
+        let line = format!(
+            r#"{{"timestamp":"2023-01-31 12:00:00.000000Z","level":"Info","message":"{message}"}}"#
+        );
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .unwrap();
+        writeln!(file, "{line}").unwrap();
+    };
+    let wait = |message: &str| {
+        (0..100).any(|_| {
+            let found = db
+                .fetch_node_log(SystemTime::UNIX_EPOCH)
+                .any(|line| line.message == message);
+            if !found {
+                thread::sleep(Duration::from_millis(50));
+            }
+            found
+        })
+    };
+
+    let terminating = Arc::new(AtomicBool::new(false));
+    let handle = spawn_tail(path.clone(), db.clone(), terminating.clone());
+    write("first");
+    assert!(wait("first"));
+
+    // the new file is already longer than the old one when the tail notices it
+    fs::rename(&path, dir.path().join("mina.log.1")).unwrap();
+    write("second, it is longer than the first line");
+    write("third");
+    assert!(wait("second, it is longer than the first line"));
+    assert!(wait("third"));
+
+    // the new file is shorter
+    fs::rename(&path, dir.path().join("mina.log.2")).unwrap();
+    write("4th");
+    assert!(wait("4th"));
+
+    terminating.store(true, Ordering::SeqCst);
+    handle.join().unwrap();
+}
@@ -0,0 +1,218 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use serde::Deserialize;
+
+use crate::database::{DbCore, NodeStatus, ReportedPeer};
+
+const QUERY: &str = r#"{"query":"query { daemonStatus { syncStatus consensusTimeNow { epoch slot globalSlot } } bestChain(maxLength: 1) { stateHash protocolState { consensusState { blockHeight slotSinceGenesis } } } }"}"#;
+
+const PEERS_QUERY: &str = r#"{"query":"query { getPeers { host libp2pPort peerId } }"}"#;
+
+#[derive(Deserialize)]
+struct Response {
+    data: ResponseData,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ResponseData {
+    daemon_status: DaemonStatus,
+    // null while the node is bootstrapping
+    best_chain: Option<Vec<Block>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DaemonStatus {
+    sync_status: String,
+    consensus_time_now: ConsensusTime,
+}
+
+// graphql of the node encodes numbers as strings
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ConsensusTime {
+    epoch: String,
+    slot: String,
+    global_slot: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Block {
+    state_hash: String,
+    protocol_state: ProtocolState,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProtocolState {
+    consensus_state: ConsensusState,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ConsensusState {
+    block_height: String,
+    slot_since_genesis: String,
+}
+
+#[derive(Deserialize)]
+struct PeersResponse {
+    data: PeersResponseData,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PeersResponseData {
+    get_peers: Vec<Peer>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Peer {
+    host: String,
+    libp2p_port: u16,
+    peer_id: String,
+}
+
+/// Parse the response of the graphql query of the peers of the node.
+pub fn parse_peers_response(s: &str) -> Result<Vec<ReportedPeer>, String> {
+    let response = serde_json::from_str::<PeersResponse>(s).map_err(|err| err.to_string())?;
+    let peers = response
+        .data
+        .get_peers
+        .into_iter()
+        .map(|peer| ReportedPeer {
+            peer_id: peer.peer_id,
+            host: peer.host,
+            libp2p_port: peer.libp2p_port,
+        })
+        .collect();
+    Ok(peers)
+}
+
+/// Parse the response of the graphql query of the node.
+pub fn parse_response(s: &str, timestamp: SystemTime) -> Result<NodeStatus, String> {
+    let response = serde_json::from_str::<Response>(s).map_err(|err| err.to_string())?;
+    let ResponseData {
+        daemon_status,
+        best_chain,
+    } = response.data;
+    let number = |s: &str| s.parse::<u32>().map_err(|err| format!("{s}: {err}"));
+    let best_tip = best_chain.and_then(|chain| chain.into_iter().last());
+    let (best_tip_hash, best_tip_height, best_tip_slot) = match best_tip {
+        Some(block) => {
+            let state = block.protocol_state.consensus_state;
+            (
+                block.state_hash,
+                number(&state.block_height)?,
+                number(&state.slot_since_genesis)?,
+            )
+        }
+        None => (String::new(), 0, 0),
+    };
+    let time = daemon_status.consensus_time_now;
+
+    Ok(NodeStatus {
+        timestamp,
+        sync_status: daemon_status.sync_status,
+        epoch: number(&time.epoch)?,
+        slot: number(&time.slot)?,
+        global_slot: number(&time.global_slot)?,
+        best_tip_hash,
+        best_tip_height,
+        best_tip_slot,
+    })
+}
+
+/// Poll the graphql endpoint of the node, store the status when it changes,
+/// keep the latest peer list of the node to compare it with the wire.
+pub fn spawn_poll(
+    url: reqwest::Url,
+    interval: Duration,
+    db: DbCore,
+    terminating: Arc<AtomicBool>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let client = match reqwest::blocking::ClientBuilder::new()
+            .timeout(interval.max(Duration::from_secs(1)))
+            .build()
+        {
+            Ok(v) => v,
+            Err(err) => {
+                log::error!("cannot create graphql client: {err}");
+                return;
+            }
+        };
+        let mut last = None::<NodeStatus>;
+        while !terminating.load(Ordering::SeqCst) {
+            let response = client
+                .post(url.clone())
+                .body(QUERY)
+                .header("content-type", "application/json")
+                .send()
+                .and_then(|r| r.text());
+            let status = match response {
+                Ok(s) => parse_response(&s, SystemTime::now()),
+                Err(err) => Err(err.to_string()),
+            };
+            match status {
+                Ok(status) => {
+                    if last.as_ref().map(|l| !l.same(&status)).unwrap_or(true) {
+                        if let Err(err) = db.put_node_status(&status) {
+                            log::error!("cannot store node status: {err}");
+                        }
+                        last = Some(status);
+                    }
+                }
+                Err(err) => log::debug!("cannot poll node graphql {url}: {err}"),
+            }
+            let response = client
+                .post(url.clone())
+                .body(PEERS_QUERY)
+                .header("content-type", "application/json")
+                .send()
+                .and_then(|r| r.text());
+            let peers = match response {
+                Ok(s) => parse_peers_response(&s),
+                Err(err) => Err(err.to_string()),
+            };
+            match peers {
+                Ok(peers) => db.reported_peers().update(SystemTime::now(), peers),
+                Err(err) => log::debug!("cannot poll node peers {url}: {err}"),
+            }
+            thread::sleep(interval);
+        }
+    })
+}
+
+#[cfg(test)]
+#[test]
+fn parse_graphql_response() {
+    let s = r#"{"data":{"daemonStatus":{"syncStatus":"CATCHUP","consensusTimeNow":{"epoch":"2","slot":"15","globalSlot":"14295"}},"bestChain":[{"stateHash":"3NKeMoncuHab5ScarV5ViyF16cJPT4taWNSaTLS64Dp67wuXigPZ","protocolState":{"consensusState":{"blockHeight":"4010","slotSinceGenesis":"14290"}}}]}}"#;
+    let v = parse_response(s, SystemTime::UNIX_EPOCH).unwrap();
+    assert_eq!(v.sync_status, "CATCHUP");
+    assert_eq!((v.epoch, v.slot, v.global_slot), (2, 15, 14295));
+    assert_eq!(v.best_tip_height, 4010);
+    assert_eq!(v.best_tip_slot, 14290);
+
+    let s = r#"{"data":{"daemonStatus":{"syncStatus":"BOOTSTRAP","consensusTimeNow":{"epoch":"0","slot":"1","globalSlot":"1"}},"bestChain":null}}"#;
+    let v = parse_response(s, SystemTime::UNIX_EPOCH).unwrap();
+    assert_eq!(v.best_tip_hash, "");
+
+    let s = r#"{"data":{"getPeers":[{"host":"1.2.3.4","libp2pPort":8302,"peerId":"12D3KooWKG1ZakXRCR3ZGjrPsGJXEiu8Yv9xB8oWN8uH7hCBAfDS"}]}}"#;
+    let peers = parse_peers_response(s).unwrap();
+    assert_eq!(peers.len(), 1);
+    assert_eq!(
+        (peers[0].host.as_str(), peers[0].libp2p_port),
+        ("1.2.3.4", 8302)
+    );
+}
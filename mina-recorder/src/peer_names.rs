@@ -0,0 +1,127 @@
+use std::{collections::BTreeMap, env, fs, net::IpAddr, sync::mpsc, thread};
+
+use parking_lot::Mutex;
+
+use super::database::{ConnectionId, DbCore};
+
+/// Where the names of the peer addresses come from.
+#[derive(Debug, Clone, Default)]
+pub struct PeerNamesConfig {
+    /// the address to the name, like `/etc/hosts`
+    pub hosts: BTreeMap<IpAddr, String>,
+    /// resolve the addresses which are not in `hosts`
+    pub reverse_dns: bool,
+}
+
+impl PeerNamesConfig {
+    /// `PEER_NAMES` is the path to the hosts file, `PEER_REVERSE_DNS=1` enables the reverse lookup.
+    pub fn from_env() -> Self {
+        let hosts = match env::var("PEER_NAMES") {
+            Ok(path) => match fs::read_to_string(&path) {
+                Ok(s) => parse_hosts(&s),
+                Err(err) => {
+                    log::error!("cannot read peer names {path}: {err}");
+                    BTreeMap::default()
+                }
+            },
+            Err(_) => BTreeMap::default(),
+        };
+        let reverse_dns = env::var("PEER_REVERSE_DNS").as_deref() == Ok("1");
+        if reverse_dns && cfg!(not(feature = "reverse-dns")) {
+            log::error!("built without the `reverse-dns` feature, the addresses are not resolved");
+        }
+        PeerNamesConfig { hosts, reverse_dns }
+    }
+}
+
+#[cfg(feature = "reverse-dns")]
+fn lookup_addr(ip: IpAddr) -> Option<String> {
+    dns_lookup::lookup_addr(&ip)
+        .ok()
+        .filter(|name| name.parse::<IpAddr>().is_err())
+}
+
+#[cfg(not(feature = "reverse-dns"))]
+fn lookup_addr(_: IpAddr) -> Option<String> {
+    None
+}
+
+/// The format of `/etc/hosts`, the address followed by the names, the first name is taken.
+pub fn parse_hosts(s: &str) -> BTreeMap<IpAddr, String> {
+    s.lines()
+        .filter_map(|line| {
+            let line = line.split('#').next()?;
+            let mut words = line.split_whitespace();
+            let ip = words.next()?.parse().ok()?;
+            Some((ip, words.next()?.to_owned()))
+        })
+        .collect()
+}
+
+/// Names the connections, the reverse lookup is done in a separate thread,
+/// the name is attached to the connection record once it is resolved.
+pub struct PeerNames {
+    hosts: BTreeMap<IpAddr, String>,
+    // the thread exits when the sender is dropped
+    resolver: Option<Mutex<mpsc::Sender<(ConnectionId, IpAddr)>>>,
+}
+
+impl PeerNames {
+    pub fn new(config: PeerNamesConfig, db: DbCore) -> Self {
+        let resolver = if config.reverse_dns && cfg!(feature = "reverse-dns") {
+            let (tx, rx) = mpsc::channel();
+            thread::Builder::new()
+                .name("reverse-dns".to_owned())
+                .spawn(move || Self::resolve(rx, db))
+                .expect("cannot spawn thread");
+            Some(Mutex::new(tx))
+        } else {
+            None
+        };
+        PeerNames {
+            hosts: config.hosts,
+            resolver,
+        }
+    }
+
+    /// The name from the hosts mapping, otherwise it is resolved later.
+    pub fn on_connect(&self, connection_id: ConnectionId, ip: IpAddr) -> Option<String> {
+        if let Some(name) = self.hosts.get(&ip) {
+            return Some(name.clone());
+        }
+        if let Some(tx) = &self.resolver {
+            tx.lock().send((connection_id, ip)).unwrap_or_default();
+        }
+        None
+    }
+
+    fn resolve(rx: mpsc::Receiver<(ConnectionId, IpAddr)>, db: DbCore) {
+        // the peers reconnect often, do not ask again, failures are remembered too
+        let mut cache = BTreeMap::<IpAddr, Option<String>>::new();
+        for (connection_id, ip) in rx {
+            let name = cache.entry(ip).or_insert_with(|| lookup_addr(ip));
+            if let Some(name) = name {
+                if let Err(err) = db.set_peer_name(connection_id, name.clone()) {
+                    log::error!("{connection_id}: cannot store peer name {name}: {err}");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn peer_names() {
+    let hosts = parse_hosts(
+        "# localnet
+10.0.0.1 seed-1 seed-1.local
+10.0.0.2\tblock-producer-1 # comment
+fd00::3 snark-worker
+invalid line
+",
+    );
+    assert_eq!(hosts.len(), 3);
+    assert_eq!(hosts[&"10.0.0.1".parse().unwrap()], "seed-1");
+    assert_eq!(hosts[&"10.0.0.2".parse().unwrap()], "block-producer-1");
+    assert_eq!(hosts[&"fd00::3".parse().unwrap()], "snark-worker");
+}
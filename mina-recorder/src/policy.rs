@@ -0,0 +1,253 @@
+use std::{
+    collections::BTreeSet,
+    env,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use parking_lot::Mutex;
+use serde::{Serialize, Deserialize};
+
+use crate::{database::DbCore, event::ConnectionInfo};
+
+/// The capture policy the aggregator pushes to the debuggers of the fleet.
+/// The default records everything and keeps it forever.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CapturePolicy {
+    /// the aggregator increments it with every change
+    #[serde(default)]
+    pub version: u64,
+    /// the share of the new connections to record, from 0 to 1
+    #[serde(default)]
+    pub sampling: Option<f64>,
+    /// record only the connections to these remote ports, or accepted on these local ports
+    #[serde(default)]
+    pub ports: Vec<u16>,
+    /// seconds, the connections closed earlier are deleted with their messages
+    #[serde(default)]
+    pub retention: Option<u64>,
+}
+
+impl CapturePolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(sampling) = self.sampling {
+            if !(0.0..=1.0).contains(&sampling) {
+                return Err(format!("sampling {sampling} must be from 0 to 1"));
+            }
+        }
+        if self.retention == Some(0) {
+            return Err("retention must be positive".to_owned());
+        }
+        Ok(())
+    }
+
+    /// Whether to record the new connection, `listen` are the ports the process listens on,
+    /// the incoming connection is accepted on one of them. The sampling decision depends
+    /// only on the connection, not on the moment.
+    pub fn records(
+        &self,
+        info: &ConnectionInfo,
+        incoming: bool,
+        listen: Option<&BTreeSet<u16>>,
+    ) -> bool {
+        use std::{
+            collections::hash_map::DefaultHasher,
+            hash::{Hash, Hasher},
+        };
+
+        if !self.ports.is_empty() {
+            let remote = self.ports.contains(&info.addr.port());
+            let local =
+                incoming && listen.map_or(false, |l| self.ports.iter().any(|p| l.contains(p)));
+            if !remote && !local {
+                return false;
+            }
+        }
+        match self.sampling {
+            None => true,
+            Some(sampling) => {
+                let mut hasher = DefaultHasher::new();
+                info.addr.hash(&mut hasher);
+                info.pid.hash(&mut hasher);
+                info.fd.hash(&mut hasher);
+                ((hasher.finish() % 10_000) as f64) < sampling * 10_000.0
+            }
+        }
+    }
+}
+
+/// The policy in effect and when it was applied.
+#[derive(Default)]
+pub struct ActivePolicy {
+    inner: Mutex<(CapturePolicy, Option<SystemTime>)>,
+}
+
+#[derive(Serialize)]
+pub struct PolicyStatus {
+    pub policy: CapturePolicy,
+    pub applied: Option<SystemTime>,
+}
+
+impl ActivePolicy {
+    pub fn get(&self) -> CapturePolicy {
+        self.inner.lock().0.clone()
+    }
+
+    pub fn set(&self, policy: CapturePolicy, time: SystemTime) {
+        log::info!("capture policy {policy:?}");
+        *self.inner.lock() = (policy, Some(time));
+    }
+
+    pub fn status(&self) -> PolicyStatus {
+        let (policy, applied) = self.inner.lock().clone();
+        PolicyStatus { policy, applied }
+    }
+}
+
+/// The debugger tells the aggregators where its control api is,
+/// `DEBUGGER_URL` is the url the aggregator reaches it at.
+pub struct Registration {
+    aggregators: Vec<reqwest::Url>,
+    name: String,
+    url: String,
+    // `FLEET_SECRET`, the aggregator pushes the token only to the debuggers which know it
+    secret: Option<String>,
+}
+
+/// The body of `POST /debuggers` of the aggregator.
+#[derive(Serialize, Deserialize)]
+pub struct RegisterRequest {
+    pub name: String,
+    pub url: String,
+    /// the version of the policy in effect
+    pub policy_version: u64,
+}
+
+impl Registration {
+    pub fn from_env() -> Option<Self> {
+        let url = env::var("DEBUGGER_URL").ok()?;
+        let aggregators = env::var("AGGREGATOR")
+            .ok()?
+            .split(',')
+            .filter_map(|s| s.trim().parse::<reqwest::Url>().ok())
+            .filter_map(|url| url.join("debuggers").ok())
+            .collect();
+        let name = env::var("DEBUGGER_NAME").unwrap_or("noname".to_owned());
+        Some(Registration {
+            aggregators,
+            name,
+            url,
+            secret: env::var("FLEET_SECRET").ok(),
+        })
+    }
+}
+
+/// Registers at the aggregators every minute, the aggregator pushes the policy in response,
+/// and deletes the records older than the retention of the policy.
+pub fn spawn(
+    registration: Option<Registration>,
+    db: DbCore,
+    terminating: Arc<AtomicBool>,
+) -> thread::JoinHandle<()> {
+    const INTERVAL: Duration = Duration::from_secs(60);
+
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .unwrap_or_default();
+    thread::spawn(move || {
+        let mut next = SystemTime::now();
+        while !terminating.load(Ordering::SeqCst) {
+            if SystemTime::now() < next {
+                thread::sleep(Duration::from_secs(1));
+                continue;
+            }
+            next += INTERVAL;
+
+            let policy = db.capture_policy().get();
+            if let Some(r) = &registration {
+                let body = RegisterRequest {
+                    name: r.name.clone(),
+                    url: r.url.clone(),
+                    policy_version: policy.version,
+                };
+                let body = serde_json::to_string(&body).unwrap_or_default();
+                for url in &r.aggregators {
+                    let mut request = client
+                        .post(url.clone())
+                        .header("Content-Type", "application/json")
+                        .body(body.clone());
+                    if let Some(secret) = &r.secret {
+                        request = request.bearer_auth(secret);
+                    }
+                    let result = request.send().and_then(|r| r.error_for_status());
+                    if let Err(err) = result {
+                        log::warn!("cannot register at {url}: {err}");
+                    }
+                }
+            }
+            if let Some(secs) = policy.retention {
+                let before = SystemTime::now() - Duration::from_secs(secs);
+                match db.prune_before(before) {
+                    Ok((0, 0)) => (),
+                    Ok((cns, msgs)) => {
+                        log::info!("retention deleted {cns} connections and {msgs} messages")
+                    }
+                    Err(err) => log::error!("retention: {err}"),
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+#[test]
+fn capture_policy() {
+    let info = |port, fd| ConnectionInfo {
+        addr: ([1, 2, 3, 4], port).into(),
+        pid: 1,
+        fd,
+    };
+
+    let all = CapturePolicy::default();
+    assert!(all.records(&info(8302, 10), false, None));
+
+    let ports = CapturePolicy {
+        ports: vec![8302],
+        ..CapturePolicy::default()
+    };
+    assert!(ports.records(&info(8302, 10), false, None));
+    assert!(!ports.records(&info(45000, 10), false, None));
+    let listen = [8302].into_iter().collect();
+    assert!(ports.records(&info(45000, 10), true, Some(&listen)));
+    assert!(!ports.records(&info(45000, 10), false, Some(&listen)));
+
+    let none = CapturePolicy {
+        sampling: Some(0.0),
+        ..CapturePolicy::default()
+    };
+    assert!(!none.records(&info(8302, 10), false, None));
+    let half = CapturePolicy {
+        sampling: Some(0.5),
+        ..CapturePolicy::default()
+    };
+    let recorded = (0..1000)
+        .filter(|fd| half.records(&info(8302, *fd), false, None))
+        .count();
+    assert!((350..650).contains(&recorded), "{recorded}");
+    assert_eq!(
+        half.records(&info(8302, 7), false, None),
+        half.records(&info(8302, 7), false, None)
+    );
+
+    assert!(CapturePolicy {
+        sampling: Some(1.5),
+        ..CapturePolicy::default()
+    }
+    .validate()
+    .is_err());
+}
@@ -0,0 +1,295 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env, fs,
+    sync::Arc,
+};
+
+use serde::{Serialize, Deserialize};
+
+/// The names the network uses, the hard forks change them. Each connection gets the profile
+/// of its chain id, so one debugger records the nodes of different networks at once.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NetworkProfile {
+    pub name: String,
+    /// the chain ids of the private network, the first one is used for the node
+    /// without a chain id whose alias begins with the name of the profile
+    #[serde(default)]
+    pub chain_ids: Vec<String>,
+    /// the protocol the peers agree on to the protocol the decoders know,
+    /// for example `"mina/rpcs/1.0.0": "coda/rpcs/0.0.1"`
+    #[serde(default)]
+    pub protocols: BTreeMap<String, String>,
+    /// the gossip topics, the subscriptions to other topics are reported, empty allows any
+    #[serde(default)]
+    pub topics: Vec<String>,
+    /// every connection of the network negotiates these protocols,
+    /// each item lists the alternatives, for example yamux or mplex
+    #[serde(default)]
+    pub expected: Vec<Vec<String>>,
+    /// the connection may negotiate these protocols, the others are unexpected
+    #[serde(default)]
+    pub optional: Vec<String>,
+}
+
+/// Which protocols of the profile the connection negotiated.
+#[derive(Debug, Serialize)]
+pub struct ProtocolChecklist {
+    pub profile: String,
+    /// as the peers agreed on them
+    pub negotiated: Vec<String>,
+    /// the expected protocols the connection did not negotiate, the alternatives joined by `|`
+    pub missing: Vec<String>,
+    /// neither expected nor optional
+    pub unexpected: Vec<String>,
+    pub compatible: bool,
+}
+
+impl NetworkProfile {
+    /// The name of the protocol the decoders know.
+    pub fn canonical<'a>(&'a self, protocol: &'a str) -> &'a str {
+        self.protocols
+            .get(protocol)
+            .map(String::as_str)
+            .unwrap_or(protocol)
+    }
+
+    pub fn knows_topic(&self, topic: &str) -> bool {
+        self.topics.is_empty() || self.topics.iter().any(|t| t == topic)
+    }
+
+    /// The `negotiated` are the agreed protocols of the streams of the connection,
+    /// the unknown profile expects nothing.
+    pub fn checklist<'a, I>(&self, negotiated: I) -> ProtocolChecklist
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let negotiated = negotiated
+            .into_iter()
+            .filter(|p| !p.is_empty())
+            .map(ToOwned::to_owned)
+            .collect::<BTreeSet<_>>();
+        let canonical = negotiated
+            .iter()
+            .map(|p| self.canonical(p))
+            .collect::<BTreeSet<_>>();
+        let missing = self
+            .expected
+            .iter()
+            .filter(|alternatives| !alternatives.iter().any(|p| canonical.contains(p.as_str())))
+            .map(|alternatives| alternatives.join("|"))
+            .collect::<Vec<_>>();
+        let unexpected = if self.expected.is_empty() && self.optional.is_empty() {
+            vec![]
+        } else {
+            negotiated
+                .iter()
+                .filter(|p| {
+                    let p = self.canonical(p);
+                    !self.expected.iter().flatten().any(|e| e == p)
+                        && !self.optional.iter().any(|o| o == p)
+                })
+                .cloned()
+                .collect()
+        };
+        ProtocolChecklist {
+            profile: self.name.clone(),
+            compatible: missing.is_empty() && unexpected.is_empty(),
+            negotiated: negotiated.into_iter().collect(),
+            missing,
+            unexpected,
+        }
+    }
+
+    fn builtin() -> Vec<Self> {
+        let strings = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let profile = |name: &str, chain_id: &str, topics: &[&str]| NetworkProfile {
+            name: name.to_owned(),
+            chain_ids: vec![chain_id.to_owned()],
+            protocols: BTreeMap::new(),
+            topics: strings(topics),
+            expected: vec![
+                strings(&["/noise"]),
+                strings(&["/coda/yamux/1.0.0", "/coda/mplex/1.0.0"]),
+                strings(&["/meshsub/1.1.0"]),
+                strings(&["coda/rpcs/0.0.1"]),
+                strings(&["/ipfs/id/1.0.0"]),
+                strings(&["/coda/kad/1.0.0"]),
+            ],
+            optional: strings(&[
+                "/ipfs/id/push/1.0.0",
+                "/p2p/id/delta/1.0.0",
+                "/mina/peer-exchange",
+                "/mina/bitswap-exchange",
+                "/mina/node-status",
+            ]),
+        };
+        const CONSENSUS: &str = "coda/consensus-messages/0.0.1";
+
+        vec![
+            profile(
+                "mainnet",
+                "/coda/0.0.1/5f704cc0c82e0ed70e873f0893d7e06f148524e3f0bdae2afb02e7819a0c24d1",
+                &[CONSENSUS],
+            ),
+            profile(
+                "devnet",
+                "/coda/0.0.1/b6ee40d336f4cc3f33c1cc04dee7618eb8e556664c2b2d82ad4676b512a82418",
+                &[CONSENSUS],
+            ),
+            profile(
+                "berkeley",
+                "/coda/0.0.1/fb30d090bb37e8aa354114d8c794b0f7072648a67bd1a08613684ac6f7c86028",
+                &[
+                    CONSENSUS,
+                    "mina/block/1.0.0",
+                    "mina/tx/1.0.0",
+                    "mina/snark-work/1.0.0",
+                ],
+            ),
+        ]
+    }
+}
+
+/// The profiles the debugger knows, the built in ones and the ones from `NETWORK_PROFILES`.
+pub struct Profiles {
+    profiles: Vec<Arc<NetworkProfile>>,
+    // `NETWORK_PROFILE`, every connection gets it regardless of the chain id
+    forced: Option<Arc<NetworkProfile>>,
+    // the chain id is unknown, accept any name
+    fallback: Arc<NetworkProfile>,
+}
+
+impl Profiles {
+    /// The `profiles` replace the built in ones of the same name.
+    pub fn new(profiles: Vec<NetworkProfile>, forced: Option<&str>) -> Self {
+        let mut all = NetworkProfile::builtin();
+        for profile in profiles {
+            all.retain(|p| p.name != profile.name);
+            all.push(profile);
+        }
+        let profiles = all.into_iter().map(Arc::new).collect::<Vec<_>>();
+        let forced = forced.and_then(|name| {
+            let profile = profiles.iter().find(|p| p.name == name).cloned();
+            if profile.is_none() {
+                log::error!("unknown network profile {name}, select by the chain id");
+            }
+            profile
+        });
+        let fallback = Arc::new(NetworkProfile {
+            name: "unknown".to_owned(),
+            ..NetworkProfile::default()
+        });
+        Profiles {
+            profiles,
+            forced,
+            fallback,
+        }
+    }
+
+    /// `NETWORK_PROFILES` is the path to the json array of the profiles,
+    /// `NETWORK_PROFILE` is the name of the profile to use for every connection.
+    pub fn from_env() -> Self {
+        let profiles = match env::var("NETWORK_PROFILES") {
+            Ok(path) => match fs::read_to_string(&path).map(|s| serde_json::from_str(&s)) {
+                Ok(Ok(v)) => v,
+                Ok(Err(err)) => {
+                    log::error!("bad network profiles {path}: {err}");
+                    vec![]
+                }
+                Err(err) => {
+                    log::error!("cannot read network profiles {path}: {err}");
+                    vec![]
+                }
+            },
+            Err(_) => vec![],
+        };
+        let forced = env::var("NETWORK_PROFILE").ok();
+        Profiles::new(profiles, forced.as_deref())
+    }
+
+    /// The profile of the connection with the chain id.
+    pub fn select(&self, chain_id: &str) -> Arc<NetworkProfile> {
+        if let Some(profile) = &self.forced {
+            return profile.clone();
+        }
+        self.profiles
+            .iter()
+            .find(|p| p.chain_ids.iter().any(|c| c == chain_id))
+            .unwrap_or(&self.fallback)
+            .clone()
+    }
+
+    pub fn fallback(&self) -> Arc<NetworkProfile> {
+        self.fallback.clone()
+    }
+
+    /// The chain id of the network the application named `alias` belongs to.
+    pub fn chain_id(&self, alias: &str) -> String {
+        let mut it = alias.split('-');
+        let network = it.next().expect("`split` must yield at least one");
+        self.profiles
+            .iter()
+            .find(|p| p.name == network)
+            .and_then(|p| p.chain_ids.first())
+            .map(String::as_str)
+            .unwrap_or(network)
+            .to_owned()
+    }
+
+    pub fn all(&self) -> Vec<NetworkProfile> {
+        self.profiles.iter().map(|p| (**p).clone()).collect()
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn profiles() {
+    let fork = NetworkProfile {
+        name: "fork".to_owned(),
+        chain_ids: vec!["/coda/0.0.1/fork".to_owned()],
+        protocols: [("mina/rpcs/1.0.0".to_owned(), "coda/rpcs/0.0.1".to_owned())]
+            .into_iter()
+            .collect(),
+        topics: vec!["mina/block/1.0.0".to_owned()],
+        expected: vec![
+            vec!["/noise".to_owned()],
+            vec![
+                "/coda/yamux/1.0.0".to_owned(),
+                "/coda/mplex/1.0.0".to_owned(),
+            ],
+            vec!["coda/rpcs/0.0.1".to_owned()],
+        ],
+        optional: vec!["/ipfs/id/1.0.0".to_owned()],
+    };
+    let profiles = Profiles::new(vec![fork], None);
+
+    let mainnet = profiles.chain_id("mainnet-seed-1");
+    assert_eq!(profiles.select(&mainnet).name, "mainnet");
+    assert_eq!(profiles.chain_id("fork-node"), "/coda/0.0.1/fork");
+    assert_eq!(profiles.chain_id("custom"), "custom");
+
+    let fork = profiles.select("/coda/0.0.1/fork");
+    assert_eq!(fork.canonical("mina/rpcs/1.0.0"), "coda/rpcs/0.0.1");
+    assert_eq!(fork.canonical("/meshsub/1.1.0"), "/meshsub/1.1.0");
+    assert!(fork.knows_topic("mina/block/1.0.0"));
+    assert!(!fork.knows_topic("coda/consensus-messages/0.0.1"));
+
+    let checklist = fork.checklist(["/noise", "/coda/mplex/1.0.0", "mina/rpcs/1.0.0", "/kad"]);
+    assert_eq!(checklist.missing, Vec::<String>::new());
+    assert_eq!(checklist.unexpected, ["/kad"]);
+    assert!(!checklist.compatible);
+    let checklist = fork.checklist(["/noise", "/ipfs/id/1.0.0", ""]);
+    assert_eq!(
+        checklist.missing,
+        ["/coda/yamux/1.0.0|/coda/mplex/1.0.0", "coda/rpcs/0.0.1"]
+    );
+    assert!(checklist.unexpected.is_empty());
+
+    let unknown = profiles.select("/coda/0.0.1/other");
+    assert_eq!(unknown.name, "unknown");
+    assert!(unknown.knows_topic("anything"));
+    assert!(unknown.checklist(["/kad"]).compatible);
+
+    let forced = Profiles::new(vec![], Some("devnet"));
+    assert_eq!(forced.select(&mainnet).name, "devnet");
+}
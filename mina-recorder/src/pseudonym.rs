@@ -0,0 +1,183 @@
+use std::{
+    env,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+};
+
+use libp2p_core::{identity, PeerId};
+use multiaddr::{Multiaddr, Protocol};
+use serde_json::Value;
+
+/// Replaces the addresses and the peer ids the api shows, so the public demo of the debugger
+/// shows the real dynamics of the network without the real peers. The same input gives
+/// the same pseudonym, so the connections of one peer are still seen together.
+pub trait Pseudonyms: Send + Sync {
+    fn ip(&self, ip: IpAddr) -> IpAddr;
+
+    fn peer_id(&self, peer_id: &PeerId) -> PeerId;
+}
+
+/// The pseudonyms of `PSEUDONYMIZE_KEY`, `None` if the variable is not set.
+pub fn from_env() -> Option<Arc<dyn Pseudonyms>> {
+    let key = env::var("PSEUDONYMIZE_KEY").ok()?;
+    if key.is_empty() {
+        log::error!("empty PSEUDONYMIZE_KEY, the addresses are not pseudonymized");
+        return None;
+    }
+    Some(Arc::new(Keyed::new(key.as_bytes())))
+}
+
+/// The keyed blake2b of the address or the peer id. Without the key the pseudonyms
+/// cannot be reversed, nor checked against a guess of the address.
+pub struct Keyed {
+    key: [u8; 32],
+}
+
+impl Keyed {
+    pub fn new(key: &[u8]) -> Self {
+        use blake2::digest::{Update, FixedOutput, typenum};
+
+        let key = blake2::Blake2b::<typenum::U32>::default()
+            .chain(key)
+            .finalize_fixed()
+            .into();
+        Keyed { key }
+    }
+
+    fn mac(&self, domain: &[u8], data: &[u8]) -> [u8; 32] {
+        use blake2::digest::{Mac, Update, FixedOutput, typenum};
+
+        blake2::Blake2bMac::<typenum::U32>::new_from_slice(&self.key)
+            .expect("the key is 32 bytes")
+            .chain(domain)
+            .chain(data)
+            .finalize_fixed()
+            .into()
+    }
+}
+
+impl Pseudonyms for Keyed {
+    /// The ipv4 address in `10.0.0.0/8`, the ipv6 in `fd00::/8`, the unique local ranges.
+    fn ip(&self, ip: IpAddr) -> IpAddr {
+        match ip {
+            IpAddr::V4(v4) => {
+                let h = self.mac(b"ip4", &v4.octets());
+                IpAddr::V4(Ipv4Addr::new(10, h[0], h[1], h[2]))
+            }
+            IpAddr::V6(v6) => {
+                let mut h = self.mac(b"ip6", &v6.octets());
+                h[0] = 0xfd;
+                let octets = <[u8; 16]>::try_from(&h[..16]).expect("the hash is 32 bytes");
+                IpAddr::V6(Ipv6Addr::from(octets))
+            }
+        }
+    }
+
+    /// The peer id of the ed25519 key derived from the original, the same format as the real.
+    fn peer_id(&self, peer_id: &PeerId) -> PeerId {
+        let secret = self.mac(b"peer_id", &peer_id.to_bytes());
+        let secret = identity::ed25519::SecretKey::from_bytes(secret)
+            .expect("any 32 bytes are the ed25519 secret key");
+        let pk = identity::ed25519::Keypair::from(secret).public();
+        PeerId::from_public_key(&identity::PublicKey::Ed25519(pk))
+    }
+}
+
+/// Replaces every address and peer id in the json, the strings and the keys of the objects.
+/// The port is kept, it tells the listening socket from the ephemeral one.
+pub fn apply(pseudonyms: &dyn Pseudonyms, value: &mut Value) {
+    match value {
+        Value::String(s) => {
+            if let Some(new) = replace(pseudonyms, s) {
+                *s = new;
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|v| apply(pseudonyms, v)),
+        Value::Object(map) => {
+            let old = std::mem::take(map);
+            for (key, mut v) in old {
+                apply(pseudonyms, &mut v);
+                let key = replace(pseudonyms, &key).unwrap_or(key);
+                map.insert(key, v);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn replace(pseudonyms: &dyn Pseudonyms, s: &str) -> Option<String> {
+    if let Ok(addr) = s.parse::<SocketAddr>() {
+        let addr = SocketAddr::new(pseudonyms.ip(addr.ip()), addr.port());
+        return Some(addr.to_string());
+    }
+    if let Ok(ip) = s.parse::<IpAddr>() {
+        return Some(pseudonyms.ip(ip).to_string());
+    }
+    if s.starts_with('/') {
+        let addr = s.parse::<Multiaddr>().ok()?;
+        let addr = addr
+            .iter()
+            .map(|protocol| match protocol {
+                Protocol::Ip4(v) => match pseudonyms.ip(IpAddr::V4(v)) {
+                    IpAddr::V4(v) => Protocol::Ip4(v),
+                    IpAddr::V6(v) => Protocol::Ip6(v),
+                },
+                Protocol::Ip6(v) => match pseudonyms.ip(IpAddr::V6(v)) {
+                    IpAddr::V4(v) => Protocol::Ip4(v),
+                    IpAddr::V6(v) => Protocol::Ip6(v),
+                },
+                Protocol::P2p(hash) => match PeerId::from_multihash(hash) {
+                    Ok(peer_id) => Protocol::P2p(pseudonyms.peer_id(&peer_id).into()),
+                    Err(hash) => Protocol::P2p(hash),
+                },
+                // the hostname might be the real one as well
+                Protocol::Dns(_) | Protocol::Dns4(_) | Protocol::Dns6(_) => {
+                    Protocol::Dns("redacted".into())
+                }
+                protocol => protocol,
+            })
+            .collect::<Multiaddr>();
+        return Some(addr.to_string());
+    }
+    // the base58 of the ed25519, the secp256k1 and the legacy sha256 peer ids,
+    // other base58 strings, like the hashes of the blocks, are not touched
+    let looks_like_peer_id = ["12D3KooW", "16Uiu2HA", "Qm"]
+        .iter()
+        .any(|prefix| s.starts_with(prefix));
+    if looks_like_peer_id {
+        let peer_id = s.parse::<PeerId>().ok()?;
+        return Some(pseudonyms.peer_id(&peer_id).to_base58());
+    }
+    None
+}
+
+#[cfg(test)]
+#[test]
+fn pseudonyms_are_stable() {
+    let keyed = Keyed::new(b"demo");
+    let peer_id = "12D3KooWKG1ZakXwZ5mDJsS3NyyX3mvyRd2rhc6kZMYNbCGjwSrB";
+    let mut value = serde_json::json!({
+        "1.2.3.4:8302": [peer_id, "1.2.3.4", "/ip4/1.2.3.4/tcp/8302/p2p/".to_owned() + peer_id],
+        "hash": "3NKeMoncuHab5ScarV5ViyF16cJPT4taWNSaTLS64Dp67wuXigPZ",
+        "size": 10,
+    });
+    apply(&keyed, &mut value);
+
+    let (addr, items) = value.as_object().unwrap().iter().next().unwrap();
+    let addr = addr.parse::<SocketAddr>().unwrap();
+    assert_eq!(addr.port(), 8302);
+    assert_eq!(addr.ip().to_string(), items[1]);
+    assert_ne!(items[1], "1.2.3.4");
+    let fake = items[0].as_str().unwrap();
+    assert!(fake.starts_with("12D3KooW"));
+    assert_ne!(fake, peer_id);
+    assert_eq!(items[2], format!("/ip4/{}/tcp/8302/p2p/{fake}", addr.ip()));
+    assert_eq!(
+        value["hash"],
+        "3NKeMoncuHab5ScarV5ViyF16cJPT4taWNSaTLS64Dp67wuXigPZ"
+    );
+
+    // another key, other pseudonyms
+    let other = Keyed::new(b"other");
+    assert_ne!(other.ip(addr.ip()), keyed.ip(addr.ip()));
+}
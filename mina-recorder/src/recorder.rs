@@ -1,8 +1,11 @@
 use std::{
-    collections::BTreeMap,
-    time::SystemTime,
+    collections::{BTreeMap, BTreeSet},
+    time::{SystemTime, Duration},
     net::{SocketAddr, IpAddr},
-    sync::{Arc, mpsc},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, mpsc,
+    },
     thread::{self, JoinHandle},
 };
 
@@ -12,7 +15,15 @@ use parking_lot::Mutex;
 use super::{
     event::{EventMetadata, ConnectionInfo, DirectedId},
     connection::{HandleData, pnet, multistream_select, noise, mux, mina_protocol},
-    database::{DbFacade, DbGroup},
+    database::{
+        DbFacade, DbGroup, DbError, ConnectionId, DecoderProgress, Pipelines, KeyInjection,
+        InjectionStatus,
+    },
+    chunk::EncryptionStatus,
+    local_pair::{self, LocalPairs},
+    tcp_info,
+    peer_names::{PeerNames, PeerNamesConfig},
+    kube::KubeMetadata,
     tester::Tester,
     stats::{Stats, StatsState},
 };
@@ -24,21 +35,126 @@ type Inner = multistream_select::State<mina_protocol::State>;
 
 pub struct P2pRecorder {
     tester: Option<Tester>,
-    cns: BTreeMap<ConnectionInfo, ThreadContext>,
+    workers: Vec<Worker>,
+    cns: BTreeMap<ConnectionInfo, (usize, ConnectionId)>,
     cns_main_thread: BTreeMap<ConnectionInfo, ConnectionContext>,
+    // pid -> session id
+    sessions: BTreeMap<u32, u64>,
+    // pid -> the ports the process listens on, learned from the bind and the listen
+    listen_ports: BTreeMap<u32, BTreeSet<u16>>,
     // this is used by capnp reader
     // TODO: split
     pub cx: Arc<Cx>,
 }
 
-pub struct ThreadContext {
+/// Decrypts and parses the connections of its shard in a dedicated thread,
+/// keeps the order of the chunks within each connection.
+/// The connection belongs to exactly one shard, so the sequence numbers
+/// of its messages are assigned in the order of the data on the wire.
+pub struct Worker {
     handle: JoinHandle<()>,
-    tx: mpsc::Sender<NetworkChunk>,
+    tx: mpsc::Sender<WorkerMessage>,
+}
+
+enum WorkerMessage {
+    Connect {
+        info: ConnectionInfo,
+        cn_cx: Box<ConnectionContext>,
+    },
+    Data(NetworkChunk),
+    Disconnect(DirectedId),
 }
 
 pub struct ConnectionContext {
     cn: Cn,
     db: DbGroup,
+    progress: DecoderProgress,
+    // the decoder stalled and the watchdog stopped it, only raw bytes are stored
+    raw: bool,
+    // the remote end might be a tracked process on this host
+    local: Option<SocketAddr>,
+    // the chunks so far and the time of the latest pipeline snapshot
+    chunks: u64,
+    pipeline_updated: SystemTime,
+}
+
+impl ConnectionContext {
+    fn new(cn: Cn, db: DbGroup) -> Self {
+        ConnectionContext {
+            cn,
+            db,
+            progress: DecoderProgress::default(),
+            raw: false,
+            local: None,
+            chunks: 0,
+            pipeline_updated: SystemTime::UNIX_EPOCH,
+        }
+    }
+
+    fn update_pipeline(&mut self, time: SystemTime) {
+        if Pipelines::outdated(self.chunks, self.pipeline_updated, time) {
+            self.pipeline_updated = time;
+            let snapshot = self.cn.snapshot();
+            self.db
+                .update_pipeline(time, self.chunks, self.raw, snapshot);
+        }
+    }
+
+    fn on_data(&mut self, id: DirectedId, bytes: &mut [u8], cx: &Cx) {
+        let time = id.metadata.time;
+        self.chunks += 1;
+        self.on_data_inner(id, bytes, cx);
+        self.update_pipeline(time);
+    }
+
+    fn on_data_inner(&mut self, id: DirectedId, bytes: &mut [u8], cx: &Cx) {
+        let time = id.metadata.time;
+        if self.raw {
+            if let Err(err) = self
+                .db
+                .add_raw(EncryptionStatus::Raw, id.incoming, time, bytes)
+            {
+                log::error!("{id}: {err}");
+            }
+            return;
+        }
+
+        let len = bytes.len();
+        if let Err(err) = self.cn.on_data(id.clone(), bytes, cx, &self.db) {
+            log::error!("{id}: {err}");
+        }
+
+        let config = self.db.watchdog().config().clone();
+        let timeout = match config.timeout {
+            Some(v) if !self.cn.skipped() => v,
+            _ => return,
+        };
+        if let Some((since, bytes)) = self.progress.observe(time, self.db.seq(), len, timeout) {
+            let snapshot = self.cn.snapshot();
+            self.db.on_stall(time, since, bytes, config.reset, snapshot);
+            self.raw = config.reset;
+        }
+    }
+
+    fn on_disconnect(self, id: &DirectedId, cx: &Cx) {
+        log::info!("{id} {} disconnect", self.db.id());
+        self.db.remove_pipeline();
+        if let Some(local) = self.local {
+            cx.local_pairs
+                .lock()
+                .closed(self.db.id(), local, id.metadata.id.addr);
+        }
+        let survivor =
+            cx.simultaneous_connect
+                .lock()
+                .closed(&id.metadata.id, self.db.id(), id.metadata.time);
+        if let Some(survivor) = survivor {
+            log::info!("{id} {} superseded by {survivor}", self.db.id());
+            if let Err(err) = self.db.set_superseded(survivor) {
+                log::error!("{id} {}: {err}", self.db.id());
+            }
+        }
+    }
 }
 
 pub struct NetworkChunk {
@@ -48,32 +164,112 @@ pub struct NetworkChunk {
     pub buffered: usize,
 }
 
-// my local sandbox
-// /coda/0.0.1/dd0f3f26be5a093f00077d1cd5d89abc253c95f301e9c12ae59e2d7c6052cc4d
-const CHAINS: [(&str, &str); 3] = [
-    (
-        "mainnet",
-        "/coda/0.0.1/5f704cc0c82e0ed70e873f0893d7e06f148524e3f0bdae2afb02e7819a0c24d1",
-    ),
-    (
-        "devnet",
-        "/coda/0.0.1/b6ee40d336f4cc3f33c1cc04dee7618eb8e556664c2b2d82ad4676b512a82418",
-    ),
-    (
-        "berkeley",
-        "/coda/0.0.1/fb30d090bb37e8aa354114d8c794b0f7072648a67bd1a08613684ac6f7c86028",
-    ),
-];
-
 pub struct Cx {
     pub apps: Mutex<BTreeMap<u32, (String, SocketAddr)>>,
     pub stats_state: Mutex<BTreeMap<SocketAddr, StatsState>>,
     pub db: DbFacade,
     pub stats: Stats,
     pub aggregator: Option<Aggregator>,
+    pub simultaneous_connect: Mutex<SimultaneousConnect>,
+    pub local_pairs: Mutex<LocalPairs>,
+    pub peer_names: PeerNames,
+    pub kube: KubeMetadata,
 }
 
 impl Cx {
+    pub fn on_simultaneous_connect(&self, id: &DirectedId, connection_id: ConnectionId) {
+        self.simultaneous_connect.lock().negotiated(
+            &id.metadata.id,
+            connection_id,
+            id.metadata.time,
+        );
+    }
+
+    /// The local address of the socket, the node might be behind a nat or have many interfaces.
+    fn on_local_addr(&self, id: &DirectedId, connection_id: ConnectionId) -> Option<SocketAddr> {
+        let ConnectionInfo { pid, fd, .. } = id.metadata.id;
+        let local = local_pair::local_addr(pid, fd)?;
+        if let Err(err) = self.db.core().set_local_addr(connection_id, local) {
+            log::error!("{id} {connection_id}: {err}");
+        }
+        Some(local)
+    }
+
+    /// The mss, the window scaling and the congestion control of the socket,
+    /// the process might close the socket meanwhile, then nothing is recorded.
+    fn on_tcp_params(&self, id: &DirectedId, connection_id: ConnectionId) {
+        let ConnectionInfo { pid, fd, .. } = id.metadata.id;
+        match tcp_info::tcp_params(pid, fd) {
+            Ok(params) => {
+                if let Err(err) = self.db.core().set_tcp_params(connection_id, params) {
+                    log::error!("{id} {connection_id}: {err}");
+                }
+            }
+            Err(err) => log::debug!("{id} {connection_id} cannot read the tcp parameters: {err}"),
+        }
+    }
+
+    /// If the remote end is on this host, it might be a tracked process,
+    /// link both connections and return the local address of this one.
+    fn on_local_connect(
+        &self,
+        id: &DirectedId,
+        connection_id: ConnectionId,
+        local: Option<SocketAddr>,
+    ) -> Option<SocketAddr> {
+        let ConnectionInfo { addr, pid, .. } = id.metadata.id;
+        let on_this_host =
+            addr.ip().is_loopback() || self.apps.lock().values().any(|(_, a)| a.ip() == addr.ip());
+        if !on_this_host {
+            return None;
+        }
+        let local = local?;
+        let paired = self
+            .local_pairs
+            .lock()
+            .connected(connection_id, pid, local, addr);
+        if let Some((other, other_pid)) = paired {
+            let other_alias = self
+                .apps
+                .lock()
+                .get(&other_pid)
+                .map(|(a, _)| a.clone())
+                .unwrap_or_default();
+            log::info!("{id} {connection_id} paired with {other} of {other_alias}");
+            let pair = [
+                (connection_id, other, other_alias),
+                (other, connection_id, id.alias.clone()),
+            ];
+            for (this, other, peer_alias) in pair {
+                if let Err(err) = self.db.set_paired(this, other, peer_alias) {
+                    log::error!("{id} {this}: {err}");
+                }
+            }
+        }
+        Some(local)
+    }
+
+    fn on_peer_name(&self, id: &DirectedId, connection_id: ConnectionId) {
+        let ip = id.metadata.id.addr.ip();
+        if let Some(name) = self.peer_names.on_connect(connection_id, ip) {
+            if let Err(err) = self.db.core().set_peer_name(connection_id, name) {
+                log::error!("{id} {connection_id}: {err}");
+            }
+        }
+    }
+
+    fn on_kube(&self, id: &DirectedId, connection_id: ConnectionId) {
+        let local = self.kube.pod_of_pid(id.metadata.id.pid);
+        let remote = self.kube.pod_of_ip(id.metadata.id.addr.ip());
+        if local.is_none() && remote.is_none() {
+            return;
+        }
+        let (local, remote) = (local.unwrap_or_default(), remote.unwrap_or_default());
+        if let Err(err) = self.db.core().set_pods(connection_id, local, remote) {
+            log::error!("{id} {connection_id}: {err}");
+        }
+    }
+
     pub fn pid_to_addr(&self, pid: u32) -> SocketAddr {
         self.apps
             .lock()
@@ -84,19 +280,78 @@ impl Cx {
     }
 }
 
+/// Simultaneous connect may leave two tcp connections between the same pair of nodes,
+/// the node discards one of them soon after the negotiation.
+/// Tracks the connections which negotiated `/libp2p/simultaneous-connect`
+/// to link the discarded connection to the surviving one.
+#[derive(Default)]
+pub struct SimultaneousConnect {
+    // (pid, remote ip) -> connections and the time of the negotiation
+    negotiated: BTreeMap<(u32, IpAddr), Vec<(ConnectionId, SystemTime)>>,
+}
+
+impl SimultaneousConnect {
+    /// The discarded connection closes within this time after the negotiation.
+    const WINDOW: Duration = Duration::from_secs(30);
+
+    pub fn negotiated(&mut self, info: &ConnectionInfo, id: ConnectionId, time: SystemTime) {
+        let cns = self
+            .negotiated
+            .entry((info.pid, info.addr.ip()))
+            .or_default();
+        // both directions report the negotiation
+        if !cns.iter().any(|(c, _)| *c == id) {
+            cns.push((id, time));
+        }
+    }
+
+    /// The connection is closed, returns the surviving connection if this one is discarded.
+    pub fn closed(
+        &mut self,
+        info: &ConnectionInfo,
+        id: ConnectionId,
+        time: SystemTime,
+    ) -> Option<ConnectionId> {
+        let key = (info.pid, info.addr.ip());
+        let cns = self.negotiated.get_mut(&key)?;
+        let pos = cns.iter().position(|(c, _)| *c == id)?;
+        let (_, negotiated) = cns.remove(pos);
+        let survivor = cns
+            .iter()
+            .filter(|(_, t)| {
+                let d = t
+                    .duration_since(negotiated)
+                    .unwrap_or_else(|e| e.duration());
+                d < Self::WINDOW
+            })
+            .map(|(c, _)| *c)
+            .last();
+        if cns.is_empty() {
+            self.negotiated.remove(&key);
+        }
+        let discarded = time
+            .duration_since(negotiated)
+            .map_or(true, |d| d < Self::WINDOW);
+        survivor.filter(|_| discarded)
+    }
+}
+
 #[derive(Clone)]
 pub struct Aggregator {
     pub client: reqwest::blocking::Client,
-    pub url: reqwest::Url,
+    /// the primary and the standbys, the event goes to the first one which accepts it
+    pub urls: Vec<reqwest::Url>,
     pub debugger_name: String,
+    // the index of the aggregator which accepted the last event
+    pub current: Arc<AtomicUsize>,
 }
 
 impl Aggregator {
-    pub fn post_event<T>(&self, event: T)
+    /// `pods` maps the addresses of the event to `namespace/name` of the pod, might be empty.
+    pub fn post_event<T>(&self, event: T, pods: &BTreeMap<IpAddr, String>)
     where
         T: Serialize,
     {
-        let url = self.url.clone();
         let event_str = match serde_json::to_string(&event) {
             Ok(v) => v,
             Err(err) => {
@@ -104,12 +359,31 @@ impl Aggregator {
                 return;
             }
         };
+        let pods_str = serde_json::to_string(pods).unwrap_or_default();
         let body = format!(
-            "{{\"alias\": \"{}\", \"event\": {event_str} }}",
+            "{{\"alias\": \"{}\", \"event\": {event_str}, \"pods\": {pods_str} }}",
             self.debugger_name
         );
-        if let Err(err) = self.client.post(url).body(body).send() {
-            log::error!("failed to post event on aggregator {err}");
+        let current = self.current.load(Ordering::SeqCst);
+        for i in 0..self.urls.len() {
+            let i = (current + i) % self.urls.len();
+            let url = self.urls[i].clone();
+            let result = self
+                .client
+                .post(url.clone())
+                .body(body.clone())
+                .send()
+                .and_then(|r| r.error_for_status());
+            match result {
+                Ok(_) => {
+                    if i != current {
+                        log::warn!("switched to the aggregator {url}");
+                        self.current.store(i, Ordering::SeqCst);
+                    }
+                    return;
+                }
+                Err(err) => log::error!("failed to post event on aggregator {url}: {err}"),
+            }
         }
     }
 }
@@ -120,10 +394,17 @@ impl P2pRecorder {
 
         let aggregator = if let Ok(aggregator_str) = env::var("AGGREGATOR") {
             log::info!("use aggregator {aggregator_str}");
-            if let Ok(aggregator) = aggregator_str.parse::<reqwest::Url>() {
+            let urls = aggregator_str
+                .split(',')
+                .map(|s| s.trim().parse::<reqwest::Url>())
+                .collect::<Result<Vec<_>, _>>();
+            if let Ok(urls) = urls {
                 let debugger_name = env::var("DEBUGGER_NAME").unwrap_or("noname".to_owned());
                 let client = reqwest::blocking::Client::new();
-                let url = aggregator.join("new").expect("url is valid");
+                let urls = urls
+                    .into_iter()
+                    .map(|url| url.join("new").expect("url is valid"))
+                    .collect();
                 // let body = format!("{{\"alias\": {hostname:?}, \"port\": {port} }}");
                 // match client.post(url).body(body).send() {
                 //     Ok(_) => (),
@@ -131,8 +412,9 @@ impl P2pRecorder {
                 // }
                 Some(Aggregator {
                     client,
-                    url,
+                    urls,
                     debugger_name,
+                    current: Arc::default(),
                 })
             } else {
                 log::error!("cannot parse aggregator url {aggregator_str}");
@@ -142,26 +424,85 @@ impl P2pRecorder {
             None
         };
 
+        let workers_number = env::var("DECRYPT_WORKERS")
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(0);
+
+        let peer_names = PeerNamesConfig::from_env();
+        let kube = KubeMetadata::from_env();
+
+        Self::with_options(db, test, aggregator, workers_number, peer_names, kube)
+    }
+
+    /// Like `new`, but does not read the environment.
+    pub fn with_options(
+        db: DbFacade,
+        test: bool,
+        aggregator: Option<Aggregator>,
+        workers_number: usize,
+        peer_names: PeerNamesConfig,
+        kube: KubeMetadata,
+    ) -> Self {
+        let peer_names = PeerNames::new(peer_names, db.core());
+        let cx = Arc::new(Cx {
+            apps: Mutex::default(),
+            db,
+            stats: Stats::default(),
+            stats_state: Mutex::default(),
+            aggregator,
+            simultaneous_connect: Mutex::default(),
+            local_pairs: Mutex::default(),
+            peer_names,
+            kube,
+        });
+
+        if workers_number != 0 {
+            log::info!("use {workers_number} decryption workers");
+        }
+        let workers = (0..workers_number)
+            .map(|i| Worker::spawn(i, cx.clone()))
+            .collect();
+
         P2pRecorder {
             tester: if test { Some(Tester::default()) } else { None },
+            workers,
             cns: BTreeMap::default(),
             cns_main_thread: BTreeMap::default(),
-            cx: Arc::new(Cx {
-                apps: Mutex::default(),
-                db,
-                stats: Stats::default(),
-                stats_state: Mutex::default(),
-                aggregator,
-            }),
+            sessions: BTreeMap::default(),
+            listen_ports: BTreeMap::default(),
+            cx,
         }
     }
 
-    pub fn set_port(&mut self, pid: u32, port: u16) {
-        self.cx
-            .apps
-            .lock()
-            .get_mut(&pid)
-            .map(|(_, addr)| addr.set_port(port));
+    fn shard(&self, info: &ConnectionInfo) -> usize {
+        use std::{
+            collections::hash_map::DefaultHasher,
+            hash::{Hash, Hasher},
+        };
+
+        let mut hasher = DefaultHasher::new();
+        info.addr.hash(&mut hasher);
+        info.pid.hash(&mut hasher);
+        info.fd.hash(&mut hasher);
+        (hasher.finish() as usize) % self.workers.len()
+    }
+
+    /// The process listens on the address, the first port is the p2p port of the node,
+    /// it replaces the default 8302. Returns whether the port is new for the process.
+    pub fn on_listen(&mut self, pid: u32, local: SocketAddr) -> bool {
+        let mut apps = self.cx.apps.lock();
+        let Some((_, addr)) = apps.get_mut(&pid) else {
+            return false;
+        };
+        let ports = self.listen_ports.entry(pid).or_default();
+        if ports.is_empty() {
+            addr.set_port(local.port());
+            if addr.ip().is_unspecified() && !local.ip().is_unspecified() {
+                addr.set_ip(local.ip());
+            }
+        }
+        ports.insert(local.port())
     }
 
     pub fn on_alias(&mut self, pid: u32, alias: String) {
@@ -171,12 +512,40 @@ impl P2pRecorder {
             .unwrap_or("0.0.0.0")
             .parse()
             .unwrap_or(IpAddr::V4(0.into()));
+        // the default port until the process listens
+        self.listen_ports.remove(&pid);
         self.cx
             .apps
             .lock()
             .insert(pid, (alias, SocketAddr::new(ip, 8302)));
     }
 
+    /// Start a new capture session for the process, finish the previous one if any.
+    pub fn begin_session(&mut self, pid: u32, time: SystemTime) {
+        self.end_session(pid, time);
+        let alias = {
+            let lock = self.cx.apps.lock();
+            lock.get(&pid).cloned().map(|(a, _)| a).unwrap_or_default()
+        };
+        match self.cx.db.begin_session(alias, pid, time) {
+            Ok(id) => {
+                log::info!("{pid} begin session {id}");
+                self.sessions.insert(pid, id);
+            }
+            Err(err) => log::error!("{pid} cannot begin session: {err}"),
+        }
+    }
+
+    /// The connections of the process should be already closed.
+    pub fn end_session(&mut self, pid: u32, time: SystemTime) {
+        if let Some(id) = self.sessions.remove(&pid) {
+            match self.cx.db.end_session(id, time) {
+                Ok(()) => log::info!("{pid} end session {id}"),
+                Err(err) => log::error!("{pid} cannot end session {id}: {err}"),
+            }
+        }
+    }
+
     pub fn on_connect<const MAIN_THREAD: bool>(
         &mut self,
         incoming: bool,
@@ -198,14 +567,17 @@ impl P2pRecorder {
         let chain_id = if !suggested_chain_id.is_empty() {
             suggested_chain_id
         } else {
-            let mut it = alias.split('-');
-            let network = it.next().expect("`split` must yield at least one");
-            CHAINS
-                .iter()
-                .find_map(|(k, v)| if *k == network { Some(*v) } else { None })
-                .unwrap_or(network)
-                .to_owned()
+            self.cx.db.profiles().chain_id(&alias)
         };
+        let policy = self.cx.db.capture_policy();
+        let listen = self.listen_ports.get(&metadata.id.pid);
+        if !policy.records(&metadata.id, incoming, listen) {
+            log::debug!(
+                "{:?} skip the connection by the capture policy",
+                metadata.id
+            );
+            return;
+        }
         let id = DirectedId {
             metadata,
             alias: alias.clone(),
@@ -218,56 +590,36 @@ impl P2pRecorder {
             id.alias.clone(),
             id.metadata.time,
         ) {
-            Ok(group) => {
-                log::debug!("{id} {} new connection", group.id());
+            Ok(mut group) => {
+                let profile = self.cx.db.profiles().select(&chain_id);
+                log::debug!("{id} {} new connection, {}", group.id(), profile.name);
+                group.set_profile(profile);
                 let info = id.metadata.id.clone();
 
-                let (tx, rx) = mpsc::channel();
-                let cx = self.cx.clone();
-                let mut cn = Cn::new(chain_id.as_bytes());
-
-                if MAIN_THREAD {
-                    self.cns_main_thread.insert(
-                        id.metadata.id,
-                        ConnectionContext {
-                            cn: Cn::new(chain_id.as_bytes()),
-                            db: group,
-                        },
-                    );
+                let mut cn_cx = ConnectionContext::new(Cn::new(chain_id.as_bytes()), group);
+                cn_cx.update_pipeline(id.metadata.time);
+                let local = self.cx.on_local_addr(&id, cn_cx.db.id());
+                cn_cx.local = self.cx.on_local_connect(&id, cn_cx.db.id(), local);
+                self.cx.on_tcp_params(&id, cn_cx.db.id());
+                self.cx.on_peer_name(&id, cn_cx.db.id());
+                self.cx.on_kube(&id, cn_cx.db.id());
 
+                if MAIN_THREAD || self.workers.is_empty() {
+                    self.cns_main_thread.insert(info, cn_cx);
                     return;
                 }
 
-                let handle = thread::spawn(move || {
-                    while let Ok(NetworkChunk {
-                        metadata,
-                        mut data,
-                        incoming,
-                        buffered,
-                    }) = rx.recv()
-                    {
-                        let alias = {
-                            let lock = cx.apps.lock();
-                            lock.get(&metadata.id.pid)
-                                .cloned()
-                                .map(|(a, _)| a)
-                                .unwrap_or_default()
-                        };
-                        let id = DirectedId {
-                            metadata,
-                            alias,
-                            incoming,
-                            buffered,
-                        };
-                        if let Err(err) = cn.on_data(id.clone(), &mut data, &cx, &group) {
-                            log::error!("{id}: {err}");
-                        }
-                    }
-                    log::debug!("{id} {} disconnect", group.id());
-                });
-                let t_cx = ThreadContext { handle, tx };
-
-                self.cns.insert(info, t_cx);
+                let shard = self.shard(&info);
+                let connection_id = cn_cx.db.id();
+                let msg = WorkerMessage::Connect {
+                    info: info.clone(),
+                    cn_cx: Box::new(cn_cx),
+                };
+                if self.workers[shard].tx.send(msg).is_ok() {
+                    self.cns.insert(info, (shard, connection_id));
+                } else {
+                    log::error!("{id} decryption worker {shard} is dead");
+                }
             }
             Err(err) => {
                 log::error!("{id} new connection, cannot write in db {err}");
@@ -294,14 +646,11 @@ impl P2pRecorder {
             incoming,
             buffered,
         };
-        if let Some(t_cx) = self.cns.remove(&id.metadata.id) {
-            drop(t_cx.tx);
-            match t_cx.handle.join() {
-                Ok(()) => log::info!("{id} join thread"),
-                Err(err) => log::error!("{id} {err:?}"),
-            }
+        if let Some((shard, _)) = self.cns.remove(&id.metadata.id) {
+            let msg = WorkerMessage::Disconnect(id);
+            self.workers[shard].tx.send(msg).unwrap_or_default();
         } else if let Some(cn_cx) = self.cns_main_thread.remove(&id.metadata.id) {
-            log::info!("{id} {} disconnect", cn_cx.db.id());
+            cn_cx.on_disconnect(&id, &self.cx);
         }
     }
 
@@ -317,13 +666,13 @@ impl P2pRecorder {
             tester.on_data(incoming, metadata, bytes);
             return;
         }
-        if let Some(t_cx) = self.cns.get_mut(&metadata.id) {
-            t_cx.tx.send(NetworkChunk {
+        if let Some((shard, _)) = self.cns.get(&metadata.id) {
+            self.workers[*shard].tx.send(WorkerMessage::Data(NetworkChunk {
                 metadata,
                 data: bytes,
                 incoming,
                 buffered,
-            }).unwrap_or_default();
+            })).unwrap_or_default();
         } else if let Some(cn_cx) = self.cns_main_thread.get_mut(&metadata.id) {
             let alias = {
                 let lock = self.cx.apps.lock();
@@ -338,8 +687,113 @@ impl P2pRecorder {
                 incoming,
                 buffered,
             };
-            if let Err(err) = cn_cx.cn.on_data(id.clone(), &mut bytes, &self.cx, &cn_cx.db) {
-                log::error!("{id}: {err}");
+            cn_cx.on_data(id, &mut bytes, &self.cx);
+        }
+    }
+
+    /// Decrypts again the connections whose keys were injected via the http interface.
+    pub fn on_key_injections(&mut self) {
+        let core = self.cx.db.core();
+        for injection in core.key_injections().take_pending() {
+            let status = match self.redecrypt(&injection) {
+                Ok(status) => status,
+                Err(err) => InjectionStatus::Failed {
+                    error: err.to_string(),
+                },
+            };
+            core.key_injections().finish(injection.id, status);
+        }
+    }
+
+    fn redecrypt(&mut self, injection: &KeyInjection) -> Result<InjectionStatus, DbError> {
+        let core = self.cx.db.core();
+        let original = injection.connection_id;
+        if !core.manifest().capture_redaction.is_none() {
+            return Ok(InjectionStatus::Failed {
+                error: "the raw chunks are redacted at capture time".to_owned(),
+            });
+        }
+        let mut cn = core.fetch_connection(original.0)?;
+        let chunks = core
+            .fetch_chunks(original)
+            .filter(|(_, header, _)| matches!(header.encryption_status, EncryptionStatus::Raw))
+            .collect::<Vec<_>>();
+        if chunks.is_empty() {
+            return Ok(InjectionStatus::Failed {
+                error: "the connection has no raw chunks".to_owned(),
+            });
+        }
+        // the noise decoder looks up the secret keys among the randomness
+        for key in &injection.keys {
+            self.cx.db.add_randomness(key.to_vec())?;
+        }
+
+        let chain_id = injection
+            .chain_id
+            .clone()
+            .unwrap_or_else(|| self.cx.db.profiles().chain_id(&cn.alias));
+        let mut group =
+            self.cx
+                .db
+                .add(cn.info.clone(), cn.incoming, cn.alias.clone(), cn.timestamp)?;
+        group.set_profile(self.cx.db.profiles().select(&chain_id));
+        let connection_id = group.id();
+        log::info!("{original} decrypt again as {connection_id}");
+        let mut cn_cx = ConnectionContext::new(Cn::new(chain_id.as_bytes()), group);
+        let total = chunks.len() as u64;
+        for (_, header, mut bytes) in chunks {
+            let id = DirectedId {
+                metadata: EventMetadata {
+                    id: cn.info.clone(),
+                    time: header.time,
+                    better_time: header.time,
+                    duration: Duration::ZERO,
+                },
+                alias: cn.alias.clone(),
+                incoming: header.incoming,
+                buffered: 0,
+            };
+            cn_cx.on_data(id, &mut bytes, &self.cx);
+        }
+        let messages = cn_cx.db.seq();
+        cn_cx.db.remove_pipeline();
+        drop(cn_cx);
+
+        // the group sets the close time when dropped, restore the original one
+        if cn.timestamp_close != SystemTime::UNIX_EPOCH {
+            let mut new = core.fetch_connection(connection_id.0)?;
+            new.timestamp_close = cn.timestamp_close;
+            core.put_cn(connection_id, new)?;
+        }
+        cn.superseded_by = Some(connection_id);
+        core.put_cn(original, cn)?;
+
+        Ok(InjectionStatus::Done {
+            connection_id,
+            chunks: total,
+            messages,
+        })
+    }
+
+    pub fn on_syscall_error(&mut self, metadata: EventMetadata, syscall: String, errno: u32) {
+        let connection_id = if let Some((_, connection_id)) = self.cns.get(&metadata.id) {
+            *connection_id
+        } else if let Some(cn_cx) = self.cns_main_thread.get(&metadata.id) {
+            cn_cx.db.id()
+        } else {
+            return;
+        };
+        match self
+            .cx
+            .db
+            .core()
+            .add_syscall_error(connection_id, syscall, errno)
+        {
+            Ok(count) => {
+                log::debug!("{metadata} {connection_id} error {errno} happened {count} times")
+            }
+            Err(err) => {
+                log::error!("{metadata} {connection_id} failed to store syscall error: {err}")
             }
         }
     }
@@ -358,3 +812,178 @@ impl P2pRecorder {
         }
     }
 }
+
+impl Drop for P2pRecorder {
+    fn drop(&mut self) {
+        for (i, Worker { handle, tx }) in self.workers.drain(..).enumerate() {
+            drop(tx);
+            if handle.join().is_err() {
+                log::error!("decryption worker {i} panic");
+            }
+        }
+    }
+}
+
+impl Worker {
+    fn spawn(i: usize, cx: Arc<Cx>) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let handle = thread::Builder::new()
+            .name(format!("decrypt-{i}"))
+            .spawn(move || Self::run(rx, cx))
+            .expect("failed to spawn decryption worker");
+        Worker { handle, tx }
+    }
+
+    fn run(rx: mpsc::Receiver<WorkerMessage>, cx: Arc<Cx>) {
+        let mut cns = BTreeMap::<ConnectionInfo, ConnectionContext>::new();
+        while let Ok(msg) = rx.recv() {
+            match msg {
+                WorkerMessage::Connect { info, cn_cx } => {
+                    cns.insert(info, *cn_cx);
+                }
+                WorkerMessage::Data(NetworkChunk {
+                    metadata,
+                    mut data,
+                    incoming,
+                    buffered,
+                }) => {
+                    let cn_cx = match cns.get_mut(&metadata.id) {
+                        Some(v) => v,
+                        None => continue,
+                    };
+                    let alias = {
+                        let lock = cx.apps.lock();
+                        lock.get(&metadata.id.pid)
+                            .cloned()
+                            .map(|(a, _)| a)
+                            .unwrap_or_default()
+                    };
+                    let id = DirectedId {
+                        metadata,
+                        alias,
+                        incoming,
+                        buffered,
+                    };
+                    cn_cx.on_data(id, &mut data, &cx);
+                }
+                WorkerMessage::Disconnect(id) => {
+                    if let Some(cn_cx) = cns.remove(&id.metadata.id) {
+                        cn_cx.on_disconnect(&id, &cx);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn sharded_recorder(
+    path: &std::path::Path,
+) -> (P2pRecorder, crate::database::DbCore, Vec<ConnectionInfo>) {
+    let db = DbFacade::open(path).unwrap();
+    let core = db.core();
+    let mut recorder = P2pRecorder::with_options(
+        db,
+        false,
+        None,
+        4,
+        PeerNamesConfig::default(),
+        KubeMetadata::default(),
+    );
+    recorder.on_alias(999_999, "node-1.2.3.4".to_owned());
+    let infos = (0..16)
+        .map(|fd| ConnectionInfo {
+            addr: SocketAddr::new(IpAddr::V4([10, 0, 0, fd as u8].into()), 8302),
+            pid: 999_999,
+            fd,
+        })
+        .collect::<Vec<_>>();
+    for info in &infos {
+        let metadata = EventMetadata {
+            id: info.clone(),
+            ..EventMetadata::default()
+        };
+        recorder.on_connect::<false>(false, metadata, 0, String::new());
+    }
+    (recorder, core, infos)
+}
+
+#[cfg(test)]
+fn send_chunks(recorder: &mut P2pRecorder, infos: &[ConnectionInfo], chunks: u64) {
+    // the nonce of the private network, then the chunks are numbered,
+    // the connections are interleaved, so each worker gets many connections at once
+    for i in 0..=chunks {
+        for info in infos {
+            let metadata = EventMetadata {
+                id: info.clone(),
+                ..EventMetadata::default()
+            };
+            let bytes = if i == 0 {
+                vec![0; 24]
+            } else {
+                [&i.to_le_bytes()[..], &[info.fd as u8; 56][..]].concat()
+            };
+            recorder.on_data(false, metadata, 0, bytes);
+        }
+    }
+}
+
+#[cfg(test)]
+fn stored_chunks(core: &crate::database::DbCore, info: &ConnectionInfo) -> Vec<u64> {
+    let (id, _) = core
+        .fetch_all_connections()
+        .find(|(_, cn)| cn.info == *info)
+        .unwrap();
+    core.fetch_chunks(ConnectionId(id))
+        .filter(|(_, header, _)| matches!(header.encryption_status, EncryptionStatus::Raw))
+        .filter(|(_, _, bytes)| bytes.len() != 24)
+        .map(|(_, _, bytes)| u64::from_le_bytes(bytes[..8].try_into().unwrap()))
+        .collect()
+}
+
+#[cfg(test)]
+#[test]
+fn workers_keep_order() {
+    let dir = temp_dir::TempDir::new().unwrap();
+    let (mut recorder, core, infos) = sharded_recorder(dir.path());
+    assert_eq!(recorder.workers.len(), 4);
+    let shards = infos
+        .iter()
+        .map(|info| recorder.shard(info))
+        .collect::<BTreeSet<_>>();
+    assert!(shards.len() > 1, "the connections must be spread");
+    for info in &infos {
+        let (shard, _) = recorder.cns[info];
+        assert_eq!(shard, recorder.shard(info));
+    }
+
+    send_chunks(&mut recorder, &infos, 100);
+    drop(recorder);
+
+    for info in &infos {
+        assert_eq!(stored_chunks(&core, info), (1..=100).collect::<Vec<_>>());
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn drop_joins_workers() {
+    let dir = temp_dir::TempDir::new().unwrap();
+    let (mut recorder, core, infos) = sharded_recorder(dir.path());
+
+    // dropped right after the sending, the workers still have the chunks in their queues
+    send_chunks(&mut recorder, &infos, 1000);
+    for info in &infos {
+        let metadata = EventMetadata {
+            id: info.clone(),
+            ..EventMetadata::default()
+        };
+        recorder.on_disconnect(metadata, 0);
+    }
+    assert!(recorder.cns.is_empty());
+    drop(recorder);
+
+    for info in &infos {
+        assert_eq!(stored_chunks(&core, info).len(), 1000);
+    }
+}
@@ -0,0 +1,341 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    ffi::CStr,
+    io::{self, Write},
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use flate2::{write::GzEncoder, Compression};
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::{
+    bundle::{self, BundleError, BundleOptions, BundleSummary},
+    database::{ConnectionId, DbCore, DbStatistics, HandshakeFailure, Redaction},
+    health::HealthReport,
+};
+
+#[derive(Debug, Error)]
+pub enum ReportError {
+    #[error("{_0}")]
+    Io(#[from] io::Error),
+    #[error("{_0}")]
+    Json(#[from] serde_json::Error),
+    #[error("{_0}")]
+    Bundle(#[from] BundleError),
+}
+
+#[derive(Clone)]
+pub struct ReportOptions {
+    /// the health samples, the anomalies and the failing connections of this long ago
+    pub window: Duration,
+    /// the failing connections in the bundle, the latest first
+    pub connections: usize,
+    pub anomalies: usize,
+}
+
+impl Default for ReportOptions {
+    fn default() -> Self {
+        ReportOptions {
+            window: Duration::from_secs(24 * 60 * 60),
+            connections: 16,
+            anomalies: 1000,
+        }
+    }
+}
+
+#[derive(Default, Debug, Serialize)]
+pub struct ReportSummary {
+    pub anomalies: usize,
+    pub failing_connections: usize,
+    pub failed_checks: usize,
+    pub bundle: BundleSummary,
+}
+
+/// One check of the environment the recorder needs.
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub ok: bool,
+    pub detail: String,
+}
+
+/// Where the debugger runs, what the maintainers ask first about the bug.
+#[derive(Debug, Serialize)]
+pub struct Environment {
+    pub version: &'static str,
+    pub arch: &'static str,
+    pub kernel: String,
+    pub checks: Vec<Check>,
+    /// the variables configuring the debugger, the secrets are redacted
+    pub variables: BTreeMap<&'static str, String>,
+}
+
+impl Environment {
+    const VARIABLES: &'static [&'static str] = &[
+        "AGGREGATOR",
+        "ANOMALY_DETECTOR",
+        "API_TOKENS",
+        "BASELINE",
+        "CAPTURE_TRIGGERS",
+        "CAPTURES",
+        "DB_PATH",
+        "DEBUGGER_NAME",
+        "DEBUGGER_TOKEN",
+        "DECODER_WATCHDOG",
+        "DECRYPT_WORKERS",
+        "EVENT_CLOCK",
+        "FIREWALL_INTERFACE",
+        "FORWARD_IDENTITY",
+        "FORWARD_TO",
+        "GOSSIP_DEDUP",
+        "HEALTH_INTERVAL",
+        "NETWORK_PROFILE",
+        "NOISE_EXPORT_SECRETS",
+        "PSEUDONYMIZE_KEY",
+        "REDACTION",
+        "REPLAY",
+        "RUST_LOG",
+        "SELF_VERIFY_CONNECTIONS",
+        "SELF_VERIFY_INTERVAL",
+        "SERVER_PORT",
+        "SINKS",
+    ];
+
+    pub fn collect() -> Self {
+        let variables = Self::VARIABLES
+            .iter()
+            .filter_map(|name| {
+                let value = env::var(name).ok()?;
+                let secret = ["TOKEN", "SECRET", "IDENTITY", "KEY"]
+                    .iter()
+                    .any(|s| name.contains(s));
+                let value = if secret {
+                    "<redacted>".to_owned()
+                } else {
+                    value
+                };
+                Some((*name, value))
+            })
+            .collect();
+        Environment {
+            version: env!("CARGO_PKG_VERSION"),
+            arch: env::consts::ARCH,
+            kernel: kernel_release().unwrap_or_default(),
+            checks: doctor(),
+            variables,
+        }
+    }
+}
+
+fn kernel_release() -> Option<String> {
+    let mut name = unsafe { std::mem::zeroed::<libc::utsname>() };
+    if unsafe { libc::uname(&mut name) } != 0 {
+        return None;
+    }
+    let release = unsafe { CStr::from_ptr(name.release.as_ptr()) };
+    Some(release.to_string_lossy().into_owned())
+}
+
+/// The checks of the host the bpf recorder needs, each tells what is wrong if it fails.
+pub fn doctor() -> Vec<Check> {
+    let path = |name, path: &str| Check {
+        name,
+        ok: Path::new(path).exists(),
+        detail: path.to_owned(),
+    };
+
+    let kernel = kernel_release().unwrap_or_default();
+    let version = kernel
+        .split(|c: char| !c.is_ascii_digit())
+        .take(2)
+        .map(|s| s.parse::<u32>().unwrap_or_default())
+        .collect::<Vec<_>>();
+    // `pidfd_getfd` and the ring buffer map
+    let recent = version.as_slice() >= [5, 6].as_slice();
+    // the newer kernels charge the bpf maps to the cgroup instead
+    let memcg = version.as_slice() >= [5, 11].as_slice();
+
+    let euid = unsafe { libc::geteuid() };
+    let mut memlock = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    let memlock = match unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut memlock) } {
+        0 if memlock.rlim_cur == libc::RLIM_INFINITY => "unlimited".to_owned(),
+        0 => memlock.rlim_cur.to_string(),
+        _ => io::Error::last_os_error().to_string(),
+    };
+
+    vec![
+        Check {
+            name: "kernel",
+            ok: recent,
+            detail: format!("{kernel}, 5.6 or newer is needed"),
+        },
+        Check {
+            name: "root",
+            ok: euid == 0,
+            detail: format!("euid {euid}"),
+        },
+        Check {
+            name: "memlock",
+            ok: memcg || memlock == "unlimited",
+            detail: memlock,
+        },
+        path("btf", "/sys/kernel/btf/vmlinux"),
+        path("bpffs", "/sys/fs/bpf"),
+        path("tracefs", "/sys/kernel/debug/tracing"),
+    ]
+}
+
+#[derive(Serialize)]
+struct SelfMetrics {
+    health: HealthReport,
+    database: DbStatistics,
+}
+
+#[derive(Serialize)]
+struct FailingConnection {
+    connection_id: ConnectionId,
+    failure: HandshakeFailure,
+}
+
+struct Archive<W>
+where
+    W: Write,
+{
+    inner: tar::Builder<GzEncoder<W>>,
+    mtime: u64,
+}
+
+impl<W> Archive<W>
+where
+    W: Write,
+{
+    fn file(&mut self, path: &str, data: &[u8]) -> io::Result<()> {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(self.mtime);
+        header.set_cksum();
+        self.inner.append_data(&mut header, path, data)
+    }
+
+    fn json<T>(&mut self, path: &str, value: &T) -> Result<(), ReportError>
+    where
+        T: Serialize,
+    {
+        let data = serde_json::to_vec_pretty(value)?;
+        self.file(path, &data).map_err(Into::into)
+    }
+}
+
+/// Writes the diagnostic bundle for the bug against the debugger itself, the gzip compressed tar
+/// of the manifest of the capture, the health of the recorder, the recent anomalies,
+/// the failing connections with the redacted payloads and the environment.
+pub fn write<W>(db: &DbCore, options: &ReportOptions, out: W) -> Result<ReportSummary, ReportError>
+where
+    W: Write,
+{
+    let until = SystemTime::now();
+    let since = until - options.window;
+    let mut archive = Archive {
+        inner: tar::Builder::new(GzEncoder::new(out, Compression::default())),
+        mtime: until
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+    };
+    let mut summary = ReportSummary::default();
+
+    archive.json("manifest.json", db.manifest())?;
+
+    let health = HealthReport::new(since, until, db.fetch_health(since, until).collect());
+    let metrics = SelfMetrics {
+        health,
+        database: db.fetch_db_statistics(),
+    };
+    archive.json("self_metrics.json", &metrics)?;
+
+    let anomalies = db
+        .fetch_anomalies(since)
+        .take(options.anomalies)
+        .collect::<Vec<_>>();
+    summary.anomalies = anomalies.len();
+    archive.json("anomalies.json", &anomalies)?;
+
+    let failing = db
+        .fetch_failed_handshakes(since, until, options.connections)
+        .into_iter()
+        .map(|(connection_id, failure)| FailingConnection {
+            connection_id,
+            failure,
+        })
+        .collect::<Vec<_>>();
+    summary.failing_connections = failing.len();
+    archive.json("failing_connections.json", &failing)?;
+
+    // never the secrets of the handshakes, nor the payloads in clear
+    let ids = failing.iter().map(|f| f.connection_id).collect::<Vec<_>>();
+    let bundle_options = BundleOptions {
+        redaction: Redaction::Hash,
+        ..Default::default()
+    };
+    let mut bundle = vec![];
+    summary.bundle = bundle::export(db, &ids, &bundle_options, &mut bundle)?;
+    archive.file("connections.bundle", &bundle)?;
+
+    let environment = Environment::collect();
+    summary.failed_checks = environment.checks.iter().filter(|c| !c.ok).count();
+    archive.json("environment.json", &environment)?;
+
+    archive.inner.into_inner()?.finish()?.flush()?;
+    Ok(summary)
+}
+
+#[cfg(test)]
+#[test]
+fn report_archive() {
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+
+    use crate::{database::DbFacade, event::ConnectionInfo};
+
+    let dir = temp_dir::TempDir::new().unwrap();
+    let db = DbFacade::open(dir.path()).unwrap();
+    let time = SystemTime::now() - Duration::from_secs(60);
+    let info = ConnectionInfo {
+        addr: "1.2.3.4:8302".parse().unwrap(),
+        pid: 1,
+        fd: 10,
+    };
+    // closes before the handshake
+    drop(db.add(info, true, "node".to_owned(), time).unwrap());
+
+    let mut out = vec![];
+    let summary = write(&db.core(), &ReportOptions::default(), &mut out).unwrap();
+    assert_eq!(summary.failing_connections, 1);
+    assert_eq!(summary.bundle.connections, 1);
+
+    let mut archive = tar::Archive::new(GzDecoder::new(out.as_slice()));
+    let mut files = BTreeMap::new();
+    for entry in archive.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let path = entry.path().unwrap().display().to_string();
+        let mut data = vec![];
+        entry.read_to_end(&mut data).unwrap();
+        files.insert(path, data);
+    }
+    assert_eq!(files.len(), 6);
+    let failing = serde_json::from_slice::<serde_json::Value>(&files["failing_connections.json"]);
+    assert_eq!(
+        failing.unwrap(),
+        serde_json::json!([{"connection_id": 0, "failure": "no_handshake"}])
+    );
+    let environment = serde_json::from_slice::<serde_json::Value>(&files["environment.json"]);
+    assert_eq!(environment.unwrap()["checks"].as_array().unwrap().len(), 6);
+}
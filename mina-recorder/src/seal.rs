@@ -0,0 +1,126 @@
+use std::{
+    env, fs,
+    io::{self, Read, Write},
+    iter,
+    path::Path,
+    str::FromStr,
+};
+
+use age::{
+    stream::{StreamReader, StreamWriter},
+    x25519, Decryptor, Encryptor,
+};
+
+/// The age header, the sealed data starts with it.
+const AGE_MAGIC: &[u8] = b"age-encryption.org/v1";
+
+fn invalid_data<E>(err: E) -> io::Error
+where
+    E: ToString,
+{
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
+
+/// The public key of the analysis team, `age1...`. The data sealed to it is readable
+/// only with the matching identity, neither the transport nor the collector can read it.
+#[derive(Clone)]
+pub struct Recipient(x25519::Recipient);
+
+impl FromStr for Recipient {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        x25519::Recipient::from_str(s.trim())
+            .map(Recipient)
+            .map_err(|err| format!("bad recipient {s}: {err}"))
+    }
+}
+
+impl Recipient {
+    pub const ENV: &str = "EXPORT_RECIPIENT";
+
+    /// The recipient from `EXPORT_RECIPIENT`, `None` if it is not set or invalid.
+    pub fn from_env() -> Option<Self> {
+        let s = env::var(Self::ENV).ok()?;
+        s.parse()
+            .map_err(|err| log::error!("{}: {err}", Self::ENV))
+            .ok()
+    }
+
+    /// Everything written to the result is encrypted, `StreamWriter::finish` must be called.
+    pub fn seal<W>(&self, out: W) -> io::Result<StreamWriter<W>>
+    where
+        W: Write,
+    {
+        let recipient = Box::new(self.0.clone()) as Box<dyn age::Recipient + Send>;
+        Encryptor::with_recipients(vec![recipient])
+            .ok_or_else(|| invalid_data("no recipient"))?
+            .wrap_output(out)
+    }
+
+    pub fn seal_bytes(&self, plain: &[u8]) -> io::Result<Vec<u8>> {
+        let mut w = self.seal(vec![])?;
+        w.write_all(plain)?;
+        w.finish()
+    }
+}
+
+/// The secret key of the analysis team, `AGE-SECRET-KEY-1...`.
+pub struct Identity(x25519::Identity);
+
+impl Identity {
+    /// The identity file as `age-keygen` writes it, the comments are skipped.
+    pub fn read<P>(path: P) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let s = fs::read_to_string(path)?;
+        let key = s
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with('#'))
+            .ok_or_else(|| invalid_data("the identity file has no key"))?;
+        x25519::Identity::from_str(key)
+            .map(Identity)
+            .map_err(invalid_data)
+    }
+
+    pub fn open<R>(&self, input: R) -> io::Result<StreamReader<R>>
+    where
+        R: Read,
+    {
+        match Decryptor::new(input).map_err(invalid_data)? {
+            Decryptor::Recipients(d) => d
+                .decrypt(iter::once(&self.0 as &dyn age::Identity))
+                .map_err(invalid_data),
+            _ => Err(invalid_data("sealed with a passphrase, not to a recipient")),
+        }
+    }
+
+    pub fn open_bytes(&self, sealed: &[u8]) -> io::Result<Vec<u8>> {
+        let mut plain = vec![];
+        self.open(sealed)?.read_to_end(&mut plain)?;
+        Ok(plain)
+    }
+}
+
+/// Whether the data is sealed, checks the beginning of it.
+pub fn is_sealed(prefix: &[u8]) -> bool {
+    prefix.starts_with(AGE_MAGIC)
+}
+
+#[cfg(test)]
+#[test]
+fn seal_round_trip() {
+    let secret = x25519::Identity::generate();
+    let recipient = Recipient(secret.to_public());
+    let identity = Identity(secret);
+
+    let sealed = recipient.seal_bytes(b"payload").unwrap();
+    assert!(is_sealed(&sealed));
+    assert!(!sealed.windows(7).any(|w| w == b"payload"));
+    assert_eq!(identity.open_bytes(&sealed).unwrap(), b"payload");
+
+    let other = Identity(x25519::Identity::generate());
+    assert!(other.open_bytes(&sealed).is_err());
+}
@@ -1,161 +1,1844 @@
-use std::{thread, path::Path};
+use std::{
+    thread,
+    path::Path,
+    sync::Arc,
+    time::{SystemTime, Duration},
+};
+
+use serde::{Serialize, Deserialize};
+use libp2p_core::PeerId;
+
+use warp::{
+    Filter, Rejection, Reply,
+    filters::BoxedFilter,
+    reply::{WithStatus, Json, self},
+    http::StatusCode,
+};
+
+use crate::{
+    meshsub_stats::BlockStat,
+    application::Application,
+    node_log, log_filter,
+    grafana::{self, QueryRequest, SearchRequest},
+    decode::{self, preview::PreviewLimits},
+    bundle::{self, BundleOptions},
+    export::{ExportRequest, ExportJobStatus},
+    seal::Recipient,
+    access::{AccessTokens, Scope},
+    beacon::PeerAlignment,
+    event::canonical_ip,
+    policy::CapturePolicy,
+    health::HealthReport,
+    verify::VerificationReport,
+    pseudonym::{self, Pseudonyms},
+};
+
+use super::database::{
+    DbCore, DbFacade, Params, Redaction, ConnectionId, NodeLogLine, SubscriptionPeer, Stall,
+    FullMessage, DbError, DecoderFilter, TimeBeacon, KeyInjections, Baseline, Captures,
+};
+
+#[derive(Deserialize)]
+struct ExportParams {
+    // redact the payload before sending it
+    redaction: Option<Redaction>,
+}
+
+#[derive(Deserialize)]
+struct BundleParams {
+    // comma separated connection ids
+    connections: String,
+    // include the noise keys, if the capture has them
+    keys: Option<bool>,
+    redaction: Option<Redaction>,
+    // seal the bundle to the age public key, by default `EXPORT_RECIPIENT`
+    recipient: Option<String>,
+}
+
+#[derive(Debug)]
+enum AccessDenied {
+    Unauthorized,
+    Forbidden,
+    Pseudonymized,
+    ReadOnly,
+}
+
+impl warp::reject::Reject for AccessDenied {}
+
+/// The scope of the token the client presents, rejects the unknown token.
+fn scope(
+    tokens: Arc<AccessTokens>,
+) -> impl Filter<Extract = (Scope,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::header::optional::<String>("authorization").and_then(
+        move |authorization: Option<String>| {
+            let scope = tokens.scope(authorization.as_deref());
+            async move { scope.ok_or_else(|| warp::reject::custom(AccessDenied::Unauthorized)) }
+        },
+    )
+}
+
+/// The route cannot redact its output, only the full scope passes.
+fn full_access(
+    tokens: Arc<AccessTokens>,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone + Sync + Send + 'static {
+    scope(tokens)
+        .and_then(|scope: Scope| async move {
+            match scope {
+                Scope::Full => Ok(()),
+                Scope::Redacted => Err(warp::reject::custom(AccessDenied::Forbidden)),
+            }
+        })
+        .untuple_one()
+}
+
+/// The routes of the mounted capture only read it.
+fn writable(
+    read_only: bool,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::any()
+        .and_then(move || async move {
+            if read_only {
+                Err(warp::reject::custom(AccessDenied::ReadOnly))
+            } else {
+                Ok(())
+            }
+        })
+        .untuple_one()
+}
+
+/// The raw bytes might contain the real addresses, not served if the api pseudonymizes them.
+fn raw_bytes(
+    pseudonymized: bool,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::any()
+        .and_then(move || async move {
+            if pseudonymized {
+                Err(warp::reject::custom(AccessDenied::Pseudonymized))
+            } else {
+                Ok(())
+            }
+        })
+        .untuple_one()
+}
+
+/// Replaces the addresses and the peer ids in the json of the reply, see `PSEUDONYMIZE_KEY`.
+async fn pseudonymize<R>(pseudonyms: Option<Arc<dyn Pseudonyms>>, reply: R) -> reply::Response
+where
+    R: Reply,
+{
+    use warp::{http::header::CONTENT_LENGTH, hyper::body};
+
+    let response = reply.into_response();
+    let pseudonyms = match pseudonyms {
+        Some(v) => v,
+        None => return response,
+    };
+    let (mut parts, b) = response.into_parts();
+    let bytes = match body::to_bytes(b).await {
+        Ok(v) => v,
+        Err(err) => {
+            log::error!("cannot read the reply: {err}");
+            return reply::with_status(reply::json(&()), StatusCode::INTERNAL_SERVER_ERROR)
+                .into_response();
+        }
+    };
+    let mut value = match serde_json::from_slice(&bytes) {
+        Ok(v) => v,
+        Err(_) => return reply::Response::from_parts(parts, bytes.into()),
+    };
+    pseudonym::apply(&*pseudonyms, &mut value);
+    parts.headers.remove(CONTENT_LENGTH);
+    let bytes = serde_json::to_vec(&value).unwrap_or_default();
+    reply::Response::from_parts(parts, bytes.into())
+}
+
+/// The `redaction` of the query, but at least the one the scope of the client enforces.
+fn redaction(
+    tokens: Arc<AccessTokens>,
+) -> impl Filter<Extract = (Redaction,), Error = Rejection> + Clone + Sync + Send + 'static {
+    scope(tokens)
+        .and(warp::query::query())
+        .map(|scope: Scope, params: ExportParams| scope.redaction(params.redaction))
+}
+
+async fn access_denied(err: Rejection) -> Result<WithStatus<Json>, Rejection> {
+    match err.find::<AccessDenied>() {
+        Some(AccessDenied::Unauthorized) => Ok(reply::with_status(
+            reply::json(&"unknown or missing token"),
+            StatusCode::UNAUTHORIZED,
+        )),
+        Some(AccessDenied::Forbidden) => Ok(reply::with_status(
+            reply::json(&"the token has no access to the payloads"),
+            StatusCode::FORBIDDEN,
+        )),
+        Some(AccessDenied::Pseudonymized) => Ok(reply::with_status(
+            reply::json(&"the addresses are pseudonymized, the raw bytes are not served"),
+            StatusCode::FORBIDDEN,
+        )),
+        Some(AccessDenied::ReadOnly) => Ok(reply::with_status(
+            reply::json(&"the mounted capture is read only"),
+            StatusCode::METHOD_NOT_ALLOWED,
+        )),
+        None => Err(err),
+    }
+}
+
+#[derive(Deserialize)]
+struct TimeParams {
+    // the start of the list, unix time in seconds
+    timestamp: Option<u64>,
+    // how many records to read, default is 16
+    limit: Option<usize>,
+}
+
+impl TimeParams {
+    fn from(&self) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(self.timestamp.unwrap_or(0))
+    }
+
+    fn limit(&self) -> usize {
+        self.limit.unwrap_or(16)
+    }
+}
+
+fn connection(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("connection" / u64).map(move |id: u64| -> reply::WithStatus<Json> {
+        match db.fetch_connection(id) {
+            Ok(v) => {
+                let mut v = v.post_process(None);
+                let syscall_errors = db.fetch_syscall_errors(ConnectionId(id));
+                if let Ok(syscall_errors) = serde_json::to_value(syscall_errors) {
+                    v.as_object_mut()
+                        .expect("connection must be a structure")
+                        .insert("syscall_errors".to_owned(), syscall_errors);
+                }
+                reply::with_status(reply::json(&v), StatusCode::OK)
+            }
+            Err(err) => reply::with_status(
+                reply::json(&err.to_string()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+        }
+    })
+}
+
+fn connection_negotiations(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("connection" / u64 / "negotiations").map(move |id: u64| -> WithStatus<Json> {
+        let v = db.fetch_negotiations(ConnectionId(id));
+        reply::with_status(reply::json(&v), StatusCode::OK)
+    })
+}
+
+fn connection_stream_ends(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("connection" / u64 / "stream_ends").map(move |id: u64| -> WithStatus<Json> {
+        let v = db.fetch_stream_ends(ConnectionId(id));
+        reply::with_status(reply::json(&v), StatusCode::OK)
+    })
+}
+
+fn connection_protocols(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("connection" / u64 / "protocols").map(move |id: u64| -> WithStatus<Json> {
+        match db.fetch_protocol_checklist(ConnectionId(id)) {
+            Ok(v) => reply::with_status(reply::json(&v), StatusCode::OK),
+            Err(DbError::NoItemAtCursor(_)) => {
+                reply::with_status(reply::json(&()), StatusCode::NOT_FOUND)
+            }
+            Err(err) => reply::with_status(
+                reply::json(&err.to_string()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+        }
+    })
+}
+
+fn connection_noise(
+    db: DbCore,
+    tokens: Arc<AccessTokens>,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("connection" / u64 / "noise")
+        .and(full_access(tokens))
+        .map(move |id: u64| -> WithStatus<Json> {
+            match db.fetch_noise_handshake(ConnectionId(id)) {
+                Ok(v) => reply::with_status(reply::json(&v), StatusCode::OK),
+                Err(DbError::NoItemAtCursor(_)) => {
+                    reply::with_status(reply::json(&()), StatusCode::NOT_FOUND)
+                }
+                Err(err) => reply::with_status(
+                    reply::json(&err.to_string()),
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                ),
+            }
+        })
+}
+
+fn connection_pipeline(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("connection" / u64 / "pipeline").map(move |id: u64| -> WithStatus<Json> {
+        match db.pipelines().get(ConnectionId(id)) {
+            Some(v) => reply::with_status(reply::json(&v), StatusCode::OK),
+            None => reply::with_status(reply::json(&()), StatusCode::NOT_FOUND),
+        }
+    })
+}
+
+fn pipelines(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("pipelines").map(move || -> WithStatus<Json> {
+        let v = db.pipelines().all();
+        reply::with_status(reply::json(&v), StatusCode::OK)
+    })
+}
+
+/// The query refers to the freeze which is already released.
+fn released(db: &DbCore, freeze: Option<u64>) -> Option<WithStatus<Json>> {
+    let id = freeze?;
+    if db.freezes().get(id).is_some() {
+        return None;
+    }
+    let err = format!("freeze {id} is released");
+    Some(reply::with_status(reply::json(&err), StatusCode::NOT_FOUND))
+}
+
+fn freeze(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("freeze").map(move || -> WithStatus<Json> {
+        let v = db.freeze(SystemTime::now());
+        reply::with_status(reply::json(&v), StatusCode::CREATED)
+    })
+}
+
+fn freeze_release(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("freeze" / u64 / "release").map(move |id: u64| -> WithStatus<Json> {
+        match db.freezes().release(id) {
+            Some(v) => reply::with_status(reply::json(&v), StatusCode::OK),
+            None => reply::with_status(reply::json(&()), StatusCode::NOT_FOUND),
+        }
+    })
+}
+
+fn freezes(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("freeze").map(move || -> WithStatus<Json> {
+        let v = db.freezes().all();
+        reply::with_status(reply::json(&v), StatusCode::OK)
+    })
+}
+
+fn connections(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("connections").and(warp::query::query()).map(
+        move |params: Params| -> WithStatus<Json> {
+            match params.validate_connection() {
+                Ok(valid) => {
+                    if let Some(reply) = released(&db, valid.coordinate.freeze) {
+                        return reply;
+                    }
+                    let v = db.fetch_connections(&valid);
+                    reply::with_status(reply::json(&v.collect::<Vec<_>>()), StatusCode::OK)
+                }
+                Err(err) => reply::with_status(
+                    reply::json(&err.to_string()),
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                ),
+            }
+        },
+    )
+}
+
+fn messages(
+    db: DbCore,
+    tokens: Arc<AccessTokens>,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("messages")
+        .and(warp::query::query())
+        .and(warp::query::query())
+        .and(scope(tokens))
+        .map(
+            move |params: Params, preview: PreviewParams, scope: Scope| -> WithStatus<Json> {
+                match params.validate() {
+                    Ok(valid) => {
+                        if let Some(reply) = released(&db, valid.coordinate.freeze) {
+                            return reply;
+                        }
+                        let v = db.fetch_messages(&valid);
+                        let limits =
+                            PreviewLimits::new(preview.preview_fields, preview.preview_bytes);
+                        let limits = match limits {
+                            Some(limits) => limits,
+                            None => {
+                                let v = v.collect::<Vec<_>>();
+                                return reply::with_status(reply::json(&v), StatusCode::OK);
+                            }
+                        };
+                        let v = v
+                            .map(|(id, msg)| {
+                                let mut msg = serde_json::to_value(msg).unwrap_or_default();
+                                let preview = db
+                                    .fetch_preview(id, limits, scope.redaction(None))
+                                    .unwrap_or_else(
+                                        |err| serde_json::json!({ "error": err.to_string() }),
+                                    );
+                                if let Some(msg) = msg.as_object_mut() {
+                                    msg.insert("preview".to_owned(), preview);
+                                }
+                                (id, msg)
+                            })
+                            .collect::<Vec<_>>();
+                        reply::with_status(reply::json(&v), StatusCode::OK)
+                    }
+                    Err(err) => reply::with_status(
+                        reply::json(&err.to_string()),
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    ),
+                }
+            },
+        )
+}
+
+fn captures(
+    captures: Arc<Captures>,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("captures").map(move || -> WithStatus<Json> {
+        reply::with_status(reply::json(&captures.summary()), StatusCode::OK)
+    })
+}
+
+fn captures_messages(
+    captures: Arc<Captures>,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("captures" / "messages")
+        .and(warp::query::query())
+        .map(move |params: Params| -> WithStatus<Json> {
+            match params.validate() {
+                Ok(valid) => {
+                    reply::with_status(reply::json(&captures.search(&valid)), StatusCode::OK)
+                }
+                Err(err) => reply::with_status(
+                    reply::json(&err.to_string()),
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                ),
+            }
+        })
+}
+
+fn message(
+    db: DbCore,
+    tokens: Arc<AccessTokens>,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("message" / u64).and(redaction(tokens)).map(
+        move |id: u64, redaction: Redaction| -> reply::WithStatus<Json> {
+            match db.fetch_full_message(id, redaction) {
+                Ok(v) => reply::with_status(reply::json(&v), StatusCode::OK),
+                Err(err) => reply::with_status(
+                    reply::json(&err.to_string()),
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                ),
+            }
+        },
+    )
+}
+
+#[derive(Deserialize)]
+struct PreviewParams {
+    // inline the decoded message cut to this many fields of each object ...
+    preview_fields: Option<usize>,
+    // ... and this many bytes
+    preview_bytes: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct BulkMessage {
+    id: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<FullMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+// the page of the frontend is much smaller
+const BULK_LIMIT: usize = 0x400;
+
+fn messages_bulk(
+    db: DbCore,
+    tokens: Arc<AccessTokens>,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("messages" / "bulk")
+        .and(redaction(tokens))
+        .and(warp::body::json())
+        .map(
+            move |redaction: Redaction, ids: Vec<u64>| -> WithStatus<Json> {
+                if ids.len() > BULK_LIMIT {
+                    let err = format!("too many ids {}, limit is {BULK_LIMIT}", ids.len());
+                    return reply::with_status(reply::json(&err), StatusCode::BAD_REQUEST);
+                }
+                let v = ids
+                    .into_iter()
+                    .map(|id| match db.fetch_full_message(id, redaction) {
+                        Ok(message) => BulkMessage {
+                            id,
+                            message: Some(message),
+                            error: None,
+                        },
+                        Err(err) => BulkMessage {
+                            id,
+                            message: None,
+                            error: Some(err.to_string()),
+                        },
+                    })
+                    .collect::<Vec<_>>();
+                reply::with_status(reply::json(&v), StatusCode::OK)
+            },
+        )
+}
+
+fn message_hex(
+    db: DbCore,
+    tokens: Arc<AccessTokens>,
+    pseudonymized: bool,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("message_hex" / u64)
+        .and(raw_bytes(pseudonymized))
+        .and(redaction(tokens))
+        .map(
+            move |id: u64, redaction: Redaction| -> reply::WithStatus<Json> {
+                match db.fetch_full_message_hex(id, redaction) {
+                    Ok(v) => reply::with_status(reply::json(&v), StatusCode::OK),
+                    Err(err) => reply::with_status(
+                        reply::json(&err.to_string()),
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    ),
+                }
+            },
+        )
+}
+
+fn message_bin(
+    db: DbCore,
+    tokens: Arc<AccessTokens>,
+    pseudonymized: bool,
+) -> impl Filter<Extract = (WithStatus<Vec<u8>>,), Error = Rejection> + Clone + Sync + Send + 'static
+{
+    warp::path!("message_bin" / u64)
+        .and(raw_bytes(pseudonymized))
+        .and(redaction(tokens))
+        .map(
+            move |id: u64, redaction: Redaction| -> reply::WithStatus<Vec<u8>> {
+                match db.fetch_full_message_bin(id, redaction) {
+                    Ok(v) => reply::with_status(v, StatusCode::OK),
+                    Err(err) => reply::with_status(
+                        err.to_string().as_bytes().to_vec(),
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    ),
+                }
+            },
+        )
+}
+
+fn export_bundle(
+    db: DbCore,
+    tokens: Arc<AccessTokens>,
+    pseudonymized: bool,
+) -> impl Filter<Extract = (WithStatus<Vec<u8>>,), Error = Rejection> + Clone + Sync + Send + 'static
+{
+    warp::path!("export" / "bundle")
+        .and(raw_bytes(pseudonymized))
+        .and(warp::query::query())
+        .and(scope(tokens))
+        .map(
+            move |params: BundleParams, scope: Scope| -> reply::WithStatus<Vec<u8>> {
+                let ids = params
+                    .connections
+                    .split(',')
+                    .map(|id| id.trim().parse().map(ConnectionId))
+                    .collect::<Result<Vec<_>, _>>();
+                let ids = match ids {
+                    Ok(v) => v,
+                    Err(err) => {
+                        return reply::with_status(
+                            err.to_string().as_bytes().to_vec(),
+                            StatusCode::BAD_REQUEST,
+                        )
+                    }
+                };
+                let recipient = match params.recipient.map(|s| s.parse::<Recipient>()) {
+                    Some(Ok(v)) => Some(v),
+                    Some(Err(err)) => {
+                        return reply::with_status(err.into_bytes(), StatusCode::BAD_REQUEST)
+                    }
+                    None => Recipient::from_env(),
+                };
+                let options = BundleOptions {
+                    // the noise keys decrypt the raw chunks
+                    keys: params.keys.unwrap_or_default() && scope == Scope::Full,
+                    redaction: scope.redaction(params.redaction),
+                    recipient,
+                };
+                let mut bundle = vec![];
+                match bundle::export(&db, &ids, &options, &mut bundle) {
+                    Ok(_) => reply::with_status(bundle, StatusCode::OK),
+                    Err(err) => reply::with_status(
+                        err.to_string().as_bytes().to_vec(),
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    ),
+                }
+            },
+        )
+}
+
+fn export_job_create(
+    db: DbCore,
+    tokens: Arc<AccessTokens>,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("export" / "jobs")
+        .and(warp::body::json())
+        .and(scope(tokens))
+        .map(
+            move |mut request: ExportRequest, scope: Scope| -> WithStatus<Json> {
+                request.redaction = scope.redaction(Some(request.redaction));
+                match db.export_jobs().create(&db, request) {
+                    Ok(job) => reply::with_status(
+                        reply::json(&ExportJobStatus::from(job)),
+                        StatusCode::CREATED,
+                    ),
+                    Err(err) => reply::with_status(reply::json(&err), StatusCode::BAD_REQUEST),
+                }
+            },
+        )
+}
+
+fn export_jobs(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("export" / "jobs").map(move || -> WithStatus<Json> {
+        let v = db
+            .export_jobs()
+            .list()
+            .into_iter()
+            .map(ExportJobStatus::from)
+            .collect::<Vec<_>>();
+        reply::with_status(reply::json(&v), StatusCode::OK)
+    })
+}
+
+fn export_job(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("export" / "jobs" / u64).map(move |id: u64| -> WithStatus<Json> {
+        match db.export_jobs().get(id) {
+            Some(job) => {
+                reply::with_status(reply::json(&ExportJobStatus::from(job)), StatusCode::OK)
+            }
+            None => reply::with_status(reply::json(&()), StatusCode::NOT_FOUND),
+        }
+    })
+}
+
+fn export_job_cancel(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("export" / "jobs" / u64 / "cancel").map(move |id: u64| -> WithStatus<Json> {
+        match db.export_jobs().cancel(id) {
+            Some(job) => {
+                reply::with_status(reply::json(&ExportJobStatus::from(job)), StatusCode::OK)
+            }
+            None => reply::with_status(reply::json(&()), StatusCode::NOT_FOUND),
+        }
+    })
+}
+
+/// Streams the file, the result of a day long capture does not fit in memory.
+fn export_job_result(
+    db: DbCore,
+    tokens: Arc<AccessTokens>,
+    pseudonymized: bool,
+) -> impl Filter<Extract = (reply::Response,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("export" / "jobs" / u64 / "result")
+        .and(raw_bytes(pseudonymized))
+        .and(scope(tokens))
+        .and_then(move |id: u64, scope: Scope| {
+            let result = db.export_jobs().result(id);
+            async move {
+                let (job, path) = match result {
+                    Some(v) => v,
+                    None => {
+                        let v = reply::with_status(reply::json(&()), StatusCode::NOT_FOUND);
+                        return Ok(v.into_response());
+                    }
+                };
+                // the client cannot fetch the result less redacted than its scope allows
+                if job.request.redaction < scope.redaction(None) {
+                    return Err(warp::reject::custom(AccessDenied::Forbidden));
+                }
+                match tokio::fs::File::open(path).await {
+                    Ok(file) => {
+                        let stream = tokio_util::io::ReaderStream::new(file);
+                        Ok(reply::Response::new(warp::hyper::Body::wrap_stream(stream)))
+                    }
+                    Err(err) => {
+                        let v = reply::with_status(
+                            err.to_string().into_bytes(),
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                        );
+                        Ok(v.into_response())
+                    }
+                }
+            }
+        })
+}
+
+fn sessions(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("sessions").map(move || -> WithStatus<Json> {
+        let v = db.fetch_sessions().collect::<Vec<_>>();
+        reply::with_status(reply::json(&v), StatusCode::OK)
+    })
+}
+
+fn session(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("session" / u64).map(move |id| -> WithStatus<Json> {
+        match db.fetch_session(id) {
+            Ok(v) => reply::with_status(reply::json(&v), StatusCode::OK),
+            Err(err) => reply::with_status(
+                reply::json(&err.to_string()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+        }
+    })
+}
+
+fn timeline(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("timeline").and(warp::query::query()).map(
+        move |params: Params| -> WithStatus<Json> {
+            match params.validate() {
+                Ok(valid) => {
+                    let v = db.fetch_timeline(&valid);
+                    reply::with_status(reply::json(&v), StatusCode::OK)
+                }
+                Err(err) => reply::with_status(
+                    reply::json(&err.to_string()),
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                ),
+            }
+        },
+    )
+}
+
+fn node_log_get(
+    db: DbCore,
+    tokens: Arc<AccessTokens>,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("node-log")
+        .and(full_access(tokens))
+        .and(warp::query::query())
+        .map(move |params: TimeParams| -> WithStatus<Json> {
+            let v = db
+                .fetch_node_log(params.from())
+                .take(params.limit())
+                .map(NodeLogLine::post_process)
+                .collect::<Vec<_>>();
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
+}
+
+fn node_status(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("node-status").and(warp::query::query()).map(
+        move |params: TimeParams| -> WithStatus<Json> {
+            let v = db
+                .fetch_node_status(params.from())
+                .take(params.limit())
+                .collect::<Vec<_>>();
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        },
+    )
+}
+
+fn message_node_status(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("message" / u64 / "node-status").map(move |id| -> WithStatus<Json> {
+        match db.fetch_message_node_status(id) {
+            Ok((before, after)) => {
+                let v = serde_json::json!({ "before": before, "after": after });
+                reply::with_status(reply::json(&v), StatusCode::OK)
+            }
+            Err(err) => reply::with_status(
+                reply::json(&err.to_string()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+        }
+    })
+}
+
+fn grafana_health(
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("grafana")
+        .map(move || -> WithStatus<Json> { reply::with_status(reply::json(&()), StatusCode::OK) })
+}
+
+fn grafana_search(
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("grafana" / "search")
+        .and(warp::body::json())
+        .map(move |request: SearchRequest| -> WithStatus<Json> {
+            reply::with_status(reply::json(&grafana::search(&request)), StatusCode::OK)
+        })
+}
+
+fn grafana_query(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("grafana" / "query")
+        .and(warp::body::json())
+        .map(move |request: QueryRequest| -> WithStatus<Json> {
+            match grafana::query(&db, &request) {
+                Ok(v) => reply::with_status(reply::json(&v), StatusCode::OK),
+                Err(err) => reply::with_status(reply::json(&err), StatusCode::BAD_REQUEST),
+            }
+        })
+}
+
+fn node_log_push(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("node-log")
+        .and(warp::body::bytes())
+        .and(warp::post())
+        .map(move |body: warp::hyper::body::Bytes| -> WithStatus<Json> {
+            match std::str::from_utf8(&body) {
+                Ok(lines) => {
+                    let stored = node_log::ingest(&db, lines);
+                    reply::with_status(reply::json(&stored), StatusCode::OK)
+                }
+                Err(err) => {
+                    reply::with_status(reply::json(&err.to_string()), StatusCode::BAD_REQUEST)
+                }
+            }
+        })
+}
+
+fn log_filter(
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("log" / "filter").map(move || -> WithStatus<Json> {
+        match log_filter::current() {
+            Some(spec) => reply::with_status(reply::json(&spec), StatusCode::OK),
+            None => reply::with_status(reply::json(&()), StatusCode::NOT_FOUND),
+        }
+    })
+}
+
+fn log_filter_set(
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("log" / "filter")
+        .and(warp::body::bytes())
+        .and(warp::post())
+        .map(move |body: warp::hyper::body::Bytes| -> WithStatus<Json> {
+            let result = std::str::from_utf8(&body)
+                .map_err(|err| err.to_string())
+                .and_then(|spec| log_filter::set(spec.trim()));
+            match result {
+                Ok(()) => reply::with_status(reply::json(&()), StatusCode::OK),
+                Err(err) => reply::with_status(reply::json(&err), StatusCode::BAD_REQUEST),
+            }
+        })
+}
+
+fn identity_history(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("peers" / String / "identity-history").map(move |id: String| -> WithStatus<Json> {
+        match id.parse::<PeerId>() {
+            Ok(peer_id) => {
+                let v = db.fetch_identity_history(peer_id);
+                reply::with_status(reply::json(&v), StatusCode::OK)
+            }
+            Err(err) => reply::with_status(reply::json(&err.to_string()), StatusCode::BAD_REQUEST),
+        }
+    })
+}
+
+fn peer_directory(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("peers" / "directory").map(move || -> WithStatus<Json> {
+        let v = db.peer_directory().fetch_all();
+        reply::with_status(reply::json(&v), StatusCode::OK)
+    })
+}
+
+fn peer_directory_record(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("peers" / "directory" / String).map(move |id: String| -> WithStatus<Json> {
+        let peer_id = match id.parse::<PeerId>() {
+            Ok(v) => v,
+            Err(err) => {
+                return reply::with_status(reply::json(&err.to_string()), StatusCode::BAD_REQUEST)
+            }
+        };
+        match db.peer_directory().fetch(peer_id) {
+            // `null` if the peer is unknown
+            Ok(v) => reply::with_status(reply::json(&v), StatusCode::OK),
+            Err(err) => reply::with_status(
+                reply::json(&err.to_string()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+        }
+    })
+}
+
+#[derive(Deserialize)]
+struct RangeParams {
+    // unix time in seconds, default is the beginning
+    since: Option<u64>,
+    // unix time in seconds, default is now
+    until: Option<u64>,
+}
+
+impl RangeParams {
+    fn range(&self) -> (SystemTime, SystemTime) {
+        let since = SystemTime::UNIX_EPOCH + Duration::from_secs(self.since.unwrap_or(0));
+        let until = self.until.map_or_else(SystemTime::now, |secs| {
+            SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+        });
+        (since, until)
+    }
+}
+
+fn subscriptions(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("subscriptions").and(warp::query::query()).map(
+        move |params: RangeParams| -> WithStatus<Json> {
+            let (since, until) = params.range();
+            let v = db.fetch_subscription_timeline(SubscriptionPeer::Local, since, until);
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        },
+    )
+}
+
+fn peer_subscriptions(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("peers" / String / "subscriptions")
+        .and(warp::query::query())
+        .map(move |id: String, params: RangeParams| -> WithStatus<Json> {
+            let peer = match (id.parse(), id.parse::<PeerId>()) {
+                (Ok(ip), _) => SubscriptionPeer::Ip(canonical_ip(ip)),
+                (_, Ok(peer_id)) => SubscriptionPeer::PeerId(peer_id),
+                (_, Err(err)) => {
+                    return reply::with_status(
+                        reply::json(&err.to_string()),
+                        StatusCode::BAD_REQUEST,
+                    )
+                }
+            };
+            let (since, until) = params.range();
+            let v = db.fetch_subscription_timeline(peer, since, until);
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
+}
+
+fn stats(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("block_v1" / u32).map(move |id| -> WithStatus<Json> {
+        let v = db.fetch_stats(id).map(|(_, v)| v);
+        reply::with_status(reply::json(&v), StatusCode::OK)
+    })
+}
+
+fn stats_block_v2(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("block" / u32).map(move |height| -> WithStatus<Json> {
+        let events = db.fetch_stats_block_v2(height);
+        let v = BlockStat { height, events };
+        reply::with_status(reply::json(&v), StatusCode::OK)
+    })
+}
+
+fn stats_last(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("block_v1" / "last").map(move || -> WithStatus<Json> {
+        let v = db.fetch_last_stat().map(|(_, v)| v);
+        reply::with_status(reply::json(&v), StatusCode::OK)
+    })
+}
+
+fn stats_latest(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("block_v1" / "latest").map(move || -> WithStatus<Json> {
+        let v = db.fetch_last_stat().map(|(_, v)| v);
+        reply::with_status(reply::json(&v), StatusCode::OK)
+    })
+}
+
+fn stats_block_v2_latest(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("block" / "latest").map(move || -> WithStatus<Json> {
+        let v = db
+            .fetch_last_stat_block_v2()
+            .map(|(height, events)| BlockStat { height, events });
+        reply::with_status(reply::json(&v), StatusCode::OK)
+    })
+}
+
+fn stats_db(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("stats" / "db").map(move || -> WithStatus<Json> {
+        let v = db.fetch_db_statistics();
+        reply::with_status(reply::json(&v), StatusCode::OK)
+    })
+}
+
+fn capture_triggers(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("capture" / "triggers").map(move || -> WithStatus<Json> {
+        let v = db.triggers().fired();
+        reply::with_status(reply::json(&v), StatusCode::OK)
+    })
+}
+
+#[derive(Serialize)]
+struct WatchdogState {
+    timeout_secs: Option<u64>,
+    reset: bool,
+    stalls: Vec<Stall>,
+}
+
+fn watchdog(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("watchdog").map(move || -> WithStatus<Json> {
+        let watchdog = db.watchdog();
+        let v = WatchdogState {
+            timeout_secs: watchdog.config().timeout.map(|t| t.as_secs()),
+            reset: watchdog.config().reset,
+            stalls: watchdog.stalls(),
+        };
+        reply::with_status(reply::json(&v), StatusCode::OK)
+    })
+}
+
+fn anomalies(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("anomalies").and(warp::query::query()).map(
+        move |params: TimeParams| -> WithStatus<Json> {
+            let v = db
+                .fetch_anomalies(params.from())
+                .take(params.limit())
+                .collect::<Vec<_>>();
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        },
+    )
+}
+
+#[derive(Deserialize)]
+struct SloParams {
+    // unix time in seconds, default is one period before `until`
+    since: Option<u64>,
+    // unix time in seconds, default is now
+    until: Option<u64>,
+}
+
+fn slo(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("slo")
+        .and(warp::query::query())
+        .map(move |params: SloParams| -> WithStatus<Json> {
+            let until = params.until.map_or_else(SystemTime::now, |secs| {
+                SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+            });
+            let since = match params.since {
+                Some(secs) => SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+                None => until - db.slo().period,
+            };
+            let v = db.fetch_slo_report(since, until);
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
+}
+
+#[derive(Deserialize)]
+struct SloReportsParams {
+    // how many periods, default is 24
+    limit: Option<usize>,
+}
+
+fn slo_reports(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("slo" / "reports")
+        .and(warp::query::query())
+        .map(move |params: SloReportsParams| -> WithStatus<Json> {
+            let periods = db
+                .slo()
+                .periods(SystemTime::now(), params.limit.unwrap_or(24));
+            let v = periods
+                .into_iter()
+                .map(|(since, until)| {
+                    let key = format!("slo?since={since:?}&until={until:?}");
+                    db.http_cache()
+                        .aggregation(&key, || db.fetch_slo_report(since, until))
+                })
+                .collect::<Vec<_>>();
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
+}
+
+fn stats_layers(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("stats" / "layers").map(move || -> WithStatus<Json> {
+        let v = db
+            .http_cache()
+            .aggregation("stats/layers", || db.fetch_layer_stats());
+        reply::with_status(reply::json(&v), StatusCode::OK)
+    })
+}
+
+fn stats_framing(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("stats" / "framing").map(move || -> WithStatus<Json> {
+        let v = db
+            .http_cache()
+            .aggregation("stats/framing", || db.fetch_framing_stats());
+        reply::with_status(reply::json(&v), StatusCode::OK)
+    })
+}
+
+fn decoder_versions(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("messages" / "decoder_versions").map(move || -> WithStatus<Json> {
+        let v = db
+            .http_cache()
+            .aggregation("messages/decoder_versions", || db.fetch_decoder_versions());
+        reply::with_status(reply::json(&v), StatusCode::OK)
+    })
+}
+
+#[derive(Deserialize)]
+struct RedecodeParams {
+    // decode the messages of this version ...
+    decoder_version: Option<u32>,
+    // ... or of any older version, default is the current version
+    decoder_version_below: Option<u32>,
+}
+
+fn redecode(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("messages" / "redecode")
+        .and(warp::query::query())
+        .and(warp::post())
+        .map(move |params: RedecodeParams| -> WithStatus<Json> {
+            let filter = match (params.decoder_version, params.decoder_version_below) {
+                (Some(version), None) => DecoderFilter::Version(version),
+                (None, version) => {
+                    DecoderFilter::Below(version.unwrap_or(crate::decode::DECODER_VERSION))
+                }
+                (Some(_), Some(_)) => {
+                    let err = "cannot use together decoder_version and decoder_version_below";
+                    return reply::with_status(reply::json(&err), StatusCode::BAD_REQUEST);
+                }
+            };
+            match db.redecode(filter) {
+                Ok(v) => reply::with_status(reply::json(&v), StatusCode::OK),
+                Err(err) => reply::with_status(
+                    reply::json(&err.to_string()),
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                ),
+            }
+        })
+}
+
+#[derive(Deserialize)]
+struct KeyInjectionRequest {
+    // hex of the 32 bytes secret keys, static or ephemeral
+    keys: Vec<String>,
+    // the chain id of the private network, default is derived from the alias of the node
+    chain_id: Option<String>,
+}
+
+fn key_injection(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("connection" / u64 / "keys")
+        .and(warp::body::json())
+        .map(
+            move |id: u64, request: KeyInjectionRequest| -> WithStatus<Json> {
+                let keys = request
+                    .keys
+                    .iter()
+                    .map(String::as_str)
+                    .map(KeyInjections::parse_key)
+                    .collect::<Result<Vec<_>, _>>();
+                let keys = match keys {
+                    Ok(v) if !v.is_empty() => v,
+                    Ok(_) => {
+                        let err = "no keys";
+                        return reply::with_status(reply::json(&err), StatusCode::BAD_REQUEST);
+                    }
+                    Err(err) => {
+                        return reply::with_status(reply::json(&err), StatusCode::BAD_REQUEST)
+                    }
+                };
+                if let Err(err) = db.fetch_connection(id) {
+                    return reply::with_status(
+                        reply::json(&err.to_string()),
+                        StatusCode::NOT_FOUND,
+                    );
+                }
+                let v = db.key_injections().request(
+                    ConnectionId(id),
+                    keys,
+                    request.chain_id,
+                    SystemTime::now(),
+                );
+                reply::with_status(reply::json(&v), StatusCode::ACCEPTED)
+            },
+        )
+}
+
+fn key_injections(
+    db: DbCore,
+    tokens: Arc<AccessTokens>,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("connection" / u64 / "keys")
+        .and(full_access(tokens))
+        .map(move |id: u64| -> WithStatus<Json> {
+            let v = db.key_injections().of(ConnectionId(id));
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
+}
+
+fn capture_policy(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("capture" / "policy").map(move || -> WithStatus<Json> {
+        let v = db.capture_policy().status();
+        reply::with_status(reply::json(&v), StatusCode::OK)
+    })
+}
+
+fn network_profiles(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("network" / "profiles").map(move || -> WithStatus<Json> {
+        let v = db.profiles().all();
+        reply::with_status(reply::json(&v), StatusCode::OK)
+    })
+}
+
+fn capture_policy_set(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("capture" / "policy")
+        .and(warp::body::json())
+        .map(move |policy: CapturePolicy| -> WithStatus<Json> {
+            if let Err(err) = policy.validate() {
+                return reply::with_status(reply::json(&err), StatusCode::BAD_REQUEST);
+            }
+            db.capture_policy().set(policy, SystemTime::now());
+            let v = db.capture_policy().status();
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
+}
+
+fn peers_geo(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("peers" / "geo").map(move || -> WithStatus<Json> {
+        let v = db
+            .http_cache()
+            .aggregation("peers/geo", || db.fetch_peer_geo());
+        reply::with_status(reply::json(&v), StatusCode::OK)
+    })
+}
+
+fn peers_nat(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("peers" / "nat").map(move || -> WithStatus<Json> {
+        let v = db.http_cache().aggregation("peers/nat", || db.fetch_nat());
+        reply::with_status(reply::json(&v), StatusCode::OK)
+    })
+}
+
+fn stats_ports(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("stats" / "ports")
+        .and(warp::query::query())
+        .map(move |params: RangeParams| -> WithStatus<Json> {
+            let (since, until) = params.range();
+            let key = format!(
+                "stats/ports?since={:?}&until={:?}",
+                params.since, params.until
+            );
+            let v = db
+                .http_cache()
+                .aggregation(&key, || db.fetch_port_usage(since, until));
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
+}
 
-use warp::{
-    Filter, Rejection, Reply,
-    reply::{WithStatus, Json, self},
-    http::StatusCode,
-};
+#[derive(Deserialize)]
+struct ActivityParams {
+    // seconds, default is one hour
+    window: Option<u64>,
+}
 
-use crate::{meshsub_stats::BlockStat, application::Application};
+fn stats_activity(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("stats" / "activity")
+        .and(warp::query::query())
+        .map(move |params: ActivityParams| -> WithStatus<Json> {
+            let window = params.window.unwrap_or(3600);
+            let key = format!("stats/activity?window={window}");
+            let v = db
+                .http_cache()
+                .aggregation(&key, || db.fetch_activity(Duration::from_secs(window)));
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
+}
 
-use super::database::{DbCore, DbFacade, Params};
+#[derive(Deserialize)]
+struct ConsistencyParams {
+    // seconds, default is one minute
+    window: Option<u64>,
+}
 
-fn connection(
+fn peer_consistency(
     db: DbCore,
 ) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
-    warp::path!("connection" / u64).map(move |id: u64| -> reply::WithStatus<Json> {
-        match db.fetch_connection(id) {
-            Ok(v) => {
-                let v = v.post_process(None);
-                reply::with_status(reply::json(&v), StatusCode::OK)
-            }
-            Err(err) => reply::with_status(
-                reply::json(&err.to_string()),
-                StatusCode::INTERNAL_SERVER_ERROR,
-            ),
-        }
-    })
+    warp::path!("peers" / "consistency")
+        .and(warp::query::query())
+        .map(move |params: ConsistencyParams| -> WithStatus<Json> {
+            let window = Duration::from_secs(params.window.unwrap_or(60));
+            let v = db.fetch_peer_consistency(window);
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
 }
 
-fn connections(
+#[derive(Deserialize)]
+struct ChurnParams {
+    // unix time in seconds, default is one day before `until`
+    since: Option<u64>,
+    // unix time in seconds, default is now
+    until: Option<u64>,
+}
+
+fn stats_churn(
     db: DbCore,
 ) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
-    warp::path!("connections").and(warp::query::query()).map(
-        move |params: Params| -> WithStatus<Json> {
-            match params.validate_connection() {
-                Ok(valid) => {
-                    let v = db.fetch_connections(&valid);
-                    reply::with_status(reply::json(&v.collect::<Vec<_>>()), StatusCode::OK)
-                }
-                Err(err) => reply::with_status(
-                    reply::json(&err.to_string()),
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                ),
-            }
-        },
-    )
+    warp::path!("stats" / "churn")
+        .and(warp::query::query())
+        .map(move |params: ChurnParams| -> WithStatus<Json> {
+            let until = params.until.map_or_else(SystemTime::now, |secs| {
+                SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+            });
+            let since = match params.since {
+                Some(secs) => SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+                None => until - Duration::from_secs(24 * 3600),
+            };
+            let key = format!(
+                "stats/churn?since={:?}&until={:?}",
+                params.since, params.until
+            );
+            let v = db
+                .http_cache()
+                .aggregation(&key, || db.fetch_churn(since, until));
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
 }
 
-fn messages(
+#[derive(Deserialize)]
+struct HealthParams {
+    // unix time in seconds, default is one hour before `until`
+    since: Option<u64>,
+    // unix time in seconds, default is now
+    until: Option<u64>,
+}
+
+fn health(
     db: DbCore,
 ) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
-    warp::path!("messages").and(warp::query::query()).map(
-        move |params: Params| -> WithStatus<Json> {
-            match params.validate() {
-                Ok(valid) => {
-                    let v = db.fetch_messages(&valid);
-                    reply::with_status(reply::json(&v.collect::<Vec<_>>()), StatusCode::OK)
-                }
-                Err(err) => reply::with_status(
-                    reply::json(&err.to_string()),
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                ),
-            }
+    warp::path!("health").and(warp::query::query()).map(
+        move |params: HealthParams| -> WithStatus<Json> {
+            let until = params.until.map_or_else(SystemTime::now, |secs| {
+                SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+            });
+            let since = match params.since {
+                Some(secs) => SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+                None => until - Duration::from_secs(3600),
+            };
+            let samples = db.fetch_health(since, until).collect();
+            let v = HealthReport::new(since, until, samples);
+            reply::with_status(reply::json(&v), StatusCode::OK)
         },
     )
 }
 
-fn message(
+fn verification(
     db: DbCore,
 ) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
-    warp::path!("message" / u64).map(move |id: u64| -> reply::WithStatus<Json> {
-        match db.fetch_full_message(id) {
-            Ok(v) => reply::with_status(reply::json(&v), StatusCode::OK),
-            Err(err) => reply::with_status(
-                reply::json(&err.to_string()),
-                StatusCode::INTERNAL_SERVER_ERROR,
-            ),
-        }
-    })
+    warp::path!("health" / "verification")
+        .and(warp::query::query())
+        .map(move |params: HealthParams| -> WithStatus<Json> {
+            let until = params.until.map_or_else(SystemTime::now, |secs| {
+                SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+            });
+            let since = match params.since {
+                Some(secs) => SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+                None => until - Duration::from_secs(86400),
+            };
+            let v = VerificationReport::new(since, until, db.fetch_verifications(since, until));
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
 }
 
-fn message_hex(
+fn health_ring_buffer(
     db: DbCore,
 ) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
-    warp::path!("message_hex" / u64).map(move |id: u64| -> reply::WithStatus<Json> {
-        match db.fetch_full_message_hex(id) {
-            Ok(v) => reply::with_status(reply::json(&v), StatusCode::OK),
-            Err(err) => reply::with_status(
-                reply::json(&err.to_string()),
-                StatusCode::INTERNAL_SERVER_ERROR,
-            ),
-        }
+    warp::path!("health" / "ring_buffer").map(move || -> WithStatus<Json> {
+        let v = db.health().ring_buffer();
+        reply::with_status(reply::json(&v), StatusCode::OK)
     })
 }
 
-fn message_bin(
+fn health_ring_buffer_pause(
     db: DbCore,
-) -> impl Filter<Extract = (WithStatus<Vec<u8>>,), Error = Rejection> + Clone + Sync + Send + 'static
-{
-    warp::path!("message_bin" / u64).map(move |id: u64| -> reply::WithStatus<Vec<u8>> {
-        match db.fetch_full_message_bin(id) {
-            Ok(v) => reply::with_status(v, StatusCode::OK),
-            Err(err) => reply::with_status(
-                err.to_string().as_bytes().to_vec(),
-                StatusCode::INTERNAL_SERVER_ERROR,
-            ),
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("health" / "ring_buffer" / String)
+        .and(warp::post())
+        .map(move |action: String| -> WithStatus<Json> {
+            let paused = match action.as_str() {
+                "pause" => true,
+                "resume" => false,
+                _ => return reply::with_status(reply::json(&()), StatusCode::NOT_FOUND),
+            };
+            if db.health().pause(paused) {
+                log::info!("the reader of the ring buffer is {action}d");
+                reply::with_status(reply::json(&()), StatusCode::OK)
+            } else {
+                let err = "no ring buffer";
+                reply::with_status(reply::json(&err), StatusCode::NOT_FOUND)
+            }
+        })
+}
+
+#[derive(Deserialize)]
+struct TimeBeaconParams {
+    // unix time in seconds, default is one hour ago
+    since: Option<u64>,
+}
+
+impl TimeBeaconParams {
+    fn since(&self) -> SystemTime {
+        match self.since {
+            Some(secs) => SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+            None => SystemTime::now() - Duration::from_secs(3600),
         }
-    })
+    }
 }
 
-fn stats(
+#[derive(Serialize)]
+struct TimeBeaconSample {
+    #[serde(flatten)]
+    beacon: TimeBeacon,
+    offset_ns: i64,
+    delay_ns: i64,
+}
+
+fn time_beacons(
     db: DbCore,
 ) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
-    warp::path!("block_v1" / u32).map(move |id| -> WithStatus<Json> {
-        let v = db.fetch_stats(id).map(|(_, v)| v);
-        reply::with_status(reply::json(&v), StatusCode::OK)
-    })
+    warp::path!("time" / "beacons")
+        .and(warp::query::query())
+        .map(move |params: TimeBeaconParams| -> WithStatus<Json> {
+            let v = db
+                .fetch_time_beacons(params.since())
+                .map(|beacon| TimeBeaconSample {
+                    offset_ns: beacon.offset_ns(),
+                    delay_ns: beacon.delay_ns(),
+                    beacon,
+                })
+                .collect::<Vec<_>>();
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
 }
 
-fn stats_block_v2(
+fn time_alignment(
     db: DbCore,
 ) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
-    warp::path!("block" / u32).map(move |height| -> WithStatus<Json> {
-        let events = db.fetch_stats_block_v2(height);
-        let v = BlockStat { height, events };
-        reply::with_status(reply::json(&v), StatusCode::OK)
-    })
+    warp::path!("time" / "alignment")
+        .and(warp::query::query())
+        .map(move |params: TimeBeaconParams| -> WithStatus<Json> {
+            let v = PeerAlignment::build(db.fetch_time_beacons(params.since()));
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
 }
 
-fn stats_last(
+#[derive(Deserialize)]
+struct PartialHandshakesParams {
+    // unix time in seconds, default is one day before `until`
+    since: Option<u64>,
+    // unix time in seconds, default is now
+    until: Option<u64>,
+    // how many connections to return, the latest first, default is 100
+    limit: Option<usize>,
+}
+
+fn partial_handshakes(
     db: DbCore,
 ) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
-    warp::path!("block_v1" / "last").map(move || -> WithStatus<Json> {
-        let v = db.fetch_last_stat().map(|(_, v)| v);
-        reply::with_status(reply::json(&v), StatusCode::OK)
-    })
+    warp::path!("handshakes" / "partial")
+        .and(warp::query::query())
+        .map(move |params: PartialHandshakesParams| -> WithStatus<Json> {
+            let until = params.until.map_or_else(SystemTime::now, |secs| {
+                SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+            });
+            let since = match params.since {
+                Some(secs) => SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+                None => until - Duration::from_secs(24 * 3600),
+            };
+            let limit = params.limit.unwrap_or(100);
+            let v = db.fetch_partial_handshakes(since, until, limit);
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
 }
 
-fn stats_latest(
+#[derive(Deserialize)]
+struct DuplicationParams {
+    // unix time in seconds, default is one hour before `until`
+    since: Option<u64>,
+    // unix time in seconds, default is now
+    until: Option<u64>,
+    // seconds, the width of the bucket
+    interval: Option<u64>,
+}
+
+fn gossip_duplication(
     db: DbCore,
 ) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
-    warp::path!("block_v1" / "latest").map(move || -> WithStatus<Json> {
-        let v = db.fetch_last_stat().map(|(_, v)| v);
-        reply::with_status(reply::json(&v), StatusCode::OK)
-    })
+    warp::path!("gossip" / "duplication")
+        .and(warp::query::query())
+        .map(move |params: DuplicationParams| -> WithStatus<Json> {
+            let until = params.until.map_or_else(SystemTime::now, |secs| {
+                SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+            });
+            let since = match params.since {
+                Some(secs) => SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+                None => until - Duration::from_secs(3600),
+            };
+            let interval = params.interval.map(Duration::from_secs);
+            let key = format!(
+                "gossip/duplication?since={:?}&until={:?}&interval={:?}",
+                params.since, params.until, params.interval
+            );
+            let v = db
+                .http_cache()
+                .aggregation(&key, || db.fetch_gossip_duplication(since, until, interval));
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
 }
 
-fn stats_block_v2_latest(
+#[derive(Deserialize)]
+struct FirstSeenParams {
+    // unix time in seconds, default is ten minutes before `until`
+    since: Option<u64>,
+    // unix time in seconds, default is now
+    until: Option<u64>,
+    // default is 1000
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct AgentVersionParams {
+    // unix time in seconds, default is one day before `until`
+    since: Option<u64>,
+    // unix time in seconds, default is now
+    until: Option<u64>,
+    // seconds, default is one hour
+    interval: Option<u64>,
+}
+
+fn peers_agent_versions(
     db: DbCore,
 ) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
-    warp::path!("block" / "latest").map(move || -> WithStatus<Json> {
-        let v = db
-            .fetch_last_stat_block_v2()
-            .map(|(height, events)| BlockStat { height, events });
-        reply::with_status(reply::json(&v), StatusCode::OK)
+    warp::path!("peers" / "agent_versions")
+        .and(warp::query::query())
+        .map(move |params: AgentVersionParams| -> WithStatus<Json> {
+            let until = params.until.map_or_else(SystemTime::now, |secs| {
+                SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+            });
+            let since = match params.since {
+                Some(secs) => SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+                None => until - Duration::from_secs(86400),
+            };
+            let interval = Duration::from_secs(params.interval.unwrap_or(3600));
+            let v = db.fetch_agent_versions(since, until, interval);
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
+}
+
+fn gossip_first_seen(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("gossip" / "first_seen")
+        .and(warp::query::query())
+        .map(move |params: FirstSeenParams| -> WithStatus<Json> {
+            let until = params.until.map_or_else(SystemTime::now, |secs| {
+                SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+            });
+            let since = match params.since {
+                Some(secs) => SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+                None => until - Duration::from_secs(600),
+            };
+            let limit = params.limit.unwrap_or(1000);
+            let v = db
+                .fetch_gossip_first_seen(since, until)
+                .take(limit)
+                .collect::<Vec<_>>();
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
+}
+
+fn gossip_duplicates(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("gossip" / "duplicates")
+        .and(warp::query::query())
+        .map(move |params: FirstSeenParams| -> WithStatus<Json> {
+            let until = params.until.map_or_else(SystemTime::now, |secs| {
+                SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+            });
+            let since = match params.since {
+                Some(secs) => SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+                None => until - Duration::from_secs(600),
+            };
+            let limit = params.limit.unwrap_or(1000);
+            let v = db
+                .fetch_gossip_duplicates(since, until)
+                .take(limit)
+                .collect::<Vec<_>>();
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
+}
+
+#[derive(Deserialize)]
+struct LeaderboardParams {
+    // unix time in seconds, default is one hour before `until`
+    since: Option<u64>,
+    // unix time in seconds, default is now
+    until: Option<u64>,
+    // how many of each, default is 20
+    limit: Option<usize>,
+}
+
+fn leaderboard(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("stats" / "largest")
+        .and(warp::query::query())
+        .map(move |params: LeaderboardParams| -> WithStatus<Json> {
+            let until = params.until.map_or_else(SystemTime::now, |secs| {
+                SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+            });
+            let since = match params.since {
+                Some(secs) => SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+                None => until - Duration::from_secs(3600),
+            };
+            let limit = params.limit.unwrap_or(20);
+            let key = format!(
+                "stats/largest?since={:?}&until={:?}&limit={limit}",
+                params.since, params.until
+            );
+            let v = db
+                .http_cache()
+                .aggregation(&key, || db.fetch_leaderboard(since, until, limit));
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
+}
+
+#[derive(Deserialize)]
+struct ValidationParams {
+    // unix time in seconds, default is one hour before `until`
+    since: Option<u64>,
+    // unix time in seconds, default is now
+    until: Option<u64>,
+}
+
+fn gossip_validation(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("gossip" / "validation")
+        .and(warp::query::query())
+        .map(move |params: ValidationParams| -> WithStatus<Json> {
+            let until = params.until.map_or_else(SystemTime::now, |secs| {
+                SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+            });
+            let since = match params.since {
+                Some(secs) => SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+                None => until - Duration::from_secs(3600),
+            };
+            let key = format!(
+                "gossip/validation?since={:?}&until={:?}",
+                params.since, params.until
+            );
+            let v = db
+                .http_cache()
+                .aggregation(&key, || db.fetch_validation_latency(since, until));
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
+}
+
+#[derive(Deserialize)]
+struct BaselineParams {
+    // unix time in seconds, default is one hour before `until`
+    since: Option<u64>,
+    // unix time in seconds, default is now
+    until: Option<u64>,
+    // how many times bigger or smaller is the deviation, default is 2
+    threshold: Option<f64>,
+}
+
+fn baseline(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("baseline").map(move || -> WithStatus<Json> {
+        match db.baseline() {
+            Some(v) => reply::with_status(reply::json(v), StatusCode::OK),
+            None => {
+                let err = "no baseline, set BASELINE";
+                reply::with_status(reply::json(&err), StatusCode::NOT_FOUND)
+            }
+        }
     })
 }
 
+fn baseline_deviations(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("baseline" / "deviations")
+        .and(warp::query::query())
+        .map(move |params: BaselineParams| -> WithStatus<Json> {
+            let until = params.until.map_or_else(SystemTime::now, |secs| {
+                SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+            });
+            let since = match params.since {
+                Some(secs) => SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+                None => until - Duration::from_secs(3600),
+            };
+            let threshold = params.threshold.unwrap_or(Baseline::THRESHOLD);
+            if threshold.is_nan() || threshold <= 1.0 {
+                let err = "the threshold must be above 1";
+                return reply::with_status(reply::json(&err), StatusCode::BAD_REQUEST);
+            }
+            if db.baseline().is_none() {
+                let err = "no baseline, set BASELINE";
+                return reply::with_status(reply::json(&err), StatusCode::NOT_FOUND);
+            }
+            let key = format!(
+                "baseline/deviations?since={:?}&until={:?}&threshold={threshold}",
+                params.since, params.until
+            );
+            let v = db.http_cache().aggregation(&key, || {
+                db.fetch_baseline_deviations(since, until, threshold)
+            });
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
+}
+
+#[derive(Deserialize)]
+struct KademliaParams {
+    // unix time in seconds, default is one hour before `until`
+    since: Option<u64>,
+    // unix time in seconds, default is now
+    until: Option<u64>,
+}
+
+impl KademliaParams {
+    fn range(&self) -> (SystemTime, SystemTime) {
+        let until = self.until.map_or_else(SystemTime::now, |secs| {
+            SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+        });
+        let since = match self.since {
+            Some(secs) => SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+            None => until - Duration::from_secs(3600),
+        };
+        (since, until)
+    }
+}
+
+fn kademlia_stats(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("stats" / "kademlia")
+        .and(warp::query::query())
+        .map(move |params: KademliaParams| -> WithStatus<Json> {
+            let (since, until) = params.range();
+            let key = format!(
+                "stats/kademlia?since={:?}&until={:?}",
+                params.since, params.until
+            );
+            let v = db
+                .http_cache()
+                .aggregation(&key, || db.fetch_kademlia_report(since, until));
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
+}
+
+fn kademlia_learned(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("kademlia" / "learned")
+        .and(warp::query::query())
+        .map(move |params: KademliaParams| -> WithStatus<Json> {
+            let (since, until) = params.range();
+            let key = format!(
+                "kademlia/learned?since={:?}&until={:?}",
+                params.since, params.until
+            );
+            let v = db
+                .http_cache()
+                .aggregation(&key, || db.fetch_learned_peers(since, until));
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
+}
+
 fn stats_tx(
     db: DbCore,
 ) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
@@ -211,8 +1894,10 @@ impl BlockParams {
 
 fn capnp(
     db: DbCore,
+    tokens: Arc<AccessTokens>,
 ) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
     warp::path!("capnp" / "block" / u32)
+        .and(full_access(tokens))
         .and(warp::query::query())
         .map(move |height, params: BlockParams| -> WithStatus<Json> {
             let v = db.fetch_capnp(height, params.all()).collect::<Vec<_>>();
@@ -222,8 +1907,10 @@ fn capnp(
 
 fn libp2p_ipc(
     db: DbCore,
+    tokens: Arc<AccessTokens>,
 ) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
     warp::path!("libp2p_ipc" / "block" / u32)
+        .and(full_access(tokens))
         .and(warp::query::query())
         .map(move |height, params: BlockParams| -> WithStatus<Json> {
             let v = db.fetch_capnp(height, params.all()).collect::<Vec<_>>();
@@ -233,17 +1920,22 @@ fn libp2p_ipc(
 
 fn libp2p_ipc_all(
     db: DbCore,
+    tokens: Arc<AccessTokens>,
 ) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
-    warp::path!("libp2p_ipc" / "block" / "all").map(move || -> WithStatus<Json> {
-        let v = db.fetch_capnp_all().collect::<Vec<_>>();
-        reply::with_status(reply::json(&v), StatusCode::OK)
-    })
+    warp::path!("libp2p_ipc" / "block" / "all")
+        .and(full_access(tokens))
+        .map(move || -> WithStatus<Json> {
+            let v = db.fetch_capnp_all().collect::<Vec<_>>();
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
 }
 
 fn capnp_latest(
     db: DbCore,
+    tokens: Arc<AccessTokens>,
 ) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
     warp::path!("capnp" / "block" / "latest")
+        .and(full_access(tokens))
         .and(warp::query::query())
         .map(move |params: BlockParams| -> WithStatus<Json> {
             let all = params.all();
@@ -254,8 +1946,10 @@ fn capnp_latest(
 
 fn libp2p_ipc_latest(
     db: DbCore,
+    tokens: Arc<AccessTokens>,
 ) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
     warp::path!("libp2p_ipc" / "block" / "latest")
+        .and(full_access(tokens))
         .and(warp::query::query())
         .map(move |params: BlockParams| -> WithStatus<Json> {
             let all = params.all();
@@ -308,6 +2002,26 @@ fn firewall_stats(
     })
 }
 
+fn manifest(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("manifest").and(warp::query::query()).map(
+        move |params: ExportParams| -> WithStatus<Json> {
+            let mut manifest = db.manifest().clone();
+            manifest.export_redaction = params.redaction;
+            reply::with_status(reply::json(&manifest), StatusCode::OK)
+        },
+    )
+}
+
+fn sinks(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("sinks").map(move || -> WithStatus<Json> {
+        reply::with_status(reply::json(&db.sinks().stats()), StatusCode::OK)
+    })
+}
+
 fn version(
 ) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
     warp::path!("version")
@@ -317,6 +2031,13 @@ fn version(
         })
 }
 
+fn schemas(
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("schemas").map(move || -> WithStatus<Json> {
+        reply::with_status(reply::json(&decode::schema::schemas()), StatusCode::OK)
+    })
+}
+
 fn openapi(
 ) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
     warp::path!("openapi")
@@ -332,6 +2053,8 @@ fn openapi(
 fn routes(
     db: DbCore,
     app: Option<Application>,
+    captures: Arc<Captures>,
+    read_only: bool,
 ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone + Sync + Send + 'static {
     use warp::reply::with;
 
@@ -356,42 +2079,163 @@ fn routes(
         ])
         .build();
 
+    let tokens = Arc::new(AccessTokens::from_env());
+    if tokens.is_open() {
+        log::info!("no API_TOKENS, the api is open");
+    }
+
+    let pseudonyms = pseudonym::from_env();
+    let pseudonymized = pseudonyms.is_some();
+    if pseudonymized {
+        log::info!("the api pseudonymizes the addresses and the peer ids");
+    }
+
     let binary = warp::get()
-        .and(message_bin(db.clone()))
+        .and(
+            message_bin(db.clone(), tokens.clone(), pseudonymized)
+                .or(export_bundle(db.clone(), tokens.clone(), pseudonymized))
+                .or(export_job_result(db.clone(), tokens.clone(), pseudonymized)),
+        )
         .with(with::header("Content-Type", "application/octet-stream"))
         // .with(with::header("Access-Control-Allow-Origin", "*"))
         .with(cors_filter.clone());
 
-    let gets = warp::get().and(
+    let authorized = scope(tokens.clone()).map(|_| ()).untuple_one();
+    let gets = warp::get().and(authorized.clone()).and(
         connection(db.clone())
+            .or(connection_negotiations(db.clone()))
+            .or(connection_stream_ends(db.clone()))
+            .or(connection_protocols(db.clone()))
+            .or(connection_noise(db.clone(), tokens.clone()))
+            .or(connection_pipeline(db.clone()))
+            .or(pipelines(db.clone()))
+            .or(key_injections(db.clone(), tokens.clone()))
+            .or(capture_policy(db.clone()))
+            .or(network_profiles(db.clone()))
+            .or(export_jobs(db.clone()))
+            .or(export_job(db.clone()))
+            .or(peers_geo(db.clone()))
+            .or(peers_nat(db.clone()))
+            .or(stats_ports(db.clone()))
+            .or(freezes(db.clone()))
             .or(connections(db.clone()))
-            .or(message(db.clone()))
-            .or(message_hex(db.clone()))
-            .or(messages(db.clone()))
+            .or(message(db.clone(), tokens.clone()))
+            .or(message_hex(db.clone(), tokens.clone(), pseudonymized))
+            .or(messages(db.clone(), tokens.clone()))
+            .or(captures_messages(captures.clone()))
+            .or(self::captures(captures))
+            .or(decoder_versions(db.clone()))
+            .or(timeline(db.clone()))
+            .or(node_log_get(db.clone(), tokens.clone()))
+            .or(node_status(db.clone()))
+            .or(message_node_status(db.clone()))
+            .or(identity_history(db.clone()))
+            .or(peer_directory(db.clone()))
+            .or(peers_agent_versions(db.clone()))
+            .or(peer_directory_record(db.clone()))
+            .or(subscriptions(db.clone()))
+            .or(peer_subscriptions(db.clone()))
+            .or(sessions(db.clone()))
+            .or(session(db.clone()))
             .or(stats(db.clone()))
             .or(stats_last(db.clone()))
             .or(stats_latest(db.clone()))
             .or(stats_block_v2(db.clone()))
             .or(stats_block_v2_latest(db.clone()))
+            .or(stats_db(db.clone()))
+            .or(stats_activity(db.clone()))
+            .or(peer_consistency(db.clone()))
+            .or(gossip_duplication(db.clone()))
+            .or(gossip_first_seen(db.clone()))
+            .or(gossip_duplicates(db.clone()))
+            .or(gossip_validation(db.clone()))
+            .or(baseline(db.clone()))
+            .or(baseline_deviations(db.clone()))
+            .or(stats_churn(db.clone()))
+            .or(leaderboard(db.clone()))
+            .or(kademlia_stats(db.clone()))
+            .or(kademlia_learned(db.clone()))
+            .or(partial_handshakes(db.clone()))
+            .or(time_beacons(db.clone()))
+            .or(health(db.clone()))
+            .or(health_ring_buffer(db.clone()))
+            .or(verification(db.clone()))
+            .or(time_alignment(db.clone()))
+            .or(stats_layers(db.clone()))
+            .or(stats_framing(db.clone()))
+            .or(capture_triggers(db.clone()))
+            .or(watchdog(db.clone()))
+            .or(anomalies(db.clone()))
+            .or(slo(db.clone()))
+            .or(slo_reports(db.clone()))
             .or(stats_tx(db.clone()))
             .or(stats_tx_latest(db.clone()))
             .or(snark(db.clone()))
-            .or(capnp(db.clone()))
-            .or(libp2p_ipc(db.clone()))
-            .or(capnp_latest(db.clone()))
-            .or(libp2p_ipc_latest(db.clone()))
-            .or(libp2p_ipc_all(db))
+            .or(capnp(db.clone(), tokens.clone()))
+            .or(libp2p_ipc(db.clone(), tokens.clone()))
+            .or(capnp_latest(db.clone(), tokens.clone()))
+            .or(libp2p_ipc_latest(db.clone(), tokens.clone()))
+            .or(libp2p_ipc_all(db.clone(), tokens.clone()))
+            .or(manifest(db.clone()))
+            .or(sinks(db.clone()))
             .or(firewall_stats(app.clone()))
+            .or(grafana_health())
+            .or(log_filter())
+            .or(schemas())
             .or(version().or(openapi())),
     );
-    let posts =
-        warp::post().and(firewall_whitelist_set(app.clone()).or(firewall_whitelist_clear(app)));
+    // freezing the view changes nothing in the capture, any token may,
+    // so may the exports, redacted as the scope of the token enforces
+    let views = warp::post().and(writable(read_only)).and(authorized).and(
+        freeze(db.clone())
+            .or(freeze_release(db.clone()))
+            .or(export_job_create(db.clone(), tokens.clone()))
+            .or(export_job_cancel(db.clone())),
+    );
+    // the posts control the debugger
+    let posts = warp::post()
+        .and(writable(read_only))
+        .and(full_access(tokens))
+        .and(
+            firewall_whitelist_set(app.clone())
+                .or(firewall_whitelist_clear(app))
+                .or(node_log_push(db.clone()))
+                .or(health_ring_buffer_pause(db.clone()))
+                .or(messages_bulk(db.clone(), tokens.clone()))
+                .or(redecode(db.clone()))
+                .or(key_injection(db.clone()))
+                .or(capture_policy_set(db.clone()))
+                .or(log_filter_set())
+                .or(grafana_search())
+                .or(grafana_query(db)),
+        );
 
-    gets.or(posts)
+    gets.or(views)
+        .or(posts)
+        .then(move |reply| pseudonymize(pseudonyms.clone(), reply))
         .with(with::header("Content-Type", "application/json"))
         // .with(with::header("Access-Control-Allow-Origin", "*"))
         .with(cors_filter)
         .or(binary)
+        .recover(access_denied)
+}
+
+/// The routes of each of the mounted captures under `/capture/{name}`,
+/// only the `GET` ones, the `POST` ones are `405`.
+fn mounted(captures: &Captures) -> BoxedFilter<(Box<dyn Reply>,)> {
+    let mut filter = warp::any()
+        .and_then(|| async { Err::<Box<dyn Reply>, _>(warp::reject::not_found()) })
+        .boxed();
+    for (name, core) in captures.iter() {
+        filter = warp::path("capture")
+            .and(warp::path(name.to_owned()))
+            .and(routes(core.clone(), None, Arc::default(), true))
+            .map(|reply| Box::new(reply) as Box<dyn Reply>)
+            .or(filter)
+            .unify()
+            .boxed();
+    }
+    filter
 }
 
 pub fn spawn<P, Q, R>(
@@ -428,7 +2272,8 @@ where
     };
     log::info!("using db {}", path.as_ref().display());
     let addr = ([0, 0, 0, 0], port);
-    let routes = routes(db.core(), app);
+    let captures = Arc::new(Captures::from_env());
+    let routes = mounted(&captures).or(routes(db.core(), app, captures, false));
     let shutdown = async move {
         rx.await.expect("corresponding sender should exist");
         log::info!("terminating http server...");
@@ -447,3 +2292,31 @@ where
     let callback = move || tx.send(()).expect("corresponding receiver should exist");
     (db, callback, handle)
 }
+
+#[cfg(test)]
+#[test]
+fn mounted_read_only() {
+    use tokio::runtime::Runtime;
+
+    let dir = temp_dir::TempDir::new().unwrap();
+    drop(DbFacade::open(dir.path()).unwrap());
+    let mut captures = Captures::default();
+    captures.mount("node", dir.path()).unwrap();
+    let filter = mounted(&captures);
+
+    let rt = Runtime::new().unwrap();
+    let status = |method: &str, path: &str| {
+        let request = warp::test::request().method(method).path(path);
+        rt.block_on(request.reply(&filter)).status()
+    };
+    assert_eq!(status("GET", "/capture/node/connections"), StatusCode::OK);
+    assert_eq!(
+        status("POST", "/capture/node/freeze"),
+        StatusCode::METHOD_NOT_ALLOWED
+    );
+    assert_eq!(
+        status("POST", "/capture/node/export/jobs"),
+        StatusCode::METHOD_NOT_ALLOWED
+    );
+    assert!(captures.get("node").unwrap().freezes().all().is_empty());
+}
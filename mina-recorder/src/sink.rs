@@ -0,0 +1,422 @@
+use std::{
+    fs::File,
+    io::{self, Write, BufWriter},
+    net::SocketAddr,
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use libp2p_core::PeerId;
+use parking_lot::RwLock;
+use radiation::{Absorb, Emit};
+use serde::{Serialize, Serializer};
+
+use crate::{
+    event::ConnectionInfo,
+    chunk::EncryptionStatus,
+    custom_coding,
+    meshsub_stats::Event,
+    database::{ConnectionStats, LayerStats, StreamId, StreamKind},
+    forward::Forwarder,
+    seal::Recipient,
+    flows::{FlowSink, FlowRotation},
+};
+
+/// What the recorder outputs. Connection ids are assigned by the recorder,
+/// a sink that stores events may assign its own.
+#[derive(Absorb, Emit, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SinkEvent {
+    Connection(ConnectionEvent),
+    Close(u64),
+    Update(UpdateEvent),
+    PeerIdentity(PeerIdentityEvent),
+    Raw(ChunkEvent),
+    Message(MessageEvent),
+    Stats(StatsEvent),
+    StatsBlockV2(Event),
+    StatsTx(StatsTxEvent),
+    Superseded(SupersededEvent),
+}
+
+#[derive(Absorb, Emit, Serialize)]
+pub struct ConnectionEvent {
+    pub id: u64,
+    pub info: ConnectionInfo,
+    pub incoming: bool,
+    pub alias: String,
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub timestamp: SystemTime,
+}
+
+#[derive(Absorb, Emit, Serialize)]
+pub struct UpdateEvent {
+    pub id: u64,
+    pub stats: ConnectionStats,
+    pub incoming: bool,
+    /// the total of the direction, not the increment
+    pub layers: LayerStats,
+}
+
+#[derive(Absorb, Emit, Serialize)]
+pub struct PeerIdentityEvent {
+    pub id: u64,
+    #[custom_absorb(custom_coding::peer_id_absorb)]
+    #[custom_emit(custom_coding::peer_id_emit)]
+    pub peer_id: PeerId,
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub timestamp: SystemTime,
+}
+
+/// The connection `id` is discarded after simultaneous connect in favor of `by`.
+#[derive(Absorb, Emit, Serialize)]
+pub struct SupersededEvent {
+    pub id: u64,
+    pub by: u64,
+}
+
+#[derive(Absorb, Emit, Serialize)]
+pub struct ChunkEvent {
+    pub id: u64,
+    pub encryption_status: EncryptionStatus,
+    pub incoming: bool,
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub time: SystemTime,
+    #[serde(serialize_with = "hex_bytes")]
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Absorb, Emit, Serialize)]
+pub struct MessageEvent {
+    pub id: u64,
+    /// position within the connection, see `Message::seq`
+    pub seq: u64,
+    pub stream_id: StreamId,
+    pub stream_kind: StreamKind,
+    pub incoming: bool,
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub time: SystemTime,
+    #[serde(serialize_with = "hex_bytes")]
+    pub bytes: Vec<u8>,
+}
+
+/// Block statistics, already encoded.
+#[derive(Absorb, Emit, Serialize)]
+pub struct StatsEvent {
+    pub height: u32,
+    #[custom_absorb(custom_coding::addr_absorb)]
+    #[custom_emit(custom_coding::addr_emit)]
+    pub node_address: SocketAddr,
+    #[serde(serialize_with = "hex_bytes")]
+    pub value: Vec<u8>,
+}
+
+/// Transaction statistics, already encoded.
+#[derive(Absorb, Emit, Serialize)]
+pub struct StatsTxEvent {
+    pub height: u32,
+    #[serde(serialize_with = "hex_bytes")]
+    pub value: Vec<u8>,
+}
+
+fn hex_bytes<S>(v: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&hex::encode(v))
+}
+
+/// Output of the recorder. Each sink runs in its own thread and has its own queue,
+/// a slow sink loses its own events and does not slow down the recorder or other sinks.
+pub trait Sink: Send + 'static {
+    fn send(&mut self, event: &SinkEvent) -> io::Result<()>;
+
+    /// Called when the queue is empty.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Sink for Box<dyn Sink> {
+    fn send(&mut self, event: &SinkEvent) -> io::Result<()> {
+        (**self).send(event)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (**self).flush()
+    }
+}
+
+/// Discards everything, useful to measure the capture alone.
+pub struct NullSink;
+
+impl Sink for NullSink {
+    fn send(&mut self, event: &SinkEvent) -> io::Result<()> {
+        let _ = event;
+        Ok(())
+    }
+}
+
+/// Writes each event as a json line.
+pub struct NdjsonSink(BufWriter<File>);
+
+impl NdjsonSink {
+    pub fn create(path: &PathBuf) -> io::Result<Self> {
+        File::options()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map(BufWriter::new)
+            .map(NdjsonSink)
+    }
+}
+
+impl Sink for NdjsonSink {
+    fn send(&mut self, event: &SinkEvent) -> io::Result<()> {
+        serde_json::to_writer(&mut self.0, event)?;
+        self.0.write_all(b"\n")
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Sinks configured by `SINKS`, comma separated:
+/// `database`, `null`, `ndjson:<path>`, `forward:<host>:<port>`, `flows:<dir>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SinkConfig {
+    Database,
+    Null,
+    Ndjson(PathBuf),
+    Forward(String),
+    Flows(PathBuf, FlowRotation),
+}
+
+impl FromStr for SinkConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "database" => Ok(SinkConfig::Database),
+            None if s == "null" => Ok(SinkConfig::Null),
+            Some(("ndjson", path)) => Ok(SinkConfig::Ndjson(path.into())),
+            Some(("forward", remote)) => Ok(SinkConfig::Forward(remote.to_owned())),
+            Some(("flows", dir)) => Ok(SinkConfig::Flows(dir.into(), FlowRotation::default())),
+            _ => Err(s.to_owned()),
+        }
+    }
+}
+
+impl SinkConfig {
+    pub fn parse_list(s: &str) -> Result<Vec<Self>, String> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::parse)
+            .collect()
+    }
+
+    fn name(&self) -> String {
+        match self {
+            SinkConfig::Database => "database".to_owned(),
+            SinkConfig::Null => "null".to_owned(),
+            SinkConfig::Ndjson(path) => format!("ndjson:{}", path.display()),
+            SinkConfig::Forward(remote) => format!("forward:{remote}"),
+            SinkConfig::Flows(dir, _) => format!("flows:{}", dir.display()),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    queued: AtomicU64,
+    sent: AtomicU64,
+    dropped: AtomicU64,
+    failed: AtomicU64,
+}
+
+struct SinkHandle {
+    name: String,
+    tx: mpsc::SyncSender<Arc<SinkEvent>>,
+    counters: Arc<Counters>,
+}
+
+#[derive(Serialize)]
+pub struct SinkStats {
+    pub name: String,
+    /// in the queue, not yet handled by the sink
+    pub queued: u64,
+    pub sent: u64,
+    /// the queue was full
+    pub dropped: u64,
+    /// the sink returned an error
+    pub failed: u64,
+}
+
+/// All outputs of the recorder. The database is not a queued sink,
+/// ids are assigned when the data is stored, so it is a flag.
+pub struct Sinks {
+    database: AtomicBool,
+    handles: RwLock<Vec<SinkHandle>>,
+}
+
+impl Default for Sinks {
+    fn default() -> Self {
+        Sinks {
+            database: AtomicBool::new(true),
+            handles: RwLock::default(),
+        }
+    }
+}
+
+impl Sinks {
+    const QUEUE_SIZE: usize = 0x10000;
+
+    const FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+
+    /// Replace the sinks, the database is enabled only if listed.
+    pub fn configure(&self, configs: &[SinkConfig]) -> io::Result<()> {
+        let mut handles = vec![];
+        for config in configs {
+            let name = config.name();
+            match config {
+                SinkConfig::Database => continue,
+                SinkConfig::Null => handles.push(Self::spawn(name, NullSink)),
+                SinkConfig::Ndjson(path) => {
+                    handles.push(Self::spawn(name, NdjsonSink::create(path)?))
+                }
+                SinkConfig::Forward(remote) => {
+                    let forwarder = Forwarder::new(remote.clone()).sealed(Recipient::from_env());
+                    handles.push(Self::spawn(name, forwarder))
+                }
+                SinkConfig::Flows(dir, rotation) => {
+                    handles.push(Self::spawn(name, FlowSink::create(dir.clone(), *rotation)?))
+                }
+            }
+        }
+        let database = configs.contains(&SinkConfig::Database);
+        self.database.store(database, Ordering::SeqCst);
+        *self.handles.write() = handles;
+
+        Ok(())
+    }
+
+    /// Add a custom sink.
+    pub fn add<S>(&self, name: String, sink: S)
+    where
+        S: Sink,
+    {
+        self.handles.write().push(Self::spawn(name, sink));
+    }
+
+    fn spawn<S>(name: String, mut sink: S) -> SinkHandle
+    where
+        S: Sink,
+    {
+        let (tx, rx) = mpsc::sync_channel::<Arc<SinkEvent>>(Self::QUEUE_SIZE);
+        let counters = Arc::new(Counters::default());
+        thread::spawn({
+            let name = name.clone();
+            let counters = counters.clone();
+            move || loop {
+                match rx.recv_timeout(Self::FLUSH_INTERVAL) {
+                    Ok(event) => {
+                        counters.queued.fetch_sub(1, Ordering::Relaxed);
+                        match sink.send(&event) {
+                            Ok(()) => {
+                                counters.sent.fetch_add(1, Ordering::Relaxed);
+                            }
+                            Err(err) => {
+                                let failed = counters.failed.fetch_add(1, Ordering::Relaxed) + 1;
+                                if failed.is_power_of_two() {
+                                    log::error!("sink {name} failed {failed} times: {err}");
+                                }
+                            }
+                        }
+                    }
+                    Err(mpsc::RecvTimeoutError::Timeout) => {
+                        if let Err(err) = sink.flush() {
+                            log::error!("sink {name} flush: {err}");
+                        }
+                    }
+                    Err(mpsc::RecvTimeoutError::Disconnected) => {
+                        sink.flush().unwrap_or_default();
+                        break;
+                    }
+                }
+            }
+        });
+
+        SinkHandle { name, tx, counters }
+    }
+
+    pub fn database(&self) -> bool {
+        self.database.load(Ordering::Relaxed)
+    }
+
+    /// The event is created only if there is a sink.
+    pub fn send<F>(&self, f: F)
+    where
+        F: FnOnce() -> SinkEvent,
+    {
+        let handles = self.handles.read();
+        if handles.is_empty() {
+            return;
+        }
+        let event = Arc::new(f());
+        for handle in handles.iter() {
+            let counters = &handle.counters;
+            counters.queued.fetch_add(1, Ordering::Relaxed);
+            if handle.tx.try_send(event.clone()).is_err() {
+                counters.queued.fetch_sub(1, Ordering::Relaxed);
+                let dropped = counters.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                if dropped.is_power_of_two() {
+                    log::warn!("sink {} is too slow, dropped {dropped} events", handle.name);
+                }
+            }
+        }
+    }
+
+    pub fn stats(&self) -> Vec<SinkStats> {
+        let handles = self.handles.read();
+        handles
+            .iter()
+            .map(|handle| SinkStats {
+                name: handle.name.clone(),
+                queued: handle.counters.queued.load(Ordering::Relaxed),
+                sent: handle.counters.sent.load(Ordering::Relaxed),
+                dropped: handle.counters.dropped.load(Ordering::Relaxed),
+                failed: handle.counters.failed.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn parse_sink_config() {
+    let v =
+        SinkConfig::parse_list("database, ndjson:/tmp/a.ndjson,forward:10.0.0.2:8100,flows:/tmp/f")
+            .unwrap();
+    assert_eq!(
+        v,
+        [
+            SinkConfig::Database,
+            SinkConfig::Ndjson("/tmp/a.ndjson".into()),
+            SinkConfig::Forward("10.0.0.2:8100".to_owned()),
+            SinkConfig::Flows("/tmp/f".into(), FlowRotation::default()),
+        ]
+    );
+    assert!(SinkConfig::parse_list("kafka").is_err());
+}
@@ -0,0 +1,253 @@
+use std::{
+    env,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+
+use crate::meshsub_stats::Hash;
+
+/// The share of the blocks which must propagate within the time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Objective {
+    /// from 0 to 100
+    pub percent: f64,
+    pub within: Duration,
+}
+
+/// Propagation objectives and the length of the reporting period.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SloConfig {
+    pub objectives: Vec<Objective>,
+    pub period: Duration,
+}
+
+impl Default for SloConfig {
+    fn default() -> Self {
+        SloConfig {
+            objectives: vec![Objective {
+                percent: 95.0,
+                within: Duration::from_secs(5),
+            }],
+            period: Duration::from_secs(3600),
+        }
+    }
+}
+
+impl FromStr for SloConfig {
+    type Err = String;
+
+    /// Comma separated objectives `percent:seconds`, optionally the period in seconds,
+    /// like `95:5,99:10,period:3600`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = SloConfig {
+            objectives: vec![],
+            ..SloConfig::default()
+        };
+        for item in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (name, value) = item.split_once(':').ok_or_else(|| item.to_owned())?;
+            let seconds = value
+                .parse::<f64>()
+                .ok()
+                .filter(|v| v.is_finite() && *v > 0.0)
+                .ok_or_else(|| item.to_owned())?;
+            if name == "period" {
+                config.period = Duration::from_secs_f64(seconds.max(1.0));
+                continue;
+            }
+            let percent = name
+                .parse::<f64>()
+                .ok()
+                .filter(|v| *v > 0.0 && *v <= 100.0)
+                .ok_or_else(|| item.to_owned())?;
+            config.objectives.push(Objective {
+                percent,
+                within: Duration::from_secs_f64(seconds),
+            });
+        }
+        if config.objectives.is_empty() {
+            config.objectives = SloConfig::default().objectives;
+        }
+        Ok(config)
+    }
+}
+
+impl SloConfig {
+    /// `PROPAGATION_SLO`, the default is `95:5,period:3600`.
+    pub fn from_env() -> Self {
+        match env::var("PROPAGATION_SLO") {
+            Ok(s) => s.parse().unwrap_or_else(|item| {
+                log::error!("bad propagation slo {item}, use default");
+                SloConfig::default()
+            }),
+            Err(_) => SloConfig::default(),
+        }
+    }
+
+    /// The last `count` finished periods, the latest first,
+    /// the periods are aligned to the unix epoch.
+    pub fn periods(&self, now: SystemTime, count: usize) -> Vec<(SystemTime, SystemTime)> {
+        let period = self.period.as_secs().max(1);
+        let now = now
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let end = now - now % period;
+        (0..count as u64)
+            .map_while(|i| {
+                let until = end.checked_sub(i * period)?;
+                let since = until.checked_sub(period)?;
+                let time = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+                Some((time(since), time(until)))
+            })
+            .collect()
+    }
+}
+
+/// How long the block propagated, the recorder measures from the first local observation,
+/// the aggregator from the first observation in the network.
+pub struct Sample {
+    pub hash: Hash,
+    pub height: u32,
+    /// the first observation
+    pub time: SystemTime,
+    pub latency: Duration,
+    /// the slowest node or peer, the propagation ended there
+    pub node: String,
+}
+
+#[derive(Serialize)]
+pub struct SloReport {
+    pub since: SystemTime,
+    pub until: SystemTime,
+    pub blocks: usize,
+    pub objectives: Vec<ObjectiveReport>,
+}
+
+#[derive(Serialize)]
+pub struct ObjectiveReport {
+    pub percent: f64,
+    pub within_secs: f64,
+    /// blocks propagated within the time
+    pub met: usize,
+    /// the share of such blocks in percent, `None` if there are no blocks
+    pub observed_percent: Option<f64>,
+    /// the latency of the `percent` percentile
+    pub percentile_secs: Option<f64>,
+    pub ok: bool,
+    /// the blocks which propagated longer, the slowest first
+    pub violations: Vec<Violation>,
+}
+
+#[derive(Serialize)]
+pub struct Violation {
+    pub hash: Hash,
+    pub height: u32,
+    pub time: SystemTime,
+    pub latency_secs: f64,
+    pub node: String,
+}
+
+impl SloReport {
+    /// The samples first observed outside the range are ignored.
+    pub fn build<I>(config: &SloConfig, samples: I, since: SystemTime, until: SystemTime) -> Self
+    where
+        I: IntoIterator<Item = Sample>,
+    {
+        let mut samples = samples
+            .into_iter()
+            .filter(|sample| (since..until).contains(&sample.time))
+            .collect::<Vec<_>>();
+        samples.sort_by(|a, b| b.latency.cmp(&a.latency));
+
+        let blocks = samples.len();
+        let objectives = config
+            .objectives
+            .iter()
+            .map(|&Objective { percent, within }| {
+                let violations = samples
+                    .iter()
+                    .take_while(|sample| sample.latency > within)
+                    .map(|sample| Violation {
+                        hash: sample.hash,
+                        height: sample.height,
+                        time: sample.time,
+                        latency_secs: sample.latency.as_secs_f64(),
+                        node: sample.node.clone(),
+                    })
+                    .collect::<Vec<_>>();
+                let met = blocks - violations.len();
+                let observed_percent = (blocks != 0).then(|| met as f64 * 100.0 / blocks as f64);
+                // the samples are sorted from the slowest
+                let percentile_secs = (blocks != 0).then(|| {
+                    let rank = ((percent / 100.0 * blocks as f64).ceil() as usize).clamp(1, blocks);
+                    samples[blocks - rank].latency.as_secs_f64()
+                });
+                ObjectiveReport {
+                    percent,
+                    within_secs: within.as_secs_f64(),
+                    met,
+                    observed_percent,
+                    percentile_secs,
+                    ok: observed_percent.map_or(true, |observed| observed >= percent),
+                    violations,
+                }
+            })
+            .collect();
+
+        SloReport {
+            since,
+            until,
+            blocks,
+            objectives,
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn slo_report() {
+    let config = "90:2,50:1.5,period:60".parse::<SloConfig>().unwrap();
+    assert_eq!(config.objectives.len(), 2);
+    assert_eq!(config.period, Duration::from_secs(60));
+    assert!("95".parse::<SloConfig>().is_err());
+    assert!("120:5".parse::<SloConfig>().is_err());
+    assert_eq!("".parse::<SloConfig>().unwrap(), SloConfig::default());
+
+    let since = SystemTime::UNIX_EPOCH + Duration::from_secs(1_675_166_400);
+    let until = since + Duration::from_secs(60);
+    let periods = config.periods(until + Duration::from_secs(30), 2);
+    assert_eq!(
+        periods,
+        [(since, until), (since - Duration::from_secs(60), since)]
+    );
+
+    let samples = (0..10u8).map(|i| Sample {
+        hash: Hash([i; 32]),
+        height: 100 + i as u32,
+        time: since + Duration::from_secs(i as u64 * 6),
+        latency: Duration::from_millis(i as u64 * 300),
+        node: format!("10.0.0.{i}"),
+    });
+    // the last block is outside the range
+    let samples = samples.chain(Some(Sample {
+        hash: Hash([0xff; 32]),
+        height: 110,
+        time: until,
+        latency: Duration::from_secs(100),
+        node: String::new(),
+    }));
+    let report = SloReport::build(&config, samples, since, until);
+    assert_eq!(report.blocks, 10);
+    let strict = &report.objectives[0];
+    // 2.1, 2.4 and 2.7 seconds
+    assert_eq!(strict.met, 7);
+    assert!(!strict.ok);
+    assert_eq!(strict.violations[0].height, 109);
+    assert_eq!(strict.violations[0].node, "10.0.0.9");
+    assert_eq!(strict.percentile_secs, Some(2.4));
+    let median = &report.objectives[1];
+    assert_eq!((median.met, median.ok), (6, true));
+    assert_eq!(median.percentile_secs, Some(1.2));
+}
@@ -0,0 +1,150 @@
+use std::io;
+
+use radiation::{Absorb, Emit};
+use serde::Serialize;
+
+/// The parameters the kernel negotiated for the tcp connection,
+/// they often explain the throughput seen in the timeline of the payload.
+#[derive(Default, Clone, Debug, PartialEq, Eq, Absorb, Emit, Serialize)]
+pub struct TcpParams {
+    /// maximum segment size of the sending and the receiving side
+    pub snd_mss: u32,
+    pub rcv_mss: u32,
+    /// the window scale shift of each side, zero if the scaling is not negotiated
+    pub snd_wscale: u8,
+    pub rcv_wscale: u8,
+    pub timestamps: bool,
+    pub sack: bool,
+    pub ecn: bool,
+    /// the congestion control algorithm, like `cubic` or `bbr`
+    pub congestion: String,
+    /// smoothed round trip time when the connection is recorded
+    pub rtt_us: u32,
+    pub pmtu: u32,
+}
+
+impl TcpParams {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    // `struct tcp_info` of `linux/tcp.h`, only the fields of the oldest kernels
+    fn parse(info: &[u8], congestion: String) -> Option<Self> {
+        const TCPI_OPT_TIMESTAMPS: u8 = 1;
+        const TCPI_OPT_SACK: u8 = 2;
+        const TCPI_OPT_WSCALE: u8 = 4;
+        const TCPI_OPT_ECN: u8 = 8;
+
+        let u32_at = |offset: usize| {
+            let bytes = info.get(offset..(offset + 4))?.try_into().ok()?;
+            Some(u32::from_ne_bytes(bytes))
+        };
+        let options = *info.get(5)?;
+        let wscale = *info.get(6)?;
+        let scaled = options & TCPI_OPT_WSCALE != 0;
+        Some(TcpParams {
+            snd_mss: u32_at(16)?,
+            rcv_mss: u32_at(20)?,
+            snd_wscale: if scaled { wscale & 0xf } else { 0 },
+            rcv_wscale: if scaled { wscale >> 4 } else { 0 },
+            timestamps: options & TCPI_OPT_TIMESTAMPS != 0,
+            sack: options & TCPI_OPT_SACK != 0,
+            ecn: options & TCPI_OPT_ECN != 0,
+            congestion,
+            rtt_us: u32_at(68)?,
+            pmtu: u32_at(60)?,
+        })
+    }
+}
+
+/// Reads the parameters of the tcp socket `fd` of the process, the socket is duplicated
+/// by `pidfd_getfd`, so it needs linux 5.6 and the permission to ptrace the process.
+pub fn tcp_params(pid: u32, fd: u32) -> io::Result<TcpParams> {
+    let fd = Fd::of_process(pid, fd)?;
+
+    let mut info = [0u8; 104];
+    let len = fd.getsockopt(libc::TCP_INFO, &mut info)?;
+    let mut congestion = [0u8; 16];
+    // the socket might be not tcp at all, the `TCP_INFO` already failed then
+    let congestion = match fd.getsockopt(libc::TCP_CONGESTION, &mut congestion) {
+        Ok(len) => {
+            let name = &congestion[..len];
+            let end = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+            String::from_utf8_lossy(&name[..end]).into_owned()
+        }
+        Err(_) => String::new(),
+    };
+    TcpParams::parse(&info[..len], congestion)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "short tcp_info"))
+}
+
+struct Fd(i32);
+
+impl Fd {
+    fn of_process(pid: u32, fd: u32) -> io::Result<Self> {
+        let pidfd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid as libc::pid_t, 0) };
+        if pidfd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let pidfd = Fd(pidfd as i32);
+        let fd = unsafe { libc::syscall(libc::SYS_pidfd_getfd, pidfd.0, fd as i32, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Fd(fd as i32))
+    }
+
+    fn getsockopt(&self, name: i32, buf: &mut [u8]) -> io::Result<usize> {
+        let mut len = buf.len() as libc::socklen_t;
+        let ret = unsafe {
+            libc::getsockopt(
+                self.0,
+                libc::IPPROTO_TCP,
+                name,
+                buf.as_mut_ptr() as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok((len as usize).min(buf.len()))
+    }
+}
+
+impl Drop for Fd {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.0) };
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn tcp_info() {
+    use std::{
+        net::{TcpListener, TcpStream},
+        os::unix::io::AsRawFd,
+    };
+
+    let mut info = [0; 104];
+    info[5] = 4 | 2;
+    info[6] = 0x7 | (0x9 << 4);
+    info[16..20].copy_from_slice(&1448u32.to_ne_bytes());
+    let params = TcpParams::parse(&info, "bbr".to_owned()).unwrap();
+    assert_eq!((params.snd_wscale, params.rcv_wscale), (7, 9));
+    assert_eq!(params.snd_mss, 1448);
+    assert!(params.sack && !params.timestamps);
+    assert_eq!(TcpParams::parse(&info[..40], String::new()), None);
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let pid = std::process::id();
+    let params = match tcp_params(pid, stream.as_raw_fd() as u32) {
+        Ok(v) => v,
+        // the kernel is too old or the sandbox forbids `pidfd_getfd`
+        Err(err) => return log::warn!("skip: {err}"),
+    };
+    assert_ne!(params.snd_mss, 0);
+    assert!(!params.congestion.is_empty());
+    assert!(tcp_params(pid, listener.as_raw_fd() as u32 + 100).is_err());
+}
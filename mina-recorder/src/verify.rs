@@ -0,0 +1,352 @@
+use std::{
+    env, fs, process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+
+use crate::{
+    chunk::EncryptionStatus,
+    database::{
+        ConnectionId, DbCore, DbError, DbFacade, Message, RandomnessDatabase, StreamKind,
+        Verification,
+    },
+    decode::DECODER_VERSION,
+    event::EventMetadata,
+    kube::KubeMetadata,
+    peer_names::PeerNamesConfig,
+    recorder::P2pRecorder,
+};
+
+/// How often and how much of the capture is verified.
+#[derive(Debug, Clone)]
+pub struct VerifyConfig {
+    pub interval: Duration,
+    /// the connections picked at random each time
+    pub connections: u32,
+}
+
+impl VerifyConfig {
+    /// `SELF_VERIFY_INTERVAL` seconds, disabled if it is not set,
+    /// and `SELF_VERIFY_CONNECTIONS`, 4 by default.
+    pub fn from_env() -> Option<Self> {
+        let var = |name| env::var(name).ok().and_then(|s| s.parse::<u64>().ok());
+        let interval = var("SELF_VERIFY_INTERVAL")?.max(1);
+        Some(VerifyConfig {
+            interval: Duration::from_secs(interval),
+            connections: var("SELF_VERIFY_CONNECTIONS").unwrap_or(4) as u32,
+        })
+    }
+}
+
+/// The verifications in the range, the divergent ones in full.
+#[derive(Debug, Serialize)]
+pub struct VerificationReport {
+    pub since: SystemTime,
+    pub until: SystemTime,
+    pub connections: u64,
+    pub messages: u64,
+    pub divergent_connections: u64,
+    pub divergent_messages: u64,
+    pub divergences: Vec<Verification>,
+}
+
+impl VerificationReport {
+    pub fn new<I>(since: SystemTime, until: SystemTime, verifications: I) -> Self
+    where
+        I: IntoIterator<Item = Verification>,
+    {
+        let mut report = VerificationReport {
+            since,
+            until,
+            connections: 0,
+            messages: 0,
+            divergent_connections: 0,
+            divergent_messages: 0,
+            divergences: vec![],
+        };
+        for v in verifications {
+            report.connections += 1;
+            report.messages += v.messages;
+            if v.divergent != 0 {
+                report.divergent_connections += 1;
+                report.divergent_messages += v.divergent;
+                report.divergences.push(v);
+            }
+        }
+        report
+    }
+}
+
+struct Stored {
+    id: u64,
+    msg: Message,
+    bytes: Vec<u8>,
+}
+
+/// The messages to compare, with the gossip deduplication the duplicates are stored only
+/// in the connection which brought them first, so the gossip is not compared.
+fn stored(core: &DbCore, cn: ConnectionId) -> Result<Vec<Stored>, DbError> {
+    core.fetch_connection_messages(cn)
+        .filter(|(_, msg)| !(core.gossip_dedup() && msg.stream_kind == StreamKind::Meshsub))
+        .map(|(id, msg)| {
+            let bytes = core.fetch_blob(msg.connection_id, msg.offset)?;
+            Ok(Stored { id, msg, bytes })
+        })
+        .collect()
+}
+
+/// What is different, `None` if nothing is.
+fn diverges(original: &Stored, again: &Stored) -> Option<String> {
+    let (a, b) = (&original.msg, &again.msg);
+    let id = original.id;
+    if (a.stream_id, a.stream_kind, a.incoming) != (b.stream_id, b.stream_kind, b.incoming) {
+        return Some(format!(
+            "message {id}: {} {} on stream {}, derived again {} {} on stream {}",
+            a.incoming, a.stream_kind, a.stream_id, b.incoming, b.stream_kind, b.stream_id
+        ));
+    }
+    if original.bytes != again.bytes {
+        return Some(format!(
+            "message {id}: the payload of {} bytes differs, derived again {} bytes",
+            original.bytes.len(),
+            again.bytes.len()
+        ));
+    }
+    // the older decoders might see other types in the same payload
+    if a.decoder_version == DECODER_VERSION && a.brief != b.brief {
+        return Some(format!(
+            "message {id}: the types {:?}, derived again {:?}",
+            a.brief, b.brief
+        ));
+    }
+    None
+}
+
+/// Decrypts and decodes again the raw chunks of the connection in the scratch database,
+/// like the recorder did, and compares the messages with the stored ones. The difference
+/// means the pipeline is not deterministic or the storage is corrupted. `None` if there is
+/// nothing to verify: the connection is still open, it is decrypted again with the injected
+/// keys, it has no raw chunks or they are redacted.
+pub fn verify_connection(
+    core: &DbCore,
+    cn_id: ConnectionId,
+) -> Result<Option<Verification>, DbError> {
+    if !core.manifest().capture_redaction.is_none() {
+        return Ok(None);
+    }
+    let cn = core.fetch_connection(cn_id.0)?;
+    if cn.timestamp_close == SystemTime::UNIX_EPOCH || cn.superseded_by.is_some() {
+        return Ok(None);
+    }
+    let chunks = core
+        .fetch_chunks(cn_id)
+        .filter(|(_, header, _)| matches!(header.encryption_status, EncryptionStatus::Raw))
+        .collect::<Vec<_>>();
+    if chunks.is_empty() {
+        return Ok(None);
+    }
+
+    // only the exports of the scratch database would be there
+    let dir = env::temp_dir().join(format!("mina-verify-{}-{cn_id}", process::id()));
+    let scratch = DbFacade::open_scratch(&dir)?;
+    // the noise decoder looks up the secret keys among the randomness
+    for bytes in core.iterate_randomness() {
+        scratch.add_randomness(bytes.into_vec())?;
+    }
+    let scratch_core = scratch.core();
+    let mut recorder = P2pRecorder::with_options(
+        scratch,
+        false,
+        None,
+        0,
+        PeerNamesConfig::default(),
+        KubeMetadata::default(),
+    );
+    let metadata = |time| EventMetadata {
+        id: cn.info.clone(),
+        time,
+        better_time: time,
+        duration: Duration::ZERO,
+    };
+    recorder.on_alias(cn.info.pid, cn.alias.clone());
+    recorder.on_connect::<true>(cn.incoming, metadata(cn.timestamp), 0, String::new());
+    for (_, header, bytes) in &chunks {
+        recorder.on_data(header.incoming, metadata(header.time), 0, bytes.clone());
+    }
+    recorder.on_disconnect(metadata(cn.timestamp_close), 0);
+    drop(recorder);
+
+    let original = stored(core, cn_id);
+    let again = stored(&scratch_core, ConnectionId(0));
+    drop(scratch_core);
+    fs::remove_dir_all(&dir).unwrap_or_default();
+    let (original, again) = (original?, again?);
+
+    let mut divergent = 0;
+    let mut detail = None;
+    for (original, again) in original.iter().zip(&again) {
+        if let Some(d) = diverges(original, again) {
+            divergent += 1;
+            detail = detail.or(Some(d));
+        }
+    }
+    if let Some(missing) = original.get(again.len()) {
+        detail = detail.or_else(|| Some(format!("message {} is not derived again", missing.id)));
+    }
+    if let Some(extra) = again.get(original.len()) {
+        let (kind, stream) = (extra.msg.stream_kind, extra.msg.stream_id);
+        detail = detail.or_else(|| {
+            Some(format!(
+                "the extra message derived again, {kind} on stream {stream}"
+            ))
+        });
+    }
+    divergent += original.len().abs_diff(again.len()) as u64;
+
+    Ok(Some(Verification {
+        timestamp: SystemTime::now(),
+        connection_id: cn_id,
+        chunks: chunks.len() as u64,
+        messages: original.len() as u64,
+        rederived: again.len() as u64,
+        divergent,
+        detail: detail.unwrap_or_default(),
+    }))
+}
+
+/// Verifies the connections picked at random every `SELF_VERIFY_INTERVAL` and stores
+/// the results, so the long capture tells early if it cannot be trusted.
+pub fn spawn(db: DbCore, terminating: Arc<AtomicBool>) -> Option<thread::JoinHandle<()>> {
+    let config = VerifyConfig::from_env()?;
+    log::info!(
+        "verify {} connections every {:?}",
+        config.connections,
+        config.interval
+    );
+
+    Some(thread::spawn(move || {
+        let mut rng = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64
+            | 1;
+        let mut next = SystemTime::now() + config.interval;
+        while !terminating.load(Ordering::SeqCst) {
+            if SystemTime::now() < next {
+                thread::sleep(Duration::from_millis(100));
+                continue;
+            }
+            next += config.interval;
+
+            let total = db
+                .total::<{ DbCore::CONNECTIONS_CNT }>()
+                .unwrap_or_default();
+            if total == 0 {
+                continue;
+            }
+            for _ in 0..config.connections {
+                // xorshift
+                rng ^= rng << 13;
+                rng ^= rng >> 7;
+                rng ^= rng << 17;
+                let cn_id = ConnectionId(rng % total);
+                let v = match verify_connection(&db, cn_id) {
+                    Ok(Some(v)) => v,
+                    Ok(None) => continue,
+                    Err(err) => {
+                        log::warn!("cannot verify {cn_id}: {err}");
+                        continue;
+                    }
+                };
+                if v.divergent != 0 {
+                    log::error!("{cn_id} diverges in {} messages: {}", v.divergent, v.detail);
+                }
+                if let Err(err) = db.put_verification(&v) {
+                    log::error!("cannot store the verification: {err}");
+                }
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+#[test]
+fn verify_rederived() {
+    use salsa20::{
+        cipher::{generic_array::GenericArray, KeyIvInit, StreamCipher},
+        XSalsa20,
+    };
+
+    use crate::{connection::pnet, database::MessageId, event::ConnectionInfo};
+
+    let dir = temp_dir::TempDir::new().unwrap();
+    let db = DbFacade::open(dir.path()).unwrap();
+    let core = db.core();
+    let alias = "mainnet-1.2.3.4".to_owned();
+    let key = pnet::State::<()>::shared_secret(core.profiles().chain_id(&alias).as_bytes());
+
+    // the private network nonces, then the multistream select of both sides
+    let mut chunks = vec![];
+    let mut ciphers = vec![];
+    for incoming in [false, true] {
+        let nonce = [incoming as u8 + 1; 24];
+        ciphers.push(XSalsa20::new(&key, GenericArray::from_slice(&nonce)));
+        chunks.push((incoming, nonce.to_vec()));
+    }
+    for (incoming, cipher) in [false, true].into_iter().zip(&mut ciphers) {
+        let mut bytes = b"\x13/multistream/1.0.0\n".to_vec();
+        cipher.apply_keystream(&mut bytes);
+        chunks.push((incoming, bytes));
+    }
+
+    let time = SystemTime::now();
+    let metadata = || EventMetadata {
+        id: ConnectionInfo {
+            addr: "5.6.7.8:8302".parse().unwrap(),
+            pid: 1,
+            fd: 10,
+        },
+        time,
+        better_time: time,
+        duration: Duration::ZERO,
+    };
+    let mut recorder = P2pRecorder::with_options(
+        db,
+        false,
+        None,
+        0,
+        PeerNamesConfig::default(),
+        KubeMetadata::default(),
+    );
+    recorder.on_alias(1, alias);
+    recorder.on_connect::<true>(false, metadata(), 0, String::new());
+    for (incoming, bytes) in chunks {
+        recorder.on_data(incoming, metadata(), 0, bytes);
+    }
+    recorder.on_disconnect(metadata(), 0);
+    drop(recorder);
+
+    let v = verify_connection(&core, ConnectionId(0)).unwrap().unwrap();
+    assert_eq!(v.chunks, 4);
+    assert_ne!(v.messages, 0);
+    assert_eq!((v.rederived, v.divergent), (v.messages, 0));
+    assert!(v.detail.is_empty());
+
+    // the first message is lost
+    core.prune_messages_below(MessageId(1)).unwrap();
+    let v = verify_connection(&core, ConnectionId(0)).unwrap().unwrap();
+    assert_ne!(v.divergent, 0);
+    core.put_verification(&v).unwrap();
+    let report = VerificationReport::new(
+        time,
+        SystemTime::now(),
+        core.fetch_verifications(time, SystemTime::now()),
+    );
+    assert_eq!((report.connections, report.divergent_connections), (1, 1));
+}
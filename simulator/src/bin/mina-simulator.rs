@@ -5,6 +5,7 @@ use structopt::StructOpt;
 use simulator::{
     registry::{tests, server},
     peer,
+    localnet::Localnet,
 };
 
 #[derive(StructOpt)]
@@ -25,6 +26,24 @@ enum Command {
         #[structopt(short, long)]
         delay: u32,
     },
+    /// Write `docker-compose.yml` with the mock nodes, the debugger and the aggregator, and launch it.
+    Localnet {
+        #[structopt(short, long, default_value = "3")]
+        nodes: u32,
+        #[structopt(short, long, default_value = "10")]
+        blocks: u32,
+        #[structopt(short, long, default_value = "6")]
+        delay: u32,
+        #[structopt(long, default_value = "localnet")]
+        dir: PathBuf,
+        #[structopt(long, default_value = "mina-debugger:local")]
+        debugger_image: String,
+        #[structopt(long, default_value = "mina-tester-k:local")]
+        tester_image: String,
+        /// only write the file
+        #[structopt(long)]
+        dry: bool,
+    },
     Test {
         #[structopt(short, long)]
         summary_json: PathBuf,
@@ -74,6 +93,24 @@ fn main() -> anyhow::Result<()> {
             }
             Ok(())
         }
+        Command::Localnet {
+            nodes,
+            blocks,
+            delay,
+            dir,
+            debugger_image,
+            tester_image,
+            dry,
+        } => {
+            let localnet = Localnet {
+                nodes,
+                blocks,
+                delay,
+                debugger_image,
+                tester_image,
+            };
+            localnet.run(&dir, dry)
+        }
         Command::Test { summary_json, name } => {
             let summary = serde_json::from_reader(File::open(summary_json)?)?;
             match name.as_str() {
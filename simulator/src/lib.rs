@@ -2,4 +2,5 @@
 
 pub mod peer;
 pub mod registry;
+pub mod localnet;
 mod libp2p_helper;
@@ -0,0 +1,168 @@
+use std::{fmt::Write as _, fs, net::Ipv4Addr, path::Path, process::Command};
+
+/// The chain prefix of the alias, the same as the mock peers use.
+pub const CHAIN: &str =
+    "/coda/0.0.1/00000000000000000000000066616b65206e6574776f726b00000000deadbeef";
+
+/// Ports on the host.
+pub const DEBUGGER_PORT: u16 = 8000;
+pub const AGGREGATOR_PORT: u16 = 8100;
+
+/// Mock nodes on one host, traced by a single debugger, so the capture contains
+/// both ends of every connection, and the aggregator which collects the block events.
+pub struct Localnet {
+    pub nodes: u32,
+    pub blocks: u32,
+    pub delay: u32,
+    /// contains `bpf-recorder` and `mina-aggregator`, see `Dockerfile`
+    pub debugger_image: String,
+    /// contains `mina-simulator`, see `tester-k.dockerfile`
+    pub tester_image: String,
+}
+
+impl Localnet {
+    const SUBNET: [u8; 3] = [172, 28, 0];
+
+    fn ip(host: u8) -> Ipv4Addr {
+        let [a, b, c] = Self::SUBNET;
+        Ipv4Addr::new(a, b, c, host)
+    }
+
+    /// The address of the node `i`, it is also the suffix of the node's `BPF_ALIAS`.
+    pub fn node_ip(i: u32) -> Ipv4Addr {
+        Self::ip(10 + i as u8)
+    }
+
+    pub fn compose(&self) -> String {
+        let Localnet {
+            nodes,
+            blocks,
+            delay,
+            debugger_image,
+            tester_image,
+        } = self;
+        let [a, b, c] = Self::SUBNET;
+
+        let mut s = String::new();
+        // writing in string cannot fail
+        let _ = write!(
+            s,
+            r#"version: "3"
+
+services:
+  debugger:
+    image: {debugger_image}
+    privileged: true
+    # the debugger traces the processes of all containers
+    pid: host
+    environment:
+      - RUST_LOG=info
+      - SERVER_PORT=8000
+      - DB_PATH=/capture
+      - AGGREGATOR=http://aggregator:8000/
+      - DEBUGGER_NAME=localnet
+    volumes:
+      - /sys/kernel/debug:/sys/kernel/debug
+      - ./capture:/capture
+    ports:
+      - "{DEBUGGER_PORT}:8000"
+    networks:
+      localnet:
+        ipv4_address: {debugger_ip}
+
+  aggregator:
+    image: {debugger_image}
+    entrypoint: mina-aggregator
+    environment:
+      - RUST_LOG=info
+      - SERVER_PORT=8000
+    volumes:
+      - ./aggregator:/tmp/mina-aggregator-db
+    ports:
+      - "{AGGREGATOR_PORT}:8000"
+    networks:
+      localnet:
+        ipv4_address: {aggregator_ip}
+
+  registry:
+    image: {tester_image}
+    entrypoint: mina-simulator registry --nodes={nodes}
+    environment:
+      - RUST_LOG=info
+    networks:
+      localnet:
+        ipv4_address: {registry_ip}
+"#,
+            debugger_ip = Self::ip(2),
+            aggregator_ip = Self::ip(3),
+            registry_ip = Self::ip(4),
+        );
+        for i in 0..*nodes {
+            let ip = Self::node_ip(i);
+            // wait until the debugger is attached
+            let _ = write!(
+                s,
+                r#"
+  peer{i}:
+    image: {tester_image}
+    entrypoint: ["sh", "-c", "sleep 10 && mina-simulator peer-main --blocks={blocks} --delay={delay}"]
+    privileged: true
+    depends_on:
+      - debugger
+      - registry
+    environment:
+      - RUST_LOG=info
+      - REGISTRY=registry
+      - BUILD_NUMBER=0
+      - MY_POD_IP={ip}
+      - DEBUGGER=debugger:8000
+      - BPF_ALIAS={CHAIN}-{ip}
+    networks:
+      localnet:
+        ipv4_address: {ip}
+"#
+            );
+        }
+        let _ = write!(
+            s,
+            r#"
+networks:
+  localnet:
+    ipam:
+      config:
+        - subnet: {a}.{b}.{c}.0/24
+"#
+        );
+        s
+    }
+
+    /// Writes `docker-compose.yml` in the directory, the capture goes to `capture`
+    /// and the aggregator database to `aggregator` next to it. Launches it unless `dry`.
+    pub fn run(&self, dir: &Path, dry: bool) -> anyhow::Result<()> {
+        if self.nodes == 0 || self.nodes > 200 {
+            anyhow::bail!("the number of nodes must be from 1 to 200");
+        }
+        fs::create_dir_all(dir.join("capture"))?;
+        fs::create_dir_all(dir.join("aggregator"))?;
+        let compose = dir.join("docker-compose.yml");
+        fs::write(&compose, self.compose())?;
+        log::info!("written {}", compose.display());
+        log::info!(
+            "debugger http://localhost:{DEBUGGER_PORT}, aggregator http://localhost:{AGGREGATOR_PORT}"
+        );
+        if dry {
+            return Ok(());
+        }
+
+        let status = Command::new("docker")
+            .arg("compose")
+            .arg("--file")
+            .arg(&compose)
+            .arg("up")
+            .status()?;
+        if !status.success() {
+            anyhow::bail!("docker compose exited with {status}");
+        }
+        Ok(())
+    }
+}
@@ -2,6 +2,7 @@ use std::{
     time::{SystemTime, Duration},
     collections::{BTreeMap, BTreeSet},
     net::SocketAddr,
+    env,
 };
 
 use reqwest::blocking::{ClientBuilder, Client};
@@ -140,6 +141,11 @@ pub enum StreamId {
     Backward(u64),
 }
 
+/// The debugger is a sidecar by default, `DEBUGGER` overrides it, for example in the localnet.
+fn debugger() -> String {
+    env::var("DEBUGGER").unwrap_or_else(|_| "localhost:8000".to_owned())
+}
+
 fn get_messages(client: &Client, params: &str) -> anyhow::Result<Vec<(u64, FullMessage)>> {
     let time = |t: &SystemTime| {
         t.duration_since(SystemTime::UNIX_EPOCH)
@@ -148,7 +154,7 @@ fn get_messages(client: &Client, params: &str) -> anyhow::Result<Vec<(u64, FullM
     };
 
     let res = client
-        .get(&format!("http://localhost:8000/messages?{params}"))
+        .get(&format!("http://{}/messages?{params}", debugger()))
         .send()?
         .text()?;
     if let Ok(msgs) = serde_json::from_str::<Vec<(u64, FullMessage)>>(&res) {
@@ -164,7 +170,7 @@ fn get_messages(client: &Client, params: &str) -> anyhow::Result<Vec<(u64, FullM
 
 fn get_message(client: &Client, id: u64) -> anyhow::Result<Vec<u8>> {
     client
-        .get(&format!("http://localhost:8000/message_bin/{id}"))
+        .get(&format!("http://{}/message_bin/{id}", debugger()))
         .send()?
         .bytes()
         .map(|x| x.to_vec())
@@ -311,7 +317,7 @@ pub fn test_events(events: Vec<DbEventWithMetadata>, peer_id: String) -> DbTestE
     }
 
     fn get_events() -> Vec<DbEventWithMetadata> {
-        let res = reqwest::blocking::get(&format!("http://localhost:8000/libp2p_ipc/block/all"))
+        let res = reqwest::blocking::get(&format!("http://{}/libp2p_ipc/block/all", debugger()))
             .unwrap()
             .text()
             .unwrap();
@@ -319,7 +325,7 @@ pub fn test_events(events: Vec<DbEventWithMetadata>, peer_id: String) -> DbTestE
     }
 
     fn get_network_event(height: u32) -> Vec<BlockNetworkEvent> {
-        let res = reqwest::blocking::get(&format!("http://localhost:8000/block/{height}"))
+        let res = reqwest::blocking::get(&format!("http://{}/block/{height}", debugger()))
             .unwrap()
             .text()
             .unwrap();